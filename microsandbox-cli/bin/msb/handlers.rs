@@ -1,14 +1,17 @@
 use clap::{error::ErrorKind, CommandFactory};
 use microsandbox_cli::{
-    AnsiStyles, MicrosandboxArgs, MicrosandboxCliError, MicrosandboxCliResult, SelfAction,
+    AnsiStyles, MaintenanceAction, MicrosandboxArgs, MicrosandboxCliError, MicrosandboxCliResult,
+    SelfAction,
 };
 use microsandbox_core::{
-    config::START_SCRIPT_NAME,
+    config::{ReferenceOrPath, START_SCRIPT_NAME},
     management::{
         config::{self, Component, ComponentType},
-        home, menv, orchestra, sandbox, toolchain,
+        home::{self, VolumeCleanPolicy},
+        image, menv, orchestra, sandbox, toolchain, volume,
     },
-    oci::Reference,
+    oci::{self, Reference},
+    MicrosandboxError,
 };
 use microsandbox_server::MicrosandboxServerResult;
 use microsandbox_utils::{env, NAMESPACES_SUBDIR};
@@ -72,6 +75,12 @@ pub async fn add_subcommand(
     validate_build_sandbox_conflict(build, sandbox, "add", Some("[NAMES]"), None);
     unsupported_build_error(build, "add", Some("[NAMES]"));
 
+    // Reject images from registries that cannot be pulled from now, rather than when the sandbox
+    // first starts
+    if let ReferenceOrPath::Reference(reference) = image.parse()? {
+        oci::ensure_registry_supported(&reference)?;
+    }
+
     let mut scripts = scripts
         .into_iter()
         .map(|(k, v)| (k, v.into()))
@@ -246,6 +255,8 @@ pub async fn exe_subcommand(
             .exit();
     }
 
+    validate_temp_resources(cpus, memory);
+
     sandbox::run_temp(
         &image,
         script,
@@ -281,6 +292,60 @@ pub async fn up_subcommand(
     Ok(())
 }
 
+pub async fn apply_subcommand(
+    file: Option<PathBuf>,
+    detach: bool,
+    parallelism: Option<usize>,
+) -> MicrosandboxCliResult<()> {
+    if parallelism == Some(0) {
+        return Err(MicrosandboxCliError::InvalidArgument(
+            "--parallelism must be at least 1".to_string(),
+        ));
+    }
+
+    let (path, config) = parse_file_path(file);
+    let report = orchestra::apply(path.as_deref(), config.as_deref(), detach, parallelism).await?;
+
+    for name in &report.created {
+        println!("created sandbox {}", name.literal());
+    }
+
+    for name in &report.removed {
+        println!("removed sandbox {}", name.literal());
+    }
+
+    for name in &report.updated {
+        println!(
+            "sandbox {} is running an older config; restart it to pick up the changes",
+            name.literal()
+        );
+    }
+
+    for error in &report.errors {
+        println!(
+            "{} sandbox {}: {}",
+            "error:".error(),
+            error.name.literal(),
+            error.message
+        );
+    }
+
+    println!(
+        "{} created, {} removed, {} updated, {} unchanged",
+        report.created.len(),
+        report.removed.len(),
+        report.updated.len(),
+        report.unchanged.len()
+    );
+
+    if !report.errors.is_empty() {
+        let names: Vec<&str> = report.errors.iter().map(|e| e.name.as_str()).collect();
+        return Err(MicrosandboxCliError::ApplyFailed(names.join(", ")));
+    }
+
+    Ok(())
+}
+
 pub async fn down_subcommand(
     sandbox: bool,
     build: bool,
@@ -351,6 +416,31 @@ pub async fn log_subcommand(
     Ok(())
 }
 
+/// Handles the describe subcommand, which prints the resolved configuration of a sandbox
+pub async fn describe_subcommand(name: String, file: Option<PathBuf>) -> MicrosandboxCliResult<()> {
+    let (project_dir, config_file) = parse_file_path(file);
+    let resolved =
+        sandbox::describe(&name, project_dir.as_deref(), config_file.as_deref()).await?;
+
+    println!(
+        "{} {} from {}, resolved at {}",
+        "sandbox:".header(),
+        resolved.name.literal(),
+        resolved.config_file,
+        resolved.resolved_at.to_rfc3339()
+    );
+    print!(
+        "{}",
+        serde_yaml::to_string(&resolved.config).map_err(MicrosandboxError::from)?
+    );
+    println!("{} {}", "exec:".header(), resolved.exec_path.literal());
+    if !resolved.exec_args.is_empty() {
+        println!("{} {}", "args:".header(), resolved.exec_args.join(" "));
+    }
+
+    Ok(())
+}
+
 /// Handles the clean subcommand, which removes the .menv directory from a project
 pub async fn clean_subcommand(
     _sandbox: bool,
@@ -359,10 +449,17 @@ pub async fn clean_subcommand(
     all: bool,
     file: Option<PathBuf>,
     force: bool,
+    volumes: VolumeCleanPolicy,
 ) -> MicrosandboxCliResult<()> {
+    if volumes == VolumeCleanPolicy::Only {
+        home::clean(force, volumes).await?;
+        tracing::info!("unused named volumes cleaned");
+        return Ok(());
+    }
+
     if user || all {
         // User-level cleanup - clean the microsandbox home directory
-        home::clean(force).await?;
+        home::clean(force, volumes).await?;
         tracing::info!("user microsandbox home directory cleaned");
 
         // User-level cleanup - clean the user scripts (MSB-ALIAS)
@@ -428,6 +525,45 @@ pub async fn server_keygen_subcommand(
     Ok(())
 }
 
+pub async fn server_maintenance_subcommand(
+    action: MaintenanceAction,
+    host: Option<String>,
+    port: Option<u16>,
+) -> MicrosandboxCliResult<()> {
+    let enabled = match action {
+        MaintenanceAction::On => Some(true),
+        MaintenanceAction::Off => Some(false),
+        MaintenanceAction::Status => None,
+    };
+
+    let status = microsandbox_server::maintenance(enabled, host, port).await?;
+    if status.maintenance {
+        println!(
+            "maintenance mode is {}: new sessions are rejected, {} existing session(s) keep running",
+            "on".literal(),
+            status.sessions
+        );
+    } else {
+        println!(
+            "maintenance mode is {}: new sessions are accepted",
+            "off".literal()
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn server_dump_subcommand(
+    host: Option<String>,
+    port: Option<u16>,
+) -> MicrosandboxCliResult<()> {
+    let dump = microsandbox_server::dump_state(host, port).await?;
+    // The alternate format of a JSON value pretty-prints it
+    println!("{:#}", dump);
+
+    Ok(())
+}
+
 /// Handles the server ssh subcommand, which spawns a new SSH session into a sandbox
 pub async fn server_ssh_subcommand(
     _namespace: String,
@@ -454,8 +590,8 @@ pub async fn self_subcommand(action: SelfAction) -> MicrosandboxCliResult<()> {
             return Ok(());
         }
         SelfAction::Uninstall => {
-            // Clean the home directory first
-            home::clean(true).await?;
+            // Clean the home directory first, along with the named volumes
+            home::clean(true, VolumeCleanPolicy::Include).await?;
 
             // Clean user scripts
             toolchain::clean().await?;
@@ -669,6 +805,117 @@ pub async fn server_status_subcommand(
     Ok(())
 }
 
+pub async fn server_reconcile_subcommand(dry_run: bool) -> MicrosandboxCliResult<()> {
+    // Get the microsandbox home path
+    let microsandbox_home_path = env::get_microsandbox_home_path();
+    let namespaces_path = microsandbox_home_path.join(NAMESPACES_SUBDIR);
+
+    if !namespaces_path.exists() {
+        return Err(MicrosandboxCliError::NotFound(
+            "No namespaces directory found".to_string(),
+        ));
+    }
+
+    let report = orchestra::reconcile(namespaces_path.as_path(), dry_run).await?;
+    let (stop_verb, remove_verb) = if report.dry_run {
+        ("would stop", "would remove")
+    } else {
+        ("stopped", "removed")
+    };
+
+    for sandbox in &report.orphaned_vms_stopped {
+        println!(
+            "{} orphaned process {} of sandbox {} ({})",
+            stop_verb,
+            sandbox.pid.to_string().literal(),
+            sandbox.name.literal(),
+            sandbox.namespace.as_deref().unwrap_or("unknown namespace")
+        );
+    }
+
+    for sandbox in &report.dangling_records_removed {
+        println!(
+            "{} dangling record of sandbox {} ({})",
+            remove_verb,
+            sandbox.name.literal(),
+            sandbox.namespace.as_deref().unwrap_or("unknown namespace")
+        );
+    }
+
+    println!(
+        "{} orphaned process(es) {}, {} dangling record(s) {}",
+        report.orphaned_vms_stopped.len(),
+        stop_verb,
+        report.dangling_records_removed.len(),
+        remove_verb
+    );
+
+    Ok(())
+}
+
+pub async fn volume_create_subcommand(name: String) -> MicrosandboxCliResult<()> {
+    let path = volume::create(&name).await?;
+    println!("created volume {} at {}", name.literal(), path.display());
+
+    Ok(())
+}
+
+pub async fn volume_ls_subcommand() -> MicrosandboxCliResult<()> {
+    let volumes = volume::list().await?;
+    if volumes.is_empty() {
+        println!("no volumes");
+        return Ok(());
+    }
+
+    for volume in &volumes {
+        let usage = if volume.users.is_empty() {
+            "unused".to_string()
+        } else {
+            format!("in use by {}", volume.users.join(", "))
+        };
+        println!(
+            "{}  {}  ({})",
+            volume.name.literal(),
+            volume.path.display(),
+            usage
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn volume_rm_subcommand(names: Vec<String>) -> MicrosandboxCliResult<()> {
+    for name in &names {
+        volume::remove(name).await?;
+        println!("removed volume {}", name.literal());
+    }
+
+    Ok(())
+}
+
+pub async fn image_repair_subcommand() -> MicrosandboxCliResult<()> {
+    let report = image::repair().await?;
+    let Some(backup_path) = report.backup_path else {
+        println!("image database is intact");
+        return Ok(());
+    };
+
+    println!(
+        "image database was corrupted: {}",
+        report.problems.join("; ")
+    );
+    println!(
+        "rebuilt it with {} recovered images and {} registered layers",
+        report.images_recovered, report.layers_registered
+    );
+    println!(
+        "the corrupted database was kept at {}",
+        backup_path.display()
+    );
+
+    Ok(())
+}
+
 pub async fn login_subcommand() -> MicrosandboxCliResult<()> {
     println!(
         "{} login functionality is not yet implemented",
@@ -847,6 +1094,46 @@ fn parse_duration_string(duration_str: &str) -> MicrosandboxCliResult<chrono::Du
     }
 }
 
+/// Validate that the resources requested for a temporary sandbox are within the configured limits.
+///
+/// Out-of-range values are rejected before the image is pulled or the VM is started, instead of
+/// failing deep in VM start.
+///
+/// # Arguments
+///
+/// * `cpus` - The number of vCPUs requested with --cpus
+/// * `memory` - The memory in MiB requested with --memory
+fn validate_temp_resources(cpus: Option<u8>, memory: Option<u32>) {
+    let max_cpus = env::get_temp_max_cpus();
+    let min_memory = env::get_temp_min_memory_mib();
+    let max_memory = env::get_temp_max_memory_mib();
+
+    let message = match (cpus, memory) {
+        (Some(cpus), _) if cpus == 0 || cpus > max_cpus => format!(
+            "`{}` must be between 1 and {} (set `{}` to raise the limit), got {}",
+            "--cpus".literal(),
+            max_cpus,
+            env::MSB_TEMP_MAX_CPUS_ENV_VAR.literal(),
+            cpus
+        ),
+        (_, Some(memory)) if memory < min_memory || memory > max_memory => format!(
+            "`{}` must be between {} and {} MiB (set `{}` and `{}` to change the limits), got {}",
+            "--memory".literal(),
+            min_memory,
+            max_memory,
+            env::MSB_TEMP_MIN_MEMORY_MIB_ENV_VAR.literal(),
+            env::MSB_TEMP_MAX_MEMORY_MIB_ENV_VAR.literal(),
+            memory
+        ),
+        _ => return,
+    };
+
+    MicrosandboxArgs::command()
+        .override_usage(usage("exe", Some("[NAME[~SCRIPT]]"), Some("<ARGS>")))
+        .error(ErrorKind::InvalidValue, message)
+        .exit();
+}
+
 /// Validate that both `--build` and `--sandbox` flags are not specified together.
 ///
 /// # Arguments
@@ -3,9 +3,16 @@ mod msb;
 
 use clap::{CommandFactory, Parser};
 use microsandbox_cli::{
-    AnsiStyles, MicrosandboxArgs, MicrosandboxCliResult, MicrosandboxSubcommand, ServerSubcommand,
+    AnsiStyles, ImageSubcommand, MicrosandboxArgs, MicrosandboxCliResult, MicrosandboxSubcommand,
+    ServerSubcommand, VolumeSubcommand,
+};
+use microsandbox_core::{
+    management::{
+        home::{self, VolumeCleanPolicy},
+        image,
+    },
+    oci,
 };
-use microsandbox_core::management::{image, orchestra};
 use msb::handlers;
 
 //--------------------------------------------------------------------------------------------------
@@ -32,6 +39,11 @@ async fn main() -> MicrosandboxCliResult<()> {
         return Ok(());
     }
 
+    // Fail early with a clear error if the home directory cannot be used
+    if args.subcommand.is_some() {
+        home::ensure_writable().await?;
+    }
+
     match args.subcommand {
         Some(MicrosandboxSubcommand::Init { file }) => {
             let (path, _) = handlers::parse_file_path(file);
@@ -84,8 +96,17 @@ async fn main() -> MicrosandboxCliResult<()> {
             image,
             name,
             layer_path,
+            platform,
         }) => {
-            image::pull(name, image, layer_path).await?;
+            oci::ensure_registry_supported(&name)?;
+            image::pull(
+                name,
+                image,
+                layer_path,
+                platform,
+                Some(oci::cli_pull_progress()),
+            )
+            .await?;
         }
         Some(MicrosandboxSubcommand::Run {
             sandbox,
@@ -157,9 +178,12 @@ async fn main() -> MicrosandboxCliResult<()> {
         Some(MicrosandboxSubcommand::Uninstall { script }) => {
             handlers::uninstall_subcommand(script).await?;
         }
-        Some(MicrosandboxSubcommand::Apply { file, detach }) => {
-            let (path, config) = handlers::parse_file_path(file);
-            orchestra::apply(path.as_deref(), config.as_deref(), detach).await?;
+        Some(MicrosandboxSubcommand::Apply {
+            file,
+            detach,
+            parallelism,
+        }) => {
+            handlers::apply_subcommand(file, detach, parallelism).await?;
         }
         Some(MicrosandboxSubcommand::Up {
             sandbox,
@@ -196,6 +220,9 @@ async fn main() -> MicrosandboxCliResult<()> {
         }) => {
             handlers::log_subcommand(sandbox, build, name, file, follow, tail).await?;
         }
+        Some(MicrosandboxSubcommand::Describe { name, file }) => {
+            handlers::describe_subcommand(name, file).await?;
+        }
         Some(MicrosandboxSubcommand::Clean {
             sandbox,
             name,
@@ -203,9 +230,35 @@ async fn main() -> MicrosandboxCliResult<()> {
             all,
             file,
             force,
+            keep_volumes: _,
+            volumes,
+            volumes_only,
         }) => {
-            handlers::clean_subcommand(sandbox, name, user, all, file, force).await?;
+            let volumes = if volumes_only {
+                VolumeCleanPolicy::Only
+            } else if volumes {
+                VolumeCleanPolicy::Include
+            } else {
+                VolumeCleanPolicy::Keep
+            };
+            handlers::clean_subcommand(sandbox, name, user, all, file, force, volumes).await?;
         }
+        Some(MicrosandboxSubcommand::Volume { subcommand }) => match subcommand {
+            VolumeSubcommand::Create { name } => {
+                handlers::volume_create_subcommand(name).await?;
+            }
+            VolumeSubcommand::Ls => {
+                handlers::volume_ls_subcommand().await?;
+            }
+            VolumeSubcommand::Rm { names } => {
+                handlers::volume_rm_subcommand(names).await?;
+            }
+        },
+        Some(MicrosandboxSubcommand::Image { subcommand }) => match subcommand {
+            ImageSubcommand::Repair => {
+                handlers::image_repair_subcommand().await?;
+            }
+        },
         Some(MicrosandboxSubcommand::Self_ { action }) => {
             handlers::self_subcommand(action).await?;
         }
@@ -255,6 +308,15 @@ async fn main() -> MicrosandboxCliResult<()> {
             } => {
                 handlers::server_status_subcommand(sandbox, names, namespace).await?;
             }
+            ServerSubcommand::Maintenance { action, host, port } => {
+                handlers::server_maintenance_subcommand(action, host, port).await?;
+            }
+            ServerSubcommand::Dump { host, port } => {
+                handlers::server_dump_subcommand(host, port).await?;
+            }
+            ServerSubcommand::Reconcile { dry_run } => {
+                handlers::server_reconcile_subcommand(dry_run).await?;
+            }
             ServerSubcommand::Ssh {
                 namespace,
                 sandbox,
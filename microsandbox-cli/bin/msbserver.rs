@@ -1,5 +1,5 @@
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::{sync::Arc, time::Duration};
+use tokio::{signal, sync::RwLock};
 
 use axum::http::{
     header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
@@ -7,10 +7,19 @@ use axum::http::{
 };
 use clap::Parser;
 use microsandbox_cli::{MicrosandboxCliResult, MsbserverArgs};
-use microsandbox_server::{port::PortManager, route, state::AppState, Config};
+use microsandbox_core::management::home;
+use microsandbox_server::{port::PortManager, route, state::AppState, CancelReason, Config};
 use microsandbox_utils::CHECKMARK;
 use tower_http::cors::{Any, CorsLayer};
 
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How long the server keeps failing readiness checks before it stops accepting connections,
+/// giving load balancers time to notice and route elsewhere
+const DRAIN_DELAY: Duration = Duration::from_secs(5);
+
 //--------------------------------------------------------------------------------------------------
 // Functions: Main
 //--------------------------------------------------------------------------------------------------
@@ -67,6 +76,9 @@ pub async fn main() -> MicrosandboxCliResult<()> {
         );
     }
 
+    // Fail early with a clear error if the home directory cannot be used
+    home::ensure_writable().await?;
+
     // Create configuration from arguments
     let config = Arc::new(Config::new(
         args.key,
@@ -90,6 +102,29 @@ pub async fn main() -> MicrosandboxCliResult<()> {
     // Create application state
     let state = AppState::new(config.clone(), port_manager);
 
+    // Pull the images of the warmed templates, and boot a sandbox of each, if enabled
+    let warmed = state
+        .get_session_manager()
+        .warm_on_start(state.clone())
+        .await;
+    let warmed_images = warmed.cached_images.len() + warmed.pulled_images.len();
+    if warmed_images > 0 {
+        println!(
+            "{} Warmed {} images and {} sandboxes",
+            *CHECKMARK,
+            warmed_images,
+            warmed.booted_templates.len()
+        );
+    }
+
+    // Restart the sandboxes of sessions that crashed, if enabled
+    let _crash_recovery_handle = state
+        .get_session_manager()
+        .start_background_crash_recovery(state.clone());
+
+    // Persist usage kept in memory while the usage file was not writable
+    let _usage_retry_handle = state.get_session_manager().start_background_usage_retry();
+
     // Configure CORS
     let cors = CorsLayer::new()
         .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
@@ -97,6 +132,7 @@ pub async fn main() -> MicrosandboxCliResult<()> {
         .allow_origin(Any);
 
     // Build application
+    let shutdown_state = state.clone();
     let app = route::create_router(state).layer(cors);
 
     // Start server
@@ -109,7 +145,48 @@ pub async fn main() -> MicrosandboxCliResult<()> {
 
     let listener = tokio::net::TcpListener::bind(config.get_addr()).await?;
 
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(drain_on_shutdown(shutdown_state))
+        .await?;
 
     Ok(())
 }
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Wait for a shutdown signal, then drain the server before it stops accepting connections
+async fn drain_on_shutdown(state: AppState) {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to install signal handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    tracing::info!("Shutdown signal received, draining for {:?}", DRAIN_DELAY);
+    state.start_draining();
+    tokio::time::sleep(DRAIN_DELAY).await;
+
+    // Cut executions still running short, so their clients learn why instead of losing the
+    // connection
+    state
+        .get_session_manager()
+        .cancel_executions(CancelReason::ServerShutdown);
+}
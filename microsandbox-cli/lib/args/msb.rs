@@ -2,7 +2,7 @@ use std::{error::Error, path::PathBuf};
 
 use crate::styles;
 use clap::Parser;
-use microsandbox_core::oci::Reference;
+use microsandbox_core::oci::{self, Platform, Reference};
 use typed_path::Utf8UnixPathBuf;
 
 //-------------------------------------------------------------------------------------------------
@@ -197,6 +197,18 @@ pub enum MicrosandboxSubcommand {
         tail: Option<usize>,
     },
 
+    /// Show the resolved configuration a sandbox was started with
+    #[command(name = "describe")]
+    Describe {
+        /// Name of the sandbox
+        #[arg(required = true)]
+        name: String,
+
+        /// Path to the sandbox file or the project directory
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+    },
+
     /// Show tree of layers that make up a sandbox
     #[command(name = "tree")]
     Tree {
@@ -394,6 +406,11 @@ pub enum MicrosandboxSubcommand {
         /// Run sandboxes in the background
         #[arg(short, long)]
         detach: bool,
+
+        /// Maximum number of sandboxes to start at the same time in the background.
+        /// Defaults to MSB_APPLY_PARALLELISM or 4.
+        #[arg(short, long)]
+        parallelism: Option<usize>,
     },
 
     /// Run a project's sandboxes
@@ -484,6 +501,18 @@ pub enum MicrosandboxSubcommand {
         /// Force clean
         #[arg(short = 'F', long)]
         force: bool,
+
+        /// Keep named volumes when cleaning user-level caches. This is the default
+        #[arg(long, conflicts_with_all = ["volumes", "volumes_only"])]
+        keep_volumes: bool,
+
+        /// Also remove named volumes that no running sandbox uses when cleaning user-level caches
+        #[arg(long, conflicts_with = "volumes_only")]
+        volumes: bool,
+
+        /// Only remove named volumes that no running sandbox uses
+        #[arg(long)]
+        volumes_only: bool,
     },
 
     /// Build images
@@ -520,6 +549,10 @@ pub enum MicrosandboxSubcommand {
         /// Path to store the layer files
         #[arg(short = 'L', long)]
         layer_path: Option<PathBuf>,
+
+        /// Platform to pull a multi-arch image for, e.g. linux/arm64, instead of the host's
+        #[arg(long, value_parser = oci::parse_platform)]
+        platform: Option<Platform>,
     },
 
     /// Login to a registry
@@ -538,6 +571,22 @@ pub enum MicrosandboxSubcommand {
         name: String,
     },
 
+    /// Manage named volumes that persist across sandboxes
+    #[command(name = "volume")]
+    Volume {
+        /// The subcommand to run
+        #[command(subcommand)]
+        subcommand: VolumeSubcommand,
+    },
+
+    /// Manage the local image store
+    #[command(name = "image")]
+    Image {
+        /// The subcommand to run
+        #[command(subcommand)]
+        subcommand: ImageSubcommand,
+    },
+
     /// Manage microsandbox itself
     #[command(name = "self")]
     Self_ {
@@ -656,6 +705,42 @@ pub enum ServerSubcommand {
         namespace: Option<String>,
     },
 
+    /// Turn maintenance mode on or off, rejecting new sessions while existing ones keep working
+    #[command(name = "maintenance")]
+    Maintenance {
+        /// Whether to turn maintenance mode on or off, or show whether it is on
+        #[arg(value_enum)]
+        action: MaintenanceAction,
+
+        /// Host the server listens on
+        #[arg(long)]
+        host: Option<String>,
+
+        /// Port the server listens on
+        #[arg(long)]
+        port: Option<u16>,
+    },
+
+    /// Dump the internal state of the running server as JSON, for debugging
+    #[command(name = "dump")]
+    Dump {
+        /// Host the server listens on
+        #[arg(long)]
+        host: Option<String>,
+
+        /// Port the server listens on
+        #[arg(long)]
+        port: Option<u16>,
+    },
+
+    /// Detect and clean up orphaned sandboxes and dangling sandbox records
+    #[command(name = "reconcile")]
+    Reconcile {
+        /// Only report what would be cleaned up, without stopping or removing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
     /// SSH into a sandbox
     #[command(name = "ssh")]
     Ssh {
@@ -673,6 +758,51 @@ pub enum ServerSubcommand {
     },
 }
 
+/// Subcommands for the volume subcommand
+#[derive(Debug, Parser)]
+pub enum VolumeSubcommand {
+    /// Create a named volume
+    #[command(name = "create")]
+    Create {
+        /// Name of the volume
+        #[arg(required = true)]
+        name: String,
+    },
+
+    /// List named volumes and the sandboxes using them
+    #[command(name = "ls", alias = "list")]
+    Ls,
+
+    /// Remove a named volume and its data
+    #[command(name = "rm", alias = "remove")]
+    Rm {
+        /// Names of the volumes
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+}
+
+/// Subcommands for the image subcommand
+#[derive(Debug, Parser)]
+pub enum ImageSubcommand {
+    /// Check the image database and rebuild it if it is corrupted
+    #[command(name = "repair")]
+    Repair,
+}
+
+/// Actions for the server maintenance subcommand
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum MaintenanceAction {
+    /// Reject new sessions
+    On,
+
+    /// Accept new sessions again
+    Off,
+
+    /// Show whether maintenance mode is on
+    Status,
+}
+
 /// Actions for the self subcommand
 #[derive(Debug, Clone, clap::ValueEnum)]
 pub enum SelfAction {
@@ -41,4 +41,8 @@ pub enum MicrosandboxCliError {
     /// Namespace operation error
     #[error("namespace error: {0}")]
     NamespaceError(String),
+
+    /// Sandboxes that could not be started or stopped while applying a configuration
+    #[error("failed to apply sandboxes: {0}")]
+    ApplyFailed(String),
 }
@@ -0,0 +1,185 @@
+use crate::MicrosandboxError;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use std::{fmt, net::IpAddr, str::FromStr};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The maximum length of a hostname.
+const MAX_HOSTNAME_LEN: usize = 253;
+
+/// The maximum length of a single label of a hostname.
+const MAX_LABEL_LEN: usize = 63;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Represents an entry added to the hosts file of a sandbox.
+///
+/// This struct maps a hostname to the IP address it resolves to inside the sandbox.
+///
+/// ## Examples
+///
+/// ```
+/// use microsandbox_core::config::HostEntry;
+/// use std::str::FromStr;
+///
+/// // Parse a host entry from a string
+/// let entry = HostEntry::from_str("db.internal:10.0.0.5").unwrap();
+///
+/// assert_eq!(entry.get_hostname(), "db.internal");
+/// assert_eq!(entry.get_ip().to_string(), "10.0.0.5");
+///
+/// // IPv6 addresses are supported as well
+/// let entry = HostEntry::from_str("db.internal:fd00::5").unwrap();
+/// assert_eq!(entry.get_ip().to_string(), "fd00::5");
+/// ```
+#[derive(Debug, Hash, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct HostEntry {
+    /// The hostname.
+    hostname: String,
+
+    /// The IP address the hostname resolves to.
+    ip: IpAddr,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl HostEntry {
+    /// Creates a new `HostEntry` with the given hostname and IP address.
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The hostname.
+    /// * `ip` - The IP address the hostname resolves to.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `hostname` is not a valid hostname.
+    pub fn new(hostname: impl Into<String>, ip: IpAddr) -> Result<Self, MicrosandboxError> {
+        let hostname = hostname.into();
+        if !is_valid_hostname(&hostname) {
+            return Err(MicrosandboxError::InvalidHostEntry(format!(
+                "invalid hostname: {}",
+                hostname
+            )));
+        }
+
+        Ok(Self { hostname, ip })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Checks that a hostname is made of dot-separated labels of letters, digits and hyphens, none
+/// of which starts or ends with a hyphen.
+fn is_valid_hostname(hostname: &str) -> bool {
+    !hostname.is_empty()
+        && hostname.len() <= MAX_HOSTNAME_LEN
+        && hostname.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= MAX_LABEL_LEN
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl FromStr for HostEntry {
+    type Err = MicrosandboxError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Hostnames cannot contain colons, so everything after the first one is the address
+        let (hostname, ip) = s
+            .split_once(':')
+            .ok_or_else(|| MicrosandboxError::InvalidHostEntry(s.to_string()))?;
+
+        let ip = ip.parse().map_err(|_| {
+            MicrosandboxError::InvalidHostEntry(format!("invalid IP address: {}", ip))
+        })?;
+
+        Self::new(hostname, ip)
+    }
+}
+
+impl fmt::Display for HostEntry {
+    /// Formats the host entry following the format "<hostname>:<ip>".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.hostname, self.ip)
+    }
+}
+
+impl Serialize for HostEntry {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for HostEntry {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Self::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_entry_from_str() -> anyhow::Result<()> {
+        let entry: HostEntry = "db.internal:10.0.0.5".parse()?;
+        assert_eq!(entry.hostname, "db.internal");
+        assert_eq!(entry.ip, "10.0.0.5".parse::<IpAddr>()?);
+
+        let entry: HostEntry = "db:fd00::5".parse()?;
+        assert_eq!(entry.hostname, "db");
+        assert_eq!(entry.ip, "fd00::5".parse::<IpAddr>()?);
+
+        assert!("db.internal".parse::<HostEntry>().is_err());
+        assert!(":10.0.0.5".parse::<HostEntry>().is_err());
+        assert!("db.internal:".parse::<HostEntry>().is_err());
+        assert!("db.internal:10.0.0.256".parse::<HostEntry>().is_err());
+        assert!("db_internal:10.0.0.5".parse::<HostEntry>().is_err());
+        assert!("-db.internal:10.0.0.5".parse::<HostEntry>().is_err());
+        assert!("db..internal:10.0.0.5".parse::<HostEntry>().is_err());
+        assert!(format!("{}:10.0.0.5", "a".repeat(64))
+            .parse::<HostEntry>()
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_host_entry_display() -> anyhow::Result<()> {
+        let entry = HostEntry::new("db.internal", "fd00::5".parse()?)?;
+        assert_eq!(entry.to_string(), "db.internal:fd00::5");
+        assert_eq!(entry.to_string().parse::<HostEntry>()?, entry);
+
+        Ok(())
+    }
+}
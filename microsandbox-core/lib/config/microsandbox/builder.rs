@@ -1,11 +1,11 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, net::IpAddr};
 
 use microsandbox_utils::DEFAULT_SHELL;
 use semver::Version;
 use typed_path::Utf8UnixPathBuf;
 
 use crate::{
-    config::{EnvPair, PathPair, PortPair, ReferenceOrPath},
+    config::{EnvPair, HostEntry, PathPair, PortPair, ReferenceOrPath},
     MicrosandboxResult,
 };
 
@@ -52,6 +52,8 @@ pub struct MicrosandboxBuilder {
 /// - `imports`: The files to import
 /// - `exports`: The files to export
 /// - `scope`: The network scope for the sandbox
+/// - `dns_servers`: The DNS servers the sandbox resolves names with
+/// - `extra_hosts`: The entries to add to the sandbox's hosts file
 /// - `proxy`: The proxy to use
 pub struct SandboxBuilder<I> {
     version: Option<Version>,
@@ -71,6 +73,8 @@ pub struct SandboxBuilder<I> {
     imports: HashMap<String, Utf8UnixPathBuf>,
     exports: HashMap<String, Utf8UnixPathBuf>,
     scope: NetworkScope,
+    dns_servers: Vec<IpAddr>,
+    extra_hosts: Vec<HostEntry>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -153,6 +157,8 @@ impl<I> SandboxBuilder<I> {
             imports: self.imports,
             exports: self.exports,
             scope: self.scope,
+            dns_servers: self.dns_servers,
+            extra_hosts: self.extra_hosts,
         }
     }
 
@@ -248,6 +254,24 @@ impl<I> SandboxBuilder<I> {
         self.scope = scope;
         self
     }
+
+    /// Sets the DNS servers for the sandbox
+    pub fn dns_servers(
+        mut self,
+        dns_servers: impl IntoIterator<Item = IpAddr>,
+    ) -> SandboxBuilder<I> {
+        self.dns_servers = dns_servers.into_iter().collect();
+        self
+    }
+
+    /// Sets the entries to add to the sandbox's hosts file
+    pub fn extra_hosts(
+        mut self,
+        extra_hosts: impl IntoIterator<Item = HostEntry>,
+    ) -> SandboxBuilder<I> {
+        self.extra_hosts = extra_hosts.into_iter().collect();
+        self
+    }
 }
 
 impl SandboxBuilder<ReferenceOrPath> {
@@ -270,6 +294,8 @@ impl SandboxBuilder<ReferenceOrPath> {
             imports: self.imports,
             exports: self.exports,
             scope: self.scope,
+            dns_servers: self.dns_servers,
+            extra_hosts: self.extra_hosts,
         }
     }
 }
@@ -298,6 +324,8 @@ impl Default for SandboxBuilder<()> {
             imports: HashMap::new(),
             exports: HashMap::new(),
             scope: NetworkScope::default(),
+            dns_servers: Vec::new(),
+            extra_hosts: Vec::new(),
         }
     }
 }
@@ -3,6 +3,7 @@
 use std::{
     collections::HashMap,
     fmt::{self, Display},
+    net::IpAddr,
     str::FromStr,
 };
 
@@ -13,7 +14,7 @@ use typed_builder::TypedBuilder;
 use typed_path::Utf8UnixPathBuf;
 
 use crate::{
-    config::{EnvPair, PathPair, PortPair, ReferenceOrPath},
+    config::{EnvPair, HostEntry, PathPair, PortPair, ReferenceOrPath},
     MicrosandboxError, MicrosandboxResult,
 };
 
@@ -304,6 +305,15 @@ pub struct Sandbox {
     /// The network scope for the sandbox.
     #[serde(default)]
     pub(crate) scope: NetworkScope,
+
+    /// The DNS servers the sandbox resolves names with. The image's resolver configuration is
+    /// used when this is empty.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub(crate) dns_servers: Vec<IpAddr>,
+
+    /// The entries to add to the sandbox's hosts file.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub(crate) extra_hosts: Vec<HostEntry>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -1,6 +1,7 @@
 //! Configuration types and helpers.
 
 mod env_pair;
+mod host_entry;
 mod microsandbox;
 mod path_pair;
 mod path_segment;
@@ -12,6 +13,7 @@ mod reference_path;
 //--------------------------------------------------------------------------------------------------
 
 pub use env_pair::*;
+pub use host_entry::*;
 pub use microsandbox::*;
 pub use path_pair::*;
 pub use path_segment::*;
@@ -1,9 +1,20 @@
 use std::{fmt, str::FromStr};
 
+use microsandbox_utils::SupportedPathType;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use typed_path::Utf8UnixPathBuf;
+use typed_path::{Utf8UnixComponent, Utf8UnixPathBuf};
 
-use crate::MicrosandboxError;
+use crate::{management::volume, MicrosandboxError, MicrosandboxResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The prefix that marks the host side of a path pair as a named volume.
+pub const NAMED_VOLUME_PREFIX: &str = "volume:";
+
+/// The guest directories that volumes cannot be mounted at or into, besides the root itself.
+pub const PROTECTED_GUEST_PATHS: &[&str] = &["/proc", "/sys"];
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -15,6 +26,10 @@ use crate::MicrosandboxError;
 /// The path pair can be specified in two formats:
 /// - `host:guest` - Maps a host path to a different guest path (e.g., "/host/path:/container/path")
 /// - `path` or `path:path` - Maps the same path on both host and guest (e.g., "/data" or "/data:/data")
+/// - `volume:name:guest` - Mounts the named volume `name`, a directory managed under the
+///   microsandbox home, at the guest path (e.g., "volume:cache:/root/.cache")
+///
+/// Parsed guest paths are checked with [`validate_guest_path`].
 ///
 /// ## Examples
 ///
@@ -73,6 +88,14 @@ impl PathPair {
         }
     }
 
+    /// Returns the name of the named volume mounted by this path pair, if it mounts one.
+    pub fn get_volume_name(&self) -> Option<&str> {
+        match self {
+            Self::Distinct { host, .. } => host.as_str().strip_prefix(NAMED_VOLUME_PREFIX),
+            Self::Same(_) => None,
+        }
+    }
+
     /// Returns the guest path.
     pub fn get_guest(&self) -> &Utf8UnixPathBuf {
         match self {
@@ -81,6 +104,56 @@ impl PathPair {
     }
 }
 
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Checks that a volume can be mounted at a guest path.
+///
+/// The path must be absolute and must not contain `..` components, so that it cannot traverse
+/// to another directory than it appears to name. Once normalized, it must not be the root or a
+/// path within [`PROTECTED_GUEST_PATHS`], which a volume would hide from the sandbox.
+///
+/// ## Errors
+///
+/// Returns [`MicrosandboxError::InvalidGuestPath`] if a volume cannot be mounted at the path.
+pub fn validate_guest_path(path: &str) -> MicrosandboxResult<()> {
+    let invalid = |reason: &str| MicrosandboxError::InvalidGuestPath {
+        path: path.to_string(),
+        reason: reason.to_string(),
+    };
+
+    let guest = Utf8UnixPathBuf::from(path);
+    if !guest.is_absolute() {
+        return Err(invalid("must be an absolute path"));
+    }
+
+    if guest
+        .components()
+        .any(|c| c == Utf8UnixComponent::ParentDir)
+    {
+        return Err(invalid("must not contain '..' components"));
+    }
+
+    let normalized = Utf8UnixPathBuf::from(
+        microsandbox_utils::normalize_path(path, SupportedPathType::Absolute)
+            .map_err(|e| invalid(&e.to_string()))?,
+    );
+    if normalized == "/" {
+        return Err(invalid("cannot mount over the root directory"));
+    }
+
+    // Paths are compared by component, so e.g. /procfs is not within /proc
+    if let Some(protected) = PROTECTED_GUEST_PATHS
+        .iter()
+        .find(|protected| normalized.starts_with(protected))
+    {
+        return Err(invalid(&format!("cannot mount at or within {}", protected)));
+    }
+
+    Ok(())
+}
+
 //--------------------------------------------------------------------------------------------------
 // Trait Implementations
 //--------------------------------------------------------------------------------------------------
@@ -93,11 +166,28 @@ impl FromStr for PathPair {
             return Err(MicrosandboxError::InvalidPathPair(s.to_string()));
         }
 
+        if let Some(volume) = s.strip_prefix(NAMED_VOLUME_PREFIX) {
+            let Some((name, guest)) = volume.split_once(':') else {
+                return Err(MicrosandboxError::InvalidPathPair(s.to_string()));
+            };
+            if guest.is_empty() || volume::validate_name(name).is_err() {
+                return Err(MicrosandboxError::InvalidPathPair(s.to_string()));
+            }
+
+            validate_guest_path(guest)?;
+
+            return Ok(Self::Distinct {
+                host: format!("{}{}", NAMED_VOLUME_PREFIX, name).into(),
+                guest: guest.into(),
+            });
+        }
+
         if s.contains(':') {
             let (host, guest) = s.split_once(':').unwrap();
             if guest.is_empty() || host.is_empty() {
                 return Err(MicrosandboxError::InvalidPathPair(s.to_string()));
             }
+            validate_guest_path(guest)?;
 
             if guest == host {
                 return Ok(Self::Same(host.into()));
@@ -109,6 +199,8 @@ impl FromStr for PathPair {
             }
         }
 
+        validate_guest_path(s)?;
+
         Ok(Self::Same(s.into()))
     }
 }
@@ -180,6 +272,78 @@ mod tests {
         assert!("/data:".parse::<PathPair>().is_err());
     }
 
+    #[test]
+    fn test_path_pair_named_volume() {
+        let volume = "volume:cache:/root/.cache".parse::<PathPair>().unwrap();
+        assert_eq!(
+            volume,
+            PathPair::Distinct {
+                host: "volume:cache".into(),
+                guest: "/root/.cache".into()
+            }
+        );
+        assert_eq!(volume.get_volume_name(), Some("cache"));
+        assert_eq!(volume.to_string(), "volume:cache:/root/.cache");
+
+        // Plain paths are not named volumes
+        assert_eq!("/data".parse::<PathPair>().unwrap().get_volume_name(), None);
+        assert_eq!(
+            "./volume:/data"
+                .parse::<PathPair>()
+                .unwrap()
+                .get_volume_name(),
+            None
+        );
+
+        // Invalid named volumes
+        assert!("volume:cache".parse::<PathPair>().is_err());
+        assert!("volume::/data".parse::<PathPair>().is_err());
+        assert!("volume:cache:".parse::<PathPair>().is_err());
+        assert!("volume:../cache:/data".parse::<PathPair>().is_err());
+    }
+
+    #[test]
+    fn test_path_pair_guest_path_validation() {
+        // Allowed guest paths
+        assert!(validate_guest_path("/data").is_ok());
+        assert!(validate_guest_path("/var/lib/app/").is_ok());
+        assert!(validate_guest_path("/procfs").is_ok());
+        assert!(validate_guest_path("/system").is_ok());
+        assert!(validate_guest_path("/app/./data").is_ok());
+
+        // Relative, traversing and protected guest paths
+        for path in [
+            "data",
+            "./data",
+            "/data/../etc",
+            "/..",
+            "/",
+            "//",
+            "/proc",
+            "/proc/self",
+            "/sys/",
+            "//sys/kernel",
+            "/./proc",
+        ] {
+            assert!(
+                matches!(
+                    validate_guest_path(path),
+                    Err(MicrosandboxError::InvalidGuestPath { .. })
+                ),
+                "{} should be rejected",
+                path
+            );
+        }
+
+        // Parsing checks the guest path of every format
+        assert!("/host:/data/../etc".parse::<PathPair>().is_err());
+        assert!("/host:proc".parse::<PathPair>().is_err());
+        assert!("/host:/".parse::<PathPair>().is_err());
+        assert!("/proc".parse::<PathPair>().is_err());
+        assert!("volume:cache:/sys/fs".parse::<PathPair>().is_err());
+        assert!("../host:/data".parse::<PathPair>().is_ok());
+    }
+
     #[test]
     fn test_path_pair_display() {
         // Test same paths
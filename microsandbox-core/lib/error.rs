@@ -1,9 +1,9 @@
-use microsandbox_utils::MicrosandboxUtilsError;
+use microsandbox_utils::{MicrosandboxUtilsError, MICROSANDBOX_HOME_ENV_VAR, OCI_DB_FILENAME};
 use sqlx::migrate::MigrateError;
 use std::{
     error::Error,
     fmt::{self, Display},
-    path::{PathBuf, StripPrefixError},
+    path::{Path, PathBuf, StripPrefixError},
     time::SystemTimeError,
 };
 use thiserror::Error;
@@ -44,6 +44,30 @@ pub enum MicrosandboxError {
     #[error("database error: {0}")]
     Database(#[from] sqlx::Error),
 
+    /// An error that occurred when a database file failed its integrity check.
+    #[error("database {} is corrupted: {problems}{}", .path.display(), repair_hint(.path))]
+    DatabaseCorrupted {
+        /// The path of the database file
+        path: PathBuf,
+
+        /// The problems the integrity check found
+        problems: String,
+    },
+
+    /// An error that occurred when the microsandbox home directory cannot be created or written to.
+    #[error(
+        "microsandbox home {} is not writable: {reason}; fix its permissions or set {} to a writable directory",
+        .path.display(),
+        MICROSANDBOX_HOME_ENV_VAR
+    )]
+    HomeNotWritable {
+        /// The path of the home directory
+        path: PathBuf,
+
+        /// Why the home directory could not be written to
+        reason: String,
+    },
+
     /// An error that occurred when a manifest was not found.
     #[error("manifest not found")]
     ManifestNotFound,
@@ -60,10 +84,80 @@ pub enum MicrosandboxError {
     #[error("image layer download failed: {0}")]
     ImageLayerDownloadFailed(String),
 
+    /// An error that occurred when building an image with the local Docker daemon failed.
+    #[error("image build failed: {0}")]
+    ImageBuildFailed(String),
+
+    /// An error that occurred when an image could not be pulled from any of the configured registries.
+    #[error("failed to pull image {image} from any registry: {}", format_registry_failures(.failures))]
+    RegistryChainExhausted {
+        /// The image that was being pulled
+        image: String,
+
+        /// Each registry that was tried, in order, with the reason it failed
+        failures: Vec<RegistryPullFailure>,
+    },
+
+    /// An error that occurred when none of the configured registries has the requested image.
+    #[error("image {image} not found in any registry (tried: {})", .registries.join(", "))]
+    ImageNotFound {
+        /// The image that was being pulled
+        image: String,
+
+        /// The registries that were tried, in order
+        registries: Vec<String>,
+    },
+
+    /// An error that occurred when an image has more or larger layers than pulls allow.
+    #[error("image {image} is too large to pull: {reason}")]
+    PullLimitExceeded {
+        /// The image that was being pulled
+        image: String,
+
+        /// The limit the image exceeds
+        reason: String,
+    },
+
+    /// An error that occurred when an image has no manifest for the requested platform.
+    #[error("image {image} has no manifest for platform {platform} (available: {})", .available.join(", "))]
+    PlatformNotFound {
+        /// The image that was being pulled
+        image: String,
+
+        /// The requested platform, e.g. `linux/arm64`
+        platform: String,
+
+        /// The platforms the image is available for
+        available: Vec<String>,
+    },
+
+    /// An error that occurred when an image reference names a registry images cannot be pulled from.
+    #[error("image {image} uses unsupported registry {registry} (supported: {})", .supported.join(", "))]
+    UnsupportedRegistry {
+        /// The image that was requested
+        image: String,
+
+        /// The registry named by the image reference
+        registry: String,
+
+        /// The registries images can be pulled from
+        supported: Vec<String>,
+    },
+
     /// An error that occurred when an invalid path pair was used.
     #[error("invalid path pair: {0}")]
     InvalidPathPair(String),
 
+    /// An error that occurred when a volume was to be mounted at a guest path that is not allowed.
+    #[error("invalid guest path {path}: {reason}")]
+    InvalidGuestPath {
+        /// The guest path the volume was to be mounted at
+        path: String,
+
+        /// Why the guest path is not allowed
+        reason: String,
+    },
+
     /// An error that occurred when an invalid port pair was used.
     #[error("invalid port pair: {0}")]
     InvalidPortPair(String),
@@ -72,6 +166,10 @@ pub enum MicrosandboxError {
     #[error("invalid environment variable pair: {0}")]
     InvalidEnvPair(String),
 
+    /// An error that occurred when an invalid hosts file entry was used.
+    #[error("invalid host entry: {0}")]
+    InvalidHostEntry(String),
+
     /// An error that occurred when an invalid MicroVm configuration was used.
     #[error("invalid MicroVm configuration: {0}")]
     InvalidMicroVMConfig(InvalidMicroVMConfigError),
@@ -145,6 +243,10 @@ pub enum MicrosandboxError {
     #[error("failed to start VM: {0}")]
     StartVmFailed(i32),
 
+    /// An error that occurred when the host has no usable hypervisor to run MicroVms on
+    #[error("virtualization is unavailable: {0}")]
+    VirtualizationUnavailable(String),
+
     /// An error that occurred when waiting for a process to exit
     #[error("process wait error: {0}")]
     ProcessWaitError(String),
@@ -196,6 +298,38 @@ pub enum MicrosandboxError {
         layer: String,
     },
 
+    /// An error that occurred when a layer could not be extracted because the process kept
+    /// running out of file descriptors.
+    #[error(
+        "ran out of file descriptors extracting layer {layer} after {attempts} attempts; \
+         raise the open file limit (e.g. `ulimit -n 65536`) and try again"
+    )]
+    LayerExtractionFdExhausted {
+        /// The layer being extracted
+        layer: String,
+        /// The number of times the extraction was attempted
+        attempts: u32,
+    },
+
+    /// An error that occurred when the disk filled up while a layer was being extracted.
+    ///
+    /// The partial extraction is removed before this is returned.
+    #[error(
+        "disk full extracting layer {layer} to {}{}; free up space and try again",
+        .path.display(),
+        format_disk_space(*.required, *.available)
+    )]
+    DiskFull {
+        /// The layer being extracted
+        layer: String,
+        /// The directory the layer was being extracted to
+        path: PathBuf,
+        /// The estimated size of the extracted layer in bytes, if it could be determined
+        required: Option<u64>,
+        /// The space available to the extraction directory in bytes, if it could be determined
+        available: Option<u64>,
+    },
+
     /// An error that occurred when a configuration file was not found
     #[error("configuration file not found: {0}")]
     ConfigNotFound(String),
@@ -232,6 +366,10 @@ pub enum MicrosandboxError {
     #[error("log not found: {0}")]
     LogNotFound(String),
 
+    /// An error that occurred when a sandbox has no resolved configuration recorded
+    #[error("resolved config not found: {0}")]
+    ResolvedConfigNotFound(String),
+
     /// An error that occurred when a pager error occurred
     #[error("pager error: {0}")]
     PagerError(String),
@@ -299,6 +437,22 @@ pub enum MicrosandboxError {
     /// An error that occurred when a command was not found.
     #[error("command not found: {0}")]
     CommandNotFound(String),
+
+    /// An error that occurred when an invalid volume name was used.
+    #[error("invalid volume name: {0}")]
+    InvalidVolumeName(String),
+
+    /// An error that occurred when a named volume was not found.
+    #[error("volume not found: {0}")]
+    VolumeNotFound(String),
+
+    /// An error that occurred when creating a named volume that already exists.
+    #[error("volume already exists: {0}")]
+    VolumeExists(String),
+
+    /// An error that occurred when removing a named volume that running sandboxes use.
+    #[error("volume '{0}' is in use by: {users}", users = .1.join(", "))]
+    VolumeInUse(String, Vec<String>),
 }
 
 /// An error that occurred when an invalid MicroVm configuration was used.
@@ -329,6 +483,16 @@ pub enum InvalidMicroVMConfigError {
     ConflictingGuestPaths(String, String),
 }
 
+/// A registry that an image could not be pulled from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryPullFailure {
+    /// The URL of the registry
+    pub registry: String,
+
+    /// Why the pull from this registry failed
+    pub reason: String,
+}
+
 /// An error that can represent any error.
 #[derive(Debug)]
 pub struct AnyError {
@@ -368,6 +532,37 @@ pub fn Ok<T>(value: T) -> MicrosandboxResult<T> {
     Result::Ok(value)
 }
 
+/// Formats the registries a pull failed on as `registry (reason)` entries.
+fn format_registry_failures(failures: &[RegistryPullFailure]) -> String {
+    failures
+        .iter()
+        .map(|failure| format!("{} ({})", failure.registry, failure.reason))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Formats the space a layer needs and the space available, as far as they are known.
+fn format_disk_space(required: Option<u64>, available: Option<u64>) -> String {
+    match (required, available) {
+        (Some(required), Some(available)) => format!(
+            " (needs about {} bytes, {} bytes available)",
+            required, available
+        ),
+        (Some(required), None) => format!(" (needs about {} bytes)", required),
+        (None, Some(available)) => format!(" ({} bytes available)", available),
+        (None, None) => String::new(),
+    }
+}
+
+/// Suggests how to recover a corrupted database, if it can be rebuilt.
+fn repair_hint(path: &Path) -> &'static str {
+    if path.file_name().is_some_and(|name| name == OCI_DB_FILENAME) {
+        "; run `msb image repair` to rebuild it"
+    } else {
+        ""
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Trait Implementations
 //--------------------------------------------------------------------------------------------------
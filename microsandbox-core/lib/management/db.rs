@@ -5,7 +5,11 @@
 //! migrations, and operations for storing and retrieving container images, layers,
 //! and sandbox configurations.
 
-use std::path::Path;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+};
 
 use chrono::{DateTime, NaiveDateTime, Utc};
 use oci_spec::image::{ImageConfiguration, ImageIndex, ImageManifest, MediaType, Platform};
@@ -15,7 +19,7 @@ use tokio::fs;
 use crate::{
     models::{Config, Image, Index, Layer, Manifest, Sandbox},
     runtime::SANDBOX_STATUS_RUNNING,
-    MicrosandboxResult,
+    MicrosandboxError, MicrosandboxResult,
 };
 
 //--------------------------------------------------------------------------------------------------
@@ -28,12 +32,36 @@ pub static SANDBOX_DB_MIGRATOR: Migrator = sqlx::migrate!("lib/migrations/sandbo
 /// Migrator for the OCI database
 pub static OCI_DB_MIGRATOR: Migrator = sqlx::migrate!("lib/migrations/oci");
 
+/// The tables of the OCI database, parents before the tables referencing them
+const OCI_TABLES: [&str; 6] = [
+    "images",
+    "indexes",
+    "manifests",
+    "configs",
+    "layers",
+    "manifest_layers",
+];
+
+/// SQLite's primary result code for a database file whose contents are damaged
+const SQLITE_CORRUPT: i32 = 11;
+
+/// SQLite's primary result code for a file that is not a database
+const SQLITE_NOTADB: i32 = 26;
+
+/// Database files that passed the integrity check in this process
+static CHECKED_DATABASES: LazyLock<Mutex<HashSet<PathBuf>>> =
+    LazyLock::new(|| Mutex::new(HashSet::new()));
+
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
 
 /// Initializes a new SQLite database if it doesn't already exist at the specified path.
 ///
+/// The first time a process opens a database, its integrity is checked before any migration
+/// is run, so that a corrupted database fails with [`MicrosandboxError::DatabaseCorrupted`]
+/// instead of an opaque query error.
+///
 /// ## Arguments
 ///
 /// * `db_path` - Path where the SQLite database file should be created
@@ -58,7 +86,34 @@ pub async fn initialize(
     let pool = SqlitePoolOptions::new()
         .max_connections(5)
         .connect(&format!("sqlite://{}?mode=rwc", db_path.display()))
-        .await?;
+        .await
+        .map_err(|e| {
+            if is_corruption(&e) {
+                MicrosandboxError::DatabaseCorrupted {
+                    path: db_path.to_path_buf(),
+                    problems: e.to_string(),
+                }
+            } else {
+                e.into()
+            }
+        })?;
+
+    // Check the database before migrations write to it
+    let checked = CHECKED_DATABASES.lock().unwrap().contains(db_path);
+    if !checked {
+        let problems = check_integrity(&pool).await?;
+        if !problems.is_empty() {
+            pool.close().await;
+            return Err(MicrosandboxError::DatabaseCorrupted {
+                path: db_path.to_path_buf(),
+                problems: problems.join("; "),
+            });
+        }
+        CHECKED_DATABASES
+            .lock()
+            .unwrap()
+            .insert(db_path.to_path_buf());
+    }
 
     // Run migrations
     migrator.run(&pool).await?;
@@ -99,6 +154,36 @@ pub async fn get_or_create_pool(
     initialize(&db_path, migrator).await
 }
 
+/// Runs SQLite's integrity check on a database and returns the problems it found.
+///
+/// An empty list means the database is intact. A database too damaged to be checked at all is
+/// reported as a single problem rather than an error.
+pub async fn check_integrity(pool: &Pool<Sqlite>) -> MicrosandboxResult<Vec<String>> {
+    let records = match sqlx::query("PRAGMA integrity_check").fetch_all(pool).await {
+        Ok(records) => records,
+        Err(e) if is_corruption(&e) => return Ok(vec![e.to_string()]),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(records
+        .into_iter()
+        .map(|row| row.get::<String, _>(0))
+        .filter(|result| result != "ok")
+        .collect())
+}
+
+/// Checks whether SQLite failed because the database file is damaged or not a database.
+pub(crate) fn is_corruption(error: &sqlx::Error) -> bool {
+    let sqlx::Error::Database(error) = error else {
+        return false;
+    };
+
+    error
+        .code()
+        .and_then(|code| code.parse::<i32>().ok())
+        .is_some_and(|code| matches!(code & 0xff, SQLITE_CORRUPT | SQLITE_NOTADB))
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions: Sandboxes
 //--------------------------------------------------------------------------------------------------
@@ -283,6 +368,42 @@ pub(crate) async fn get_running_config_sandboxes(
         .collect())
 }
 
+/// Gets all running sandboxes in the database, regardless of their config file
+pub(crate) async fn get_running_sandboxes(pool: &Pool<Sqlite>) -> MicrosandboxResult<Vec<Sandbox>> {
+    let records = sqlx::query(
+        r#"
+        SELECT id, name, config_file, config_last_modified, status,
+               supervisor_pid, microvm_pid, rootfs_paths,
+               created_at, modified_at
+        FROM sandboxes
+        WHERE status = ?
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(SANDBOX_STATUS_RUNNING)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|row| Sandbox {
+            id: row.get("id"),
+            name: row.get("name"),
+            config_file: row.get("config_file"),
+            config_last_modified: row
+                .get::<String, _>("config_last_modified")
+                .parse::<DateTime<Utc>>()
+                .unwrap(),
+            status: row.get("status"),
+            supervisor_pid: row.get("supervisor_pid"),
+            microvm_pid: row.get("microvm_pid"),
+            rootfs_paths: row.get("rootfs_paths"),
+            created_at: parse_sqlite_datetime(&row.get::<String, _>("created_at")),
+            modified_at: parse_sqlite_datetime(&row.get::<String, _>("modified_at")),
+        })
+        .collect())
+}
+
 /// Deletes a sandbox from the database by name and config file.
 pub(crate) async fn delete_sandbox(
     pool: &Pool<Sqlite>,
@@ -697,6 +818,36 @@ pub(crate) async fn image_exists(pool: &Pool<Sqlite>, reference: &str) -> Micros
     Ok(record.get::<i64, _>("count") > 0)
 }
 
+/// Gets the platform an image was last pulled for, if it was recorded.
+pub(crate) async fn get_image_platform(
+    pool: &Pool<Sqlite>,
+    reference: &str,
+) -> MicrosandboxResult<Option<Platform>> {
+    let record = sqlx::query(
+        r#"
+        SELECT idx.platform_os, idx.platform_arch, idx.platform_variant
+        FROM indexes idx
+        JOIN images i ON idx.image_id = i.id
+        WHERE i.reference = ?
+        ORDER BY idx.id DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(reference)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.and_then(|row| {
+        let os = row.get::<Option<String>, _>("platform_os")?;
+        let arch = row.get::<Option<String>, _>("platform_arch")?;
+        let mut platform = Platform::default();
+        platform.set_os(os.as_str().into());
+        platform.set_architecture(arch.as_str().into());
+        platform.set_variant(row.get::<Option<String>, _>("platform_variant"));
+        Some(platform)
+    }))
+}
+
 /// Gets the configuration for an image from the database.
 ///
 /// This function retrieves the configuration details for a specified image reference.
@@ -789,6 +940,76 @@ pub(crate) async fn save_or_update_image(
     }
 }
 
+/// Updates the size of a layer with the size computed from its extracted directory.
+pub(crate) async fn update_layer_size(
+    pool: &Pool<Sqlite>,
+    digest: &str,
+    size_bytes: i64,
+) -> MicrosandboxResult<()> {
+    sqlx::query(
+        r#"
+        UPDATE layers
+        SET size_bytes = ?,
+            size_computed_at = CURRENT_TIMESTAMP,
+            modified_at = CURRENT_TIMESTAMP
+        WHERE digest = ?
+        "#,
+    )
+    .bind(size_bytes)
+    .bind(digest)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Gets the digests of all layers whose size was recorded as zero.
+pub(crate) async fn get_zero_size_layer_digests(
+    pool: &Pool<Sqlite>,
+) -> MicrosandboxResult<Vec<String>> {
+    let records = sqlx::query(
+        r#"
+        SELECT digest
+        FROM layers
+        WHERE size_bytes = 0
+        ORDER BY id ASC
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|row| row.get::<String, _>("digest"))
+        .collect())
+}
+
+/// Recomputes the size of every zero-size image as the sum of its layer sizes.
+///
+/// ## Returns
+///
+/// Returns the number of images that were updated
+pub(crate) async fn backfill_image_sizes(pool: &Pool<Sqlite>) -> MicrosandboxResult<u64> {
+    let result = sqlx::query(
+        r#"
+        UPDATE images
+        SET size_bytes = (
+                SELECT COALESCE(SUM(l.size_bytes), 0)
+                FROM layers l
+                JOIN manifest_layers ml ON l.id = ml.layer_id
+                JOIN manifests m ON ml.manifest_id = m.id
+                WHERE m.image_id = images.id
+            ),
+            modified_at = CURRENT_TIMESTAMP
+        WHERE size_bytes = 0
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
 /// Gets layers from the database by their digest values.
 ///
 /// This function retrieves layer information for a list of digest values without
@@ -848,6 +1069,93 @@ pub(crate) async fn get_layers_by_digest(
         .collect())
 }
 
+/// Copies the rows that can still be read from a corrupted OCI database into a fresh one.
+///
+/// Tables that cannot be read are skipped, and rows left without the records they belong to are
+/// dropped afterwards. Images that end up without any layers are removed too, so that they are
+/// pulled again rather than taken as complete.
+///
+/// ## Returns
+///
+/// Returns the number of images that were recovered
+pub(crate) async fn salvage_oci_db(
+    pool: &Pool<Sqlite>,
+    corrupted_db_path: &Path,
+) -> MicrosandboxResult<u64> {
+    // Attached databases and pragmas only apply to one connection
+    let mut conn = pool.acquire().await?;
+
+    // Rows are copied table by table, so their parents may not be there yet
+    sqlx::query("PRAGMA foreign_keys = OFF")
+        .execute(&mut *conn)
+        .await?;
+
+    let attached = sqlx::query("ATTACH DATABASE ? AS corrupted")
+        .bind(corrupted_db_path.to_string_lossy())
+        .execute(&mut *conn)
+        .await;
+
+    match attached {
+        Ok(_) => {
+            for table in OCI_TABLES {
+                match salvage_table(&mut conn, table).await {
+                    Ok(rows) => tracing::info!("salvaged {} rows of {}", rows, table),
+                    Err(e) => tracing::warn!("could not salvage {}: {}", table, e),
+                }
+            }
+            sqlx::query("DETACH DATABASE corrupted")
+                .execute(&mut *conn)
+                .await?;
+        }
+        Err(e) => tracing::warn!(
+            "could not open {} to salvage it: {}",
+            corrupted_db_path.display(),
+            e
+        ),
+    }
+
+    // Drop rows whose parents were lost, children first
+    sqlx::query(
+        r#"
+        DELETE FROM manifest_layers
+        WHERE manifest_id NOT IN (SELECT id FROM manifests)
+           OR layer_id NOT IN (SELECT id FROM layers);
+        DELETE FROM configs WHERE manifest_id NOT IN (SELECT id FROM manifests);
+        DELETE FROM manifests
+        WHERE image_id NOT IN (SELECT id FROM images)
+           OR (index_id IS NOT NULL AND index_id NOT IN (SELECT id FROM indexes));
+        DELETE FROM indexes WHERE image_id NOT IN (SELECT id FROM images);
+        "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query("PRAGMA foreign_keys = ON")
+        .execute(&mut *conn)
+        .await?;
+
+    // Removing an image cascades to its indexes, manifests and configs
+    sqlx::query(
+        r#"
+        DELETE FROM images
+        WHERE id NOT IN (
+            SELECT m.image_id
+            FROM manifests m
+            JOIN manifest_layers ml ON m.id = ml.manifest_id
+        )
+        "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let images = sqlx::query("SELECT COUNT(*) FROM images")
+        .fetch_one(&mut *conn)
+        .await?
+        .get::<i64, _>(0);
+
+    Ok(images as u64)
+}
+
 /// Gets all layer digests for an image manifest from the database.
 ///
 /// This function retrieves just the digest strings for all layers associated with a specific
@@ -975,6 +1283,117 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_backfill_zero_layer_and_image_sizes() -> MicrosandboxResult<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("test_oci.db");
+        let pool = initialize(&db_path, &OCI_DB_MIGRATOR).await?;
+
+        // Simulate a local Docker import, which records zero sizes
+        let image_id = save_image(&pool, "local/app:latest", 0).await?;
+        let manifest_id = sqlx::query(
+            r#"
+            INSERT INTO manifests (image_id, schema_version, media_type)
+            VALUES (?, 2, 'application/vnd.oci.image.manifest.v1+json')
+            RETURNING id
+            "#,
+        )
+        .bind(image_id)
+        .fetch_one(&pool)
+        .await?
+        .get::<i64, _>("id");
+
+        for digest in ["sha256:aaa", "sha256:bbb"] {
+            let layer_id = save_layer(&pool, "application/x-tar", digest, 0, digest).await?;
+            save_manifest_layer(&pool, manifest_id, layer_id).await?;
+        }
+
+        assert_eq!(
+            get_zero_size_layer_digests(&pool).await?,
+            vec!["sha256:aaa".to_string(), "sha256:bbb".to_string()]
+        );
+
+        update_layer_size(&pool, "sha256:aaa", 100).await?;
+        update_layer_size(&pool, "sha256:bbb", 250).await?;
+        assert!(get_zero_size_layer_digests(&pool).await?.is_empty());
+
+        assert_eq!(backfill_image_sizes(&pool).await?, 1);
+        let size = sqlx::query("SELECT size_bytes FROM images WHERE id = ?")
+            .bind(image_id)
+            .fetch_one(&pool)
+            .await?
+            .get::<i64, _>("size_bytes");
+        assert_eq!(size, 350);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_initialize_detects_corrupted_db() -> MicrosandboxResult<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("oci.db");
+        let pool = initialize(&db_path, &OCI_DB_MIGRATOR).await?;
+        assert!(check_integrity(&pool).await?.is_empty());
+        pool.close().await;
+
+        // Overwrite the file header, as an interrupted write to it would
+        let mut contents = fs::read(&db_path).await?;
+        contents[..100].fill(0xff);
+        fs::write(&db_path, contents).await?;
+
+        // A new process checks the database again
+        CHECKED_DATABASES.lock().unwrap().remove(&db_path);
+        let error = initialize(&db_path, &OCI_DB_MIGRATOR).await.unwrap_err();
+        assert!(matches!(error, MicrosandboxError::DatabaseCorrupted { .. }));
+        assert!(error.to_string().contains("msb image repair"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_salvage_oci_db() -> MicrosandboxResult<()> {
+        let temp_dir = tempdir()?;
+        let old_db_path = temp_dir.path().join("old.db");
+        let old_pool = initialize(&old_db_path, &OCI_DB_MIGRATOR).await?;
+
+        // One complete image, and one whose layers were never recorded
+        let image_id = save_image(&old_pool, "library/alpine:latest", 100).await?;
+        let manifest_id = sqlx::query(
+            r#"
+            INSERT INTO manifests (image_id, schema_version, media_type)
+            VALUES (?, 2, 'application/vnd.oci.image.manifest.v1+json')
+            RETURNING id
+            "#,
+        )
+        .bind(image_id)
+        .fetch_one(&old_pool)
+        .await?
+        .get::<i64, _>("id");
+        let layer_id = save_layer(
+            &old_pool,
+            "application/x-tar",
+            "sha256:aaa",
+            100,
+            "sha256:aaa",
+        )
+        .await?;
+        save_manifest_layer(&old_pool, manifest_id, layer_id).await?;
+        save_image(&old_pool, "library/busybox:latest", 0).await?;
+        old_pool.close().await;
+
+        let pool = initialize(temp_dir.path().join("new.db"), &OCI_DB_MIGRATOR).await?;
+        assert_eq!(salvage_oci_db(&pool, &old_db_path).await?, 1);
+
+        assert!(image_exists(&pool, "library/alpine:latest").await?);
+        assert!(!image_exists(&pool, "library/busybox:latest").await?);
+        assert_eq!(
+            get_image_layer_digests(&pool, "library/alpine:latest").await?,
+            vec!["sha256:aaa".to_string()]
+        );
+
+        Ok(())
+    }
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -988,6 +1407,39 @@ fn parse_sqlite_datetime(s: &str) -> DateTime<Utc> {
     DateTime::from_naive_utc_and_offset(naive_dt, Utc)
 }
 
+/// Copies the readable rows of a table of the attached `corrupted` database into the main one.
+///
+/// Only the columns both databases have are copied, in case the corrupted one was created by an
+/// older version.
+async fn salvage_table(conn: &mut sqlx::SqliteConnection, table: &str) -> MicrosandboxResult<u64> {
+    let mut columns = Vec::new();
+    for schema in ["main", "corrupted"] {
+        let names: HashSet<String> =
+            sqlx::query(&format!("PRAGMA {}.table_info({})", schema, table))
+                .fetch_all(&mut *conn)
+                .await?
+                .into_iter()
+                .map(|row| row.get::<String, _>("name"))
+                .collect();
+        columns.push(names);
+    }
+    let mut columns: Vec<&String> = columns[0].intersection(&columns[1]).collect();
+    columns.sort();
+
+    let columns = columns
+        .into_iter()
+        .map(|column| column.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let result = sqlx::query(&format!(
+        "INSERT OR IGNORE INTO main.{table} ({columns}) SELECT {columns} FROM corrupted.{table}",
+    ))
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
 /// Sometimes the json columns in the database can have literal "null" values.
 /// This function converts those to None.
 fn null_to_none(value: Option<String>) -> Option<String> {
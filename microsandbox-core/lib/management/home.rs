@@ -6,18 +6,21 @@
 
 use crate::{
     config::{EnvPair, Microsandbox, PathPair, PortPair, ReferenceOrPath, Sandbox},
-    management::{config, db, image, menv},
+    management::{config, db, image, menv, volume},
     oci::Reference,
     MicrosandboxError, MicrosandboxResult,
 };
 use microsandbox_utils::{
     env, path::INSTALLS_SUBDIR, MICROSANDBOX_CONFIG_FILENAME, MICROSANDBOX_HOME_DIR,
-    OCI_DB_FILENAME, XDG_BIN_DIR, XDG_HOME_DIR,
+    OCI_DB_FILENAME, VOLUMES_SUBDIR, XDG_BIN_DIR, XDG_HOME_DIR,
 };
 
 #[cfg(feature = "cli")]
 use microsandbox_utils::term;
-use std::os::unix::fs::PermissionsExt;
+use std::{
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
 use tokio::fs;
 use typed_path::Utf8UnixPathBuf;
 
@@ -31,45 +34,99 @@ const REMOVE_HOME_DIR_MSG: &str = "Remove microsandbox home";
 #[cfg(feature = "cli")]
 const INSTALL_SANDBOX_MSG: &str = "Install sandbox";
 
+/// The file written and removed to check that the home directory is writable
+const WRITE_CHECK_FILENAME: &str = ".msb-write-check";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// What cleaning the home directory does with named volumes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VolumeCleanPolicy {
+    /// Keep all named volumes
+    #[default]
+    Keep,
+
+    /// Remove the named volumes that no running sandbox uses along with everything else
+    Include,
+
+    /// Remove only the named volumes that no running sandbox uses
+    Only,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
 
+/// Ensure the global microsandbox home directory exists and is writable
+///
+/// Creates the home directory if it is missing, then writes and removes a file in it. Run at
+/// startup, so that a home on a read-only mount or without permissions fails with the path and
+/// a fix instead of a low-level error from whichever operation first touches it.
+///
+/// ## Returns
+/// The path of the home directory, or `MicrosandboxError::HomeNotWritable` if it cannot be
+/// created or written to
+///
+/// ## Example
+/// ```no_run
+/// use microsandbox_core::management::home;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let home_path = home::ensure_writable().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn ensure_writable() -> MicrosandboxResult<PathBuf> {
+    let home_path = env::get_microsandbox_home_path();
+    check_writable(&home_path).await?;
+    Ok(home_path)
+}
+
 /// Clean up the global microsandbox home directory
 ///
-/// This removes the entire microsandbox home directory and all its contents, effectively
-/// cleaning up all global microsandbox data including cached images, layers, and databases.
+/// This removes the contents of the microsandbox home directory, cleaning up global microsandbox
+/// data such as cached images, layers, and databases. Named volumes hold data that outlives
+/// sandboxes, so they are kept unless `volumes` says otherwise, and volumes that a running
+/// sandbox has mounted are never removed.
+///
+/// Without `force`, what will be removed is printed before it is removed.
 ///
 /// ## Arguments
 /// * `force` - Whether to force cleaning even if configuration files exist
+/// * `volumes` - What to do with named volumes
 ///
 /// ## Example
 /// ```no_run
-/// use microsandbox_core::management::home;
+/// use microsandbox_core::management::home::{self, VolumeCleanPolicy};
 ///
 /// # async fn example() -> anyhow::Result<()> {
-/// // Clean with force = true to remove everything regardless of configs
-/// home::clean(true).await?;
+/// // Clean with force = true to remove everything but named volumes regardless of configs
+/// home::clean(true, VolumeCleanPolicy::Keep).await?;
 /// # Ok(())
 /// # }
 /// ```
-pub async fn clean(force: bool) -> MicrosandboxResult<()> {
+pub async fn clean(force: bool, volumes: VolumeCleanPolicy) -> MicrosandboxResult<()> {
     // Get the microsandbox home path from environment or default
     let home_path = env::get_microsandbox_home_path();
     let installs_path = home_path.join(INSTALLS_SUBDIR);
 
-    #[cfg(feature = "cli")]
-    let remove_home_dir_sp = term::create_spinner(REMOVE_HOME_DIR_MSG.to_string(), None, None);
+    // Check if home directory exists
+    if !home_path.exists() {
+        tracing::info!(
+            "No microsandbox home directory found at {}",
+            home_path.display()
+        );
+        return Ok(());
+    }
 
     // Check if installs directory exists and has config files
-    if installs_path.exists() {
+    if installs_path.exists() && volumes != VolumeCleanPolicy::Only {
         let config_path = installs_path.join(MICROSANDBOX_CONFIG_FILENAME);
 
         // If config file exists and force is false, don't clean
         if config_path.exists() && !force {
-            #[cfg(feature = "cli")]
-            term::finish_with_error(&remove_home_dir_sp);
-
             #[cfg(feature = "cli")]
             println!(
                 "Configuration file exists at {}. Use {} to clean the home directory",
@@ -85,17 +142,71 @@ pub async fn clean(force: bool) -> MicrosandboxResult<()> {
         }
     }
 
-    // Check if home directory exists
-    if home_path.exists() {
-        // Remove the home directory and all its contents
-        fs::remove_dir_all(&home_path).await?;
+    // Work out what to remove, leaving out volumes that running sandboxes use
+    let mut targets = match volumes {
+        VolumeCleanPolicy::Only => Vec::new(),
+        _ => clean_targets(&home_path).await?,
+    };
+
+    if volumes != VolumeCleanPolicy::Keep {
+        for volume_info in volume::list().await? {
+            if volume_info.users.is_empty() {
+                targets.push(home_path.join(VOLUMES_SUBDIR).join(&volume_info.name));
+            } else {
+                #[cfg(feature = "cli")]
+                println!(
+                    "Keeping volume {} in use by {}",
+                    console::style(&volume_info.name).yellow(),
+                    volume_info.users.join(", ")
+                );
+
+                tracing::warn!(
+                    "keeping volume {} in use by {}",
+                    volume_info.name,
+                    volume_info.users.join(", ")
+                );
+            }
+        }
+    }
+
+    if targets.is_empty() {
+        tracing::info!("Nothing to clean in {}", home_path.display());
+        return Ok(());
+    }
+
+    if !force {
+        #[cfg(feature = "cli")]
+        {
+            println!("Removing:");
+            for target in &targets {
+                println!("  {}", target.display());
+            }
+        }
+
         tracing::info!(
-            "Removed microsandbox home directory at {}",
+            "removing {} paths from {}",
+            targets.len(),
             home_path.display()
         );
-    } else {
+    }
+
+    #[cfg(feature = "cli")]
+    let remove_home_dir_sp = term::create_spinner(REMOVE_HOME_DIR_MSG.to_string(), None, None);
+
+    for target in &targets {
+        if fs::symlink_metadata(target).await?.is_dir() {
+            fs::remove_dir_all(target).await?;
+        } else {
+            fs::remove_file(target).await?;
+        }
+        tracing::info!("Removed {}", target.display());
+    }
+
+    // Remove the volumes directory and the home directory itself if nothing is left in them
+    let _ = fs::remove_dir(home_path.join(VOLUMES_SUBDIR)).await;
+    if fs::remove_dir(&home_path).await.is_ok() {
         tracing::info!(
-            "No microsandbox home directory found at {}",
+            "Removed microsandbox home directory at {}",
             home_path.display()
         );
     }
@@ -206,8 +317,12 @@ pub async fn install(
     // This creates necessary directories and the sandbox database
     menv::initialize(Some(installs_path.clone())).await?;
 
-    // Parse the volume, port, and env strings into their respective types
-    let volumes: Vec<PathPair> = volumes.into_iter().filter_map(|v| v.parse().ok()).collect();
+    // Parse the volume, port, and env strings into their respective types. Volumes are rejected
+    // rather than dropped, as a dropped volume could hide a guest path that is not allowed
+    let volumes = volumes
+        .into_iter()
+        .map(|v| v.parse())
+        .collect::<MicrosandboxResult<Vec<PathPair>>>()?;
     let ports: Vec<PortPair> = ports.into_iter().filter_map(|p| p.parse().ok()).collect();
     let envs: Vec<EnvPair> = envs.into_iter().filter_map(|e| e.parse().ok()).collect();
 
@@ -249,7 +364,14 @@ pub async fn install(
     // Apply image configuration defaults if enabled
     if use_image_defaults {
         // Pull the image from the registry if not already pulled
-        image::pull(image.clone(), true, None).await?;
+        image::pull(
+            image.clone(),
+            true,
+            None,
+            None,
+            image::default_pull_progress(),
+        )
+        .await?;
 
         // Get the OCI database path and create a connection pool
         let db_path = home_path.join(OCI_DB_FILENAME);
@@ -381,6 +503,36 @@ pub async fn uninstall(script_name: &str) -> MicrosandboxResult<()> {
 // Functions: Helpers
 //--------------------------------------------------------------------------------------------------
 
+/// Returns the entries of the home directory that cleaning removes, apart from named volumes
+async fn clean_targets(home_path: &Path) -> MicrosandboxResult<Vec<PathBuf>> {
+    let mut targets = Vec::new();
+    let mut entries = fs::read_dir(home_path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_name() != VOLUMES_SUBDIR {
+            targets.push(entry.path());
+        }
+    }
+
+    targets.sort();
+    Ok(targets)
+}
+
+/// Check that a directory exists or can be created, and that files can be written to it
+async fn check_writable(path: &Path) -> MicrosandboxResult<()> {
+    let not_writable = |e: std::io::Error| MicrosandboxError::HomeNotWritable {
+        path: path.to_path_buf(),
+        reason: e.to_string(),
+    };
+
+    fs::create_dir_all(path).await.map_err(not_writable)?;
+
+    let check_path = path.join(format!("{}.{}", WRITE_CHECK_FILENAME, std::process::id()));
+    fs::write(&check_path, b"").await.map_err(not_writable)?;
+    fs::remove_file(&check_path).await.map_err(not_writable)?;
+
+    Ok(())
+}
+
 /// Check if a command with the given name exists in the system PATH
 ///
 /// This function uses `which` to check if a command exists in any directory
@@ -461,3 +613,46 @@ fi
         run_command
     )
 }
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check_writable() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        // A missing home is created and left without the check file
+        let home_path = temp_dir.path().join("home");
+        check_writable(&home_path).await.unwrap();
+        assert!(home_path.is_dir());
+        assert_eq!(std::fs::read_dir(&home_path).unwrap().count(), 0);
+
+        // A home that cannot be created reports its path
+        let blocker = temp_dir.path().join("file");
+        std::fs::write(&blocker, "").unwrap();
+        let home_path = blocker.join("home");
+        match check_writable(&home_path).await {
+            Err(MicrosandboxError::HomeNotWritable { path, .. }) => assert_eq!(path, home_path),
+            other => panic!("expected HomeNotWritable, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clean_targets_skip_volumes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let home_path = temp_dir.path();
+        std::fs::create_dir_all(home_path.join("layers")).unwrap();
+        std::fs::create_dir_all(home_path.join(VOLUMES_SUBDIR).join("cache")).unwrap();
+        std::fs::write(home_path.join(OCI_DB_FILENAME), "").unwrap();
+
+        assert_eq!(
+            clean_targets(home_path).await.unwrap(),
+            [home_path.join("layers"), home_path.join(OCI_DB_FILENAME)]
+        );
+    }
+}
@@ -2,16 +2,24 @@
 //!
 //! This module provides functionality for managing container images from various
 //! registries. It supports pulling images from Docker and Sandboxes.io registries,
-//! handling image layers, and managing the local image cache.
+//! building images with the local Docker daemon, handling image layers, and managing
+//! the local image cache.
 
 use crate::{
-    management::db::{self, OCI_DB_MIGRATOR},
-    oci::{DockerRegistry, OciRegistryPull, Reference},
-    MicrosandboxError, MicrosandboxResult,
+    management::{
+        db::{self, OCI_DB_MIGRATOR},
+        rootfs::OwnershipOverride,
+    },
+    oci::{
+        self, DockerRegistry, ExtractionBudget, PullLimits, PullProgress, PullProgressCallback,
+        PullProgressTracker, Reference, DOCKER_REGISTRY, SANDBOXES_REGISTRY,
+    },
+    MicrosandboxError, MicrosandboxResult, RegistryPullFailure,
 };
 #[cfg(feature = "cli")]
 use flate2::read::GzDecoder;
 use futures::future;
+use oci_spec::image::Platform;
 use serde_json;
 #[cfg(feature = "cli")]
 use indicatif::{ProgressBar, ProgressStyle};
@@ -19,14 +27,19 @@ use indicatif::{ProgressBar, ProgressStyle};
 use microsandbox_utils::term::{self, MULTI_PROGRESS};
 use microsandbox_utils::{env, EXTRACTED_LAYER_SUFFIX, LAYERS_SUBDIR, OCI_DB_FILENAME};
 use sqlx::{Pool, Sqlite};
+use std::collections::{HashMap, HashSet, VecDeque};
 #[cfg(feature = "cli")]
 use std::io::Result as IoResult;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
 use std::ffi::CStr;
 use std::io::Read;
+use std::time::{Duration, Instant};
 use tar::Archive;
-use tempfile::tempdir;
-use tokio::fs;
+use tokio::{
+    fs,
+    sync::{Semaphore, SemaphorePermit},
+};
 #[cfg(feature = "cli")]
 use tokio::task::spawn_blocking;
 
@@ -34,16 +47,98 @@ use tokio::task::spawn_blocking;
 // Constants
 //--------------------------------------------------------------------------------------------------
 
-/// The domain name for the Docker registry.
-const DOCKER_REGISTRY: &str = "docker.io";
-
-/// The domain name for the Sandboxes registry.
-const SANDBOXES_REGISTRY: &str = "sandboxes.io";
-
 #[cfg(feature = "cli")]
 /// Spinner message used for extracting layers.
 const EXTRACT_LAYERS_MSG: &str = "Extracting layers";
 
+/// The number of trailing build log lines included in a build failure error.
+const BUILD_ERROR_TAIL_LINES: usize = 20;
+
+/// The media type recorded for extracted layers that [`repair`] finds without a record.
+const RECOVERED_LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+
+/// The maximum number of images pulled at the same time by [`prefetch`].
+const PREFETCH_CONCURRENCY: usize = 4;
+
+/// The maximum number of layers extracted at the same time, across all pulls in the process.
+///
+/// Each extraction keeps files open while it unpacks, so extracting every layer of a large image
+/// at once can exhaust the process's file descriptors.
+const LAYER_EXTRACTION_CONCURRENCY: usize = 4;
+
+/// The number of times a layer extraction is attempted when it runs out of file descriptors.
+const LAYER_EXTRACTION_MAX_ATTEMPTS: u32 = 3;
+
+/// The delay before retrying a layer extraction that ran out of file descriptors, multiplied by
+/// the number of attempts made so far.
+const LAYER_EXTRACTION_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// How long a successful [`check_image_layers`] result is reused before the image's layers are
+/// checked again.
+const LAYER_CHECK_CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// Limits the number of layers extracted at the same time to [`LAYER_EXTRACTION_CONCURRENCY`].
+static LAYER_EXTRACTION_PERMITS: Semaphore = Semaphore::const_new(LAYER_EXTRACTION_CONCURRENCY);
+
+/// Limits the number of layers downloaded at the same time, across all pulls in the process, to
+/// `MSB_MAX_GLOBAL_PULLS`.
+///
+/// Prefetching during `apply` while sessions pull their own images can otherwise start enough
+/// downloads at once to starve running sandboxes of network and disk bandwidth.
+static LAYER_DOWNLOAD_PERMITS: LazyLock<Semaphore> =
+    LazyLock::new(|| Semaphore::new(env::get_max_global_pulls()));
+
+/// The images whose layers [`check_image_layers`] recently found valid, keyed by reference and
+/// layers directory, with the time they were checked.
+///
+/// Creating many sessions from the same image otherwise scans its layer directories every time.
+/// Entries are dropped when the image is pulled again or the database loses images.
+static VALIDATED_IMAGES: LazyLock<Mutex<HashMap<(String, PathBuf), Instant>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A progress event reported while building an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildProgress {
+    /// A line of output from the Docker build.
+    Log(String),
+
+    /// Progress of importing the built image into the local image store.
+    Import(PullProgress),
+}
+
+/// A callback that receives the progress of an image build.
+pub type BuildProgressCallback = Box<dyn Fn(BuildProgress) + Send + Sync>;
+
+/// The outcome of prefetching a set of images.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrefetchReport {
+    /// Images that were already in the local image store
+    pub cache_hits: Vec<Reference>,
+
+    /// Images that had to be pulled
+    pub pulled: Vec<Reference>,
+}
+
+/// The outcome of repairing the local image store.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Problems the integrity check found in the image database, empty if it was intact
+    pub problems: Vec<String>,
+
+    /// Where the corrupted image database was moved to, if it was rebuilt
+    pub backup_path: Option<PathBuf>,
+
+    /// Images whose records were recovered from the corrupted database
+    pub images_recovered: u64,
+
+    /// Extracted layers on disk that were missing from the recovered records
+    pub layers_registered: usize,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
@@ -57,19 +152,28 @@ const EXTRACT_LAYERS_MSG: &str = "Extracting layers";
 /// - Library repository images are pulled from Docker registry for compatibility
 /// - Other namespaces are also pulled from Docker registry with a warning about potential future changes
 ///
+/// Downloads are staged in a scratch directory under `MSB_PULL_STAGING_DIR`, or under
+/// `<MICROSANDBOX_HOME>/staging` if it is not set. The scratch directory is removed once the pull
+/// finishes, whether it succeeded or not.
+///
 /// ## Arguments
 ///
 /// * `name` - The reference to the image or image group to pull
 /// * `image` - If true, indicates that a single image should be pulled
 /// * `image_group` - If true, indicates that an image group should be pulled (Sandboxes.io only)
 /// * `layer_path` - The path to store the layer files
+/// * `platform` - The platform to pull multi-arch images for, Linux on the host's architecture
+///   if `None`. Images from the local Docker daemon are only used when no platform is requested.
+/// * `progress` - Optional callback that receives the pull progress (bytes downloaded, layers
+///   done/total). The CLI renders its own progress spinners, so it passes `None`.
 ///
 /// ## Errors
 ///
 /// Returns an error in the following cases:
 /// * Both `image` and `image_group` are true (invalid combination)
 /// * Image group pull is requested for a non-Sandboxes.io registry
-/// * Unsupported registry is specified
+/// * The reference names a registry other than those listed by [`oci::supported_registries`]
+/// * The image has no manifest for the requested platform
 /// * Registry-specific pull operations fail
 ///
 /// # Examples
@@ -82,20 +186,33 @@ const EXTRACT_LAYERS_MSG: &str = "Extracting layers";
 /// # #[tokio::main]
 /// # async fn main() -> anyhow::Result<()> {
 /// // Pull a single image from Docker registry
-/// image::pull("docker.io/library/ubuntu:latest".parse().unwrap(), true, false, None).await?;
+/// image::pull("docker.io/library/ubuntu:latest".parse().unwrap(), true, None, None, None).await?;
 ///
 /// // Pull an image from Sandboxes.io registry
-/// image::pull("sandboxes.io/library/alpine:latest".parse().unwrap(), true, false, None).await?;
+/// image::pull("sandboxes.io/library/alpine:latest".parse().unwrap(), true, None, None, None).await?;
 ///
 /// // Pull an image from the default registry (when no registry is specified in the reference)
-/// image::pull("nginx:latest".parse().unwrap(), true, false, None).await?;
+/// image::pull("nginx:latest".parse().unwrap(), true, None, None, None).await?;
 ///
 /// // You can set the OCI_REGISTRY_DOMAIN environment variable to specify your default registry
 /// std::env::set_var("OCI_REGISTRY_DOMAIN", "docker.io");
-/// image::pull("alpine:latest".parse().unwrap(), true, false, None).await?;
+/// image::pull("alpine:latest".parse().unwrap(), true, None, None, None).await?;
 ///
 /// // Pull an image from Docker registry and store the layers in a custom directory
-/// image::pull("docker.io/library/ubuntu:latest".parse().unwrap(), true, Some(PathBuf::from("/custom/path"))).await?;
+/// image::pull("docker.io/library/ubuntu:latest".parse().unwrap(), true, Some(PathBuf::from("/custom/path")), None, None).await?;
+///
+/// // Pull the arm64 variant of a multi-arch image
+/// let platform = microsandbox_core::oci::parse_platform("linux/arm64")?;
+/// image::pull("alpine:latest".parse().unwrap(), true, None, Some(platform), None).await?;
+///
+/// // Pull an image and report its progress
+/// image::pull(
+///     "alpine:latest".parse().unwrap(),
+///     true,
+///     None,
+///     None,
+///     Some(Box::new(|p| println!("{}/{} layers, {} bytes", p.layers_done, p.layers_total, p.bytes_downloaded))),
+/// ).await?;
 /// # Ok(())
 /// # }
 /// ```
@@ -103,10 +220,21 @@ pub async fn pull(
     name: Reference,
     _image: bool,
     layer_path: Option<PathBuf>,
+    platform: Option<Platform>,
+    progress: Option<PullProgressCallback>,
 ) -> MicrosandboxResult<()> {
     // Single image pull mode (default if both flags are false, or if image is true)
     let registry = name.to_string().split('/').next().unwrap_or("").to_string();
-    let temp_download_dir = tempdir()?.into_path();
+
+    // Stage downloads under a configurable directory rather than the system temp, which is often
+    // small or tmpfs-backed. The staging directory is removed when `staging` is dropped, whether
+    // the pull succeeds or fails.
+    let staging_root = env::get_pull_staging_dir();
+    fs::create_dir_all(&staging_root).await?;
+    let staging = tempfile::Builder::new()
+        .prefix("pull-")
+        .tempdir_in(&staging_root)?;
+    let temp_download_dir = staging.path().to_path_buf();
 
     tracing::info!(
         "temporary download directory: {}",
@@ -119,13 +247,21 @@ pub async fn pull(
     // Only try local Docker daemon for images that might be local builds
     // Check if this looks like a local image (contains "local" in the name or is not from official registry)
     let image_name = name.to_string();
-    let should_try_local_first = image_name.contains("local") || 
+    // The local daemon holds images for a single platform, so it is skipped when one is requested
+    let should_try_local_first = platform.is_none() && (image_name.contains("local") || 
                                  image_name.contains("localhost") ||
-                                 !image_name.starts_with("docker.io/microsandbox/");
+                                 !image_name.starts_with("docker.io/microsandbox/"));
     
     if should_try_local_first {
         tracing::info!("attempting to pull image {} from local Docker daemon first (detected as local image)", name);
-        match pull_from_local_docker(&name, &temp_download_dir, layer_path.clone()).await {
+        match pull_from_local_docker(
+            &name,
+            &temp_download_dir,
+            layer_path.clone(),
+            progress.as_ref(),
+        )
+        .await
+        {
             Ok(()) => {
                 tracing::info!("successfully pulled image {} from local Docker daemon", name);
                 return Ok(());
@@ -136,17 +272,235 @@ pub async fn pull(
         }
     }
 
-    // If local pull fails, try remote registries based on registry type
-    if registry == DOCKER_REGISTRY {
-        pull_from_docker_registry(&name, &temp_download_dir, layer_path).await
-    } else if registry == SANDBOXES_REGISTRY {
-        pull_from_sandboxes_registry(&name, &temp_download_dir, layer_path).await
+    // If local pull fails, try remote registries based on registry type. Docker Hub and the
+    // registries of the chain serve the same repositories, so they are all pulled through the chain.
+    oci::ensure_registry_supported(&name)?;
+    if registry == SANDBOXES_REGISTRY {
+        pull_from_sandboxes_registry(
+            &name,
+            &temp_download_dir,
+            layer_path,
+            platform.as_ref(),
+            progress.as_ref(),
+        )
+        .await
     } else {
-        Err(MicrosandboxError::InvalidArgument(format!(
-            "Unsupported registry: {}",
-            registry
-        )))
+        pull_from_docker_registry(
+            &name,
+            &temp_download_dir,
+            layer_path,
+            platform.as_ref(),
+            progress.as_ref(),
+        )
+        .await
+    }
+}
+
+/// Pulls a set of images concurrently so they are in the local image store before use.
+///
+/// Duplicate references are pulled once. Images whose layers are all present locally are
+/// reported as cache hits and not pulled again. At most [`PREFETCH_CONCURRENCY`] images are
+/// pulled at the same time.
+///
+/// ## Arguments
+///
+/// * `references` - The images to prefetch
+/// * `progress` - Optional function that creates the progress callback for each image pulled
+///
+/// ## Errors
+///
+/// Returns an error if the image database cannot be opened or any of the pulls fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use microsandbox_core::management::image;
+///
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let report = image::prefetch(
+///     vec![
+///         "alpine:latest".parse().unwrap(),
+///         "python:3.11-slim".parse().unwrap(),
+///     ],
+///     None,
+/// ).await?;
+/// println!("{} cached, {} pulled", report.cache_hits.len(), report.pulled.len());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn prefetch(
+    references: impl IntoIterator<Item = Reference>,
+    progress: Option<&(dyn Fn(&Reference) -> PullProgressCallback + Sync)>,
+) -> MicrosandboxResult<PrefetchReport> {
+    use futures::{StreamExt, TryStreamExt};
+
+    let mut seen = HashSet::new();
+    let references: Vec<Reference> = references
+        .into_iter()
+        .filter(|reference| seen.insert(reference.to_string()))
+        .collect();
+
+    let mut report = PrefetchReport::default();
+    if references.is_empty() {
+        return Ok(report);
     }
+
+    let microsandbox_home_path = env::get_microsandbox_home_path();
+    let db_path = microsandbox_home_path.join(OCI_DB_FILENAME);
+    let layers_dir = microsandbox_home_path.join(LAYERS_SUBDIR);
+    let pool = db::get_or_create_pool(&db_path, &OCI_DB_MIGRATOR).await?;
+
+    tracing::info!("prefetching {} images", references.len());
+
+    let results: Vec<(Reference, bool)> = futures::stream::iter(references)
+        .map(|reference| {
+            let pool = &pool;
+            let layers_dir = &layers_dir;
+            async move {
+                if check_image_layers(pool, &reference, layers_dir).await? {
+                    tracing::info!("image {} already cached, skipping prefetch", reference);
+                    return Ok::<_, MicrosandboxError>((reference, true));
+                }
+
+                let callback = progress.map(|progress| progress(&reference));
+                pull(reference.clone(), true, None, None, callback).await?;
+                tracing::info!("prefetched image {}", reference);
+                Ok((reference, false))
+            }
+        })
+        .buffer_unordered(PREFETCH_CONCURRENCY)
+        .try_collect()
+        .await?;
+
+    for (reference, cache_hit) in results {
+        if cache_hit {
+            report.cache_hits.push(reference);
+        } else {
+            report.pulled.push(reference);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Builds an image with the local Docker daemon and imports it into the local image store.
+///
+/// The image is built with `docker build` and then registered in the OCI database through the
+/// same path as images pulled from the local Docker daemon, so it can be used by sandboxes like
+/// any other image.
+///
+/// ## Arguments
+///
+/// * `context_dir` - The build context directory
+/// * `dockerfile` - Optional path to the Dockerfile. Defaults to `Dockerfile` in `context_dir`
+/// * `tag` - The reference to tag the built image with
+/// * `progress` - Optional callback that receives each build log line and the import progress
+///
+/// ## Errors
+///
+/// Returns an error if:
+/// * The context directory or Dockerfile does not exist
+/// * The `docker` command cannot be run
+/// * The build fails, in which case the error contains the last lines of the build log
+/// * The built image cannot be imported
+///
+/// # Examples
+///
+/// ```no_run
+/// use microsandbox_core::management::image::{self, BuildProgress};
+/// use std::path::Path;
+///
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// image::build(
+///     Path::new("./my-sandbox"),
+///     None,
+///     &"my-sandbox:latest".parse().unwrap(),
+///     Some(Box::new(|p| {
+///         if let BuildProgress::Log(line) = p {
+///             println!("{}", line);
+///         }
+///     })),
+/// ).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn build(
+    context_dir: &Path,
+    dockerfile: Option<&Path>,
+    tag: &Reference,
+    progress: Option<BuildProgressCallback>,
+) -> MicrosandboxResult<()> {
+    use std::process::Stdio;
+    use tokio::process::Command;
+
+    if !context_dir.is_dir() {
+        return Err(MicrosandboxError::PathNotFound(format!(
+            "build context directory not found: {}",
+            context_dir.display()
+        )));
+    }
+
+    let dockerfile = match dockerfile {
+        Some(path) => path.to_path_buf(),
+        None => context_dir.join("Dockerfile"),
+    };
+
+    if !dockerfile.is_file() {
+        return Err(MicrosandboxError::PathNotFound(format!(
+            "Dockerfile not found: {}",
+            dockerfile.display()
+        )));
+    }
+
+    tracing::info!("building image {} from {}", tag, context_dir.display());
+
+    let mut child = Command::new("docker")
+        .arg("build")
+        .arg("--progress=plain")
+        .arg("-t")
+        .arg(tag.to_string())
+        .arg("-f")
+        .arg(&dockerfile)
+        .arg(context_dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            MicrosandboxError::ImageBuildFailed(format!("failed to run docker build: {}", e))
+        })?;
+
+    // BuildKit writes most of its output to stderr, so both streams are forwarded as log lines
+    let progress = progress.map(Arc::new);
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let (_, stderr_tail) = tokio::join!(
+        forward_build_output(stdout, progress.as_deref()),
+        forward_build_output(stderr, progress.as_deref())
+    );
+    let status = child.wait().await?;
+
+    if !status.success() {
+        return Err(MicrosandboxError::ImageBuildFailed(format!(
+            "docker build exited with {}:\n{}",
+            status,
+            Vec::from(stderr_tail).join("\n")
+        )));
+    }
+
+    // Import the built image the same way a local Docker image is pulled
+    let staging_root = env::get_pull_staging_dir();
+    fs::create_dir_all(&staging_root).await?;
+    let staging = tempfile::Builder::new()
+        .prefix("build-")
+        .tempdir_in(&staging_root)?;
+
+    let import_progress: Option<PullProgressCallback> = progress.map(|callback| {
+        Box::new(move |p| callback(BuildProgress::Import(p))) as PullProgressCallback
+    });
+
+    pull_from_local_docker(tag, staging.path(), None, import_progress.as_ref()).await
 }
 
 /// Pulls a single image from the local Docker daemon.
@@ -159,6 +513,7 @@ pub async fn pull(
 /// * `image` - The reference to the Docker image to pull from local daemon
 /// * `download_dir` - The directory to download the image layers to
 /// * `layer_path` - Optional custom path to store layers
+/// * `progress` - Optional callback that receives the layer extraction progress
 ///
 /// ## Errors
 ///
@@ -170,6 +525,7 @@ pub async fn pull_from_local_docker(
     image: &Reference,
     download_dir: impl AsRef<Path>,
     layer_path: Option<PathBuf>,
+    progress: Option<&PullProgressCallback>,
 ) -> MicrosandboxResult<()> {
     println!("KIRO DEBUG: pull_from_local_docker called for image: {}", image);
     use std::process::Stdio;
@@ -194,6 +550,7 @@ pub async fn pull_from_local_docker(
     // For local Docker pulls, we always try to pull from Docker daemon
     // even if the image exists in our database, in case it has been updated locally
     tracing::info!("attempting to pull image {} from local Docker daemon", image);
+    forget_validated_image(image);
 
     // Try to export the image from local Docker daemon
     let image_name = image.to_string();
@@ -366,23 +723,34 @@ pub async fn pull_from_local_docker(
         ));
     }
 
-    #[cfg(feature = "cli")]
-    let extract_layers_sp = term::create_spinner(
-        EXTRACT_LAYERS_MSG.to_string(),
-        None,
-        Some(layer_paths.len() as u64),
-    );
+    let limits = PullLimits::from_env();
+    limits.check_layer_count(&image.to_string(), layer_paths.len())?;
+    let budget = Arc::new(ExtractionBudget::new(image.to_string(), limits));
+
+    // The exported layers are already on disk, so progress is reported as layers are extracted
+    let tracker = PullProgressTracker::new(progress);
+    let mut layer_sizes = Vec::with_capacity(layer_paths.len());
+    for path in &layer_paths {
+        layer_sizes.push(fs::metadata(path).await?.len());
+    }
+    tracker.start(layer_sizes.iter().sum(), layer_paths.len());
+
+    let layer_names: Vec<String> = layer_paths
+        .iter()
+        .filter_map(|path| path.file_name().and_then(|n| n.to_str()).map(String::from))
+        .collect();
 
     let extraction_futures: Vec<_> = layer_paths
         .into_iter()
-        .map(|path| {
+        .zip(layer_sizes)
+        .map(|(path, size)| {
             let layers_dir = layers_dir.clone();
-            #[cfg(feature = "cli")]
-            let extract_layers_sp = extract_layers_sp.clone();
+            let budget = budget.clone();
+            let tracker = &tracker;
             async move {
-                let result = extract_layer(path, &layers_dir).await;
-                #[cfg(feature = "cli")]
-                extract_layers_sp.inc(1);
+                let result = extract_layer(path, &layers_dir, budget).await;
+                tracker.add_bytes(size);
+                tracker.layer_done();
                 result
             }
         })
@@ -393,12 +761,17 @@ pub async fn pull_from_local_docker(
         result?;
     }
 
-    #[cfg(feature = "cli")]
-    extract_layers_sp.finish();
+    // Docker save output carries no size info, so measure the extracted layers instead
+    let mut extracted_sizes = HashMap::with_capacity(layer_names.len());
+    for name in layer_names {
+        let extract_dir = layers_dir.join(format!("{}.{}", name, EXTRACTED_LAYER_SUFFIX));
+        let size = get_extracted_layer_size(extract_dir).await? as i64;
+        extracted_sizes.insert(name, size);
+    }
 
     // Register the image in the database
     let reference = image.to_string();
-    let total_size = 0i64; // We don't have accurate size info from docker save
+    let total_size: i64 = extracted_sizes.values().sum();
     
     tracing::info!("registering local Docker image {} in database", reference);
     let image_id = db::save_or_update_image(&pool, &reference, total_size).await?;
@@ -462,7 +835,8 @@ pub async fn pull_from_local_docker(
                                                                 let digest_str = format!("sha256:{}", digest_part);
                                                                 
                                                                 // Save layer to database
-                                                                match db::save_layer(&pool, "application/vnd.docker.image.rootfs.diff.tar.gzip", &digest_str, 0, &digest_str).await {
+                                                                let size = extracted_sizes.get(&digest_str).copied().unwrap_or(0);
+                                                                match db::save_or_update_layer(&pool, "application/vnd.docker.image.rootfs.diff.tar.gzip", &digest_str, size, &digest_str).await {
                                                                     Ok(layer_id) => {
                                                                         // Link layer to manifest
                                                                         if let Err(e) = db::save_manifest_layer(&pool, manifest_id, layer_id).await {
@@ -513,27 +887,44 @@ pub async fn pull_from_local_docker(
     }
 
     tracing::info!("successfully processed and registered local Docker image {}", image);
+
+    // Checks made while the image was being replaced may have found its old layers valid
+    forget_validated_image(image);
     Ok(())
 }
 
 /// Pulls a single image from the Docker registry.
 ///
+/// The registries configured in `registries.yaml` are tried in order, e.g. an internal mirror
+/// before Docker Hub, and the first one that serves the image is used. Without that file the
+/// image is pulled from Docker Hub.
+///
 /// ## Arguments
 ///
 /// * `image` - The reference to the Docker image to pull
 /// * `download_dir` - The directory to download the image layers to
 /// * `layer_path` - Optional custom path to store layers
+/// * `platform` - The platform to pull multi-arch images for, Linux on the host's architecture
+///   if `None`. An image already pulled for another platform is pulled again.
+/// * `progress` - Optional callback that receives the download progress
 ///
 /// ## Errors
 ///
 /// Returns an error if:
 /// * Failed to create temporary directories
-/// * Failed to initialize Docker registry client
-/// * Failed to pull the image from Docker registry
+/// * Failed to read the registry configuration
+/// * Failed to pull the image from every configured registry, in which case the error lists
+///   each registry and why it failed
+/// * None of the configured registries has the image, in which case the error is
+///   [`MicrosandboxError::ImageNotFound`]
+/// * The image has no manifest for the platform, in which case the error is
+///   [`MicrosandboxError::PlatformNotFound`] listing the platforms it has
 pub async fn pull_from_docker_registry(
     image: &Reference,
     download_dir: impl AsRef<Path>,
     layer_path: Option<PathBuf>,
+    platform: Option<&Platform>,
+    progress: Option<&PullProgressCallback>,
 ) -> MicrosandboxResult<()> {
     let download_dir = download_dir.as_ref();
     let microsandbox_home_path = env::get_microsandbox_home_path();
@@ -548,23 +939,83 @@ pub async fn pull_from_docker_registry(
     // Create layers directory if it doesn't exist
     fs::create_dir_all(&layers_dir).await?;
 
-    let docker_registry = DockerRegistry::new(download_dir, &db_path).await?;
-
     // Get or create a connection pool to the database
     let pool = db::get_or_create_pool(&db_path, &OCI_DB_MIGRATOR).await?;
 
-    // Check if we need to pull the image
-    if check_image_layers(&pool, image, &layers_dir).await? {
+    // Check if we need to pull the image, again if it was pulled for another platform
+    let pulled = db::get_image_platform(&pool, &image.to_string()).await?;
+    let pulled_for_platform = is_pulled_for_platform(platform, pulled.as_ref());
+    if pulled_for_platform && check_image_layers(&pool, image, &layers_dir).await? {
         tracing::info!("image {} and all its layers exist, skipping pull", image);
         return Ok(());
     }
+    forget_validated_image(image);
+
+    // Try each registry in turn, keeping every failure in case none of them serves the image
+    let mut failures = Vec::new();
+    let mut pulled = false;
+    let mut all_not_found = true;
+    for endpoint in oci::load_registry_chain()? {
+        let registry = endpoint.url.clone();
+        let result = async {
+            let mut docker_registry =
+                DockerRegistry::with_endpoint(download_dir, &db_path, endpoint).await?;
+            if let Some(platform) = platform {
+                docker_registry.set_platform(platform.clone());
+            }
 
-    docker_registry
-        .pull_image(image.get_repository(), image.get_selector().clone())
-        .await?;
+            docker_registry
+                .pull_image_with_progress(
+                    image.get_repository(),
+                    image.get_selector().clone(),
+                    progress,
+                )
+                .await
+        }
+        .await;
+
+        match result {
+            Ok(()) => {
+                tracing::info!("pulled image {} from registry {}", image, registry);
+                pulled = true;
+                break;
+            }
+            // The registry has the image, just not for this platform, which the other
+            // registries serving the same image will not change
+            Err(e @ MicrosandboxError::PlatformNotFound { .. }) => return Err(e),
+            Err(e) => {
+                tracing::warn!("failed to pull image {} from registry {}: {}", image, registry, e);
+                if !matches!(&e, MicrosandboxError::DockerRegistryResponseError(e) if e.is_not_found())
+                {
+                    all_not_found = false;
+                }
+                failures.push(RegistryPullFailure {
+                    registry,
+                    reason: e.to_string(),
+                });
+            }
+        }
+    }
 
-    // Find and extract layers in parallel
+    // Every registry saying the image does not exist is a mistake in the reference, not an
+    // outage, so it gets its own error
+    if !pulled && all_not_found && !failures.is_empty() {
+        return Err(MicrosandboxError::ImageNotFound {
+            image: image.to_string(),
+            registries: failures.into_iter().map(|f| f.registry).collect(),
+        });
+    }
+
+    if !pulled {
+        return Err(MicrosandboxError::RegistryChainExhausted {
+            image: image.to_string(),
+            failures,
+        });
+    }
+
+    // Find and extract layers in parallel, stopping once they grow larger than an image may be
     let layer_paths = collect_layer_files(download_dir).await?;
+    let budget = Arc::new(ExtractionBudget::new(image.to_string(), PullLimits::from_env()));
 
     #[cfg(feature = "cli")]
     let extract_layers_sp = term::create_spinner(
@@ -577,10 +1028,11 @@ pub async fn pull_from_docker_registry(
         .into_iter()
         .map(|path| {
             let layers_dir = layers_dir.clone();
+            let budget = budget.clone();
             #[cfg(feature = "cli")]
             let extract_layers_sp = extract_layers_sp.clone();
             async move {
-                let result = extract_layer(path, &layers_dir).await;
+                let result = extract_layer(path, &layers_dir, budget).await;
                 #[cfg(feature = "cli")]
                 extract_layers_sp.inc(1);
                 result
@@ -596,6 +1048,8 @@ pub async fn pull_from_docker_registry(
     #[cfg(feature = "cli")]
     extract_layers_sp.finish();
 
+    // Checks made while the image was being replaced may have found its old layers valid
+    forget_validated_image(image);
     Ok(())
 }
 
@@ -609,6 +1063,9 @@ pub async fn pull_from_docker_registry(
 /// * `image` - The reference to the Sandboxes.io image to pull
 /// * `download_dir` - The directory to download the image layers to
 /// * `layer_path` - Optional custom path to store layers
+/// * `platform` - The platform to pull multi-arch images for, Linux on the host's architecture
+///   if `None`
+/// * `progress` - Optional callback that receives the download progress
 ///
 /// ## Errors
 ///
@@ -617,6 +1074,8 @@ pub async fn pull_from_sandboxes_registry(
     image: &Reference,
     download_dir: impl AsRef<Path>,
     layer_path: Option<PathBuf>,
+    platform: Option<&Platform>,
+    progress: Option<&PullProgressCallback>,
 ) -> MicrosandboxResult<()> {
     // Check if this is a library repository image
     let repository = image.get_repository();
@@ -650,7 +1109,161 @@ pub async fn pull_from_sandboxes_registry(
         );
     }
 
-    pull_from_docker_registry(&docker_reference, download_dir, layer_path).await
+    pull_from_docker_registry(
+        &docker_reference,
+        download_dir,
+        layer_path,
+        platform,
+        progress,
+    )
+    .await
+}
+
+/// Recomputes the sizes of layers and images that were recorded with a size of zero.
+///
+/// Images imported from the local Docker daemon by older versions were saved with zero sizes
+/// because `docker save` carries no size info. This stats the extracted layer directories to
+/// compute the real layer sizes, then sets each zero-size image to the sum of its layer sizes.
+/// Layers that have not been extracted are left untouched.
+///
+/// ## Returns
+///
+/// Returns the number of layers whose size was updated
+///
+/// ## Errors
+///
+/// Returns an error if:
+/// * Failed to open the OCI database
+/// * Failed to read an extracted layer directory
+/// * Failed to update the database records
+pub async fn recompute_sizes() -> MicrosandboxResult<usize> {
+    let microsandbox_home_path = env::get_microsandbox_home_path();
+    let db_path = microsandbox_home_path.join(OCI_DB_FILENAME);
+    let layers_dir = microsandbox_home_path.join(LAYERS_SUBDIR);
+    let pool = db::get_or_create_pool(&db_path, &OCI_DB_MIGRATOR).await?;
+
+    let mut updated = 0;
+    for digest in db::get_zero_size_layer_digests(&pool).await? {
+        let extract_dir = layers_dir.join(format!("{}.{}", digest, EXTRACTED_LAYER_SUFFIX));
+        if !extract_dir.exists() {
+            tracing::warn!("layer {} is not extracted, skipping size recompute", digest);
+            continue;
+        }
+
+        let size = get_extracted_layer_size(extract_dir).await? as i64;
+        db::update_layer_size(&pool, &digest, size).await?;
+        updated += 1;
+    }
+
+    let images = db::backfill_image_sizes(&pool).await?;
+    tracing::info!(
+        "recomputed sizes for {} layers and {} images",
+        updated,
+        images
+    );
+
+    Ok(updated)
+}
+
+/// Checks the image database and rebuilds it if it is corrupted.
+///
+/// An intact database is left untouched. A corrupted one is moved aside next to itself, and a
+/// new database is filled with whatever records can still be read from it. Extracted layers on
+/// disk that have no record are registered again, so that their sizes are accounted for. Images
+/// whose records could not be recovered are pulled again the next time they are used, which
+/// reuses any of their layers still on disk.
+///
+/// Images must not be pulled while the database is repaired.
+///
+/// ## Errors
+///
+/// Returns an error if:
+/// * Failed to move the corrupted database aside
+/// * Failed to create the new database
+/// * Failed to read the layers directory
+pub async fn repair() -> MicrosandboxResult<RepairReport> {
+    let microsandbox_home_path = env::get_microsandbox_home_path();
+    let db_path = microsandbox_home_path.join(OCI_DB_FILENAME);
+    let layers_dir = microsandbox_home_path.join(LAYERS_SUBDIR);
+    if !fs::try_exists(&db_path).await? {
+        return Ok(RepairReport::default());
+    }
+
+    let problems = match db::get_pool(&db_path).await {
+        Ok(pool) => {
+            let problems = db::check_integrity(&pool).await;
+            pool.close().await;
+            problems?
+        }
+        Err(MicrosandboxError::Database(e)) if db::is_corruption(&e) => vec![e.to_string()],
+        Err(e) => return Err(e),
+    };
+    if problems.is_empty() {
+        tracing::info!("image database {} is intact", db_path.display());
+        return Ok(RepairReport::default());
+    }
+
+    // Keep the corrupted database, together with its journal, in case it is needed by hand
+    let backup_path = db_path.with_file_name(format!(
+        "{}.corrupted-{}",
+        OCI_DB_FILENAME,
+        chrono::Utc::now().format("%Y%m%d%H%M%S")
+    ));
+    for suffix in ["", "-wal", "-shm"] {
+        let path = PathBuf::from(format!("{}{}", db_path.display(), suffix));
+        if fs::try_exists(&path).await? {
+            fs::rename(&path, format!("{}{}", backup_path.display(), suffix)).await?;
+        }
+    }
+    tracing::warn!(
+        "image database is corrupted, moved it to {}",
+        backup_path.display()
+    );
+
+    let pool = db::get_or_create_pool(&db_path, &OCI_DB_MIGRATOR).await?;
+    let images_recovered = db::salvage_oci_db(&pool, &backup_path).await?;
+
+    // Salvaging drops the images whose records were lost, which may have been checked recently
+    forget_validated_images();
+
+    let mut layers_registered = 0;
+    if fs::try_exists(&layers_dir).await? {
+        let mut entries = fs::read_dir(&layers_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let Some(digest) = file_name.strip_suffix(&format!(".{}", EXTRACTED_LAYER_SUFFIX))
+            else {
+                continue;
+            };
+            if !entry.file_type().await?.is_dir()
+                || !db::get_layers_by_digest(&pool, &[digest.to_string()])
+                    .await?
+                    .is_empty()
+            {
+                continue;
+            }
+
+            // The uncompressed digest is not known from the extracted files, so the layer is
+            // recorded under its compressed digest until a pull records it properly
+            let size = get_extracted_layer_size(entry.path()).await? as i64;
+            db::save_layer(&pool, RECOVERED_LAYER_MEDIA_TYPE, digest, size, digest).await?;
+            layers_registered += 1;
+        }
+    }
+
+    pool.close().await;
+    tracing::info!(
+        "rebuilt image database with {} images and {} registered layers",
+        images_recovered,
+        layers_registered
+    );
+
+    Ok(RepairReport {
+        problems,
+        backup_path: Some(backup_path),
+        images_recovered,
+        layers_registered,
+    })
 }
 
 /// Pulls an image group from the Sandboxes.io registry.
@@ -668,10 +1281,32 @@ pub async fn pull_group_from_sandboxes_registry(_group: &Reference) -> Microsand
     ));
 }
 
+/// Waits for a free layer download slot, shared by every pull in the process.
+///
+/// The download may start once the permit is held, and the slot is freed when it is dropped.
+pub(crate) async fn acquire_layer_download_permit() -> MicrosandboxResult<SemaphorePermit<'static>>
+{
+    LAYER_DOWNLOAD_PERMITS
+        .acquire()
+        .await
+        .map_err(|e| MicrosandboxError::ImageLayerDownloadFailed(format!("{:?}", e)))
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions: Helpers
 //--------------------------------------------------------------------------------------------------
 
+/// Returns the progress callback for pulls the caller does not report progress for itself.
+///
+/// With the `cli` feature this is the CLI's layer spinner, otherwise pulls are not reported.
+pub(crate) fn default_pull_progress() -> Option<PullProgressCallback> {
+    #[cfg(feature = "cli")]
+    return Some(oci::cli_pull_progress());
+
+    #[cfg(not(feature = "cli"))]
+    return None;
+}
+
 /// Checks if all layers for an image exist in both the database and the layers directory.
 ///
 /// ## Arguments
@@ -684,6 +1319,9 @@ pub async fn pull_group_from_sandboxes_registry(_group: &Reference) -> Microsand
 ///
 /// Returns Ok(true) if all layers exist and are valid, Ok(false) if any layers are missing
 /// or invalid. Any errors during the check process will return Ok(false) with a warning log.
+/// Layer directories are checked concurrently, and a check that takes longer than
+/// `MSB_LAYER_CHECK_TIMEOUT_SECS` also returns Ok(false) so the image is pulled again. Valid
+/// results are reused for [`LAYER_CHECK_CACHE_TTL`], or until the image is pulled again.
 async fn check_image_layers(
     pool: &Pool<Sqlite>,
     image: &Reference,
@@ -691,6 +1329,12 @@ async fn check_image_layers(
 ) -> MicrosandboxResult<bool> {
     let layers_dir = layers_dir.as_ref();
 
+    let cache_key = (image.to_string(), layers_dir.to_path_buf());
+    if is_image_recently_validated(&cache_key) {
+        tracing::info!("layers of image {} checked recently, skipping check", image);
+        return Ok(true);
+    }
+
     // Check if the image exists in the database
     match db::image_exists(pool, &image.to_string()).await {
         Ok(true) => {
@@ -703,23 +1347,23 @@ async fn check_image_layers(
                         return Ok(false);
                     }
 
-                    // Check if all layers exist in the layers directory
-                    for digest in &layer_digests {
-                        let layer_path =
-                            layers_dir.join(format!("{}.{}", digest, EXTRACTED_LAYER_SUFFIX));
-                        if !layer_path.exists() {
-                            tracing::warn!("layer {} not found in layers directory", digest);
-                            return Ok(false);
+                    // Check if all layers exist in the layers directory, giving up on a slow filesystem
+                    let timeout = env::get_layer_check_timeout();
+                    let check = check_layer_dirs(layers_dir, &layer_digests);
+                    match tokio::time::timeout(timeout, check).await {
+                        Ok(found) => {
+                            if !found? {
+                                return Ok(false);
+                            }
                         }
-
-                        // Also check that the layer directory actually has content
-                        let mut read_dir = fs::read_dir(&layer_path).await?;
-                        let dir_empty = read_dir.next_entry().await?.is_none();
-                        if dir_empty {
-                            tracing::warn!("layer {} exists but is empty", digest);
+                        Err(_) => {
+                            tracing::warn!(
+                                "checking layers of image {} timed out after {:?}, will pull image",
+                                image,
+                                timeout
+                            );
+                            return Ok(false);
                         }
-
-                        tracing::info!("layer {} found in layers directory", digest);
                     }
 
                     // Get the layers from database to verify database records exist for all digests
@@ -734,6 +1378,7 @@ async fn check_image_layers(
                     }
 
                     tracing::info!("all layers for image {} exist and are valid", image);
+                    mark_image_validated(cache_key);
                     Ok(true)
                 }
                 Err(e) => {
@@ -753,6 +1398,95 @@ async fn check_image_layers(
     }
 }
 
+/// Checks that the extracted directory of each layer exists in the layers directory, checking up
+/// to `MSB_LAYER_CHECK_CONCURRENCY` layers at the same time.
+///
+/// Returns Ok(false) as soon as a layer is found missing.
+async fn check_layer_dirs(layers_dir: &Path, digests: &[String]) -> MicrosandboxResult<bool> {
+    use futures::{StreamExt, TryStreamExt};
+
+    // The checks own their inputs so the future stays `Send` when spawned by callers of `pull`
+    let mut checks = futures::stream::iter(digests.to_vec())
+        .map(|digest| {
+            let layers_dir = layers_dir.to_path_buf();
+            async move { check_layer_dir(&layers_dir, &digest).await }
+        })
+        .buffer_unordered(env::get_layer_check_concurrency());
+
+    while let Some(found) = checks.try_next().await? {
+        if !found {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Checks that the extracted directory of a layer exists in the layers directory, warning if it
+/// is empty.
+async fn check_layer_dir(layers_dir: &Path, digest: &str) -> MicrosandboxResult<bool> {
+    let layer_path = layers_dir.join(format!("{}.{}", digest, EXTRACTED_LAYER_SUFFIX));
+    if !fs::try_exists(&layer_path).await.unwrap_or(false) {
+        tracing::warn!("layer {} not found in layers directory", digest);
+        return Ok(false);
+    }
+
+    // Also check that the layer directory actually has content
+    let mut read_dir = fs::read_dir(&layer_path).await?;
+    if read_dir.next_entry().await?.is_none() {
+        tracing::warn!("layer {} exists but is empty", digest);
+    }
+
+    tracing::info!("layer {} found in layers directory", digest);
+    Ok(true)
+}
+
+/// Returns whether [`check_image_layers`] found the image's layers valid within the last
+/// [`LAYER_CHECK_CACHE_TTL`], dropping older results.
+fn is_image_recently_validated(key: &(String, PathBuf)) -> bool {
+    let mut validated = VALIDATED_IMAGES.lock().unwrap();
+    validated.retain(|_, checked_at| checked_at.elapsed() < LAYER_CHECK_CACHE_TTL);
+    validated.contains_key(key)
+}
+
+/// Records that [`check_image_layers`] found the image's layers valid.
+fn mark_image_validated(key: (String, PathBuf)) {
+    VALIDATED_IMAGES.lock().unwrap().insert(key, Instant::now());
+}
+
+/// Forgets that [`check_image_layers`] found the image's layers valid, in any layers directory,
+/// so that the next check looks at them again.
+///
+/// Called when the image is about to be pulled, or was just pulled, so that sessions created
+/// while its layers are being replaced are not started from a half-written image.
+fn forget_validated_image(image: &Reference) {
+    let image = image.to_string();
+    VALIDATED_IMAGES
+        .lock()
+        .unwrap()
+        .retain(|(reference, _), _| *reference != image);
+}
+
+/// Forgets every image [`check_image_layers`] found valid, for when images are removed from
+/// the database.
+fn forget_validated_images() {
+    VALIDATED_IMAGES.lock().unwrap().clear();
+}
+
+/// Returns whether an image pulled for the `pulled` platform can be used for the `requested` one,
+/// Linux on the host's architecture if `None`.
+///
+/// Images without a recorded platform, such as those from the local Docker daemon, were built
+/// for the host, so they are only used when no platform is requested.
+fn is_pulled_for_platform(requested: Option<&Platform>, pulled: Option<&Platform>) -> bool {
+    match (requested, pulled) {
+        (Some(requested), Some(pulled)) => oci::platform_matches(requested, pulled),
+        (None, Some(pulled)) => oci::platform_matches(&oci::host_platform(), pulled),
+        (Some(_), None) => false,
+        (None, None) => true,
+    }
+}
+
 /// Helper function to get full mode with file type bits
 fn get_full_mode(entry_type: &tar::EntryType, permission_bits: u32) -> u32 {
     let file_type_bits = if entry_type.is_file() {
@@ -819,6 +1553,8 @@ fn set_stat_xattr(
                 "Filesystem does not support xattrs for {}, continuing without stat shadowing",
                 path.display()
             );
+        } else if errno.raw_os_error() == Some(libc::ENOSPC) {
+            return Err(MicrosandboxError::Io(errno));
         } else {
             return Err(MicrosandboxError::LayerExtraction(format!(
                 "Failed to set xattr on {}: {}",
@@ -833,9 +1569,15 @@ fn set_stat_xattr(
 /// Extracts a layer from the downloaded tar.gz file into an extracted directory.
 /// The extracted directory will be named as <layer-name>.extracted
 /// Custom extraction function that modifies file ownership during extraction
+///
+/// The ownership recorded in the xattr is the layer's original uid/gid unless `ownership`
+/// overrides it. The size of every file is counted against `budget` before it is written, so
+/// extraction stops once the image's layers grow larger than an image may be.
 fn extract_tar_with_ownership_override<R: Read>(
     archive: &mut Archive<R>,
     extract_dir: &Path,
+    ownership: OwnershipOverride,
+    budget: &ExtractionBudget,
 ) -> MicrosandboxResult<()> {
     use std::ffi::CString;
     use std::os::unix::fs::PermissionsExt;
@@ -855,18 +1597,17 @@ fn extract_tar_with_ownership_override<R: Read>(
 
     // Store hard links to process after all regular files are extracted
     let mut hard_links = Vec::new();
+    let mut charge = budget.layer();
 
     for entry in archive.entries()? {
-        let mut entry =
-            entry.map_err(|e| MicrosandboxError::LayerExtraction(format!("{:?}", e)))?;
-        let path = entry
-            .path()
-            .map_err(|e| MicrosandboxError::LayerExtraction(format!("{:?}", e)))?;
+        let mut entry = entry.map_err(layer_extraction_error)?;
+        charge.charge(entry.size())?;
+        let path = entry.path().map_err(layer_extraction_error)?;
         let full_path = extract_dir.join(&path);
 
         // Get the original metadata from the tar entry
-        let original_uid = entry.header().uid()?;
-        let original_gid = entry.header().gid()?;
+        let (original_uid, original_gid) =
+            ownership.apply(entry.header().uid()?, entry.header().gid()?);
         let permission_bits = entry.header().mode()?;
 
         // Check the entry type
@@ -892,9 +1633,7 @@ fn extract_tar_with_ownership_override<R: Read>(
         }
 
         // Extract the entry (regular files, directories, symlinks)
-        entry
-            .unpack(&full_path)
-            .map_err(|e| MicrosandboxError::LayerExtraction(format!("{:?}", e)))?;
+        entry.unpack(&full_path).map_err(layer_extraction_error)?;
 
         // Skip all operations for symlinks
         if is_symlink {
@@ -1005,12 +1744,14 @@ fn extract_tar_with_ownership_override<R: Read>(
         }
     }
 
+    charge.commit();
     Ok(())
 }
 
 async fn extract_layer(
     layer_path: impl AsRef<Path>,
     extract_base_dir: impl AsRef<Path>,
+    budget: Arc<ExtractionBudget>,
 ) -> MicrosandboxResult<()> {
     let layer_path = layer_path.as_ref();
     let file_name = layer_path
@@ -1054,12 +1795,90 @@ async fn extract_layer(
             layer: file_name.to_string(),
         })?;
 
+    // Wait for a free extraction slot before opening any of the layer's files
+    let _permit = LAYER_EXTRACTION_PERMITS
+        .acquire()
+        .await
+        .map_err(|e| MicrosandboxError::LayerExtraction(format!("{:?}", e)))?;
+
     tracing::info!(
         "extracting layer {} to {}",
         file_name,
         extract_dir.display()
     );
 
+    let ownership = OwnershipOverride::from_env();
+    let mut attempts = 1;
+    loop {
+        let Err(e) = unpack_layer(layer_path, file_name, &extract_dir, ownership, &budget).await
+        else {
+            break;
+        };
+
+        // Don't leave a partial extraction behind, a later pull would mistake it for a complete one
+        if let Err(remove_err) = fs::remove_dir_all(&extract_dir).await {
+            tracing::warn!(
+                "failed to remove partial extraction {}: {}",
+                extract_dir.display(),
+                remove_err
+            );
+        }
+
+        if is_disk_full(&e) {
+            return Err(MicrosandboxError::DiskFull {
+                layer: file_name.to_string(),
+                path: extract_dir,
+                required: gzip_uncompressed_size(layer_path).await,
+                available: available_space(extract_base_dir.as_ref()),
+            });
+        }
+
+        if !is_fd_exhaustion(&e) {
+            return Err(e);
+        }
+
+        if attempts == LAYER_EXTRACTION_MAX_ATTEMPTS {
+            return Err(MicrosandboxError::LayerExtractionFdExhausted {
+                layer: file_name.to_string(),
+                attempts,
+            });
+        }
+
+        let delay = LAYER_EXTRACTION_RETRY_DELAY * attempts;
+        tracing::warn!(
+            "ran out of file descriptors extracting layer {} (attempt {}/{}), retrying in {:?}",
+            file_name,
+            attempts,
+            LAYER_EXTRACTION_MAX_ATTEMPTS,
+            delay
+        );
+        tokio::time::sleep(delay).await;
+
+        fs::create_dir_all(&extract_dir)
+            .await
+            .map_err(|e| MicrosandboxError::LayerHandling {
+                source: e,
+                layer: file_name.to_string(),
+            })?;
+        attempts += 1;
+    }
+
+    tracing::info!(
+        "successfully extracted layer {} to {}",
+        file_name,
+        extract_dir.display()
+    );
+    Ok(())
+}
+
+/// Unpacks a gzipped layer tarball into an existing extraction directory.
+async fn unpack_layer(
+    layer_path: &Path,
+    file_name: &str,
+    extract_dir: &Path,
+    ownership: OwnershipOverride,
+    budget: &Arc<ExtractionBudget>,
+) -> MicrosandboxResult<()> {
     #[cfg(feature = "cli")]
     struct ProgressReader<R> {
         inner: R,
@@ -1096,8 +1915,9 @@ async fn extract_layer(
         pb.set_prefix(format!("{}", digest_short));
 
         let layer_path_clone = layer_path.to_path_buf();
-        let extract_dir_clone = extract_dir.clone();
+        let extract_dir_clone = extract_dir.to_path_buf();
         let pb_clone = pb.clone();
+        let budget = budget.clone();
 
         spawn_blocking(move || -> MicrosandboxResult<()> {
             let file = std::fs::File::open(&layer_path_clone)?;
@@ -1107,7 +1927,12 @@ async fn extract_layer(
             };
             let decoder = GzDecoder::new(reader);
             let mut archive = Archive::new(decoder);
-            extract_tar_with_ownership_override(&mut archive, &extract_dir_clone)?;
+            extract_tar_with_ownership_override(
+                &mut archive,
+                &extract_dir_clone,
+                ownership,
+                &budget,
+            )?;
             Ok(())
         })
         .await
@@ -1127,17 +1952,156 @@ async fn extract_layer(
             })?;
         let decoder = GzDecoder::new(file);
         let mut archive = Archive::new(decoder);
-        extract_tar_with_ownership_override(&mut archive, &extract_dir)?;
+        extract_tar_with_ownership_override(&mut archive, extract_dir, ownership, budget)?;
     }
 
-    tracing::info!(
-        "successfully extracted layer {} to {}",
-        file_name,
-        extract_dir.display()
-    );
     Ok(())
 }
 
+/// Converts an error from reading a layer tarball into a layer extraction error.
+///
+/// Running out of file descriptors or disk space is kept as an I/O error so [`extract_layer`]
+/// can tell it apart, and retry or report it.
+fn layer_extraction_error(error: std::io::Error) -> MicrosandboxError {
+    if is_io_fd_exhaustion(&error) || is_io_disk_full(&error) {
+        MicrosandboxError::Io(error)
+    } else {
+        MicrosandboxError::LayerExtraction(format!("{:?}", error))
+    }
+}
+
+/// Checks whether an error was caused by the process or system running out of file descriptors.
+fn is_fd_exhaustion(error: &MicrosandboxError) -> bool {
+    match error {
+        MicrosandboxError::Io(e) | MicrosandboxError::LayerHandling { source: e, .. } => {
+            is_io_fd_exhaustion(e)
+        }
+        _ => false,
+    }
+}
+
+/// Checks whether an I/O error, or any error it wraps, is `EMFILE` or `ENFILE`.
+fn is_io_fd_exhaustion(error: &std::io::Error) -> bool {
+    matches!(io_os_error(error), Some(libc::EMFILE | libc::ENFILE))
+}
+
+/// Checks whether an error was caused by the disk filling up.
+fn is_disk_full(error: &MicrosandboxError) -> bool {
+    match error {
+        MicrosandboxError::Io(e) | MicrosandboxError::LayerHandling { source: e, .. } => {
+            is_io_disk_full(e)
+        }
+        _ => false,
+    }
+}
+
+/// Checks whether an I/O error, or any error it wraps, is `ENOSPC`.
+fn is_io_disk_full(error: &std::io::Error) -> bool {
+    io_os_error(error) == Some(libc::ENOSPC)
+}
+
+/// Returns the OS error code of an I/O error, or of the first I/O error it wraps.
+fn io_os_error(error: &std::io::Error) -> Option<i32> {
+    if let Some(code) = error.raw_os_error() {
+        return Some(code);
+    }
+
+    // Archive errors wrap the I/O error that caused them, possibly behind another error type
+    let mut current = error
+        .get_ref()
+        .map(|e| e as &(dyn std::error::Error + 'static));
+    while let Some(e) = current {
+        if let Some(io_error) = e.downcast_ref::<std::io::Error>() {
+            return io_os_error(io_error);
+        }
+        current = e.source();
+    }
+
+    None
+}
+
+/// Estimates the size of a gzipped layer once extracted from the size its gzip trailer records.
+///
+/// The trailer holds the uncompressed size modulo 2^32, so this is only exact for layers of less
+/// than 4 GiB. Returns `None` if the file is not gzipped or cannot be read.
+async fn gzip_uncompressed_size(layer_path: &Path) -> Option<u64> {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let mut file = fs::File::open(layer_path).await.ok()?;
+    let mut magic = [0u8; 2];
+    file.read_exact(&mut magic).await.ok()?;
+    if magic != [0x1f, 0x8b] {
+        return None;
+    }
+
+    let mut trailer = [0u8; 4];
+    file.seek(std::io::SeekFrom::End(-4)).await.ok()?;
+    file.read_exact(&mut trailer).await.ok()?;
+    Some(u32::from_le_bytes(trailer) as u64)
+}
+
+/// Returns the space available to unprivileged users on the filesystem of a path, in bytes.
+fn available_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+
+    let path_cstring = CString::new(path.as_os_str().as_encoded_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+
+    // SAFETY: the path is a valid C string and `stat` is only read once statvfs filled it in
+    let stat = unsafe {
+        if libc::statvfs(path_cstring.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stat.assume_init()
+    };
+
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Forwards each line of `docker build` output to the progress callback and returns the last
+/// [`BUILD_ERROR_TAIL_LINES`] lines for error reporting.
+async fn forward_build_output<R: tokio::io::AsyncRead + Unpin>(
+    reader: Option<R>,
+    progress: Option<&BuildProgressCallback>,
+) -> VecDeque<String> {
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    let mut tail = VecDeque::with_capacity(BUILD_ERROR_TAIL_LINES);
+    let Some(reader) = reader else {
+        return tail;
+    };
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        tracing::debug!("docker build: {}", line);
+        if let Some(callback) = progress {
+            callback(BuildProgress::Log(line.clone()));
+        }
+
+        if tail.len() == BUILD_ERROR_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(line);
+    }
+
+    tail
+}
+
+/// Calculates the size of an extracted layer directory by summing the sizes of its files.
+async fn get_extracted_layer_size(extract_dir: PathBuf) -> MicrosandboxResult<u64> {
+    tokio::task::spawn_blocking(move || -> MicrosandboxResult<u64> {
+        let mut total: u64 = 0;
+        for entry in walkdir::WalkDir::new(&extract_dir).follow_links(false) {
+            let entry = entry?;
+            if entry.file_type().is_file() {
+                total += entry.metadata()?.len();
+            }
+        }
+        Ok(total)
+    })
+    .await?
+}
+
 /// Collects all layer files in the given directory that start with "sha256:".
 async fn collect_layer_files(dir: impl AsRef<Path>) -> MicrosandboxResult<Vec<PathBuf>> {
     let mut layer_paths = Vec::new();
@@ -1167,6 +2131,250 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
 
+    #[test]
+    fn test_is_pulled_for_platform() {
+        let host = oci::host_platform();
+        let other_arch = if host.architecture().to_string() == "arm64" {
+            "amd64"
+        } else {
+            "arm64"
+        };
+        let other = oci::parse_platform(&format!("linux/{}", other_arch)).unwrap();
+
+        // Without a requested platform, only images pulled for the host are reused
+        assert!(is_pulled_for_platform(None, Some(&host)));
+        assert!(!is_pulled_for_platform(None, Some(&other)));
+        assert!(is_pulled_for_platform(None, None));
+
+        // A requested platform must match the one the image was pulled for
+        assert!(is_pulled_for_platform(Some(&other), Some(&other)));
+        assert!(!is_pulled_for_platform(Some(&other), Some(&host)));
+        assert!(!is_pulled_for_platform(Some(&other), None));
+    }
+
+    #[tokio::test]
+    async fn test_forward_build_output_reports_lines_and_keeps_tail() {
+        let output = (0..BUILD_ERROR_TAIL_LINES + 5)
+            .map(|i| format!("step {}", i))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let callback: BuildProgressCallback = {
+            let seen = seen.clone();
+            Box::new(move |p| seen.lock().unwrap().push(p))
+        };
+
+        let tail = forward_build_output(Some(output.as_bytes()), Some(&callback)).await;
+
+        assert_eq!(seen.lock().unwrap().len(), BUILD_ERROR_TAIL_LINES + 5);
+        assert_eq!(
+            seen.lock().unwrap()[0],
+            BuildProgress::Log("step 0".to_string())
+        );
+        assert_eq!(tail.len(), BUILD_ERROR_TAIL_LINES);
+        assert_eq!(tail.front().unwrap(), "step 5");
+        assert_eq!(
+            tail.back().unwrap(),
+            &format!("step {}", BUILD_ERROR_TAIL_LINES + 4)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_without_references_is_a_noop() -> MicrosandboxResult<()> {
+        let report = prefetch(Vec::new(), None).await?;
+        assert_eq!(report, PrefetchReport::default());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_layer_dirs_requires_every_layer() -> MicrosandboxResult<()> {
+        let layers_dir = TempDir::new()?;
+        let digests: Vec<String> = (0..5).map(|i| format!("sha256:{}", i)).collect();
+        for digest in &digests[..4] {
+            let layer_dir = layers_dir
+                .path()
+                .join(format!("{}.{}", digest, EXTRACTED_LAYER_SUFFIX));
+            std::fs::create_dir_all(layer_dir.join("etc"))?;
+        }
+
+        assert!(check_layer_dirs(layers_dir.path(), &digests[..4]).await?);
+        assert!(!check_layer_dirs(layers_dir.path(), &digests).await?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_validated_images_are_remembered() {
+        let key = (
+            "example.com/validated:latest".to_string(),
+            PathBuf::from("/validated/layers"),
+        );
+        assert!(!is_image_recently_validated(&key));
+
+        mark_image_validated(key.clone());
+        assert!(is_image_recently_validated(&key));
+
+        VALIDATED_IMAGES
+            .lock()
+            .unwrap()
+            .insert(key.clone(), Instant::now() - LAYER_CHECK_CACHE_TTL);
+        assert!(!is_image_recently_validated(&key));
+    }
+
+    #[test]
+    fn test_validated_images_are_forgotten_on_pull() {
+        let image: Reference = "example.com/forgotten:latest".parse().unwrap();
+        let other: Reference = "example.com/kept:latest".parse().unwrap();
+        let keys = [
+            (image.to_string(), PathBuf::from("/forgotten/layers")),
+            (image.to_string(), PathBuf::from("/forgotten/other-layers")),
+            (other.to_string(), PathBuf::from("/forgotten/layers")),
+        ];
+        for key in &keys {
+            mark_image_validated(key.clone());
+        }
+
+        forget_validated_image(&image);
+        assert!(!is_image_recently_validated(&keys[0]));
+        assert!(!is_image_recently_validated(&keys[1]));
+        assert!(is_image_recently_validated(&keys[2]));
+    }
+
+    #[test]
+    fn test_extract_tar_applies_ownership_override() -> MicrosandboxResult<()> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut dir_header = tar::Header::new_gnu();
+        dir_header.set_size(0);
+        dir_header.set_uid(0);
+        dir_header.set_gid(0);
+        dir_header.set_mode(0o755);
+        dir_header.set_entry_type(tar::EntryType::Directory);
+        dir_header.set_cksum();
+        builder.append_data(&mut dir_header, "etc/", std::io::empty())?;
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mode(0o644);
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_cksum();
+        builder.append_data(&mut header, "etc/hello", &b"hello"[..])?;
+        let data = builder.into_inner()?;
+
+        let temp_dir = TempDir::new()?;
+        let mut archive = Archive::new(&data[..]);
+        let ownership = OwnershipOverride {
+            uid: Some(1000),
+            gid: None,
+        };
+        extract_tar_with_ownership_override(
+            &mut archive,
+            temp_dir.path(),
+            ownership,
+            &ExtractionBudget::unlimited(),
+        )?;
+
+        let file_path = temp_dir.path().join("etc/hello");
+        assert_eq!(std::fs::read(&file_path)?, b"hello");
+
+        // The filesystem may not support user xattrs, in which case extraction falls back
+        // to leaving them unset
+        if let Ok(Some(value)) = xattr::get(&file_path, "user.containers.override_stat") {
+            assert_eq!(value, b"1000:0:0100644");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_layer_extraction_error_keeps_fd_exhaustion() {
+        let emfile = std::io::Error::from_raw_os_error(libc::EMFILE);
+        assert!(is_fd_exhaustion(&layer_extraction_error(emfile)));
+
+        // Errors from the archive wrap the original I/O error
+        let wrapped = std::io::Error::other(std::io::Error::from_raw_os_error(libc::ENFILE));
+        assert!(is_fd_exhaustion(&layer_extraction_error(wrapped)));
+
+        let not_found = std::io::Error::from(std::io::ErrorKind::NotFound);
+        let error = layer_extraction_error(not_found);
+        assert!(matches!(error, MicrosandboxError::LayerExtraction(_)));
+        assert!(!is_fd_exhaustion(&error));
+
+        let error = MicrosandboxError::LayerHandling {
+            source: std::io::Error::from_raw_os_error(libc::EMFILE),
+            layer: "sha256:abc".to_string(),
+        };
+        assert!(is_fd_exhaustion(&error));
+    }
+
+    #[test]
+    fn test_layer_extraction_error_keeps_disk_full() {
+        // Archive errors wrap the original I/O error
+        let wrapped = std::io::Error::other(std::io::Error::from_raw_os_error(libc::ENOSPC));
+        let error = layer_extraction_error(wrapped);
+        assert!(is_disk_full(&error));
+        assert!(!is_fd_exhaustion(&error));
+
+        let error = MicrosandboxError::DiskFull {
+            layer: "sha256:abc".to_string(),
+            path: PathBuf::from("/layers/sha256:abc.extracted"),
+            required: Some(2048),
+            available: Some(1024),
+        };
+        assert_eq!(
+            error.to_string(),
+            "disk full extracting layer sha256:abc to /layers/sha256:abc.extracted \
+             (needs about 2048 bytes, 1024 bytes available); free up space and try again"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gzip_uncompressed_size() -> anyhow::Result<()> {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let temp_dir = TempDir::new()?;
+        let layer_path = temp_dir.path().join("layer.tar.gz");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[0u8; 10_000])?;
+        std::fs::write(&layer_path, encoder.finish()?)?;
+        assert_eq!(gzip_uncompressed_size(&layer_path).await, Some(10_000));
+
+        let plain_path = temp_dir.path().join("layer.tar");
+        std::fs::write(&plain_path, [0u8; 512])?;
+        assert_eq!(gzip_uncompressed_size(&plain_path).await, None);
+
+        assert!(available_space(temp_dir.path()).is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_layer_download_permits_are_shared_by_the_process() -> MicrosandboxResult<()> {
+        let mut permits = Vec::new();
+        for _ in 0..env::get_max_global_pulls() {
+            permits.push(acquire_layer_download_permit().await?);
+        }
+
+        // Every slot is taken, so the next download waits until one is freed
+        let waiting = tokio::time::timeout(
+            Duration::from_millis(50),
+            acquire_layer_download_permit(),
+        );
+        assert!(waiting.await.is_err());
+
+        permits.pop();
+        let permit = tokio::time::timeout(
+            Duration::from_millis(50),
+            acquire_layer_download_permit(),
+        )
+        .await;
+        assert!(permit.is_ok());
+
+        Ok(())
+    }
+
     #[test_log::test(tokio::test)]
     #[ignore = "makes network requests to Docker registry to pull an image"]
     async fn test_image_pull_from_docker_registry() -> MicrosandboxResult<()> {
@@ -1184,7 +2392,7 @@ mod tests {
         let image_ref: Reference = "docker.io/library/nginx:stable-alpine".parse().unwrap();
 
         // Call the function under test
-        pull_from_docker_registry(&image_ref, &download_dir, None).await?;
+        pull_from_docker_registry(&image_ref, &download_dir, None, None, None).await?;
 
         // Initialize database connection for verification
         let db_path = microsandbox_home.join(OCI_DB_FILENAME);
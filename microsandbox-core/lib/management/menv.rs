@@ -158,6 +158,11 @@ pub async fn clean(
 
         // Check if .menv directory exists
         if menv_path.exists() {
+            #[cfg(feature = "cli")]
+            if !force {
+                print_removals(&remove_menv_dir_sp, &[&menv_path]);
+            }
+
             // Remove the .menv directory and all its contents
             fs::remove_dir_all(&menv_path).await?;
             tracing::info!(
@@ -216,6 +221,20 @@ pub async fn clean(
     let rw_path = menv_path.join(RW_SUBDIR).join(&namespaced_name);
     let patch_path = menv_path.join(PATCH_SUBDIR).join(&namespaced_name);
 
+    let log_file = menv_path
+        .join(LOG_SUBDIR)
+        .join(config_file)
+        .join(format!("{}.log", sandbox_name));
+
+    #[cfg(feature = "cli")]
+    if !force {
+        let existing = [&rw_path, &patch_path, &log_file]
+            .into_iter()
+            .filter(|path| path.exists())
+            .collect::<Vec<_>>();
+        print_removals(&clean_sandbox_sp, &existing);
+    }
+
     // Remove sandbox directories if they exist
     if rw_path.exists() {
         fs::remove_dir_all(&rw_path).await?;
@@ -231,11 +250,6 @@ pub async fn clean(
     }
 
     // Remove log file if it exists
-    let log_file = menv_path
-        .join(LOG_SUBDIR)
-        .join(config_file)
-        .join(format!("{}.log", sandbox_name));
-
     if log_file.exists() {
         fs::remove_file(&log_file).await?;
         tracing::info!("Removed sandbox log file at {}", log_file.display());
@@ -649,6 +663,19 @@ pub fn print_namespace_header(namespace: &str) {
 // Functions: Helpers
 //--------------------------------------------------------------------------------------------------
 
+/// Print the paths that are about to be removed above a spinner
+#[cfg(feature = "cli")]
+fn print_removals(spinner: &indicatif::ProgressBar, paths: &[&PathBuf]) {
+    if paths.is_empty() {
+        return;
+    }
+
+    spinner.println("Removing:");
+    for path in paths {
+        spinner.println(format!("  {}", path.display()));
+    }
+}
+
 /// Create the required directories and files for a microsandbox environment
 pub(crate) async fn ensure_menv_files(menv_path: &PathBuf) -> MicrosandboxResult<()> {
     // Create log directory if it doesn't exist
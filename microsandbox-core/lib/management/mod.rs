@@ -14,6 +14,7 @@
 //! - `orchestra`: Orchestra management for sandboxes
 //! - `home`: Home directory management
 //! - `toolchain`: Toolchain management
+//! - `volume`: Named volume management
 
 //--------------------------------------------------------------------------------------------------
 // Exports
@@ -28,3 +29,4 @@ pub mod orchestra;
 pub mod rootfs;
 pub mod sandbox;
 pub mod toolchain;
+pub mod volume;
@@ -8,18 +8,24 @@
 //! The main operations provided by this module are:
 //! - `up`: Start up all sandboxes defined in configuration
 //! - `down`: Gracefully shut down all running sandboxes
-//! - `apply`: Reconcile running sandboxes with configuration
+//! - `apply`: Reconcile running sandboxes with configuration, prefetching their images first
+//! - `reconcile`: Clean up orphaned sandbox processes and dangling database records
 
 use crate::{
-    config::{Microsandbox, START_SCRIPT_NAME},
+    config::{Microsandbox, ReferenceOrPath, START_SCRIPT_NAME},
+    models,
+    runtime::SANDBOX_STATUS_RUNNING,
     MicrosandboxError, MicrosandboxResult,
 };
 
+use chrono::{DateTime, Utc};
+
 #[cfg(feature = "cli")]
 use console::style;
+use futures::stream::{FuturesUnordered, StreamExt};
 #[cfg(feature = "cli")]
 use microsandbox_utils::term;
-use microsandbox_utils::{MICROSANDBOX_ENV_DIR, SANDBOX_DB_FILENAME};
+use microsandbox_utils::{env, MICROSANDBOX_ENV_DIR, SANDBOX_DB_FILENAME};
 use nix::{
     sys::signal::{self, Signal},
     unistd::Pid,
@@ -28,13 +34,14 @@ use once_cell::sync::Lazy;
 #[cfg(feature = "cli")]
 use std::io::{self, IsTerminal};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    future::Future,
     path::{Path, PathBuf},
     sync::RwLock,
     time::{Duration, Instant},
 };
 
-use super::{config, db, menv, sandbox};
+use super::{config, db, image, menv, sandbox};
 
 //--------------------------------------------------------------------------------------------------
 // Constants
@@ -52,6 +59,10 @@ const START_SANDBOXES_MSG: &str = "Starting sandboxes";
 #[cfg(feature = "cli")]
 const STOP_SANDBOXES_MSG: &str = "Stopping sandboxes";
 
+/// How long to wait before re-checking a supervisor that has no database record. A freshly
+/// started supervisor registers its sandbox shortly after launch, so this avoids killing it.
+const ORPHAN_RECHECK_DELAY: Duration = Duration::from_secs(2);
+
 /// Global cache path -> (size, last_updated)
 static DISK_SIZE_CACHE: Lazy<RwLock<HashMap<String, (u64, Instant)>>> =
     Lazy::new(|| RwLock::new(HashMap::new()));
@@ -88,6 +99,83 @@ pub struct SandboxStatus {
     pub rootfs_paths: Option<String>,
 }
 
+/// A sandbox process or record that was acted on during reconciliation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReconciledSandbox {
+    /// The namespace the sandbox belongs to, if it could be determined
+    pub namespace: Option<String>,
+
+    /// The name of the sandbox
+    pub name: String,
+
+    /// The config file that defines the sandbox
+    pub config_file: String,
+
+    /// The PID of the process that was stopped, or the supervisor PID of the removed record
+    pub pid: u32,
+}
+
+/// The outcome of reconciling running sandbox processes against their database records
+#[derive(Debug, Clone, Default)]
+pub struct ReconcileReport {
+    /// Whether this was a dry run, in which case nothing was stopped or removed
+    pub dry_run: bool,
+
+    /// Processes that were running with no matching database record and were stopped
+    pub orphaned_vms_stopped: Vec<ReconciledSandbox>,
+
+    /// Database records marked as running whose supervisor is gone, and were removed
+    pub dangling_records_removed: Vec<ReconciledSandbox>,
+}
+
+/// The outcome of applying a configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApplyReport {
+    /// Sandboxes in the config that were not running and were started
+    pub created: Vec<String>,
+
+    /// Running sandboxes that are no longer in the config and were stopped
+    pub removed: Vec<String>,
+
+    /// Running sandboxes whose config file changed since they started. They are left running
+    /// as they are and pick up the changes when restarted
+    pub updated: Vec<String>,
+
+    /// Running sandboxes whose config file has not changed since they started
+    pub unchanged: Vec<String>,
+
+    /// Sandboxes that could not be started or stopped
+    pub errors: Vec<ApplyError>,
+}
+
+/// A sandbox that could not be started or stopped while applying a configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApplyError {
+    /// The name of the sandbox
+    pub name: String,
+
+    /// What went wrong
+    pub message: String,
+}
+
+/// A running `msbrun` process discovered on the host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MsbrunProcess {
+    pid: u32,
+    ppid: u32,
+    kind: MsbrunProcessKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MsbrunProcessKind {
+    Supervisor {
+        sandbox_name: String,
+        config_file: String,
+        sandbox_db_path: PathBuf,
+    },
+    MicroVm,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
@@ -99,6 +187,13 @@ pub struct SandboxStatus {
 /// - Starting any sandboxes that are in the config but not running
 /// - Stopping any sandboxes that are running but not in the config
 ///
+/// A sandbox that fails to start or stop does not stop the others from being applied; the
+/// failure is recorded in the returned report instead.
+///
+/// In detached mode, sandboxes are started in `depends_on` order with up to `parallelism` of
+/// them starting at the same time. A sandbox waits for the sandboxes it depends on to start,
+/// and is not started if one of them fails to.
+///
 /// The function uses a file-based lock to prevent concurrent apply operations.
 /// If another apply operation is in progress, this function will fail immediately.
 /// The lock is automatically released when the function completes or if it fails.
@@ -108,13 +203,16 @@ pub struct SandboxStatus {
 /// * `project_dir` - Optional path to the project directory. If None, defaults to current directory
 /// * `config_file` - Optional path to the Microsandbox config file. If None, uses default filename
 /// * `detach` - Whether to run sandboxes in detached mode (true) or with prefixed output (false)
+/// * `parallelism` - How many sandboxes to start at the same time in detached mode. If None,
+///   uses `MSB_APPLY_PARALLELISM` or the default
 ///
 /// ## Returns
 ///
-/// Returns `MicrosandboxResult<()>` indicating success or failure. Possible failures include:
+/// Returns an `ApplyReport` listing the sandboxes that were created, removed, updated or left
+/// unchanged, and those that failed to start or stop. Possible failures include:
 /// - Config file not found or invalid
 /// - Database errors
-/// - Sandbox start/stop failures
+/// - Image prefetch failures
 ///
 /// ## Example
 ///
@@ -125,13 +223,15 @@ pub struct SandboxStatus {
 /// #[tokio::main]
 /// async fn main() -> anyhow::Result<()> {
 ///     // Apply configuration changes from the default microsandbox.yaml
-///     orchestra::apply(None, None, true).await?;
+///     let report = orchestra::apply(None, None, true, None).await?;
+///     println!("{} created, {} removed", report.created.len(), report.removed.len());
 ///
 ///     // Or specify a custom project directory and config file, in non-detached mode
 ///     orchestra::apply(
 ///         Some(&PathBuf::from("/path/to/project")),
 ///         Some("custom-config.yaml"),
 ///         false,
+///         None,
 ///     ).await?;
 ///     Ok(())
 /// }
@@ -140,7 +240,8 @@ pub async fn apply(
     project_dir: Option<&Path>,
     config_file: Option<&str>,
     detach: bool,
-) -> MicrosandboxResult<()> {
+    parallelism: Option<usize>,
+) -> MicrosandboxResult<ApplyReport> {
     // Create spinner for CLI feedback
     #[cfg(feature = "cli")]
     let apply_config_sp = term::create_spinner(APPLY_CONFIG_MSG.to_string(), None, None);
@@ -196,29 +297,103 @@ pub async fn apply(
         .filter(|name| !running_sandbox_names.contains(*name))
         .collect();
 
-    if sandboxes_to_start.is_empty() {
-        tracing::info!("No new sandboxes to start");
-    } else if detach {
-        // Start sandboxes in detached mode
-        for name in sandboxes_to_start {
-            tracing::info!("starting sandbox: {}", name);
-            if let Err(e) = sandbox::run(
-                name,
-                Some(START_SCRIPT_NAME),
-                Some(&canonical_project_dir),
-                Some(&config_file),
-                vec![],
-                true, // detached mode
-                None,
-                true,
-            )
+    // Running sandboxes started before the last change to the config file are out of date
+    let config_last_modified: DateTime<Utc> =
+        match tokio::fs::metadata(canonical_project_dir.join(&config_file))
             .await
-            {
+            .and_then(|metadata| metadata.modified())
+        {
+            Ok(modified) => modified.into(),
+            Err(e) => {
                 #[cfg(feature = "cli")]
                 term::finish_with_error(&apply_config_sp);
-                return Err(e);
+                return Err(e.into());
             }
+        };
+    let (sandboxes_to_stop, updated, unchanged) = sort_running_sandboxes(
+        &running_sandboxes,
+        |name| config_sandboxes.contains_key(name),
+        config_last_modified,
+    );
+    let mut report = ApplyReport {
+        updated,
+        unchanged,
+        ..Default::default()
+    };
+
+    // Pull the images of all sandboxes to start up front and concurrently, rather than one by
+    // one as each sandbox starts
+    let images_to_prefetch = sandboxes_to_start.iter().filter_map(|name| {
+        match config_sandboxes.get(*name).map(|sandbox| sandbox.get_image()) {
+            Some(ReferenceOrPath::Reference(reference)) => Some(reference.clone()),
+            _ => None,
+        }
+    });
+    match image::prefetch(images_to_prefetch, None).await {
+        Ok(report) => tracing::info!(
+            "prefetched images: {} cache hits ({}), {} pulled ({})",
+            report.cache_hits.len(),
+            report
+                .cache_hits
+                .iter()
+                .map(|r| r.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            report.pulled.len(),
+            report
+                .pulled
+                .iter()
+                .map(|r| r.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Err(e) => {
+            #[cfg(feature = "cli")]
+            term::finish_with_error(&apply_config_sp);
+            return Err(e);
         }
+    }
+
+    if sandboxes_to_start.is_empty() {
+        tracing::info!("No new sandboxes to start");
+    } else if detach {
+        // Only dependencies that are being started too have to be waited for
+        let dependencies: HashMap<String, Vec<String>> = sandboxes_to_start
+            .iter()
+            .map(|name| {
+                let depends_on = config_sandboxes[*name]
+                    .get_depends_on()
+                    .iter()
+                    .filter(|dependency| sandboxes_to_start.contains(dependency))
+                    .cloned()
+                    .collect();
+                ((*name).clone(), depends_on)
+            })
+            .collect();
+
+        // Start sandboxes in detached mode
+        let parallelism = parallelism.unwrap_or_else(env::get_apply_parallelism);
+        let (created, errors) = start_in_dependency_order(&dependencies, parallelism, |name| {
+            let canonical_project_dir = &canonical_project_dir;
+            let config_file = &config_file;
+            async move {
+                tracing::info!("starting sandbox: {}", name);
+                sandbox::run(
+                    &name,
+                    Some(START_SCRIPT_NAME),
+                    Some(canonical_project_dir),
+                    Some(config_file),
+                    vec![],
+                    true, // detached mode
+                    None,
+                    true,
+                )
+                .await
+            }
+        })
+        .await;
+        report.created = created;
+        report.errors = errors;
     } else {
         // Start sandboxes in non-detached mode with multiplexed output
         let sandbox_commands = match prepare_sandbox_commands(
@@ -247,29 +422,34 @@ pub async fn apply(
             }
 
             // Return early as we've already finished the spinner
-            return Ok(());
+            report.created = sandboxes_to_start.into_iter().cloned().collect();
+            return Ok(report);
         }
     }
 
     // Stop sandboxes that are active but not in config
-    for sandbox in running_sandboxes {
-        if !config_sandboxes.contains_key(&sandbox.name) {
-            tracing::info!("stopping sandbox: {}", sandbox.name);
-            if let Err(e) = signal::kill(
-                Pid::from_raw(sandbox.supervisor_pid as i32),
-                Signal::SIGTERM,
-            ) {
-                #[cfg(feature = "cli")]
-                term::finish_with_error(&apply_config_sp);
-                return Err(e.into());
-            }
+    for sandbox in sandboxes_to_stop {
+        tracing::info!("stopping sandbox: {}", sandbox.name);
+        match signal::kill(
+            Pid::from_raw(sandbox.supervisor_pid as i32),
+            Signal::SIGTERM,
+        ) {
+            Ok(()) => report.removed.push(sandbox.name.clone()),
+            Err(e) => report.errors.push(ApplyError {
+                name: sandbox.name.clone(),
+                message: MicrosandboxError::from(e).to_string(),
+            }),
         }
     }
 
     #[cfg(feature = "cli")]
-    apply_config_sp.finish();
+    if report.errors.is_empty() {
+        apply_config_sp.finish();
+    } else {
+        term::finish_with_error(&apply_config_sp);
+    }
 
-    Ok(())
+    Ok(report)
 }
 
 /// Starts specified sandboxes from the configuration if they are not already running.
@@ -920,10 +1100,315 @@ pub async fn show_status_namespaces(
     Ok(())
 }
 
+/// Cross-checks running sandbox processes against the sandbox databases of all namespaces and
+/// cleans up whatever has drifted apart, e.g. after a crash.
+///
+/// Two kinds of drift are handled:
+/// - A supervisor process that is still running but has no matching `RUNNING` record is
+///   considered orphaned and is sent `SIGTERM`, which also stops its microVM.
+/// - A `RUNNING` record whose supervisor is gone is considered dangling and is removed. If the
+///   microVM it points at outlived its supervisor, that microVM is stopped as well.
+///
+/// ## Arguments
+///
+/// * `namespaces_parent_dir` - The directory containing all namespace directories
+/// * `dry_run` - If true, only report what would be done without stopping or removing anything
+///
+/// ## Returns
+///
+/// Returns a `ReconcileReport` describing the orphaned processes stopped and the dangling
+/// records removed. Possible failures include:
+/// - The namespaces directory does not exist
+/// - Database errors
+/// - Failure to list or signal processes
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::path::PathBuf;
+/// use microsandbox_core::management::orchestra;
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let report = orchestra::reconcile(&PathBuf::from("/path/to/namespaces"), true).await?;
+///     println!("{} orphaned, {} dangling",
+///         report.orphaned_vms_stopped.len(),
+///         report.dangling_records_removed.len());
+///     Ok(())
+/// }
+/// ```
+pub async fn reconcile(
+    namespaces_parent_dir: &Path,
+    dry_run: bool,
+) -> MicrosandboxResult<ReconcileReport> {
+    if !namespaces_parent_dir.exists() {
+        return Err(MicrosandboxError::PathNotFound(format!(
+            "Namespaces directory not found at {}",
+            namespaces_parent_dir.display()
+        )));
+    }
+
+    let namespaces_parent_dir = namespaces_parent_dir.canonicalize()?;
+    let mut report = ReconcileReport {
+        dry_run,
+        ..Default::default()
+    };
+
+    // Only consider supervisors that belong to one of our namespaces
+    let processes = list_msbrun_processes().await?;
+    let supervisors: Vec<&MsbrunProcess> = processes
+        .iter()
+        .filter(|p| match &p.kind {
+            MsbrunProcessKind::Supervisor {
+                sandbox_db_path, ..
+            } => sandbox_db_path.starts_with(&namespaces_parent_dir),
+            MsbrunProcessKind::MicroVm => false,
+        })
+        .collect();
+
+    let mut namespace_dirs = Vec::new();
+    let mut entries = tokio::fs::read_dir(&namespaces_parent_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.is_dir() {
+            namespace_dirs.push(path);
+        }
+    }
+    namespace_dirs.sort();
+
+    // Remove running records whose supervisor is no longer alive
+    let mut tracked_supervisors = HashSet::new();
+    for namespace_dir in &namespace_dirs {
+        let db_path = namespace_dir
+            .join(MICROSANDBOX_ENV_DIR)
+            .join(SANDBOX_DB_FILENAME);
+        if !db_path.exists() {
+            continue;
+        }
+
+        let namespace = namespace_dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string());
+        let pool = db::get_or_create_pool(&db_path, &db::SANDBOX_DB_MIGRATOR).await?;
+
+        for record in db::get_running_sandboxes(&pool).await? {
+            let supervisor_alive = supervisors.iter().any(|p| {
+                p.pid == record.supervisor_pid
+                    && matches!(&p.kind, MsbrunProcessKind::Supervisor { sandbox_name, config_file, .. }
+                        if *sandbox_name == record.name && *config_file == record.config_file)
+            });
+
+            if supervisor_alive {
+                tracked_supervisors.insert(record.supervisor_pid);
+                continue;
+            }
+
+            // A microVM whose supervisor crashed keeps running on its own
+            let orphaned_microvm = processes.iter().any(|p| {
+                p.pid == record.microvm_pid
+                    && p.kind == MsbrunProcessKind::MicroVm
+                    && !supervisors.iter().any(|s| s.pid == p.ppid)
+            });
+
+            if orphaned_microvm {
+                tracing::info!(
+                    "stopping orphaned microvm {} of sandbox {}",
+                    record.microvm_pid,
+                    record.name
+                );
+                if !dry_run {
+                    terminate_process(record.microvm_pid)?;
+                }
+
+                report.orphaned_vms_stopped.push(ReconciledSandbox {
+                    namespace: namespace.clone(),
+                    name: record.name.clone(),
+                    config_file: record.config_file.clone(),
+                    pid: record.microvm_pid,
+                });
+            }
+
+            tracing::info!("removing dangling record for sandbox {}", record.name);
+            if !dry_run {
+                db::delete_sandbox(&pool, &record.name, &record.config_file).await?;
+            }
+
+            report.dangling_records_removed.push(ReconciledSandbox {
+                namespace: namespace.clone(),
+                name: record.name,
+                config_file: record.config_file,
+                pid: record.supervisor_pid,
+            });
+        }
+    }
+
+    // Stop supervisors that are not backed by a running record
+    let candidates: Vec<&MsbrunProcess> = supervisors
+        .into_iter()
+        .filter(|p| !tracked_supervisors.contains(&p.pid))
+        .collect();
+
+    if !candidates.is_empty() {
+        tokio::time::sleep(ORPHAN_RECHECK_DELAY).await;
+    }
+
+    for process in candidates {
+        let MsbrunProcessKind::Supervisor {
+            sandbox_name,
+            config_file,
+            sandbox_db_path,
+        } = &process.kind
+        else {
+            continue;
+        };
+
+        if sandbox_db_path.exists() {
+            let pool = db::get_or_create_pool(sandbox_db_path, &db::SANDBOX_DB_MIGRATOR).await?;
+            let registered = db::get_sandbox(&pool, sandbox_name, config_file)
+                .await?
+                .is_some_and(|s| {
+                    s.supervisor_pid == process.pid && s.status == SANDBOX_STATUS_RUNNING
+                });
+
+            if registered {
+                continue;
+            }
+        }
+
+        tracing::info!(
+            "stopping orphaned supervisor {} of sandbox {}",
+            process.pid,
+            sandbox_name
+        );
+        if !dry_run {
+            terminate_process(process.pid)?;
+        }
+
+        report.orphaned_vms_stopped.push(ReconciledSandbox {
+            namespace: sandbox_db_path
+                .strip_prefix(&namespaces_parent_dir)
+                .ok()
+                .and_then(|p| p.components().next())
+                .map(|c| c.as_os_str().to_string_lossy().to_string()),
+            name: sandbox_name.clone(),
+            config_file: config_file.clone(),
+            pid: process.pid,
+        });
+    }
+
+    Ok(report)
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions: Helpers
 //--------------------------------------------------------------------------------------------------
 
+/// Sorts the running sandboxes of a config into those it no longer defines, and the names of
+/// those it defines that started before or after its last change.
+fn sort_running_sandboxes(
+    running_sandboxes: &[models::Sandbox],
+    is_defined: impl Fn(&str) -> bool,
+    config_last_modified: DateTime<Utc>,
+) -> (Vec<&models::Sandbox>, Vec<String>, Vec<String>) {
+    let mut undefined = Vec::new();
+    let mut outdated = Vec::new();
+    let mut current = Vec::new();
+    for sandbox in running_sandboxes {
+        if !is_defined(&sandbox.name) {
+            undefined.push(sandbox);
+        } else if sandbox.config_last_modified < config_last_modified {
+            outdated.push(sandbox.name.clone());
+        } else {
+            current.push(sandbox.name.clone());
+        }
+    }
+
+    (undefined, outdated, current)
+}
+
+/// Starts sandboxes after the sandboxes they depend on, running up to `parallelism` starts at
+/// the same time.
+///
+/// `dependencies` maps each sandbox to start to the sandboxes among them it depends on. A
+/// sandbox whose dependency fails to start is not started, and neither is one waiting on a
+/// dependency cycle. Returns the sandboxes that started and the errors of those that did not.
+async fn start_in_dependency_order<F, Fut>(
+    dependencies: &HashMap<String, Vec<String>>,
+    parallelism: usize,
+    start: F,
+) -> (Vec<String>, Vec<ApplyError>)
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = MicrosandboxResult<()>>,
+{
+    let mut pending: Vec<&String> = dependencies.keys().collect();
+    pending.sort();
+
+    let mut started = Vec::new();
+    let mut errors = Vec::new();
+    let mut failed = HashSet::new();
+    let mut in_flight = FuturesUnordered::new();
+    let start = &start;
+
+    loop {
+        // Give up on sandboxes whose dependencies failed, which in turn fails their dependents
+        while let Some((index, dependency)) = pending.iter().enumerate().find_map(|(i, name)| {
+            dependencies[*name]
+                .iter()
+                .find(|dependency| failed.contains(dependency.as_str()))
+                .map(|dependency| (i, dependency))
+        }) {
+            let name = pending.remove(index);
+            errors.push(ApplyError {
+                name: name.clone(),
+                message: format!("dependency {} failed to start", dependency),
+            });
+            failed.insert(name.clone());
+        }
+
+        // Start the sandboxes whose dependencies have all started, up to the limit
+        while in_flight.len() < parallelism.max(1) {
+            let Some(index) = pending.iter().position(|name| {
+                dependencies[*name]
+                    .iter()
+                    .all(|dependency| started.contains(dependency))
+            }) else {
+                break;
+            };
+
+            let name = pending.remove(index).clone();
+            in_flight.push(async move {
+                let result = start(name.clone()).await;
+                (name, result)
+            });
+        }
+
+        let Some((name, result)) = in_flight.next().await else {
+            break;
+        };
+        match result {
+            Ok(()) => started.push(name),
+            Err(e) => {
+                errors.push(ApplyError {
+                    name: name.clone(),
+                    message: e.to_string(),
+                });
+                failed.insert(name);
+            }
+        }
+    }
+
+    // Whatever is left waits on a dependency cycle
+    for name in pending {
+        errors.push(ApplyError {
+            name: name.clone(),
+            message: "waits on a depends_on cycle".to_string(),
+        });
+    }
+
+    (started, errors)
+}
+
 // Helper function to prepare commands for multiple sandboxes
 async fn prepare_sandbox_commands(
     sandbox_names: &[&String],
@@ -1622,6 +2107,71 @@ async fn get_directory_size(path: &str) -> MicrosandboxResult<u64> {
     Ok(size)
 }
 
+/// Lists all running `msbrun` supervisor and microVM processes on the host.
+///
+/// This shells out to `ps` rather than using psutil since reading another process's command line
+/// is not supported by psutil on macOS.
+async fn list_msbrun_processes() -> MicrosandboxResult<Vec<MsbrunProcess>> {
+    let output = tokio::process::Command::new("ps")
+        .args(["-axww", "-o", "pid=,ppid=,command="])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(MicrosandboxError::custom(anyhow::anyhow!(
+            "ps exited with status {}",
+            output.status
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse_ps_line)
+        .collect())
+}
+
+/// Parses a `pid ppid command` line produced by `ps` into an `MsbrunProcess`.
+///
+/// Returns `None` for lines that do not belong to an `msbrun` supervisor or microVM.
+fn parse_ps_line(line: &str) -> Option<MsbrunProcess> {
+    let mut fields = line.trim_start().splitn(2, char::is_whitespace);
+    let pid = fields.next()?.parse().ok()?;
+    let rest = fields.next()?.trim_start();
+    let mut fields = rest.splitn(2, char::is_whitespace);
+    let ppid = fields.next()?.parse().ok()?;
+    let command = fields.next()?.trim_start();
+
+    let kind = if command.contains(" supervisor --") {
+        // Flag values may contain spaces, so each one runs up to the next flag
+        let flag_value = |flag: &str| {
+            let start = command.find(&format!(" {} ", flag))? + flag.len() + 2;
+            let value = &command[start..];
+            let end = value.find(" --").unwrap_or(value.len());
+            Some(value[..end].to_string())
+        };
+
+        MsbrunProcessKind::Supervisor {
+            sandbox_name: flag_value("--sandbox-name")?,
+            config_file: flag_value("--config-file")?,
+            sandbox_db_path: PathBuf::from(flag_value("--sandbox-db-path")?),
+        }
+    } else if command.contains(" microvm --exec-path=") {
+        MsbrunProcessKind::MicroVm
+    } else {
+        return None;
+    };
+
+    Some(MsbrunProcess { pid, ppid, kind })
+}
+
+/// Sends `SIGTERM` to a process, treating an already exited process as success.
+fn terminate_process(pid: u32) -> MicrosandboxResult<()> {
+    match signal::kill(Pid::from_raw(pid as i32), Signal::SIGTERM) {
+        Ok(()) | Err(nix::errno::Errno::ESRCH) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
 /// Checks if specified sandboxes from the configuration are running.
 async fn _check_running(
     sandbox_names: Vec<String>,
@@ -1663,3 +2213,157 @@ async fn _check_running(
 
     Ok(statuses)
 }
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ps_line_supervisor() {
+        let line = "  4242     1 /usr/local/bin/msbrun supervisor --log-dir /home/me/ns/.menv/log \
+            --sandbox-name app --config-file Sandboxfile --config-last-modified 2025-01-01T00:00:00+00:00 \
+            --sandbox-db-path /home/my user/ns/.menv/sandbox.db --scope public --exec-path /bin/sh";
+
+        let process = parse_ps_line(line).unwrap();
+        assert_eq!(process.pid, 4242);
+        assert_eq!(process.ppid, 1);
+        assert_eq!(
+            process.kind,
+            MsbrunProcessKind::Supervisor {
+                sandbox_name: "app".to_string(),
+                config_file: "Sandboxfile".to_string(),
+                sandbox_db_path: PathBuf::from("/home/my user/ns/.menv/sandbox.db"),
+            }
+        );
+    }
+
+    #[test]
+    fn test_sort_running_sandboxes() {
+        let config_last_modified = DateTime::parse_from_rfc3339("2025-01-02T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let running = |name: &str, modified: &str| models::Sandbox {
+            id: 0,
+            name: name.to_string(),
+            config_file: "Sandboxfile".to_string(),
+            config_last_modified: DateTime::parse_from_rfc3339(modified)
+                .unwrap()
+                .with_timezone(&Utc),
+            status: SANDBOX_STATUS_RUNNING.to_string(),
+            supervisor_pid: 1,
+            microvm_pid: 2,
+            rootfs_paths: String::new(),
+            created_at: config_last_modified,
+            modified_at: config_last_modified,
+        };
+        let sandboxes = [
+            running("stale", "2025-01-01T00:00:00Z"),
+            running("current", "2025-01-02T00:00:00Z"),
+            running("gone", "2025-01-01T00:00:00Z"),
+        ];
+
+        let (undefined, outdated, current) = sort_running_sandboxes(
+            &sandboxes,
+            |name| name != "gone",
+            config_last_modified,
+        );
+        assert_eq!(
+            undefined.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(),
+            ["gone"]
+        );
+        assert_eq!(outdated, ["stale"]);
+        assert_eq!(current, ["current"]);
+    }
+
+    #[tokio::test]
+    async fn test_start_in_dependency_order() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        };
+
+        let dependencies: HashMap<String, Vec<String>> = [
+            ("db", vec![]),
+            ("cache", vec![]),
+            ("queue", vec![]),
+            ("api", vec!["db", "cache"]),
+            ("web", vec!["api"]),
+            ("broken", vec![]),
+            ("worker", vec!["broken"]),
+            ("cron", vec!["worker"]),
+            ("ping", vec!["pong"]),
+            ("pong", vec!["ping"]),
+        ]
+        .into_iter()
+        .map(|(name, deps)| {
+            (
+                name.to_string(),
+                deps.into_iter().map(str::to_string).collect(),
+            )
+        })
+        .collect();
+
+        let order = Mutex::new(Vec::new());
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        let (started, errors) = start_in_dependency_order(&dependencies, 2, |name| {
+            let (order, running, max_running) = (&order, &running, &max_running);
+            async move {
+                let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now_running, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+
+                order.lock().unwrap().push(name.clone());
+                if name == "broken" {
+                    return Err(MicrosandboxError::SupervisorError("boom".to_string()));
+                }
+                Ok(())
+            }
+        })
+        .await;
+
+        // Independent sandboxes start in parallel, but never more than the limit
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+
+        // Dependents start after their dependencies
+        let order = order.into_inner().unwrap();
+        let position = |name: &str| order.iter().position(|n| n == name).unwrap();
+        assert!(position("api") > position("db"));
+        assert!(position("api") > position("cache"));
+        assert!(position("web") > position("api"));
+
+        let mut started = started;
+        started.sort();
+        assert_eq!(started, ["api", "cache", "db", "queue", "web"]);
+
+        // Failures propagate to dependents, and cycles are never started
+        let errors: HashMap<&str, &str> = errors
+            .iter()
+            .map(|e| (e.name.as_str(), e.message.as_str()))
+            .collect();
+        assert_eq!(errors.len(), 5);
+        assert!(errors["broken"].contains("boom"));
+        assert_eq!(errors["worker"], "dependency broken failed to start");
+        assert_eq!(errors["cron"], "dependency worker failed to start");
+        assert_eq!(errors["ping"], "waits on a depends_on cycle");
+        assert_eq!(errors["pong"], "waits on a depends_on cycle");
+        assert!(!order.iter().any(|n| n == "worker" || n == "ping"));
+    }
+
+    #[test]
+    fn test_parse_ps_line_microvm_and_unrelated() {
+        let process =
+            parse_ps_line("4243 4242 /usr/local/bin/msbrun microvm --exec-path=/bin/sh").unwrap();
+        assert_eq!(process.pid, 4243);
+        assert_eq!(process.ppid, 4242);
+        assert_eq!(process.kind, MsbrunProcessKind::MicroVm);
+
+        assert!(parse_ps_line("100 1 /usr/bin/bash --login").is_none());
+        assert!(parse_ps_line("not a pid line").is_none());
+    }
+}
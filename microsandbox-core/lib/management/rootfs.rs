@@ -5,16 +5,24 @@
 //! Container Initiative) specifications.
 
 use std::{
-    collections::HashMap,
-    fs::Permissions,
+    collections::{HashMap, VecDeque},
+    ffi::OsString,
+    fs::{Metadata, Permissions},
+    net::IpAddr,
     os::unix::fs::PermissionsExt,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
 use async_recursion::async_recursion;
 use tokio::fs;
 
-use crate::{config::PathPair, vm::VIRTIOFS_TAG_PREFIX, MicrosandboxResult};
+use microsandbox_utils::env;
+
+use crate::{
+    config::{HostEntry, PathPair},
+    vm::VIRTIOFS_TAG_PREFIX,
+    MicrosandboxResult,
+};
 
 //--------------------------------------------------------------------------------------------------
 // Constants
@@ -26,10 +34,25 @@ pub const OPAQUE_WHITEOUT_MARKER: &str = ".wh..wh..opq";
 /// The prefix for whiteout files in OCI layers.
 pub const WHITEOUT_PREFIX: &str = ".wh.";
 
+/// The maximum number of symlinks followed when resolving a path inside a rootfs.
+const MAX_SYMLINK_FOLLOWS: usize = 40;
+
 //--------------------------------------------------------------------------------------------------
 // Structs
 //--------------------------------------------------------------------------------------------------
 
+/// A uid/gid override applied to the ownership that files report inside the sandbox.
+///
+/// When a field is `None`, the ownership recorded in the image layer is kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OwnershipOverride {
+    /// The uid every file is reported as owned by
+    pub uid: Option<u32>,
+
+    /// The gid every file is reported as owned by
+    pub gid: Option<u32>,
+}
+
 /// RAII guard that temporarily changes file permissions and restores them when dropped
 struct PermissionGuard {
     path: PathBuf,
@@ -55,6 +78,24 @@ impl PermissionGuard {
     }
 }
 
+impl OwnershipOverride {
+    /// Reads the override from the `MSB_LAYER_UID` and `MSB_LAYER_GID` environment variables.
+    pub fn from_env() -> Self {
+        Self {
+            uid: env::get_layer_uid_override(),
+            gid: env::get_layer_gid_override(),
+        }
+    }
+
+    /// Returns the uid and gid to record for a file originally owned by `uid:gid`.
+    pub fn apply(&self, uid: u64, gid: u64) -> (u64, u64) {
+        (
+            self.uid.map(u64::from).unwrap_or(uid),
+            self.gid.map(u64::from).unwrap_or(gid),
+        )
+    }
+}
+
 impl Drop for PermissionGuard {
     fn drop(&mut self) {
         // Attempt to restore original permissions, ignore errors during drop
@@ -205,9 +246,9 @@ pub async fn patch_with_virtiofs_mounts(
 /// Creates the file if it doesn't exist.
 ///
 /// This method:
-/// 1. Creates or updates the /etc/hosts file in the guest rootfs
-/// 2. Adds entries for each IP address and hostname pair
-/// 3. Sets appropriate permissions on the hosts file
+/// 1. Starts from the /etc/hosts file of the topmost layer that has one
+/// 2. Adds entries for each hostname and IP address pair that is not already present
+/// 3. Writes the result to the top layer and sets appropriate permissions on it
 ///
 /// ## Format
 /// Each hostname mapping follows the standard hosts file format:
@@ -217,31 +258,37 @@ pub async fn patch_with_virtiofs_mounts(
 /// ```
 ///
 /// ## Arguments
-/// * `root_path` - Path to the guest rootfs
-/// * `hostname_mappings` - List of (IPv4 address, hostname) pairs to add
+/// * `root_paths` - Paths to the layers of the guest rootfs, from bottom to top
+/// * `host_entries` - The hostname mappings to add
 ///
 /// ## Errors
 /// Returns an error if:
 /// - Cannot create directories in the rootfs
 /// - Cannot read or write the hosts file
 /// - Cannot set permissions on the hosts file
-async fn _patch_with_hostnames(
-    root_path: &Path,
-    hostname_mappings: &[(std::net::Ipv4Addr, String)],
+pub async fn patch_with_hostnames(
+    root_paths: &[PathBuf],
+    host_entries: &[HostEntry],
 ) -> MicrosandboxResult<()> {
-    let hosts_path = root_path.join("etc/hosts");
+    let Some(top_layer) = root_paths.last() else {
+        return Ok(());
+    };
+    let hosts_path = top_layer.join("etc/hosts");
 
     // Create parent directories if they don't exist
     if let Some(parent) = hosts_path.parent() {
         fs::create_dir_all(parent).await?;
     }
 
-    // Read existing hosts content if it exists
-    let mut hosts_content = if hosts_path.exists() {
-        fs::read_to_string(&hosts_path).await?
-    } else {
-        String::new()
-    };
+    // Read the hosts content visible through the layers, if there is any
+    let mut hosts_content = String::new();
+    for root_path in root_paths.iter().rev() {
+        let layer_hosts_path = root_path.join("etc/hosts");
+        if layer_hosts_path.exists() {
+            hosts_content = fs::read_to_string(&layer_hosts_path).await?;
+            break;
+        }
+    }
 
     // Add header comment if file is empty
     if hosts_content.is_empty() {
@@ -251,13 +298,15 @@ async fn _patch_with_hostnames(
              127.0.0.1\tlocalhost\n\
              ::1\tlocalhost ip6-localhost ip6-loopback\n",
         );
+    } else if !hosts_content.ends_with('\n') {
+        hosts_content.push('\n');
     }
 
     // Add entries for hostname mappings
-    for (ip_addr, hostname) in hostname_mappings {
+    for host_entry in host_entries {
         // Check if this mapping already exists
-        let entry = format!("{}\t{}", ip_addr, hostname);
-        if !hosts_content.contains(&entry) {
+        let entry = format!("{}\t{}", host_entry.get_ip(), host_entry.get_hostname());
+        if !hosts_content.lines().any(|line| line == entry) {
             hosts_content.push_str(&format!("{}\n", entry));
         }
     }
@@ -274,6 +323,49 @@ async fn _patch_with_hostnames(
     Ok(())
 }
 
+/// Writes an /etc/resolv.conf file that uses the given DNS servers to the top layer of the guest
+/// rootfs, replacing any resolver configuration of the layers below it.
+///
+/// ## Arguments
+/// * `root_paths` - Paths to the layers of the guest rootfs, from bottom to top
+/// * `dns_servers` - The DNS servers to use, in order of preference
+///
+/// ## Errors
+/// Returns an error if:
+/// - Cannot create directories in the rootfs
+/// - Cannot write the resolv.conf file
+/// - Cannot set permissions on the resolv.conf file
+pub async fn patch_with_dns_servers(
+    root_paths: &[PathBuf],
+    dns_servers: &[IpAddr],
+) -> MicrosandboxResult<()> {
+    let Some(top_layer) = root_paths.last() else {
+        return Ok(());
+    };
+    let resolv_path = top_layer.join("etc/resolv.conf");
+
+    // Create parent directories if they don't exist
+    if let Some(parent) = resolv_path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    let mut resolv_content = String::from("# /etc/resolv.conf: DNS resolver configuration\n");
+    for dns_server in dns_servers {
+        resolv_content.push_str(&format!("nameserver {}\n", dns_server));
+    }
+
+    // Write the file
+    fs::write(&resolv_path, resolv_content).await?;
+
+    // Set proper permissions (644 - rw-r--r--)
+    let perms = fs::metadata(&resolv_path).await?.permissions();
+    let mut new_perms = perms;
+    new_perms.set_mode(0o644);
+    fs::set_permissions(&resolv_path, new_perms).await?;
+
+    Ok(())
+}
+
 /// Updates the /etc/resolv.conf file in the guest rootfs to add default DNS servers if none exist.
 /// Creates the file if it doesn't exist.
 ///
@@ -462,6 +554,9 @@ async fn copy_file_with_permissions(
 /// 1. Sets the extended attribute user.containers.override_stat to "0:0:040755"
 /// 2. This overrides the UID:GID:MODE of the rootfs directory when accessed inside the VM
 ///
+/// If an [`OwnershipOverride`] is configured through the environment, its uid/gid replace
+/// root's. Filesystems without xattr support are skipped with a warning.
+///
 /// ## Arguments
 /// * `root_path` - Path to the rootfs directory to modify
 ///
@@ -474,7 +569,8 @@ pub async fn patch_with_stat_override(root_path: &Path) -> MicrosandboxResult<()
 
     // The value in the format "uid:gid:mode" (0:0:040755 means root:root directory with rwxr-xr-x permissions)
     // 040000 is S_IFDIR (directory file type), 0755 are the permissions
-    let xattr_value = "0:0:040755";
+    let (uid, gid) = OwnershipOverride::from_env().apply(0, 0);
+    let xattr_value = format!("{}:{}:040755", uid, gid);
 
     // Convert path to CString for xattr crate
     let path_str = root_path.to_str().ok_or_else(|| {
@@ -495,6 +591,13 @@ pub async fn patch_with_stat_override(root_path: &Path) -> MicrosandboxResult<()
             );
             Ok(())
         }
+        Err(err) if err.raw_os_error() == Some(libc::ENOTSUP) => {
+            tracing::warn!(
+                "Filesystem does not support xattrs for {}, continuing without stat override",
+                root_path.display()
+            );
+            Ok(())
+        }
         Err(err) => Err(crate::MicrosandboxError::Io(std::io::Error::new(
             std::io::ErrorKind::Other,
             format!("Failed to set xattr on {}: {}", root_path.display(), err),
@@ -502,6 +605,98 @@ pub async fn patch_with_stat_override(root_path: &Path) -> MicrosandboxResult<()
     }
 }
 
+/// Returns the first of `shells` that exists in the rootfs made up of `layers`.
+///
+/// `layers` are ordered from the lowest to the topmost, the order they are stacked in the
+/// overlay. A native rootfs is passed as a single layer.
+pub fn find_shell<'a>(
+    layers: &[PathBuf],
+    shells: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    shells
+        .into_iter()
+        .find(|shell| path_exists_in_layers(layers, shell))
+}
+
+/// Checks whether a guest path exists in the rootfs made up of `layers`.
+///
+/// Symlinks are resolved against the rootfs instead of the host, as images commonly link `/bin`
+/// to `/usr/bin` or `/bin/sh` to a multi-call binary like busybox with absolute targets.
+/// Whiteouts and opaque directories in upper layers hide the entries of lower layers.
+fn path_exists_in_layers(layers: &[PathBuf], guest_path: &str) -> bool {
+    let mut pending = path_components(Path::new(guest_path));
+    let mut resolved = PathBuf::new();
+    let mut follows = 0;
+
+    while let Some(component) = pending.pop_front() {
+        if component == ".." {
+            resolved.pop();
+            continue;
+        }
+
+        let candidate = resolved.join(&component);
+        let Some((layer, metadata)) = find_in_layers(layers, &resolved, &component) else {
+            return false;
+        };
+
+        if !metadata.file_type().is_symlink() {
+            resolved = candidate;
+            continue;
+        }
+
+        follows += 1;
+        if follows > MAX_SYMLINK_FOLLOWS {
+            return false;
+        }
+
+        let Ok(target) = std::fs::read_link(layer.join(&candidate)) else {
+            return false;
+        };
+        if target.is_absolute() {
+            resolved = PathBuf::new();
+        }
+        for component in path_components(&target).into_iter().rev() {
+            pending.push_front(component);
+        }
+    }
+
+    true
+}
+
+/// Finds the topmost layer that has `name` in the directory `dir`, unless an upper layer hides it.
+fn find_in_layers<'a>(
+    layers: &'a [PathBuf],
+    dir: &Path,
+    name: &OsString,
+) -> Option<(&'a PathBuf, Metadata)> {
+    let mut whiteout = OsString::from(WHITEOUT_PREFIX);
+    whiteout.push(name);
+
+    for layer in layers.iter().rev() {
+        let layer_dir = layer.join(dir);
+        if let Ok(metadata) = std::fs::symlink_metadata(layer_dir.join(name)) {
+            return Some((layer, metadata));
+        }
+
+        if layer_dir.join(&whiteout).exists() || layer_dir.join(OPAQUE_WHITEOUT_MARKER).exists() {
+            return None;
+        }
+    }
+
+    None
+}
+
+/// Splits a path into its named components, keeping `..` and dropping the root and `.`.
+fn path_components(path: &Path) -> VecDeque<OsString> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_os_string()),
+            Component::ParentDir => Some(OsString::from("..")),
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => None,
+        })
+        .collect()
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
@@ -656,23 +851,21 @@ mod tests {
 
     #[tokio::test]
     async fn test_patch_with_hostnames() -> anyhow::Result<()> {
-        use std::net::Ipv4Addr;
-
         // Create a temporary directory to act as our rootfs
         let root_dir = TempDir::new()?;
-        let root_path = root_dir.path();
+        let root_paths = [root_dir.path().to_path_buf()];
 
         // Create test hostname mappings
-        let hostname_mappings = vec![
-            (Ipv4Addr::new(192, 168, 1, 100), "host1.local".to_string()),
-            (Ipv4Addr::new(192, 168, 1, 101), "host2.local".to_string()),
+        let host_entries = vec![
+            "host1.local:192.168.1.100".parse::<HostEntry>()?,
+            "host2.local:192.168.1.101".parse::<HostEntry>()?,
         ];
 
         // Update hosts file
-        _patch_with_hostnames(root_path, &hostname_mappings).await?;
+        patch_with_hostnames(&root_paths, &host_entries).await?;
 
         // Verify hosts file was created with correct content
-        let hosts_path = root_path.join("etc/hosts");
+        let hosts_path = root_dir.path().join("etc/hosts");
         assert!(hosts_path.exists());
 
         let hosts_content = fs::read_to_string(&hosts_path).await?;
@@ -691,13 +884,13 @@ mod tests {
         assert_eq!(perms.mode() & 0o777, 0o644);
 
         // Test updating existing hosts file with new entries
-        let new_mappings = vec![
-            (Ipv4Addr::new(192, 168, 1, 100), "host1.local".to_string()), // Existing entry
-            (Ipv4Addr::new(192, 168, 1, 102), "host3.local".to_string()), // New entry
+        let new_entries = vec![
+            "host1.local:192.168.1.100".parse::<HostEntry>()?, // Existing entry
+            "host3.local:fd00::102".parse::<HostEntry>()?,     // New entry
         ];
 
         // Update hosts file again
-        _patch_with_hostnames(root_path, &new_mappings).await?;
+        patch_with_hostnames(&root_paths, &new_entries).await?;
 
         // Verify updated content
         let updated_content = fs::read_to_string(&hosts_path).await?;
@@ -708,7 +901,7 @@ mod tests {
 
         // Should contain both old and new entries without duplicates
         assert!(updated_content.contains("192.168.1.100\thost1.local"));
-        assert!(updated_content.contains("192.168.1.102\thost3.local"));
+        assert!(updated_content.contains("fd00::102\thost3.local"));
 
         // Count occurrences of the first IP to ensure no duplicates
         let count = updated_content
@@ -717,6 +910,59 @@ mod tests {
             .count();
         assert_eq!(count, 1, "Should not have duplicate entries");
 
+        // Test extending the hosts file of a lower layer (overlayfs)
+        let layers_dir = TempDir::new()?;
+        let lower_layer = layers_dir.path().join("lower");
+        let patch_layer = layers_dir.path().join("patch");
+        fs::create_dir_all(lower_layer.join("etc")).await?;
+        fs::write(lower_layer.join("etc/hosts"), "10.0.0.1\timage-host").await?;
+
+        patch_with_hostnames(&[lower_layer.clone(), patch_layer.clone()], &host_entries).await?;
+
+        // The lower layer is left alone and the patch layer has both sets of entries
+        let lower_content = fs::read_to_string(lower_layer.join("etc/hosts")).await?;
+        assert_eq!(lower_content, "10.0.0.1\timage-host");
+        let patch_content = fs::read_to_string(patch_layer.join("etc/hosts")).await?;
+        assert_eq!(
+            patch_content,
+            "10.0.0.1\timage-host\n192.168.1.100\thost1.local\n192.168.1.101\thost2.local\n"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_patch_with_dns_servers() -> anyhow::Result<()> {
+        let root_dir = TempDir::new()?;
+        let lower_layer = root_dir.path().join("lower");
+        let patch_layer = root_dir.path().join("patch");
+        fs::create_dir_all(lower_layer.join("etc")).await?;
+        fs::write(
+            lower_layer.join("etc/resolv.conf"),
+            "nameserver 192.168.1.1\n",
+        )
+        .await?;
+
+        let dns_servers = ["10.0.0.53".parse::<IpAddr>()?, "fd00::53".parse()?];
+        patch_with_dns_servers(&[lower_layer.clone(), patch_layer.clone()], &dns_servers).await?;
+
+        // The configured servers replace the lower layer's in the patch layer
+        let lower_content = fs::read_to_string(lower_layer.join("etc/resolv.conf")).await?;
+        assert_eq!(lower_content, "nameserver 192.168.1.1\n");
+
+        let patch_resolv = patch_layer.join("etc/resolv.conf");
+        let content = fs::read_to_string(&patch_resolv).await?;
+        assert_eq!(
+            content,
+            "# /etc/resolv.conf: DNS resolver configuration\n\
+             nameserver 10.0.0.53\n\
+             nameserver fd00::53\n"
+        );
+
+        // Verify file permissions
+        let perms = fs::metadata(&patch_resolv).await?.permissions();
+        assert_eq!(perms.mode() & 0o777, 0o644);
+
         Ok(())
     }
 
@@ -1049,4 +1295,60 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_ownership_override_apply() {
+        let none = OwnershipOverride::default();
+        assert_eq!(none.apply(0, 0), (0, 0));
+        assert_eq!(none.apply(33, 33), (33, 33));
+
+        let uid_only = OwnershipOverride {
+            uid: Some(1000),
+            gid: None,
+        };
+        assert_eq!(uid_only.apply(0, 42), (1000, 42));
+
+        let both = OwnershipOverride {
+            uid: Some(1000),
+            gid: Some(1000),
+        };
+        assert_eq!(both.apply(0, 0), (1000, 1000));
+    }
+
+    #[test]
+    fn test_find_shell_resolves_symlinks_within_layers() -> anyhow::Result<()> {
+        let lower = TempDir::new()?;
+        let upper = TempDir::new()?;
+
+        // Lower layer has the binaries under /usr/bin
+        std::fs::create_dir_all(lower.path().join("usr/bin"))?;
+        std::fs::write(lower.path().join("usr/bin/bash"), "")?;
+        std::fs::write(lower.path().join("usr/bin/busybox"), "")?;
+
+        // Upper layer links /bin to /usr/bin with an absolute target and adds a busybox shell
+        std::fs::create_dir_all(upper.path().join("usr/bin"))?;
+        std::os::unix::fs::symlink("/usr/bin", upper.path().join("bin"))?;
+        std::os::unix::fs::symlink("busybox", upper.path().join("usr/bin/sh"))?;
+        std::os::unix::fs::symlink("/missing", upper.path().join("usr/bin/ash"))?;
+
+        let layers = vec![lower.path().to_path_buf(), upper.path().to_path_buf()];
+        assert_eq!(
+            find_shell(&layers, ["/bin/bash", "/bin/sh"]),
+            Some("/bin/bash")
+        );
+        assert_eq!(
+            find_shell(&layers, ["/bin/zsh", "/bin/sh"]),
+            Some("/bin/sh")
+        );
+        assert_eq!(find_shell(&layers, ["/bin/ash", "/bin/zsh"]), None);
+
+        // A whiteout in the upper layer hides the lower layer's bash
+        std::fs::write(upper.path().join("usr/bin/.wh.bash"), "")?;
+        assert_eq!(
+            find_shell(&layers, ["/bin/bash", "/bin/sh"]),
+            Some("/bin/sh")
+        );
+
+        Ok(())
+    }
 }
@@ -11,10 +11,13 @@ use std::{
 
 use chrono::{DateTime, Utc};
 use microsandbox_utils::{
-    env, DEFAULT_MSBRUN_EXE_PATH, DEFAULT_SHELL, EXTRACTED_LAYER_SUFFIX, LAYERS_SUBDIR, LOG_SUBDIR,
-    MICROSANDBOX_CONFIG_FILENAME, MICROSANDBOX_ENV_DIR, MSBRUN_EXE_ENV_VAR, OCI_DB_FILENAME,
-    PATCH_SUBDIR, RW_SUBDIR, SANDBOX_DB_FILENAME, SANDBOX_DIR, SCRIPTS_DIR, SHELL_SCRIPT_NAME,
+    env, DEFAULT_FALLBACK_SHELLS, DEFAULT_MEMORY_MIB, DEFAULT_MSBRUN_EXE_PATH, DEFAULT_NUM_VCPUS,
+    DEFAULT_SHELL, EXTRACTED_LAYER_SUFFIX, LAYERS_SUBDIR, LOG_SUBDIR, MICROSANDBOX_CONFIG_FILENAME,
+    MICROSANDBOX_ENV_DIR, MSBRUN_EXE_ENV_VAR, OCI_DB_FILENAME, PATCH_SUBDIR,
+    RESOLVED_CONFIG_SUBDIR, RW_SUBDIR, SANDBOX_DB_FILENAME, SANDBOX_DIR, SCRIPTS_DIR,
+    SHELL_SCRIPT_NAME,
 };
+use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Sqlite};
 use tempfile;
 use tokio::{fs, process::Command};
@@ -24,9 +27,9 @@ use crate::{
     config::{
         EnvPair, Microsandbox, PathPair, PortPair, ReferenceOrPath, Sandbox, START_SCRIPT_NAME,
     },
-    management::{config, db, image, menv, rootfs},
+    management::{config, db, image, menv, rootfs, volume},
     oci::Reference,
-    vm::Rootfs,
+    vm::{self, Rootfs},
     MicrosandboxError, MicrosandboxResult,
 };
 
@@ -36,6 +39,35 @@ use crate::{
 
 const TEMPORARY_SANDBOX_NAME: &str = "tmp";
 
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The fully resolved configuration a sandbox was started with.
+///
+/// This is the sandbox config after image defaults have been applied and unset resources have
+/// been filled in with their defaults, together with the command the sandbox runs.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ResolvedSandbox {
+    /// The name of the sandbox
+    pub name: String,
+
+    /// The config file the sandbox is defined in
+    pub config_file: String,
+
+    /// The resolved sandbox config
+    pub config: Sandbox,
+
+    /// The path of the program the sandbox runs
+    pub exec_path: String,
+
+    /// The arguments passed to the program
+    pub exec_args: Vec<String>,
+
+    /// When the config was resolved
+    pub resolved_at: DateTime<Utc>,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
@@ -162,6 +194,9 @@ pub async fn prepare_run(
     exec: Option<&str>,
     use_image_defaults: bool,
 ) -> MicrosandboxResult<(Command, bool)> {
+    // Fail before pulling images or setting up the rootfs if the sandbox cannot boot anyway
+    vm::check_virtualization()?;
+
     // Load the configuration
     let (config, canonical_project_dir, config_file) =
         config::load_config(project_dir, config_file).await?;
@@ -196,7 +231,7 @@ pub async fn prepare_run(
             setup_native_rootfs(
                 &canonical_project_dir.join(root_path),
                 sandbox_name,
-                &sandbox_config,
+                &mut sandbox_config,
                 &config_file,
                 &config_last_modified,
                 &sandbox_pool,
@@ -222,6 +257,17 @@ pub async fn prepare_run(
     let (exec_path, exec_args) =
         determine_exec_path_and_args(exec, script_name, &sandbox_config, sandbox_name)?;
 
+    // Record the resolved config so it can be inspected with `describe`
+    save_resolved_config(
+        &menv_path,
+        &config_file,
+        sandbox_name,
+        &sandbox_config,
+        &exec_path,
+        &exec_args,
+    )
+    .await?;
+
     // Log directory
     let log_dir = menv_path.join(LOG_SUBDIR);
     fs::create_dir_all(&log_dir).await?;
@@ -279,6 +325,15 @@ pub async fn prepare_run(
 
     // Volumes
     for volume in sandbox_config.get_volumes() {
+        if let Some(volume_name) = volume.get_volume_name() {
+            // Named volume, mount its managed directory
+            let host_path =
+                volume::acquire(volume_name, &sandbox_db_path, &config_file, sandbox_name).await?;
+            let combined_volume = format!("{}:{}", host_path.display(), volume.get_guest());
+            command.arg("--mapped-dir").arg(combined_volume);
+            continue;
+        }
+
         match volume {
             PathPair::Distinct { host, guest } => {
                 if host.is_absolute() {
@@ -459,8 +514,12 @@ pub async fn run_temp(
     // Initialize menv in the temporary directory
     menv::initialize(Some(temp_dir_path.clone())).await?;
 
-    // Parse the volume, port, and env strings into their respective types
-    let volumes: Vec<PathPair> = volumes.into_iter().filter_map(|v| v.parse().ok()).collect();
+    // Parse the volume, port, and env strings into their respective types. Volumes are rejected
+    // rather than dropped, as a dropped volume could hide a guest path that is not allowed
+    let volumes = volumes
+        .into_iter()
+        .map(|v| v.parse())
+        .collect::<MicrosandboxResult<Vec<PathPair>>>()?;
     let ports: Vec<PortPair> = ports.into_iter().filter_map(|p| p.parse().ok()).collect();
     let envs: Vec<EnvPair> = envs.into_iter().filter_map(|e| e.parse().ok()).collect();
 
@@ -528,10 +587,167 @@ pub async fn run_temp(
     Ok(())
 }
 
+/// Returns the resolved configuration a sandbox was last started with.
+///
+/// The configuration is recorded every time the sandbox is started, after the config file, image
+/// defaults and built-in defaults have been merged. It shows exactly what the sandbox runs with,
+/// which may differ from what the config file says.
+///
+/// ## Arguments
+///
+/// * `sandbox_name` - The name of the sandbox as defined in the Microsandbox config file
+/// * `project_dir` - Optional path to the project directory. If None, defaults to current directory
+/// * `config_file` - Optional path to the Microsandbox config file. If None, uses default filename
+///
+/// ## Errors
+///
+/// Returns an error if:
+/// - The configuration cannot be loaded
+/// - The sandbox has never been started, so no resolved configuration exists
+/// - The recorded configuration cannot be read or parsed
+///
+/// ## Example
+///
+/// ```no_run
+/// use microsandbox_core::management::sandbox;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let resolved = sandbox::describe("my-sandbox", None, None).await?;
+/// println!("memory: {:?} MiB", resolved.config.get_memory());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn describe(
+    sandbox_name: &str,
+    project_dir: Option<&Path>,
+    config_file: Option<&str>,
+) -> MicrosandboxResult<ResolvedSandbox> {
+    let (_, canonical_project_dir, config_file) =
+        config::load_config(project_dir, config_file).await?;
+
+    let resolved_path = resolved_config_path(
+        &canonical_project_dir.join(MICROSANDBOX_ENV_DIR),
+        &config_file,
+        sandbox_name,
+    );
+
+    if !resolved_path.exists() {
+        return Err(MicrosandboxError::ResolvedConfigNotFound(format!(
+            "sandbox '{}' has not been started yet",
+            sandbox_name
+        )));
+    }
+
+    let contents = fs::read_to_string(&resolved_path).await?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions: Helpers
 //--------------------------------------------------------------------------------------------------
 
+/// Returns the path the resolved config of a sandbox is recorded at:
+/// `<menv>/resolved/<config>/<sandbox>.json`.
+fn resolved_config_path(menv_path: &Path, config_file: &str, sandbox_name: &str) -> PathBuf {
+    menv_path
+        .join(RESOLVED_CONFIG_SUBDIR)
+        .join(config_file)
+        .join(format!("{}.json", sandbox_name))
+}
+
+/// Replaces the shell of a sandbox with one that exists in its rootfs.
+///
+/// The configured shell, or [`DEFAULT_SHELL`] if none is configured, is kept if the rootfs has it.
+/// Otherwise the first of [`DEFAULT_FALLBACK_SHELLS`] the rootfs has is used, so minimal images
+/// without bash still get a working shell. If the rootfs has none of them, the configured shell
+/// is kept and the sandbox fails the same way it would have without the check.
+fn resolve_shell(sandbox_config: &mut Sandbox, layers: &[PathBuf]) {
+    let configured = sandbox_config
+        .get_shell()
+        .clone()
+        .unwrap_or_else(|| DEFAULT_SHELL.to_string());
+    let candidates =
+        std::iter::once(configured.as_str()).chain(DEFAULT_FALLBACK_SHELLS.iter().copied());
+
+    let shell = match rootfs::find_shell(layers, candidates) {
+        Some(shell) if shell != configured => {
+            tracing::warn!(
+                "shell {} not found in rootfs, falling back to {}",
+                configured,
+                shell
+            );
+            shell.to_string()
+        }
+        Some(_) => configured,
+        None => {
+            tracing::warn!(
+                "none of the shells {} or {} found in rootfs, keeping {}",
+                configured,
+                DEFAULT_FALLBACK_SHELLS.join(", "),
+                configured
+            );
+            configured
+        }
+    };
+
+    sandbox_config.shell = Some(shell);
+}
+
+/// Applies the sandbox's DNS servers and extra hosts to the top layer of its rootfs.
+///
+/// Without configured DNS servers, default ones are added only if no layer sets any.
+async fn patch_with_network_settings(
+    layers: &[PathBuf],
+    sandbox_config: &Sandbox,
+) -> MicrosandboxResult<()> {
+    let dns_servers = sandbox_config.get_dns_servers();
+    if dns_servers.is_empty() {
+        rootfs::patch_with_default_dns_settings(layers).await?;
+    } else {
+        tracing::info!("patching with {} dns servers", dns_servers.len());
+        rootfs::patch_with_dns_servers(layers, dns_servers).await?;
+    }
+
+    let extra_hosts = sandbox_config.get_extra_hosts();
+    if !extra_hosts.is_empty() {
+        tracing::info!("patching with {} extra hosts", extra_hosts.len());
+        rootfs::patch_with_hostnames(layers, extra_hosts).await?;
+    }
+
+    Ok(())
+}
+
+async fn save_resolved_config(
+    menv_path: &Path,
+    config_file: &str,
+    sandbox_name: &str,
+    sandbox_config: &Sandbox,
+    exec_path: &str,
+    exec_args: &[String],
+) -> MicrosandboxResult<()> {
+    let mut config = sandbox_config.clone();
+    config.memory = Some(config.memory.unwrap_or(DEFAULT_MEMORY_MIB));
+    config.cpus = Some(config.cpus.unwrap_or(DEFAULT_NUM_VCPUS));
+
+    let resolved = ResolvedSandbox {
+        name: sandbox_name.to_string(),
+        config_file: config_file.to_string(),
+        config,
+        exec_path: exec_path.to_string(),
+        exec_args: exec_args.to_vec(),
+        resolved_at: Utc::now(),
+    };
+
+    let resolved_path = resolved_config_path(menv_path, config_file, sandbox_name);
+    if let Some(parent) = resolved_path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(&resolved_path, serde_json::to_string_pretty(&resolved)?).await?;
+
+    tracing::debug!("saved resolved config to {}", resolved_path.display());
+    Ok(())
+}
+
 async fn setup_image_rootfs(
     image: &Reference,
     sandbox_name: &str,
@@ -544,7 +760,14 @@ async fn setup_image_rootfs(
 ) -> MicrosandboxResult<Rootfs> {
     // Pull the image from the registry
     tracing::info!("pulling image: {}", image);
-    image::pull(image.clone(), true, None).await?;
+    image::pull(
+        image.clone(),
+        true,
+        None,
+        None,
+        image::default_pull_progress(),
+    )
+    .await?;
 
     // Get the microsandbox home path and database path
     let microsandbox_home_path = env::get_microsandbox_home_path();
@@ -594,6 +817,11 @@ async fn setup_image_rootfs(
     fs::create_dir_all(&top_rw_path).await?;
     tracing::info!("top_rw_path: {}", top_rw_path.display());
 
+    // Settle on a shell the image actually has before it is written into the scripts
+    let mut rootfs_layers = layer_paths.clone();
+    rootfs_layers.push(top_rw_path.clone());
+    resolve_shell(sandbox_config, &rootfs_layers);
+
     // Check if we need to patch rootfs (scripts, volumes, etc.)
     let should_patch = has_sandbox_config_changed(
         sandbox_pool,
@@ -613,6 +841,14 @@ async fn setup_image_rootfs(
             fs::remove_dir_all(&rw_scripts_dir).await?;
         }
 
+        // Drop network files written for a previous config so they don't outlive it
+        for network_file in ["etc/resolv.conf", "etc/hosts"] {
+            let patched_file = patch_dir.join(network_file);
+            if patched_file.exists() {
+                fs::remove_file(&patched_file).await?;
+            }
+        }
+
         // Patch with sandbox scripts
         rootfs::patch_with_sandbox_scripts(
             &script_dir,
@@ -624,10 +860,10 @@ async fn setup_image_rootfs(
         )
         .await?;
 
-        // Patch with default DNS settings - check all layers
+        // Patch with the configured DNS servers, or default DNS settings - check all layers
         let mut all_layers = layer_paths.clone();
         all_layers.push(patch_dir.clone());
-        rootfs::patch_with_default_dns_settings(&all_layers).await?;
+        patch_with_network_settings(&all_layers, sandbox_config).await?;
 
         // Patch with volume mounts if there are any volumes defined
         let volumes = &sandbox_config.get_volumes();
@@ -652,7 +888,7 @@ async fn setup_image_rootfs(
 async fn setup_native_rootfs(
     root_path: &Path,
     sandbox_name: &str,
-    sandbox_config: &Sandbox,
+    sandbox_config: &mut Sandbox,
     config_file: &str,
     config_last_modified: &DateTime<Utc>,
     sandbox_pool: &Pool<Sqlite>,
@@ -661,6 +897,9 @@ async fn setup_native_rootfs(
     let scripts_dir = root_path.join(SANDBOX_DIR).join(SCRIPTS_DIR);
     fs::create_dir_all(&scripts_dir).await?;
 
+    // Settle on a shell the rootfs actually has before it is written into the scripts
+    resolve_shell(sandbox_config, &[root_path.to_path_buf()]);
+
     // Check if we need to patch rootfs (scripts, volumes, etc.)
     let should_patch = has_sandbox_config_changed(
         sandbox_pool,
@@ -685,8 +924,9 @@ async fn setup_native_rootfs(
         )
         .await?;
 
-        // Patch with default DNS settings - for native rootfs, just pass the single root path
-        rootfs::patch_with_default_dns_settings(&[root_path.to_path_buf()]).await?;
+        // Patch with the configured DNS servers, or default DNS settings - for native rootfs,
+        // just pass the single root path
+        patch_with_network_settings(&[root_path.to_path_buf()], sandbox_config).await?;
 
         // Patch with volume mounts if there are any volumes defined
         let volumes = &sandbox_config.get_volumes();
@@ -0,0 +1,294 @@
+//! Named volume management for Microsandbox.
+//!
+//! Named volumes are directories managed under the microsandbox home that outlive the sandboxes
+//! mounting them, so state kept in them survives a sandbox being removed and recreated. A
+//! sandbox mounts one with a `volume:<name>:<guest path>` entry in its `volumes`, which creates
+//! the volume on first use.
+//!
+//! Each volume is laid out as:
+//!
+//! ```text
+//! <MICROSANDBOX_HOME>/volumes/<name>/
+//! ├── data/      # mounted into sandboxes
+//! └── leases/    # one file per sandbox that has mounted the volume
+//! ```
+//!
+//! A lease names the sandbox database, config file and sandbox it belongs to. The volume counts
+//! as in use while any of those sandboxes is still running, which keeps `remove` from deleting
+//! data out from under it. Leases of sandboxes that have stopped are pruned as they are found.
+
+use std::path::{Path, PathBuf};
+
+use microsandbox_utils::{env, VOLUMES_SUBDIR};
+use nix::{sys::signal, unistd::Pid};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+use crate::{
+    management::db, runtime::SANDBOX_STATUS_RUNNING, MicrosandboxError, MicrosandboxResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The directory of a volume that is mounted into sandboxes
+const VOLUME_DATA_SUBDIR: &str = "data";
+
+/// The directory of a volume where the leases of sandboxes using it are stored
+const VOLUME_LEASES_SUBDIR: &str = "leases";
+
+/// The longest name a volume can have
+const MAX_VOLUME_NAME_LEN: usize = 64;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A named volume and the sandboxes currently using it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VolumeInfo {
+    /// The name of the volume
+    pub name: String,
+
+    /// The host directory that is mounted into sandboxes
+    pub path: PathBuf,
+
+    /// The running sandboxes that have the volume mounted, as `name (project dir)`
+    pub users: Vec<String>,
+}
+
+/// A record of a sandbox having mounted a volume
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VolumeLease {
+    /// The database of the project the sandbox belongs to
+    sandbox_db_path: PathBuf,
+
+    /// The config file the sandbox is defined in
+    config_file: String,
+
+    /// The name of the sandbox
+    sandbox_name: String,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Checks that a volume name is usable as a directory name.
+///
+/// Names start with a letter or digit, are at most 64 characters long and only contain letters,
+/// digits, `_`, `-` and `.`.
+pub fn validate_name(name: &str) -> MicrosandboxResult<()> {
+    let valid = name.len() <= MAX_VOLUME_NAME_LEN
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+
+    if !valid {
+        return Err(MicrosandboxError::InvalidVolumeName(name.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Creates a named volume and returns the directory that is mounted into sandboxes.
+///
+/// ## Example
+///
+/// ```no_run
+/// use microsandbox_core::management::volume;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let path = volume::create("cache").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn create(name: &str) -> MicrosandboxResult<PathBuf> {
+    validate_name(name)?;
+
+    let volume_dir = volume_dir(name);
+    if fs::try_exists(&volume_dir).await? {
+        return Err(MicrosandboxError::VolumeExists(name.to_string()));
+    }
+
+    init_volume_dir(&volume_dir).await
+}
+
+/// Lists the named volumes together with the running sandboxes using them.
+pub async fn list() -> MicrosandboxResult<Vec<VolumeInfo>> {
+    let volumes_dir = env::get_microsandbox_home_path().join(VOLUMES_SUBDIR);
+    if !fs::try_exists(&volumes_dir).await? {
+        return Ok(Vec::new());
+    }
+
+    let mut volumes = Vec::new();
+    let mut entries = fs::read_dir(&volumes_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if validate_name(&name).is_err() || !entry.file_type().await?.is_dir() {
+            continue;
+        }
+
+        volumes.push(VolumeInfo {
+            path: entry.path().join(VOLUME_DATA_SUBDIR),
+            users: volume_users(&entry.path()).await?,
+            name,
+        });
+    }
+
+    volumes.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(volumes)
+}
+
+/// Removes a named volume and its data.
+///
+/// Fails with [`MicrosandboxError::VolumeInUse`] if a running sandbox has the volume mounted.
+pub async fn remove(name: &str) -> MicrosandboxResult<()> {
+    validate_name(name)?;
+
+    let volume_dir = volume_dir(name);
+    if !fs::try_exists(&volume_dir).await? {
+        return Err(MicrosandboxError::VolumeNotFound(name.to_string()));
+    }
+
+    let users = volume_users(&volume_dir).await?;
+    if !users.is_empty() {
+        return Err(MicrosandboxError::VolumeInUse(name.to_string(), users));
+    }
+
+    fs::remove_dir_all(&volume_dir).await?;
+    tracing::info!("removed volume {}", name);
+
+    Ok(())
+}
+
+/// Records that a sandbox mounts a named volume and returns the directory to mount.
+///
+/// The volume is created if it does not exist yet.
+pub(crate) async fn acquire(
+    name: &str,
+    sandbox_db_path: &Path,
+    config_file: &str,
+    sandbox_name: &str,
+) -> MicrosandboxResult<PathBuf> {
+    validate_name(name)?;
+
+    let data_dir = init_volume_dir(&volume_dir(name)).await?;
+    let lease = VolumeLease {
+        sandbox_db_path: sandbox_db_path.to_path_buf(),
+        config_file: config_file.to_string(),
+        sandbox_name: sandbox_name.to_string(),
+    };
+    let lease_json = serde_json::to_string(&lease)?;
+
+    // The same sandbox always gets the same lease file, so restarts do not pile up leases
+    let lease_id = hex::encode(&Sha256::digest(lease_json.as_bytes())[..8]);
+    let lease_path = volume_dir(name)
+        .join(VOLUME_LEASES_SUBDIR)
+        .join(format!("{}.json", lease_id));
+    fs::write(&lease_path, lease_json).await?;
+
+    tracing::debug!("sandbox {} leased volume {}", sandbox_name, name);
+    Ok(data_dir)
+}
+
+/// Returns the directory of a named volume.
+fn volume_dir(name: &str) -> PathBuf {
+    env::get_microsandbox_home_path()
+        .join(VOLUMES_SUBDIR)
+        .join(name)
+}
+
+/// Creates the directories of a volume if they are missing and returns its data directory.
+async fn init_volume_dir(volume_dir: &Path) -> MicrosandboxResult<PathBuf> {
+    let data_dir = volume_dir.join(VOLUME_DATA_SUBDIR);
+    fs::create_dir_all(&data_dir).await?;
+    fs::create_dir_all(volume_dir.join(VOLUME_LEASES_SUBDIR)).await?;
+
+    Ok(data_dir)
+}
+
+/// Returns the running sandboxes holding a lease on a volume, pruning the leases of the rest.
+async fn volume_users(volume_dir: &Path) -> MicrosandboxResult<Vec<String>> {
+    let leases_dir = volume_dir.join(VOLUME_LEASES_SUBDIR);
+    if !fs::try_exists(&leases_dir).await? {
+        return Ok(Vec::new());
+    }
+
+    let mut users = Vec::new();
+    let mut entries = fs::read_dir(&leases_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let lease = fs::read_to_string(entry.path())
+            .await
+            .ok()
+            .and_then(|contents| serde_json::from_str::<VolumeLease>(&contents).ok());
+
+        match lease {
+            Some(lease) if is_lease_held(&lease).await? => {
+                let project_dir = lease
+                    .sandbox_db_path
+                    .parent()
+                    .and_then(Path::parent)
+                    .unwrap_or(&lease.sandbox_db_path);
+                users.push(format!(
+                    "{} ({})",
+                    lease.sandbox_name,
+                    project_dir.display()
+                ));
+            }
+            _ => {
+                tracing::debug!("pruning stale volume lease {}", entry.path().display());
+                fs::remove_file(entry.path()).await?;
+            }
+        }
+    }
+
+    users.sort();
+    Ok(users)
+}
+
+/// Checks whether the sandbox a lease belongs to is still running.
+async fn is_lease_held(lease: &VolumeLease) -> MicrosandboxResult<bool> {
+    // Opening a missing database would create it, and a removed project holds no volumes
+    if !fs::try_exists(&lease.sandbox_db_path).await? {
+        return Ok(false);
+    }
+
+    let pool = db::get_pool(&lease.sandbox_db_path).await?;
+    let sandbox = db::get_sandbox(&pool, &lease.sandbox_name, &lease.config_file).await?;
+    pool.close().await;
+
+    Ok(sandbox.is_some_and(|sandbox| {
+        sandbox.status == SANDBOX_STATUS_RUNNING
+            && signal::kill(Pid::from_raw(sandbox.supervisor_pid as i32), None).is_ok()
+    }))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("cache").is_ok());
+        assert!(validate_name("pip-cache_3.12").is_ok());
+        assert!(validate_name(&"a".repeat(MAX_VOLUME_NAME_LEN)).is_ok());
+
+        assert!(validate_name("").is_err());
+        assert!(validate_name(".").is_err());
+        assert!(validate_name("..").is_err());
+        assert!(validate_name("-cache").is_err());
+        assert!(validate_name("a/b").is_err());
+        assert!(validate_name("a:b").is_err());
+        assert!(validate_name(&"a".repeat(MAX_VOLUME_NAME_LEN + 1)).is_err());
+    }
+}
@@ -0,0 +1,3 @@
+-- Add down migration script here
+
+ALTER TABLE layers DROP COLUMN size_computed_at;
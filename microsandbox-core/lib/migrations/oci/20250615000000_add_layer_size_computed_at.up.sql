@@ -0,0 +1,16 @@
+-- Add up migration script here
+
+-- Track when a layer's size was computed from its extracted directory
+ALTER TABLE layers ADD COLUMN size_computed_at DATETIME;
+
+-- Backfill image sizes that were recorded as zero from the sizes of their layers
+UPDATE images
+SET size_bytes = (
+        SELECT COALESCE(SUM(l.size_bytes), 0)
+        FROM layers l
+        JOIN manifest_layers ml ON l.id = ml.layer_id
+        JOIN manifests m ON ml.manifest_id = m.id
+        WHERE m.image_id = images.id
+    ),
+    modified_at = CURRENT_TIMESTAMP
+WHERE size_bytes = 0;
@@ -9,9 +9,9 @@ use chrono::{DateTime, Utc};
 use futures::{future, stream::BoxStream, StreamExt};
 use getset::{Getters, Setters};
 use microsandbox_utils::{env, EXTRACTED_LAYER_SUFFIX, LAYERS_SUBDIR};
-use oci_spec::image::{Digest, ImageConfiguration, ImageIndex, ImageManifest, Os, Platform};
+use oci_spec::image::{Digest, ImageConfiguration, ImageIndex, ImageManifest, Platform};
 use reqwest::Client;
-use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, RequestBuilder};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
 use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Sqlite};
@@ -22,8 +22,11 @@ use tokio::{
 };
 
 use crate::{
-    management::db,
-    oci::{OciRegistryPull, ReferenceSelector},
+    management::{db, image},
+    oci::{
+        self, OciRegistryPull, PullLimits, PullProgressCallback, PullProgressTracker,
+        ReferenceSelector, RegistryAuth, RegistryEndpoint,
+    },
     utils, MicrosandboxError, MicrosandboxResult,
 };
 
@@ -39,9 +42,6 @@ use microsandbox_utils::term::{self, MULTI_PROGRESS};
 /// The domain name of the Docker registry, used to construct image references.
 pub const DOCKER_REFERENCE_REGISTRY_DOMAIN: &str = "docker.io";
 
-/// Base URL for Docker Registry v2 API, used for accessing image manifests, layers, and other registry operations.
-const DOCKER_REGISTRY_URL: &str = "https://registry-1.docker.io";
-
 /// The service name used during token authentication, as specified by Docker's token-based authentication scheme.
 const DOCKER_AUTH_SERVICE: &str = "registry.docker.io";
 
@@ -64,14 +64,16 @@ const DOCKER_CONFIG_MIME_TYPE: &str = "application/vnd.docker.container.image.v1
 /// The annotation key used to identify attestation manifests in the Docker Registry.
 const DOCKER_REFERENCE_TYPE_ANNOTATION: &str = "vnd.docker.reference.type";
 
+/// The registry error codes that mean the requested repository or tag does not exist.
+///
+/// Docker Hub answers anonymous requests for repositories that do not exist with `UNAUTHORIZED`
+/// rather than `NAME_UNKNOWN`, so that code counts as not found too.
+const DOCKER_NOT_FOUND_ERROR_CODES: &[&str] = &["MANIFEST_UNKNOWN", "NAME_UNKNOWN", "UNAUTHORIZED"];
+
 #[cfg(feature = "cli")]
 /// Spinner message used for fetching image details.
 const FETCH_IMAGE_DETAILS_MSG: &str = "Fetch image details";
 
-#[cfg(feature = "cli")]
-/// Spinner message used for downloading layers.
-const DOWNLOAD_LAYER_MSG: &str = "Download layers";
-
 //--------------------------------------------------------------------------------------------------
 // Types
 //--------------------------------------------------------------------------------------------------
@@ -96,6 +98,16 @@ pub struct DockerRegistry {
 
     /// The database where image configurations, indexes, and manifests are stored.
     oci_db: Pool<Sqlite>,
+
+    /// The registry that images are pulled from.
+    endpoint: RegistryEndpoint,
+
+    /// The platform whose manifest is pulled from multi-arch images, Linux on the host's
+    /// architecture by default.
+    platform: Platform,
+
+    /// The largest image that is pulled, checked against the manifest before downloading layers.
+    limits: PullLimits,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -152,6 +164,27 @@ impl DockerRegistry {
     pub async fn new(
         layer_download_dir: impl Into<PathBuf>,
         oci_db_path: impl AsRef<Path>,
+    ) -> MicrosandboxResult<Self> {
+        Self::with_endpoint(
+            layer_download_dir,
+            oci_db_path,
+            RegistryEndpoint::docker_hub(),
+        )
+        .await
+    }
+
+    /// Creates a new registry client like [`DockerRegistry::new`], pulling from the given
+    /// registry instead of Docker Hub.
+    ///
+    /// ## Arguments
+    ///
+    /// * `layer_download_dir` - The directory where downloaded image layers will be stored
+    /// * `oci_db_path` - The path to the SQLite database that stores OCI-related metadata
+    /// * `endpoint` - The registry to pull from and its credentials
+    pub async fn with_endpoint(
+        layer_download_dir: impl Into<PathBuf>,
+        oci_db_path: impl AsRef<Path>,
+        endpoint: RegistryEndpoint,
     ) -> MicrosandboxResult<Self> {
         let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
         let client_builder = ClientBuilder::new(Client::new());
@@ -163,6 +196,9 @@ impl DockerRegistry {
             client,
             layer_download_dir: layer_download_dir.into(),
             oci_db: db::get_or_create_pool(oci_db_path.as_ref(), &db::OCI_DB_MIGRATOR).await?,
+            endpoint,
+            platform: oci::host_platform(),
+            limits: PullLimits::from_env(),
         })
     }
 
@@ -187,17 +223,20 @@ impl DockerRegistry {
         service: &str,
         scopes: &[&str],
     ) -> MicrosandboxResult<DockerAuthMaterial> {
-        let request = self
-            .client
-            .get(DOCKER_AUTH_REALM)
-            .query(&[
-                ("service", service),
-                (
-                    "scope",
-                    format!("repository:{}:{}", repository, scopes.join(",")).as_str(),
-                ),
-            ])
-            .build()?;
+        let mut request = self.client.get(DOCKER_AUTH_REALM).query(&[
+            ("service", service),
+            (
+                "scope",
+                format!("repository:{}:{}", repository, scopes.join(",")).as_str(),
+            ),
+        ]);
+
+        // Authenticated token requests get the user's pull rate limit and private repositories
+        if let RegistryAuth::Basic { username, password } = &self.endpoint.auth {
+            request = request.basic_auth(username, Some(password));
+        }
+
+        let request = request.build()?;
 
         let response = self.client.execute(request).await?;
         let auth_credentials = response.json::<DockerAuthMaterial>().await?;
@@ -205,6 +244,31 @@ impl DockerRegistry {
         Ok(auth_credentials)
     }
 
+    /// Adds the endpoint's credentials to a pull request for the given repository.
+    ///
+    /// Docker Hub needs a short-lived token per request, while other registries receive the
+    /// configured credentials directly.
+    async fn authorize(
+        &self,
+        request: RequestBuilder,
+        repository: &str,
+    ) -> MicrosandboxResult<RequestBuilder> {
+        match &self.endpoint.auth {
+            RegistryAuth::Bearer { token } => Ok(request.bearer_auth(token)),
+            _ if self.endpoint.is_docker_hub() => {
+                let token = self
+                    .get_access_credentials(repository, DOCKER_AUTH_SERVICE, &["pull"])
+                    .await?
+                    .token;
+                Ok(request.bearer_auth(token))
+            }
+            RegistryAuth::Basic { username, password } => {
+                Ok(request.basic_auth(username, Some(password)))
+            }
+            RegistryAuth::Anonymous => Ok(request),
+        }
+    }
+
     /// Downloads a blob from the registry, supports download resumption if the file already partially exists.
     ///
     /// Returns a tuple (MicrosandboxResult<()>, bool) where the boolean indicates whether a download
@@ -214,6 +278,38 @@ impl DockerRegistry {
         repository: &str,
         digest: &Digest,
         download_size: u64,
+    ) -> MicrosandboxResult<bool> {
+        let tracker = PullProgressTracker::new(None);
+        self.download_image_blob_tracked(repository, digest, download_size, &tracker)
+            .await
+    }
+
+    /// Pulls an OCI image like [`OciRegistryPull::pull_image`], reporting the download progress
+    /// to the given callback.
+    ///
+    /// ## Arguments
+    ///
+    /// * `repository` - The repository to pull the image from
+    /// * `selector` - Selects the image by tag or digest
+    /// * `progress` - Optional callback that receives the bytes downloaded and layers done
+    pub async fn pull_image_with_progress(
+        &self,
+        repository: &str,
+        selector: ReferenceSelector,
+        progress: Option<&PullProgressCallback>,
+    ) -> MicrosandboxResult<()> {
+        let tracker = PullProgressTracker::new(progress);
+        self.pull_image_tracked(repository, selector, &tracker)
+            .await
+    }
+
+    /// Downloads a blob from the registry, recording the downloaded bytes in the given tracker.
+    async fn download_image_blob_tracked(
+        &self,
+        repository: &str,
+        digest: &Digest,
+        download_size: u64,
+        tracker: &PullProgressTracker<'_>,
     ) -> MicrosandboxResult<bool> {
         #[cfg(feature = "cli")]
         let progress_bar = {
@@ -257,6 +353,7 @@ impl DockerRegistry {
                             "extracted layer already exists: {}, skipping download",
                             extracted_layer_path.display()
                         );
+                        tracker.add_bytes(download_size);
                         return Ok(false); // Return false to indicate no download occurred
                     }
                 }
@@ -267,6 +364,9 @@ impl DockerRegistry {
             }
         }
 
+        // Wait for a free download slot, so that concurrent pulls share the process-wide limit
+        let _permit = image::acquire_layer_download_permit().await?;
+
         // Ensure the destination directory exists
         if let Some(parent) = download_path.parent() {
             fs::create_dir_all(parent).await?;
@@ -286,12 +386,14 @@ impl DockerRegistry {
                 .await?
         } else if downloaded_size < download_size {
             tracing::info!("layer {} exists, but is incomplete, downloading", digest);
+            tracker.add_bytes(downloaded_size);
             OpenOptions::new().append(true).open(&download_path).await?
         } else {
             tracing::info!(
                 "file already exists skipping download: {}",
                 download_path.display()
             );
+            tracker.add_bytes(download_size);
             return Ok(false); // Return false to indicate no download occurred
         };
 
@@ -303,6 +405,7 @@ impl DockerRegistry {
         while let Some(chunk) = stream.next().await {
             let bytes = chunk?;
             file.write_all(&bytes).await?;
+            tracker.add_bytes(bytes.len() as u64);
             #[cfg(feature = "cli")]
             progress_bar.inc(bytes.len() as u64);
         }
@@ -325,18 +428,13 @@ impl DockerRegistry {
 
         Ok(true) // Return true to indicate a download occurred
     }
-}
 
-//--------------------------------------------------------------------------------------------------
-// Trait Implementations
-//--------------------------------------------------------------------------------------------------
-
-#[async_trait]
-impl OciRegistryPull for DockerRegistry {
-    async fn pull_image(
+    /// Pulls an OCI image, recording the download progress in the given tracker.
+    async fn pull_image_tracked(
         &self,
         repository: &str,
         selector: ReferenceSelector,
+        tracker: &PullProgressTracker<'_>,
     ) -> MicrosandboxResult<()> {
         // Calculate total size and save image record
         #[cfg(feature = "cli")]
@@ -364,38 +462,38 @@ impl OciRegistryPull for DockerRegistry {
 
         let image_id = db::save_or_update_image(&self.oci_db, &reference, total_size).await?;
 
-        // Save index
-        let platform = Platform::default();
-        let index_id = db::save_index(&self.oci_db, image_id, &index, Some(&platform)).await?;
-
-        // Select the right manifest for the platform or choose first if not specified
-        let manifest_desc = index
-            .manifests()
-            .iter()
+        // Select the manifest for the platform, skipping attestation manifests, which carry
+        // an `unknown/unknown` platform
+        let image_manifests = index.manifests().iter().filter(|m| {
+            !m.annotations()
+                .as_ref()
+                .is_some_and(|a| a.contains_key(DOCKER_REFERENCE_TYPE_ANNOTATION))
+        });
+        let manifest_desc = image_manifests
+            .clone()
             .find(|m| {
-                m.platform().as_ref().is_some_and(|p| {
-                    // First priority: match both Linux OS and architecture
-                    matches!(p.os(), Os::Linux) &&
-                    p.architecture() == platform.architecture() &&
-                    // Skip attestation manifests
-                    !m.annotations().as_ref().is_some_and(|a| a.contains_key(DOCKER_REFERENCE_TYPE_ANNOTATION))
-                })
-            })
-            .or_else(|| {
-                // Second priority: match architecture only, if no Linux match found
-                index.manifests().iter().find(|m| {
-                    m.platform().as_ref().is_some_and(|p| {
-                        p.architecture() == platform.architecture() &&
-                        !m.annotations().as_ref().is_some_and(|a| a.contains_key(DOCKER_REFERENCE_TYPE_ANNOTATION))
-                    })
-                })
+                m.platform()
+                    .as_ref()
+                    .is_some_and(|p| oci::platform_matches(&self.platform, p))
             })
-            .ok_or(MicrosandboxError::ManifestNotFound)?;
+            .ok_or_else(|| MicrosandboxError::PlatformNotFound {
+                image: reference.clone(),
+                platform: oci::platform_to_string(&self.platform),
+                available: image_manifests
+                    .filter_map(|m| m.platform().as_ref().map(oci::platform_to_string))
+                    .collect(),
+            })?;
+
+        // Save index
+        let index_id = db::save_index(&self.oci_db, image_id, &index, Some(&self.platform)).await?;
 
         let manifest = self
             .fetch_manifest(repository, manifest_desc.digest())
             .await?;
 
+        self.limits
+            .check_manifest(&reference, manifest.layers().iter().map(|l| l.size()))?;
+
         let manifest_id =
             db::save_manifest(&self.oci_db, image_id, Some(index_id), &manifest).await?;
 
@@ -409,13 +507,7 @@ impl OciRegistryPull for DockerRegistry {
         fetch_details_sp.finish();
 
         let layers = manifest.layers();
-
-        #[cfg(feature = "cli")]
-        let download_layers_sp = term::create_spinner(
-            DOWNLOAD_LAYER_MSG.to_string(),
-            None,
-            Some(layers.len() as u64),
-        );
+        tracker.start(layers.iter().map(|l| l.size()).sum(), layers.len());
 
         // Download layers concurrently and save to database
         let layer_futures: Vec<_> = layers
@@ -425,11 +517,15 @@ impl OciRegistryPull for DockerRegistry {
                 // Download the layer if it doesn't exist
                 // Check if the layer was actually downloaded
                 let layer_downloaded = self
-                    .download_image_blob(repository, layer_desc.digest(), layer_desc.size())
+                    .download_image_blob_tracked(
+                        repository,
+                        layer_desc.digest(),
+                        layer_desc.size(),
+                        tracker,
+                    )
                     .await?;
 
-                #[cfg(feature = "cli")]
-                download_layers_sp.inc(1);
+                tracker.layer_done();
 
                 // Get or create layer record in database
                 let layer_id = if layer_downloaded {
@@ -486,22 +582,44 @@ impl OciRegistryPull for DockerRegistry {
             result?;
         }
 
-        #[cfg(feature = "cli")]
-        download_layers_sp.finish();
-
         Ok(())
     }
+}
+
+impl DockerRegistryResponseError {
+    /// Returns whether the registry reported that the requested image does not exist.
+    pub fn is_not_found(&self) -> bool {
+        self.errors.as_array().is_some_and(|errors| {
+            errors.iter().any(|error| {
+                error
+                    .get("code")
+                    .and_then(|code| code.as_str())
+                    .is_some_and(|code| DOCKER_NOT_FOUND_ERROR_CODES.contains(&code))
+            })
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+#[async_trait]
+impl OciRegistryPull for DockerRegistry {
+    async fn pull_image(
+        &self,
+        repository: &str,
+        selector: ReferenceSelector,
+    ) -> MicrosandboxResult<()> {
+        self.pull_image_with_progress(repository, selector, None)
+            .await
+    }
 
     async fn fetch_index(
         &self,
         repository: &str,
         selector: ReferenceSelector,
     ) -> MicrosandboxResult<ImageIndex> {
-        let token = self
-            .get_access_credentials(repository, DOCKER_AUTH_SERVICE, &["pull"])
-            .await?
-            .token;
-
         // Construct URL based on selector type
         let reference = match &selector {
             ReferenceSelector::Tag { tag, digest } => {
@@ -516,13 +634,13 @@ impl OciRegistryPull for DockerRegistry {
             }
         };
 
+        let request = self.client.get(format!(
+            "{}/v2/{}/manifests/{}",
+            self.endpoint.url, repository, reference
+        ));
         let request = self
-            .client
-            .get(format!(
-                "{}/v2/{}/manifests/{}",
-                DOCKER_REGISTRY_URL, repository, reference
-            ))
-            .bearer_auth(token)
+            .authorize(request, repository)
+            .await?
             .header("Accept", DOCKER_MANIFEST_LIST_MIME_TYPE)
             .build()?;
 
@@ -542,18 +660,13 @@ impl OciRegistryPull for DockerRegistry {
         repository: &str,
         digest: &Digest,
     ) -> MicrosandboxResult<ImageManifest> {
-        let token = self
-            .get_access_credentials(repository, DOCKER_AUTH_SERVICE, &["pull"])
-            .await?
-            .token;
-
+        let request = self.client.get(format!(
+            "{}/v2/{}/manifests/{}",
+            self.endpoint.url, repository, digest
+        ));
         let request = self
-            .client
-            .get(format!(
-                "{}/v2/{}/manifests/{}",
-                DOCKER_REGISTRY_URL, repository, digest
-            ))
-            .bearer_auth(token)
+            .authorize(request, repository)
+            .await?
             .header("Accept", DOCKER_MANIFEST_MIME_TYPE)
             .build()?;
 
@@ -573,18 +686,13 @@ impl OciRegistryPull for DockerRegistry {
         repository: &str,
         digest: &Digest,
     ) -> MicrosandboxResult<ImageConfiguration> {
-        let token = self
-            .get_access_credentials(repository, DOCKER_AUTH_SERVICE, &["pull"])
-            .await?
-            .token;
-
+        let request = self.client.get(format!(
+            "{}/v2/{}/blobs/{}",
+            self.endpoint.url, repository, digest
+        ));
         let request = self
-            .client
-            .get(format!(
-                "{}/v2/{}/blobs/{}",
-                DOCKER_REGISTRY_URL, repository, digest
-            ))
-            .bearer_auth(token)
+            .authorize(request, repository)
+            .await?
             .header("Accept", DOCKER_CONFIG_MIME_TYPE)
             .build()?;
 
@@ -614,18 +722,13 @@ impl OciRegistryPull for DockerRegistry {
 
         tracing::info!("fetching blob: {digest} {start}-{end}");
 
-        let token = self
-            .get_access_credentials(repository, DOCKER_AUTH_SERVICE, &["pull"])
-            .await?
-            .token;
-
+        let request = self.client.get(format!(
+            "{}/v2/{}/blobs/{}",
+            self.endpoint.url, repository, digest
+        ));
         let request = self
-            .client
-            .get(format!(
-                "{}/v2/{}/blobs/{}",
-                DOCKER_REGISTRY_URL, repository, digest
-            ))
-            .bearer_auth(token)
+            .authorize(request, repository)
+            .await?
             .header("Accept", DOCKER_IMAGE_BLOB_MIME_TYPE)
             .header("Range", format!("bytes={start}-{end}"))
             .build()?;
@@ -912,6 +1015,30 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    async fn test_docker_response_error_is_not_found() {
+        let error = |body: &str| serde_json::from_str::<DockerRegistryResponseError>(body).unwrap();
+
+        assert!(
+            error(r#"{"errors":[{"code":"MANIFEST_UNKNOWN","message":"manifest unknown"}]}"#)
+                .is_not_found()
+        );
+        assert!(error(
+            r#"{"errors":[{"code":"NAME_UNKNOWN","message":"repository name not known"}]}"#
+        )
+        .is_not_found());
+        assert!(error(
+            r#"{"errors":[{"code":"UNAUTHORIZED","message":"authentication required"}]}"#
+        )
+        .is_not_found());
+
+        assert!(
+            !error(r#"{"errors":[{"code":"TOOMANYREQUESTS","message":"rate limited"}]}"#)
+                .is_not_found()
+        );
+        assert!(!error(r#"{"errors":"unexpected"}"#).is_not_found());
+    }
 }
 
 #[cfg(test)]
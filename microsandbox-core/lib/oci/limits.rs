@@ -0,0 +1,244 @@
+//! Limits on the size of images that are pulled.
+//!
+//! A broken or malicious image with thousands of tiny layers, or a single enormous one, can run
+//! the host out of disk space or inodes while it is extracted. Servers that pull images named by
+//! their users check every image against a maximum layer count and size:
+//!
+//! - Before any layer is downloaded, against the manifest. The manifest only records compressed
+//!   layer sizes, which are a lower bound of the extracted size.
+//! - While layers are extracted, against the sizes of the files in them, so that an image whose
+//!   layers compress well is still stopped before it fills the disk.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use getset::CopyGetters;
+use microsandbox_utils::env;
+
+use crate::{MicrosandboxError, MicrosandboxResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The largest image that is pulled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CopyGetters)]
+#[getset(get_copy = "pub with_prefix")]
+pub struct PullLimits {
+    /// The maximum number of layers.
+    max_layers: usize,
+
+    /// The maximum size of the layers, in bytes.
+    max_size: u64,
+}
+
+/// Bytes extracted from the layers of one image, checked against the maximum image size.
+///
+/// Layers are extracted concurrently, so the count is atomic.
+#[derive(Debug)]
+pub(crate) struct ExtractionBudget {
+    /// The image whose layers are extracted.
+    image: String,
+
+    /// The maximum number of bytes that may be extracted.
+    limit: u64,
+
+    /// The number of bytes extracted so far.
+    used: AtomicU64,
+}
+
+/// Bytes extracted from one attempt at extracting a layer, given back to the budget if the
+/// attempt is dropped without being committed, e.g. because it failed and is retried.
+pub(crate) struct LayerCharge<'a> {
+    budget: &'a ExtractionBudget,
+    bytes: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl PullLimits {
+    /// Creates limits with the given maximum layer count and size in bytes.
+    pub fn new(max_layers: usize, max_size: u64) -> Self {
+        Self {
+            max_layers,
+            max_size,
+        }
+    }
+
+    /// Creates limits from `MSB_MAX_IMAGE_LAYERS` and `MSB_MAX_IMAGE_SIZE_MIB`, falling back to
+    /// the defaults.
+    pub fn from_env() -> Self {
+        Self::new(env::get_max_image_layers(), env::get_max_image_size())
+    }
+
+    /// Checks the layers listed in an image's manifest, given their compressed sizes.
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`MicrosandboxError::PullLimitExceeded`] if the image has too many layers, or its
+    /// compressed layers alone are larger than the maximum size.
+    pub fn check_manifest(
+        &self,
+        image: &str,
+        layer_sizes: impl IntoIterator<Item = u64>,
+    ) -> MicrosandboxResult<()> {
+        let (count, size) = layer_sizes
+            .into_iter()
+            .fold((0usize, 0u64), |(count, size), layer| {
+                (count + 1, size.saturating_add(layer))
+            });
+
+        self.check_layer_count(image, count)?;
+        if size > self.max_size {
+            return Err(MicrosandboxError::PullLimitExceeded {
+                image: image.to_string(),
+                reason: format!(
+                    "its compressed layers are {} bytes, more than the maximum image size of {} \
+                     bytes (set {} to raise it)",
+                    size,
+                    self.max_size,
+                    env::MSB_MAX_IMAGE_SIZE_MIB_ENV_VAR
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks the number of layers of an image.
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`MicrosandboxError::PullLimitExceeded`] if the image has too many layers.
+    pub fn check_layer_count(&self, image: &str, count: usize) -> MicrosandboxResult<()> {
+        if count > self.max_layers {
+            return Err(MicrosandboxError::PullLimitExceeded {
+                image: image.to_string(),
+                reason: format!(
+                    "it has {} layers, more than the maximum of {} (set {} to raise it)",
+                    count,
+                    self.max_layers,
+                    env::MSB_MAX_IMAGE_LAYERS_ENV_VAR
+                ),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl ExtractionBudget {
+    /// Creates a budget for extracting the layers of an image.
+    pub(crate) fn new(image: impl Into<String>, limits: PullLimits) -> Self {
+        Self {
+            image: image.into(),
+            limit: limits.max_size,
+            used: AtomicU64::new(0),
+        }
+    }
+
+    /// Creates a budget that never runs out, for extracting outside of a pull.
+    #[cfg(test)]
+    pub(crate) fn unlimited() -> Self {
+        Self::new(String::new(), PullLimits::new(usize::MAX, u64::MAX))
+    }
+
+    /// Starts counting the bytes of an attempt at extracting a layer.
+    pub(crate) fn layer(&self) -> LayerCharge<'_> {
+        LayerCharge {
+            budget: self,
+            bytes: 0,
+        }
+    }
+}
+
+impl LayerCharge<'_> {
+    /// Counts the bytes of a file about to be extracted.
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`MicrosandboxError::PullLimitExceeded`] if extracting the file would make the
+    /// image larger than the maximum size.
+    pub(crate) fn charge(&mut self, bytes: u64) -> MicrosandboxResult<()> {
+        let budget = self.budget;
+        let used = budget.used.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.bytes += bytes;
+
+        if used > budget.limit {
+            return Err(MicrosandboxError::PullLimitExceeded {
+                image: budget.image.clone(),
+                reason: format!(
+                    "its extracted layers are more than the maximum image size of {} bytes \
+                     (set {} to raise it)",
+                    budget.limit,
+                    env::MSB_MAX_IMAGE_SIZE_MIB_ENV_VAR
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Keeps the counted bytes, once the layer is extracted.
+    pub(crate) fn commit(mut self) {
+        self.bytes = 0;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Drop for LayerCharge<'_> {
+    fn drop(&mut self) {
+        self.budget.used.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pull_limits_check_manifest() {
+        let limits = PullLimits::new(3, 100);
+
+        assert!(limits.check_manifest("img", [10, 20, 30]).is_ok());
+        assert!(matches!(
+            limits.check_manifest("img", [1, 1, 1, 1]),
+            Err(MicrosandboxError::PullLimitExceeded { .. })
+        ));
+        assert!(matches!(
+            limits.check_manifest("img", [60, 60]),
+            Err(MicrosandboxError::PullLimitExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn test_extraction_budget_refunds_uncommitted_layers() {
+        let budget = ExtractionBudget::new("img", PullLimits::new(10, 100));
+
+        let mut first = budget.layer();
+        first.charge(60).unwrap();
+        first.commit();
+
+        // A failed attempt gives its bytes back, so retrying it is not counted twice
+        {
+            let mut failed = budget.layer();
+            failed.charge(30).unwrap();
+        }
+        let mut retry = budget.layer();
+        retry.charge(30).unwrap();
+        retry.commit();
+        assert_eq!(budget.used.load(Ordering::Relaxed), 90);
+
+        let mut last = budget.layer();
+        let err = last.charge(20).unwrap_err();
+        assert!(err.to_string().contains("maximum image size of 100 bytes"));
+    }
+}
@@ -4,15 +4,24 @@
 //! - Pulling container images from OCI-compliant registries
 //! - Parsing and validating image references (tags and digests)
 //! - Managing image manifests, configurations, and layers
+//! - Configuring the ordered chain of registries that images are pulled from
+//! - Limiting the number and size of the layers of pulled images
+//! - Selecting the platform (OS and CPU architecture) that multi-arch images are pulled for
 
 mod implementations;
+mod limits;
+mod platform;
 mod pull;
 mod reference;
+mod registry;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
 pub use implementations::*;
+pub use limits::*;
+pub use platform::*;
 pub use pull::*;
 pub use reference::*;
+pub use registry::*;
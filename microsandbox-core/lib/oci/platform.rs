@@ -0,0 +1,118 @@
+//! Selection of the platform that images are pulled for.
+//!
+//! Multi-arch images are published as a manifest list with one manifest per OS and CPU
+//! architecture. Sandboxes are Linux MicroVms running on the host's CPU, so images are pulled for
+//! `linux` and the host architecture unless another platform is requested, e.g. `linux/arm64` to
+//! prepare images for a different machine.
+
+use oci_spec::image::{Arch, Os};
+
+use crate::{MicrosandboxError, MicrosandboxResult};
+
+pub use oci_spec::image::Platform;
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the platform images are pulled for by default: Linux on the host's CPU architecture.
+pub fn host_platform() -> Platform {
+    let mut platform = Platform::default();
+    platform.set_os(Os::Linux);
+    platform
+}
+
+/// Parses a platform in the `os/arch[/variant]` form used by Docker, e.g. `linux/arm64/v8`.
+///
+/// ## Errors
+///
+/// Returns [`MicrosandboxError::InvalidArgument`] if the OS or architecture is missing.
+pub fn parse_platform(platform: &str) -> MicrosandboxResult<Platform> {
+    let invalid = || {
+        MicrosandboxError::InvalidArgument(format!(
+            "invalid platform '{}', expected os/arch[/variant], e.g. linux/arm64",
+            platform
+        ))
+    };
+
+    let mut parts = platform.trim().split('/');
+    let os = parts.next().filter(|s| !s.is_empty()).ok_or_else(invalid)?;
+    let arch = parts.next().filter(|s| !s.is_empty()).ok_or_else(invalid)?;
+    let variant = parts.next().filter(|s| !s.is_empty());
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+
+    let mut parsed = Platform::default();
+    parsed.set_os(Os::from(os));
+    parsed.set_architecture(Arch::from(arch));
+    parsed.set_variant(variant.map(str::to_string));
+    Ok(parsed)
+}
+
+/// Formats a platform in the `os/arch[/variant]` form accepted by [`parse_platform`].
+pub fn platform_to_string(platform: &Platform) -> String {
+    match platform.variant() {
+        Some(variant) => format!("{}/{}/{}", platform.os(), platform.architecture(), variant),
+        None => format!("{}/{}", platform.os(), platform.architecture()),
+    }
+}
+
+/// Checks whether a manifest built for `candidate` satisfies the `requested` platform.
+///
+/// The OS and architecture must be the same. The variant is only compared if one was requested,
+/// so `linux/arm64` accepts a `linux/arm64/v8` manifest.
+pub fn platform_matches(requested: &Platform, candidate: &Platform) -> bool {
+    requested.os() == candidate.os()
+        && requested.architecture() == candidate.architecture()
+        && requested
+            .variant()
+            .as_ref()
+            .is_none_or(|variant| candidate.variant().as_ref() == Some(variant))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_platform() {
+        let platform = parse_platform("linux/arm64/v8").unwrap();
+        assert_eq!(platform.os(), &Os::Linux);
+        assert_eq!(platform.architecture(), &Arch::ARM64);
+        assert_eq!(platform.variant().as_deref(), Some("v8"));
+        assert_eq!(platform_to_string(&platform), "linux/arm64/v8");
+
+        let platform = parse_platform("linux/amd64").unwrap();
+        assert_eq!(platform.variant(), &None);
+        assert_eq!(platform_to_string(&platform), "linux/amd64");
+
+        for invalid in ["", "linux", "linux/", "/amd64", "linux/arm/v7/extra"] {
+            assert!(
+                parse_platform(invalid).is_err(),
+                "{invalid} should not parse"
+            );
+        }
+    }
+
+    #[test]
+    fn test_platform_matches() {
+        let arm64 = parse_platform("linux/arm64").unwrap();
+        let arm64_v8 = parse_platform("linux/arm64/v8").unwrap();
+        let arm_v7 = parse_platform("linux/arm/v7").unwrap();
+
+        assert!(platform_matches(&arm64, &arm64_v8));
+        assert!(platform_matches(&arm64_v8, &arm64_v8));
+        assert!(!platform_matches(&arm64_v8, &arm64));
+        assert!(!platform_matches(&arm64, &arm_v7));
+        assert!(!platform_matches(
+            &parse_platform("windows/arm64").unwrap(),
+            &arm64
+        ));
+        assert_eq!(host_platform().os(), &Os::Linux);
+    }
+}
@@ -1,14 +1,65 @@
-use std::ops::RangeBounds;
+use std::{
+    ops::RangeBounds,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
 
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures::stream::BoxStream;
+#[cfg(feature = "cli")]
+use indicatif::ProgressBar;
+#[cfg(feature = "cli")]
+use microsandbox_utils::term;
 use oci_spec::image::{Digest, ImageConfiguration, ImageIndex, ImageManifest};
 
 use crate::MicrosandboxResult;
 
 use super::ReferenceSelector;
 
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(feature = "cli")]
+/// Spinner message used for pulling layers.
+const PULL_LAYERS_MSG: &str = "Pull layers";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A snapshot of the progress of an image pull.
+///
+/// Reported to a [`PullProgressCallback`] whenever bytes are downloaded or a layer completes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PullProgress {
+    /// The number of layer bytes downloaded so far, including bytes that were already present.
+    pub bytes_downloaded: u64,
+
+    /// The total number of layer bytes to download, if known.
+    pub total_bytes: u64,
+
+    /// The number of layers that are done.
+    pub layers_done: usize,
+
+    /// The total number of layers in the image.
+    pub layers_total: usize,
+}
+
+/// A callback that receives the progress of an image pull.
+pub type PullProgressCallback = Box<dyn Fn(PullProgress) + Send + Sync>;
+
+/// Accumulates the progress of an image pull and forwards it to an optional callback.
+///
+/// Layers are downloaded concurrently, so the counters are atomic.
+pub(crate) struct PullProgressTracker<'a> {
+    callback: Option<&'a PullProgressCallback>,
+    bytes_downloaded: AtomicU64,
+    total_bytes: AtomicU64,
+    layers_done: AtomicUsize,
+    layers_total: AtomicUsize,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Traits
 //--------------------------------------------------------------------------------------------------
@@ -65,3 +116,203 @@ pub trait OciRegistryPull {
         range: impl RangeBounds<u64> + Send,
     ) -> MicrosandboxResult<BoxStream<'static, MicrosandboxResult<Bytes>>>;
 }
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<'a> PullProgressTracker<'a> {
+    /// Creates a new tracker that reports to the given callback, if any.
+    pub(crate) fn new(callback: Option<&'a PullProgressCallback>) -> Self {
+        Self {
+            callback,
+            bytes_downloaded: AtomicU64::new(0),
+            total_bytes: AtomicU64::new(0),
+            layers_done: AtomicUsize::new(0),
+            layers_total: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets the total number of bytes and layers, and reports the initial progress.
+    pub(crate) fn start(&self, total_bytes: u64, layers_total: usize) {
+        self.total_bytes.store(total_bytes, Ordering::Relaxed);
+        self.layers_total.store(layers_total, Ordering::Relaxed);
+        self.report();
+    }
+
+    /// Records downloaded bytes and reports the progress.
+    pub(crate) fn add_bytes(&self, bytes: u64) {
+        self.bytes_downloaded.fetch_add(bytes, Ordering::Relaxed);
+        self.report();
+    }
+
+    /// Records a completed layer and reports the progress.
+    pub(crate) fn layer_done(&self) {
+        self.layers_done.fetch_add(1, Ordering::Relaxed);
+        self.report();
+    }
+
+    /// Returns the current progress.
+    pub(crate) fn snapshot(&self) -> PullProgress {
+        PullProgress {
+            bytes_downloaded: self.bytes_downloaded.load(Ordering::Relaxed),
+            total_bytes: self.total_bytes.load(Ordering::Relaxed),
+            layers_done: self.layers_done.load(Ordering::Relaxed),
+            layers_total: self.layers_total.load(Ordering::Relaxed),
+        }
+    }
+
+    fn report(&self) {
+        if let Some(callback) = self.callback {
+            callback(self.snapshot());
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Creates the progress callback the CLI uses to show the layers of an image being pulled.
+///
+/// A spinner counting the layers that are done is added on the first report and finished once
+/// every layer is done, so the same callback can be reused if a pull falls back to another source.
+#[cfg(feature = "cli")]
+pub fn cli_pull_progress() -> PullProgressCallback {
+    let spinner = std::sync::Mutex::new(None::<ProgressBar>);
+    Box::new(move |progress| {
+        let mut spinner = spinner.lock().unwrap_or_else(|e| e.into_inner());
+        update_layer_spinner(&mut spinner, progress);
+    })
+}
+
+/// Updates the layer spinner of [`cli_pull_progress`] with a progress report.
+///
+/// Reports without any layers are skipped, so a spinner is neither added nor finished before the
+/// layers of the image are known.
+#[cfg(feature = "cli")]
+fn update_layer_spinner(spinner: &mut Option<ProgressBar>, progress: PullProgress) {
+    if progress.layers_total == 0 {
+        return;
+    }
+
+    let sp = spinner.get_or_insert_with(|| {
+        term::create_spinner(
+            PULL_LAYERS_MSG.to_string(),
+            None,
+            Some(progress.layers_total as u64),
+        )
+    });
+
+    sp.set_length(progress.layers_total as u64);
+    sp.set_position(progress.layers_done as u64);
+    if progress.layers_done >= progress.layers_total {
+        sp.finish();
+        *spinner = None;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[test]
+    fn test_pull_progress_tracker_reports_to_callback() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let callback: PullProgressCallback = {
+            let reports = reports.clone();
+            Box::new(move |progress| reports.lock().unwrap().push(progress))
+        };
+
+        let tracker = PullProgressTracker::new(Some(&callback));
+        tracker.start(300, 2);
+        tracker.add_bytes(100);
+        tracker.layer_done();
+        tracker.add_bytes(200);
+        tracker.layer_done();
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 5);
+        assert_eq!(
+            reports[0],
+            PullProgress {
+                bytes_downloaded: 0,
+                total_bytes: 300,
+                layers_done: 0,
+                layers_total: 2,
+            }
+        );
+        assert_eq!(
+            reports[4],
+            PullProgress {
+                bytes_downloaded: 300,
+                total_bytes: 300,
+                layers_done: 2,
+                layers_total: 2,
+            }
+        );
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn test_cli_pull_progress_reused_across_pulls() {
+        let mut spinner = None;
+        let progress = |layers_done| PullProgress {
+            layers_done,
+            layers_total: 2,
+            ..Default::default()
+        };
+
+        // A pull that falls back to another source reports its layers again from the start
+        for _ in 0..2 {
+            update_layer_spinner(&mut spinner, progress(0));
+            let sp = spinner.clone().expect("spinner added on the first report");
+            assert_eq!(sp.length(), Some(2));
+            assert_eq!(sp.position(), 0);
+
+            update_layer_spinner(&mut spinner, progress(1));
+            assert_eq!(sp.position(), 1);
+            assert!(!sp.is_finished());
+
+            update_layer_spinner(&mut spinner, progress(2));
+            assert!(sp.is_finished());
+            assert!(spinner.is_none());
+        }
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn test_cli_pull_progress_waits_for_layers() {
+        let mut spinner = None;
+
+        // Nothing is shown, or finished, before the layers of the image are known
+        update_layer_spinner(&mut spinner, PullProgress::default());
+        assert!(spinner.is_none());
+
+        update_layer_spinner(
+            &mut spinner,
+            PullProgress {
+                layers_total: 1,
+                ..Default::default()
+            },
+        );
+        assert!(!spinner.as_ref().unwrap().is_finished());
+    }
+
+    #[test]
+    fn test_pull_progress_tracker_without_callback() {
+        let tracker = PullProgressTracker::new(None);
+        tracker.start(10, 1);
+        tracker.add_bytes(10);
+        tracker.layer_done();
+
+        assert_eq!(tracker.snapshot().bytes_downloaded, 10);
+        assert_eq!(tracker.snapshot().layers_done, 1);
+    }
+}
@@ -0,0 +1,300 @@
+//! Registry endpoints that images are pulled from.
+//!
+//! By default images are pulled from Docker Hub. A `registries.yaml` file in the microsandbox
+//! home directory replaces that with an ordered chain, e.g. an internal mirror followed by
+//! Docker Hub:
+//!
+//! ```yaml
+//! registries:
+//!   - url: https://mirror.internal.example.com
+//!     auth:
+//!       type: basic
+//!       username: ci
+//!       password: secret
+//!   - url: https://registry-1.docker.io
+//! ```
+//!
+//! Registries are tried in order and the first successful pull wins. Every registry in the
+//! chain is expected to serve the same repositories, as a pull-through mirror does.
+//!
+//! Image references can name `docker.io`, `sandboxes.io`, or the host of any registry in the
+//! chain, e.g. `mirror.internal.example.com/library/alpine`. Use [`supported_registries`] to list
+//! them and [`ensure_registry_supported`] to reject other references before pulling.
+
+use std::path::Path;
+
+use microsandbox_utils::{env, REGISTRIES_FILENAME};
+use serde::{Deserialize, Serialize};
+
+use crate::{oci::Reference, MicrosandboxError, MicrosandboxResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Base URL of the Docker Hub registry API.
+pub const DOCKER_HUB_REGISTRY_URL: &str = "https://registry-1.docker.io";
+
+/// The domain name for the Docker registry.
+pub const DOCKER_REGISTRY: &str = "docker.io";
+
+/// The domain name for the Sandboxes registry.
+pub const SANDBOXES_REGISTRY: &str = "sandboxes.io";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A registry that images can be pulled from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryEndpoint {
+    /// Base URL of the registry API, e.g. `https://registry-1.docker.io`
+    pub url: String,
+
+    /// Credentials used when pulling from the registry
+    #[serde(default)]
+    pub auth: RegistryAuth,
+}
+
+/// Credentials for a registry.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RegistryAuth {
+    /// No credentials.
+    #[default]
+    Anonymous,
+
+    /// A username and password.
+    ///
+    /// Docker Hub exchanges these for a pull token; other registries receive them as HTTP
+    /// basic authentication.
+    Basic {
+        /// The registry username
+        username: String,
+
+        /// The registry password or access token
+        password: String,
+    },
+
+    /// A bearer token sent as is.
+    Bearer {
+        /// The bearer token
+        token: String,
+    },
+}
+
+/// The contents of the registries file.
+#[derive(Debug, Default, Deserialize)]
+struct RegistriesConfig {
+    /// Registries in the order they are tried
+    #[serde(default)]
+    registries: Vec<RegistryEndpoint>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl RegistryEndpoint {
+    /// Returns the anonymous Docker Hub endpoint.
+    pub fn docker_hub() -> Self {
+        Self {
+            url: DOCKER_HUB_REGISTRY_URL.to_string(),
+            auth: RegistryAuth::Anonymous,
+        }
+    }
+
+    /// Returns whether this endpoint is Docker Hub, which uses token authentication.
+    pub fn is_docker_hub(&self) -> bool {
+        self.url == DOCKER_HUB_REGISTRY_URL
+    }
+
+    /// Returns the host of the registry, including its port if the URL has one.
+    pub fn host(&self) -> &str {
+        let without_scheme = self
+            .url
+            .split_once("://")
+            .map_or(self.url.as_str(), |(_, rest)| rest);
+        without_scheme.split('/').next().unwrap_or(without_scheme)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Loads the registries that images are pulled from, in the order they are tried.
+///
+/// Reads `registries.yaml` from the microsandbox home directory. If the file does not exist or
+/// lists no registries, Docker Hub is the only registry.
+///
+/// ## Errors
+///
+/// Returns an error if the file cannot be read or parsed, or if a registry URL is not an
+/// `http` or `https` URL.
+pub fn load_registry_chain() -> MicrosandboxResult<Vec<RegistryEndpoint>> {
+    load_registry_chain_from(&env::get_microsandbox_home_path().join(REGISTRIES_FILENAME))
+}
+
+/// Returns the registries that image references can name, in the order they are listed in
+/// errors.
+///
+/// These are `docker.io` and `sandboxes.io`, followed by the host of every registry in the chain
+/// other than Docker Hub.
+///
+/// ## Errors
+///
+/// Returns an error if the registries file cannot be loaded.
+pub fn supported_registries() -> MicrosandboxResult<Vec<String>> {
+    Ok(supported_registries_for(&load_registry_chain()?))
+}
+
+/// Returns whether images can be pulled from the registry named by the reference.
+///
+/// ## Errors
+///
+/// Returns an error if the registries file cannot be loaded.
+pub fn is_registry_supported(reference: &Reference) -> MicrosandboxResult<bool> {
+    Ok(supported_registries()?.contains(reference.get_registry()))
+}
+
+/// Checks that images can be pulled from the registry named by the reference.
+///
+/// ## Errors
+///
+/// Returns [`MicrosandboxError::UnsupportedRegistry`], listing the supported registries, if the
+/// reference names any other registry, or an error if the registries file cannot be loaded.
+pub fn ensure_registry_supported(reference: &Reference) -> MicrosandboxResult<()> {
+    let supported = supported_registries()?;
+    if supported.contains(reference.get_registry()) {
+        return Ok(());
+    }
+
+    Err(MicrosandboxError::UnsupportedRegistry {
+        image: reference.to_string(),
+        registry: reference.get_registry().clone(),
+        supported,
+    })
+}
+
+/// Returns the registries that image references can name when pulling through `chain`.
+fn supported_registries_for(chain: &[RegistryEndpoint]) -> Vec<String> {
+    let mut registries = vec![DOCKER_REGISTRY.to_string(), SANDBOXES_REGISTRY.to_string()];
+    for endpoint in chain.iter().filter(|endpoint| !endpoint.is_docker_hub()) {
+        let host = endpoint.host().to_string();
+        if !registries.contains(&host) {
+            registries.push(host);
+        }
+    }
+
+    registries
+}
+
+/// Loads the registry chain from the given registries file.
+fn load_registry_chain_from(path: &Path) -> MicrosandboxResult<Vec<RegistryEndpoint>> {
+    if !path.exists() {
+        return Ok(vec![RegistryEndpoint::docker_hub()]);
+    }
+
+    let content = std::fs::read_to_string(path)?;
+    let config: RegistriesConfig = serde_yaml::from_str(&content)?;
+
+    let mut registries = Vec::with_capacity(config.registries.len());
+    for mut registry in config.registries {
+        if !(registry.url.starts_with("https://") || registry.url.starts_with("http://")) {
+            return Err(MicrosandboxError::ConfigValidation(format!(
+                "registry url must start with http:// or https:// in {}: {}",
+                path.display(),
+                registry.url
+            )));
+        }
+
+        registry.url = registry.url.trim_end_matches('/').to_string();
+        registries.push(registry);
+    }
+
+    if registries.is_empty() {
+        registries.push(RegistryEndpoint::docker_hub());
+    }
+
+    Ok(registries)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_registry_chain_from_file() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(REGISTRIES_FILENAME);
+
+        // Without a file, only Docker Hub is used
+        assert_eq!(
+            load_registry_chain_from(&path)?,
+            vec![RegistryEndpoint::docker_hub()]
+        );
+
+        std::fs::write(
+            &path,
+            r#"
+registries:
+  - url: https://mirror.example.com/
+    auth:
+      type: basic
+      username: ci
+      password: secret
+  - url: https://registry-1.docker.io
+"#,
+        )?;
+        assert_eq!(
+            load_registry_chain_from(&path)?,
+            vec![
+                RegistryEndpoint {
+                    url: "https://mirror.example.com".to_string(),
+                    auth: RegistryAuth::Basic {
+                        username: "ci".to_string(),
+                        password: "secret".to_string(),
+                    },
+                },
+                RegistryEndpoint::docker_hub(),
+            ]
+        );
+
+        std::fs::write(&path, "registries:\n  - url: mirror.example.com\n")?;
+        assert!(load_registry_chain_from(&path).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_supported_registries_include_chain_hosts() {
+        assert_eq!(
+            supported_registries_for(&[RegistryEndpoint::docker_hub()]),
+            vec![DOCKER_REGISTRY, SANDBOXES_REGISTRY]
+        );
+
+        let mirror = |url: &str| RegistryEndpoint {
+            url: url.to_string(),
+            auth: RegistryAuth::Anonymous,
+        };
+        assert_eq!(
+            supported_registries_for(&[
+                mirror("https://mirror.example.com/v2"),
+                mirror("http://localhost:5000"),
+                mirror("https://mirror.example.com"),
+                RegistryEndpoint::docker_hub(),
+            ]),
+            vec![
+                DOCKER_REGISTRY,
+                SANDBOXES_REGISTRY,
+                "mirror.example.com",
+                "localhost:5000"
+            ]
+        );
+    }
+}
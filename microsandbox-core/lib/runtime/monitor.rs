@@ -8,7 +8,7 @@ use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use microsandbox_utils::{
     ChildIo, MicrosandboxUtilsError, MicrosandboxUtilsResult, ProcessMonitor, RotatingLog,
-    LOG_SUFFIX,
+    SupervisorEvent, LOG_SUFFIX,
 };
 use sqlx::{Pool, Sqlite};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
@@ -313,6 +313,25 @@ impl ProcessMonitor for MicroVmMonitor {
         Ok(())
     }
 
+    fn on_event(&mut self, event: &SupervisorEvent) {
+        match event {
+            SupervisorEvent::Started { pid } => {
+                tracing::info!(sandbox = %self.sandbox_name, microvm_pid = pid, "microvm started");
+            }
+            SupervisorEvent::Terminating { pid, signal } => {
+                tracing::info!(sandbox = %self.sandbox_name, microvm_pid = pid, signal, "terminating microvm");
+            }
+            SupervisorEvent::Exited {
+                pid, code: Some(0), ..
+            } => {
+                tracing::info!(sandbox = %self.sandbox_name, microvm_pid = pid, "microvm exited");
+            }
+            SupervisorEvent::Exited { pid, code, signal } => {
+                tracing::error!(sandbox = %self.sandbox_name, microvm_pid = pid, ?code, ?signal, "microvm exited abnormally");
+            }
+        }
+    }
+
     async fn stop(&mut self) -> MicrosandboxUtilsResult<()> {
         // Restore terminal settings if they were modified
         self.restore_terminal_settings();
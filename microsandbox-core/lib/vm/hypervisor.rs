@@ -0,0 +1,114 @@
+//! Detection of the hypervisor that MicroVms run on.
+//!
+//! MicroVms need hardware virtualization from the host: KVM on Linux and Hypervisor.framework on
+//! macOS. Without it libkrun only fails once the VM boots, with a status code that does not say
+//! what is missing, so sandboxes check for the hypervisor before they get that far.
+
+use std::sync::OnceLock;
+
+use crate::{MicrosandboxError, MicrosandboxResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The device that KVM is used through
+#[cfg(target_os = "linux")]
+const KVM_DEVICE_PATH: &str = "/dev/kvm";
+
+/// Whether the hypervisor is available, probed once per process
+static VIRTUALIZATION: OnceLock<Result<(), String>> = OnceLock::new();
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Checks that the host can run MicroVms.
+///
+/// The host is only probed the first time, later calls return the same result.
+///
+/// ## Errors
+///
+/// Returns [`MicrosandboxError::VirtualizationUnavailable`] with what to fix if the hypervisor
+/// is missing or cannot be used by the current user.
+pub fn check_virtualization() -> MicrosandboxResult<()> {
+    VIRTUALIZATION
+        .get_or_init(|| {
+            let result = probe();
+            if let Err(reason) = &result {
+                tracing::error!("virtualization is unavailable: {}", reason);
+            }
+            result
+        })
+        .clone()
+        .map_err(MicrosandboxError::VirtualizationUnavailable)
+}
+
+/// Checks that KVM exists and can be opened by the current user
+#[cfg(target_os = "linux")]
+fn probe() -> Result<(), String> {
+    use std::{fs::OpenOptions, io::ErrorKind};
+
+    match OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(KVM_DEVICE_PATH)
+    {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Err(format!(
+            "{} does not exist. Enable virtualization in the BIOS and load the kvm_intel or \
+             kvm_amd module, or enable nested virtualization if this host is itself a VM",
+            KVM_DEVICE_PATH
+        )),
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => Err(format!(
+            "permission denied opening {}. Add the user to the group owning it \
+             (e.g. `sudo usermod -aG kvm $USER`, then log in again)",
+            KVM_DEVICE_PATH
+        )),
+        Err(e) => Err(format!("failed to open {}: {}", KVM_DEVICE_PATH, e)),
+    }
+}
+
+/// Checks that the Mac supports Hypervisor.framework
+#[cfg(target_os = "macos")]
+fn probe() -> Result<(), String> {
+    let mut supported: libc::c_int = 0;
+    let mut size = std::mem::size_of::<libc::c_int>();
+    let result = unsafe {
+        libc::sysctlbyname(
+            c"kern.hv_support".as_ptr(),
+            &mut supported as *mut libc::c_int as *mut libc::c_void,
+            &mut size,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+
+    if result != 0 || supported == 0 {
+        return Err(
+            "Hypervisor.framework is not supported on this Mac. If this is a VM, enable nested \
+             virtualization for it"
+                .to_string(),
+        );
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_virtualization_is_cached() {
+        let first = check_virtualization().map_err(|e| e.to_string());
+        let second = check_virtualization().map_err(|e| e.to_string());
+
+        assert_eq!(first, second);
+        assert_eq!(VIRTUALIZATION.get().cloned(), Some(probe()));
+    }
+}
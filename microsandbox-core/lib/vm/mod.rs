@@ -2,6 +2,7 @@
 
 mod builder;
 mod ffi;
+mod hypervisor;
 mod rlimit;
 mod vm;
 
@@ -12,5 +13,6 @@ mod vm;
 pub use builder::*;
 #[allow(unused)]
 pub use ffi::*;
+pub use hypervisor::*;
 pub use rlimit::*;
 pub use vm::*;
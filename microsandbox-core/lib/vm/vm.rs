@@ -6,7 +6,7 @@ use microsandbox_utils::SupportedPathType;
 use typed_path::Utf8UnixPathBuf;
 
 use crate::{
-    config::{EnvPair, NetworkScope, PathPair, PortPair},
+    config::{validate_guest_path, EnvPair, NetworkScope, PathPair, PortPair},
     utils, InvalidMicroVMConfigError, MicrosandboxError, MicrosandboxResult,
 };
 
@@ -265,6 +265,8 @@ impl MicroVm {
     /// - The MicroVm is automatically cleaned up when this returns
     /// - A non-zero status indicates the guest process failed
     pub fn start(&self) -> MicrosandboxResult<i32> {
+        super::check_virtualization()?;
+
         let ctx_id = self.ctx_id;
         let status = unsafe { ffi::krun_start_enter(ctx_id) };
         if status < 0 {
@@ -478,7 +480,10 @@ impl MicroVmConfig {
         MicroVmConfigBuilder::default()
     }
 
-    /// Validates that guest paths are not subsets of each other.
+    /// Validates that guest paths are allowed and not subsets of each other.
+    ///
+    /// Each guest path is checked with [`validate_guest_path`], since path pairs that were built
+    /// rather than parsed have not been checked yet.
     ///
     /// For example, these paths would conflict:
     /// - /app and /app/data
@@ -492,6 +497,10 @@ impl MicroVmConfig {
     /// - Ok(()) if no paths are subsets of each other
     /// - Err with details about conflicting paths
     fn validate_guest_paths(mapped_dirs: &[PathPair]) -> MicrosandboxResult<()> {
+        for dir in mapped_dirs {
+            validate_guest_path(dir.get_guest().as_str())?;
+        }
+
         // Early return if we have 0 or 1 paths - no conflicts possible
         if mapped_dirs.len() <= 1 {
             return Ok(());
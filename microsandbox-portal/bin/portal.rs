@@ -4,16 +4,38 @@
 //! It serves as the main entry point for the microsandbox portal service.
 
 use anyhow::Result;
+use axum::Router;
 use clap::Parser;
-use microsandbox_utils::DEFAULT_PORTAL_GUEST_PORT;
-use std::{net::SocketAddr, sync::Arc};
-use tokio::{net::TcpListener, signal};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
+use microsandbox_utils::{
+    DEFAULT_PORTAL_GUEST_PORT, DEFAULT_PORTAL_MAX_BODY_SIZE, DEFAULT_PORTAL_MAX_FILE_READ_SIZE,
+    MSB_RUN_AS_USER_ENV_VAR,
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+};
+use tokio::{net::TcpListener, signal, sync::Mutex};
+use tokio_rustls::TlsAcceptor;
 use tracing;
 
 use microsandbox_portal::{
-    portal::repl::{start_engines, EngineHandle},
+    portal::{
+        packages::init_package_dirs,
+        repl::{start_engines, EngineHandle},
+        upload::init_upload_cache,
+        user::init_run_as_user,
+    },
     route::create_router,
     state::SharedState,
+    tls::load_tls_acceptor,
 };
 
 //--------------------------------------------------------------------------------------------------
@@ -31,9 +53,34 @@ const DEFAULT_HOST: &str = "0.0.0.0";
 #[derive(Debug, Parser)]
 #[command(name = "portal", author, about = "JSON-RPC portal for microsandbox")]
 struct PortalArgs {
+    /// Address to bind to
+    #[arg(long, default_value = DEFAULT_HOST)]
+    host: IpAddr,
+
     /// Port number to listen on
     #[arg(short, long)]
     port: Option<u16>,
+
+    /// Path to a PEM encoded TLS certificate chain; enables TLS together with `--tls-key`
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// Path to the PEM encoded private key for `--tls-cert`
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Maximum size of a request body, in bytes
+    #[arg(long, default_value_t = DEFAULT_PORTAL_MAX_BODY_SIZE)]
+    max_body_size: usize,
+
+    /// Maximum size of a file read with `sandbox.file.read`, in bytes; larger files are rejected
+    #[arg(long, default_value_t = DEFAULT_PORTAL_MAX_FILE_READ_SIZE)]
+    max_file_read_size: u64,
+
+    /// User to execute code and commands as, by name, uid or uid:gid; a missing named user is
+    /// created. Falls back to `MSB_RUN_AS_USER`, and to root if neither is set
+    #[arg(long)]
+    run_as_user: Option<String>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -41,7 +88,10 @@ struct PortalArgs {
 //--------------------------------------------------------------------------------------------------
 
 /// Shutdown signal handler
-async fn shutdown_signal(engine_handle: Option<EngineHandle>) {
+async fn shutdown_signal(
+    engine_handle: Option<EngineHandle>,
+    runtimes: Arc<Mutex<HashMap<String, EngineHandle>>>,
+) {
     let ctrl_c = async {
         signal::ctrl_c()
             .await
@@ -75,9 +125,63 @@ async fn shutdown_signal(engine_handle: Option<EngineHandle>) {
         }
     }
 
+    // Shutdown the engines of any named runtimes
+    for (runtime, handle) in runtimes.lock().await.drain() {
+        if let Err(e) = handle.shutdown().await {
+            tracing::error!("Error shutting down engines for runtime '{}': {}", runtime, e);
+        }
+    }
+
     tracing::info!("Server shutdown complete");
 }
 
+/// Serves the router over TLS until the shutdown future completes.
+///
+/// Each accepted connection performs its TLS handshake on its own task so that a slow or
+/// failing client cannot stall the accept loop.
+async fn serve_tls(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    app: Router,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    tokio::pin!(shutdown);
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(conn) => conn,
+                Err(e) => {
+                    tracing::warn!("Failed to accept connection: {}", e);
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+
+        let acceptor = acceptor.clone();
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::warn!("TLS handshake with {} failed: {}", peer, e);
+                    return;
+                }
+            };
+
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!("Connection from {} closed with error: {}", peer, e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize tracing
@@ -88,12 +192,44 @@ async fn main() -> Result<()> {
 
     // Resolve the server address
     let port = args.port.unwrap_or(DEFAULT_PORTAL_GUEST_PORT);
-    let addr = format!("{}:{}", DEFAULT_HOST, port)
-        .parse::<SocketAddr>()
-        .unwrap();
+    let addr = SocketAddr::new(args.host, port);
+
+    // Validate the TLS certificate and key before doing any other work
+    let tls_acceptor = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => Some(load_tls_acceptor(cert, key)?),
+        _ => None,
+    };
+
+    // Drop executions to the run-as user before any engine or command is started
+    let run_as_user = args
+        .run_as_user
+        .or_else(|| std::env::var(MSB_RUN_AS_USER_ENV_VAR).ok());
+    match init_run_as_user(run_as_user.as_deref())? {
+        Some(user) => tracing::info!(
+            "Executing code and commands as {} ({}:{})",
+            user.name,
+            user.uid,
+            user.gid
+        ),
+        None => tracing::info!("Executing code and commands as root"),
+    }
+
+    // Create the package directories before the engines read their search paths
+    if let Err(e) = init_package_dirs() {
+        tracing::warn!("Failed to set up package directories: {}", e);
+    }
+
+    // Lock the upload cache away from executed code before any code runs
+    if let Err(e) = init_upload_cache() {
+        tracing::warn!("Not caching uploads: {}", e);
+    }
 
     // Initialize the engine handle
-    let state = SharedState::default();
+    let state = SharedState {
+        max_body_size: args.max_body_size,
+        max_file_read_size: args.max_file_read_size,
+        ..Default::default()
+    };
     let engine_handle_for_shutdown = Arc::clone(&state.engine_handle);
 
     // Try to start the REPL engines
@@ -110,7 +246,14 @@ async fn main() -> Result<()> {
         }
     }
 
-    tracing::info!("Starting microsandbox portal server on {}", addr);
+    tracing::info!(
+        "Starting microsandbox portal server on {} ({})",
+        addr,
+        if tls_acceptor.is_some() { "https" } else { "http" }
+    );
+
+    // Keep the named runtimes around for shutdown
+    let runtimes_for_shutdown = Arc::clone(&state.runtimes);
 
     // Create the router
     let app = create_router(state);
@@ -120,9 +263,15 @@ async fn main() -> Result<()> {
 
     // Start the server with graceful shutdown
     let listener = TcpListener::bind(addr).await?;
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal(engine_handle_clone))
-        .await?;
+    let shutdown = shutdown_signal(engine_handle_clone, runtimes_for_shutdown);
+    match tls_acceptor {
+        Some(acceptor) => serve_tls(listener, acceptor, app, shutdown).await?,
+        None => {
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown)
+                .await?
+        }
+    }
 
     Ok(())
 }
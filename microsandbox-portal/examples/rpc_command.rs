@@ -24,7 +24,7 @@
 //! Then, in another terminal, run this example:
 //!
 //! ```bash
-//! cargo run --example rpc_command
+//! cargo run --features client --example rpc_command
 //! ```
 //!
 //! # Requirements
@@ -60,81 +60,48 @@
 //! additional error handling and more sophisticated request/response processing.
 
 use anyhow::Result;
-use reqwest::Client;
-use serde_json::{json, Value};
 
-// Import the parameter types from the microsandbox-portal crate
-use microsandbox_portal::payload::{JsonRpcRequest, SandboxCommandRunParams, JSONRPC_VERSION};
+// Import the parameter and result types from the microsandbox-portal crate
+use microsandbox_portal::{
+    client::{CommandRunResult, OutputLine, RpcClient},
+    payload::SandboxCommandRunParams,
+    portal::repl::Stream,
+};
 
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
 
-/// Send a JSON-RPC request to the portal server
-async fn send_rpc_request<T: serde::Serialize>(
-    client: &Client,
-    method: &str,
-    params: T,
-) -> Result<Value> {
-    // Create a properly structured JSON-RPC request
-    let request = JsonRpcRequest {
-        jsonrpc: JSONRPC_VERSION.to_string(),
-        method: method.to_string(),
-        params: serde_json::to_value(params)?,
-        id: Some(Value::from(1)),
-    };
-
-    let response = client
-        .post("http://127.0.0.1:4444/api/v1/rpc")
-        .json(&request)
-        .send()
-        .await?
-        .json::<Value>()
-        .await?;
-
-    // Check for errors in the JSON-RPC error field
-    if response.get("error").is_some() {
-        let error = &response["error"];
-        eprintln!(
-            "RPC Error {}: {}",
-            error["code"].as_i64().unwrap_or(0),
-            error["message"].as_str().unwrap_or("Unknown error")
-        );
-        anyhow::bail!(
-            "RPC request failed: {}",
-            error["message"].as_str().unwrap_or("Unknown error")
-        );
+/// Print command output lines
+fn print_output_lines(output: &[OutputLine]) {
+    if output.is_empty() {
+        println!("No output lines found.");
     }
 
-    // Extract the result or return empty object if it doesn't exist
-    let result = response.get("result").cloned().unwrap_or(json!({}));
-    Ok(result)
+    for line in output {
+        let stream = match line.stream {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        };
+        println!("[{}] {}", stream, line.text);
+    }
 }
 
-/// Print command output lines from JSON
-fn print_output_lines(output: &Value) {
-    if let Some(output_array) = output.as_array() {
-        if output_array.is_empty() {
-            println!("No output lines found.");
-        } else {
-            for line in output_array {
-                let stream = line
-                    .get("stream")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("unknown");
-                let text = line.get("text").and_then(|v| v.as_str()).unwrap_or("");
-                println!("[{}] {}", stream, text);
-            }
-        }
-    } else {
-        println!("No output found in response.");
-    }
+/// Print the details and output of a command execution
+fn print_command_result(result: &CommandRunResult) {
+    println!("Command: {}", result.command);
+    println!("Args: {:?}", result.args);
+    println!("Exit code: {}", result.exit_code);
+    println!("Success: {}", result.success);
+
+    println!("\nOutput from execute response:");
+    print_output_lines(&result.output);
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Create HTTP client
-    let client = Client::new();
+    // Create a client for the portal
+    let client = RpcClient::new("http://127.0.0.1:4444");
 
     // Execute a simple 'ls' command using the typed params
     println!("\n📁 Running 'ls' command:");
@@ -142,44 +109,17 @@ async fn main() -> Result<()> {
         command: "ls".to_string(),
         args: vec!["-la".to_string()],
         timeout: Some(30), // Add a 30 second timeout
+        grace_period: None,
+        seed: None,
+        compression: None,
+        binary_output: false,
+        output_files: Vec::new(),
+        max_output_bytes: None,
+        output_overflow: None,
     };
 
-    let result = send_rpc_request(&client, "sandbox.command.run", ls_params).await?;
-
-    // Extract command execution details
-    let command = result
-        .get("command")
-        .and_then(|v| v.as_str())
-        .unwrap_or("unknown");
-
-    let args = result
-        .get("args")
-        .and_then(|v| v.as_array())
-        .map(|arr| format!("{:?}", arr))
-        .unwrap_or_else(|| "[]".to_string());
-
-    let exit_code = result
-        .get("exit_code")
-        .and_then(|v| v.as_i64())
-        .unwrap_or(-1);
-
-    let success = result
-        .get("success")
-        .and_then(|v| v.as_bool())
-        .unwrap_or(false);
-
-    println!("Command: {}", command);
-    println!("Args: {}", args);
-    println!("Exit code: {}", exit_code);
-    println!("Success: {}", success);
-
-    // Print the output lines
-    println!("\nOutput from execute response:");
-    if let Some(output) = result.get("output") {
-        print_output_lines(output);
-    } else {
-        println!("No output found in response.");
-    }
+    let result = client.command_run(&ls_params).await?;
+    print_command_result(&result);
 
     // Execute another command with environment variables using the typed params
     println!("\n🔄 Running 'echo' command:");
@@ -187,44 +127,17 @@ async fn main() -> Result<()> {
         command: "echo".to_string(),
         args: vec!["Hello from the sandbox!".to_string()],
         timeout: None, // No timeout needed for simple echo command
+        grace_period: None,
+        seed: None,
+        compression: None,
+        binary_output: false,
+        output_files: Vec::new(),
+        max_output_bytes: None,
+        output_overflow: None,
     };
 
-    let result = send_rpc_request(&client, "sandbox.command.run", echo_params).await?;
-
-    // Extract command execution details
-    let command = result
-        .get("command")
-        .and_then(|v| v.as_str())
-        .unwrap_or("unknown");
-
-    let args = result
-        .get("args")
-        .and_then(|v| v.as_array())
-        .map(|arr| format!("{:?}", arr))
-        .unwrap_or_else(|| "[]".to_string());
-
-    let exit_code = result
-        .get("exit_code")
-        .and_then(|v| v.as_i64())
-        .unwrap_or(-1);
-
-    let success = result
-        .get("success")
-        .and_then(|v| v.as_bool())
-        .unwrap_or(false);
-
-    println!("Command: {}", command);
-    println!("Args: {}", args);
-    println!("Exit code: {}", exit_code);
-    println!("Success: {}", success);
-
-    // Print the output lines
-    println!("\nOutput from execute response:");
-    if let Some(output) = result.get("output") {
-        print_output_lines(output);
-    } else {
-        println!("No output found in response.");
-    }
+    let result = client.command_run(&echo_params).await?;
+    print_command_result(&result);
 
     // Execute a command that will fail to demonstrate error handling
     println!("\n❌ Running a command that will fail:");
@@ -232,26 +145,27 @@ async fn main() -> Result<()> {
         command: "nonexistent_command".to_string(),
         args: vec![],
         timeout: Some(5), // Short timeout
+        grace_period: None,
+        seed: None,
+        compression: None,
+        binary_output: false,
+        output_files: Vec::new(),
+        max_output_bytes: None,
+        output_overflow: None,
     };
 
     // This will likely fail, so handle the error case
-    match send_rpc_request(&client, "sandbox.command.run", fail_params).await {
+    match client.command_run(&fail_params).await {
         Ok(result) => {
             // Still might get a result with error details
-            let exit_code = result
-                .get("exit_code")
-                .and_then(|v| v.as_i64())
-                .unwrap_or(-1);
-
-            println!("Command executed but failed with exit code: {}", exit_code);
+            println!(
+                "Command executed but failed with exit code: {}",
+                result.exit_code
+            );
 
             // Print any output/error messages
             println!("\nError output:");
-            if let Some(output) = result.get("output") {
-                print_output_lines(output);
-            } else {
-                println!("No error output found in response.");
-            }
+            print_output_lines(&result.output);
         }
         Err(e) => {
             println!("Failed to execute nonexistent command: {}", e);
@@ -0,0 +1,147 @@
+//! Example measuring the bandwidth saved by compressing large execution outputs.
+//!
+//! This example runs the same large-output command twice through the portal's JSON-RPC API,
+//! once without and once with `compression` set, and compares the size of the two response
+//! bodies. It demonstrates:
+//!
+//! - Requesting compressed output for a command
+//! - Decoding the `output_compressed` field back into output lines
+//! - The response size reduction for a large output
+//!
+//! # Running the Example
+//!
+//! First, start the portal server:
+//!
+//! ```bash
+//! cargo run --bin portal
+//! ```
+//!
+//! Then, in another terminal, run this example:
+//!
+//! ```bash
+//! cargo run --example rpc_compression
+//! ```
+//!
+//! # Requirements
+//!
+//! - A running microsandbox-portal server on localhost:4444
+//! - `seq` available on the portal host
+//!
+//! # Example Output
+//!
+//! ```text
+//! 📦 Running 'seq 1 200000' (200000 output lines):
+//! Uncompressed response: 7089068 bytes, 200000 lines in 2.056836648s
+//! Compressed response:   624524 bytes, 200000 lines in 2.660411749s
+//! Reduction: 91.2%
+//! ```
+
+use std::{io::Read, time::Instant};
+
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use flate2::read::GzDecoder;
+use reqwest::Client;
+use serde_json::Value;
+
+use microsandbox_portal::{
+    payload::{JsonRpcRequest, SandboxCommandRunParams, JSONRPC_VERSION},
+    portal::compression::{Compression, COMPRESSED_OUTPUT_FIELD},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Number of lines the benchmark command prints
+const LINE_COUNT: u32 = 200_000;
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Run the benchmark command and return the raw response body
+async fn run_command(client: &Client, compression: Option<Compression>) -> Result<Vec<u8>> {
+    let request = JsonRpcRequest {
+        jsonrpc: JSONRPC_VERSION.to_string(),
+        method: "sandbox.command.run".to_string(),
+        params: serde_json::to_value(SandboxCommandRunParams {
+            command: "seq".to_string(),
+            args: vec!["1".to_string(), LINE_COUNT.to_string()],
+            timeout: Some(60),
+            grace_period: None,
+            seed: None,
+            compression,
+            binary_output: false,
+            output_files: Vec::new(),
+            max_output_bytes: None,
+            output_overflow: None,
+        })?,
+        id: Some(Value::from(1)),
+    };
+
+    let body = client
+        .post("http://127.0.0.1:4444/api/v1/rpc")
+        .json(&request)
+        .send()
+        .await?
+        .bytes()
+        .await?;
+
+    Ok(body.to_vec())
+}
+
+/// Count the output lines of a response, decompressing them if needed
+fn count_output_lines(body: &[u8]) -> Result<usize> {
+    let response: Value = serde_json::from_slice(body)?;
+    let result = &response["result"];
+
+    let output = match result.get(COMPRESSED_OUTPUT_FIELD) {
+        Some(compressed) => {
+            let data = BASE64.decode(compressed["data"].as_str().unwrap_or_default())?;
+            let mut json = Vec::new();
+            GzDecoder::new(data.as_slice()).read_to_end(&mut json)?;
+            serde_json::from_slice(&json)?
+        }
+        None => result["output"].clone(),
+    };
+
+    Ok(output.as_array().map_or(0, Vec::len))
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let client = Client::new();
+
+    println!(
+        "\n📦 Running 'seq 1 {}' ({} output lines):",
+        LINE_COUNT, LINE_COUNT
+    );
+
+    let start = Instant::now();
+    let plain = run_command(&client, None).await?;
+    let plain_elapsed = start.elapsed();
+
+    let start = Instant::now();
+    let compressed = run_command(&client, Some(Compression::Gzip)).await?;
+    let compressed_elapsed = start.elapsed();
+
+    println!(
+        "Uncompressed response: {} bytes, {} lines in {:?}",
+        plain.len(),
+        count_output_lines(&plain)?,
+        plain_elapsed
+    );
+    println!(
+        "Compressed response:   {} bytes, {} lines in {:?}",
+        compressed.len(),
+        count_output_lines(&compressed)?,
+        compressed_elapsed
+    );
+    println!(
+        "Reduction: {:.1}%",
+        100.0 * (1.0 - compressed.len() as f64 / plain.len() as f64)
+    );
+
+    Ok(())
+}
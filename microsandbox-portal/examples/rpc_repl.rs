@@ -58,99 +58,54 @@
 //! error handling and more sophisticated request/response processing.
 
 use anyhow::Result;
-use reqwest::Client;
-use serde_json::{json, Value};
 
-// Import the parameter types from the microsandbox-portal crate
-use microsandbox_portal::payload::{JsonRpcRequest, SandboxReplRunParams, JSONRPC_VERSION};
+// Import the parameter and result types from the microsandbox-portal crate
+use microsandbox_portal::{
+    client::{OutputLine, ReplRunResult, RpcClient, RpcClientError},
+    payload::SandboxReplRunParams,
+    portal::repl::Stream,
+};
 
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
 
-/// Send a JSON-RPC request to the portal server
-async fn send_rpc_request<T: serde::Serialize>(
-    client: &Client,
-    method: &str,
-    params: T,
-) -> Result<Value> {
-    // Create a properly structured JSON-RPC request
-    let request = JsonRpcRequest {
-        jsonrpc: JSONRPC_VERSION.to_string(),
-        method: method.to_string(),
-        params: serde_json::to_value(params)?,
-        id: Some(Value::from(1)),
-    };
-
-    let response = client
-        .post("http://127.0.0.1:4444/api/v1/rpc")
-        .json(&request)
-        .send()
-        .await?
-        .json::<Value>()
-        .await?;
-
-    // Print the full response for debugging
-    println!("RPC Response: {}", response);
-
-    // Check for errors in the JSON-RPC error field
-    if response.get("error").is_some() {
-        let error = &response["error"];
-        eprintln!(
-            "RPC Error {}: {}",
-            error["code"].as_i64().unwrap_or(0),
-            error["message"].as_str().unwrap_or("Unknown error")
-        );
-        anyhow::bail!(
-            "RPC request failed: {}",
-            error["message"].as_str().unwrap_or("Unknown error")
-        );
+/// Print output lines
+fn print_output_lines(output: &[OutputLine]) {
+    if output.is_empty() {
+        println!("No output lines found.");
     }
 
-    // Also check for direct error codes (might be incorrectly formatted responses)
-    if let Some(code) = response.get("code") {
-        if let Some(message) = response.get("message") {
-            eprintln!(
-                "Direct Error {}: {}",
-                code.as_i64().unwrap_or(0),
-                message.as_str().unwrap_or("Unknown error")
-            );
-            anyhow::bail!(
-                "RPC request failed: {}",
-                message.as_str().unwrap_or("Unknown error")
-            );
-        }
+    for line in output {
+        let stream = match line.stream {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        };
+        println!("[{}] {}", stream, line.text);
     }
-
-    // Extract the result or return empty object if it doesn't exist
-    let result = response.get("result").cloned().unwrap_or(json!({}));
-    Ok(result)
 }
 
-/// Print output lines from JSON
-fn print_output_lines(output: &Value) {
-    if let Some(output_array) = output.as_array() {
-        if output_array.is_empty() {
-            println!("No output lines found.");
-        } else {
-            for line in output_array {
-                let stream = line
-                    .get("stream")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("unknown");
-                let text = line.get("text").and_then(|v| v.as_str()).unwrap_or("");
-                println!("[{}] {}", stream, text);
-            }
+/// Print the status and output of a REPL execution, or why it failed
+fn print_repl_result(result: Result<ReplRunResult, RpcClientError>, language: &str) {
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            println!("Error running {} code in REPL: {}", language, e);
+            return;
         }
-    } else {
-        println!("No output found in response.");
-    }
+    };
+
+    println!("Status: {}", result.status);
+
+    // Print the output lines directly from the run response
+    println!("\nOutput:");
+    print_output_lines(&result.output);
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Create HTTP client
-    let client = Client::new();
+    // Create a client for the portal
+    let client = RpcClient::new("http://127.0.0.1:4444");
 
     // Execute Python code in REPL
     println!("\n🐍 Running Python example in REPL:");
@@ -171,35 +126,22 @@ for i in range(1, 6):
     // Create typed parameters for Python code execution
     let python_params = SandboxReplRunParams {
         code: python_code.to_string(),
+        exec_file: None,
         language: "python".to_string(),
+        runtime: None,
         timeout: Some(30), // Add a 30 second timeout
+        seed: None,
+        preamble: None,
+        eval: false,
+        compression: None,
+        binary_output: false,
+        output_files: Vec::new(),
+        max_output_bytes: None,
+        output_overflow: None,
     };
 
     // Send sandbox.repl.run request with the typed parameters
-    let run_result = match send_rpc_request(&client, "sandbox.repl.run", python_params).await {
-        Ok(result) => result,
-        Err(e) => {
-            println!("Error running Python code in REPL: {}", e);
-            println!("Execution will continue but might fail...");
-            json!({})
-        }
-    };
-
-    // Extract execution details
-    let status = run_result
-        .get("status")
-        .and_then(|v| v.as_str())
-        .unwrap_or("unknown");
-
-    println!("Status: {}", status);
-
-    // Print the output lines directly from the run response
-    println!("\nOutput:");
-    if let Some(output) = run_result.get("output") {
-        print_output_lines(output);
-    } else {
-        println!("No output found in the response.");
-    }
+    print_repl_result(client.repl_run(&python_params).await, "Python");
 
     // Execute JavaScript code in REPL
     println!("\n🟨 Running JavaScript example in REPL:");
@@ -228,34 +170,22 @@ for (let i = 0; i < 5; i++) {
     // Create typed parameters for JavaScript code execution
     let js_params = SandboxReplRunParams {
         code: js_code.to_string(),
+        exec_file: None,
         language: "nodejs".to_string(),
+        runtime: None,
         timeout: Some(30), // Add a 30 second timeout
+        seed: None,
+        preamble: None,
+        eval: false,
+        compression: None,
+        binary_output: false,
+        output_files: Vec::new(),
+        max_output_bytes: None,
+        output_overflow: None,
     };
 
     // Send sandbox.repl.run request
-    let js_result = match send_rpc_request(&client, "sandbox.repl.run", js_params).await {
-        Ok(result) => result,
-        Err(e) => {
-            println!("Error running JavaScript code in REPL: {}", e);
-            json!({})
-        }
-    };
-
-    // Print status
-    let status = js_result
-        .get("status")
-        .and_then(|v| v.as_str())
-        .unwrap_or("unknown");
-
-    println!("Status: {}", status);
-
-    // Print the output lines directly from the run response
-    println!("\nOutput:");
-    if let Some(output) = js_result.get("output") {
-        print_output_lines(output);
-    } else {
-        println!("No output found in the response.");
-    }
+    print_repl_result(client.repl_run(&js_params).await, "JavaScript");
 
     Ok(())
 }
@@ -0,0 +1,604 @@
+//! Client for the JSON-RPC API of a microsandbox portal.
+//!
+//! [`RpcClient`] wraps the portal's `/api/v1/rpc` endpoint with a typed method per RPC, taking
+//! the same parameter types the portal parses, so that examples and tests talking to a portal
+//! directly do not each build requests and pick apart responses by hand.
+//!
+//! The portal has no RPC to cancel a running execution, executions are bounded by their
+//! `timeout` instead, so the client has no method for it either.
+//!
+//! Only available with the `client` feature.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{
+    payload::{
+        JsonRpcError, JsonRpcRequest, JsonRpcResponse, SandboxCommandRunParams,
+        SandboxEnvSetParams, SandboxEnvUnsetParams, SandboxFileListParams, SandboxFileReadParams,
+        SandboxFileWriteParams, SandboxPackagesInstallParams, SandboxReplRunParams,
+    },
+    portal::{
+        command::Termination,
+        compression::Compression,
+        fs::{DirListing, OutputFile},
+        output::OutputEncoding,
+        repl::Stream,
+        runtime::RuntimeInfo,
+        stats::SandboxStats,
+        upload::UploadCacheStats,
+    },
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Path of the JSON-RPC endpoint on the portal
+const RPC_PATH: &str = "/api/v1/rpc";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Client for the JSON-RPC API of a portal
+///
+/// ```no_run
+/// # use microsandbox_portal::{client::RpcClient, payload::SandboxCommandRunParams};
+/// # async fn example() -> Result<(), microsandbox_portal::client::RpcClientError> {
+/// let client = RpcClient::new("http://127.0.0.1:4444");
+/// let result = client
+///     .command_run(&SandboxCommandRunParams {
+///         command: "ls".to_string(),
+///         args: vec!["-la".to_string()],
+///         timeout: Some(30),
+///         grace_period: None,
+///         seed: None,
+///         compression: None,
+///         binary_output: false,
+///         output_files: Vec::new(),
+///         max_output_bytes: None,
+///         output_overflow: None,
+///     })
+///     .await?;
+/// println!("exit code: {}", result.exit_code);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RpcClient {
+    /// Base URL of the portal, e.g. `http://127.0.0.1:4444`
+    base_url: String,
+
+    /// HTTP client requests are sent with
+    http: reqwest::Client,
+
+    /// ID of the next request
+    next_id: AtomicU64,
+}
+
+/// Error returned by [`RpcClient`]
+#[derive(Debug, Error)]
+pub enum RpcClientError {
+    /// The portal could not be reached or its response could not be read
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The portal answered with a JSON-RPC error
+    #[error("RPC error {code}: {message}")]
+    Rpc {
+        /// JSON-RPC error code
+        code: i32,
+
+        /// Error message
+        message: String,
+
+        /// Optional error data
+        data: Option<Value>,
+    },
+
+    /// The parameters could not be serialized
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    /// The portal answered with something that is not the expected result
+    #[error("Invalid response: {0}")]
+    InvalidResponse(String),
+}
+
+/// One line of output of an execution
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutputLine {
+    /// Stream the line was printed to
+    pub stream: Stream,
+
+    /// Text of the line, base64 encoded if `encoding` is [`OutputEncoding::Base64`]
+    pub text: String,
+
+    /// How `text` is encoded, only set if the request asked for binary output
+    #[serde(default)]
+    pub encoding: Option<OutputEncoding>,
+}
+
+/// Output lines the portal compressed because the request asked for compression
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompressedOutput {
+    /// Encoding the output lines are compressed with
+    pub encoding: Compression,
+
+    /// Base64 encoded compressed JSON array of the output lines
+    pub data: String,
+}
+
+/// Result of running code with `sandbox.repl.run`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReplRunResult {
+    /// Status of the execution
+    pub status: String,
+
+    /// Language the code was run in
+    pub language: String,
+
+    /// Runtime the code was run with, if one was requested
+    #[serde(default)]
+    pub runtime: Option<String>,
+
+    /// Output lines, empty if they were compressed into `output_compressed`
+    #[serde(default)]
+    pub output: Vec<OutputLine>,
+
+    /// Compressed output lines, if the output was large enough to be compressed
+    #[serde(default)]
+    pub output_compressed: Option<CompressedOutput>,
+
+    /// Bytes of output dropped past the output limit
+    #[serde(default)]
+    pub dropped_bytes: u64,
+
+    /// Representation of the value of the last expression, if the request asked for it
+    #[serde(default)]
+    pub value: Option<String>,
+
+    /// Files the request asked to return with the result
+    #[serde(default)]
+    pub output_files: Vec<OutputFile>,
+}
+
+/// Result of running a command with `sandbox.command.run`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommandRunResult {
+    /// Command that was run
+    pub command: String,
+
+    /// Arguments the command was run with
+    pub args: Vec<String>,
+
+    /// Exit code of the command, `128 + signal` if it was killed by a signal
+    pub exit_code: i32,
+
+    /// Whether the command exited with code 0
+    pub success: bool,
+
+    /// How the command ended
+    pub termination: Termination,
+
+    /// Output lines, empty if they were compressed into `output_compressed`
+    #[serde(default)]
+    pub output: Vec<OutputLine>,
+
+    /// Compressed output lines, if the output was large enough to be compressed
+    #[serde(default)]
+    pub output_compressed: Option<CompressedOutput>,
+
+    /// Bytes of output dropped past the output limit
+    #[serde(default)]
+    pub dropped_bytes: u64,
+
+    /// Files the request asked to return with the result
+    #[serde(default)]
+    pub output_files: Vec<OutputFile>,
+}
+
+/// One chunk of a file read from the sandbox
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileReadResult {
+    /// Path of the file
+    pub path: String,
+
+    /// Total size of the file, in bytes
+    pub size: u64,
+
+    /// Byte offset the chunk starts at
+    pub offset: u64,
+
+    /// Length of the chunk, in bytes
+    pub length: usize,
+
+    /// Base64 encoded chunk data
+    pub data: String,
+
+    /// Whether the chunk ends at the end of the file
+    pub eof: bool,
+
+    /// Whether the chunk is shorter than requested because of the portal's chunk size
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+/// Outcome of writing one chunk of a file uploaded to the sandbox
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileWriteResult {
+    /// Path of the file
+    pub path: String,
+
+    /// Size of the file written so far, in bytes
+    pub size: u64,
+
+    /// Whether the file was copied from the upload cache instead of written
+    pub cached: bool,
+
+    /// Whether the file is complete
+    pub complete: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl From<JsonRpcError> for RpcClientError {
+    fn from(error: JsonRpcError) -> Self {
+        RpcClientError::Rpc {
+            code: error.code,
+            message: error.message,
+            data: error.data,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl RpcClient {
+    /// Create a client for the portal at the given base URL, e.g. `http://127.0.0.1:4444`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_http_client(base_url, reqwest::Client::new())
+    }
+
+    /// Create a client that sends requests with an existing HTTP client, e.g. one set up to
+    /// trust the portal's TLS certificate
+    pub fn with_http_client(base_url: impl Into<String>, http: reqwest::Client) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            http,
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Get the base URL of the portal
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Check that the portal accepts connections
+    ///
+    /// The portal has no health endpoint, so any HTTP response to a `HEAD` request counts as
+    /// healthy.
+    pub async fn health(&self) -> Result<(), RpcClientError> {
+        self.http.head(&self.base_url).send().await?;
+        Ok(())
+    }
+
+    /// Call an RPC method and deserialize its result
+    ///
+    /// The typed methods cover the RPCs the portal knows, this is for anything else.
+    pub async fn call<P: Serialize, R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: P,
+    ) -> Result<R, RpcClientError> {
+        let params = serde_json::to_value(params)?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = JsonRpcRequest::new(method.to_string(), params, json!(id));
+
+        let response = self
+            .http
+            .post(format!("{}{}", self.base_url, RPC_PATH))
+            .json(&request)
+            .send()
+            .await?;
+
+        // Methods that fail are answered with a JSON-RPC response carrying the error, requests
+        // the portal rejects before running a method with a bare JSON-RPC error object
+        if !response.status().is_success() {
+            let status = response.status();
+            let invalid = |e: serde_json::Error| {
+                RpcClientError::InvalidResponse(format!("{} ({}): {}", method, status, e))
+            };
+            let mut body: Value = response.json().await?;
+            let error = match body.get_mut("error") {
+                Some(error) => error.take(),
+                None => body,
+            };
+            let error: JsonRpcError = serde_json::from_value(error).map_err(invalid)?;
+            return Err(error.into());
+        }
+
+        let response: JsonRpcResponse = response.json().await?;
+        if let Some(error) = response.error {
+            return Err(error.into());
+        }
+
+        let result = response.result.unwrap_or(Value::Null);
+        serde_json::from_value(result)
+            .map_err(|e| RpcClientError::InvalidResponse(format!("{}: {}", method, e)))
+    }
+
+    /// Run code in a REPL with `sandbox.repl.run`
+    pub async fn repl_run(
+        &self,
+        params: &SandboxReplRunParams,
+    ) -> Result<ReplRunResult, RpcClientError> {
+        self.call("sandbox.repl.run", params).await
+    }
+
+    /// Run a command with `sandbox.command.run`
+    pub async fn command_run(
+        &self,
+        params: &SandboxCommandRunParams,
+    ) -> Result<CommandRunResult, RpcClientError> {
+        self.call("sandbox.command.run", params).await
+    }
+
+    /// Install packages with `sandbox.packages.install`
+    pub async fn packages_install(
+        &self,
+        params: &SandboxPackagesInstallParams,
+    ) -> Result<Value, RpcClientError> {
+        self.call("sandbox.packages.install", params).await
+    }
+
+    /// Set environment variables for the session with `sandbox.env.set`
+    pub async fn env_set(&self, params: &SandboxEnvSetParams) -> Result<Value, RpcClientError> {
+        self.call("sandbox.env.set", params).await
+    }
+
+    /// Unset environment variables of the session with `sandbox.env.unset`
+    pub async fn env_unset(&self, params: &SandboxEnvUnsetParams) -> Result<Value, RpcClientError> {
+        self.call("sandbox.env.unset", params).await
+    }
+
+    /// Read one chunk of a file with `sandbox.file.read`
+    pub async fn file_read(
+        &self,
+        params: &SandboxFileReadParams,
+    ) -> Result<FileReadResult, RpcClientError> {
+        self.call("sandbox.file.read", params).await
+    }
+
+    /// Write one chunk of a file with `sandbox.file.write`
+    pub async fn file_write(
+        &self,
+        params: &SandboxFileWriteParams,
+    ) -> Result<FileWriteResult, RpcClientError> {
+        self.call("sandbox.file.write", params).await
+    }
+
+    /// List one page of a directory with `sandbox.file.list`
+    pub async fn file_list(
+        &self,
+        params: &SandboxFileListParams,
+    ) -> Result<DirListing, RpcClientError> {
+        self.call("sandbox.file.list", params).await
+    }
+
+    /// Get the statistics of the upload cache with `sandbox.file.cache.stats`
+    pub async fn file_cache_stats(&self) -> Result<UploadCacheStats, RpcClientError> {
+        self.call("sandbox.file.cache.stats", json!({})).await
+    }
+
+    /// Get the interpreters and operating system of the sandbox with `sandbox.runtime.info`
+    pub async fn runtime_info(&self) -> Result<RuntimeInfo, RpcClientError> {
+        self.call("sandbox.runtime.info", json!({})).await
+    }
+
+    /// Get the current memory, CPU and disk usage of the sandbox with `sandbox.stats`
+    pub async fn stats(&self) -> Result<SandboxStats, RpcClientError> {
+        self.call("sandbox.stats", json!({})).await
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use axum::{http::StatusCode, routing::post, Json, Router};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{payload::FILE_TOO_LARGE_ERROR_CODE, portal::command::TerminationPhase};
+
+    /// Answers `sandbox.command.run` and `sandbox.repl.run` like the portal does, rejects
+    /// `sandbox.file.read` with a bare error object, and fails everything else the way the portal
+    /// fails a method
+    async fn rpc(Json(request): Json<JsonRpcRequest>) -> (StatusCode, Json<Value>) {
+        let result = match request.method.as_str() {
+            "sandbox.command.run" => json!({
+                "command": "sleep",
+                "args": ["60"],
+                "exit_code": 143,
+                "success": false,
+                "termination": { "kind": "timeout", "value": "sigterm" },
+                "output": [
+                    { "stream": "stdout", "text": "started" },
+                    { "stream": "stderr", "text": "//4=", "encoding": "base64" },
+                ],
+                "dropped_bytes": 12,
+            }),
+            "sandbox.repl.run" => json!({
+                "status": "success",
+                "language": "python",
+                "runtime": null,
+                "output": [{ "stream": "stdout", "text": "hello" }],
+                "dropped_bytes": 0,
+                "value": "42",
+                "output_files": [
+                    { "path": "/tmp/out.txt", "size": 2, "data": "aGk=" },
+                    { "path": "/tmp/missing.txt", "error": "No such file or directory" },
+                ],
+            }),
+            "sandbox.file.read" => {
+                let error = JsonRpcError {
+                    code: FILE_TOO_LARGE_ERROR_CODE,
+                    message: "File too large".to_string(),
+                    data: Some(json!({ "size": 2048, "max_file_read_size": 1024 })),
+                };
+                return (StatusCode::PAYLOAD_TOO_LARGE, Json(json!(error)));
+            }
+            method => {
+                let error = JsonRpcError {
+                    code: -32601,
+                    message: format!("Method not found: {}", method),
+                    data: None,
+                };
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!(JsonRpcResponse::error(error, request.id))),
+                );
+            }
+        };
+
+        (
+            StatusCode::OK,
+            Json(json!(JsonRpcResponse::success(result, request.id))),
+        )
+    }
+
+    /// Starts a test portal and returns a client for it
+    async fn test_client() -> RpcClient {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route(RPC_PATH, post(rpc));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        RpcClient::new(format!("http://{}", addr))
+    }
+
+    fn command_params() -> SandboxCommandRunParams {
+        SandboxCommandRunParams {
+            command: "sleep".to_string(),
+            args: vec!["60".to_string()],
+            timeout: Some(1),
+            grace_period: None,
+            seed: None,
+            compression: None,
+            binary_output: true,
+            output_files: Vec::new(),
+            max_output_bytes: None,
+            output_overflow: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_command_run_returns_typed_result() {
+        let client = test_client().await;
+
+        let result = client.command_run(&command_params()).await.unwrap();
+        assert_eq!(result.command, "sleep");
+        assert_eq!(result.args, vec!["60"]);
+        assert_eq!(result.exit_code, 143);
+        assert!(!result.success);
+        assert_eq!(
+            result.termination,
+            Termination::Timeout(TerminationPhase::Sigterm)
+        );
+        assert_eq!(result.output.len(), 2);
+        assert_eq!(result.output[0].stream, Stream::Stdout);
+        assert_eq!(result.output[0].encoding, None);
+        assert_eq!(result.output[1].stream, Stream::Stderr);
+        assert_eq!(result.output[1].encoding, Some(OutputEncoding::Base64));
+        assert_eq!(result.dropped_bytes, 12);
+        assert!(result.output_compressed.is_none());
+        assert!(result.output_files.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_repl_run_returns_typed_result() {
+        let client = test_client().await;
+        let params = SandboxReplRunParams {
+            code: "print('hello'); 42".to_string(),
+            exec_file: None,
+            language: "python".to_string(),
+            runtime: None,
+            timeout: None,
+            seed: None,
+            preamble: None,
+            eval: true,
+            compression: None,
+            binary_output: false,
+            output_files: vec!["/tmp/out.txt".to_string(), "/tmp/missing.txt".to_string()],
+            max_output_bytes: None,
+            output_overflow: None,
+        };
+
+        let result = client.repl_run(&params).await.unwrap();
+        assert_eq!(result.status, "success");
+        assert_eq!(result.language, "python");
+        assert_eq!(result.runtime, None);
+        assert_eq!(result.output.len(), 1);
+        assert_eq!(result.output[0].text, "hello");
+        assert_eq!(result.value.as_deref(), Some("42"));
+        assert_eq!(result.output_files.len(), 2);
+        assert_eq!(result.output_files[0].data.as_deref(), Some("aGk="));
+        assert!(result.output_files[1].error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_call_returns_rpc_error() {
+        let client = test_client().await;
+
+        match client.call::<_, Value>("sandbox.unknown", json!({})).await {
+            Err(RpcClientError::Rpc { code, message, .. }) => {
+                assert_eq!(code, -32601);
+                assert!(message.contains("sandbox.unknown"));
+            }
+            other => panic!("expected an RPC error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_call_returns_bare_rpc_error() {
+        let client = test_client().await;
+
+        match client
+            .call::<_, Value>("sandbox.file.read", json!({}))
+            .await
+        {
+            Err(RpcClientError::Rpc { code, data, .. }) => {
+                assert_eq!(code, FILE_TOO_LARGE_ERROR_CODE);
+                assert_eq!(data.unwrap()["max_file_read_size"], 1024);
+            }
+            other => panic!("expected an RPC error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_command_run_returns_http_error_when_unreachable() {
+        // Nothing listens on the port once the listener is dropped
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let client = RpcClient::new(format!("http://{}", addr));
+
+        match client.command_run(&command_params()).await {
+            Err(RpcClientError::Http(_)) => {}
+            other => panic!("expected an HTTP error, got {:?}", other),
+        }
+    }
+}
@@ -5,9 +5,10 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
+use serde_json::json;
 use thiserror::Error;
 
-use crate::payload::JsonRpcError;
+use crate::payload::{JsonRpcError, FILE_TOO_LARGE_ERROR_CODE, PAYLOAD_TOO_LARGE_ERROR_CODE};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -31,6 +32,29 @@ pub enum PortalError {
     /// Error during parsing
     #[error("Parse error: {0}")]
     Parse(String),
+
+    /// Request body exceeds the maximum body size, in bytes
+    #[error("Payload too large: request body exceeds the limit of {0} bytes")]
+    PayloadTooLarge(usize),
+
+    /// A file to read exceeds the maximum file read size, in bytes
+    #[error(
+        "File too large: {path} is {size} bytes, which exceeds the read limit of {limit} bytes"
+    )]
+    FileTooLarge {
+        /// Path of the file
+        path: String,
+
+        /// Size of the file
+        size: u64,
+
+        /// Maximum file read size
+        limit: u64,
+    },
+
+    /// TLS certificate or private key could not be loaded
+    #[error("TLS configuration error: {0}")]
+    Tls(String),
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -39,6 +63,7 @@ pub enum PortalError {
 
 impl IntoResponse for PortalError {
     fn into_response(self) -> Response {
+        let message = self.to_string();
         let (status, error_response) = match self {
             PortalError::JsonRpc(message) => {
                 let error = JsonRpcError {
@@ -64,7 +89,7 @@ impl IntoResponse for PortalError {
                 };
                 (StatusCode::BAD_REQUEST, error)
             }
-            PortalError::Internal(message) => {
+            PortalError::Internal(message) | PortalError::Tls(message) => {
                 let error = JsonRpcError {
                     code: -32603,
                     message,
@@ -72,6 +97,22 @@ impl IntoResponse for PortalError {
                 };
                 (StatusCode::INTERNAL_SERVER_ERROR, error)
             }
+            PortalError::PayloadTooLarge(limit) => {
+                let error = JsonRpcError {
+                    code: PAYLOAD_TOO_LARGE_ERROR_CODE,
+                    message,
+                    data: Some(json!({ "max_body_size": limit })),
+                };
+                (StatusCode::PAYLOAD_TOO_LARGE, error)
+            }
+            PortalError::FileTooLarge { size, limit, .. } => {
+                let error = JsonRpcError {
+                    code: FILE_TOO_LARGE_ERROR_CODE,
+                    message,
+                    data: Some(json!({ "size": size, "max_file_read_size": limit })),
+                };
+                (StatusCode::PAYLOAD_TOO_LARGE, error)
+            }
         };
 
         (status, Json(error_response)).into_response()
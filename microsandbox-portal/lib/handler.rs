@@ -1,22 +1,45 @@
 //! Request handlers for the microsandbox portal JSON-RPC server.
 
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum::{
+    extract::{rejection::JsonRejection, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use microsandbox_utils::DEFAULT_PORTAL_FILE_CHUNK_SIZE;
 use serde_json::{json, Value};
+use std::path::Path;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use tracing::debug;
 
 use crate::{
     error::PortalError,
     payload::{
         JsonRpcError, JsonRpcRequest, JsonRpcResponse, SandboxCommandRunParams,
-        SandboxReplRunParams, JSONRPC_VERSION,
+        SandboxEnvSetParams, SandboxEnvUnsetParams, SandboxFileListParams, SandboxFileReadParams,
+        SandboxFileWriteParams, SandboxPackagesInstallParams, SandboxReplRunParams,
+        FILE_TOO_LARGE_ERROR_CODE, JSONRPC_VERSION, PAYLOAD_TOO_LARGE_ERROR_CODE,
+    },
+    portal::{
+        command::{create_command_executor, CommandHandle},
+        compression::compress_output,
+        env::{validate_env_name, validate_env_value},
+        fs::{list_dir, read_output_files, resolve_exec_file, validate_output_files},
+        output::{output_line_json, OutputLimit, OutputOverflow},
+        packages::{validate_packages, PackageManager},
+        repl::start_engines,
+        runtime::{invalidate_runtime_info, runtime_info},
+        seed::seed_envs,
+        stats::sandbox_stats,
+        upload::{
+            cache_stats, check_writable, give_to_run_as_user, restore, store, validate_sha256,
+        },
+        user::run_as_user,
     },
-    portal::command::create_command_executor,
     state::SharedState,
 };
 
-#[cfg(any(feature = "python", feature = "nodejs"))]
-use crate::portal::repl::{start_engines, Language};
-
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
@@ -24,9 +47,21 @@ use crate::portal::repl::{start_engines, Language};
 /// Handles JSON-RPC requests
 pub async fn json_rpc_handler(
     State(state): State<SharedState>,
-    req: Json<JsonRpcRequest>,
+    req: Result<Json<JsonRpcRequest>, JsonRejection>,
 ) -> Result<impl IntoResponse, PortalError> {
-    let request = req.0;
+    let request = match req {
+        Ok(Json(request)) => request,
+        Err(rejection) if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+            let error = PortalError::PayloadTooLarge(state.max_body_size);
+            return Ok(create_error_response(error, None));
+        }
+        Err(rejection) => {
+            return Ok(create_error_response(
+                PortalError::Parse(rejection.body_text()),
+                None,
+            ));
+        }
+    };
     debug!(?request, "Received JSON-RPC request");
 
     // Check for required JSON-RPC fields
@@ -72,6 +107,47 @@ pub async fn json_rpc_handler(
                 }
             }
         }
+        "sandbox.packages.install" => {
+            match sandbox_packages_install_impl(state, request.params).await {
+                Ok(result) => Ok((StatusCode::OK, Json(JsonRpcResponse::success(result, id)))),
+                Err(e) => Ok(create_error_response(e, id)),
+            }
+        }
+        "sandbox.env.set" => match sandbox_env_set_impl(state, request.params).await {
+            Ok(result) => Ok((StatusCode::OK, Json(JsonRpcResponse::success(result, id)))),
+            Err(e) => Ok(create_error_response(e, id)),
+        },
+        "sandbox.env.unset" => match sandbox_env_unset_impl(state, request.params).await {
+            Ok(result) => Ok((StatusCode::OK, Json(JsonRpcResponse::success(result, id)))),
+            Err(e) => Ok(create_error_response(e, id)),
+        },
+        "sandbox.file.read" => match sandbox_file_read_impl(state, request.params).await {
+            Ok(result) => Ok((StatusCode::OK, Json(JsonRpcResponse::success(result, id)))),
+            Err(e) => Ok(create_error_response(e, id)),
+        },
+        "sandbox.file.write" => match sandbox_file_write_impl(state, request.params).await {
+            Ok(result) => Ok((StatusCode::OK, Json(JsonRpcResponse::success(result, id)))),
+            Err(e) => Ok(create_error_response(e, id)),
+        },
+        "sandbox.file.list" => match sandbox_file_list_impl(state, request.params).await {
+            Ok(result) => Ok((StatusCode::OK, Json(JsonRpcResponse::success(result, id)))),
+            Err(e) => Ok(create_error_response(e, id)),
+        },
+        "sandbox.file.cache.stats" => match cache_stats().await {
+            Ok(stats) => Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(stats), id)),
+            )),
+            Err(e) => Ok(create_error_response(e, id)),
+        },
+        "sandbox.runtime.info" => Ok((
+            StatusCode::OK,
+            Json(JsonRpcResponse::success(json!(runtime_info().await), id)),
+        )),
+        "sandbox.stats" => Ok((
+            StatusCode::OK,
+            Json(JsonRpcResponse::success(json!(sandbox_stats().await), id)),
+        )),
         _ => {
             let error = PortalError::MethodNotFound(format!("Method not found: {}", method));
             Ok(create_error_response(error, id))
@@ -84,105 +160,157 @@ pub async fn json_rpc_handler(
 //--------------------------------------------------------------------------------------------------
 
 /// Implementation for sandbox run method
-async fn sandbox_run_impl(_state: SharedState, params: Value) -> Result<Value, PortalError> {
+async fn sandbox_run_impl(state: SharedState, params: Value) -> Result<Value, PortalError> {
     debug!(?params, "Sandbox run method called");
 
+    // `code` defaults to empty, so tell an empty snippet apart from a missing one
+    let code_given = params.get("code").is_some_and(|code| !code.is_null());
+
     // Deserialize parameters using the structured type
     let params: SandboxReplRunParams = serde_json::from_value(params)
         .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
-
-    // Convert language string to Language enum
-    #[cfg(any(feature = "python", feature = "nodejs"))]
-    let language;
-
-    match params.language.to_lowercase().as_str() {
-        #[cfg(feature = "python")]
-        "python" => language = Language::Python,
-        #[cfg(feature = "nodejs")]
-        "node" | "nodejs" | "javascript" => language = Language::Node,
-        _ => {
-            // Check if we're being asked for a language that is supported but not enabled via features
-            let error_msg = match params.language.to_lowercase().as_str() {
-                "python" => {
-                    "Python language support is not enabled. Recompile with --features python"
-                        .to_string()
-                }
-                "node" | "nodejs" | "javascript" => {
-                    "Node.js language support is not enabled. Recompile with --features nodejs"
-                        .to_string()
-                }
-                _ => format!("Unsupported language: {}", params.language),
-            };
-            return Err(PortalError::JsonRpc(error_msg));
+    validate_output_files(&params.output_files)?;
+
+    // Run a script file already in the sandbox instead of code, if one is named
+    let exec_file = match (&params.exec_file, code_given) {
+        (Some(_), true) => {
+            return Err(PortalError::JsonRpc(
+                "'code' and 'exec_file' are mutually exclusive".to_string(),
+            ))
+        }
+        (None, false) => {
+            return Err(PortalError::JsonRpc(
+                "Either 'code' or 'exec_file' is required".to_string(),
+            ))
+        }
+        (Some(_), false) if params.eval => {
+            return Err(PortalError::JsonRpc(
+                "'eval' is not supported with 'exec_file'".to_string(),
+            ))
         }
+        (Some(path), false) => Some(resolve_exec_file(path)?),
+        (None, true) => None,
     };
 
+    // The interpreter is shared by the executions of its runtime, so it cannot be paused
+    let limit = OutputLimit::new(params.max_output_bytes, params.output_overflow);
+    if limit.overflow == OutputOverflow::Pause {
+        return Err(PortalError::JsonRpc(
+            "Output overflow 'pause' is only supported by sandbox.command.run".to_string(),
+        ));
+    }
+
+    // Select the executor of the requested language
+    let executor = state
+        .executors
+        .get(&params.language)
+        .ok_or_else(|| PortalError::JsonRpc(unsupported_language_error(&params.language)))?;
+
     // Get or initialize engine handle
     // With tokio::sync::Mutex, we can safely .await while holding the lock
-    #[cfg(any(feature = "python", feature = "nodejs"))]
-    let engine_handle = {
-        // Get the current engine handle if it exists
-        let mut lock = _state.engine_handle.lock().await;
-
-        if let Some(ref handle) = *lock {
-            handle.clone()
-        } else {
-            // Otherwise initialize a new engine
-            let handle = start_engines()
-                .await
-                .map_err(|e| PortalError::Internal(format!("Failed to start engines: {}", e)))?;
-
-            // Store the new handle in the shared state
-            *lock = Some(handle.clone());
-
-            handle
+    let engine_handle = match params.runtime.as_deref() {
+        Some(runtime) => {
+            // Get the engine handle for the named runtime if it exists
+            let mut lock = state.runtimes.lock().await;
+
+            if let Some(handle) = lock.get(runtime) {
+                handle.clone()
+            } else {
+                // Otherwise initialize a new engine for the named runtime
+                let handle = start_engines().await.map_err(|e| {
+                    PortalError::Internal(format!(
+                        "Failed to start engines for runtime '{}': {}",
+                        runtime, e
+                    ))
+                })?;
+
+                // Store the new handle in the shared state
+                lock.insert(runtime.to_string(), handle.clone());
+
+                handle
+            }
+        }
+        None => {
+            // Get the current engine handle if it exists
+            let mut lock = state.engine_handle.lock().await;
+
+            if let Some(ref handle) = *lock {
+                handle.clone()
+            } else {
+                // Otherwise initialize a new engine
+                let handle = start_engines().await.map_err(|e| {
+                    PortalError::Internal(format!("Failed to start engines: {}", e))
+                })?;
+
+                // Store the new handle in the shared state
+                *lock = Some(handle.clone());
+
+                handle
+            }
         }
     };
 
-    #[cfg(any(feature = "python", feature = "nodejs"))]
     debug!("Language: {}", params.language);
 
-    // Use a temporary identifier for evaluation
-    #[cfg(any(feature = "python", feature = "nodejs"))]
-    let temp_id = uuid::Uuid::new_v4().to_string();
-
-    // Execute the code in REPL
-    #[cfg(any(feature = "python", feature = "nodejs"))]
-    let lines = engine_handle
-        .eval(&params.code, language, &temp_id, params.timeout)
+    // Prepare and execute the code in the REPL
+    let env = state.env.lock().await.clone();
+    let code = match &exec_file {
+        Some(path) => executor.exec_file_code(path),
+        None => params.code,
+    };
+    let execution = executor.prepare(
+        &code,
+        params.eval,
+        params.seed,
+        params.preamble.as_deref(),
+        &env,
+    );
+    let output = executor
+        .execute(&engine_handle, &execution, params.timeout, limit.max_bytes)
         .await
         .map_err(|e| PortalError::Internal(format!("REPL execution failed: {}", e)))?;
 
-    #[cfg(any(feature = "python", feature = "nodejs"))]
-    debug!("REPL execution produced {} output lines", lines.len());
+    debug!(
+        "REPL execution produced {} output lines",
+        output.lines.len()
+    );
 
     // Convert the lines to a format suitable for JSON
-    #[cfg(any(feature = "python", feature = "nodejs"))]
-    let output_lines: Vec<Value> = lines
+    let output_lines: Vec<Value> = output
+        .lines
         .iter()
         .map(|line| {
-            json!({
-                "stream": match line.stream {
-                    crate::portal::repl::Stream::Stdout => "stdout",
-                    crate::portal::repl::Stream::Stderr => "stderr",
-                },
-                "text": line.text,
-            })
+            output_line_json(
+                line.stream,
+                &line.text,
+                line.raw.as_deref(),
+                params.binary_output,
+            )
         })
         .collect();
 
     // Construct the result JSON object with explicit String conversions
-    #[cfg(any(feature = "python", feature = "nodejs"))]
-    let result = json!({
+    let mut result = json!({
         "status": "success".to_string(),
         "language": params.language.to_string(),
+        "runtime": params.runtime,
         "output": output_lines,
+        "dropped_bytes": output.dropped_bytes,
     });
 
-    #[cfg(any(feature = "python", feature = "nodejs"))]
+    if params.eval {
+        result["value"] = json!(output.value);
+    }
+
+    attach_output_files(&mut result, params.output_files).await?;
+
     debug!("Returning result with output: {}", result);
 
-    #[cfg(any(feature = "python", feature = "nodejs"))]
+    // Compress large outputs if the client asked for it
+    if let Some(compression) = params.compression {
+        compress_output(&mut result, compression)?;
+    }
+
     Ok(result)
 }
 
@@ -193,28 +321,33 @@ async fn sandbox_command_run_impl(state: SharedState, params: Value) -> Result<V
     // Deserialize parameters using the structured type
     let params: SandboxCommandRunParams = serde_json::from_value(params)
         .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
+    validate_output_files(&params.output_files)?;
 
-    // Get or initialize command executor handle
-    let cmd_handle = {
-        // Get the current command handle if it exists
-        let mut lock = state.command_handle.lock().await;
-
-        if let Some(ref handle) = *lock {
-            handle.clone()
-        } else {
-            // Otherwise initialize a new command executor
-            let handle = create_command_executor();
+    // A paused command only ends when its timeout stops it
+    let limit = OutputLimit::new(params.max_output_bytes, params.output_overflow);
+    if limit.overflow == OutputOverflow::Pause && params.timeout.is_none() {
+        return Err(PortalError::JsonRpc(
+            "Output overflow 'pause' requires a timeout".to_string(),
+        ));
+    }
 
-            // Store the new handle in the shared state
-            *lock = Some(handle.clone());
+    // Get or initialize command executor handle
+    let cmd_handle = command_executor(&state).await;
 
-            handle
-        }
-    };
+    // The seed's variables take precedence over the session's
+    let mut envs = state.env.lock().await.envs();
+    envs.extend(params.seed.map(seed_envs).unwrap_or_default());
 
     // Execute the command
-    let (exit_code, output_lines) = cmd_handle
-        .execute(&params.command, params.args.clone(), params.timeout)
+    let (termination, output_lines, dropped_bytes) = cmd_handle
+        .execute_with_limit(
+            &params.command,
+            params.args.clone(),
+            envs,
+            params.timeout,
+            params.grace_period,
+            limit,
+        )
         .await
         .map_err(|e| PortalError::Internal(format!("Command execution failed: {}", e)))?;
 
@@ -222,34 +355,340 @@ async fn sandbox_command_run_impl(state: SharedState, params: Value) -> Result<V
     let formatted_lines = output_lines
         .iter()
         .map(|line| {
-            json!({
-                "stream": match line.stream {
-                    crate::portal::repl::Stream::Stdout => "stdout",
-                    crate::portal::repl::Stream::Stderr => "stderr",
-                },
-                "text": line.text,
-            })
+            output_line_json(
+                line.stream,
+                &line.text,
+                line.raw.as_deref(),
+                params.binary_output,
+            )
         })
         .collect::<Vec<Value>>();
 
     // Construct the result JSON object
-    let result = json!({
+    let mut result = json!({
         "command": params.command,
         "args": params.args,
-        "exit_code": exit_code,
-        "success": exit_code == 0,
+        "exit_code": termination.exit_code(),
+        "success": termination.is_success(),
+        "termination": termination,
         "output": formatted_lines,
+        "dropped_bytes": dropped_bytes,
     });
+    attach_output_files(&mut result, params.output_files).await?;
 
     debug!("Returning command result with output: {}", result);
 
+    // Compress large outputs if the client asked for it
+    if let Some(compression) = params.compression {
+        compress_output(&mut result, compression)?;
+    }
+
     Ok(result)
 }
 
+/// Implementation for sandbox packages install method
+///
+/// Installs packages with the package manager of a language, so that the code executed in the
+/// sandbox can import them. A failed install is returned as a result with `success` unset and
+/// the package manager's output, like a failed command.
+async fn sandbox_packages_install_impl(
+    state: SharedState,
+    params: Value,
+) -> Result<Value, PortalError> {
+    debug!(?params, "Sandbox packages install method called");
+
+    let params: SandboxPackagesInstallParams = serde_json::from_value(params)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
+
+    let manager = PackageManager::for_language(&params.language)?;
+    validate_packages(&params.packages)?;
+    let (program, args) = manager.install_command(&params.packages);
+
+    let cmd_handle = command_executor(&state).await;
+    let envs = state.env.lock().await.envs();
+    let (termination, output_lines) = cmd_handle
+        .execute_with_env(&program, args, envs, params.timeout, None)
+        .await
+        .map_err(|e| PortalError::Internal(format!("Package install failed to run: {}", e)))?;
+
+    // Even a failed install may have installed some of the packages
+    invalidate_runtime_info().await;
+
+    let formatted_lines = output_lines
+        .iter()
+        .map(|line| output_line_json(line.stream, &line.text, line.raw.as_deref(), false))
+        .collect::<Vec<Value>>();
+
+    Ok(json!({
+        "language": params.language,
+        "packages": params.packages,
+        "exit_code": termination.exit_code(),
+        "success": termination.is_success(),
+        "termination": termination,
+        "output": formatted_lines,
+    }))
+}
+
+/// Implementation for sandbox env set method
+///
+/// Sets environment variables that every later execution of the session inherits. The request
+/// is rejected as a whole if any of the variables cannot be set.
+async fn sandbox_env_set_impl(state: SharedState, params: Value) -> Result<Value, PortalError> {
+    let params: SandboxEnvSetParams = serde_json::from_value(params)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
+    debug!(names = ?params.vars.keys().collect::<Vec<_>>(), "Sandbox env set method called");
+
+    for (name, value) in &params.vars {
+        validate_env_name(name)?;
+        validate_env_value(name, value)?;
+    }
+
+    let mut env = state.env.lock().await;
+    for (name, value) in params.vars {
+        env.set(name, value);
+    }
+
+    Ok(json!({ "vars": env.names() }))
+}
+
+/// Implementation for sandbox env unset method
+///
+/// Unsets environment variables set with `sandbox.env.set`. Names that are not set are ignored.
+async fn sandbox_env_unset_impl(state: SharedState, params: Value) -> Result<Value, PortalError> {
+    debug!(?params, "Sandbox env unset method called");
+
+    let params: SandboxEnvUnsetParams = serde_json::from_value(params)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
+
+    for name in &params.names {
+        validate_env_name(name)?;
+    }
+
+    let mut env = state.env.lock().await;
+    let unset: Vec<&String> = params.names.iter().filter(|name| env.unset(name)).collect();
+
+    Ok(json!({ "vars": env.names(), "unset": unset }))
+}
+
+/// Implementation for sandbox file read method
+///
+/// Reads one chunk of a file and returns it base64 encoded along with the file's total size, so
+/// that clients can download large files piece by piece without the portal buffering them.
+/// Files larger than the maximum file read size are rejected rather than returned in part.
+async fn sandbox_file_read_impl(state: SharedState, params: Value) -> Result<Value, PortalError> {
+    debug!(?params, "Sandbox file read method called");
+
+    let params: SandboxFileReadParams = serde_json::from_value(params)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
+
+    let mut file = tokio::fs::File::open(&params.path)
+        .await
+        .map_err(|e| PortalError::Internal(format!("Failed to open {}: {}", params.path, e)))?;
+
+    let metadata = file
+        .metadata()
+        .await
+        .map_err(|e| PortalError::Internal(format!("Failed to stat {}: {}", params.path, e)))?;
+    if !metadata.is_file() {
+        return Err(PortalError::JsonRpc(format!(
+            "Not a regular file: {}",
+            params.path
+        )));
+    }
+
+    // Only hand out files that the executed code could read itself
+    if run_as_user().is_some_and(|user| !user.can_read(&metadata)) {
+        return Err(PortalError::JsonRpc(format!(
+            "Permission denied: {}",
+            params.path
+        )));
+    }
+    let size = metadata.len();
+    if size > state.max_file_read_size {
+        return Err(PortalError::FileTooLarge {
+            path: params.path,
+            size,
+            limit: state.max_file_read_size,
+        });
+    }
+
+    // Read up to one chunk from the requested offset
+    let requested = params.length.unwrap_or(DEFAULT_PORTAL_FILE_CHUNK_SIZE);
+    let length = requested.min(DEFAULT_PORTAL_FILE_CHUNK_SIZE);
+    let mut data = Vec::with_capacity(length.min(size.saturating_sub(params.offset) as usize));
+    file.seek(std::io::SeekFrom::Start(params.offset))
+        .await
+        .map_err(|e| PortalError::Internal(format!("Failed to seek {}: {}", params.path, e)))?;
+    file.take(length as u64)
+        .read_to_end(&mut data)
+        .await
+        .map_err(|e| PortalError::Internal(format!("Failed to read {}: {}", params.path, e)))?;
+
+    let eof = params.offset + data.len() as u64 >= size;
+
+    // The chunk cap cut the read short of the requested length before the end of the file
+    let truncated = data.len() < requested && !eof;
+
+    Ok(json!({
+        "path": params.path,
+        "size": size,
+        "offset": params.offset,
+        "length": data.len(),
+        "data": BASE64.encode(&data),
+        "eof": eof,
+        "truncated": truncated,
+    }))
+}
+
+/// Implementation for sandbox file list method
+///
+/// Lists one page of the entries of a directory with their sizes and modification times, for
+/// clients browsing the files in the sandbox.
+async fn sandbox_file_list_impl(_state: SharedState, params: Value) -> Result<Value, PortalError> {
+    debug!(?params, "Sandbox file list method called");
+
+    let params: SandboxFileListParams = serde_json::from_value(params)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
+
+    let listing =
+        tokio::task::spawn_blocking(move || list_dir(&params.path, params.offset, params.limit))
+            .await
+            .map_err(|e| PortalError::Internal(format!("Failed to list directory: {}", e)))??;
+
+    Ok(json!(listing))
+}
+
+/// Implementation for sandbox file write method
+///
+/// Writes one chunk of an uploaded file. A request with a checksum but no data copies the file
+/// from the upload cache instead, if it is cached, so that clients only send files the cache
+/// does not have yet.
+async fn sandbox_file_write_impl(_state: SharedState, params: Value) -> Result<Value, PortalError> {
+    let params: SandboxFileWriteParams = serde_json::from_value(params)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
+    debug!(
+        path = %params.path,
+        offset = params.offset,
+        eof = params.eof,
+        "Sandbox file write method called"
+    );
+
+    let sha256 = params.sha256.as_deref().map(validate_sha256).transpose()?;
+    let path = Path::new(&params.path);
+    check_writable(path)?;
+
+    let Some(data) = params.data else {
+        let sha256 = sha256.ok_or_else(|| {
+            PortalError::JsonRpc("Either data or sha256 must be given".to_string())
+        })?;
+        let size = restore(&sha256, path).await?;
+
+        return Ok(json!({
+            "path": params.path,
+            "size": size.unwrap_or(0),
+            "cached": size.is_some(),
+            "complete": size.is_some(),
+        }));
+    };
+
+    let data = BASE64
+        .decode(&data)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid chunk data: {}", e)))?;
+
+    // The first chunk replaces the file, later ones must continue where the last one ended
+    let existed = path.exists();
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create(params.offset == 0)
+        .truncate(params.offset == 0)
+        .open(path)
+        .await
+        .map_err(|e| PortalError::Internal(format!("Failed to open {}: {}", params.path, e)))?;
+    if !existed {
+        give_to_run_as_user(path)?;
+    }
+
+    let size = file
+        .metadata()
+        .await
+        .map_err(|e| PortalError::Internal(format!("Failed to stat {}: {}", params.path, e)))?
+        .len();
+    if params.offset != size {
+        return Err(PortalError::JsonRpc(format!(
+            "Chunk offset {} does not continue {} at {} bytes",
+            params.offset, params.path, size
+        )));
+    }
+
+    file.seek(std::io::SeekFrom::Start(params.offset))
+        .await
+        .map_err(|e| PortalError::Internal(format!("Failed to seek {}: {}", params.path, e)))?;
+    file.write_all(&data)
+        .await
+        .map_err(|e| PortalError::Internal(format!("Failed to write {}: {}", params.path, e)))?;
+
+    let cached = match (&sha256, params.eof) {
+        (Some(sha256), true) => store(sha256, path).await?,
+        _ => false,
+    };
+
+    Ok(json!({
+        "path": params.path,
+        "size": params.offset + data.len() as u64,
+        "cached": cached,
+        "complete": params.eof,
+    }))
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions: Helpers
 //--------------------------------------------------------------------------------------------------
 
+/// Reads the output files an execution asked for into the `output_files` field of its result
+async fn attach_output_files(result: &mut Value, paths: Vec<String>) -> Result<(), PortalError> {
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    let output_files = tokio::task::spawn_blocking(move || read_output_files(&paths))
+        .await
+        .map_err(|e| PortalError::Internal(format!("Failed to read output files: {}", e)))?;
+    result["output_files"] = json!(output_files);
+
+    Ok(())
+}
+
+/// Describes why no executor is registered for a language, pointing at the feature flag to
+/// enable for the languages the portal supports
+fn unsupported_language_error(language: &str) -> String {
+    match language.to_lowercase().as_str() {
+        "python" => {
+            "Python language support is not enabled. Recompile with --features python".to_string()
+        }
+        "node" | "nodejs" | "javascript" => {
+            "Node.js language support is not enabled. Recompile with --features nodejs".to_string()
+        }
+        _ => format!("Unsupported language: {}", language),
+    }
+}
+
+/// Returns the shared command executor, starting it on first use
+async fn command_executor(state: &SharedState) -> CommandHandle {
+    let mut lock = state.command_handle.lock().await;
+
+    if let Some(ref handle) = *lock {
+        handle.clone()
+    } else {
+        // Otherwise initialize a new command executor
+        let handle = create_command_executor();
+
+        // Store the new handle in the shared state
+        *lock = Some(handle.clone());
+
+        handle
+    }
+}
+
 /// Helper function to create a JSON-RPC error response from a PortalError
 fn create_error_response(
     error: PortalError,
@@ -260,18 +699,31 @@ fn create_error_response(
         PortalError::JsonRpc(_) => -32600,        // Invalid Request
         PortalError::MethodNotFound(_) => -32601, // Method not found
         PortalError::Parse(_) => -32700,          // Parse error
-        PortalError::Internal(_) => -32603,       // Internal error
+        PortalError::Internal(_) | PortalError::Tls(_) => -32603, // Internal error
+        PortalError::PayloadTooLarge(_) => PAYLOAD_TOO_LARGE_ERROR_CODE,
+        PortalError::FileTooLarge { .. } => FILE_TOO_LARGE_ERROR_CODE,
+    };
+
+    // Oversized payloads and files carry the limit so that clients can report it. Both use
+    // status 413, which the server relays to clients as-is
+    let (status, data) = match &error {
+        PortalError::PayloadTooLarge(limit) => (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Some(json!({ "max_body_size": limit })),
+        ),
+        PortalError::FileTooLarge { size, limit, .. } => (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Some(json!({ "size": size, "max_file_read_size": limit })),
+        ),
+        _ => (StatusCode::BAD_REQUEST, None),
     };
 
     let json_rpc_error = JsonRpcError {
         code,
         message: error.to_string(),
-        data: None,
+        data,
     };
 
     // Return the properly formatted error response
-    (
-        StatusCode::BAD_REQUEST,
-        Json(JsonRpcResponse::error(json_rpc_error, id)),
-    )
+    (status, Json(JsonRpcResponse::error(json_rpc_error, id)))
 }
@@ -6,20 +6,26 @@
 // Types
 //--------------------------------------------------------------------------------------------------
 
+#[cfg(feature = "client")]
+pub mod client;
 pub mod error;
 pub mod handler;
 pub mod payload;
 pub mod portal;
 pub mod route;
 pub mod state;
+pub mod tls;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
+#[cfg(feature = "client")]
+pub use client::*;
 pub use error::*;
 pub use handler::*;
 pub use payload::*;
 pub use portal::*;
 pub use route::*;
 pub use state::*;
+pub use tls::*;
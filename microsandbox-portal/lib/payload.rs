@@ -2,6 +2,9 @@
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::portal::{compression::Compression, output::OutputOverflow};
 
 //--------------------------------------------------------------------------------------------------
 // Constants
@@ -10,6 +13,12 @@ use serde_json::Value;
 /// JSON-RPC version - always "2.0"
 pub const JSONRPC_VERSION: &str = "2.0";
 
+/// JSON-RPC error code for request bodies that exceed the portal's maximum body size
+pub const PAYLOAD_TOO_LARGE_ERROR_CODE: i32 = -32005;
+
+/// JSON-RPC error code for files read that exceed the portal's maximum file read size
+pub const FILE_TOO_LARGE_ERROR_CODE: i32 = -32004;
+
 //--------------------------------------------------------------------------------------------------
 // Types: JSON-RPC Structures
 //--------------------------------------------------------------------------------------------------
@@ -72,14 +81,66 @@ pub struct JsonRpcError {
 /// Request parameters for executing code in a REPL environment
 #[derive(Debug, Deserialize, Serialize)]
 pub struct SandboxReplRunParams {
-    /// Code to be executed
+    /// Code to be executed. Required unless `exec_file` is given
+    #[serde(default)]
     pub code: String,
 
+    /// Optional absolute path of a script file already in the sandbox to run instead of `code`,
+    /// e.g. the entrypoint of an uploaded multi-file project. Mutually exclusive with `code`
+    #[serde(default)]
+    pub exec_file: Option<String>,
+
     /// Programming language to use for execution
     pub language: String,
 
+    /// Optional named runtime to execute in. Each named runtime keeps its own interpreter
+    /// state, while sharing the sandbox filesystem with the others. Uses the default
+    /// runtime if not specified.
+    #[serde(default)]
+    pub runtime: Option<String>,
+
     /// Optional timeout in seconds after which execution will be cancelled
     pub timeout: Option<u64>,
+
+    /// Optional seed for reproducible runs. It is exposed as `PYTHONHASHSEED` and
+    /// `RANDOM_SEED`, and Python code also gets `random` and `numpy` (if installed) seeded.
+    /// Seeding is best-effort and does not make every execution deterministic.
+    #[serde(default)]
+    pub seed: Option<u32>,
+
+    /// Optional code run before the code, e.g. `import numpy as np`. It is run as a separate
+    /// statement, so the line numbers reported for the code are not shifted by it.
+    #[serde(default)]
+    pub preamble: Option<String>,
+
+    /// Whether to return the repr of the code's final expression in a `value` field, like a
+    /// notebook cell does. The field is null when the code does not end in an expression.
+    #[serde(default)]
+    pub eval: bool,
+
+    /// Optional encoding to compress large outputs with
+    #[serde(default)]
+    pub compression: Option<Compression>,
+
+    /// Whether to return output lines that are not valid UTF-8 base64 encoded, with an
+    /// `encoding` field on every line, instead of decoding them lossily
+    #[serde(default)]
+    pub binary_output: bool,
+
+    /// Absolute paths of files to read after the execution and return base64 encoded in an
+    /// `output_files` field. A file that cannot be returned gets an error entry instead
+    #[serde(default)]
+    pub output_files: Vec<String>,
+
+    /// Optional maximum number of bytes of output buffered, past which the output is dropped.
+    /// Defaults to 8 MiB
+    #[serde(default)]
+    pub max_output_bytes: Option<usize>,
+
+    /// What happens to output past `max_output_bytes`. Only `drop` is supported, as the
+    /// interpreter is shared by the executions of its runtime
+    #[serde(default)]
+    pub output_overflow: Option<OutputOverflow>,
 }
 
 /// Request parameters for executing a shell command
@@ -94,6 +155,118 @@ pub struct SandboxCommandRunParams {
 
     /// Optional timeout in seconds after which execution will be cancelled
     pub timeout: Option<u64>,
+
+    /// Optional time in seconds a timed out command is given to exit after `SIGTERM` before it
+    /// is killed with `SIGKILL`. Defaults to 5 seconds
+    #[serde(default)]
+    pub grace_period: Option<u64>,
+
+    /// Optional seed for reproducible runs, set as the `PYTHONHASHSEED` and `RANDOM_SEED`
+    /// environment variables of the command
+    #[serde(default)]
+    pub seed: Option<u32>,
+
+    /// Optional encoding to compress large outputs with
+    #[serde(default)]
+    pub compression: Option<Compression>,
+
+    /// Whether to return output lines that are not valid UTF-8 base64 encoded, with an
+    /// `encoding` field on every line, instead of decoding them lossily
+    #[serde(default)]
+    pub binary_output: bool,
+
+    /// Absolute paths of files to read after the execution and return base64 encoded in an
+    /// `output_files` field. A file that cannot be returned gets an error entry instead
+    #[serde(default)]
+    pub output_files: Vec<String>,
+
+    /// Optional maximum number of bytes of output buffered. Defaults to 8 MiB
+    #[serde(default)]
+    pub max_output_bytes: Option<usize>,
+
+    /// What happens to output past `max_output_bytes`: `drop` (the default) drops it, `pause`
+    /// stops reading it until the timeout stops the command, which then needs a timeout
+    #[serde(default)]
+    pub output_overflow: Option<OutputOverflow>,
+}
+
+/// Request parameters for installing packages into the sandbox
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SandboxPackagesInstallParams {
+    /// Language whose package manager installs the packages (python, node)
+    pub language: String,
+
+    /// Package specs as the package manager takes them, e.g. `requests==2.32.3` or `lodash@4`
+    pub packages: Vec<String>,
+
+    /// Optional timeout in seconds after which the install will be cancelled
+    pub timeout: Option<u64>,
+}
+
+/// Request parameters for setting environment variables for the session
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SandboxEnvSetParams {
+    /// Variables to set, by name, replacing the values of the ones already set
+    pub vars: BTreeMap<String, String>,
+}
+
+/// Request parameters for unsetting environment variables of the session
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SandboxEnvUnsetParams {
+    /// Names of the variables to unset
+    pub names: Vec<String>,
+}
+
+/// Request parameters for reading a chunk of a file in the sandbox
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SandboxFileReadParams {
+    /// Path of the file to read
+    pub path: String,
+
+    /// Byte offset to start reading at
+    #[serde(default)]
+    pub offset: u64,
+
+    /// Maximum number of bytes to read, capped at the portal's chunk size
+    pub length: Option<usize>,
+}
+
+/// Request parameters for listing a directory in the sandbox
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SandboxFileListParams {
+    /// Absolute path of the directory to list
+    pub path: String,
+
+    /// Index of the first entry to return, in name order
+    #[serde(default)]
+    pub offset: usize,
+
+    /// Maximum number of entries to return, capped at the portal's page size
+    pub limit: Option<usize>,
+}
+
+/// Request parameters for writing a chunk of a file uploaded to the sandbox
+///
+/// Without `data`, the request only asks whether a file with the given `sha256` is cached, and
+/// copies it to `path` if it is.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SandboxFileWriteParams {
+    /// Path of the file to write
+    pub path: String,
+
+    /// Base64 encoded chunk data
+    pub data: Option<String>,
+
+    /// Byte offset the chunk starts at. A chunk at offset zero replaces the file
+    #[serde(default)]
+    pub offset: u64,
+
+    /// Whether the chunk is the last one of the file
+    #[serde(default)]
+    pub eof: bool,
+
+    /// Hex encoded sha256 of the whole file, to check it against and cache it under
+    pub sha256: Option<String>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -3,8 +3,10 @@
 //! This module provides functionality for executing system commands in a sandboxed environment.
 //! It handles:
 //! - Spawning and managing command processes using tokio::process::Command
-//! - Streaming stdout and stderr output in real-time
-//! - Managing command lifecycle and termination
+//! - Streaming stdout and stderr output in real-time, buffering it up to an output limit
+//! - Managing command lifecycle and termination, giving timed out commands a grace period to
+//!   exit after `SIGTERM` before killing them with `SIGKILL`
+//! - Reporting how a command terminated (exit code, signal, timeout or OOM kill)
 //! - Providing a secure execution environment for system commands
 //!
 //! # Architecture
@@ -21,22 +23,31 @@
 //! variables to maintain system security. Command execution is isolated to prevent
 //! damage to the host system.
 
+use microsandbox_utils::DEFAULT_PORTAL_KILL_GRACE_PERIOD_SECS;
+use serde::{Deserialize, Serialize};
 use std::{
     fmt,
+    os::unix::process::ExitStatusExt,
+    process::ExitStatus,
     sync::{Arc, Mutex},
 };
 use tokio::{
-    io::{AsyncBufReadExt, BufReader},
-    process::Command,
+    io::BufReader,
+    process::{Child, Command},
     sync::{
         mpsc::{self, Sender},
         oneshot,
     },
-    time::{sleep, Duration},
+    time::{sleep, sleep_until, Duration, Instant},
 };
 use uuid::Uuid;
 
-use crate::portal::repl::types::Stream;
+use crate::portal::{
+    output::{line_len, OutputBuffer, OutputLimit, OutputLines, OutputOverflow},
+    packages::package_envs,
+    repl::types::Stream,
+    user::RunAsUserExt,
+};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -62,6 +73,38 @@ pub enum CommandError {
     Unavailable(String),
 }
 
+/// How a command terminated
+///
+/// Serialized as `{"kind": "exited", "value": 0}`, `{"kind": "signaled", "value": 9}`,
+/// `{"kind": "timeout", "value": "sigterm"}` or `{"kind": "oom_killed"}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum Termination {
+    /// The command exited normally with the given exit code
+    Exited(i32),
+
+    /// The command was killed by the given signal
+    Signaled(i32),
+
+    /// The command was stopped because it exceeded its timeout, by the given phase of its
+    /// termination
+    Timeout(TerminationPhase),
+
+    /// The command was killed by the kernel OOM killer
+    OomKilled,
+}
+
+/// The phase of stopping a timed out command that ended it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TerminationPhase {
+    /// The command exited within its grace period after `SIGTERM`
+    Sigterm,
+
+    /// The command was still running at the end of its grace period and was killed with `SIGKILL`
+    Sigkill,
+}
+
 /// A single line of output from command execution
 #[derive(Debug, Clone)]
 pub struct CommandLine {
@@ -70,6 +113,9 @@ pub struct CommandLine {
 
     /// Line content
     pub text: String,
+
+    /// Raw bytes of the line, if they are not valid UTF-8 and `text` is their lossy decoding
+    pub raw: Option<Vec<u8>>,
 }
 
 /// Response from a command execution
@@ -85,6 +131,9 @@ pub enum CommandResp {
 
         /// Line content
         text: String,
+
+        /// Raw bytes of the line, if they are not valid UTF-8 and `text` is their lossy decoding
+        raw: Option<Vec<u8>>,
     },
 
     /// Execution completed successfully
@@ -92,8 +141,8 @@ pub enum CommandResp {
         /// Unique identifier for the execution
         id: String,
 
-        /// Exit code from the command
-        exit_code: i32,
+        /// How the command terminated
+        termination: Termination,
     },
 
     /// Execution resulted in an error
@@ -129,15 +178,56 @@ struct CommandRequest {
     id: String,
     command: String,
     args: Vec<String>,
+    envs: Vec<(String, String)>,
     resp_tx: Sender<CommandResp>,
-    done_tx: oneshot::Sender<Result<i32, CommandError>>,
+    done_tx: oneshot::Sender<Result<Termination, CommandError>>,
     timeout: Option<u64>,
+    grace_period: Duration,
 }
 
 //--------------------------------------------------------------------------------------------------
 // Methods
 //--------------------------------------------------------------------------------------------------
 
+impl Termination {
+    /// The signal the kernel OOM killer uses
+    const SIGKILL: i32 = 9;
+
+    /// Derives the termination from a process wait status
+    ///
+    /// A `SIGKILL` is attributed to the OOM killer if the kernel's OOM kill count went up while
+    /// the command was running.
+    fn from_status(status: ExitStatus, oom_kills_before: Option<u64>) -> Self {
+        if let Some(code) = status.code() {
+            return Self::Exited(code);
+        }
+
+        match status.signal() {
+            Some(Self::SIGKILL) if oom_kills_increased(oom_kills_before) => Self::OomKilled,
+            Some(signal) => Self::Signaled(signal),
+            None => Self::Exited(1),
+        }
+    }
+
+    /// Returns a shell-style exit code for the termination
+    ///
+    /// Signals map to `128 + signal`, a timeout maps to `124` (like coreutils `timeout`) and an
+    /// OOM kill maps to `137` (a `SIGKILL`).
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Exited(code) => *code,
+            Self::Signaled(signal) => 128 + signal,
+            Self::Timeout(_) => 124,
+            Self::OomKilled => 128 + Self::SIGKILL,
+        }
+    }
+
+    /// Returns whether the command exited normally with exit code 0
+    pub fn is_success(&self) -> bool {
+        *self == Self::Exited(0)
+    }
+}
+
 impl CommandHandle {
     /// Creates a new command handle
     pub fn new() -> Self {
@@ -150,14 +240,25 @@ impl CommandHandle {
                     id,
                     command,
                     args,
+                    envs,
                     resp_tx,
                     done_tx,
                     timeout,
+                    grace_period,
                 } = req;
 
                 // Execute the command in a separate task
                 tokio::spawn(async move {
-                    let result = execute_command(id, command, args, resp_tx.clone(), timeout).await;
+                    let result = execute_command(
+                        id,
+                        command,
+                        args,
+                        envs,
+                        resp_tx.clone(),
+                        timeout,
+                        grace_period,
+                    )
+                    .await;
                     let _ = done_tx.send(result);
                 });
             }
@@ -176,14 +277,82 @@ impl CommandHandle {
     ///
     /// # Returns
     ///
-    /// A tuple containing the exit code and a vector of output lines
+    /// A tuple containing how the command terminated and a vector of output lines
     pub async fn execute<S: Into<String>>(
         &self,
         command: S,
         args: Vec<String>,
         timeout: Option<u64>,
-    ) -> Result<(i32, Vec<CommandLine>), CommandError> {
+    ) -> Result<(Termination, Vec<CommandLine>), CommandError> {
+        self.execute_with_env(command, args, Vec::new(), timeout, None)
+            .await
+    }
+
+    /// Executes a command with extra environment variables and streams the output
+    ///
+    /// # Parameters
+    ///
+    /// * `command` - The command to execute
+    /// * `args` - Arguments to pass to the command
+    /// * `envs` - Environment variables set for the command, on top of the portal's own
+    /// * `timeout` - Optional timeout in seconds after which execution will be cancelled
+    /// * `grace_period` - Optional time in seconds a timed out command is given to exit after
+    ///   `SIGTERM` before it is killed, [`DEFAULT_PORTAL_KILL_GRACE_PERIOD_SECS`] if `None`
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing how the command terminated and a vector of output lines
+    pub async fn execute_with_env<S: Into<String>>(
+        &self,
+        command: S,
+        args: Vec<String>,
+        envs: Vec<(String, String)>,
+        timeout: Option<u64>,
+        grace_period: Option<u64>,
+    ) -> Result<(Termination, Vec<CommandLine>), CommandError> {
+        let (termination, lines, _) = self
+            .execute_with_limit(
+                command,
+                args,
+                envs,
+                timeout,
+                grace_period,
+                OutputLimit::new(None, None),
+            )
+            .await?;
+
+        Ok((termination, lines))
+    }
+
+    /// Executes a command with extra environment variables, buffering its output up to a limit
+    ///
+    /// # Parameters
+    ///
+    /// * `command` - The command to execute
+    /// * `args` - Arguments to pass to the command
+    /// * `envs` - Environment variables set for the command, on top of the portal's own
+    /// * `timeout` - Optional timeout in seconds after which execution will be cancelled
+    /// * `grace_period` - Optional time in seconds a timed out command is given to exit after
+    ///   `SIGTERM` before it is killed, [`DEFAULT_PORTAL_KILL_GRACE_PERIOD_SECS`] if `None`
+    /// * `limit` - How much output is buffered, and what happens to the rest. Pausing the
+    ///   command needs a timeout to stop it; without one, the rest of the output is dropped
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing how the command terminated, a vector of output lines and the number of
+    /// bytes of output dropped
+    pub async fn execute_with_limit<S: Into<String>>(
+        &self,
+        command: S,
+        args: Vec<String>,
+        envs: Vec<(String, String)>,
+        timeout: Option<u64>,
+        grace_period: Option<u64>,
+        limit: OutputLimit,
+    ) -> Result<(Termination, Vec<CommandLine>, u64), CommandError> {
         let command = command.into();
+        let grace_period =
+            Duration::from_secs(grace_period.unwrap_or(DEFAULT_PORTAL_KILL_GRACE_PERIOD_SECS));
 
         // Generate a unique execution ID
         let execution_id = Uuid::new_v4().to_string();
@@ -191,7 +360,7 @@ impl CommandHandle {
         // Channels for communication
         let (resp_tx, mut resp_rx) = mpsc::channel::<CommandResp>(100);
         let (line_tx, mut line_rx) = mpsc::channel::<CommandLine>(100);
-        let (done_tx, done_rx) = oneshot::channel::<Result<i32, CommandError>>();
+        let (done_tx, done_rx) = oneshot::channel::<Result<Termination, CommandError>>();
 
         // Send the command execution request
         self.cmd_sender
@@ -199,16 +368,18 @@ impl CommandHandle {
                 id: execution_id,
                 command,
                 args,
+                envs,
                 resp_tx,
                 done_tx,
                 timeout,
+                grace_period,
             })
             .await
             .map_err(|_| CommandError::Unavailable("Command executor not available".to_string()))?;
 
         // Process responses in a separate task
         let process_handle = tokio::spawn(async move {
-            let mut exit_code = 0;
+            let mut termination = Termination::Exited(0);
 
             while let Some(resp) = resp_rx.recv().await {
                 match resp {
@@ -216,14 +387,15 @@ impl CommandHandle {
                         id: _,
                         stream,
                         text,
+                        raw,
                     } => {
-                        let _ = line_tx.send(CommandLine { stream, text }).await;
+                        let _ = line_tx.send(CommandLine { stream, text, raw }).await;
                     }
                     CommandResp::Done {
                         id: _,
-                        exit_code: code,
+                        termination: done,
                     } => {
-                        exit_code = code;
+                        termination = done;
                         break;
                     }
                     CommandResp::Error { id: _, message } => {
@@ -231,6 +403,7 @@ impl CommandHandle {
                             .send(CommandLine {
                                 stream: Stream::Stderr,
                                 text: format!("Error: {}", message),
+                                raw: None,
                             })
                             .await;
                         break;
@@ -238,24 +411,41 @@ impl CommandHandle {
                 }
             }
 
-            exit_code
+            termination
         });
 
-        // Collect all output lines
-        let mut lines = Vec::new();
+        // Collect the output lines up to the limit
+        let deadline = timeout.map(|secs| Instant::now() + Duration::from_secs(secs));
+        let mut output = OutputBuffer::new(limit.max_bytes);
+        let mut paused = false;
         while let Some(line) = line_rx.recv().await {
-            lines.push(line);
+            let len = line_len(&line.text, line.raw.as_deref());
+            if !output.fits(len) && limit.overflow == OutputOverflow::Pause && !paused {
+                // Stop reading output, so the command blocks on its next write, until the
+                // timeout stops it
+                if let Some(deadline) = deadline {
+                    sleep_until(deadline).await;
+                }
+                paused = true;
+            }
+            output.push(line, len);
         }
 
         // Wait for processing to complete
-        let _exit_code = process_handle.await.unwrap_or(1);
+        let _termination = process_handle.await.unwrap_or(Termination::Exited(1));
 
         // Wait for execution completion
         let result = done_rx
             .await
             .map_err(|_| CommandError::ExecutionError("Command execution failed".to_string()))??;
 
-        Ok((result, lines))
+        let (lines, dropped_bytes) = output.finish(|text| CommandLine {
+            stream: Stream::Stderr,
+            text,
+            raw: None,
+        });
+
+        Ok((result, lines, dropped_bytes))
     }
 }
 
@@ -273,15 +463,23 @@ async fn execute_command(
     id: String,
     command: String,
     args: Vec<String>,
+    envs: Vec<(String, String)>,
     resp_tx: Sender<CommandResp>,
     timeout: Option<u64>,
-) -> Result<i32, CommandError> {
+    grace_period: Duration,
+) -> Result<Termination, CommandError> {
+    // Snapshot the OOM kill count so a SIGKILL can be attributed to the OOM killer
+    let oom_kills_before = read_oom_kill_count();
+
     // Spawn the command process
     let mut process = Command::new(&command)
         .args(&args)
+        .envs(package_envs())
+        .envs(envs)
         .stdin(std::process::Stdio::null())
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
+        .as_run_as_user()
         .spawn()
         .map_err(|e| CommandError::SpawnError(format!("Failed to spawn command: {}", e)))?;
 
@@ -306,15 +504,16 @@ async fn execute_command(
     let stdout_processing = Arc::clone(&processing);
 
     let stdout_handle = tokio::spawn(async move {
-        let mut lines = stdout_reader.lines();
+        let mut lines = OutputLines::new(stdout_reader);
 
-        while let Ok(Some(line)) = lines.next_line().await {
+        while let Ok(Some((line, raw))) = lines.next_line().await {
             if *stdout_processing.lock().unwrap() {
                 let _ = stdout_resp_tx
                     .send(CommandResp::Line {
                         id: stdout_id.clone(),
                         stream: Stream::Stdout,
                         text: line,
+                        raw,
                     })
                     .await;
             } else {
@@ -330,15 +529,16 @@ async fn execute_command(
     let stderr_processing = Arc::clone(&processing);
 
     let stderr_handle = tokio::spawn(async move {
-        let mut lines = stderr_reader.lines();
+        let mut lines = OutputLines::new(stderr_reader);
 
-        while let Ok(Some(line)) = lines.next_line().await {
+        while let Ok(Some((line, raw))) = lines.next_line().await {
             if *stderr_processing.lock().unwrap() {
                 let _ = stderr_resp_tx
                     .send(CommandResp::Line {
                         id: stderr_id.clone(),
                         stream: Stream::Stderr,
                         text: line,
+                        raw,
                     })
                     .await;
             } else {
@@ -349,30 +549,10 @@ async fn execute_command(
 
     // Set a timeout for the command execution if specified
     let process_wait = async {
-        match process.wait().await {
-            Ok(status) => {
-                let exit_code = status.code().unwrap_or(1);
-                let _ = resp_tx
-                    .send(CommandResp::Done {
-                        id: id.clone(),
-                        exit_code,
-                    })
-                    .await;
-                Ok(exit_code)
-            }
-            Err(e) => {
-                let _ = resp_tx
-                    .send(CommandResp::Error {
-                        id: id.clone(),
-                        message: format!("Command execution failed: {}", e),
-                    })
-                    .await;
-                Err(CommandError::ExecutionError(format!(
-                    "Failed to wait for command: {}",
-                    e
-                )))
-            }
-        }
+        process
+            .wait()
+            .await
+            .map(|status| Termination::from_status(status, oom_kills_before))
     };
 
     // Execute with timeout only if specified
@@ -382,15 +562,9 @@ async fn execute_command(
             tokio::select! {
                 result = process_wait => result,
                 _ = sleep(timeout_duration) => {
-                    // Kill the process on timeout
-                    let _ = process.kill().await;
-                    let _ = resp_tx
-                        .send(CommandResp::Error {
-                            id: id.clone(),
-                            message: format!("Command timed out after {} seconds", timeout_secs),
-                        })
-                        .await;
-                    Err(CommandError::Timeout(timeout_secs))
+                    // Stop the process on timeout, letting it clean up first
+                    let phase = terminate(&mut process, grace_period).await;
+                    Ok(Termination::Timeout(phase))
                 }
             }
         }
@@ -400,15 +574,91 @@ async fn execute_command(
         }
     };
 
-    // Signal to output handlers to stop
-    {
+    // A timed out command may have left children behind that hold its output open, so stop
+    // reading at their next line
+    if let Ok(Termination::Timeout(_)) = result {
         let mut guard = processing.lock().unwrap();
         *guard = false;
     }
 
-    // Wait for output handlers to complete
+    // Wait for output handlers to complete, so every line is sent before the command is done
     let _ = stdout_handle.await;
     let _ = stderr_handle.await;
 
-    result
+    match result {
+        Ok(termination) => {
+            if let Termination::Timeout(_) = termination {
+                let _ = resp_tx
+                    .send(CommandResp::Line {
+                        id: id.clone(),
+                        stream: Stream::Stderr,
+                        text: format!(
+                            "Command timed out after {} seconds",
+                            timeout.unwrap_or_default()
+                        ),
+                        raw: None,
+                    })
+                    .await;
+            }
+
+            let _ = resp_tx
+                .send(CommandResp::Done {
+                    id: id.clone(),
+                    termination,
+                })
+                .await;
+            Ok(termination)
+        }
+        Err(e) => {
+            let _ = resp_tx
+                .send(CommandResp::Error {
+                    id: id.clone(),
+                    message: format!("Command execution failed: {}", e),
+                })
+                .await;
+            Err(CommandError::ExecutionError(format!(
+                "Failed to wait for command: {}",
+                e
+            )))
+        }
+    }
+}
+
+/// Stops a process by sending it `SIGTERM`, then killing it with `SIGKILL` if it is still
+/// running after `grace_period`
+///
+/// Returns the phase that ended the process.
+async fn terminate(process: &mut Child, grace_period: Duration) -> TerminationPhase {
+    if let Some(pid) = process.id() {
+        // SAFETY: `pid` is the id of a child that has not been reaped yet, so it cannot have been
+        // reused by another process
+        if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } == 0 {
+            let exited = tokio::time::timeout(grace_period, process.wait()).await;
+            if exited.is_ok() {
+                return TerminationPhase::Sigterm;
+            }
+        }
+    }
+
+    let _ = process.kill().await;
+    TerminationPhase::Sigkill
+}
+
+/// Reads the number of processes killed by the OOM killer since boot
+///
+/// Returns `None` if the kernel does not expose the counter.
+fn read_oom_kill_count() -> Option<u64> {
+    let vmstat = std::fs::read_to_string("/proc/vmstat").ok()?;
+    vmstat
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))
+        .and_then(|count| count.trim().parse().ok())
+}
+
+/// Checks whether the OOM killer has killed any process since `before` was read
+fn oom_kills_increased(before: Option<u64>) -> bool {
+    match (before, read_oom_kill_count()) {
+        (Some(before), Some(after)) => after > before,
+        _ => false,
+    }
 }
@@ -0,0 +1,145 @@
+//! Compression of large execution outputs in portal responses.
+//!
+//! Executions that print a lot of text produce large JSON responses. A client can opt in to
+//! compression by setting `compression` on a `sandbox.repl.run` or `sandbox.command.run`
+//! request. When the serialized `output` lines of the result are larger than
+//! [`DEFAULT_PORTAL_COMPRESSION_THRESHOLD`], they are compressed and moved to an
+//! `output_compressed` field:
+//!
+//! ```json
+//! {
+//!   "output_compressed": {
+//!     "encoding": "gzip",
+//!     "data": "H4sIAAAAAAAA/..."
+//!   }
+//! }
+//! ```
+//!
+//! `data` is the base64 encoded, compressed JSON array that would otherwise have been sent as
+//! `output`. Smaller outputs are left as they are, since compressing them saves little.
+
+use std::io::Write;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use flate2::{write::GzEncoder, Compression as GzLevel};
+use microsandbox_utils::DEFAULT_PORTAL_COMPRESSION_THRESHOLD;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::error::PortalError;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Result field holding the compressed output lines
+pub const COMPRESSED_OUTPUT_FIELD: &str = "output_compressed";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Encodings the portal can compress execution output with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Gzip (RFC 1952)
+    Gzip,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Compresses the `output` lines of an execution result if they exceed the threshold
+///
+/// Results without an `output` field, or with one smaller than
+/// [`DEFAULT_PORTAL_COMPRESSION_THRESHOLD`] once serialized, are left untouched.
+pub fn compress_output(result: &mut Value, compression: Compression) -> Result<(), PortalError> {
+    let Some(fields) = result.as_object_mut() else {
+        return Ok(());
+    };
+    let Some(output) = fields.get("output") else {
+        return Ok(());
+    };
+
+    let serialized = serde_json::to_vec(output)
+        .map_err(|e| PortalError::Internal(format!("Failed to serialize output: {}", e)))?;
+    if serialized.len() <= DEFAULT_PORTAL_COMPRESSION_THRESHOLD {
+        return Ok(());
+    }
+
+    let compressed = match compression {
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), GzLevel::default());
+            encoder
+                .write_all(&serialized)
+                .and_then(|_| encoder.finish())
+                .map_err(|e| PortalError::Internal(format!("Failed to compress output: {}", e)))?
+        }
+    };
+
+    fields.remove("output");
+    fields.insert(
+        COMPRESSED_OUTPUT_FIELD.to_string(),
+        json!({
+            "encoding": compression,
+            "data": BASE64.encode(compressed),
+        }),
+    );
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    /// Output lines whose serialized JSON is larger than the compression threshold
+    fn large_output() -> Value {
+        let lines: Vec<Value> = (0..DEFAULT_PORTAL_COMPRESSION_THRESHOLD / 8)
+            .map(|i| json!({ "stream": "stdout", "text": i.to_string() }))
+            .collect();
+        Value::Array(lines)
+    }
+
+    #[test]
+    fn test_compress_output_round_trip() {
+        let output = large_output();
+        let mut result = json!({ "output": output, "exit_code": 0 });
+        compress_output(&mut result, Compression::Gzip).unwrap();
+
+        assert!(result.get("output").is_none());
+        assert_eq!(result["exit_code"], 0);
+        let compressed = &result[COMPRESSED_OUTPUT_FIELD];
+        assert_eq!(compressed["encoding"], "gzip");
+
+        let data = BASE64.decode(compressed["data"].as_str().unwrap()).unwrap();
+        assert!(data.len() < serde_json::to_vec(&output).unwrap().len());
+        let mut json = Vec::new();
+        GzDecoder::new(data.as_slice())
+            .read_to_end(&mut json)
+            .unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&json).unwrap(), output);
+    }
+
+    #[test]
+    fn test_compress_output_leaves_small_results() {
+        let small = json!({ "output": [{ "stream": "stdout", "text": "hello" }] });
+        let mut result = small.clone();
+        compress_output(&mut result, Compression::Gzip).unwrap();
+        assert_eq!(result, small);
+
+        let mut result = json!({ "exit_code": 1 });
+        compress_output(&mut result, Compression::Gzip).unwrap();
+        assert_eq!(result, json!({ "exit_code": 1 }));
+    }
+}
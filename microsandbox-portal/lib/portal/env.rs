@@ -0,0 +1,151 @@
+//! Persistent environment variables of the session in the microsandbox portal.
+//!
+//! The `sandbox.env.set` and `sandbox.env.unset` methods update environment variables that the
+//! portal keeps for the rest of the session. Every later execution inherits them:
+//! - Commands and package installs get them on top of the portal's own environment.
+//! - REPL code gets them through a preamble that updates the interpreter's environment before
+//!   the code runs, which also removes the variables unset since.
+//!
+//! Only variables set through `sandbox.env.set` can be unset, so the variables the portal itself
+//! was started with stay in place. Names starting with `MICROSANDBOX_` are reserved for the
+//! portal and rejected.
+//!
+//! The variables of an execution, such as the ones that expose its seed, take precedence over
+//! the ones of the session.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::error::PortalError;
+
+#[cfg(any(feature = "python", feature = "nodejs"))]
+use super::repl::Language;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Prefix of the environment variable names reserved for the portal
+pub const RESERVED_ENV_PREFIX: &str = "MICROSANDBOX_";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The environment variables set for the session
+#[derive(Debug, Clone, Default)]
+pub struct SessionEnv {
+    /// The variables set, by name
+    vars: BTreeMap<String, String>,
+
+    /// The variables unset since they were set, which REPL interpreters may still have
+    removed: BTreeSet<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl SessionEnv {
+    /// Sets a variable, replacing its value if it is already set
+    pub fn set(&mut self, name: String, value: String) {
+        self.removed.remove(&name);
+        self.vars.insert(name, value);
+    }
+
+    /// Unsets a variable, returning whether it was set
+    pub fn unset(&mut self, name: &str) -> bool {
+        let was_set = self.vars.remove(name).is_some();
+        if was_set {
+            self.removed.insert(name.to_string());
+        }
+
+        was_set
+    }
+
+    /// Returns the names of the variables set
+    pub fn names(&self) -> Vec<String> {
+        self.vars.keys().cloned().collect()
+    }
+
+    /// Returns the variables set, as name-value pairs to pass to a command
+    pub fn envs(&self) -> Vec<(String, String)> {
+        self.vars
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Returns whether no variables are set or left to remove from REPL interpreters
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty() && self.removed.is_empty()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Checks that a name can be set as an environment variable and is not reserved for the portal
+pub fn validate_env_name(name: &str) -> Result<(), PortalError> {
+    if name.is_empty() || name.contains(['=', '\0']) {
+        return Err(PortalError::JsonRpc(format!(
+            "Invalid environment variable name: '{}'",
+            name
+        )));
+    }
+
+    if name
+        .get(..RESERVED_ENV_PREFIX.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(RESERVED_ENV_PREFIX))
+    {
+        return Err(PortalError::JsonRpc(format!(
+            "Environment variable '{}' is reserved: names starting with {} cannot be changed",
+            name, RESERVED_ENV_PREFIX
+        )));
+    }
+
+    Ok(())
+}
+
+/// Checks that a value can be set as an environment variable
+pub fn validate_env_value(name: &str, value: &str) -> Result<(), PortalError> {
+    if value.contains('\0') {
+        return Err(PortalError::JsonRpc(format!(
+            "Value of environment variable '{}' contains a NUL byte",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+/// Prepends the statements that apply the session's environment to code evaluated in a REPL
+///
+/// Like the seed preamble, it only uses single-line statements that evaluate to nothing.
+#[cfg(any(feature = "python", feature = "nodejs"))]
+pub fn env_code(language: Language, env: &SessionEnv, code: &str) -> String {
+    if env.is_empty() {
+        return code.to_string();
+    }
+
+    // JSON objects and arrays of strings are literals in both languages
+    let vars = serde_json::to_string(&env.vars).expect("string maps always serialize");
+    let removed = serde_json::to_string(&env.removed).expect("string sets always serialize");
+
+    let preamble = match language {
+        #[cfg(feature = "python")]
+        Language::Python => format!(
+            "import os as _msb_os\n\
+             _msb_os.environ.update({vars})\n\
+             [_msb_os.environ.pop(_msb_name, None) for _msb_name in {removed}].clear()\n\
+             del _msb_os\n",
+        ),
+        #[cfg(feature = "nodejs")]
+        Language::Node => format!(
+            "void Object.assign(process.env, {vars});\n\
+             void {removed}.forEach((name) => delete process.env[name]);\n",
+        ),
+    };
+
+    format!("{}{}", preamble, code)
+}
@@ -0,0 +1,286 @@
+//! Pluggable executors that run REPL code for each language.
+//!
+//! An [`Executor`] turns the code of a `sandbox.repl.run` request into code for an interpreter,
+//! runs it and can cancel it. Executors are registered by language name in an
+//! [`ExecutorRegistry`]: the handler picks the executor by the request's `language`, and the
+//! request's `runtime` picks the interpreter processes the executor runs the code on.
+//!
+//! Supporting another language, e.g. Deno, Ruby or Go, means implementing [`Executor`] and
+//! registering it in [`ExecutorRegistry::new`], without changes to the handler.
+//!
+//! # Feature Flags
+//!
+//! The Python and Node.js executors are registered when the `python` and `nodejs` features are
+//! enabled. Without either, the registry is empty.
+
+use async_trait::async_trait;
+use std::{collections::HashMap, fmt, path::Path, sync::Arc};
+
+use super::{
+    env::SessionEnv,
+    repl::{EngineError, EngineHandle, Line},
+};
+
+#[cfg(any(feature = "python", feature = "nodejs"))]
+use super::{
+    env::env_code,
+    preamble::preamble_code,
+    repl::Language,
+    script::script_code,
+    seed::seed_code,
+    value::{take_value, value_code},
+};
+#[cfg(any(feature = "python", feature = "nodejs"))]
+use std::sync::Mutex;
+#[cfg(any(feature = "python", feature = "nodejs"))]
+use tokio::sync::oneshot;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Code prepared by an [`Executor`] for execution
+#[derive(Debug, Clone)]
+pub struct Execution {
+    /// Unique identifier for the execution
+    pub id: String,
+
+    /// The code sent to the interpreter
+    pub code: String,
+
+    /// The marker the value of the final expression is printed after, if it is captured
+    pub value_marker: Option<String>,
+}
+
+/// The outcome of an [`Executor::execute`] call
+#[derive(Debug, Clone)]
+pub struct ExecutionOutput {
+    /// Output lines of the execution
+    pub lines: Vec<Line>,
+
+    /// The repr of the value of the final expression, if it was captured and the code has one
+    pub value: Option<String>,
+
+    /// The number of bytes of output dropped past the output limit
+    pub dropped_bytes: u64,
+}
+
+/// The executors available to REPL requests, by language name
+#[derive(Clone, Default)]
+pub struct ExecutorRegistry {
+    executors: HashMap<String, Arc<dyn Executor>>,
+}
+
+/// Executes code on the REPL engines of a runtime in one language
+#[cfg(any(feature = "python", feature = "nodejs"))]
+pub struct ReplExecutor {
+    /// The language the code is evaluated in
+    language: Language,
+
+    /// Cancellation senders of the executions in progress, by execution id
+    running: Mutex<HashMap<String, oneshot::Sender<()>>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Traits
+//--------------------------------------------------------------------------------------------------
+
+/// Prepares, executes and cancels REPL code in one language
+#[async_trait]
+pub trait Executor: Send + Sync {
+    /// Prepares code for execution
+    ///
+    /// Applies the session's environment, the preamble and the seed, if any, and with `eval` set
+    /// wraps the code so that the value of its final expression is captured.
+    fn prepare(
+        &self,
+        code: &str,
+        eval: bool,
+        seed: Option<u32>,
+        preamble: Option<&str>,
+        env: &SessionEnv,
+    ) -> Execution;
+
+    /// Returns code that runs a script file already in the sandbox, to prepare in place of the
+    /// code of a request
+    fn exec_file_code(&self, path: &Path) -> String;
+
+    /// Executes prepared code on the REPL engines of a runtime
+    ///
+    /// # Parameters
+    ///
+    /// * `engines` - The engines of the runtime to execute the code on
+    /// * `execution` - The code, prepared by [`Executor::prepare`]
+    /// * `timeout` - Optional timeout in seconds after which execution will be cancelled
+    /// * `max_output_bytes` - The maximum number of bytes of output buffered, past which the
+    ///   output is dropped
+    async fn execute(
+        &self,
+        engines: &EngineHandle,
+        execution: &Execution,
+        timeout: Option<u64>,
+        max_output_bytes: usize,
+    ) -> Result<ExecutionOutput, EngineError>;
+
+    /// Cancels an execution in progress, which then fails with [`EngineError::Cancelled`]
+    ///
+    /// Returns whether an execution with the id was in progress.
+    async fn cancel(&self, execution_id: &str) -> bool;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ExecutorRegistry {
+    /// Creates a registry with the executors of the languages enabled by feature flags
+    pub fn new() -> Self {
+        #[allow(unused_mut)]
+        let mut registry = Self::default();
+
+        #[cfg(feature = "python")]
+        registry.register(&["python"], Arc::new(ReplExecutor::new(Language::Python)));
+
+        #[cfg(feature = "nodejs")]
+        registry.register(
+            &["node", "nodejs", "javascript"],
+            Arc::new(ReplExecutor::new(Language::Node)),
+        );
+
+        registry
+    }
+
+    /// Registers an executor under one or more language names, replacing any executor
+    /// registered under the same names
+    pub fn register(&mut self, names: &[&str], executor: Arc<dyn Executor>) {
+        for name in names {
+            self.executors
+                .insert(name.to_lowercase(), Arc::clone(&executor));
+        }
+    }
+
+    /// Returns the executor registered for a language name, ignoring case
+    pub fn get(&self, language: &str) -> Option<Arc<dyn Executor>> {
+        self.executors.get(&language.to_lowercase()).cloned()
+    }
+}
+
+#[cfg(any(feature = "python", feature = "nodejs"))]
+impl ReplExecutor {
+    /// Creates an executor that evaluates code in the given language
+    pub fn new(language: Language) -> Self {
+        Self {
+            language,
+            running: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(any(feature = "python", feature = "nodejs"))]
+#[async_trait]
+impl Executor for ReplExecutor {
+    fn prepare(
+        &self,
+        code: &str,
+        eval: bool,
+        seed: Option<u32>,
+        preamble: Option<&str>,
+        env: &SessionEnv,
+    ) -> Execution {
+        let id = uuid::Uuid::new_v4().to_string();
+
+        // Capture the final expression in eval mode, behind a marker unique to this execution
+        let value_marker = eval.then(|| format!("__msb_value_{}__", id));
+        let code = match &value_marker {
+            Some(marker) => value_code(self.language, marker, code),
+            None => code.to_string(),
+        };
+
+        // Apply the seed, if any, before the code runs
+        let code = match seed {
+            Some(seed) => seed_code(self.language, seed, &code),
+            None => code,
+        };
+
+        // Run the preamble before the seed, so that it is applied to generators it imports
+        let code = match preamble {
+            Some(preamble) => preamble_code(self.language, preamble, &code),
+            None => code,
+        };
+
+        // Apply the session's environment first, so that the seed's variables take precedence
+        // and the preamble sees the session's variables
+        let code = env_code(self.language, env, &code);
+
+        Execution {
+            id,
+            code,
+            value_marker,
+        }
+    }
+
+    fn exec_file_code(&self, path: &Path) -> String {
+        script_code(self.language, path)
+    }
+
+    async fn execute(
+        &self,
+        engines: &EngineHandle,
+        execution: &Execution,
+        timeout: Option<u64>,
+        max_output_bytes: usize,
+    ) -> Result<ExecutionOutput, EngineError> {
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        self.running
+            .lock()
+            .unwrap()
+            .insert(execution.id.clone(), cancel_tx);
+
+        // A cancelled execution stops waiting for its output. Like a timed out one, the
+        // interpreter still finishes the code before it runs the next execution of the runtime
+        let result = tokio::select! {
+            result = engines.eval_with_limit(
+                execution.code.clone(),
+                self.language,
+                execution.id.clone(),
+                timeout,
+                max_output_bytes,
+            ) => result,
+            Ok(()) = cancel_rx => Err(EngineError::Cancelled),
+        };
+        self.running.lock().unwrap().remove(&execution.id);
+
+        let (mut lines, dropped_bytes) = result?;
+        let value = execution
+            .value_marker
+            .as_deref()
+            .and_then(|marker| take_value(&mut lines, marker));
+
+        Ok(ExecutionOutput {
+            lines,
+            value,
+            dropped_bytes,
+        })
+    }
+
+    async fn cancel(&self, execution_id: &str) -> bool {
+        match self.running.lock().unwrap().remove(execution_id) {
+            Some(cancel_tx) => cancel_tx.send(()).is_ok(),
+            None => false,
+        }
+    }
+}
+
+impl fmt::Debug for ExecutorRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut languages: Vec<&String> = self.executors.keys().collect();
+        languages.sort();
+        f.debug_struct("ExecutorRegistry")
+            .field("languages", &languages)
+            .finish()
+    }
+}
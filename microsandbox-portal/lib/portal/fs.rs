@@ -1 +1,276 @@
-//! TODO: Implement file system operations for the microsandbox portal.
+//! File system operations for the microsandbox portal.
+//!
+//! Directories are listed with `sandbox.file.list`, for clients that browse the files executions
+//! leave behind. Listings are sorted by name and returned a page at a time, so that a directory
+//! with many thousands of entries does not produce one huge response.
+//!
+//! Executions can also name output files to return with their result, which saves clients a
+//! separate download for the common "run, then fetch the result file" pattern. Each file is
+//! returned base64 encoded, or with an error of its own if it cannot be returned. They can
+//! likewise name a script file to run instead of sending code.
+//!
+//! Paths must be absolute and free of `..` components, and are resolved before they are read.
+//! When the portal executes code as an unprivileged user, only directories that user could list
+//! itself are listed, which keeps clients out of places like the root-only upload cache.
+
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use microsandbox_utils::{
+    DEFAULT_PORTAL_DIR_PAGE_SIZE, DEFAULT_PORTAL_MAX_OUTPUT_FILES,
+    DEFAULT_PORTAL_OUTPUT_FILE_MAX_SIZE,
+};
+use serde::{Deserialize, Serialize};
+
+use super::user::run_as_user;
+use crate::error::PortalError;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An entry of a directory in the sandbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirEntryInfo {
+    /// Name of the entry within its directory
+    pub name: String,
+
+    /// Whether the entry is a directory. Symlinks are not followed, so a link to a directory
+    /// is not one
+    pub is_dir: bool,
+
+    /// Size of the entry, in bytes
+    pub size: u64,
+
+    /// Last modification time, in seconds since the Unix epoch
+    pub mtime: Option<i64>,
+}
+
+/// One page of the entries of a directory in the sandbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirListing {
+    /// Resolved path of the directory
+    pub path: String,
+
+    /// Number of entries in the directory
+    pub total: usize,
+
+    /// Index of the first entry of the page
+    pub offset: usize,
+
+    /// Entries of the page, sorted by name
+    pub entries: Vec<DirEntryInfo>,
+
+    /// Offset of the next page, if there are more entries
+    pub next_offset: Option<usize>,
+}
+
+/// A file returned with the result of an execution
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputFile {
+    /// Path of the file, as requested
+    pub path: String,
+
+    /// Size of the file, in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+
+    /// Base64 encoded contents of the file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+
+    /// Why the file could not be returned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Checks that an execution does not ask for more output files than the portal returns
+pub fn validate_output_files(paths: &[String]) -> Result<(), PortalError> {
+    if paths.len() > DEFAULT_PORTAL_MAX_OUTPUT_FILES {
+        return Err(PortalError::JsonRpc(format!(
+            "Too many output files: {} requested, at most {} allowed",
+            paths.len(),
+            DEFAULT_PORTAL_MAX_OUTPUT_FILES
+        )));
+    }
+
+    Ok(())
+}
+
+/// Reads the output files of an execution
+///
+/// A file that is missing, unreadable or larger than the portal's output file size limit gets
+/// an entry with an error instead of failing the others.
+pub fn read_output_files(paths: &[String]) -> Vec<OutputFile> {
+    paths
+        .iter()
+        .map(|path| match read_output_file(path) {
+            Ok(contents) => OutputFile {
+                path: path.clone(),
+                size: Some(contents.len() as u64),
+                data: Some(BASE64.encode(&contents)),
+                error: None,
+            },
+            Err(e) => OutputFile {
+                path: path.clone(),
+                size: None,
+                data: None,
+                error: Some(match e {
+                    PortalError::JsonRpc(message) | PortalError::Internal(message) => message,
+                    e => e.to_string(),
+                }),
+            },
+        })
+        .collect()
+}
+
+/// Resolves a script file for an execution to run, if it is a regular file that the executed
+/// code could read itself
+pub fn resolve_exec_file(path: &str) -> Result<PathBuf, PortalError> {
+    if Path::new(path).is_absolute() && !Path::new(path).exists() {
+        return Err(PortalError::JsonRpc(format!("File not found: {}", path)));
+    }
+
+    let file = resolve_path(path)?;
+
+    let metadata = fs::metadata(&file)
+        .map_err(|e| PortalError::JsonRpc(format!("Failed to stat {}: {}", path, e)))?;
+    if !metadata.is_file() {
+        return Err(PortalError::JsonRpc(format!(
+            "Not a regular file: {}",
+            path
+        )));
+    }
+
+    if run_as_user().is_some_and(|user| !user.can_read(&metadata)) {
+        return Err(PortalError::JsonRpc(format!("Permission denied: {}", path)));
+    }
+
+    Ok(file)
+}
+
+/// Lists one page of the entries of a directory
+///
+/// Returns at most `limit` entries starting at `offset`, capped at the portal's page size.
+pub fn list_dir(
+    path: &str,
+    offset: usize,
+    limit: Option<usize>,
+) -> Result<DirListing, PortalError> {
+    let dir = resolve_path(path)?;
+
+    let metadata = fs::metadata(&dir)
+        .map_err(|e| PortalError::JsonRpc(format!("Failed to stat {}: {}", path, e)))?;
+    if !metadata.is_dir() {
+        return Err(PortalError::JsonRpc(format!("Not a directory: {}", path)));
+    }
+
+    // Only list directories that the executed code could list itself
+    if run_as_user().is_some_and(|user| !user.can_read(&metadata)) {
+        return Err(PortalError::JsonRpc(format!("Permission denied: {}", path)));
+    }
+
+    let mut names = fs::read_dir(&dir)
+        .map_err(|e| PortalError::Internal(format!("Failed to list {}: {}", path, e)))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    names.sort();
+
+    let total = names.len();
+    let limit = limit
+        .unwrap_or(DEFAULT_PORTAL_DIR_PAGE_SIZE)
+        .clamp(1, DEFAULT_PORTAL_DIR_PAGE_SIZE);
+    let end = offset.saturating_add(limit).min(total);
+
+    // Entries removed since the directory was read are left out of the page
+    let entries = names
+        .get(offset..end)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|name| {
+            let metadata = fs::symlink_metadata(dir.join(name)).ok()?;
+            let mtime = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_secs() as i64);
+
+            Some(DirEntryInfo {
+                name: name.clone(),
+                is_dir: metadata.is_dir(),
+                size: metadata.len(),
+                mtime,
+            })
+        })
+        .collect();
+
+    Ok(DirListing {
+        path: dir.to_string_lossy().into_owned(),
+        total,
+        offset,
+        entries,
+        next_offset: (end < total).then_some(end),
+    })
+}
+
+/// Resolves an absolute path without `..` components to the path it refers to
+fn resolve_path(path: &str) -> Result<PathBuf, PortalError> {
+    let requested = Path::new(path);
+    if !requested.is_absolute() {
+        return Err(PortalError::JsonRpc(format!(
+            "Path must be absolute: {}",
+            path
+        )));
+    }
+
+    if requested
+        .components()
+        .any(|component| component == Component::ParentDir)
+    {
+        return Err(PortalError::JsonRpc(format!(
+            "Path must not contain '..': {}",
+            path
+        )));
+    }
+
+    requested
+        .canonicalize()
+        .map_err(|e| PortalError::JsonRpc(format!("Failed to resolve {}: {}", path, e)))
+}
+
+/// Reads one output file, if it is a regular file within the size limit that the executed code
+/// could read itself
+fn read_output_file(path: &str) -> Result<Vec<u8>, PortalError> {
+    let file = resolve_path(path)?;
+
+    let metadata = fs::metadata(&file)
+        .map_err(|e| PortalError::JsonRpc(format!("Failed to stat {}: {}", path, e)))?;
+    if !metadata.is_file() {
+        return Err(PortalError::JsonRpc(format!(
+            "Not a regular file: {}",
+            path
+        )));
+    }
+
+    if run_as_user().is_some_and(|user| !user.can_read(&metadata)) {
+        return Err(PortalError::JsonRpc(format!("Permission denied: {}", path)));
+    }
+
+    if metadata.len() > DEFAULT_PORTAL_OUTPUT_FILE_MAX_SIZE {
+        return Err(PortalError::JsonRpc(format!(
+            "File is larger than {} bytes, download it with sandbox.file.read: {}",
+            DEFAULT_PORTAL_OUTPUT_FILE_MAX_SIZE, path
+        )));
+    }
+
+    fs::read(&file).map_err(|e| PortalError::Internal(format!("Failed to read {}: {}", path, e)))
+}
@@ -10,7 +10,20 @@
 //!
 //! - `repl`: Provides multi-language REPL engines for interactive code execution
 //! - `command`: Handles sandboxed execution of system commands
+//! - `compression`: Compresses large execution outputs in responses
+//! - `env`: Keeps the environment variables set for the session
+//! - `executor`: Prepares, executes and cancels REPL code with an executor per language
 //! - `fs`: Manages secure file system operations
+//! - `output`: Reads execution output that is not valid UTF-8 and encodes it in results
+//! - `packages`: Installs pip and npm packages for executed code to use
+//! - `preamble`: Runs the startup preamble of REPL executions before their code
+//! - `runtime`: Reports the interpreter versions, packages and OS of the sandbox
+//! - `script`: Runs script files already in the sandbox instead of code sent with a request
+//! - `seed`: Applies best-effort deterministic seeds to executions
+//! - `stats`: Samples the live memory, CPU and disk usage of the sandbox
+//! - `upload`: Caches uploaded files by content
+//! - `user`: Drops executions to an unprivileged user
+//! - `value`: Captures the value of the final expression of REPL code
 //!
 //! # Architecture
 //!
@@ -56,7 +69,8 @@
 //!     let cmd_handle = create_command_executor();
 //!
 //!     // Execute a system command
-//!     let (exit_code, output) = cmd_handle.execute("ls", vec!["-la"]).await?;
+//!     let (termination, output) = cmd_handle.execute("ls", vec!["-la".to_string()], None).await?;
+//!     println!("Exit code: {}", termination.exit_code());
 //!
 //!     // Process the output
 //!     for line in output {
@@ -74,5 +88,22 @@
 //--------------------------------------------------------------------------------------------------
 
 pub mod command;
+pub mod compression;
+pub mod env;
+pub mod executor;
 pub mod fs;
+pub mod output;
+pub mod packages;
 pub mod repl;
+pub mod runtime;
+pub mod seed;
+pub mod stats;
+pub mod upload;
+pub mod user;
+
+#[cfg(any(feature = "python", feature = "nodejs"))]
+pub mod preamble;
+#[cfg(any(feature = "python", feature = "nodejs"))]
+pub mod script;
+#[cfg(any(feature = "python", feature = "nodejs"))]
+pub mod value;
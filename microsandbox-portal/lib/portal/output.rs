@@ -0,0 +1,241 @@
+//! Reading and encoding of execution output that is not valid UTF-8.
+//!
+//! Programs can print anything to stdout and stderr, e.g. binary data or logs in Latin-1.
+//! Output is read line by line as raw bytes, so such output does not end the stream. A line that
+//! is not valid UTF-8 is decoded lossily by default, replacing the invalid bytes with `U+FFFD`.
+//!
+//! A client that needs the exact bytes sets `binary_output` on a `sandbox.repl.run` or
+//! `sandbox.command.run` request. Every output line then has an `encoding` field, and lines that
+//! are not valid UTF-8 are returned base64 encoded instead:
+//!
+//! ```json
+//! [
+//!   { "stream": "stdout", "text": "header", "encoding": "utf8" },
+//!   { "stream": "stdout", "text": "iVBORw0KGgo=", "encoding": "base64" }
+//! ]
+//! ```
+//!
+//! Lines never include their line ending, as with text output.
+//!
+//! # Output Limits
+//!
+//! The output of an execution is buffered in the portal until the execution finishes, up to
+//! `max_output_bytes` (8 MiB by default), so a chatty program cannot exhaust the portal's memory.
+//! What happens past the limit is set by `output_overflow`:
+//!
+//! - `drop` (the default) drops the rest of the output. The result ends with a stderr marker line
+//!   and reports the number of bytes dropped in `dropped_bytes`.
+//! - `pause` stops reading the output of a `sandbox.command.run` command, which blocks the command
+//!   on its next write until its timeout stops it. Output written after that is dropped.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use microsandbox_utils::DEFAULT_PORTAL_MAX_OUTPUT_BYTES;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use super::repl::Stream;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// How the text of an output line is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputEncoding {
+    /// The line as it was printed
+    Utf8,
+
+    /// The base64 encoded bytes of a line that is not valid UTF-8
+    Base64,
+}
+
+/// What happens to the output of an execution past its output limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputOverflow {
+    /// The rest of the output is dropped and counted
+    #[default]
+    Drop,
+
+    /// The command is paused until its timeout stops it, and output written after that is dropped
+    Pause,
+}
+
+/// How much output of an execution is buffered, and what happens to the rest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputLimit {
+    /// The maximum number of bytes of output buffered
+    pub max_bytes: usize,
+
+    /// What happens to output past the limit
+    pub overflow: OutputOverflow,
+}
+
+/// Buffers the output lines of an execution up to a number of bytes
+#[derive(Debug)]
+pub struct OutputBuffer<T> {
+    /// The maximum number of bytes buffered
+    max_bytes: usize,
+
+    /// The buffered lines
+    lines: Vec<T>,
+
+    /// The number of bytes of the buffered lines
+    buffered_bytes: usize,
+
+    /// The number of bytes of the lines dropped
+    dropped_bytes: u64,
+}
+
+/// Reads the lines of a stream as raw bytes
+#[derive(Debug)]
+pub struct OutputLines<R> {
+    /// Stream the lines are read from
+    reader: R,
+
+    /// Bytes of the line being read
+    buf: Vec<u8>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl OutputLimit {
+    /// Creates a limit from the optional `max_output_bytes` and `output_overflow` of a request
+    pub fn new(max_bytes: Option<usize>, overflow: Option<OutputOverflow>) -> Self {
+        Self {
+            max_bytes: max_bytes.unwrap_or(DEFAULT_PORTAL_MAX_OUTPUT_BYTES),
+            overflow: overflow.unwrap_or_default(),
+        }
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> OutputLines<R> {
+    /// Creates a reader of the lines of a stream
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Reads the next line without its line ending, or `None` at the end of the stream
+    ///
+    /// Returns the line decoded as UTF-8, lossily if it is not valid UTF-8, and the raw bytes
+    /// of such a line.
+    pub async fn next_line(&mut self) -> std::io::Result<Option<(String, Option<Vec<u8>>)>> {
+        self.buf.clear();
+        if self.reader.read_until(b'\n', &mut self.buf).await? == 0 {
+            return Ok(None);
+        }
+
+        if self.buf.ends_with(b"\n") {
+            self.buf.pop();
+            if self.buf.ends_with(b"\r") {
+                self.buf.pop();
+            }
+        }
+
+        Ok(Some(decode_line(std::mem::take(&mut self.buf))))
+    }
+}
+
+impl<T> OutputBuffer<T> {
+    /// Creates a buffer that holds up to `max_bytes` of output
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            lines: Vec::new(),
+            buffered_bytes: 0,
+            dropped_bytes: 0,
+        }
+    }
+
+    /// Returns whether a line of `len` bytes fits in the buffer
+    ///
+    /// Once a line has been dropped nothing fits anymore, so the buffered output never has gaps.
+    pub fn fits(&self, len: usize) -> bool {
+        self.dropped_bytes == 0 && self.buffered_bytes + len <= self.max_bytes
+    }
+
+    /// Buffers a line of `len` bytes, or drops it if it does not fit
+    pub fn push(&mut self, line: T, len: usize) {
+        if self.fits(len) {
+            self.buffered_bytes += len;
+            self.lines.push(line);
+        } else {
+            self.dropped_bytes += len as u64;
+        }
+    }
+
+    /// Returns the buffered lines and the number of bytes dropped
+    ///
+    /// If output was dropped, the lines end with a marker line made by `marker` from its text.
+    pub fn finish(mut self, marker: impl FnOnce(String) -> T) -> (Vec<T>, u64) {
+        if self.dropped_bytes > 0 {
+            let text = format!(
+                "[{} bytes of output dropped past the output limit]",
+                self.dropped_bytes
+            );
+            self.lines.push(marker(text));
+        }
+
+        (self.lines, self.dropped_bytes)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the number of bytes of an output line, from its raw bytes if it has them
+pub fn line_len(text: &str, raw: Option<&[u8]>) -> usize {
+    raw.map_or(text.len(), <[u8]>::len)
+}
+
+/// Decodes a line as UTF-8, returning its raw bytes too if it is not valid UTF-8
+pub fn decode_line(bytes: Vec<u8>) -> (String, Option<Vec<u8>>) {
+    match String::from_utf8(bytes) {
+        Ok(text) => (text, None),
+        Err(e) => {
+            let bytes = e.into_bytes();
+            (String::from_utf8_lossy(&bytes).into_owned(), Some(bytes))
+        }
+    }
+}
+
+/// Formats an output line for a result
+///
+/// With `binary_output`, the line gets an `encoding` field, and a line that is not valid UTF-8
+/// is returned as its base64 encoded `raw` bytes instead of its lossy `text`.
+pub fn output_line_json(
+    stream: Stream,
+    text: &str,
+    raw: Option<&[u8]>,
+    binary_output: bool,
+) -> Value {
+    let stream = match stream {
+        Stream::Stdout => "stdout",
+        Stream::Stderr => "stderr",
+    };
+
+    if !binary_output {
+        return json!({ "stream": stream, "text": text });
+    }
+
+    match raw {
+        Some(raw) => json!({
+            "stream": stream,
+            "text": BASE64.encode(raw),
+            "encoding": OutputEncoding::Base64,
+        }),
+        None => json!({
+            "stream": stream,
+            "text": text,
+            "encoding": OutputEncoding::Utf8,
+        }),
+    }
+}
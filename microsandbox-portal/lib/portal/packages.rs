@@ -0,0 +1,244 @@
+//! Installing pip and npm packages into the sandbox from the microsandbox portal.
+//!
+//! Packages are installed with `sandbox.packages.install`, typically once when a session starts,
+//! so that the code executed afterwards can import them. They are installed as the run-as user
+//! into directories the portal owns rather than into the system site-packages:
+//!
+//! ```text
+//! /opt/msb/packages/
+//! ├── python/              # pip install --target, on PYTHONPATH
+//! └── node/node_modules/   # npm install --prefix, on NODE_PATH
+//! ```
+//!
+//! The directories are created when the portal starts and put on the search paths of the REPL
+//! engines and commands it spawns, so packages installed later are found without restarting
+//! anything.
+//!
+//! If the package cache directory exists, which is the case when the server mounted a volume
+//! there, pip and npm keep their download caches in it so that later sandboxes mounting the same
+//! volume do not download the packages again.
+
+use std::{
+    fs,
+    os::unix::fs::{chown, MetadataExt},
+    path::{Path, PathBuf},
+};
+
+use microsandbox_utils::DEFAULT_PACKAGE_CACHE_GUEST_PATH;
+
+use super::user::run_as_user;
+use crate::error::PortalError;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Directory that packages are installed under
+pub const PACKAGES_DIR: &str = "/opt/msb/packages";
+
+/// Environment variable that adds directories to Python's module search path
+pub const PYTHONPATH_ENV_VAR: &str = "PYTHONPATH";
+
+/// Environment variable that adds directories to Node.js's module search path
+pub const NODE_PATH_ENV_VAR: &str = "NODE_PATH";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Package manager used to install packages for a language
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageManager {
+    /// pip, for Python packages
+    Pip,
+
+    /// npm, for Node.js packages
+    Npm,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl PackageManager {
+    /// All package managers
+    pub const ALL: [PackageManager; 2] = [PackageManager::Pip, PackageManager::Npm];
+
+    /// Returns the package manager for a language, as named in REPL requests
+    pub fn for_language(language: &str) -> Result<Self, PortalError> {
+        match language.to_lowercase().as_str() {
+            "python" => Ok(PackageManager::Pip),
+            "node" | "nodejs" | "javascript" => Ok(PackageManager::Npm),
+            _ => Err(PortalError::JsonRpc(format!(
+                "Unsupported language for package installs: {}",
+                language
+            ))),
+        }
+    }
+
+    /// Returns the directory packages are installed into
+    pub fn install_dir(self) -> PathBuf {
+        match self {
+            PackageManager::Pip => Path::new(PACKAGES_DIR).join("python"),
+            PackageManager::Npm => Path::new(PACKAGES_DIR).join("node"),
+        }
+    }
+
+    /// Returns the directory the installed packages are searched in
+    pub fn search_dir(self) -> PathBuf {
+        match self {
+            PackageManager::Pip => self.install_dir(),
+            PackageManager::Npm => self.install_dir().join("node_modules"),
+        }
+    }
+
+    /// Returns the download cache directory, if the package cache is mounted and usable
+    ///
+    /// The cache is only used once [`init_package_dirs`] has handed it to the run-as user, as
+    /// pip and npm would otherwise fail to write to it.
+    pub fn cache_dir(self) -> Option<PathBuf> {
+        let dir = self.cache_path()?;
+        let metadata = fs::metadata(&dir).ok()?;
+        let usable =
+            metadata.is_dir() && run_as_user().is_none_or(|user| metadata.uid() == user.uid);
+
+        usable.then_some(dir)
+    }
+
+    /// Returns where the download cache lives, if the package cache is mounted
+    fn cache_path(self) -> Option<PathBuf> {
+        let cache_root = Path::new(DEFAULT_PACKAGE_CACHE_GUEST_PATH);
+        cache_root.is_dir().then(|| match self {
+            PackageManager::Pip => cache_root.join("pip"),
+            PackageManager::Npm => cache_root.join("npm"),
+        })
+    }
+
+    /// Returns the program and arguments that install the given packages
+    pub fn install_command(self, packages: &[String]) -> (String, Vec<String>) {
+        let install_dir = self.install_dir().display().to_string();
+        let cache_dir = self.cache_dir().map(|dir| dir.display().to_string());
+
+        let (program, mut args) = match self {
+            PackageManager::Pip => {
+                let mut args = [
+                    "-m",
+                    "pip",
+                    "install",
+                    "--disable-pip-version-check",
+                    "--no-input",
+                    "--upgrade",
+                    "--target",
+                ]
+                .map(String::from)
+                .to_vec();
+                args.push(install_dir);
+                if let Some(cache_dir) = cache_dir {
+                    args.extend(["--cache-dir".to_string(), cache_dir]);
+                }
+                ("python3", args)
+            }
+            PackageManager::Npm => {
+                let mut args = [
+                    "install",
+                    "--no-save",
+                    "--no-audit",
+                    "--no-fund",
+                    "--prefix",
+                ]
+                .map(String::from)
+                .to_vec();
+                args.push(install_dir);
+                if let Some(cache_dir) = cache_dir {
+                    args.extend(["--cache".to_string(), cache_dir]);
+                }
+                ("npm", args)
+            }
+        };
+
+        // Packages come after `--` so that none of them can be taken for an option
+        args.push("--".to_string());
+        args.extend(packages.iter().cloned());
+
+        (program.to_string(), args)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Creates the package directories and hands them to the run-as user
+///
+/// Must be called before any engine is started, so that the search paths exist when the
+/// interpreters read them. A download cache that cannot be set up is left unused.
+pub fn init_package_dirs() -> Result<(), PortalError> {
+    for manager in PackageManager::ALL {
+        for dir in [manager.install_dir(), manager.search_dir()] {
+            init_dir(&dir)?;
+        }
+
+        if let Some(dir) = manager.cache_path() {
+            if let Err(e) = init_dir(&dir) {
+                tracing::warn!("Not caching package downloads: {}", e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the environment variables that put installed packages on the search paths
+pub fn package_envs() -> Vec<(String, String)> {
+    [
+        (PYTHONPATH_ENV_VAR, PackageManager::Pip),
+        (NODE_PATH_ENV_VAR, PackageManager::Npm),
+    ]
+    .into_iter()
+    .map(|(name, manager)| {
+        let dir = manager.search_dir().display().to_string();
+        let value = match std::env::var(name) {
+            Ok(existing) if !existing.is_empty() => format!("{}:{}", dir, existing),
+            _ => dir,
+        };
+        (name.to_string(), value)
+    })
+    .collect()
+}
+
+/// Checks that package specs are non-empty and cannot be mistaken for options
+pub fn validate_packages(packages: &[String]) -> Result<(), PortalError> {
+    if packages.is_empty() {
+        return Err(PortalError::JsonRpc(
+            "At least one package must be given".to_string(),
+        ));
+    }
+
+    for package in packages {
+        if package.is_empty()
+            || package.starts_with('-')
+            || package.chars().any(|c| c.is_whitespace() || c.is_control())
+        {
+            return Err(PortalError::JsonRpc(format!(
+                "Invalid package spec: '{}'",
+                package
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates a directory owned by the run-as user
+fn init_dir(dir: &Path) -> Result<(), PortalError> {
+    fs::create_dir_all(dir)
+        .map_err(|e| PortalError::Internal(format!("Failed to create {}: {}", dir.display(), e)))?;
+
+    if let Some(user) = run_as_user() {
+        chown(dir, Some(user.uid), Some(user.gid)).map_err(|e| {
+            PortalError::Internal(format!("Failed to chown {}: {}", dir.display(), e))
+        })?;
+    }
+
+    Ok(())
+}
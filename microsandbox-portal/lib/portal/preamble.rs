@@ -0,0 +1,60 @@
+//! Startup preambles run before the code of REPL executions.
+//!
+//! A `sandbox.repl.run` request can carry a preamble, e.g. `import numpy as np`, that the portal
+//! runs before the request's code, so clients don't have to repeat the same setup at the top of
+//! every snippet. The server passes the preamble configured for the session's template, or the
+//! one the session was created with.
+//!
+//! The preamble is run as a single statement of its own, compiled under the `<preamble>` file
+//! name:
+//! - Python runs it with `exec` in the REPL's globals.
+//! - Node.js runs it as a script with `vm.runInThisContext`.
+//!
+//! Both REPLs number the lines of each statement they are sent from 1, so the preamble does not
+//! shift the line numbers reported for the code, and errors raised by the preamble itself point
+//! at `<preamble>`. Like the code, the preamble only writes to the output what it prints itself.
+//!
+//! The preamble runs before every execution, so it has to be safe to run repeatedly. Imports
+//! are, but JavaScript `const` and `let` declarations are not, as they cannot be declared again
+//! in the same context; assign to `globalThis` or use `var` instead.
+
+use super::{repl::Language, value::literal};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// File name errors raised by the preamble are reported with
+pub const PREAMBLE_FILE_NAME: &str = "<preamble>";
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Prepends the statement that runs a preamble to code evaluated in a REPL
+///
+/// The statement is a single line that evaluates to nothing, so it neither depends on how the
+/// REPL terminates blocks nor adds anything to the output. Blank preambles are skipped.
+pub fn preamble_code(language: Language, preamble: &str, code: &str) -> String {
+    if preamble.trim().is_empty() {
+        return code.to_string();
+    }
+
+    let preamble = literal(preamble);
+    let file_name = literal(PREAMBLE_FILE_NAME);
+
+    let statement = match language {
+        #[cfg(feature = "python")]
+        Language::Python => format!(
+            "exec(compile({}, {}, \"exec\"), globals())\n",
+            preamble, file_name
+        ),
+        #[cfg(feature = "nodejs")]
+        Language::Node => format!(
+            "void require('vm').runInThisContext({}, {{ filename: {} }});\n",
+            preamble, file_name
+        ),
+    };
+
+    format!("{}{}", statement, code)
+}
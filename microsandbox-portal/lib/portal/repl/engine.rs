@@ -47,6 +47,7 @@
 //! }
 //! ```
 
+use microsandbox_utils::DEFAULT_PORTAL_MAX_OUTPUT_BYTES;
 use tokio::sync::mpsc;
 
 #[cfg(feature = "nodejs")]
@@ -55,6 +56,7 @@ use super::nodejs;
 use super::python;
 
 use super::types::{Cmd, EngineError, EngineHandle, Language, Line, Resp, Stream};
+use crate::portal::output::{line_len, OutputBuffer};
 
 #[cfg(any(feature = "python", feature = "nodejs"))]
 use super::types::Engine;
@@ -108,6 +110,48 @@ impl EngineHandle {
         execution_id: S,
         timeout: Option<u64>,
     ) -> Result<Vec<Line>, EngineError> {
+        let (lines, _) = self
+            .eval_with_limit(
+                code,
+                language,
+                execution_id,
+                timeout,
+                DEFAULT_PORTAL_MAX_OUTPUT_BYTES,
+            )
+            .await?;
+
+        Ok(lines)
+    }
+
+    /// Evaluates code in the specified language, buffering its output up to a limit
+    ///
+    /// Output past `max_output_bytes` is dropped. The interpreter is shared by the executions of
+    /// its runtime, so its output is never paused.
+    ///
+    /// # Parameters
+    ///
+    /// * `code` - The code to evaluate
+    /// * `language` - The language to use for evaluation
+    /// * `execution_id` - A unique identifier for this evaluation
+    /// * `timeout` - Optional timeout in seconds after which evaluation will be cancelled
+    /// * `max_output_bytes` - The maximum number of bytes of output buffered
+    ///
+    /// # Returns
+    ///
+    /// A vector of output lines from the evaluation and the number of bytes of output dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `EngineError` if the evaluation fails or if the reactor
+    /// thread is not available.
+    pub async fn eval_with_limit<S: Into<String>>(
+        &self,
+        code: S,
+        language: Language,
+        execution_id: S,
+        timeout: Option<u64>,
+        max_output_bytes: usize,
+    ) -> Result<(Vec<Line>, u64), EngineError> {
         let code = code.into();
         let execution_id = execution_id.into();
         // Create channel for receiving results
@@ -134,8 +178,9 @@ impl EngineHandle {
                         id: _,
                         stream,
                         text,
+                        raw,
                     } => {
-                        let _ = line_tx.send(Line { stream, text }).await;
+                        let _ = line_tx.send(Line { stream, text, raw }).await;
                     }
                     Resp::Done { id: _ } => {
                         break;
@@ -145,6 +190,7 @@ impl EngineHandle {
                             .send(Line {
                                 stream: Stream::Stderr,
                                 text: format!("Error: {}", message),
+                                raw: None,
                             })
                             .await;
                         break;
@@ -153,16 +199,21 @@ impl EngineHandle {
             }
         });
 
-        // Collect all lines
-        let mut lines = Vec::new();
+        // Collect the lines up to the limit, dropping the rest
+        let mut output = OutputBuffer::new(max_output_bytes);
         while let Some(line) = line_rx.recv().await {
-            lines.push(line);
+            let len = line_len(&line.text, line.raw.as_deref());
+            output.push(line, len);
         }
 
         // Wait for processing to complete
         let _ = process_handle.await;
 
-        Ok(lines)
+        Ok(output.finish(|text| Line {
+            stream: Stream::Stderr,
+            text,
+            raw: None,
+        }))
     }
 
     /// Shuts down all engines and the reactor
@@ -13,7 +13,7 @@ use async_trait::async_trait;
 use rand::{distr::Alphanumeric, Rng};
 use std::sync::{Arc, Mutex};
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    io::{AsyncWriteExt, BufReader},
     process::Command,
     sync::{
         mpsc::{self, Sender},
@@ -23,6 +23,7 @@ use tokio::{
 };
 
 use super::types::{Engine, EngineError, Resp, Stream};
+use crate::portal::{output::OutputLines, packages::package_envs, user::RunAsUserExt};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -95,6 +96,8 @@ impl Engine for NodeEngine {
                 .stdin(std::process::Stdio::piped())
                 .stdout(std::process::Stdio::piped())
                 .stderr(std::process::Stdio::piped())
+                .envs(package_envs())
+                .as_run_as_user()
                 .spawn()
             {
                 Ok(p) => p,
@@ -139,7 +142,7 @@ impl Engine for NodeEngine {
             let stdout_exec_status = Arc::clone(&execution_status);
 
             tokio::task::spawn_blocking(move || {
-                let mut lines_future = stdout_reader.lines();
+                let mut lines_future = OutputLines::new(stdout_reader);
                 let runtime = tokio::runtime::Handle::current();
 
                 loop {
@@ -147,7 +150,7 @@ impl Engine for NodeEngine {
                     let line_result = runtime.block_on(lines_future.next_line());
 
                     match line_result {
-                        Ok(Some(line)) => {
+                        Ok(Some((line, raw))) => {
                             // Skip Node.js REPL response tags '>' and '..'
                             if !line.trim().is_empty()
                                 && !line.starts_with('>')
@@ -184,6 +187,7 @@ impl Engine for NodeEngine {
                                             id: status.id.clone(),
                                             stream: Stream::Stdout,
                                             text: line,
+                                            raw,
                                         }));
                                     }
                                 }
@@ -204,7 +208,7 @@ impl Engine for NodeEngine {
             let stderr_exec_status = Arc::clone(&execution_status);
 
             tokio::task::spawn_blocking(move || {
-                let mut lines_future = stderr_reader.lines();
+                let mut lines_future = OutputLines::new(stderr_reader);
                 let runtime = tokio::runtime::Handle::current();
 
                 loop {
@@ -212,13 +216,14 @@ impl Engine for NodeEngine {
                     let line_result = runtime.block_on(lines_future.next_line());
 
                     match line_result {
-                        Ok(Some(line)) => {
+                        Ok(Some((line, raw))) => {
                             if let Some(status) = stderr_exec_status.lock().unwrap().as_ref() {
                                 // Use block_on to send the message
                                 let _ = runtime.block_on(status.sender.send(Resp::Line {
                                     id: status.id.clone(),
                                     stream: Stream::Stderr,
                                     text: line,
+                                    raw,
                                 }));
                             }
                         }
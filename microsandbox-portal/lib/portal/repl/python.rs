@@ -13,7 +13,7 @@ use async_trait::async_trait;
 use rand::{distr::Alphanumeric, Rng};
 use std::sync::{Arc, Mutex};
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    io::{AsyncWriteExt, BufReader},
     process::Command,
     sync::{
         mpsc::{self, Sender},
@@ -23,6 +23,7 @@ use tokio::{
 };
 
 use super::types::{Engine, EngineError, Resp, Stream};
+use crate::portal::{output::OutputLines, packages::package_envs, user::RunAsUserExt};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -92,6 +93,8 @@ impl Engine for PythonEngine {
                 .stdin(std::process::Stdio::piped())
                 .stdout(std::process::Stdio::piped())
                 .stderr(std::process::Stdio::piped())
+                .envs(package_envs())
+                .as_run_as_user()
                 .spawn()
             {
                 Ok(p) => p,
@@ -136,7 +139,7 @@ impl Engine for PythonEngine {
             let stdout_exec_status = Arc::clone(&execution_status);
 
             tokio::task::spawn_blocking(move || {
-                let mut lines_future = stdout_reader.lines();
+                let mut lines_future = OutputLines::new(stdout_reader);
                 let runtime = tokio::runtime::Handle::current();
 
                 loop {
@@ -144,7 +147,7 @@ impl Engine for PythonEngine {
                     let line_result = runtime.block_on(lines_future.next_line());
 
                     match line_result {
-                        Ok(Some(line)) => {
+                        Ok(Some((line, raw))) => {
                             // Check if this is an end-of-execution marker line
                             let mut should_send = true;
 
@@ -174,6 +177,7 @@ impl Engine for PythonEngine {
                                         id: status.id.clone(),
                                         stream: Stream::Stdout,
                                         text: line,
+                                        raw,
                                     }));
                                 }
                             }
@@ -193,7 +197,7 @@ impl Engine for PythonEngine {
             let stderr_exec_status = Arc::clone(&execution_status);
 
             tokio::task::spawn_blocking(move || {
-                let mut lines_future = stderr_reader.lines();
+                let mut lines_future = OutputLines::new(stderr_reader);
                 let runtime = tokio::runtime::Handle::current();
 
                 loop {
@@ -201,13 +205,14 @@ impl Engine for PythonEngine {
                     let line_result = runtime.block_on(lines_future.next_line());
 
                     match line_result {
-                        Ok(Some(line)) => {
+                        Ok(Some((line, raw))) => {
                             if let Some(status) = stderr_exec_status.lock().unwrap().as_ref() {
                                 // Use block_on to send the message
                                 let _ = runtime.block_on(status.sender.send(Resp::Line {
                                     id: status.id.clone(),
                                     stream: Stream::Stderr,
                                     text: line,
+                                    raw,
                                 }));
                             }
                         }
@@ -27,6 +27,7 @@
 //! The design accounts for concurrent use by leveraging thread-safe primitives and
 //! message passing through channels to communicate between components.
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::sync::mpsc::Sender;
 
@@ -47,7 +48,8 @@ pub enum Language {
 }
 
 /// Stream type for output lines
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Stream {
     /// Standard output stream
     Stdout,
@@ -64,6 +66,9 @@ pub struct Line {
 
     /// Line content
     pub text: String,
+
+    /// Raw bytes of the line, if they are not valid UTF-8 and `text` is their lossy decoding
+    pub raw: Option<Vec<u8>>,
 }
 
 /// Handle for interacting with the REPL engines
@@ -94,6 +99,10 @@ pub enum EngineError {
     #[error("Evaluation timeout after {0} seconds")]
     Timeout(u64),
 
+    /// Evaluation cancelled before it completed
+    #[error("Evaluation cancelled")]
+    Cancelled,
+
     /// Engine unavailable (shutdown or crashed)
     #[error("Engine unavailable: {0}")]
     Unavailable(String),
@@ -134,6 +143,9 @@ pub enum Resp {
 
         /// Line content
         text: String,
+
+        /// Raw bytes of the line, if they are not valid UTF-8 and `text` is their lossy decoding
+        raw: Option<Vec<u8>>,
     },
 
     /// Evaluation completed successfully
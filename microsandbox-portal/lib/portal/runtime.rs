@@ -0,0 +1,199 @@
+//! Reporting the interpreters and operating system of the sandbox from the microsandbox portal.
+//!
+//! `sandbox.runtime.info` returns the Python and Node.js versions the sandbox provides, the
+//! packages installed for each, and the operating system the sandbox runs, so that clients can
+//! write code for what is actually there. An interpreter the image does not have is reported as
+//! `null`.
+//!
+//! The sandbox is probed on the first request and the result is kept for the portal's lifetime,
+//! which is the sandbox's. Installing packages through the portal clears it, so that the next
+//! request reports them.
+
+use std::{collections::BTreeMap, process::Stdio, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::{process::Command, sync::Mutex};
+
+use super::{packages::package_envs, user::RunAsUserExt};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How long an interpreter may take to report its version and packages
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Operating system identification of the sandbox
+const OS_RELEASE_PATH: &str = "/etc/os-release";
+
+/// Release of the kernel the sandbox runs
+const KERNEL_RELEASE_PATH: &str = "/proc/sys/kernel/osrelease";
+
+/// Prints the Python version and installed distributions as JSON
+const PYTHON_PROBE: &str = r#"
+import json, platform
+try:
+    from importlib import metadata
+    packages = {d.metadata["Name"]: d.version for d in metadata.distributions() if d.metadata["Name"]}
+except Exception:
+    packages = {}
+print(json.dumps({"version": platform.python_version(), "packages": packages}))
+"#;
+
+/// Prints the Node.js version and the packages in NODE_PATH and the global modules as JSON
+const NODE_PROBE: &str = r#"
+const fs = require("fs"), path = require("path");
+const list = (dir) => { try { return fs.readdirSync(dir); } catch (e) { return []; } };
+const dirs = (process.env.NODE_PATH || "").split(path.delimiter).filter(Boolean);
+dirs.push(path.join(path.dirname(path.dirname(process.execPath)), "lib", "node_modules"));
+const packages = {};
+for (const dir of dirs) {
+  for (const name of list(dir).flatMap((n) => n.startsWith("@") ? list(path.join(dir, n)).map((s) => n + "/" + s) : [n])) {
+    try {
+      const pkg = JSON.parse(fs.readFileSync(path.join(dir, name, "package.json"), "utf8"));
+      if (!(name in packages)) packages[name] = pkg.version;
+    } catch (e) {}
+  }
+}
+console.log(JSON.stringify({ version: process.versions.node, packages }));
+"#;
+
+/// What the sandbox was last probed to provide
+static RUNTIME_INFO: Mutex<Option<RuntimeInfo>> = Mutex::const_new(None);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Interpreters and operating system the sandbox provides
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeInfo {
+    /// Python, if the sandbox has `python3`
+    pub python: Option<InterpreterInfo>,
+
+    /// Node.js, if the sandbox has `node`
+    pub node: Option<InterpreterInfo>,
+
+    /// Operating system of the sandbox
+    pub os: OsInfo,
+}
+
+/// Version of an interpreter and the packages it can import
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterpreterInfo {
+    /// Interpreter version, e.g. `3.12.4` or `20.11.1`
+    pub version: String,
+
+    /// Installed packages by name, with their versions
+    pub packages: BTreeMap<String, String>,
+}
+
+/// Operating system of the sandbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OsInfo {
+    /// Human-readable name of the distribution, e.g. `Debian GNU/Linux 12 (bookworm)`
+    pub name: Option<String>,
+
+    /// Identifier of the distribution, e.g. `debian`
+    pub id: Option<String>,
+
+    /// Version of the distribution, e.g. `12`
+    pub version: Option<String>,
+
+    /// Release of the kernel the sandbox runs
+    pub kernel: Option<String>,
+
+    /// CPU architecture, e.g. `x86_64` or `aarch64`
+    pub arch: String,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns what the sandbox provides, probing it if it was not probed yet
+pub async fn runtime_info() -> RuntimeInfo {
+    let mut cached = RUNTIME_INFO.lock().await;
+    if let Some(info) = cached.as_ref() {
+        return info.clone();
+    }
+
+    let (python, node) = tokio::join!(
+        probe_interpreter("python3", &["-c", PYTHON_PROBE]),
+        probe_interpreter("node", &["-e", NODE_PROBE]),
+    );
+    let info = RuntimeInfo {
+        python,
+        node,
+        os: os_info().await,
+    };
+
+    *cached = Some(info.clone());
+    info
+}
+
+/// Forgets what the sandbox was probed to provide, e.g. because packages were installed
+pub async fn invalidate_runtime_info() {
+    RUNTIME_INFO.lock().await.take();
+}
+
+/// Runs a probe script with an interpreter as executed code would, returning `None` if the
+/// interpreter is missing or the probe fails
+async fn probe_interpreter(program: &str, args: &[&str]) -> Option<InterpreterInfo> {
+    let output = Command::new(program)
+        .args(args)
+        .envs(package_envs())
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .as_run_as_user()
+        .output();
+
+    let output = match tokio::time::timeout(PROBE_TIMEOUT, output).await {
+        Ok(Ok(output)) if output.status.success() => output,
+        Ok(Ok(output)) => {
+            tracing::warn!(
+                "{} runtime probe failed: {}",
+                program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return None;
+        }
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Ok(Err(e)) => {
+            tracing::warn!("Failed to run {} runtime probe: {}", program, e);
+            return None;
+        }
+        Err(_) => {
+            tracing::warn!("{} runtime probe timed out", program);
+            return None;
+        }
+    };
+
+    serde_json::from_slice(&output.stdout)
+        .inspect_err(|e| tracing::warn!("Invalid {} runtime probe output: {}", program, e))
+        .ok()
+}
+
+/// Reads the operating system of the sandbox
+async fn os_info() -> OsInfo {
+    let os_release = tokio::fs::read_to_string(OS_RELEASE_PATH)
+        .await
+        .unwrap_or_default();
+    let field = |key: &str| {
+        os_release.lines().find_map(|line| {
+            let value = line.strip_prefix(key)?.strip_prefix('=')?;
+            Some(value.trim().trim_matches('"').to_string())
+        })
+    };
+
+    OsInfo {
+        name: field("PRETTY_NAME"),
+        id: field("ID"),
+        version: field("VERSION_ID"),
+        kernel: tokio::fs::read_to_string(KERNEL_RELEASE_PATH)
+            .await
+            .ok()
+            .map(|release| release.trim().to_string()),
+        arch: std::env::consts::ARCH.to_string(),
+    }
+}
@@ -0,0 +1,44 @@
+//! Running script files already in the sandbox as REPL executions.
+//!
+//! A `sandbox.repl.run` request can name a guest file in `exec_file` instead of sending `code`,
+//! e.g. the entrypoint of a multi-file project uploaded with `sandbox.file.write`. The portal
+//! checks that the file exists and is readable, and then runs it in the runtime's interpreter
+//! the way the interpreter would run it from the command line:
+//! - Python runs it with `runpy.run_path` as `__main__`, after adding its directory to
+//!   `sys.path` so that it can import the modules next to it.
+//! - Node.js loads it with `require`, which resolves the modules it requires from its
+//!   directory. It is dropped from the module cache first, so that running it again runs it
+//!   again.
+//!
+//! The script runs in a namespace of its own rather than the REPL's globals, but modules it
+//! imports stay loaded in the runtime like any others. The session's environment, the preamble
+//! and the seed apply as they do to code.
+
+use std::path::Path;
+
+use super::{repl::Language, value::literal};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns REPL code that runs a script file
+///
+/// Like the other preambles, it only uses single-line statements that evaluate to nothing.
+pub fn script_code(language: Language, path: &Path) -> String {
+    let path = literal(&path.to_string_lossy());
+
+    match language {
+        #[cfg(feature = "python")]
+        Language::Python => format!(
+            "import os as _msb_os, sys as _msb_sys\n\
+             [_msb_sys.path.insert(0, _msb_dir) for _msb_dir in [_msb_os.path.dirname({path})] if _msb_dir not in _msb_sys.path].clear()\n\
+             del _msb_os, _msb_sys\n\
+             [__import__(\"runpy\").run_path({path}, run_name=\"__main__\")].clear()\n",
+        ),
+        #[cfg(feature = "nodejs")]
+        Language::Node => format!(
+            "void ((file) => {{ delete require.cache[file]; require(file); }})({path});\n",
+        ),
+    }
+}
@@ -0,0 +1,66 @@
+//! Deterministic seeding for executions in the microsandbox portal.
+//!
+//! An execution can carry a seed to make its randomness reproducible. The portal exposes the
+//! seed to the executed code through environment variables and, for Python, also seeds the
+//! standard `random` module and `numpy` (if it is installed) before the code runs.
+//!
+//! Seeding is best-effort:
+//! - `PYTHONHASHSEED` only affects Python interpreters started after it is set, such as a
+//!   `python3` command or a subprocess spawned from the REPL. The REPL's own interpreter is
+//!   already running, so its string hashing stays randomized.
+//! - Node.js has no way to seed `Math.random`, so JavaScript code only receives `RANDOM_SEED`
+//!   and has to use it with a seedable generator itself.
+//! - Other sources of nondeterminism, such as threads, wall-clock time or other libraries'
+//!   generators, are not controlled.
+
+#[cfg(any(feature = "python", feature = "nodejs"))]
+use super::repl::Language;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Environment variable that fixes Python's string hashing for new interpreters
+pub const PYTHONHASHSEED_ENV_VAR: &str = "PYTHONHASHSEED";
+
+/// Environment variable that carries the seed for code to use with its own generators
+pub const RANDOM_SEED_ENV_VAR: &str = "RANDOM_SEED";
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the environment variables that expose a seed to a command
+pub fn seed_envs(seed: u32) -> Vec<(String, String)> {
+    [PYTHONHASHSEED_ENV_VAR, RANDOM_SEED_ENV_VAR]
+        .into_iter()
+        .map(|name| (name.to_string(), seed.to_string()))
+        .collect()
+}
+
+/// Prepends the statements that apply a seed to code evaluated in a REPL
+///
+/// The preamble only uses single-line statements that evaluate to nothing, so it neither
+/// depends on how the REPL terminates blocks nor adds anything to the output.
+#[cfg(any(feature = "python", feature = "nodejs"))]
+pub fn seed_code(language: Language, seed: u32, code: &str) -> String {
+    let preamble = match language {
+        #[cfg(feature = "python")]
+        Language::Python => format!(
+            "import os as _msb_os, random as _msb_random, importlib.util as _msb_util\n\
+             _msb_os.environ.update({hash}=\"{seed}\", {random}=\"{seed}\"); _msb_random.seed({seed})\n\
+             _msb_util.find_spec(\"numpy\") and __import__(\"numpy\").random.seed({seed})\n\
+             del _msb_os, _msb_random, _msb_util\n",
+            hash = PYTHONHASHSEED_ENV_VAR,
+            random = RANDOM_SEED_ENV_VAR,
+        ),
+        #[cfg(feature = "nodejs")]
+        Language::Node => format!(
+            "void Object.assign(process.env, {{ {hash}: \"{seed}\", {random}: \"{seed}\" }});\n",
+            hash = PYTHONHASHSEED_ENV_VAR,
+            random = RANDOM_SEED_ENV_VAR,
+        ),
+    };
+
+    format!("{}{}", preamble, code)
+}
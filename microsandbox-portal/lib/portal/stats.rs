@@ -0,0 +1,223 @@
+//! Sampling the live resource usage of the sandbox from the microsandbox portal.
+//!
+//! `sandbox.stats` returns the memory, CPU and disk usage of the sandbox at the time of the
+//! request, so that clients can watch a long run and see it approaching its memory limit before
+//! it is killed. Every figure is read from a single kernel file or call, so sampling is cheap
+//! enough to poll every second:
+//! - Memory comes from the sandbox's cgroup when the portal runs in one with a memory
+//!   controller, and from `/proc/meminfo` otherwise, which in a microVM is the whole sandbox.
+//! - CPU usage is the share of time all CPUs were busy since the previous request, from
+//!   `/proc/stat`. The first request, and one made too soon or too long after the previous one,
+//!   samples over a short window instead.
+//! - Disk usage is that of the filesystem mounted at `/`.
+//!
+//! A figure that cannot be read is reported as `null`.
+
+use std::{
+    ffi::CString,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Memory used by the portal's cgroup, in bytes
+const CGROUP_MEMORY_CURRENT_PATH: &str = "/sys/fs/cgroup/memory.current";
+
+/// Memory limit of the portal's cgroup, in bytes, or `max`
+const CGROUP_MEMORY_MAX_PATH: &str = "/sys/fs/cgroup/memory.max";
+
+/// Memory of the whole sandbox
+const MEMINFO_PATH: &str = "/proc/meminfo";
+
+/// Time the CPUs of the sandbox spent in each state since boot
+const PROC_STAT_PATH: &str = "/proc/stat";
+
+/// Filesystem whose usage is reported
+const DISK_PATH: &str = "/";
+
+/// How long CPU usage is sampled for when there is no usable earlier sample
+const CPU_SAMPLE_WINDOW: Duration = Duration::from_millis(100);
+
+/// How old the previous CPU sample may be to average CPU usage since
+const MAX_CPU_SAMPLE_AGE: Duration = Duration::from_secs(10);
+
+/// The CPU times read by the previous request
+static LAST_CPU_SAMPLE: Mutex<Option<CpuSample>> = Mutex::const_new(None);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Resource usage of the sandbox at the time of a request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxStats {
+    /// Memory in use, in bytes
+    pub memory_used_bytes: Option<u64>,
+
+    /// Memory available to the sandbox, in bytes
+    pub memory_limit_bytes: Option<u64>,
+
+    /// Share of the time all CPUs were busy, from 0 to 100
+    pub cpu_percent: Option<f64>,
+
+    /// Space used on the root filesystem, in bytes
+    pub disk_used_bytes: Option<u64>,
+
+    /// Size of the root filesystem, in bytes
+    pub disk_total_bytes: Option<u64>,
+}
+
+/// Cumulative CPU times of all CPUs, in clock ticks
+#[derive(Debug, Clone, Copy)]
+struct CpuSample {
+    /// Ticks spent doing anything but idling or waiting for I/O
+    busy: u64,
+
+    /// Ticks spent in any state
+    total: u64,
+
+    /// When the times were read
+    taken_at: Instant,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Samples the current resource usage of the sandbox
+pub async fn sandbox_stats() -> SandboxStats {
+    let ((memory_used_bytes, memory_limit_bytes), cpu_percent) =
+        tokio::join!(memory_usage(), cpu_percent());
+    let disk = disk_usage();
+
+    SandboxStats {
+        memory_used_bytes,
+        memory_limit_bytes,
+        cpu_percent,
+        disk_used_bytes: disk.map(|(used, _)| used),
+        disk_total_bytes: disk.map(|(_, total)| total),
+    }
+}
+
+/// Reads the memory in use and the memory limit, preferring the cgroup's figures
+async fn memory_usage() -> (Option<u64>, Option<u64>) {
+    let meminfo = tokio::fs::read_to_string(MEMINFO_PATH).await.ok();
+    let field = |key: &str| meminfo.as_deref().and_then(|m| meminfo_bytes(m, key));
+    let total = field("MemTotal");
+
+    let cgroup_current = read_number(CGROUP_MEMORY_CURRENT_PATH).await;
+    if let Some(current) = cgroup_current {
+        // An unlimited cgroup is bounded by the memory of the sandbox
+        let limit = read_number(CGROUP_MEMORY_MAX_PATH).await.or(total);
+        return (Some(current), limit);
+    }
+
+    let used = total
+        .zip(field("MemAvailable"))
+        .map(|(total, available)| total.saturating_sub(available));
+    (used, total)
+}
+
+/// Returns a field of `/proc/meminfo` in bytes
+fn meminfo_bytes(meminfo: &str, key: &str) -> Option<u64> {
+    meminfo.lines().find_map(|line| {
+        let value = line.strip_prefix(key)?.strip_prefix(':')?;
+        let kib = value
+            .trim()
+            .strip_suffix("kB")?
+            .trim()
+            .parse::<u64>()
+            .ok()?;
+        Some(kib * 1024)
+    })
+}
+
+/// Reads a file holding a single number, returning `None` if it is missing or holds anything
+/// else, such as `max`
+async fn read_number(path: impl AsRef<Path>) -> Option<u64> {
+    tokio::fs::read_to_string(path)
+        .await
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Works out the share of time all CPUs were busy since the previous request, or over
+/// [`CPU_SAMPLE_WINDOW`] if the previous sample is missing, too recent or too old
+async fn cpu_percent() -> Option<f64> {
+    // Held across the sampling window, so that concurrent requests do not replace each other's
+    // samples
+    let mut last = LAST_CPU_SAMPLE.lock().await;
+
+    let mut current = read_cpu_sample().await?;
+    let previous = match last.take() {
+        Some(previous)
+            if (CPU_SAMPLE_WINDOW..=MAX_CPU_SAMPLE_AGE).contains(&previous.taken_at.elapsed()) =>
+        {
+            previous
+        }
+        _ => {
+            let start = current;
+            tokio::time::sleep(CPU_SAMPLE_WINDOW).await;
+            current = read_cpu_sample().await?;
+            start
+        }
+    };
+    *last = Some(current);
+
+    let total = current.total.saturating_sub(previous.total);
+    let busy = current.busy.saturating_sub(previous.busy);
+    if total == 0 {
+        return Some(0.0);
+    }
+
+    Some((busy as f64 / total as f64 * 100.0).min(100.0))
+}
+
+/// Reads the cumulative CPU times of all CPUs from the first line of `/proc/stat`
+async fn read_cpu_sample() -> Option<CpuSample> {
+    let stat = tokio::fs::read_to_string(PROC_STAT_PATH).await.ok()?;
+    let times = stat
+        .lines()
+        .next()?
+        .strip_prefix("cpu ")?
+        .split_whitespace()
+        .map(|ticks| ticks.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+
+    // user nice system idle iowait irq softirq steal, then guest times already counted in user
+    let total: u64 = times.iter().take(8).sum();
+    let idle = times.get(3).copied().unwrap_or(0) + times.get(4).copied().unwrap_or(0);
+
+    Some(CpuSample {
+        busy: total.saturating_sub(idle),
+        total,
+        taken_at: Instant::now(),
+    })
+}
+
+/// Reads the used and total space of the root filesystem, in bytes
+fn disk_usage() -> Option<(u64, u64)> {
+    let path = CString::new(DISK_PATH).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        tracing::warn!(
+            "Failed to read usage of {}: {}",
+            DISK_PATH,
+            std::io::Error::last_os_error()
+        );
+        return None;
+    }
+
+    let block_size = stat.f_frsize as u64;
+    let total = stat.f_blocks as u64 * block_size;
+    let free = stat.f_bfree as u64 * block_size;
+    Some((total.saturating_sub(free), total))
+}
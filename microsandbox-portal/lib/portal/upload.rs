@@ -0,0 +1,306 @@
+//! Content-addressed caching of files uploaded to the sandbox through the microsandbox portal.
+//!
+//! Files are uploaded with `sandbox.file.write`, chunk by chunk. An upload that carries the
+//! sha256 of the file is kept in the upload cache once its last chunk is written, which the
+//! server mounts from a directory shared by all sandboxes of a namespace:
+//!
+//! ```text
+//! /var/cache/msb-uploads/
+//! └── sha256/
+//!     └── <digest>        # one file per distinct content
+//! ```
+//!
+//! Before sending any data, a client can ask with only the checksum whether the file is cached.
+//! On a hit the portal copies the cached file to the requested path, so the same dataset pushed
+//! to many sandboxes is only sent and stored once.
+//!
+//! Cached files are copied rather than hard-linked. The written file belongs to the run-as user
+//! and may be changed by the executed code, which must not change what other sandboxes get. The
+//! cache itself is only accessible to root, so executed code cannot plant content under a
+//! checksum it does not match.
+
+use std::{
+    fs, io,
+    os::unix::fs::{chown, MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use microsandbox_utils::DEFAULT_UPLOAD_CACHE_GUEST_PATH;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::user::run_as_user;
+use crate::error::PortalError;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Directory of the upload cache that files are stored in by their sha256
+const SHA256_SUBDIR: &str = "sha256";
+
+/// Uploads that were served from the cache since the portal started
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+
+/// Checksum lookups that missed the cache since the portal started
+static CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Statistics of the upload cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadCacheStats {
+    /// Whether the server mounted an upload cache into the sandbox
+    pub available: bool,
+
+    /// Number of distinct files in the cache
+    pub files: u64,
+
+    /// Total size of the files in the cache, in bytes
+    pub bytes: u64,
+
+    /// Uploads served from the cache since the sandbox started
+    pub hits: u64,
+
+    /// Checksum lookups that missed the cache since the sandbox started
+    pub misses: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Hands the upload cache to root alone, if the server mounted one
+///
+/// Must be called before any code is executed, so that it never gets to write to the cache.
+pub fn init_upload_cache() -> Result<(), PortalError> {
+    let cache_root = Path::new(DEFAULT_UPLOAD_CACHE_GUEST_PATH);
+    if !cache_root.is_dir() {
+        return Ok(());
+    }
+
+    chown(cache_root, Some(0), Some(0))
+        .and_then(|_| fs::set_permissions(cache_root, fs::Permissions::from_mode(0o700)))
+        .and_then(|_| fs::create_dir_all(cache_root.join(SHA256_SUBDIR)))
+        .map_err(|e| {
+            PortalError::Internal(format!(
+                "Failed to set up upload cache {}: {}",
+                DEFAULT_UPLOAD_CACHE_GUEST_PATH, e
+            ))
+        })
+}
+
+/// Checks that a checksum is a sha256 hex digest and returns it in lower case
+pub fn validate_sha256(sha256: &str) -> Result<String, PortalError> {
+    if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(PortalError::JsonRpc(format!(
+            "Invalid sha256 checksum: '{}'",
+            sha256
+        )));
+    }
+
+    Ok(sha256.to_ascii_lowercase())
+}
+
+/// Checks that the run-as user may write a file, or create it if it does not exist
+pub fn check_writable(path: &Path) -> Result<(), PortalError> {
+    let Some(user) = run_as_user() else {
+        return Ok(());
+    };
+
+    let target = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => path
+            .parent()
+            .and_then(|parent| fs::metadata(parent).ok())
+            .ok_or_else(|| {
+                PortalError::JsonRpc(format!(
+                    "Parent directory does not exist: {}",
+                    path.display()
+                ))
+            })?,
+        Err(e) => {
+            return Err(PortalError::Internal(format!(
+                "Failed to stat {}: {}",
+                path.display(),
+                e
+            )))
+        }
+    };
+
+    if !user.can_write(&target) {
+        return Err(PortalError::JsonRpc(format!(
+            "Permission denied: {}",
+            path.display()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Copies a cached file to `path`, returning its size, or `None` if nothing is cached under the
+/// checksum
+pub async fn restore(sha256: &str, path: &Path) -> Result<Option<u64>, PortalError> {
+    let Some(cached) = sha256_dir()
+        .map(|dir| dir.join(sha256))
+        .filter(|p| p.is_file())
+    else {
+        CACHE_MISSES.fetch_add(1, Ordering::Relaxed);
+        return Ok(None);
+    };
+
+    let existed = path.exists();
+    let size = tokio::fs::copy(&cached, path).await.map_err(|e| {
+        PortalError::Internal(format!(
+            "Failed to copy cached upload to {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    if !existed {
+        give_to_run_as_user(path)?;
+    }
+
+    CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+    tracing::debug!("Restored {} from upload cache {}", path.display(), sha256);
+    Ok(Some(size))
+}
+
+/// Checks a fully written upload against its checksum and keeps it in the cache
+///
+/// Returns whether the file is now cached, which it is not if the server mounted no cache. A
+/// file that does not match its checksum is removed, as its content is not what was uploaded.
+pub async fn store(sha256: &str, path: &Path) -> Result<bool, PortalError> {
+    let Some(dir) = sha256_dir() else {
+        verify(sha256, path, path).await?;
+        return Ok(false);
+    };
+    let cached = dir.join(sha256);
+    if cached.is_file() {
+        verify(sha256, path, path).await?;
+        return Ok(true);
+    }
+
+    // Hash a private copy rather than the written file, which executed code can change until it
+    // is cached, and give it its final name only once it is complete
+    let partial = dir.join(format!(".{}.{}", sha256, uuid::Uuid::new_v4()));
+    if let Err(e) = tokio::fs::copy(path, &partial).await {
+        let _ = tokio::fs::remove_file(&partial).await;
+        tracing::warn!("Not caching upload {}: {}", path.display(), e);
+        verify(sha256, path, path).await?;
+        return Ok(false);
+    }
+
+    let result = match verify(sha256, &partial, path).await {
+        Ok(()) => tokio::fs::rename(&partial, &cached).await,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(e);
+        }
+    };
+    if let Err(e) = result {
+        let _ = tokio::fs::remove_file(&partial).await;
+        tracing::warn!("Not caching upload {}: {}", path.display(), e);
+        return Ok(false);
+    }
+
+    tracing::debug!("Cached upload {} as {}", path.display(), sha256);
+    Ok(true)
+}
+
+/// Returns the statistics of the upload cache
+pub async fn cache_stats() -> Result<UploadCacheStats, PortalError> {
+    let mut stats = UploadCacheStats {
+        available: false,
+        files: 0,
+        bytes: 0,
+        hits: CACHE_HITS.load(Ordering::Relaxed),
+        misses: CACHE_MISSES.load(Ordering::Relaxed),
+    };
+    let Some(dir) = sha256_dir() else {
+        return Ok(stats);
+    };
+    stats.available = true;
+
+    let mut entries = tokio::fs::read_dir(&dir)
+        .await
+        .map_err(|e| PortalError::Internal(format!("Failed to read upload cache: {}", e)))?;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| PortalError::Internal(format!("Failed to read upload cache: {}", e)))?
+    {
+        // Partial copies start with a dot and are not part of the cache yet
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if let Ok(metadata) = entry.metadata().await {
+            if metadata.is_file() {
+                stats.files += 1;
+                stats.bytes += metadata.size();
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Hands a file the portal created to the run-as user
+pub fn give_to_run_as_user(path: &Path) -> Result<(), PortalError> {
+    if let Some(user) = run_as_user() {
+        chown(path, Some(user.uid), Some(user.gid)).map_err(|e| {
+            PortalError::Internal(format!("Failed to chown {}: {}", path.display(), e))
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Returns the directory cached files are stored in, if the server mounted an upload cache
+///
+/// The cache is only used once [`init_upload_cache`] has locked it away from the run-as user.
+fn sha256_dir() -> Option<PathBuf> {
+    let metadata = fs::metadata(DEFAULT_UPLOAD_CACHE_GUEST_PATH).ok()?;
+    let usable = metadata.is_dir()
+        && (run_as_user().is_none() || (metadata.uid() == 0 && metadata.mode() & 0o077 == 0));
+
+    usable.then(|| Path::new(DEFAULT_UPLOAD_CACHE_GUEST_PATH).join(SHA256_SUBDIR))
+}
+
+/// Checks that `file` matches the checksum of the upload written to `path`
+///
+/// The upload is removed if it does not.
+async fn verify(sha256: &str, file: &Path, path: &Path) -> Result<(), PortalError> {
+    let actual = sha256_file(file).await?;
+    if actual != sha256 {
+        let _ = tokio::fs::remove_file(path).await;
+        return Err(PortalError::JsonRpc(format!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            path.display(),
+            sha256,
+            actual
+        )));
+    }
+
+    Ok(())
+}
+
+/// Computes the sha256 hex digest of a file
+async fn sha256_file(path: &Path) -> Result<String, PortalError> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut hasher = Sha256::new();
+        fs::File::open(&path)
+            .and_then(|mut file| io::copy(&mut file, &mut hasher))
+            .map_err(|e| {
+                PortalError::Internal(format!("Failed to hash {}: {}", path.display(), e))
+            })?;
+
+        Ok(hex::encode(hasher.finalize()))
+    })
+    .await
+    .map_err(|e| PortalError::Internal(format!("Failed to hash upload: {}", e)))?
+}
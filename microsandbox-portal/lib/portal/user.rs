@@ -0,0 +1,314 @@
+//! Unprivileged execution of code and commands in the microsandbox portal.
+//!
+//! The portal itself runs as root inside the sandbox, but the code and commands it executes
+//! can be dropped to another user. The user is chosen once at startup with `--run-as-user` or
+//! the `MSB_RUN_AS_USER` environment variable, and applies to every REPL engine and command
+//! process spawned afterwards.
+//!
+//! The user may be given as:
+//! - A name from `/etc/passwd`. A name the image does not know is created with the first free
+//!   uid from 1000 upwards, a group of the same name and a home directory under `/home`.
+//! - A numeric `uid` or `uid:gid`, which is used as-is.
+//! - `root` or `0`, which keeps executions running as root.
+//!
+//! Files read through the portal are checked against the permission bits the user has on
+//! them, so a file the executed code cannot read cannot be read through the portal either.
+
+use std::{
+    fs::{self, Metadata, OpenOptions},
+    io::Write,
+    os::unix::fs::{chown, MetadataExt},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use tokio::process::Command;
+
+use crate::error::PortalError;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// User database of the sandbox
+const PASSWD_PATH: &str = "/etc/passwd";
+
+/// Group database of the sandbox
+const GROUP_PATH: &str = "/etc/group";
+
+/// Directory that home directories of created users are placed in
+const HOME_ROOT: &str = "/home";
+
+/// Lowest uid and gid given to a created user
+const FIRST_CREATED_ID: u32 = 1000;
+
+/// Login shell of a created user
+const CREATED_USER_SHELL: &str = "/bin/sh";
+
+/// User that executions run as, set once at startup
+static RUN_AS_USER: OnceLock<Option<RunAsUser>> = OnceLock::new();
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Unprivileged user that code and commands are executed as
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunAsUser {
+    /// User name
+    pub name: String,
+
+    /// User id
+    pub uid: u32,
+
+    /// Primary group id
+    pub gid: u32,
+
+    /// Home directory
+    pub home: PathBuf,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Traits
+//--------------------------------------------------------------------------------------------------
+
+/// Extension for dropping a process to the user that executions run as
+pub trait RunAsUserExt {
+    /// Runs the process as the configured user, if one is set
+    fn as_run_as_user(&mut self) -> &mut Self;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl RunAsUser {
+    /// Resolves a user spec, creating a named user that does not exist yet
+    ///
+    /// Returns `None` for root, which needs no privileges dropped.
+    pub fn resolve(spec: &str) -> Result<Option<Self>, PortalError> {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            return Err(PortalError::Internal(
+                "Run-as user must not be empty".to_string(),
+            ));
+        }
+
+        let user = match parse_ids(spec)? {
+            Some((uid, gid)) => {
+                let entry = read_passwd()?.into_iter().find(|user| user.uid == uid);
+                RunAsUser {
+                    name: entry
+                        .as_ref()
+                        .map_or_else(|| uid.to_string(), |u| u.name.clone()),
+                    uid,
+                    gid: gid.or(entry.as_ref().map(|u| u.gid)).unwrap_or(uid),
+                    home: entry.map_or_else(|| PathBuf::from("/"), |u| u.home),
+                }
+            }
+            None => match read_passwd()?.into_iter().find(|user| user.name == spec) {
+                Some(user) => user,
+                None => create_user(spec)?,
+            },
+        };
+
+        Ok((user.uid != 0).then_some(user))
+    }
+
+    /// Checks whether the permission bits of a file let the user read it
+    pub fn can_read(&self, metadata: &Metadata) -> bool {
+        let mode = metadata.mode();
+        if metadata.uid() == self.uid {
+            mode & 0o400 != 0
+        } else if metadata.gid() == self.gid {
+            mode & 0o040 != 0
+        } else {
+            mode & 0o004 != 0
+        }
+    }
+
+    /// Checks whether the permission bits of a file let the user write it
+    pub fn can_write(&self, metadata: &Metadata) -> bool {
+        let mode = metadata.mode();
+        if metadata.uid() == self.uid {
+            mode & 0o200 != 0
+        } else if metadata.gid() == self.gid {
+            mode & 0o020 != 0
+        } else {
+            mode & 0o002 != 0
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl RunAsUserExt for Command {
+    fn as_run_as_user(&mut self) -> &mut Self {
+        if let Some(user) = run_as_user() {
+            self.uid(user.uid)
+                .gid(user.gid)
+                .env("HOME", &user.home)
+                .env("USER", &user.name)
+                .env("LOGNAME", &user.name);
+        }
+
+        self
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Sets the user that executions run as for the rest of the portal's lifetime
+///
+/// `None` or root keeps executions running as root. Must be called before any engine or
+/// command is started, and only once.
+pub fn init_run_as_user(spec: Option<&str>) -> Result<Option<&'static RunAsUser>, PortalError> {
+    let user = spec.map(RunAsUser::resolve).transpose()?.flatten();
+    RUN_AS_USER
+        .set(user)
+        .map_err(|_| PortalError::Internal("Run-as user is already set".to_string()))?;
+
+    Ok(run_as_user())
+}
+
+/// Returns the user that executions run as, or `None` if they run as root
+pub fn run_as_user() -> Option<&'static RunAsUser> {
+    RUN_AS_USER.get().and_then(Option::as_ref)
+}
+
+/// Parses a numeric `uid` or `uid:gid` spec, returning `None` for a user name
+fn parse_ids(spec: &str) -> Result<Option<(u32, Option<u32>)>, PortalError> {
+    let (uid, gid) = match spec.split_once(':') {
+        Some((uid, gid)) => (uid, Some(gid)),
+        None => (spec, None),
+    };
+    if !uid.bytes().all(|b| b.is_ascii_digit()) {
+        return match gid {
+            Some(_) => Err(PortalError::Internal(format!(
+                "Invalid run-as user '{}': expected a name, uid or uid:gid",
+                spec
+            ))),
+            None => Ok(None),
+        };
+    }
+
+    let parse = |id: &str| {
+        id.parse::<u32>().map_err(|_| {
+            PortalError::Internal(format!("Invalid id '{}' in run-as user '{}'", id, spec))
+        })
+    };
+    Ok(Some((parse(uid)?, gid.map(parse).transpose()?)))
+}
+
+/// Reads the users of the sandbox from `/etc/passwd`
+fn read_passwd() -> Result<Vec<RunAsUser>, PortalError> {
+    let passwd = match fs::read_to_string(PASSWD_PATH) {
+        Ok(passwd) => passwd,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(PortalError::Internal(format!(
+                "Failed to read {}: {}",
+                PASSWD_PATH, e
+            )))
+        }
+    };
+
+    // name:password:uid:gid:gecos:home:shell
+    Ok(passwd
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            Some(RunAsUser {
+                name: fields.first()?.to_string(),
+                uid: fields.get(2)?.parse().ok()?,
+                gid: fields.get(3)?.parse().ok()?,
+                home: PathBuf::from(fields.get(5)?),
+            })
+        })
+        .collect())
+}
+
+/// Reads the ids already used in the third field of a `/etc/passwd` style file
+fn used_ids(path: &str) -> Vec<u32> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split(':').nth(2)?.parse().ok())
+        .collect()
+}
+
+/// Adds a user with a group of the same name and a home directory to the sandbox
+fn create_user(name: &str) -> Result<RunAsUser, PortalError> {
+    if !name
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-' || b == b'.')
+    {
+        return Err(PortalError::Internal(format!(
+            "Invalid run-as user name '{}'",
+            name
+        )));
+    }
+
+    // Pick an id free as both a uid and a gid, so the group can share it
+    let uids = used_ids(PASSWD_PATH);
+    let gids = used_ids(GROUP_PATH);
+    let id = (FIRST_CREATED_ID..)
+        .find(|id| !uids.contains(id) && !gids.contains(id))
+        .expect("ids are not exhausted");
+
+    let user = RunAsUser {
+        name: name.to_string(),
+        uid: id,
+        gid: id,
+        home: Path::new(HOME_ROOT).join(name),
+    };
+
+    append_line(GROUP_PATH, &format!("{}:x:{}:", name, id))?;
+    append_line(
+        PASSWD_PATH,
+        &format!(
+            "{}:x:{}:{}::{}:{}",
+            name,
+            id,
+            id,
+            user.home.display(),
+            CREATED_USER_SHELL
+        ),
+    )?;
+
+    fs::create_dir_all(&user.home)
+        .and_then(|_| chown(&user.home, Some(id), Some(id)))
+        .map_err(|e| {
+            PortalError::Internal(format!(
+                "Failed to create home directory {}: {}",
+                user.home.display(),
+                e
+            ))
+        })?;
+
+    tracing::info!("Created run-as user {} ({}:{})", name, id, id);
+    Ok(user)
+}
+
+/// Appends a line to a file, starting it on a new line if the file does not end with one
+fn append_line(path: &str, line: &str) -> Result<(), PortalError> {
+    let needs_newline = fs::read(path)
+        .map(|content| content.last().is_some_and(|&b| b != b'\n'))
+        .unwrap_or(false);
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| {
+            if needs_newline {
+                file.write_all(b"\n")?;
+            }
+            writeln!(file, "{}", line)
+        })
+        .map_err(|e| PortalError::Internal(format!("Failed to update {}: {}", path, e)))
+}
@@ -0,0 +1,76 @@
+//! Capturing the value of the final expression of REPL code.
+//!
+//! With `eval` set on a `sandbox.repl.run` request, code is run the way a notebook cell is: if
+//! its last statement is an expression, the repr of its value is returned in a `value` field
+//! next to the output, so the code does not have to print it.
+//!
+//! The code is wrapped before it is sent to the REPL:
+//! - Python parses the code with `ast`, runs every statement but a trailing expression, then
+//!   evaluates that expression in the REPL's globals and takes its `repr()`.
+//! - Node.js runs the code as a script with `vm.runInThisContext`, whose completion value is
+//!   the value of the last expression statement, and takes its `util.inspect()`.
+//!
+//! The repr is printed as a JSON string on a line starting with a per-execution marker, which
+//! [`take_value`] removes from the output again. Code ending in anything other than an
+//! expression, or in one that evaluates to `None` or `undefined`, has no value.
+//!
+//! The wrapped code is a single line, so it does not depend on how the REPL terminates blocks.
+
+use super::repl::{Language, Line, Stream};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Python that runs `_msb_code` in `_msb_globals` and prints the repr of its final expression
+#[cfg(feature = "python")]
+const PYTHON_EVAL_HELPER: &str = r#"import ast, json
+tree = ast.parse(_msb_code, "<cell>")
+last = tree.body.pop() if tree.body and isinstance(tree.body[-1], ast.Expr) else None
+exec(compile(tree, "<cell>", "exec"), _msb_globals)
+if last is not None:
+    value = eval(compile(ast.Expression(last.value), "<cell>", "eval"), _msb_globals)
+    if value is not None:
+        print(_msb_marker + json.dumps(repr(value)))
+"#;
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Wraps REPL code so that the repr of its final expression is printed after `marker`
+pub fn value_code(language: Language, marker: &str, code: &str) -> String {
+    let code = literal(code);
+    let marker = literal(marker);
+
+    match language {
+        #[cfg(feature = "python")]
+        Language::Python => format!(
+            "exec({}, {{\"_msb_code\": {}, \"_msb_marker\": {}, \"_msb_globals\": globals()}})\n",
+            literal(PYTHON_EVAL_HELPER),
+            code,
+            marker,
+        ),
+        #[cfg(feature = "nodejs")]
+        Language::Node => format!(
+            "void ((v) => v === undefined || console.log({} + JSON.stringify(require('util').inspect(v))))(require('vm').runInThisContext({}));\n",
+            marker, code,
+        ),
+    }
+}
+
+/// Removes the line carrying the value printed by [`value_code`] from the output and returns it
+pub fn take_value(lines: &mut Vec<Line>, marker: &str) -> Option<String> {
+    let index = lines
+        .iter()
+        .rposition(|line| line.stream == Stream::Stdout && line.text.starts_with(marker))?;
+    let line = lines.remove(index);
+
+    serde_json::from_str(&line.text[marker.len()..]).ok()
+}
+
+/// Quotes a string as a literal that both Python and JavaScript accept
+pub(super) fn literal(s: &str) -> String {
+    // JSON strings only use escapes that the two languages share
+    serde_json::to_string(s).expect("strings always serialize")
+}
@@ -5,7 +5,7 @@
 //! - Router configuration and setup
 //! - Request routing and handling
 
-use axum::{routing::post, Router};
+use axum::{extract::DefaultBodyLimit, routing::post, Router};
 use tower_http::trace::TraceLayer;
 
 use crate::{handler, state::SharedState};
@@ -20,9 +20,12 @@ pub fn create_router(state: SharedState) -> Router {
     // Using an adapter function to properly handle the state parameter
     let rpc_api = Router::new().route("/", post(handler::json_rpc_handler));
 
-    // Combine all routes with tracing middleware
+    // Combine all routes with the body size limit and tracing middleware. Oversized bodies are
+    // rejected by the JSON extractor, which the handler turns into a JSON-RPC error.
+    let max_body_size = state.max_body_size;
     Router::new()
         .nest("/api/v1/rpc", rpc_api)
+        .layer(DefaultBodyLimit::max(max_body_size))
         .layer(TraceLayer::new_for_http())
         .with_state(state)
 }
@@ -1,9 +1,12 @@
 //! Shared state management for the microsandbox portal server.
 
-use std::sync::Arc;
+use microsandbox_utils::{DEFAULT_PORTAL_MAX_BODY_SIZE, DEFAULT_PORTAL_MAX_FILE_READ_SIZE};
+use std::{collections::HashMap, sync::Arc};
 use tokio::sync::Mutex;
 
-use crate::portal::{command::CommandHandle, repl::EngineHandle};
+use crate::portal::{
+    command::CommandHandle, env::SessionEnv, executor::ExecutorRegistry, repl::EngineHandle,
+};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -18,8 +21,23 @@ pub struct SharedState {
     /// Engine handle for REPL environment
     pub engine_handle: Arc<Mutex<Option<EngineHandle>>>,
 
+    /// Engine handles for named runtimes, each with its own interpreter processes
+    pub runtimes: Arc<Mutex<HashMap<String, EngineHandle>>>,
+
     /// Command handle for command execution
     pub command_handle: Arc<Mutex<Option<CommandHandle>>>,
+
+    /// Executors for REPL code, by language name
+    pub executors: Arc<ExecutorRegistry>,
+
+    /// Environment variables set for the session, inherited by every execution
+    pub env: Arc<Mutex<SessionEnv>>,
+
+    /// Maximum size of a request body, in bytes
+    pub max_body_size: usize,
+
+    /// Maximum size of a file read with `sandbox.file.read`, in bytes
+    pub max_file_read_size: u64,
 }
 
 impl Default for SharedState {
@@ -27,7 +45,12 @@ impl Default for SharedState {
         Self {
             ready: Arc::new(Mutex::new(false)),
             engine_handle: Arc::new(Mutex::new(None)),
+            runtimes: Arc::new(Mutex::new(HashMap::new())),
             command_handle: Arc::new(Mutex::new(None)),
+            executors: Arc::new(ExecutorRegistry::new()),
+            env: Arc::new(Mutex::new(SessionEnv::default())),
+            max_body_size: DEFAULT_PORTAL_MAX_BODY_SIZE,
+            max_file_read_size: DEFAULT_PORTAL_MAX_FILE_READ_SIZE,
         }
     }
 }
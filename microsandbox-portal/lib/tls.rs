@@ -0,0 +1,99 @@
+//! TLS support for the microsandbox portal server.
+//!
+//! The portal serves plaintext HTTP by default. When a certificate and private key are
+//! configured, connections are terminated with TLS using the acceptor built here.
+
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+
+use tokio_rustls::{
+    rustls::{
+        pki_types::{CertificateDer, PrivateKeyDer},
+        ServerConfig,
+    },
+    TlsAcceptor,
+};
+
+use crate::error::PortalError;
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Builds a TLS acceptor from a PEM encoded certificate chain and private key.
+///
+/// Both files are read and validated eagerly so that a misconfigured portal fails at startup
+/// instead of on the first incoming connection.
+///
+/// ## Arguments
+///
+/// * `cert_path` - Path to the PEM encoded certificate chain
+/// * `key_path` - Path to the PEM encoded private key (PKCS#1, PKCS#8 or SEC1)
+///
+/// ## Errors
+///
+/// Returns `PortalError::Tls` if either file cannot be read, contains no usable PEM entries,
+/// or if the private key does not match the certificate.
+pub fn load_tls_acceptor(cert_path: &Path, key_path: &Path) -> Result<TlsAcceptor, PortalError> {
+    let certs = load_certs(cert_path)?;
+    let key = load_private_key(key_path)?;
+
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| {
+            PortalError::Tls(format!(
+                "invalid certificate/key pair ({}, {}): {}",
+                cert_path.display(),
+                key_path.display(),
+                e
+            ))
+        })?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, PortalError> {
+    let mut reader = open_pem(path, "certificate")?;
+    let certs = rustls_pemfile::certs(&mut reader)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            PortalError::Tls(format!(
+                "failed to parse certificate file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+    if certs.is_empty() {
+        return Err(PortalError::Tls(format!(
+            "no certificates found in {}",
+            path.display()
+        )));
+    }
+
+    Ok(certs)
+}
+
+fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>, PortalError> {
+    let mut reader = open_pem(path, "private key")?;
+    rustls_pemfile::private_key(&mut reader)
+        .map_err(|e| {
+            PortalError::Tls(format!(
+                "failed to parse private key file {}: {}",
+                path.display(),
+                e
+            ))
+        })?
+        .ok_or_else(|| PortalError::Tls(format!("no private key found in {}", path.display())))
+}
+
+fn open_pem(path: &Path, kind: &str) -> Result<BufReader<File>, PortalError> {
+    File::open(path).map(BufReader::new).map_err(|e| {
+        PortalError::Tls(format!(
+            "failed to open {} file {}: {}",
+            kind,
+            path.display(),
+            e
+        ))
+    })
+}
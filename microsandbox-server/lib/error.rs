@@ -12,16 +12,21 @@
 //! - Serializable error responses for API clients
 //! - Structured error codes for frontend handling
 
+use std::time::Duration;
+
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
 use microsandbox_utils::MicrosandboxUtilsError;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use thiserror::Error;
 use tracing::error;
 
+use crate::payload::{JsonRpcError, JsonRpcResponse, PAYLOAD_TOO_LARGE_ERROR_CODE};
+
 //--------------------------------------------------------------------------------------------------
 // Types
 //--------------------------------------------------------------------------------------------------
@@ -51,6 +56,11 @@ pub enum MicrosandboxServerError {
     #[error("Server configuration failed: {0}")]
     ConfigError(String),
 
+    /// Error returned when an admin request to a running server, such as reading or changing
+    /// its maintenance mode, fails
+    #[error("Server admin request failed: {0}")]
+    AdminRequestError(String),
+
     /// Error returned when an I/O error occurs
     #[error(transparent)]
     IoError(#[from] std::io::Error),
@@ -75,6 +85,16 @@ pub enum ServerError {
     #[error("Resource not found: {0}")]
     NotFound(String),
 
+    /// Error returned when a sandbox image is not in any of the configured registries
+    #[error("Image {image} not found in any registry (tried: {})", .registries.join(", "))]
+    ImageNotFound {
+        /// The image that was requested
+        image: String,
+
+        /// The registries that were tried, in order
+        registries: Vec<String>,
+    },
+
     /// Error returned when a database operation fails
     #[error("Database error: {0}")]
     DatabaseError(String),
@@ -83,6 +103,29 @@ pub enum ServerError {
     #[error("Validation error: {0}")]
     ValidationError(ValidationError),
 
+    /// Error returned when new sessions are rejected because the server is in maintenance mode
+    #[error("Server is in maintenance mode and is not accepting new sessions")]
+    Maintenance,
+
+    /// Error returned when a namespace makes more requests than its rate limit allows
+    #[error("Rate limit of namespace '{namespace}' exceeded, retry in {} seconds", retry_after_secs(.retry_after))]
+    RateLimited {
+        /// The namespace that made the request
+        namespace: String,
+
+        /// How long to wait before the namespace may make another request
+        retry_after: Duration,
+    },
+
+    /// Error returned when an operation failed in a way that is likely to succeed if retried,
+    /// e.g. a sandbox start racing another one for a port or an image being extracted
+    #[error("Transient error: {0}")]
+    TransientError(String),
+
+    /// Error returned when a request body exceeds the maximum body size, in bytes
+    #[error("Payload too large: request body exceeds the limit of {0} bytes")]
+    PayloadTooLarge(usize),
+
     /// Error returned when an unexpected internal error occurs
     #[error("Internal server error: {0}")]
     InternalError(String),
@@ -134,12 +177,20 @@ pub enum ErrorCode {
     // Resource error codes
     /// Error returned when a requested resource cannot be found
     ResourceNotFound = 4001,
+    /// Error returned when a sandbox image cannot be found in any registry
+    ImageNotFound = 4002,
 
     // Server error codes
     /// Error returned when a database operation fails
     DatabaseError = 5001,
     /// Error returned when an unexpected server error occurs
     InternalServerError = 5002,
+    /// Error returned when the server is in maintenance mode and rejects new work
+    ServerInMaintenance = 5003,
+    /// Error returned when a namespace exceeds its rate limit
+    RateLimitExceeded = 5004,
+    /// Error returned when an operation failed in a way that is likely to succeed if retried
+    TemporarilyUnavailable = 5005,
 }
 
 /// Represents different types of authentication failures
@@ -204,6 +255,20 @@ pub enum ValidationError {
     /// Invalid or expired confirmation token
     #[error("Invalid or expired confirmation token")]
     InvalidConfirmationToken,
+
+    /// One or more sandbox configuration fields are invalid
+    #[error("Invalid sandbox configuration: {}", format_config_errors(.0))]
+    InvalidConfig(Vec<ConfigValidationError>),
+}
+
+/// A single invalid field in a sandbox configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConfigValidationError {
+    /// Path to the offending field, e.g. `memory` or `volumes[2]`
+    pub field: String,
+
+    /// Why the field is invalid
+    pub reason: String,
 }
 
 /// Represents authorization errors
@@ -223,6 +288,22 @@ pub enum AuthorizationError {
 struct ErrorResponse {
     error: String,
     code: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errors: Option<Vec<ConfigValidationError>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ConfigValidationError {
+    /// Creates a new configuration validation error for the given field
+    pub fn new(field: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            reason: reason.into(),
+        }
+    }
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -243,6 +324,20 @@ impl IntoResponse for ServerError {
         // Log the actual error with details
         error!(error = ?self, "API error occurred");
 
+        // Invalid configs also report each field so clients can point at the exact problem
+        let field_errors = match &self {
+            ServerError::ValidationError(ValidationError::InvalidConfig(errors)) => {
+                Some(errors.clone())
+            }
+            _ => None,
+        };
+
+        // Rate limited clients are told when to retry
+        let retry_after = match &self {
+            ServerError::RateLimited { retry_after, .. } => Some(retry_after_secs(retry_after)),
+            _ => None,
+        };
+
         let (status, error_message, error_code) = match self {
             ServerError::Authentication(auth_error) => {
                 match auth_error {
@@ -306,6 +401,21 @@ impl IntoResponse for ServerError {
                 details,
                 Some(ErrorCode::ResourceNotFound as u32),
             ),
+            error @ ServerError::ImageNotFound { .. } => (
+                StatusCode::NOT_FOUND,
+                error.to_string(),
+                Some(ErrorCode::ImageNotFound as u32),
+            ),
+            error @ ServerError::Maintenance => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                error.to_string(),
+                Some(ErrorCode::ServerInMaintenance as u32),
+            ),
+            error @ ServerError::RateLimited { .. } => (
+                StatusCode::TOO_MANY_REQUESTS,
+                error.to_string(),
+                Some(ErrorCode::RateLimitExceeded as u32),
+            ),
             ServerError::DatabaseError(details) => {
                 error!(details = ?details, "Database error");
                 (
@@ -335,7 +445,34 @@ impl IntoResponse for ServerError {
                     "Invalid or expired confirmation token".to_string(),
                     Some(ErrorCode::InvalidOrExpiredConfirmationToken as u32),
                 ),
+                error @ ValidationError::InvalidConfig(_) => (
+                    StatusCode::BAD_REQUEST,
+                    error.to_string(),
+                    Some(ErrorCode::InvalidInput as u32),
+                ),
             },
+            error @ ServerError::PayloadTooLarge(limit) => {
+                // Oversized bodies are only rejected on the JSON-RPC endpoints, so they get the
+                // same JSON-RPC error as the portal's, carrying the limit
+                let error = JsonRpcError {
+                    code: PAYLOAD_TOO_LARGE_ERROR_CODE,
+                    message: error.to_string(),
+                    data: Some(json!({ "max_body_size": limit })),
+                };
+                return (
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    Json(JsonRpcResponse::error(error, None)),
+                )
+                    .into_response();
+            }
+            ServerError::TransientError(details) => {
+                error!(details = ?details, "Transient error");
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Service temporarily unavailable, please retry".to_string(),
+                    Some(ErrorCode::TemporarilyUnavailable as u32),
+                )
+            }
             ServerError::InternalError(details) => {
                 error!(details = ?details, "Internal error");
                 (
@@ -349,8 +486,38 @@ impl IntoResponse for ServerError {
         let body = Json(ErrorResponse {
             error: error_message,
             code: error_code,
+            errors: field_errors,
         });
 
-        (status, body).into_response()
+        match retry_after {
+            Some(secs) => {
+                (status, [(header::RETRY_AFTER, secs.to_string())], body).into_response()
+            }
+            None => (status, body).into_response(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.reason)
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Joins configuration field errors into a single message
+fn format_config_errors(errors: &[ConfigValidationError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Rounds a wait up to the whole seconds of a `Retry-After` header
+fn retry_after_secs(retry_after: &Duration) -> u64 {
+    retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)
+}
@@ -16,28 +16,43 @@ use axum::{
     extract::{Path, State},
     http::{Request, StatusCode},
     response::{IntoResponse, Response},
-    Json,
+    Extension, Json,
+};
+use microsandbox_core::{
+    management::{
+        image, menv, orchestra,
+        sandbox::{self, ResolvedSandbox},
+    },
+    oci::{PullProgressCallback, Reference},
+    MicrosandboxError,
+};
+use microsandbox_utils::{
+    DEFAULT_CONFIG, DEFAULT_PORTAL_GUEST_PORT, DEFAULT_SANDBOX_USER,
+    DEFAULT_UPLOAD_CACHE_GUEST_PATH, MICROSANDBOX_CONFIG_FILENAME, MSB_RUN_AS_USER_ENV_VAR,
+    UPLOAD_CACHE_SUBDIR,
 };
-use microsandbox_core::management::{menv, orchestra};
-use microsandbox_utils::{DEFAULT_CONFIG, DEFAULT_PORTAL_GUEST_PORT, MICROSANDBOX_CONFIG_FILENAME};
 use reqwest;
 use serde_json::{self, json};
 use serde_yaml;
-use std::path::PathBuf;
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 use tokio::{
     fs as tokio_fs,
     time::{sleep, timeout, Duration},
 };
-use tracing::{debug, trace, warn};
+use tracing::{debug, info, trace, warn};
 
 use crate::{
     error::ServerError,
     mcp, middleware,
     payload::{
         JsonRpcError, JsonRpcRequest, JsonRpcResponse, JsonRpcResponseOrNotification,
-        RegularMessageResponse, SandboxMetricsGetParams, SandboxStartParams, SandboxStopParams,
-        JSONRPC_VERSION,
+        ReadinessResponse, RegularMessageResponse, SandboxDescribeParams, SandboxMetricsGetParams, SandboxStartParams,
+        SandboxStopParams, ServerMaintenanceResponse, ServerMaintenanceSetParams, JSONRPC_VERSION,
     },
+    simplified_mcp::{ExecutionPriority, SessionCaller},
     state::AppState,
     SandboxStatus, SandboxStatusResponse, ServerResult,
 };
@@ -56,6 +71,39 @@ pub async fn health() -> ServerResult<impl IntoResponse> {
     ))
 }
 
+/// Handler for the liveness probe, which succeeds whenever the process can serve requests
+pub async fn healthz() -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        Json(RegularMessageResponse {
+            message: "Service is alive".to_string(),
+        }),
+    )
+}
+
+/// Handler for the readiness probe
+///
+/// Returns 503 while the server is draining or overloaded, so load balancers stop routing new
+/// sessions to it until it recovers. Degraded parts of the server are reported without failing
+/// the probe.
+pub async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let reasons = state.get_readiness_problems();
+    let status = if reasons.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        Json(ReadinessResponse {
+            ready: reasons.is_empty(),
+            reasons,
+            degraded: state.get_degradations(),
+        }),
+    )
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions: JSON-RPC Handlers
 //--------------------------------------------------------------------------------------------------
@@ -64,9 +112,14 @@ pub async fn health() -> ServerResult<impl IntoResponse> {
 #[debug_handler]
 pub async fn mcp_handler(
     State(state): State<AppState>,
+    Extension(caller): Extension<SessionCaller>,
     Json(request): Json<JsonRpcRequest>,
 ) -> ServerResult<impl IntoResponse> {
-    debug!(?request, "Received MCP request");
+    debug!(
+        method = %request.method,
+        params = %state.get_session_manager().get_redactor().redact_value(&request.params),
+        "Received MCP request"
+    );
     // Check for required JSON-RPC fields
     if request.jsonrpc != JSONRPC_VERSION {
         let error = JsonRpcError {
@@ -84,7 +137,7 @@ pub async fn mcp_handler(
     let request_id = request.id.clone();
 
     // Handle MCP methods directly since all requests to /mcp are MCP requests
-    match mcp::handle_mcp_method(state, request).await {
+    match mcp::handle_mcp_method(state, caller, request).await {
         Ok(response) => {
             // The enum handles both regular responses and notifications
             Ok(response)
@@ -106,7 +159,11 @@ pub async fn json_rpc_handler(
     State(state): State<AppState>,
     Json(request): Json<JsonRpcRequest>,
 ) -> ServerResult<impl IntoResponse> {
-    debug!(?request, "Received JSON-RPC request");
+    debug!(
+        method = %request.method,
+        params = %state.get_session_manager().get_redactor().redact_value(&request.params),
+        "Received JSON-RPC request"
+    );
 
     // Check for required JSON-RPC fields
     if request.jsonrpc != JSONRPC_VERSION {
@@ -162,6 +219,24 @@ pub async fn json_rpc_handler(
                 Json(JsonRpcResponse::success(json!(result), id)),
             ))
         }
+        "sandbox.describe" => {
+            // Parse the params into a SandboxDescribeParams
+            let describe_params: SandboxDescribeParams =
+                serde_json::from_value(request.params.clone()).map_err(|e| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        format!("Invalid params for sandbox.describe: {}", e),
+                    ))
+                })?;
+
+            // Call the sandbox_describe_impl function
+            let result = sandbox_describe_impl(state, describe_params).await?;
+
+            // Create JSON-RPC response with success
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
         "sandbox.metrics.get" => {
             // Parse the params into a SandboxMetricsGetRequest
             let metrics_params: SandboxMetricsGetParams =
@@ -181,8 +256,64 @@ pub async fn json_rpc_handler(
             ))
         }
 
+        "sandbox.queue.get" => {
+            // Report the state of the execution queue
+            let result = state
+                .get_execution_queue()
+                .get_stats()
+                .map_err(|e| ServerError::InternalError(e.to_string()))?;
+
+            // Create JSON-RPC response with success
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
+
+        "server.maintenance.get" => {
+            let result = server_maintenance_impl(&state, None)?;
+
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
+        "server.maintenance.set" => {
+            let params: ServerMaintenanceSetParams = serde_json::from_value(request.params.clone())
+                .map_err(|e| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        format!("Invalid params for server.maintenance.set: {}", e),
+                    ))
+                })?;
+
+            let result = server_maintenance_impl(&state, Some(params.enabled))?;
+
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
+        "server.state.dump" => {
+            let result = server_state_dump_impl(&state).await?;
+
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(result, id)),
+            ))
+        }
+
         // Portal-forwarded methods
-        "sandbox.repl.run" | "sandbox.command.run" => {
+        "sandbox.repl.run"
+        | "sandbox.command.run"
+        | "sandbox.env.set"
+        | "sandbox.env.unset"
+        | "sandbox.file.read"
+        | "sandbox.file.write"
+        | "sandbox.file.list"
+        | "sandbox.file.cache.stats"
+        | "sandbox.packages.install"
+        | "sandbox.runtime.info"
+        | "sandbox.stats" => {
             // Forward these RPC methods to the portal
             match forward_rpc_to_portal(state, request).await {
                 Ok((status, json_response)) => Ok((status, json_response)),
@@ -245,6 +376,81 @@ pub async fn forward_rpc_to_portal(
         ));
     };
 
+    // Reject oversized code and code importing modules outside the import policy before it is
+    // queued or sent to the portal. The preamble run before the code is checked like the code
+    if request.method == "sandbox.repl.run" {
+        let config = state.get_session_manager().get_config();
+        let language = request
+            .params
+            .get("language")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        let sources = ["code", "preamble"]
+            .into_iter()
+            .filter_map(|field| request.params.get(field).and_then(|v| v.as_str()));
+        for code in sources {
+            config
+                .check_code_size(code)
+                .and_then(|_| config.check_imports(code, &language))
+                .map_err(|e| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        e.to_string(),
+                    ))
+                })?;
+        }
+    }
+
+    // Reject programs outside the command allowlist and commands with too many or too long
+    // arguments before they are queued
+    if request.method == "sandbox.command.run" {
+        let command = request
+            .params
+            .get("command")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default();
+        let args: Vec<String> = request
+            .params
+            .get("args")
+            .and_then(|v| v.as_array())
+            .map(|args| {
+                args.iter()
+                    .filter_map(|arg| arg.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let config = state.get_session_manager().get_config();
+        config
+            .check_command_allowed(command)
+            .and_then(|_| config.check_command_args(&args))
+            .map_err(|e| {
+                ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                    e.to_string(),
+                ))
+            })?;
+    }
+
+    // Queue at the requested priority, rejecting unknown ones before waiting for a slot
+    let priority: ExecutionPriority = request
+        .params
+        .get("priority")
+        .map(|v| serde_json::from_value(v.clone()))
+        .transpose()
+        .map_err(|e| {
+            ServerError::ValidationError(crate::error::ValidationError::InvalidInput(format!(
+                "Invalid 'priority' parameter: {}",
+                e
+            )))
+        })?
+        .unwrap_or_default();
+
+    // Wait for an execution slot, queued fairly against other namespaces
+    let _permit = state
+        .get_execution_queue()
+        .acquire(namespace, priority)
+        .await
+        .map_err(|e| ServerError::InternalError(e.to_string()))?;
+
     // Get the portal URL specifically for this sandbox
     let portal_url = state
         .get_portal_url_for_sandbox(namespace, sandbox_name)
@@ -318,6 +524,14 @@ pub async fn forward_rpc_to_portal(
             ServerError::InternalError(format!("Failed to forward RPC to portal: {}", e))
         })?;
 
+    // Relay oversized payload errors as-is so that clients see the portal's limit
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        let portal_response: JsonRpcResponse = response.json().await.map_err(|e| {
+            ServerError::InternalError(format!("Failed to parse portal response: {}", e))
+        })?;
+        return Ok((StatusCode::PAYLOAD_TOO_LARGE, Json(portal_response)));
+    }
+
     // Check if the request was successful
     if !response.status().is_success() {
         let status = response.status();
@@ -341,6 +555,59 @@ pub async fn forward_rpc_to_portal(
     Ok((StatusCode::OK, Json(portal_response)))
 }
 
+/// Implementation for reading and changing maintenance mode
+///
+/// Turns maintenance mode on or off if `enabled` is given, then reports the current mode.
+pub fn server_maintenance_impl(
+    state: &AppState,
+    enabled: Option<bool>,
+) -> ServerResult<ServerMaintenanceResponse> {
+    let session_manager = state.get_session_manager();
+    if let Some(enabled) = enabled {
+        session_manager.set_maintenance(enabled);
+    }
+
+    Ok(ServerMaintenanceResponse {
+        maintenance: session_manager.is_in_maintenance(),
+        sessions: session_manager
+            .get_session_count()
+            .map_err(|e| ServerError::InternalError(e.to_string()))?,
+    })
+}
+
+/// Implementation for dumping the server's internal state, for debugging
+///
+/// Covers the server configuration, the tracked sessions, the sandbox port assignments and the
+/// execution queue. The server key is never included, and secrets in the sessions are masked.
+pub async fn server_state_dump_impl(state: &AppState) -> ServerResult<serde_json::Value> {
+    let config = state.get_config();
+    let session_manager = state.get_session_manager();
+
+    let sessions = session_manager
+        .dump_state()
+        .map_err(|e| ServerError::InternalError(e.to_string()))?;
+    let ports = state.get_port_manager().read().await.get_assignments();
+    let execution_queue = state
+        .get_execution_queue()
+        .get_stats()
+        .map_err(|e| ServerError::InternalError(e.to_string()))?;
+
+    Ok(json!({
+        "server": {
+            "namespace_dir": config.get_namespace_dir(),
+            "dev_mode": config.get_dev_mode(),
+            "addr": config.get_addr(),
+            "key_configured": config.get_key().is_some(),
+            "draining": state.is_draining(),
+            "readiness_problems": state.get_readiness_problems(),
+            "degraded": state.get_degradations(),
+        },
+        "session_manager": sessions,
+        "ports": ports.mappings,
+        "execution_queue": execution_queue,
+    }))
+}
+
 /// Implementation for starting a sandbox
 pub async fn sandbox_start_impl(
     state: AppState,
@@ -350,6 +617,13 @@ pub async fn sandbox_start_impl(
     validate_sandbox_name(&params.sandbox)?;
     validate_namespace(&params.namespace)?;
 
+    // Report every invalid config field at once, before touching the namespace
+    if let Some(config) = &params.config {
+        config.validate().map_err(|errors| {
+            ServerError::ValidationError(crate::error::ValidationError::InvalidConfig(errors))
+        })?;
+    }
+
     let namespace_dir = state
         .get_config()
         .get_namespace_dir()
@@ -537,6 +811,30 @@ pub async fn sandbox_start_impl(
                 );
             }
 
+            if !config.dns_servers.is_empty() {
+                let dns_servers_array = config
+                    .dns_servers
+                    .iter()
+                    .map(|d| serde_yaml::Value::String(d.clone()))
+                    .collect::<Vec<_>>();
+                sandbox_map.insert(
+                    serde_yaml::Value::String("dns_servers".to_string()),
+                    serde_yaml::Value::Sequence(dns_servers_array),
+                );
+            }
+
+            if !config.extra_hosts.is_empty() {
+                let extra_hosts_array = config
+                    .extra_hosts
+                    .iter()
+                    .map(|h| serde_yaml::Value::String(h.clone()))
+                    .collect::<Vec<_>>();
+                sandbox_map.insert(
+                    serde_yaml::Value::String("extra_hosts".to_string()),
+                    serde_yaml::Value::Sequence(extra_hosts_array),
+                );
+            }
+
             if !config.depends_on.is_empty() {
                 let depends_on_array = config
                     .depends_on
@@ -592,17 +890,6 @@ pub async fn sandbox_start_impl(
         }
     }
 
-    // Assign a port for this sandbox
-    let sandbox_key = format!("{}/{}", params.namespace, params.sandbox);
-    let port = {
-        let mut port_manager = state.get_port_manager().write().await;
-        port_manager.assign_port(&sandbox_key).await.map_err(|e| {
-            ServerError::InternalError(format!("Failed to assign portal port: {}", e))
-        })?
-    };
-
-    debug!("Assigned portal port {} to sandbox {}", port, sandbox_key);
-
     // Get the specific sandbox configuration
     let sandbox_config = sandboxes_map
         .get_mut(&serde_yaml::Value::String(sandbox.clone()))
@@ -617,6 +904,95 @@ pub async fn sandbox_start_impl(
             ))
         })?;
 
+    // Tell the portal which user to run code as. A restart without a config keeps the user
+    // chosen before, and sandboxes that never chose one run as the unprivileged default
+    let run_as_user = params.config.as_ref().and_then(|c| c.run_as_user.clone());
+    let run_as_user_prefix = format!("{}=", MSB_RUN_AS_USER_ENV_VAR);
+    let envs_key = serde_yaml::Value::String("envs".to_string());
+    if !sandbox_config.contains_key(&envs_key) {
+        sandbox_config.insert(
+            envs_key.clone(),
+            serde_yaml::Value::Sequence(serde_yaml::Sequence::new()),
+        );
+    }
+
+    if let Some(envs_seq) = sandbox_config
+        .get_mut(&envs_key)
+        .and_then(|envs| envs.as_sequence_mut())
+    {
+        let is_run_as_user = |e: &serde_yaml::Value| {
+            e.as_str()
+                .is_some_and(|s| s.starts_with(&run_as_user_prefix))
+        };
+        if run_as_user.is_some() || !envs_seq.iter().any(is_run_as_user) {
+            envs_seq.retain(|e| !is_run_as_user(e));
+            envs_seq.push(serde_yaml::Value::String(format!(
+                "{}{}",
+                run_as_user_prefix,
+                run_as_user.as_deref().unwrap_or(DEFAULT_SANDBOX_USER)
+            )));
+        }
+    }
+
+    // Mount the namespace's upload cache, so files uploaded to one of its sandboxes are not sent
+    // again to the others
+    let upload_cache_dir = namespace_dir.join(UPLOAD_CACHE_SUBDIR);
+    tokio_fs::create_dir_all(&upload_cache_dir)
+        .await
+        .map_err(|e| {
+            ServerError::InternalError(format!("Failed to create upload cache directory: {}", e))
+        })?;
+
+    let upload_cache_suffix = format!(":{}", DEFAULT_UPLOAD_CACHE_GUEST_PATH);
+    let upload_cache_mapping = format!("{}{}", UPLOAD_CACHE_SUBDIR, upload_cache_suffix);
+    let volumes_key = serde_yaml::Value::String("volumes".to_string());
+    if !sandbox_config.contains_key(&volumes_key) {
+        sandbox_config.insert(
+            volumes_key.clone(),
+            serde_yaml::Value::Sequence(serde_yaml::Sequence::new()),
+        );
+    }
+
+    if let Some(volumes_seq) = sandbox_config
+        .get_mut(&volumes_key)
+        .and_then(|volumes| volumes.as_sequence_mut())
+    {
+        volumes_seq.retain(|v| {
+            v.as_str()
+                .map(|s| !s.ends_with(&upload_cache_suffix))
+                .unwrap_or(true)
+        });
+        volumes_seq.push(serde_yaml::Value::String(upload_cache_mapping.clone()));
+
+        // Confine the volumes to the allowed host paths, whether they come from the request or
+        // from the existing config. The upload cache is mounted by the server itself
+        let session_config = state.get_session_manager().get_config();
+        for volume in volumes_seq.iter().filter_map(|v| v.as_str()) {
+            if volume == upload_cache_mapping {
+                continue;
+            }
+
+            session_config
+                .check_volume_allowed(volume, &namespace_dir)
+                .map_err(|e| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        e.to_string(),
+                    ))
+                })?;
+        }
+    }
+
+    // Assign a port for this sandbox
+    let sandbox_key = format!("{}/{}", params.namespace, params.sandbox);
+    let port = {
+        let mut port_manager = state.get_port_manager().write().await;
+        port_manager.assign_port(&sandbox_key).await.map_err(|e| {
+            ServerError::TransientError(format!("Failed to assign portal port: {}", e))
+        })?
+    };
+
+    debug!("Assigned portal port {} to sandbox {}", port, sandbox_key);
+
     // Add or update the portal port mapping
     let guest_port = DEFAULT_PORTAL_GUEST_PORT;
     let portal_port_mapping = format!("{}:{}", port, guest_port);
@@ -642,25 +1018,52 @@ pub async fn sandbox_start_impl(
         sandbox_config.insert(ports_key, serde_yaml::Value::Sequence(ports_seq));
     }
 
-    // Write the updated config back to the file
-    let updated_config = serde_yaml::to_string(&config_yaml)
-        .map_err(|e| ServerError::InternalError(format!("Failed to serialize config: {}", e)))?;
+    // Images given by reference are pulled before the sandbox starts, so their progress is logged
+    let image_reference = sandbox_config
+        .get(serde_yaml::Value::String("image".to_string()))
+        .and_then(|image| image.as_str())
+        .and_then(|image| image.parse::<Reference>().ok());
+
+    // Write the updated config back to the file and start the sandbox, releasing the port again
+    // if the sandbox could not be started
+    let started = async {
+        let updated_config = serde_yaml::to_string(&config_yaml).map_err(|e| {
+            ServerError::InternalError(format!("Failed to serialize config: {}", e))
+        })?;
 
-    tokio_fs::write(&config_path, updated_config)
+        tokio_fs::write(&config_path, updated_config)
+            .await
+            .map_err(|e| {
+                ServerError::InternalError(format!("Failed to write config file: {}", e))
+            })?;
+
+        if let Some(reference) = image_reference {
+            image::prefetch([reference], Some(&log_pull_progress))
+                .await
+                .map_err(|e| start_error(&params.sandbox, e))?;
+        }
+
+        orchestra::up(
+            vec![sandbox.clone()],
+            Some(&namespace_dir),
+            Some(config_file),
+            true,
+        )
         .await
-        .map_err(|e| ServerError::InternalError(format!("Failed to write config file: {}", e)))?;
+        .map_err(|e| start_error(&params.sandbox, e))
+    }
+    .await;
 
-    // Start the sandbox
-    orchestra::up(
-        vec![sandbox.clone()],
-        Some(&namespace_dir),
-        Some(config_file),
-        true,
-    )
-    .await
-    .map_err(|e| {
-        ServerError::InternalError(format!("Failed to start sandbox {}: {}", params.sandbox, e))
-    })?;
+    if let Err(e) = started {
+        let mut port_manager = state.get_port_manager().write().await;
+        if let Err(release_error) = port_manager.release_port(&sandbox_key).await {
+            warn!(
+                "Failed to release portal port of sandbox {}: {}",
+                sandbox_key, release_error
+            );
+        }
+        return Err(e);
+    }
 
     // Determine if this is a first-time image pull based on config
     let potentially_first_time_pull = if let Some(config) = &params.config {
@@ -812,6 +1215,48 @@ pub async fn sandbox_stop_impl(state: AppState, params: SandboxStopParams) -> Se
     Ok(format!("Sandbox {} stopped successfully", params.sandbox))
 }
 
+/// Implementation for describing the resolved configuration of a sandbox
+pub async fn sandbox_describe_impl(
+    state: AppState,
+    params: SandboxDescribeParams,
+) -> ServerResult<ResolvedSandbox> {
+    // Validate sandbox name and namespace
+    validate_sandbox_name(&params.sandbox)?;
+    validate_namespace(&params.namespace)?;
+
+    let namespace_dir = state
+        .get_config()
+        .get_namespace_dir()
+        .join(&params.namespace);
+
+    // Verify that the namespace directory exists
+    if !namespace_dir.exists() {
+        return Err(ServerError::ValidationError(
+            crate::error::ValidationError::InvalidInput(format!(
+                "Namespace directory '{}' does not exist",
+                params.namespace
+            )),
+        ));
+    }
+
+    sandbox::describe(
+        &params.sandbox,
+        Some(&namespace_dir),
+        Some(MICROSANDBOX_CONFIG_FILENAME),
+    )
+    .await
+    .map_err(|e| match e {
+        MicrosandboxError::ResolvedConfigNotFound(_) => ServerError::NotFound(format!(
+            "No resolved config for sandbox {}: {}",
+            params.sandbox, e
+        )),
+        e => ServerError::InternalError(format!(
+            "Failed to describe sandbox {}: {}",
+            params.sandbox, e
+        )),
+    })
+}
+
 /// Implementation for sandbox metrics
 pub async fn sandbox_get_metrics_impl(
     state: AppState,
@@ -933,8 +1378,15 @@ pub async fn sandbox_get_metrics_impl(
         }
     }
 
+    let usage = state
+        .get_session_manager()
+        .get_usage(&params.namespace)
+        .map_err(|e| ServerError::InternalError(format!("Failed to get usage: {}", e)))?
+        .namespaces;
+
     Ok(SandboxStatusResponse {
         sandboxes: all_statuses,
+        usage,
     })
 }
 
@@ -987,6 +1439,69 @@ pub async fn proxy_fallback() -> ServerResult<impl IntoResponse> {
 // Functions: Helpers
 //--------------------------------------------------------------------------------------------------
 
+/// Checks whether a sandbox failed to start in a way that is likely to succeed when retried
+///
+/// Starting can fail on I/O, database and network errors, or while another session is still
+/// extracting the same image. Invalid configs and images, images over the size limits or without
+/// a manifest for the platform, and a full disk fail the same way again.
+pub(crate) fn is_transient_start_error(error: &MicrosandboxError) -> bool {
+    matches!(
+        error,
+        MicrosandboxError::Io(_)
+            | MicrosandboxError::Database(_)
+            | MicrosandboxError::HttpRequest(_)
+            | MicrosandboxError::HttpMiddleware(_)
+            | MicrosandboxError::ImageLayerDownloadFailed(_)
+            | MicrosandboxError::LayerExtraction(_)
+            | MicrosandboxError::LayerHandling { .. }
+            | MicrosandboxError::RootfsNotFound(_)
+            | MicrosandboxError::RootFsPathNotFound(_)
+            | MicrosandboxError::JoinError(_)
+    )
+}
+
+/// Converts an error starting a sandbox, or pulling its image, into a server error
+fn start_error(sandbox: &str, error: MicrosandboxError) -> ServerError {
+    match error {
+        MicrosandboxError::ImageNotFound { image, registries } => {
+            ServerError::ImageNotFound { image, registries }
+        }
+        e @ MicrosandboxError::UnsupportedRegistry { .. } => ServerError::ValidationError(
+            crate::error::ValidationError::InvalidInput(e.to_string()),
+        ),
+        e if is_transient_start_error(&e) => {
+            ServerError::TransientError(format!("Failed to start sandbox {}: {}", sandbox, e))
+        }
+        e => ServerError::InternalError(format!("Failed to start sandbox {}: {}", sandbox, e)),
+    }
+}
+
+/// Creates the progress callback for images the server pulls, which logs the pull's layers
+///
+/// A line is logged once the layers of the image are known and whenever another layer is done,
+/// rather than for every chunk of bytes downloaded.
+pub(crate) fn log_pull_progress(reference: &Reference) -> PullProgressCallback {
+    let reference = reference.to_string();
+    let logged_layers_done = AtomicUsize::new(usize::MAX);
+    Box::new(move |progress| {
+        if progress.layers_total == 0
+            || logged_layers_done.swap(progress.layers_done, Ordering::Relaxed)
+                == progress.layers_done
+        {
+            return;
+        }
+
+        info!(
+            "Pulling image {}: {}/{} layers, {}/{} bytes",
+            reference,
+            progress.layers_done,
+            progress.layers_total,
+            progress.bytes_downloaded,
+            progress.total_bytes
+        );
+    })
+}
+
 /// Validates a sandbox name
 fn validate_sandbox_name(name: &str) -> ServerResult<()> {
     // Check name length
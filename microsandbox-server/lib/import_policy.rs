@@ -0,0 +1,282 @@
+//! Import policy for code executed through the microsandbox server.
+//!
+//! This module handles:
+//! - Finding the modules Python and Node.js code imports, by looking at its import statements
+//! - Rejecting code that imports a denied module, or one outside the allowed modules
+//!
+//! The check is a cheap first filter for obviously unwanted snippets, not a security boundary.
+//! It only sees imports written out literally, so code can get around it with `exec`, `eval`,
+//! computed module names or a module that re-exports another one. Isolation of untrusted code
+//! comes from the sandbox itself.
+//!
+//! Modules are matched by their top-level name, so denying `os` also rejects `import os.path`,
+//! and a `node:` prefix is ignored, so denying `child_process` also rejects
+//! `require("node:child_process")`. Relative imports are never rejected.
+
+use std::collections::BTreeSet;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Modules that executed code may or may not import
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportPolicy {
+    /// Modules code may import, if restricted
+    allowed: Option<BTreeSet<String>>,
+
+    /// Modules code may not import, which wins over the allowed modules
+    denied: BTreeSet<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ImportPolicy {
+    /// Create a policy from the allowed modules, if restricted, and the denied modules
+    pub fn new<S: AsRef<str>>(
+        allowed: Option<impl IntoIterator<Item = S>>,
+        denied: impl IntoIterator<Item = S>,
+    ) -> Self {
+        Self {
+            allowed: allowed.map(module_set),
+            denied: module_set(denied),
+        }
+    }
+
+    /// Check whether the policy restricts imports at all
+    pub fn is_enabled(&self) -> bool {
+        self.allowed.is_some() || !self.denied.is_empty()
+    }
+
+    /// Check whether a top-level module may be imported
+    pub fn is_allowed(&self, module: &str) -> bool {
+        !self.denied.contains(module)
+            && self
+                .allowed
+                .as_ref()
+                .is_none_or(|allowed| allowed.contains(module))
+    }
+
+    /// Get the modules code in the given language imports against the policy, in order
+    pub fn disallowed_imports(&self, code: &str, language: &str) -> Vec<String> {
+        if !self.is_enabled() {
+            return Vec::new();
+        }
+
+        let mut disallowed = Vec::new();
+        for module in imported_modules(code, language) {
+            if !self.is_allowed(&module) && !disallowed.contains(&module) {
+                disallowed.push(module);
+            }
+        }
+
+        disallowed
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Collect module names, skipping empty ones
+fn module_set<S: AsRef<str>>(modules: impl IntoIterator<Item = S>) -> BTreeSet<String> {
+    modules
+        .into_iter()
+        .map(|m| m.as_ref().trim().to_string())
+        .filter(|m| !m.is_empty())
+        .collect()
+}
+
+/// Get the top-level modules code imports, for `python` and `node` code
+///
+/// Code in other languages imports nothing as far as the policy is concerned.
+pub fn imported_modules(code: &str, language: &str) -> Vec<String> {
+    match language {
+        "python" => python_imports(code),
+        "node" | "nodejs" | "javascript" => node_imports(code),
+        _ => Vec::new(),
+    }
+}
+
+/// Find `import a.b, c`, `from a.b import c` and `__import__("a")` style imports
+fn python_imports(code: &str) -> Vec<String> {
+    let mut modules = Vec::new();
+
+    for statement in code.lines().flat_map(|line| line.split(';')).map(str::trim) {
+        if let Some(names) = statement.strip_prefix("import ") {
+            for name in names.split(',') {
+                let name = name.split_whitespace().next().unwrap_or_default();
+                modules.push(top_level(name, '.'));
+            }
+        } else if let Some(rest) = statement.strip_prefix("from ") {
+            let name = rest.split_whitespace().next().unwrap_or_default();
+            if !name.starts_with('.') {
+                modules.push(top_level(name, '.'));
+            }
+        }
+    }
+
+    for call in ["__import__(", "import_module("] {
+        modules.extend(
+            string_arguments(code, call)
+                .into_iter()
+                .filter(|name| !name.starts_with('.'))
+                .map(|name| top_level(&name, '.')),
+        );
+    }
+
+    modules.retain(|m| !m.is_empty());
+    modules
+}
+
+/// Find `require("a")`, `import("a")`, `import x from "a"` and `import "a"` style imports
+fn node_imports(code: &str) -> Vec<String> {
+    let mut specifiers = Vec::new();
+    for call in ["require(", "import("] {
+        specifiers.extend(string_arguments(code, call));
+    }
+
+    for statement in code.lines().flat_map(|line| line.split(';')).map(str::trim) {
+        if !(statement.starts_with("import ") || statement.starts_with("export ")) {
+            continue;
+        }
+        let source = match statement.rfind(" from ") {
+            Some(idx) => &statement[idx + " from ".len()..],
+            None if statement.starts_with("import ") => &statement["import ".len()..],
+            None => continue,
+        };
+        if let Some(specifier) = leading_string(source.trim()) {
+            specifiers.push(specifier);
+        }
+    }
+
+    specifiers
+        .into_iter()
+        .filter(|s| !s.starts_with('.') && !s.starts_with('/'))
+        .map(|s| {
+            let s = s.strip_prefix("node:").unwrap_or(&s);
+            match s.strip_prefix('@') {
+                // Scoped packages are named by their scope and name, e.g. `@scope/name`
+                Some(scoped) => {
+                    let mut parts = scoped.splitn(3, '/');
+                    match (parts.next(), parts.next()) {
+                        (Some(scope), Some(name)) => format!("@{}/{}", scope, name),
+                        _ => s.to_string(),
+                    }
+                }
+                None => top_level(s, '/'),
+            }
+        })
+        .filter(|m| !m.is_empty())
+        .collect()
+}
+
+/// Get the string literals passed as the first argument of every call to a function
+fn string_arguments(code: &str, call: &str) -> Vec<String> {
+    code.match_indices(call)
+        .filter(|(idx, _)| {
+            // Only whole function names, so `my_require(` is not taken for `require(`
+            code[..*idx]
+                .chars()
+                .next_back()
+                .is_none_or(|c| !c.is_alphanumeric() && c != '_' && c != '$')
+        })
+        .filter_map(|(idx, _)| leading_string(code[idx + call.len()..].trim_start()))
+        .collect()
+}
+
+/// Get the content of a quoted string at the start of the text
+fn leading_string(text: &str) -> Option<String> {
+    let quote = text
+        .chars()
+        .next()
+        .filter(|c| matches!(c, '"' | '\'' | '`'))?;
+    let rest = &text[1..];
+    let end = rest.find(quote)?;
+    Some(rest[..end].to_string())
+}
+
+/// Get the first component of a module path
+fn top_level(name: &str, separator: char) -> String {
+    name.split(separator)
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_python_imports() {
+        let code = "import os, sys as system\n\
+                    from os.path import join\n\
+                    from . import sibling\n\
+                    def f():\n    import subprocess; import json\n\
+                    mod = __import__('shutil')\n\
+                    importlib.import_module(\"socket\")\n\
+                    print('import nothing')";
+        assert_eq!(
+            imported_modules(code, "python"),
+            vec!["os", "sys", "os", "subprocess", "json", "shutil", "socket"]
+        );
+    }
+
+    #[test]
+    fn test_node_imports() {
+        let code = "const cp = require('child_process');\n\
+                    const fs = require(\"node:fs/promises\");\n\
+                    import lodash from 'lodash/fp';\n\
+                    import { a } from \"@scope/pkg/sub\";\n\
+                    import './side-effect.js';\n\
+                    export { b } from 'net';\n\
+                    const m = await import(`http`);\n\
+                    my_require('os');";
+        assert_eq!(
+            imported_modules(code, "node"),
+            vec!["child_process", "fs", "http", "lodash", "@scope/pkg", "net"]
+        );
+    }
+
+    #[test]
+    fn test_import_policy() {
+        let policy = ImportPolicy::default();
+        assert!(!policy.is_enabled());
+        assert!(policy.disallowed_imports("import os", "python").is_empty());
+
+        let policy = ImportPolicy::new(None::<Vec<&str>>, ["os", "subprocess", "child_process"]);
+        assert!(policy.is_enabled());
+        assert_eq!(
+            policy.disallowed_imports(
+                "import os.path\nimport json\nfrom os import system",
+                "python"
+            ),
+            vec!["os"]
+        );
+        assert_eq!(
+            policy.disallowed_imports("require('node:child_process')", "node"),
+            vec!["child_process"]
+        );
+        assert!(policy
+            .disallowed_imports("import json", "python")
+            .is_empty());
+
+        // Denied modules win over allowed ones
+        let policy = ImportPolicy::new(Some(["json", "math", "os"]), ["os"]);
+        assert_eq!(
+            policy.disallowed_imports("import json, math, os, socket", "python"),
+            vec!["os", "socket"]
+        );
+        assert!(policy
+            .disallowed_imports("from .local import x", "python")
+            .is_empty());
+    }
+}
@@ -9,6 +9,7 @@
 pub mod config;
 pub mod error;
 pub mod handler;
+pub mod import_policy;
 pub mod management;
 pub mod mcp;
 #[cfg(test)]
@@ -18,6 +19,8 @@ mod simplified_mcp_integration_tests;
 pub mod middleware;
 pub mod payload;
 pub mod port;
+pub mod rate_limit;
+pub mod redact;
 pub mod route;
 pub mod simplified_mcp;
 pub mod state;
@@ -25,10 +28,13 @@ pub mod state;
 pub use config::*;
 pub use error::*;
 pub use handler::*;
+pub use import_policy::*;
 pub use management::*;
 pub use mcp::*;
 pub use middleware::*;
 pub use payload::*;
+pub use rate_limit::*;
+pub use redact::*;
 pub use route::*;
 pub use simplified_mcp::*;
 pub use state::*;
@@ -19,14 +19,18 @@ use jsonwebtoken::{EncodingKey, Header};
 #[cfg(feature = "cli")]
 use microsandbox_utils::term;
 use microsandbox_utils::{
-    env, DEFAULT_MSBSERVER_EXE_PATH, MSBSERVER_EXE_ENV_VAR, NAMESPACES_SUBDIR, SERVER_KEY_FILE,
-    SERVER_PID_FILE,
+    env, DEFAULT_MSBSERVER_EXE_PATH, DEFAULT_SERVER_HOST, DEFAULT_SERVER_PORT,
+    MSBSERVER_EXE_ENV_VAR, NAMESPACES_SUBDIR, SERVER_KEY_FILE, SERVER_PID_FILE,
 };
 use rand::{distr::Alphanumeric, Rng};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use tokio::{fs, process::Command};
 
-use crate::{MicrosandboxServerError, MicrosandboxServerResult};
+use crate::{
+    payload::{JsonRpcRequest, JsonRpcResponse, ServerMaintenanceResponse},
+    MicrosandboxServerError, MicrosandboxServerResult,
+};
 
 //--------------------------------------------------------------------------------------------------
 // Constants
@@ -47,12 +51,15 @@ const STOP_SERVER_MSG: &str = "Stop sandbox server";
 #[cfg(feature = "cli")]
 const KEYGEN_MSG: &str = "Generate new API key";
 
+/// How long the key used for admin requests to the running server stays valid
+const ADMIN_KEY_EXPIRY: Duration = Duration::minutes(1);
+
 //--------------------------------------------------------------------------------------------------
 // Types
 //--------------------------------------------------------------------------------------------------
 
 /// Claims for the JWT token
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     /// Expiration time
     pub exp: u64,
@@ -456,22 +463,12 @@ pub async fn keygen(
         namespace,
     };
 
-    // Encode the token
-    let jwt_token = jsonwebtoken::encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(server_key.as_bytes()),
-    )
-    .map_err(|e| {
+    // Encode the token in our custom API key format
+    let custom_token = encode_api_key(&server_key, &claims).inspect_err(|_| {
         #[cfg(feature = "cli")]
         term::finish_with_error(&keygen_sp);
-
-        MicrosandboxServerError::KeyGenError(format!("Failed to generate token: {}", e))
     })?;
 
-    // Convert the JWT token to our custom API key format
-    let custom_token = convert_jwt_to_api_key(&jwt_token)?;
-
     // Store the token information for output
     let token_str = custom_token.clone();
     let expiry_str = expiry.to_rfc3339();
@@ -495,6 +492,110 @@ pub async fn keygen(
     Ok(token_str)
 }
 
+/// Read or change the maintenance mode of the running server
+///
+/// Turns maintenance mode on or off if `enabled` is given. While it is on, the server rejects
+/// new sessions and reports not-ready, but existing sessions keep working.
+///
+/// The server is reached at `host:port`, which default to the address `msb server start` uses.
+/// Requests are authenticated with a short-lived key for all namespaces signed with the server
+/// key, so this has to run on the server's host unless the server is in dev mode.
+pub async fn maintenance(
+    enabled: Option<bool>,
+    host: Option<String>,
+    port: Option<u16>,
+) -> MicrosandboxServerResult<ServerMaintenanceResponse> {
+    let result = match enabled {
+        Some(enabled) => {
+            admin_request(
+                "server.maintenance.set",
+                json!({ "enabled": enabled }),
+                host,
+                port,
+            )
+            .await?
+        }
+        None => admin_request("server.maintenance.get", json!({}), host, port).await?,
+    };
+
+    serde_json::from_value(result).map_err(|e| {
+        MicrosandboxServerError::AdminRequestError(format!("invalid response from server: {}", e))
+    })
+}
+
+/// Dump the internal state of the running server, for debugging
+///
+/// The dump covers the server and session configuration, the tracked sessions, the sandbox
+/// port assignments and the execution queue, with secrets masked. Like [`maintenance`], it has
+/// to run on the server's host unless the server is in dev mode.
+pub async fn dump_state(
+    host: Option<String>,
+    port: Option<u16>,
+) -> MicrosandboxServerResult<serde_json::Value> {
+    admin_request("server.state.dump", json!({}), host, port).await
+}
+
+/// Call an admin method of the running server and return its result
+///
+/// The request is authenticated with a short-lived key for all namespaces signed with the
+/// server key, if there is one.
+async fn admin_request(
+    method: &str,
+    params: serde_json::Value,
+    host: Option<String>,
+    port: Option<u16>,
+) -> MicrosandboxServerResult<serde_json::Value> {
+    let host = host.unwrap_or_else(|| DEFAULT_SERVER_HOST.to_string());
+    let port = port.unwrap_or(DEFAULT_SERVER_PORT);
+    let rpc_url = format!("http://{}:{}/api/v1/rpc", host, port);
+
+    // Dev mode servers have no key and accept unauthenticated requests
+    let key_file_path = env::get_microsandbox_home_path().join(SERVER_KEY_FILE);
+    let api_key = if key_file_path.exists() {
+        let server_key = fs::read_to_string(&key_file_path).await?;
+        let now = Utc::now();
+        let claims = Claims {
+            exp: (now + ADMIN_KEY_EXPIRY).timestamp() as u64,
+            iat: now.timestamp() as u64,
+            namespace: "*".to_string(),
+        };
+        Some(encode_api_key(&server_key, &claims)?)
+    } else {
+        None
+    };
+
+    let request = JsonRpcRequest::new(method.to_string(), params, json!(1));
+    let mut builder = reqwest::Client::new().post(&rpc_url).json(&request);
+    if let Some(api_key) = api_key {
+        builder = builder.bearer_auth(api_key);
+    }
+
+    let response = builder.send().await.map_err(|e| {
+        MicrosandboxServerError::AdminRequestError(format!(
+            "failed to reach the server at {}: {}",
+            rpc_url, e
+        ))
+    })?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(MicrosandboxServerError::AdminRequestError(format!(
+            "server returned {}: {}",
+            status, body
+        )));
+    }
+
+    let response: JsonRpcResponse = response.json().await.map_err(|e| {
+        MicrosandboxServerError::AdminRequestError(format!("invalid response from server: {}", e))
+    })?;
+    if let Some(error) = response.error {
+        return Err(MicrosandboxServerError::AdminRequestError(error.message));
+    }
+
+    Ok(response.result.unwrap_or_default())
+}
+
 /// Clean up the PID file
 pub async fn clean(pid_file_path: &PathBuf) -> MicrosandboxServerResult<()> {
     // Clean up PID file
@@ -510,6 +611,20 @@ pub async fn clean(pid_file_path: &PathBuf) -> MicrosandboxServerResult<()> {
 // Functions: Helpers
 //--------------------------------------------------------------------------------------------------
 
+/// Sign claims with the server key and encode them in our custom API key format
+fn encode_api_key(server_key: &str, claims: &Claims) -> MicrosandboxServerResult<String> {
+    let jwt_token = jsonwebtoken::encode(
+        &Header::default(),
+        claims,
+        &EncodingKey::from_secret(server_key.as_bytes()),
+    )
+    .map_err(|e| {
+        MicrosandboxServerError::KeyGenError(format!("Failed to generate token: {}", e))
+    })?;
+
+    convert_jwt_to_api_key(&jwt_token)
+}
+
 /// Generate a random key for JWT token signing
 fn generate_random_key() -> String {
     rand::rng()
@@ -10,6 +10,7 @@
 //! - Integration with existing sandbox management functions
 
 use serde_json::json;
+use std::time::Duration;
 use tracing::debug;
 
 use crate::{
@@ -19,8 +20,10 @@ use crate::{
         ProcessedNotification,
     },
     simplified_mcp::{
-        ExecuteCodeRequest, ExecuteCommandRequest, GetSessionsRequest, GetVolumePathRequest,
-        StopSessionRequest, SimplifiedMcpError,
+        CancelReason, CreateSessionRequest, ExecuteCodeRequest, ExecuteCommandRequest, ExecuteOnceRequest,
+        ExecutionHistoryEntry, ExecutionKind, ExecutionValidation, GetExecutionHistoryRequest, GetQuotaRequest,
+        GetSessionsRequest, GetUsageRequest, GetVolumePathRequest, SandboxFlavor, SessionCaller,
+        SessionStatus, SimplifiedMcpError, StopSessionRequest, Termination, WaitForSessionRequest,
     },
     state::AppState,
     ServerResult,
@@ -37,6 +40,15 @@ const MCP_PROTOCOL_VERSION: &str = "2024-11-05";
 const SERVER_NAME: &str = "microsandbox-server";
 const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Client that reused sessions belong to when a request in affinity mode doesn't name one
+const DEFAULT_AFFINITY_CLIENT: &str = "anonymous";
+
+/// How long to wait for a session that is still being created to become ready
+const DEFAULT_SESSION_READY_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// JSON Schema dialect that the tool input schemas are written in
+const JSON_SCHEMA_DRAFT: &str = "http://json-schema.org/draft-07/schema#";
+
 //--------------------------------------------------------------------------------------------------
 // Helper Functions
 //--------------------------------------------------------------------------------------------------
@@ -77,8 +89,17 @@ fn convert_simplified_mcp_error(error: SimplifiedMcpError) -> ServerError {
         SimplifiedMcpError::SessionNotFound(_) => {
             ServerError::NotFound(detailed_message)
         }
+        SimplifiedMcpError::ImageNotFound { image, registries } => {
+            ServerError::ImageNotFound { image, registries }
+        }
+        SimplifiedMcpError::ServerInMaintenance => ServerError::Maintenance,
+        SimplifiedMcpError::SessionAccessDenied(_)
+        | SimplifiedMcpError::NamespaceAccessDenied(_) => ServerError::AuthorizationError(
+            crate::error::AuthorizationError::AccessDenied(detailed_message),
+        ),
         SimplifiedMcpError::UnsupportedLanguage(_) | 
         SimplifiedMcpError::InvalidFlavor(_) |
+        SimplifiedMcpError::PackageInstallFailed { .. } |
         SimplifiedMcpError::ValidationError(_) |
         SimplifiedMcpError::InvalidSessionState(_) => {
             ServerError::ValidationError(crate::error::ValidationError::InvalidInput(detailed_message))
@@ -118,7 +139,7 @@ fn create_enhanced_mcp_response(
 ) -> ServerResult<JsonRpcResponse> {
     match result {
         Ok(data) => {
-            let mcp_result = json!({
+            let mut mcp_result = json!({
                 "content": [
                     {
                         "type": "text",
@@ -127,6 +148,11 @@ fn create_enhanced_mcp_response(
                     }
                 ]
             });
+
+            // An execution that was cut short still returns its result, but did not succeed
+            if data.get("cancellation_reason").is_some_and(|reason| !reason.is_null()) {
+                mcp_result["isError"] = json!(true);
+            }
             Ok(JsonRpcResponse::success(mcp_result, request_id))
         }
         Err(error) => {
@@ -162,50 +188,16 @@ fn create_enhanced_mcp_response(
 }
 
 //--------------------------------------------------------------------------------------------------
-// Functions: Handlers
+// Functions: Tool Schemas
 //--------------------------------------------------------------------------------------------------
 
-/// Handle MCP initialize request
-pub async fn handle_mcp_initialize(
-    _state: AppState,
-    request: JsonRpcRequest,
-) -> ServerResult<JsonRpcResponse> {
-    debug!("Handling MCP initialize request");
-
-    // Extract protocolVersion from the request, or use a default if not present.
-    let protocol_version = request.params.as_object()
-        .and_then(|params| params.get("protocolVersion"))
-        .and_then(|v| v.as_str())
-        .unwrap_or(MCP_PROTOCOL_VERSION);
-
-    let result = json!({
-        "protocolVersion": protocol_version,
-        "capabilities": {
-            "toolCalling": {
-                "callTool": {},
-                "listTools": {}
-            }
-        },
-        "serverInfo": {
-            "name": SERVER_NAME,
-            "version": SERVER_VERSION
-        }
-    });
-
-    let response = JsonRpcResponse::success(result, request.id.clone());
-    debug!("MCP initialize response payload: {}", serde_json::to_string_pretty(&response).unwrap_or_default());
-
-    Ok(response)
-}
-
-/// Handle MCP list tools request
-pub async fn handle_mcp_list_tools(
-    _state: AppState,
-    request: JsonRpcRequest,
-) -> ServerResult<JsonRpcResponse> {
-    debug!("Handling MCP list tools request");
-
-    let tools = json!([
+/// Get the definitions of the MCP tools, as served by `tools/list`
+///
+/// Each tool has a name, a description and a JSON Schema of its arguments under `inputSchema`,
+/// which lets generic MCP hosts validate arguments before sending them and generate forms for
+/// them. The schemas are kept in sync with the tools' request structs by a test.
+pub fn tool_definitions() -> serde_json::Value {
+    json!([
         {
             "name": "execute_code",
             "description": "Execute code in a sandbox with automatic session management. Creates a new session if none specified or reuses existing session. Supports Python and Node.js templates.",
@@ -218,12 +210,54 @@ pub async fn handle_mcp_list_tools(
                     },
                     "template": {
                         "type": "string",
-                        "description": "Sandbox template/image to use. If not specified, uses the server's default template.",
+                        "description": "Sandbox template/image to use. If not specified, uses the server's default template, or one inferred from the code if the server has template detection enabled.",
                         "enum": ["python", "node"]
                     },
                     "session_id": {
                         "type": "string",
                         "description": "Optional session ID to use. If not specified, a new session is created."
+                    },
+                    "flavor": {
+                        "type": "string",
+                        "description": "Sandbox resource flavor of a new session. Defaults to small; auto picks one from the code with the server's flavor rules, e.g. large for code importing torch. The response reports the flavor used.",
+                        "enum": ["small", "medium", "large", "auto"]
+                    },
+                    "timeout": {
+                        "type": "integer",
+                        "description": "Execution timeout in seconds. If not specified, uses the session flavor's default (small: 30, medium: 120, large: 300).",
+                        "minimum": 1
+                    },
+                    "memory_mb": {
+                        "type": "integer",
+                        "description": "Not supported: sandboxes cannot limit the memory of a single execution, so requests that set it are rejected.",
+                        "minimum": 1
+                    },
+                    "seed": {
+                        "type": "integer",
+                        "description": "Seed for reproducible runs. Set as the PYTHONHASHSEED and RANDOM_SEED environment variables; Python code also gets random and numpy (if installed) seeded. Best-effort: PYTHONHASHSEED only affects newly started interpreters and other sources of nondeterminism are not controlled.",
+                        "minimum": 0,
+                        "maximum": u32::MAX
+                    },
+                    "reuse_session": {
+                        "type": "boolean",
+                        "description": "If no session_id is given, reuse an idle session previously created with reuse_session for the same client_id, template and flavor instead of creating a new one."
+                    },
+                    "client_id": {
+                        "type": "string",
+                        "description": "Identifies the client whose sessions may be reused when reuse_session is set. Clients without an ID share reusable sessions."
+                    },
+                    "wait_for_ready": {
+                        "type": "boolean",
+                        "description": "If the session is still being created, wait for it to become ready instead of failing. Defaults to false."
+                    },
+                    "priority": {
+                        "type": "string",
+                        "description": "Priority in the execution queue when all execution slots are taken, relative to other executions in the same namespace: high for interactive steps, low for batch jobs. Defaults to normal. Waiting executions move up a priority level every aging interval, so low priority ones still run.",
+                        "enum": ["low", "normal", "high"]
+                    },
+                    "validate_only": {
+                        "type": "boolean",
+                        "description": "Only check that the request would be admitted (template, flavor, session, resources, import policy, timeout and memory limit) and return the session_id (null for a new session), flavor, language and timeout it would get, without creating a session or executing anything. Defaults to false."
                     }
                 },
                 "required": ["code"]
@@ -231,24 +265,178 @@ pub async fn handle_mcp_list_tools(
         },
         {
             "name": "execute_command",
-            "description": "Execute a shell command in a sandbox. Each command runs in a new, separate session.",
+            "description": "Execute a shell command in a sandbox. Each command runs in a new, separate session unless a session_id is given.",
             "inputSchema": {
                 "type": "object",
                 "properties": {
                     "command": {
                         "type": "string",
                         "description": "Command to execute"
+                    },
+                    "args": {
+                        "type": "array",
+                        "description": "Arguments appended to the command, separated by spaces.",
+                        "items": { "type": "string" }
+                    },
+                    "template": {
+                        "type": "string",
+                        "description": "Sandbox template/image to use. If not specified, uses the server's default template.",
+                        "enum": ["python", "node"]
+                    },
+                    "session_id": {
+                        "type": "string",
+                        "description": "Optional session ID to run the command in. If not specified, a new session is created."
+                    },
+                    "flavor": {
+                        "type": "string",
+                        "description": "Sandbox resource flavor of a new session. Defaults to small; auto picks one from the command with the server's flavor rules, e.g. large for a command mentioning torch. The response reports the flavor used.",
+                        "enum": ["small", "medium", "large", "auto"]
+                    },
+                    "timeout": {
+                        "type": "integer",
+                        "description": "Execution timeout in seconds. If not specified, uses the session flavor's default (small: 30, medium: 120, large: 300).",
+                        "minimum": 1
+                    },
+                    "memory_mb": {
+                        "type": "integer",
+                        "description": "Not supported: sandboxes cannot limit the memory of a single execution, so requests that set it are rejected.",
+                        "minimum": 1
+                    },
+                    "seed": {
+                        "type": "integer",
+                        "description": "Seed for reproducible runs, set as the PYTHONHASHSEED and RANDOM_SEED environment variables of the command. Best-effort: other sources of nondeterminism are not controlled.",
+                        "minimum": 0,
+                        "maximum": u32::MAX
+                    },
+                    "reuse_session": {
+                        "type": "boolean",
+                        "description": "If no session_id is given, reuse an idle session previously created with reuse_session for the same client_id, template and flavor instead of creating a new one."
+                    },
+                    "client_id": {
+                        "type": "string",
+                        "description": "Identifies the client whose sessions may be reused when reuse_session is set. Clients without an ID share reusable sessions."
+                    },
+                    "wait_for_ready": {
+                        "type": "boolean",
+                        "description": "If the session is still being created, wait for it to become ready instead of failing. Defaults to false."
+                    },
+                    "priority": {
+                        "type": "string",
+                        "description": "Priority in the execution queue when all execution slots are taken, relative to other executions in the same namespace: high for interactive steps, low for batch jobs. Defaults to normal. Waiting executions move up a priority level every aging interval, so low priority ones still run.",
+                        "enum": ["low", "normal", "high"]
                     }
                 },
                 "required": ["command"]
             }
         },
+        {
+            "name": "execute_code_once",
+            "description": "Execute code in a throwaway sandbox that is created for this call and torn down before it returns, whether the execution succeeds, fails or times out. No session is left behind, so there is nothing to stop afterwards.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "code": {
+                        "type": "string",
+                        "description": "Code to execute"
+                    },
+                    "template": {
+                        "type": "string",
+                        "description": "Sandbox template/image to use. If not specified, uses the server's default template, or one inferred from the code if the server has template detection enabled.",
+                        "enum": ["python", "node"]
+                    },
+                    "flavor": {
+                        "type": "string",
+                        "description": "Sandbox resource flavor. Defaults to small; auto picks one from the code with the server's flavor rules, e.g. large for code importing torch. The response reports the flavor used.",
+                        "enum": ["small", "medium", "large", "auto"]
+                    },
+                    "timeout": {
+                        "type": "integer",
+                        "description": "Execution timeout in seconds. If not specified, uses the flavor's default (small: 30, medium: 120, large: 300).",
+                        "minimum": 1
+                    },
+                    "memory_mb": {
+                        "type": "integer",
+                        "description": "Not supported: sandboxes cannot limit the memory of a single execution, so requests that set it are rejected.",
+                        "minimum": 1
+                    },
+                    "seed": {
+                        "type": "integer",
+                        "description": "Seed for reproducible runs, with the same best-effort semantics as execute_code.",
+                        "minimum": 0,
+                        "maximum": u32::MAX
+                    },
+                    "priority": {
+                        "type": "string",
+                        "description": "Priority in the execution queue, as for execute_code. Defaults to normal.",
+                        "enum": ["low", "normal", "high"]
+                    }
+                },
+                "required": ["code"]
+            }
+        },
+        {
+            "name": "create_session",
+            "description": "Create a sandbox session ahead of its first execution. By default returns the session ID immediately while the sandbox boots in the background; use wait_for_session or get_sessions to find out when it is ready.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "template": {
+                        "type": "string",
+                        "description": "Sandbox template/image to use. If not specified, uses the server's default template.",
+                        "enum": ["python", "node"]
+                    },
+                    "flavor": {
+                        "type": "string",
+                        "description": "Sandbox resource flavor. Defaults to small; auto picks one from the packages with the server's flavor rules, e.g. large for torch.",
+                        "enum": ["small", "medium", "large", "auto"]
+                    },
+                    "wait": {
+                        "type": "boolean",
+                        "description": "Wait for the sandbox to start before returning. Defaults to false."
+                    },
+                    "packages": {
+                        "type": "array",
+                        "description": "Packages to install when the sandbox starts, with pip for the python template and npm for the node template (e.g. [\"requests==2.32.3\"]). Downloads are cached across sessions of the same template. A failed install fails the session with a package_install_failed error.",
+                        "items": { "type": "string" }
+                    },
+                    "preamble": {
+                        "type": "string",
+                        "description": "Code run before every execution in the session without showing in its output, e.g. \"import numpy as np\". Replaces the preamble the server configures for the template; an empty string disables it. Line numbers in errors still match the executed code."
+                    }
+                },
+                "required": []
+            }
+        },
+        {
+            "name": "wait_for_session",
+            "description": "Wait until a session that is being created is ready, then return it. Fails if the sandbox could not be started.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "Session ID to wait for"
+                    },
+                    "timeout": {
+                        "type": "integer",
+                        "description": "Maximum time to wait in seconds. Defaults to 120.",
+                        "minimum": 1
+                    }
+                },
+                "required": ["session_id"]
+            }
+        },
         {
             "name": "get_sessions",
             "description": "Get a list of active sandbox sessions.",
             "inputSchema": {
                 "type": "object",
-                "properties": {},
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "Optional session ID to get only that session"
+                    }
+                },
                 "required": []
             }
         },
@@ -269,13 +457,133 @@ pub async fn handle_mcp_list_tools(
         {
             "name": "get_volume_path",
             "description": "Get the host path of the shared volume.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "Optional session ID. The shared volume path is the same for all sessions."
+                    }
+                },
+                "required": []
+            }
+        },
+        {
+            "name": "get_execution_history",
+            "description": "Get the code and commands previously executed in a session, oldest first, with their truncated output and exit codes. Only the most recent executions are retained.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "Session ID to get the history for"
+                    },
+                    "offset": {
+                        "type": "integer",
+                        "description": "Number of entries to skip, counted from the oldest retained entry. Defaults to 0.",
+                        "minimum": 0
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of entries to return. Defaults to 20.",
+                        "minimum": 1
+                    }
+                },
+                "required": ["session_id"]
+            }
+        },
+        {
+            "name": "get_quota",
+            "description": "Get how many more sessions can be created before hitting resource limits: the session limit and current usage, available ports, and remaining host memory and CPU headroom.",
             "inputSchema": {
                 "type": "object",
                 "properties": {},
                 "required": []
             }
+        },
+        {
+            "name": "get_usage",
+            "description": "Get the resource-time accumulated by a namespace's executions, for chargeback: execution count, execution seconds, vCPU-seconds and memory-MB-seconds. Executions are charged for their session's full flavor while they run. Totals persist across server restarts; while the usage file can't be written they are kept in memory and written once it can.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "namespace": {
+                        "type": "string",
+                        "description": "Namespace to get the usage for, or \"*\" for all namespaces. API keys scoped to a namespace can only get their own"
+                    }
+                },
+                "required": ["namespace"]
+            }
         }
-    ]);
+    ])
+}
+
+/// Get the JSON Schema of the arguments of each MCP tool, by tool name
+///
+/// These are the `inputSchema`s of [`tool_definitions`], declared as JSON Schema draft 7 so they
+/// can be used outside of MCP as well.
+pub fn tool_input_schemas() -> serde_json::Map<String, serde_json::Value> {
+    let tools = match tool_definitions() {
+        serde_json::Value::Array(tools) => tools,
+        _ => Vec::new(),
+    };
+
+    tools
+        .into_iter()
+        .filter_map(|tool| {
+            let name = tool.get("name")?.as_str()?.to_string();
+            let mut schema = tool.get("inputSchema")?.clone();
+            schema["$schema"] = json!(JSON_SCHEMA_DRAFT);
+            Some((name, schema))
+        })
+        .collect()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Handlers
+//--------------------------------------------------------------------------------------------------
+
+/// Handle MCP initialize request
+pub async fn handle_mcp_initialize(
+    _state: AppState,
+    request: JsonRpcRequest,
+) -> ServerResult<JsonRpcResponse> {
+    debug!("Handling MCP initialize request");
+
+    // Extract protocolVersion from the request, or use a default if not present.
+    let protocol_version = request.params.as_object()
+        .and_then(|params| params.get("protocolVersion"))
+        .and_then(|v| v.as_str())
+        .unwrap_or(MCP_PROTOCOL_VERSION);
+
+    let result = json!({
+        "protocolVersion": protocol_version,
+        "capabilities": {
+            "toolCalling": {
+                "callTool": {},
+                "listTools": {}
+            }
+        },
+        "serverInfo": {
+            "name": SERVER_NAME,
+            "version": SERVER_VERSION
+        }
+    });
+
+    let response = JsonRpcResponse::success(result, request.id.clone());
+    debug!("MCP initialize response payload: {}", serde_json::to_string_pretty(&response).unwrap_or_default());
+
+    Ok(response)
+}
+
+/// Handle MCP list tools request
+pub async fn handle_mcp_list_tools(
+    _state: AppState,
+    request: JsonRpcRequest,
+) -> ServerResult<JsonRpcResponse> {
+    debug!("Handling MCP list tools request");
+
+    let tools = tool_definitions();
 
     if let Ok(tools_str) = serde_json::to_string_pretty(&tools) {
         debug!("MCP list_tools response payload:\n{}", tools_str);
@@ -289,8 +597,11 @@ pub async fn handle_mcp_list_tools(
 
 
 /// Handle MCP call tool request
+///
+/// Tools acting on sessions only use the sessions the caller owns.
 pub async fn handle_mcp_call_tool(
     state: AppState,
+    caller: &SessionCaller,
     request: JsonRpcRequest,
 ) -> ServerResult<JsonRpcResponse> {
     debug!("Handling MCP call tool request");
@@ -316,20 +627,38 @@ pub async fn handle_mcp_call_tool(
     // Handle simplified MCP tools directly
     match tool_name {
         "execute_code" => {
-            return handle_execute_code_tool(state, arguments.clone(), request.id.clone()).await;
+            return handle_execute_code_tool(state, caller, arguments.clone(), request.id.clone()).await;
         }
         "execute_command" => {
-            return handle_execute_command_tool(state, arguments.clone(), request.id.clone()).await;
+            return handle_execute_command_tool(state, caller, arguments.clone(), request.id.clone()).await;
+        }
+        "execute_code_once" => {
+            return handle_execute_code_once_tool(state, caller, arguments.clone(), request.id.clone()).await;
+        }
+        "create_session" => {
+            return handle_create_session_tool(state, caller, arguments.clone(), request.id.clone()).await;
+        }
+        "wait_for_session" => {
+            return handle_wait_for_session_tool(state, caller, arguments.clone(), request.id.clone()).await;
         }
         "get_sessions" => {
-            return handle_get_sessions_tool(state, arguments.clone(), request.id.clone()).await;
+            return handle_get_sessions_tool(state, caller, arguments.clone(), request.id.clone()).await;
         }
         "stop_session" => {
-            return handle_stop_session_tool(state, arguments.clone(), request.id.clone()).await;
+            return handle_stop_session_tool(state, caller, arguments.clone(), request.id.clone()).await;
         }
         "get_volume_path" => {
             return handle_get_volume_path_tool(state, arguments.clone(), request.id.clone()).await;
         }
+        "get_execution_history" => {
+            return handle_get_execution_history_tool(state, caller, arguments.clone(), request.id.clone()).await;
+        }
+        "get_quota" => {
+            return handle_get_quota_tool(state, arguments.clone(), request.id.clone()).await;
+        }
+        "get_usage" => {
+            return handle_get_usage_tool(state, caller, arguments.clone(), request.id.clone()).await;
+        }
         _ => {}
     }
 
@@ -359,6 +688,7 @@ pub async fn handle_mcp_notifications_initialized(
 /// Handle execute_code tool
 async fn handle_execute_code_tool(
     state: AppState,
+    caller: &SessionCaller,
     arguments: serde_json::Value,
     request_id: Option<serde_json::Value>,
 ) -> ServerResult<JsonRpcResponse> {
@@ -372,7 +702,7 @@ async fn handle_execute_code_tool(
     })?;
 
     // Execute the code and handle errors with user-friendly messages
-    let result = execute_code_with_error_handling(state, request).await;
+    let result = execute_code_with_error_handling(state, caller, request).await;
     
     // Create enhanced MCP response with structured error information
     create_enhanced_mcp_response(result, request_id)
@@ -381,24 +711,55 @@ async fn handle_execute_code_tool(
 /// Execute code with comprehensive error handling and classification
 async fn execute_code_with_error_handling(
     state: AppState,
+    caller: &SessionCaller,
     request: ExecuteCodeRequest,
 ) -> Result<serde_json::Value, SimplifiedMcpError> {
     // Get session manager from app state
     let session_manager = state.get_session_manager();
 
-    // Get template from request or use default from session manager config
-    let template = request.template.as_deref().unwrap_or_else(|| session_manager.get_default_template());
+    // Reject oversized code and a per-execution memory limit before creating a session
+    session_manager.get_config().check_code_size(&request.code)?;
+    reject_memory_limit(request.memory_mb)?;
+
+    // Get template from request, or infer it from the code when detection is enabled
+    let template = request.template.as_deref().unwrap_or_else(|| session_manager.template_for_code(&request.code));
 
     // Validate template early
     if !["python", "node"].contains(&template) {
         return Err(SimplifiedMcpError::UnsupportedLanguage(template.to_string()));
     }
 
-    // Get or create session
-    let flavor = request.flavor.unwrap_or_default();
-    let session_created = request.session_id.is_none();
-    let session = session_manager
-        .get_or_create_session(request.session_id, template, flavor)
+    // Stop after the admission checks when the client only wants to pre-flight the request
+    let flavor = session_manager.flavor_for(request.flavor, &request.code);
+    if request.validate_only.unwrap_or(false) {
+        return validate_execution(session_manager, caller, &request, template, flavor);
+    }
+
+    // Get or create session, reusing an idle one in affinity mode
+    let (session, session_created) = resolve_session(
+        session_manager,
+        caller,
+        request.session_id,
+        request
+            .reuse_session
+            .unwrap_or(false)
+            .then(|| request.client_id.as_deref().unwrap_or(DEFAULT_AFFINITY_CLIENT)),
+        request.wait_for_ready.unwrap_or(false),
+        template,
+        flavor,
+    )
+    .await?;
+
+    // Reject code importing modules outside the import policy before it runs
+    session_manager.get_config().check_imports(&request.code, &session.language)?;
+
+    // Use the explicit timeout if given, otherwise the session flavor's default
+    let exec_timeout = resolve_exec_timeout(request.timeout, session.flavor)?;
+
+    // Wait for an execution slot, queued fairly against other namespaces
+    let _permit = state
+        .get_execution_queue()
+        .acquire(&session.namespace, request.priority.unwrap_or_default())
         .await?;
 
     // Update session status to running
@@ -410,16 +771,52 @@ async fn execute_code_with_error_handling(
     let execution_result = {
         let execution_start = std::time::Instant::now();
         
+        // The portal applies the seed, if any, once execution is forwarded to the sandbox
+        if let Some(seed) = request.seed {
+            tracing::debug!("Executing in session {} with seed {}", session.id, seed);
+        }
+
+        // The portal likewise runs the session's preamble, if any, as a statement of its own
+        // before the code, so that the line numbers reported for the code are not shifted
+        if session.preamble.is_some() {
+            tracing::debug!("Executing in session {} with its preamble", session.id);
+        }
+
         // TODO: In a future task, this will integrate with actual sandbox creation and code execution
         // For now, we'll simulate the execution with enhanced error detection
-        let (stdout, stderr, exit_code) = simulate_code_execution_with_errors(&request.code, template);
+        let execution = async { simulate_code_execution_with_errors(&request.code, &session.language) };
+        let output = tokio::select! {
+            biased;
+            reason = session_manager.executions_cancelled() => Err(reason),
+            output = tokio::time::timeout(exec_timeout, execution) => output.map_err(|_| CancelReason::Timeout),
+        };
+        let (stdout, stderr, exit_code) = match output {
+            Ok(output) => output,
+            Err(reason) => {
+                let elapsed = execution_start.elapsed();
+                return cancelled_execution(session_manager, &session.id, session_created, session.flavor, elapsed, reason, None);
+            }
+        };
         
         let execution_time_ms = execution_start.elapsed().as_millis() as u64;
+
+        // Record the execution in the session history, including failed executions
+        let entry = ExecutionHistoryEntry::new(
+            ExecutionKind::Code,
+            &request.code,
+            &stdout,
+            &stderr,
+            exit_code,
+            execution_time_ms,
+        );
+        if let Err(e) = session_manager.record_execution(&session.id, entry) {
+            tracing::warn!("Failed to record execution history: {}", e);
+        }
         
         // Check for execution errors and classify them
         if !stderr.is_empty() || exit_code.map_or(false, |code| code != 0) {
-            // Classify the error based on output and template
-            let error = crate::simplified_mcp::classify_execution_error(&stdout, &stderr, exit_code, template);
+            // Classify the error based on output and language
+            let error = crate::simplified_mcp::classify_execution_error(&stdout, &stderr, exit_code, &session.language);
             
             // Update session status to error
             let error_msg = format!("Execution failed: {}", error);
@@ -451,8 +848,11 @@ async fn execute_code_with_error_handling(
         stdout: execution_result.0,
         stderr: execution_result.1,
         exit_code: execution_result.2,
+        termination: None,
         execution_time_ms: execution_result.3,
         session_created,
+        flavor: Some(session.flavor),
+        cancellation_reason: None,
     };
 
     Ok(serde_json::to_value(response).map_err(|e| {
@@ -460,9 +860,89 @@ async fn execute_code_with_error_handling(
     })?)
 }
 
+/// Handle execute_code_once tool
+async fn handle_execute_code_once_tool(
+    state: AppState,
+    caller: &SessionCaller,
+    arguments: serde_json::Value,
+    request_id: Option<serde_json::Value>,
+) -> ServerResult<JsonRpcResponse> {
+    debug!("Handling execute_code_once tool");
+
+    // Parse request
+    let request: ExecuteOnceRequest = serde_json::from_value(arguments).map_err(|e| {
+        ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+            format!("Invalid execute_code_once parameters: {}", e),
+        ))
+    })?;
+
+    // Execute the code in a throwaway session and handle errors with user-friendly messages
+    let result = execute_code_once_with_error_handling(state, caller, request).await;
+
+    // Create enhanced MCP response with structured error information
+    create_enhanced_mcp_response(result, request_id)
+}
+
+/// Execute code in a session that is torn down before returning
+///
+/// The session is torn down explicitly once the execution finishes, and by the
+/// `EphemeralSession` guard if the execution panics or the request is cancelled.
+async fn execute_code_once_with_error_handling(
+    state: AppState,
+    caller: &SessionCaller,
+    request: ExecuteOnceRequest,
+) -> Result<serde_json::Value, SimplifiedMcpError> {
+    let session_manager = state.get_session_manager();
+    session_manager.get_config().check_code_size(&request.code)?;
+    reject_memory_limit(request.memory_mb)?;
+
+    let template = request
+        .template
+        .unwrap_or_else(|| session_manager.template_for_code(&request.code).to_string());
+
+    let flavor = session_manager.flavor_for(request.flavor, &request.code);
+    let session = session_manager
+        .create_ephemeral_session(&template, flavor, caller.owner())
+        .await?;
+
+    let result = execute_code_with_error_handling(
+        state.clone(),
+        caller,
+        ExecuteCodeRequest {
+            code: request.code,
+            template: Some(template),
+            session_id: Some(session.session_id().to_string()),
+            flavor: Some(flavor.into()),
+            timeout: request.timeout,
+            memory_mb: request.memory_mb,
+            seed: request.seed,
+            reuse_session: None,
+            client_id: None,
+            wait_for_ready: None,
+            priority: request.priority,
+            validate_only: None,
+        },
+    )
+    .await;
+
+    // An execution error takes precedence over a failure to tear the session down
+    let teardown = session.teardown().await;
+    let mut response = result?;
+    teardown?;
+
+    // The session no longer exists, so don't hand out its ID
+    if let Some(fields) = response.as_object_mut() {
+        fields.remove("session_id");
+        fields.remove("session_created");
+    }
+
+    Ok(response)
+}
+
 /// Handle execute_command tool
 async fn handle_execute_command_tool(
     state: AppState,
+    caller: &SessionCaller,
     arguments: serde_json::Value,
     request_id: Option<serde_json::Value>,
 ) -> ServerResult<JsonRpcResponse> {
@@ -476,7 +956,7 @@ async fn handle_execute_command_tool(
     })?;
 
     // Execute the command and handle errors with user-friendly messages
-    let result = execute_command_with_error_handling(state, request).await;
+    let result = execute_command_with_error_handling(state, caller, request).await;
     
     // Create enhanced MCP response with structured error information
     create_enhanced_mcp_response(result, request_id)
@@ -485,11 +965,20 @@ async fn handle_execute_command_tool(
 /// Execute command with comprehensive error handling and classification
 async fn execute_command_with_error_handling(
     state: AppState,
+    caller: &SessionCaller,
     request: ExecuteCommandRequest,
 ) -> Result<serde_json::Value, SimplifiedMcpError> {
     // Get session manager from app state
     let session_manager = state.get_session_manager();
 
+    // Reject programs outside the command allowlist and oversized arguments before creating a
+    // session
+    session_manager.get_config().check_command_allowed(&request.command)?;
+    session_manager
+        .get_config()
+        .check_command_args(request.args.as_deref().unwrap_or_default())?;
+    reject_memory_limit(request.memory_mb)?;
+
     // Get template from request or use default from session manager config
     let template = request.template.as_deref().unwrap_or_else(|| session_manager.get_default_template());
 
@@ -498,11 +987,28 @@ async fn execute_command_with_error_handling(
         return Err(SimplifiedMcpError::UnsupportedLanguage(template.to_string()));
     }
 
-    let flavor = request.flavor.unwrap_or_default();
-    let session_created = request.session_id.is_none();
-    
-    let session = session_manager
-        .get_or_create_session(request.session_id, template, flavor)
+    let flavor = session_manager.flavor_for(request.flavor, &request.command);
+    let (session, session_created) = resolve_session(
+        session_manager,
+        caller,
+        request.session_id,
+        request
+            .reuse_session
+            .unwrap_or(false)
+            .then(|| request.client_id.as_deref().unwrap_or(DEFAULT_AFFINITY_CLIENT)),
+        request.wait_for_ready.unwrap_or(false),
+        template,
+        flavor,
+    )
+    .await?;
+
+    // Use the explicit timeout if given, otherwise the session flavor's default
+    let exec_timeout = resolve_exec_timeout(request.timeout, session.flavor)?;
+
+    // Wait for an execution slot, queued fairly against other namespaces
+    let _permit = state
+        .get_execution_queue()
+        .acquire(&session.namespace, request.priority.unwrap_or_default())
         .await?;
 
     // Update session status to running
@@ -521,11 +1027,42 @@ async fn execute_command_with_error_handling(
             request.command.clone()
         };
         
+        // The portal applies the seed, if any, once execution is forwarded to the sandbox
+        if let Some(seed) = request.seed {
+            tracing::debug!("Executing in session {} with seed {}", session.id, seed);
+        }
+
         // TODO: In a future task, this will integrate with actual sandbox command execution
         // For now, we'll simulate the execution with enhanced error detection
-        let (stdout, stderr, exit_code) = simulate_command_execution_with_errors(&full_command);
+        let execution = async { simulate_command_execution_with_errors(&full_command) };
+        let output = tokio::select! {
+            biased;
+            reason = session_manager.executions_cancelled() => Err(reason),
+            output = tokio::time::timeout(exec_timeout, execution) => output.map_err(|_| CancelReason::Timeout),
+        };
+        let (stdout, stderr, exit_code) = match output {
+            Ok(output) => output,
+            Err(reason) => {
+                let elapsed = execution_start.elapsed();
+                let termination = (reason == CancelReason::Timeout).then_some(Termination::Timeout);
+                return cancelled_execution(session_manager, &session.id, session_created, session.flavor, elapsed, reason, termination);
+            }
+        };
         
         let execution_time_ms = execution_start.elapsed().as_millis() as u64;
+
+        // Record the execution in the session history, including failed executions
+        let entry = ExecutionHistoryEntry::new(
+            ExecutionKind::Command,
+            &full_command,
+            &stdout,
+            &stderr,
+            Some(exit_code),
+            execution_time_ms,
+        );
+        if let Err(e) = session_manager.record_execution(&session.id, entry) {
+            tracing::warn!("Failed to record execution history: {}", e);
+        }
         
         // Check for execution errors and classify them
         if !stderr.is_empty() || exit_code != 0 {
@@ -557,13 +1094,17 @@ async fn execute_command_with_error_handling(
         .touch_session(&session.id)
         .map_err(|e| SimplifiedMcpError::InternalError(format!("Failed to touch session: {}", e)))?;
 
+    let termination = Termination::Exited(execution_result.2);
     let response = crate::simplified_mcp::ExecutionResponse {
         session_id: session.id,
         stdout: execution_result.0,
         stderr: execution_result.1,
         exit_code: Some(execution_result.2),
+        termination: Some(termination),
         execution_time_ms: execution_result.3,
         session_created,
+        flavor: Some(session.flavor),
+        cancellation_reason: CancelReason::from_termination(termination),
     };
 
     Ok(serde_json::to_value(response).map_err(|e| {
@@ -571,9 +1112,104 @@ async fn execute_command_with_error_handling(
     })?)
 }
 
+/// Handle create_session tool
+async fn handle_create_session_tool(
+    state: AppState,
+    caller: &SessionCaller,
+    arguments: serde_json::Value,
+    request_id: Option<serde_json::Value>,
+) -> ServerResult<JsonRpcResponse> {
+    debug!("Handling create_session tool");
+
+    // Parse request
+    let request: CreateSessionRequest = serde_json::from_value(arguments).map_err(|e| {
+        ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+            format!("Invalid create_session parameters: {}", e),
+        ))
+    })?;
+
+    // Get session manager from app state
+    let session_manager = state.get_session_manager().clone();
+    let template = request
+        .template
+        .unwrap_or_else(|| session_manager.get_default_template().to_string());
+    let packages = request.packages.unwrap_or_default();
+    let flavor = session_manager.flavor_for(request.flavor, &packages.join(" "));
+
+    let result = async {
+        // Reject an oversized preamble before creating the session
+        if let Some(preamble) = &request.preamble {
+            session_manager.get_config().check_code_size(preamble)?;
+        }
+
+        let session_id = if request.wait.unwrap_or(false) {
+            session_manager
+                .create_session_with_sandbox(state, &template, flavor, &packages, caller.owner())
+                .await?
+        } else {
+            session_manager
+                .create_session_with_sandbox_detached(
+                    state,
+                    &template,
+                    flavor,
+                    &packages,
+                    caller.owner(),
+                )
+                .await?
+        };
+
+        // Replace the template's preamble with the one the session was created with
+        if let Some(preamble) = &request.preamble {
+            session_manager.set_session_preamble(&session_id, preamble)?;
+        }
+
+        let session = session_manager.get_session(&session_id)?;
+        Ok(serde_json::to_value(session.to_summary()).unwrap_or_else(|_| json!({})))
+    }
+    .await;
+
+    // Create enhanced MCP response with structured error information
+    create_enhanced_mcp_response(result, request_id)
+}
+
+/// Handle wait_for_session tool
+async fn handle_wait_for_session_tool(
+    state: AppState,
+    caller: &SessionCaller,
+    arguments: serde_json::Value,
+    request_id: Option<serde_json::Value>,
+) -> ServerResult<JsonRpcResponse> {
+    debug!("Handling wait_for_session tool");
+
+    // Parse request
+    let request: WaitForSessionRequest = serde_json::from_value(arguments).map_err(|e| {
+        ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+            format!("Invalid wait_for_session parameters: {}", e),
+        ))
+    })?;
+
+    // Get session manager from app state
+    let session_manager = state.get_session_manager();
+    let timeout = request
+        .timeout
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_SESSION_READY_TIMEOUT);
+
+    let result = async {
+        session_manager.get_session_for(&request.session_id, caller)?;
+        session_manager.wait_until_ready(&request.session_id, timeout).await
+    }
+    .await
+    .map(|session| serde_json::to_value(session.to_summary()).unwrap_or_else(|_| json!({})));
+
+    // Create enhanced MCP response with structured error information
+    create_enhanced_mcp_response(result, request_id)
+}
+
 /// Handle get_sessions tool
 async fn handle_get_sessions_tool(
     state: AppState,
+    caller: &SessionCaller,
     arguments: serde_json::Value,
     request_id: Option<serde_json::Value>,
 ) -> ServerResult<JsonRpcResponse> {
@@ -589,11 +1225,19 @@ async fn handle_get_sessions_tool(
     // Get session manager from app state
     let session_manager = state.get_session_manager();
 
-    let result = session_manager
-        .get_sessions(request.session_id.as_deref())
+    let result = request
+        .session_id
+        .as_deref()
+        .map(|session_id| session_manager.get_session_for(session_id, caller))
+        .transpose()
+        .and_then(|_| session_manager.get_sessions(request.session_id.as_deref()))
         .map(|sessions| {
-            // Convert to summaries
-            let session_summaries: Vec<_> = sessions.iter().map(|s| s.to_summary()).collect();
+            // Convert to summaries, leaving out sessions of other namespaces
+            let session_summaries: Vec<_> = sessions
+                .iter()
+                .filter(|s| caller.can_access(s))
+                .map(|s| s.to_summary())
+                .collect();
             let response = crate::simplified_mcp::SessionListResponse {
                 sessions: session_summaries,
             };
@@ -607,6 +1251,7 @@ async fn handle_get_sessions_tool(
 /// Handle stop_session tool
 async fn handle_stop_session_tool(
     state: AppState,
+    caller: &SessionCaller,
     arguments: serde_json::Value,
     request_id: Option<serde_json::Value>,
 ) -> ServerResult<JsonRpcResponse> {
@@ -622,17 +1267,19 @@ async fn handle_stop_session_tool(
     // Get session manager from app state
     let session_manager = state.get_session_manager();
 
-    let result = session_manager
-        .stop_session(&request.session_id)
-        .await
-        .map(|_| {
-            let response = crate::simplified_mcp::StopSessionResponse {
-                session_id: request.session_id.clone(),
-                success: true,
-                message: Some("Session stopped successfully".to_string()),
-            };
-            serde_json::to_value(response).unwrap_or_else(|_| json!({}))
-        });
+    let result = async {
+        session_manager.get_session_for(&request.session_id, caller)?;
+        session_manager.stop_session(&request.session_id).await
+    }
+    .await
+    .map(|_| {
+        let response = crate::simplified_mcp::StopSessionResponse {
+            session_id: request.session_id.clone(),
+            success: true,
+            message: Some("Session stopped successfully".to_string()),
+        };
+        serde_json::to_value(response).unwrap_or_else(|_| json!({}))
+    });
 
     // Create enhanced MCP response with structured error information
     create_enhanced_mcp_response(result, request_id)
@@ -664,6 +1311,276 @@ async fn handle_get_volume_path_tool(
     create_enhanced_mcp_response(result, request_id)
 }
 
+/// Handle get_execution_history tool
+async fn handle_get_execution_history_tool(
+    state: AppState,
+    caller: &SessionCaller,
+    arguments: serde_json::Value,
+    request_id: Option<serde_json::Value>,
+) -> ServerResult<JsonRpcResponse> {
+    debug!("Handling get_execution_history tool");
+
+    // Parse request
+    let request: GetExecutionHistoryRequest = serde_json::from_value(arguments).map_err(|e| {
+        ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+            format!("Invalid get_execution_history parameters: {}", e),
+        ))
+    })?;
+
+    // Get session manager from app state
+    let session_manager = state.get_session_manager();
+
+    let result = session_manager
+        .get_session_for(&request.session_id, caller)
+        .and_then(|_| {
+            session_manager.get_execution_history(
+                &request.session_id,
+                request.offset.unwrap_or(0),
+                request.limit.unwrap_or(20),
+            )
+        })
+        .map(|history| serde_json::to_value(history).unwrap_or_else(|_| json!({})));
+
+    // Create enhanced MCP response with structured error information
+    create_enhanced_mcp_response(result, request_id)
+}
+
+/// Handle get_quota tool
+async fn handle_get_quota_tool(
+    state: AppState,
+    arguments: serde_json::Value,
+    request_id: Option<serde_json::Value>,
+) -> ServerResult<JsonRpcResponse> {
+    debug!("Handling get_quota tool");
+
+    // Parse request
+    let _request: GetQuotaRequest = serde_json::from_value(arguments).map_err(|e| {
+        ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+            format!("Invalid get_quota parameters: {}", e),
+        ))
+    })?;
+
+    // Get session manager from app state
+    let session_manager = state.get_session_manager();
+
+    let result = session_manager
+        .get_quota()
+        .map(|quota| serde_json::to_value(quota).unwrap_or_else(|_| json!({})));
+
+    // Create enhanced MCP response with structured error information
+    create_enhanced_mcp_response(result, request_id)
+}
+
+/// Handle get_usage tool
+async fn handle_get_usage_tool(
+    state: AppState,
+    caller: &SessionCaller,
+    arguments: serde_json::Value,
+    request_id: Option<serde_json::Value>,
+) -> ServerResult<JsonRpcResponse> {
+    debug!("Handling get_usage tool");
+
+    // Parse request
+    let request: GetUsageRequest = serde_json::from_value(arguments).map_err(|e| {
+        ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+            format!("Invalid get_usage parameters: {}", e),
+        ))
+    })?;
+
+    // Get session manager from app state
+    let session_manager = state.get_session_manager();
+
+    let result = session_manager
+        .get_usage_for(&request.namespace, caller)
+        .map(|usage| serde_json::to_value(usage).unwrap_or_else(|_| json!({})));
+
+    // Create enhanced MCP response with structured error information
+    create_enhanced_mcp_response(result, request_id)
+}
+
+/// Get the session to execute in and whether it was newly created
+///
+/// An explicit session ID always wins. Without one, affinity mode (`affinity_client` is set)
+/// reuses an idle session of the same client, template and flavor, and otherwise a new session
+/// is created. A session that is
+/// still being created is waited for if `wait_for_ready` is set, and rejected otherwise. New
+/// sessions belong to the caller's namespace, and existing ones must already.
+async fn resolve_session(
+    session_manager: &crate::simplified_mcp::SessionManager,
+    caller: &SessionCaller,
+    session_id: Option<String>,
+    affinity_client: Option<&str>,
+    wait_for_ready: bool,
+    template: &str,
+    flavor: SandboxFlavor,
+) -> Result<(crate::simplified_mcp::SessionInfo, bool), SimplifiedMcpError> {
+    let (session, created) = match (session_id, affinity_client) {
+        (None, Some(client_id)) => {
+            session_manager
+                .get_or_create_affine_session(caller, client_id, template, flavor)
+                .await?
+        }
+        (Some(session_id), _) => {
+            session_manager.get_session_for(&session_id, caller)?;
+            let session = session_manager
+                .get_or_create_session(Some(session_id), template, flavor)
+                .await?;
+            (session, false)
+        }
+        (None, None) => {
+            let session_id = session_manager
+                .create_owned_session(template, flavor, caller.owner())
+                .await?;
+            (session_manager.get_session(&session_id)?, true)
+        }
+    };
+
+    if session.status != SessionStatus::Creating {
+        return Ok((session, created));
+    }
+
+    if !wait_for_ready {
+        return Err(session_still_creating(&session.id));
+    }
+
+    let session = session_manager
+        .wait_until_ready(&session.id, DEFAULT_SESSION_READY_TIMEOUT)
+        .await?;
+    Ok((session, created))
+}
+
+/// Error for executing in a session that is still being created without waiting for it
+fn session_still_creating(session_id: &str) -> SimplifiedMcpError {
+    SimplifiedMcpError::InvalidSessionState(format!(
+        "Session {} is still being created. Retry once it is ready, or set wait_for_ready",
+        session_id
+    ))
+}
+
+/// Run the admission checks of an execute_code request without creating a session, waiting
+/// for one or executing anything, and report the session and flavor the execution would get
+fn validate_execution(
+    session_manager: &crate::simplified_mcp::SessionManager,
+    caller: &SessionCaller,
+    request: &ExecuteCodeRequest,
+    template: &str,
+    flavor: SandboxFlavor,
+) -> Result<serde_json::Value, SimplifiedMcpError> {
+    let session = match &request.session_id {
+        Some(session_id) => {
+            session_manager.get_session_for(session_id, caller)?;
+            Some(session_manager.get_usable_session(session_id, template)?)
+        }
+        None if request.reuse_session.unwrap_or(false) => session_manager.find_reusable_session(
+            caller,
+            request.client_id.as_deref().unwrap_or(DEFAULT_AFFINITY_CLIENT),
+            template,
+            flavor,
+        )?,
+        None => None,
+    };
+
+    match &session {
+        Some(session)
+            if session.status == SessionStatus::Creating
+                && !request.wait_for_ready.unwrap_or(false) =>
+        {
+            return Err(session_still_creating(&session.id));
+        }
+        Some(_) => {}
+        None => session_manager.check_session_admission(template, caller.owner())?,
+    }
+
+    // An existing session keeps the flavor and primary language it was created with
+    let flavor = session.as_ref().map_or(flavor, |session| session.flavor);
+    let language = session
+        .as_ref()
+        .map_or_else(|| template.to_string(), |session| session.language.clone());
+
+    session_manager.get_config().check_imports(&request.code, &language)?;
+    let exec_timeout = resolve_exec_timeout(request.timeout, flavor)?;
+
+    let validation = ExecutionValidation {
+        session_created: session.is_none(),
+        session_id: session.map(|session| session.id),
+        flavor,
+        language,
+        timeout_seconds: exec_timeout.as_secs(),
+    };
+
+    serde_json::to_value(validation).map_err(|e| {
+        SimplifiedMcpError::InternalError(format!("Failed to serialize response: {}", e))
+    })
+}
+
+/// Resolve the execution timeout from an explicit timeout in seconds or the flavor's default
+fn resolve_exec_timeout(
+    timeout_secs: Option<u64>,
+    flavor: SandboxFlavor,
+) -> Result<Duration, SimplifiedMcpError> {
+    match timeout_secs {
+        Some(0) => Err(SimplifiedMcpError::ValidationError(
+            "Timeout must be at least 1 second".to_string(),
+        )),
+        Some(secs) => Ok(Duration::from_secs(secs)),
+        None => Ok(flavor.default_exec_timeout()),
+    }
+}
+
+/// Reject a per-execution memory limit, which sandboxes cannot apply
+///
+/// Sandboxes do not run in a cgroup of their own, so there is no memory.max to lower for a single
+/// execution. Running without the requested limit would silently ignore it.
+fn reject_memory_limit(memory_mb: Option<u32>) -> Result<(), SimplifiedMcpError> {
+    match memory_mb {
+        Some(memory_mb) => Err(SimplifiedMcpError::ValidationError(format!(
+            "memory_mb is not supported: sandboxes cannot limit the memory of a single execution (requested {} MB)",
+            memory_mb
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Mark a session as errored after its execution was cut short, charge the time it ran, and
+/// build the result reporting why
+fn cancelled_execution(
+    session_manager: &crate::simplified_mcp::SessionManager,
+    session_id: &str,
+    session_created: bool,
+    flavor: SandboxFlavor,
+    elapsed: Duration,
+    reason: CancelReason,
+    termination: Option<crate::simplified_mcp::Termination>,
+) -> Result<serde_json::Value, SimplifiedMcpError> {
+    if let Err(e) = session_manager.update_session_status(
+        session_id,
+        crate::simplified_mcp::SessionStatus::Error(format!("Execution {}", reason)),
+    ) {
+        tracing::warn!("Failed to update session status to error: {}", e);
+    }
+
+    // The execution held the session until it was cut off, so it is still charged
+    if let Err(e) = session_manager.record_usage(session_id, elapsed) {
+        tracing::warn!("Failed to record usage of cancelled execution: {}", e);
+    }
+
+    let response = crate::simplified_mcp::ExecutionResponse {
+        session_id: session_id.to_string(),
+        stdout: String::new(),
+        stderr: String::new(),
+        exit_code: None,
+        termination,
+        execution_time_ms: elapsed.as_millis() as u64,
+        session_created,
+        flavor: Some(flavor),
+        cancellation_reason: Some(reason),
+    };
+
+    serde_json::to_value(response).map_err(|e| {
+        SimplifiedMcpError::InternalError(format!("Failed to serialize response: {}", e))
+    })
+}
+
 //--------------------------------------------------------------------------------------------------
 // Helper Functions for Simulation
 //--------------------------------------------------------------------------------------------------
@@ -814,6 +1731,7 @@ fn classify_command_execution_error(
 /// Handle MCP methods
 pub async fn handle_mcp_method(
     state: AppState,
+    caller: SessionCaller,
     request: JsonRpcRequest,
 ) -> ServerResult<JsonRpcResponseOrNotification> {
     match request.method.as_str() {
@@ -826,7 +1744,7 @@ pub async fn handle_mcp_method(
             Ok(JsonRpcResponseOrNotification::response(response))
         }
         "callTool" => {
-            let response = handle_mcp_call_tool(state, request).await?;
+            let response = handle_mcp_call_tool(state, &caller, request).await?;
             Ok(JsonRpcResponseOrNotification::response(response))
         }
         "notifications/initialized" => {
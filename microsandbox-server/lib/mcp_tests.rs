@@ -48,7 +48,7 @@ mod tests {
         
         assert_eq!(request.code, "print('Hello, World!')");
         assert_eq!(request.template, Some("python".to_string()));
-        assert_eq!(request.flavor, Some(SandboxFlavor::Small));
+        assert_eq!(request.flavor, Some(SandboxFlavor::Small.into()));
         assert_eq!(request.session_id, None);
     }
 
@@ -82,7 +82,7 @@ mod tests {
         assert_eq!(request.code, "x = 42\nprint(x)");
         assert_eq!(request.template, Some("python".to_string()));
         assert_eq!(request.session_id, Some("existing-session-123".to_string()));
-        assert_eq!(request.flavor, Some(SandboxFlavor::Medium));
+        assert_eq!(request.flavor, Some(SandboxFlavor::Medium.into()));
     }
 
     #[tokio::test]
@@ -101,7 +101,7 @@ mod tests {
         assert_eq!(request.command, "ls");
         assert_eq!(request.args, Some(vec!["-la".to_string(), "/tmp".to_string()]));
         assert_eq!(request.template, Some("python".to_string()));
-        assert_eq!(request.flavor, Some(SandboxFlavor::Small));
+        assert_eq!(request.flavor, Some(SandboxFlavor::Small.into()));
     }
 
     #[tokio::test]
@@ -287,8 +287,11 @@ mod tests {
             stdout: "Hello, World!\n".to_string(),
             stderr: "".to_string(),
             exit_code: Some(0),
+            termination: Some(Termination::Exited(0)),
             execution_time_ms: 250,
             session_created: true,
+            flavor: Some(SandboxFlavor::Small),
+            cancellation_reason: None,
         };
 
         // Test serialization
@@ -314,6 +317,7 @@ mod tests {
                 created_at: "just now".to_string(),
                 last_accessed: "just now".to_string(),
                 uptime_seconds: 0,
+                packages: Vec::new(),
             },
             SessionSummary {
                 id: "session-2".to_string(),
@@ -323,6 +327,7 @@ mod tests {
                 created_at: "2 minutes ago".to_string(),
                 last_accessed: "1 minute ago".to_string(),
                 uptime_seconds: 120,
+                packages: Vec::new(),
             },
         ];
 
@@ -482,7 +487,7 @@ mod tests {
         
         assert_eq!(_execute_request.session_id, Some(session_id.clone()));
         assert_eq!(_execute_request.template, Some("python".to_string()));
-        assert_eq!(_execute_request.flavor, Some(SandboxFlavor::Medium));
+        assert_eq!(_execute_request.flavor, Some(SandboxFlavor::Medium.into()));
 
         // Step 3: Execute command in same session
         let command_args = json!({
@@ -534,4 +539,444 @@ mod tests {
         assert!(result.is_err());
         assert!(matches!(result.unwrap_err(), SimplifiedMcpError::SessionNotFound(_)));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_tool_interface_execute_code_once_leaves_no_session() {
+        use crate::payload::JsonRpcRequest;
+
+        let state = create_test_app_state().await;
+        let request: JsonRpcRequest = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {
+                "name": "execute_code_once",
+                "arguments": {
+                    "code": "print('Hello, World!')",
+                    "template": "python"
+                }
+            },
+            "id": 1
+        }))
+        .unwrap();
+
+        let response = crate::mcp::handle_mcp_call_tool(state.clone(), &SessionCaller::default(), request).await.unwrap();
+        let response = serde_json::to_value(response).unwrap();
+        assert!(response.get("error").is_none());
+
+        // The throwaway session is gone once the tool returns
+        assert_eq!(state.get_session_manager().get_session_count().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_readiness_probe_fails_while_draining() {
+        use axum::{extract::State, http::StatusCode, response::IntoResponse};
+
+        let state = create_test_app_state().await;
+        assert!(state.get_readiness_problems().is_empty());
+        assert!(state.get_degradations().is_empty());
+
+        let response = crate::handler::readyz(State(state.clone())).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        state.start_draining();
+        assert!(state.is_draining());
+        assert_eq!(state.get_readiness_problems(), vec!["server is draining".to_string()]);
+
+        let response = crate::handler::readyz(State(state)).await.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let response = crate::handler::healthz().await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_execution_cancelled_by_shutdown_reports_reason() {
+        use crate::payload::JsonRpcRequest;
+
+        let state = create_test_app_state().await;
+        let session_manager = state.get_session_manager();
+        let session_id = session_manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+        session_manager.update_session_status(&session_id, SessionStatus::Ready).unwrap();
+        session_manager.cancel_executions(CancelReason::ServerShutdown);
+
+        let request: JsonRpcRequest = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {
+                "name": "execute_code",
+                "arguments": {
+                    "code": "print('Hello, World!')",
+                    "template": "python",
+                    "session_id": session_id
+                }
+            },
+            "id": 1
+        }))
+        .unwrap();
+        let response = crate::mcp::handle_mcp_call_tool(state.clone(), &SessionCaller::default(), request).await.unwrap();
+        let response = serde_json::to_value(response).unwrap();
+
+        // The execution is reported as failed, with a result saying why
+        assert_eq!(response["result"]["isError"], json!(true));
+        let text = response["result"]["content"][0]["text"].as_str().unwrap();
+        let result: ExecutionResponse = serde_json::from_str(text).unwrap();
+        assert_eq!(result.cancellation_reason, Some(CancelReason::ServerShutdown));
+        assert_eq!(result.session_id, session_id);
+    }
+
+    #[tokio::test]
+    async fn test_execute_code_rejects_memory_limit() {
+        use crate::payload::JsonRpcRequest;
+
+        let state = create_test_app_state().await;
+        let session_manager = state.get_session_manager();
+        let session_id = session_manager.create_session("python", SandboxFlavor::Medium).await.unwrap();
+        session_manager.update_session_status(&session_id, SessionStatus::Ready).unwrap();
+
+        let request: JsonRpcRequest = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {
+                "name": "execute_code",
+                "arguments": {
+                    "code": "print('Hello, World!')",
+                    "template": "python",
+                    "session_id": session_id,
+                    "memory_mb": 512
+                }
+            },
+            "id": 1
+        }))
+        .unwrap();
+
+        // Sandboxes cannot limit the memory of one execution, so the request is rejected
+        let response = crate::mcp::handle_mcp_call_tool(state.clone(), &SessionCaller::default(), request).await.unwrap();
+        let response = serde_json::to_value(response).unwrap();
+        assert_eq!(response["result"]["isError"], json!(true));
+        let text = response["result"]["content"][0]["text"].as_str().unwrap();
+        assert!(text.contains("memory_mb is not supported"), "{}", text);
+        assert_eq!(session_manager.get_session(&session_id).unwrap().status, SessionStatus::Ready);
+    }
+
+    #[tokio::test]
+    async fn test_execute_code_with_auto_flavor_reports_chosen_flavor() {
+        use crate::payload::JsonRpcRequest;
+
+        let state = create_test_app_state().await;
+        let request: JsonRpcRequest = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {
+                "name": "execute_code",
+                "arguments": {
+                    "code": "import torch\nprint('Hello, World!')",
+                    "template": "python",
+                    "flavor": "auto"
+                }
+            },
+            "id": 1
+        }))
+        .unwrap();
+        let response = crate::mcp::handle_mcp_call_tool(state.clone(), &SessionCaller::default(), request).await.unwrap();
+        let response = serde_json::to_value(response).unwrap();
+
+        let text = response["result"]["content"][0]["text"].as_str().unwrap();
+        let result: ExecutionResponse = serde_json::from_str(text).unwrap();
+        assert_eq!(result.flavor, Some(SandboxFlavor::Large));
+        let session = state.get_session_manager().get_session(&result.session_id).unwrap();
+        assert_eq!(session.flavor, SandboxFlavor::Large);
+    }
+
+    #[tokio::test]
+    async fn test_execute_code_validate_only_creates_nothing() {
+        use crate::payload::JsonRpcRequest;
+
+        let state = create_test_app_state().await;
+        let call = |arguments: serde_json::Value| {
+            let state = state.clone();
+            async move {
+                let request: JsonRpcRequest = serde_json::from_value(json!({
+                    "jsonrpc": "2.0",
+                    "method": "tools/call",
+                    "params": { "name": "execute_code", "arguments": arguments },
+                    "id": 1
+                }))
+                .unwrap();
+                let response = crate::mcp::handle_mcp_call_tool(state, &SessionCaller::default(), request).await.unwrap();
+                serde_json::to_value(response).unwrap()
+            }
+        };
+
+        // A request without a session reports the flavor a new session would get
+        let response = call(json!({
+            "code": "import torch",
+            "template": "python",
+            "flavor": "auto",
+            "validate_only": true
+        }))
+        .await;
+        let text = response["result"]["content"][0]["text"].as_str().unwrap();
+        let validation: ExecutionValidation = serde_json::from_str(text).unwrap();
+        assert_eq!(validation.session_id, None);
+        assert!(validation.session_created);
+        assert_eq!(validation.flavor, SandboxFlavor::Large);
+        assert_eq!(validation.language, "python");
+        assert_eq!(validation.timeout_seconds, 300);
+        assert_eq!(state.get_session_manager().get_session_count().unwrap(), 0);
+
+        // A request for an existing session reports that session and its flavor
+        let session_id = state
+            .get_session_manager()
+            .create_session("python", SandboxFlavor::Medium)
+            .await
+            .unwrap();
+        let response = call(json!({
+            "code": "print(1)",
+            "template": "python",
+            "session_id": session_id,
+            "timeout": 5,
+            "validate_only": true
+        }))
+        .await;
+        let text = response["result"]["content"][0]["text"].as_str().unwrap();
+        let validation: ExecutionValidation = serde_json::from_str(text).unwrap();
+        assert_eq!(validation.session_id.as_deref(), Some(session_id.as_str()));
+        assert!(!validation.session_created);
+        assert_eq!(validation.flavor, SandboxFlavor::Medium);
+        assert_eq!(validation.timeout_seconds, 5);
+        assert!(state.get_session_manager().get_session(&session_id).unwrap().history.is_empty());
+
+        // Failing checks are reported as they would be for the execution itself
+        let response = call(json!({
+            "code": "print(1)",
+            "template": "python",
+            "session_id": "session-missing",
+            "validate_only": true
+        }))
+        .await;
+        assert_eq!(response["result"]["isError"], json!(true));
+
+        state.get_session_manager().set_maintenance(true);
+        let response = call(json!({
+            "code": "print(1)",
+            "template": "python",
+            "validate_only": true
+        }))
+        .await;
+        assert!(serde_json::to_string(&response).unwrap().contains("server_maintenance"));
+        assert_eq!(state.get_session_manager().get_session_count().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_mode_rejects_new_sessions() {
+        use crate::payload::JsonRpcRequest;
+
+        let state = create_test_app_state().await;
+
+        let status = crate::handler::server_maintenance_impl(&state, Some(true)).unwrap();
+        assert!(status.maintenance);
+        assert_eq!(
+            state.get_readiness_problems(),
+            vec!["server is in maintenance mode".to_string()]
+        );
+
+        let request: JsonRpcRequest = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {
+                "name": "create_session",
+                "arguments": {}
+            },
+            "id": 1
+        }))
+        .unwrap();
+        let response = crate::mcp::handle_mcp_call_tool(state.clone(), &SessionCaller::default(), request).await.unwrap();
+        let response = serde_json::to_string(&response).unwrap();
+        assert!(response.contains("server_maintenance"));
+
+        let status = crate::handler::server_maintenance_impl(&state, Some(false)).unwrap();
+        assert!(!status.maintenance);
+        assert!(state.get_readiness_problems().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_restricted_to_their_namespace() {
+        use crate::payload::JsonRpcRequest;
+
+        let state = create_test_app_state().await;
+        let session_manager = state.get_session_manager();
+        let owner = SessionCaller::from_token_namespace("team-a");
+        let other = SessionCaller::from_token_namespace("team-b");
+        let session_id = session_manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+        session_manager.set_session_owner(&session_id, &owner).unwrap();
+        session_manager.update_session_status(&session_id, SessionStatus::Ready).unwrap();
+
+        let call = |caller: SessionCaller, name: &str, arguments: serde_json::Value| {
+            let state = state.clone();
+            let request: JsonRpcRequest = serde_json::from_value(json!({
+                "jsonrpc": "2.0",
+                "method": "tools/call",
+                "params": { "name": name, "arguments": arguments },
+                "id": 1
+            }))
+            .unwrap();
+            async move {
+                let response = crate::mcp::handle_mcp_call_tool(state, &caller, request).await.unwrap();
+                serde_json::to_value(response).unwrap()
+            }
+        };
+
+        // Another namespace can neither see nor use the session
+        for (name, arguments) in [
+            ("execute_code", json!({ "code": "print(1)", "template": "python", "session_id": session_id })),
+            ("get_sessions", json!({ "session_id": session_id })),
+            ("stop_session", json!({ "session_id": session_id })),
+        ] {
+            let response = call(other.clone(), name, arguments).await;
+            assert_eq!(response["result"]["isError"], json!(true), "{name}");
+            let text = response["result"]["content"][0]["text"].as_str().unwrap();
+            assert!(text.contains("session_access_denied"), "{name}: {text}");
+        }
+        let response = call(other, "get_sessions", json!({})).await;
+        let text = response["result"]["content"][0]["text"].as_str().unwrap();
+        assert!(!text.contains(&session_id));
+
+        // The owning namespace and unrestricted callers can
+        let response = call(owner, "get_sessions", json!({})).await;
+        let text = response["result"]["content"][0]["text"].as_str().unwrap();
+        assert!(text.contains(&session_id));
+        let response = call(SessionCaller::Unrestricted, "stop_session", json!({ "session_id": session_id })).await;
+        assert_eq!(response["result"]["isError"], json!(null));
+        assert_eq!(
+            session_manager.get_session(&session_id).unwrap().status,
+            SessionStatus::Stopped
+        );
+    }
+
+    /// Deserializer that records the names of the fields of the struct deserialized from it
+    struct FieldNames<'a>(&'a mut Vec<&'static str>);
+
+    impl<'de> serde::Deserializer<'de> for FieldNames<'_> {
+        type Error = serde::de::value::Error;
+
+        fn deserialize_any<V: serde::de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+            Err(serde::de::Error::custom("expected a struct"))
+        }
+
+        fn deserialize_struct<V: serde::de::Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            self.0.extend(fields);
+            Err(serde::de::Error::custom("fields recorded"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+            option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+            ignored_any
+        }
+    }
+
+    /// Get the names of the fields of a request struct, sorted
+    fn field_names<T: serde::de::DeserializeOwned>() -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        let _ = T::deserialize(FieldNames(&mut fields));
+        fields.sort();
+        fields
+    }
+
+    /// Get a value that is valid for a property schema
+    fn sample_value(schema: &serde_json::Value) -> serde_json::Value {
+        if let Some(value) = schema["enum"].get(0) {
+            return value.clone();
+        }
+
+        match schema["type"].as_str() {
+            Some("string") => json!("sample"),
+            Some("integer") => schema.get("minimum").cloned().unwrap_or(json!(1)),
+            Some("boolean") => json!(true),
+            Some("array") => json!([sample_value(&schema["items"])]),
+            other => panic!("unexpected property type {:?}", other),
+        }
+    }
+
+    /// Check that a tool's input schema describes the fields of its request struct
+    fn check_tool_schema<T: serde::de::DeserializeOwned>(
+        schemas: &serde_json::Map<String, serde_json::Value>,
+        tool: &str,
+    ) {
+        let schema = &schemas[tool];
+        assert_eq!(schema["type"], json!("object"), "{tool}");
+        let properties = schema["properties"].as_object().unwrap();
+
+        // The schema has a property for every field of the struct, and no others
+        let mut names: Vec<&str> = properties.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, field_names::<T>(), "{tool}");
+
+        // Values of the declared types are accepted
+        let arguments: serde_json::Map<String, serde_json::Value> = properties
+            .iter()
+            .map(|(name, property)| (name.clone(), sample_value(property)))
+            .collect();
+        if let Err(e) = serde_json::from_value::<T>(serde_json::Value::Object(arguments.clone())) {
+            panic!("{tool}: {e}");
+        }
+
+        // Exactly the required properties can't be left out
+        let required: Vec<&str> = schema["required"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|name| name.as_str().unwrap())
+            .collect();
+        for name in properties.keys() {
+            let mut partial = arguments.clone();
+            partial.remove(name);
+            let accepted = serde_json::from_value::<T>(serde_json::Value::Object(partial)).is_ok();
+            assert_eq!(accepted, !required.contains(&name.as_str()), "{tool}.{name}");
+        }
+    }
+
+    #[test]
+    fn test_tool_input_schemas_match_requests() {
+        let schemas = crate::mcp::tool_input_schemas();
+
+        let mut tools: Vec<&str> = schemas.keys().map(String::as_str).collect();
+        tools.sort();
+        assert_eq!(
+            tools,
+            [
+                "create_session",
+                "execute_code",
+                "execute_code_once",
+                "execute_command",
+                "get_execution_history",
+                "get_quota",
+                "get_sessions",
+                "get_usage",
+                "get_volume_path",
+                "stop_session",
+                "wait_for_session",
+            ]
+        );
+        for schema in schemas.values() {
+            assert_eq!(schema["$schema"], json!("http://json-schema.org/draft-07/schema#"));
+        }
+
+        check_tool_schema::<ExecuteCodeRequest>(&schemas, "execute_code");
+        check_tool_schema::<ExecuteCommandRequest>(&schemas, "execute_command");
+        check_tool_schema::<ExecuteOnceRequest>(&schemas, "execute_code_once");
+        check_tool_schema::<CreateSessionRequest>(&schemas, "create_session");
+        check_tool_schema::<WaitForSessionRequest>(&schemas, "wait_for_session");
+        check_tool_schema::<GetSessionsRequest>(&schemas, "get_sessions");
+        check_tool_schema::<StopSessionRequest>(&schemas, "stop_session");
+        check_tool_schema::<GetVolumePathRequest>(&schemas, "get_volume_path");
+        check_tool_schema::<GetExecutionHistoryRequest>(&schemas, "get_execution_history");
+        check_tool_schema::<GetQuotaRequest>(&schemas, "get_quota");
+        check_tool_schema::<GetUsageRequest>(&schemas, "get_usage");
+    }
+}
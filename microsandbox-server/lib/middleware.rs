@@ -18,17 +18,27 @@ use axum::{
     middleware::Next,
     response::IntoResponse,
 };
+use http_body_util::LengthLimitError;
 use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
 use serde_json::Value;
+use std::error::Error;
 
 use crate::{
     config::PROXY_AUTH_HEADER,
     error::{AuthenticationError, ServerError, ValidationError},
     management::API_KEY_PREFIX,
+    simplified_mcp::SessionCaller,
     state::AppState,
     Claims,
 };
 
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Prefix of JSON-RPC methods that act on the whole server rather than on a namespace
+const SERVER_METHOD_PREFIX: &str = "server.";
+
 //--------------------------------------------------------------------------------------------------
 // Middleware Functions
 //--------------------------------------------------------------------------------------------------
@@ -68,6 +78,29 @@ pub fn proxy_uri(original_uri: Uri, namespace: &str, sandbox_name: &str) -> Uri
         .unwrap_or_else(|_| "http://localhost:8080/".parse().unwrap())
 }
 
+/// Reject request bodies over the configured maximum body size
+///
+/// Runs before authentication, which buffers the body, so that oversized bodies get a JSON-RPC
+/// error carrying the limit instead of being read in full.
+pub async fn body_limit_middleware(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<impl IntoResponse, ServerError> {
+    let limit = state.get_session_manager().get_config().get_max_body_size();
+    let (parts, body) = req.into_parts();
+    let bytes = to_bytes(body, limit).await.map_err(|e| {
+        if e.source().is_some_and(|e| e.is::<LengthLimitError>()) {
+            ServerError::PayloadTooLarge(limit)
+        } else {
+            ServerError::InternalError(format!("Failed to read request body: {}", e))
+        }
+    })?;
+
+    let req = Request::from_parts(parts, Body::from(bytes));
+    Ok(next.run(req).await)
+}
+
 /// Log incoming requests
 pub async fn logging_middleware(
     req: Request<Body>,
@@ -91,7 +124,7 @@ pub async fn logging_middleware(
 /// Authentication middleware for verifying API keys and namespace access
 pub async fn auth_middleware(
     State(state): State<AppState>,
-    req: Request<Body>,
+    mut req: Request<Body>,
     next: Next,
 ) -> Result<impl IntoResponse, ServerError> {
     // Skip auth in dev mode if configured
@@ -105,6 +138,9 @@ pub async fn auth_middleware(
     // Validate the token and get its claims
     let claims = validate_token(&api_key, &state)?;
 
+    // Keep the claims for the rate limiter
+    req.extensions_mut().insert(claims.clone());
+
     // If token has wildcard namespace access, we can skip further namespace validation
     if claims.namespace == "*" {
         return Ok(next.run(req).await);
@@ -119,6 +155,16 @@ pub async fn auth_middleware(
         .await
         .map_err(|e| ServerError::InternalError(format!("Failed to read request body: {}", e)))?;
 
+    // Server-wide methods are reserved for tokens with access to all namespaces
+    if let Some(method) = extract_server_method(&bytes) {
+        return Err(ServerError::AuthorizationError(
+            crate::error::AuthorizationError::AccessDenied(format!(
+                "Method '{}' requires a token with access to all namespaces",
+                method
+            )),
+        ));
+    }
+
     // Parse the JSON-RPC request and extract the namespace
     let namespace_from_request = extract_namespace_from_json_rpc(&bytes)?;
 
@@ -145,11 +191,12 @@ pub async fn auth_middleware(
 /// Tool methods (tools/call) require namespace validation
 pub async fn mcp_smart_auth_middleware(
     State(state): State<AppState>,
-    req: Request<Body>,
+    mut req: Request<Body>,
     next: Next,
 ) -> Result<impl IntoResponse, ServerError> {
     // Skip auth in dev mode if configured
     if *state.get_config().get_dev_mode() {
+        req.extensions_mut().insert(SessionCaller::Unrestricted);
         return Ok(next.run(req).await);
     }
 
@@ -159,6 +206,11 @@ pub async fn mcp_smart_auth_middleware(
     // Validate the token and get its claims
     let claims = validate_token(&api_key, &state)?;
 
+    // Sessions belong to the namespace of the token that created them
+    req.extensions_mut()
+        .insert(SessionCaller::from_token_namespace(&claims.namespace));
+    req.extensions_mut().insert(claims.clone());
+
     // If token has wildcard namespace access, we can skip further namespace validation
     if claims.namespace == "*" {
         return Ok(next.run(req).await);
@@ -217,6 +269,28 @@ pub async fn mcp_smart_auth_middleware(
     Ok(next.run(req).await)
 }
 
+/// Rate limiting middleware for bounding the requests of each namespace
+///
+/// Runs after authentication and counts requests against the namespace of the API key they are
+/// made with. Requests in dev mode carry no API key and are not rate limited.
+pub async fn rate_limit_middleware(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<impl IntoResponse, ServerError> {
+    if let Some(claims) = req.extensions().get::<Claims>() {
+        state
+            .get_rate_limiter()
+            .check(&claims.namespace)
+            .map_err(|retry_after| ServerError::RateLimited {
+                namespace: claims.namespace.clone(),
+                retry_after,
+            })?;
+    }
+
+    Ok(next.run(req).await)
+}
+
 //--------------------------------------------------------------------------------------------------
 // Helper Functions
 //--------------------------------------------------------------------------------------------------
@@ -257,6 +331,16 @@ fn extract_namespace_from_json_rpc(bytes: &[u8]) -> Result<String, ServerError>
         })
 }
 
+/// Extract the method of a JSON-RPC request if it acts on the whole server
+fn extract_server_method(bytes: &[u8]) -> Option<String> {
+    let json_value: Value = serde_json::from_slice(bytes).ok()?;
+    json_value
+        .get("method")
+        .and_then(Value::as_str)
+        .filter(|method| method.starts_with(SERVER_METHOD_PREFIX))
+        .map(String::from)
+}
+
 /// Extract API key from request headers
 fn extract_api_key_from_headers(headers: &HeaderMap) -> Result<String, ServerError> {
     // First check the Proxy-Authorization header
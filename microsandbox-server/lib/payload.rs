@@ -12,9 +12,17 @@
 //! - Success message formatting for sandbox operations
 //! - Detailed error information handling
 
+use std::{collections::BTreeMap, net::IpAddr, str::FromStr};
+
+use microsandbox_core::{
+    config::{EnvPair, HostEntry, PathPair, PortPair, ReferenceOrPath},
+    oci,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::{error::ConfigValidationError, simplified_mcp::NamespaceUsage};
+
 //--------------------------------------------------------------------------------------------------
 // Constants
 //--------------------------------------------------------------------------------------------------
@@ -22,6 +30,9 @@ use serde_json::Value;
 /// JSON-RPC version - always "2.0"
 pub const JSONRPC_VERSION: &str = "2.0";
 
+/// JSON-RPC error code for request bodies over the size limit, the same as the portal's
+pub const PAYLOAD_TOO_LARGE_ERROR_CODE: i32 = -32005;
+
 //--------------------------------------------------------------------------------------------------
 // Types: JSON-RPC Payloads
 //--------------------------------------------------------------------------------------------------
@@ -117,7 +128,7 @@ pub struct ProcessedNotification {
 //--------------------------------------------------------------------------------------------------
 
 /// Request payload for starting a sandbox
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct SandboxStartParams {
     /// Sandbox name
     pub sandbox: String,
@@ -139,6 +150,16 @@ pub struct SandboxStopParams {
     pub namespace: String,
 }
 
+/// Request payload for describing the resolved configuration of a sandbox
+#[derive(Debug, Deserialize)]
+pub struct SandboxDescribeParams {
+    /// Sandbox name
+    pub sandbox: String,
+
+    /// Namespace the sandbox belongs to
+    pub namespace: String,
+}
+
 /// Request payload for getting sandbox metrics
 #[derive(Debug, Deserialize)]
 pub struct SandboxMetricsGetParams {
@@ -149,9 +170,16 @@ pub struct SandboxMetricsGetParams {
     pub namespace: String,
 }
 
+/// Request payload for turning maintenance mode on or off
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServerMaintenanceSetParams {
+    /// Whether new sessions should be rejected
+    pub enabled: bool,
+}
+
 /// Configuration for a sandbox
 /// Similar to microsandbox-core's Sandbox but with optional fields for update operations
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct SandboxConfig {
     /// The image to use (optional for updates)
     pub image: Option<String>,
@@ -190,6 +218,18 @@ pub struct SandboxConfig {
 
     /// The exec command to run
     pub exec: Option<String>,
+
+    /// The user that code and commands run as inside the sandbox, by name, uid or uid:gid.
+    /// Defaults to an unprivileged user; `root` opts in to running as root
+    pub run_as_user: Option<String>,
+
+    /// The DNS servers the sandbox resolves names with, as IP addresses
+    #[serde(default)]
+    pub dns_servers: Vec<String>,
+
+    /// The entries to add to the sandbox's hosts file, as `hostname:ip`
+    #[serde(default)]
+    pub extra_hosts: Vec<String>,
     // SECURITY: Needs networking namespacing to be implemented
     // /// The network scope for the sandbox
     // pub scope: Option<String>,
@@ -238,6 +278,104 @@ pub struct SandboxCommandGetOutputParams {
 // Methods
 //--------------------------------------------------------------------------------------------------
 
+impl SandboxConfig {
+    /// Validate the configuration, collecting every invalid field instead of stopping at the
+    /// first one
+    ///
+    /// Fields are reported by path, e.g. `memory` or `volumes[2]`, so a client can fix all of
+    /// its mistakes in one round trip.
+    pub fn validate(&self) -> Result<(), Vec<ConfigValidationError>> {
+        let mut errors = Vec::new();
+
+        if let Some(image) = &self.image {
+            if image.trim().is_empty() {
+                errors.push(ConfigValidationError::new("image", "must not be empty"));
+            } else {
+                match ReferenceOrPath::from_str(image) {
+                    Ok(ReferenceOrPath::Reference(reference)) => {
+                        if let Err(e) = oci::ensure_registry_supported(&reference) {
+                            errors.push(ConfigValidationError::new("image", e.to_string()));
+                        }
+                    }
+                    Ok(ReferenceOrPath::Path(_)) => {}
+                    Err(e) => errors.push(ConfigValidationError::new("image", e.to_string())),
+                }
+            }
+        }
+
+        if self.memory == Some(0) {
+            errors.push(ConfigValidationError::new(
+                "memory",
+                "must be greater than 0",
+            ));
+        }
+
+        if self.cpus == Some(0) {
+            errors.push(ConfigValidationError::new("cpus", "must be greater than 0"));
+        }
+
+        check_entries(&mut errors, "volumes", &self.volumes, |v| {
+            PathPair::from_str(v).map(drop).map_err(|e| e.to_string())
+        });
+        check_entries(&mut errors, "ports", &self.ports, |p| {
+            PortPair::from_str(p).map(drop).map_err(|e| e.to_string())
+        });
+        check_entries(&mut errors, "envs", &self.envs, |e| {
+            EnvPair::from_str(e).map(drop).map_err(|e| e.to_string())
+        });
+        check_entries(&mut errors, "dns_servers", &self.dns_servers, |d| {
+            IpAddr::from_str(d)
+                .map(drop)
+                .map_err(|_| "must be an IP address".to_string())
+        });
+        check_entries(&mut errors, "extra_hosts", &self.extra_hosts, |h| {
+            HostEntry::from_str(h).map(drop).map_err(|e| e.to_string())
+        });
+        check_entries(&mut errors, "depends_on", &self.depends_on, |d| {
+            if d.trim().is_empty() {
+                Err("must not be empty".to_string())
+            } else {
+                Ok(())
+            }
+        });
+
+        if let Some(workdir) = &self.workdir {
+            if !workdir.starts_with('/') {
+                errors.push(ConfigValidationError::new(
+                    "workdir",
+                    "must be an absolute path",
+                ));
+            }
+        }
+
+        if let Some(user) = &self.run_as_user {
+            let valid = !user.is_empty()
+                && user
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'.' | b':'));
+            if !valid {
+                errors.push(ConfigValidationError::new(
+                    "run_as_user",
+                    "must be a user name, uid or uid:gid",
+                ));
+            }
+        }
+
+        if self.scripts.keys().any(|name| name.trim().is_empty()) {
+            errors.push(ConfigValidationError::new(
+                "scripts",
+                "script names must not be empty",
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
 impl JsonRpcRequest {
     /// Create a new JSON-RPC request
     pub fn new(method: String, params: Value, id: Value) -> Self {
@@ -332,6 +470,29 @@ pub struct RegularMessageResponse {
     pub message: String,
 }
 
+/// Readiness probe response
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    /// Whether the server can accept new sessions
+    pub ready: bool,
+
+    /// Why the server is not ready, empty when it is
+    pub reasons: Vec<String>,
+
+    /// Parts of the server running in a reduced mode, which don't make it unready
+    pub degraded: Vec<String>,
+}
+
+/// Maintenance mode response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServerMaintenanceResponse {
+    /// Whether new sessions are rejected
+    pub maintenance: bool,
+
+    /// Number of existing sessions, which keep working during maintenance
+    pub sessions: usize,
+}
+
 /// System status response
 #[derive(Debug, Serialize)]
 pub struct SystemStatusResponse {}
@@ -341,6 +502,9 @@ pub struct SystemStatusResponse {}
 pub struct SandboxStatusResponse {
     /// List of sandbox statuses
     pub sandboxes: Vec<SandboxStatus>,
+
+    /// Resource-time accumulated by the executions of each requested namespace
+    pub usage: BTreeMap<String, NamespaceUsage>,
 }
 
 /// Sandbox configuration response
@@ -387,3 +551,96 @@ impl axum::response::IntoResponse for JsonRpcResponseOrNotification {
         }
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Check each entry of a list field, reporting failures as `field[index]`
+fn check_entries(
+    errors: &mut Vec<ConfigValidationError>,
+    field: &str,
+    entries: &[String],
+    check: impl Fn(&str) -> Result<(), String>,
+) {
+    for (index, entry) in entries.iter().enumerate() {
+        if let Err(reason) = check(entry) {
+            errors.push(ConfigValidationError::new(
+                format!("{}[{}]", field, index),
+                reason,
+            ));
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sandbox_config_validate_reports_all_fields() {
+        let valid: SandboxConfig = serde_json::from_value(json!({
+            "image": "microsandbox/python",
+            "memory": 512,
+            "cpus": 1,
+            "volumes": ["/tmp/shared:/shared"],
+            "ports": ["8080:80"],
+            "envs": ["DEBUG=1"],
+            "dns_servers": ["10.0.0.53", "fd00::53"],
+            "extra_hosts": ["db.internal:10.0.0.5"],
+            "workdir": "/workspace",
+            "run_as_user": "1000:1000",
+        }))
+        .unwrap();
+        assert!(valid.validate().is_ok());
+
+        let invalid: SandboxConfig = serde_json::from_value(json!({
+            "image": "microsandbox/python",
+            "memory": 0,
+            "cpus": 1,
+            "volumes": ["/a:/a", "/b:/b", ""],
+            "ports": ["8080:80", "http:80"],
+            "envs": ["=oops"],
+            "dns_servers": ["10.0.0.53", "dns.example.com"],
+            "extra_hosts": ["db_internal:10.0.0.5", "db.internal"],
+            "workdir": "workspace",
+            "run_as_user": "code runner",
+        }))
+        .unwrap();
+
+        let fields: Vec<String> = invalid
+            .validate()
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.field)
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                "memory",
+                "volumes[2]",
+                "ports[1]",
+                "envs[0]",
+                "dns_servers[1]",
+                "extra_hosts[0]",
+                "extra_hosts[1]",
+                "workdir",
+                "run_as_user"
+            ]
+        );
+
+        let unsupported: SandboxConfig = serde_json::from_value(json!({
+            "image": "quay.io/microsandbox/python",
+        }))
+        .unwrap();
+        let errors = unsupported.validate().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "image");
+        assert!(errors[0].reason.contains("docker.io"));
+    }
+}
@@ -264,6 +264,11 @@ impl PortManager {
         self.mappings.get_port(key)
     }
 
+    /// Get the ports assigned to all sandboxes
+    pub fn get_assignments(&self) -> PortMapping {
+        self.mappings.to_port_mapping()
+    }
+
     /// Verify that a port is still available (not bound by something else)
     fn verify_port_availability(&self, port: u16) -> bool {
         let addr = SocketAddr::new(LOCALHOST_IP, port);
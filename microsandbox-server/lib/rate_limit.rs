@@ -0,0 +1,273 @@
+//! Rate limiting of requests per namespace for the microsandbox server.
+//!
+//! This module handles:
+//! - Parsing the request rates namespaces are limited to from `MSB_RATE_LIMITS`
+//! - Token buckets counting the requests of each namespace
+//!
+//! Requests are counted against the namespace of the API key they are made with, so a buggy or
+//! malicious client flooding the server with session and execution requests only exhausts its
+//! own namespace's budget. Each namespace has a bucket holding up to its rate's request count,
+//! refilled evenly over the rate's period, so a client can burst up to the full count and then
+//! continues at the rate.
+//!
+//! Limits are set as comma-separated `<namespace>=<requests>/<unit>` entries, with a unit of
+//! `s`, `m` or `h` and `*` for the namespaces that are not listed, e.g. `*=20/s,batch=600/m`.
+//! Namespaces without a limit are not rate limited, nor is anything when `MSB_RATE_LIMITS` is
+//! unset.
+
+use std::{
+    collections::HashMap,
+    env,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Environment variable the rate limits are read from
+pub const RATE_LIMITS_ENV_VAR: &str = "MSB_RATE_LIMITS";
+
+/// Namespace of the limit applying to the namespaces that are not listed
+const DEFAULT_NAMESPACE: &str = "*";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A number of requests allowed per period
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Requests allowed per period, which is also the largest burst
+    requests: u32,
+
+    /// Period the requests are allowed in
+    period: Duration,
+}
+
+/// Limits the rate of requests of each namespace
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    /// Limit of the namespaces that are not listed, if they are limited
+    default_limit: Option<RateLimit>,
+
+    /// Limits of individual namespaces
+    limits: HashMap<String, RateLimit>,
+
+    /// Buckets of the namespaces that have made requests
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+/// Requests a namespace can still make
+#[derive(Debug)]
+struct TokenBucket {
+    /// Requests available, including the fraction of one refilled so far
+    tokens: f64,
+
+    /// When the tokens were last refilled
+    refilled_at: Instant,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl RateLimit {
+    /// Create a limit of the given number of requests per period
+    pub fn new(requests: u32, period: Duration) -> Self {
+        Self { requests, period }
+    }
+
+    /// Time it takes to refill one request
+    fn interval(&self) -> Duration {
+        self.period / self.requests
+    }
+}
+
+impl RateLimiter {
+    /// Create a rate limiter from a default limit and the limits of individual namespaces
+    pub fn new(
+        default_limit: Option<RateLimit>,
+        limits: impl IntoIterator<Item = (String, RateLimit)>,
+    ) -> Self {
+        Self {
+            default_limit,
+            limits: limits.into_iter().collect(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Create a rate limiter from `MSB_RATE_LIMITS`, which limits nothing if it is unset
+    pub fn from_env() -> Result<Self, String> {
+        match env::var(RATE_LIMITS_ENV_VAR) {
+            Ok(rules) => parse_rate_limits(&rules),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Check whether any namespace is rate limited
+    pub fn is_enabled(&self) -> bool {
+        self.default_limit.is_some() || !self.limits.is_empty()
+    }
+
+    /// Get the limit of a namespace, if it is rate limited
+    pub fn get_limit(&self, namespace: &str) -> Option<RateLimit> {
+        self.limits.get(namespace).copied().or(self.default_limit)
+    }
+
+    /// Count a request of a namespace
+    ///
+    /// Returns how long to wait before retrying if the namespace has no requests left.
+    pub fn check(&self, namespace: &str) -> Result<(), Duration> {
+        self.check_at(namespace, Instant::now())
+    }
+
+    /// Count a request of a namespace made at the given time
+    fn check_at(&self, namespace: &str, now: Instant) -> Result<(), Duration> {
+        let Some(limit) = self.get_limit(namespace) else {
+            return Ok(());
+        };
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = buckets
+            .entry(namespace.to_string())
+            .or_insert_with(|| TokenBucket {
+                tokens: limit.requests as f64,
+                refilled_at: now,
+            });
+
+        let refilled = now
+            .saturating_duration_since(bucket.refilled_at)
+            .as_secs_f64()
+            / limit.interval().as_secs_f64();
+        bucket.tokens = (bucket.tokens + refilled).min(limit.requests as f64);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        Err(limit.interval().mul_f64(1.0 - bucket.tokens))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Parse rate limits in the `<namespace>=<requests>/<unit>` form, e.g. `*=20/s,batch=600/m`
+pub fn parse_rate_limits(rules: &str) -> Result<RateLimiter, String> {
+    let mut default_limit = None;
+    let mut limits = HashMap::new();
+
+    for rule in rules.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+        let (namespace, rate) = rule
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid rate limit '{}', expected <namespace>=<rate>", rule))?;
+
+        let limit = parse_rate(rate.trim())
+            .ok_or_else(|| format!("Invalid rate '{}', expected e.g. 20/s or 600/m", rate))?;
+
+        match namespace.trim() {
+            "" => return Err(format!("Rate limit '{}' has no namespace", rule)),
+            DEFAULT_NAMESPACE => default_limit = Some(limit),
+            namespace => {
+                limits.insert(namespace.to_string(), limit);
+            }
+        }
+    }
+
+    Ok(RateLimiter::new(default_limit, limits))
+}
+
+/// Parse a rate in the `<requests>/<unit>` form
+fn parse_rate(rate: &str) -> Option<RateLimit> {
+    let (requests, unit) = rate.split_once('/')?;
+    let requests = requests.trim().parse::<u32>().ok().filter(|&n| n > 0)?;
+    let period = match unit.trim() {
+        "s" => Duration::from_secs(1),
+        "m" => Duration::from_secs(60),
+        "h" => Duration::from_secs(3600),
+        _ => return None,
+    };
+
+    Some(RateLimit::new(requests, period))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate_limits() {
+        let limiter = parse_rate_limits("*=20/s, batch=600/m,admin=10/h").unwrap();
+        assert!(limiter.is_enabled());
+        assert_eq!(
+            limiter.get_limit("batch"),
+            Some(RateLimit::new(600, Duration::from_secs(60)))
+        );
+        assert_eq!(
+            limiter.get_limit("admin"),
+            Some(RateLimit::new(10, Duration::from_secs(3600)))
+        );
+        assert_eq!(
+            limiter.get_limit("other"),
+            Some(RateLimit::new(20, Duration::from_secs(1)))
+        );
+
+        let limiter = parse_rate_limits("batch=5/s").unwrap();
+        assert_eq!(limiter.get_limit("other"), None);
+        assert!(!parse_rate_limits("").unwrap().is_enabled());
+
+        for invalid in [
+            "batch",
+            "batch=5",
+            "batch=0/s",
+            "batch=5/d",
+            "=5/s",
+            "batch=x/s",
+        ] {
+            assert!(
+                parse_rate_limits(invalid).is_err(),
+                "{invalid} should not parse"
+            );
+        }
+    }
+
+    #[test]
+    fn test_rate_limiter_refills_buckets() {
+        let limiter = parse_rate_limits("*=2/s,batch=1/m").unwrap();
+        let start = Instant::now();
+
+        // A full bucket allows a burst of the whole rate
+        assert!(limiter.check_at("default", start).is_ok());
+        assert!(limiter.check_at("default", start).is_ok());
+        assert_eq!(
+            limiter.check_at("default", start),
+            Err(Duration::from_millis(500))
+        );
+
+        // Namespaces have their own buckets
+        assert!(limiter.check_at("batch", start).is_ok());
+        assert_eq!(
+            limiter.check_at("batch", start),
+            Err(Duration::from_secs(60))
+        );
+
+        // Tokens refill evenly over the period
+        let later = start + Duration::from_millis(250);
+        assert_eq!(
+            limiter.check_at("default", later),
+            Err(Duration::from_millis(250))
+        );
+        assert!(limiter
+            .check_at("default", start + Duration::from_millis(500))
+            .is_ok());
+    }
+}
@@ -0,0 +1,214 @@
+//! Secret redaction for the microsandbox server.
+//!
+//! This module handles:
+//! - Matching environment variable names against configurable secret patterns
+//! - Masking secret values in JSON request parameters before they are logged
+//! - Masking `NAME=value` assignments in free text such as commands and their output
+//!
+//! Patterns are case-insensitive globs where `*` matches any run of characters, e.g.
+//! `*_TOKEN` matches `GITHUB_TOKEN`. Dashes in names are treated as underscores, so
+//! `--api-key=...` is caught by `*_KEY`.
+
+use serde_json::Value;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Replacement for redacted values
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Patterns redacted when none are configured
+pub const DEFAULT_REDACT_PATTERNS: &[&str] = &["*_TOKEN", "*_KEY", "*_SECRET", "*_PASSWORD"];
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Masks the values of secret-looking variables in logs and execution history
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    /// Upper-cased glob patterns for secret names
+    patterns: Vec<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl Redactor {
+    /// Create a redactor for the given name patterns
+    pub fn new<S: AsRef<str>>(patterns: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            patterns: patterns
+                .into_iter()
+                .map(|p| normalize_name(p.as_ref()))
+                .filter(|p| !p.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Check whether a variable name matches any of the secret patterns
+    pub fn is_secret_name(&self, name: &str) -> bool {
+        let name = normalize_name(name.trim_start_matches('-'));
+        !name.is_empty() && self.patterns.iter().any(|p| glob_match(p, &name))
+    }
+
+    /// Mask `NAME=value` assignments whose name is a secret
+    ///
+    /// Assignments are recognized per whitespace-separated word, so `export API_KEY=abc` and
+    /// `curl --auth-token=abc` are both masked while the rest of the text is left untouched.
+    pub fn redact_text(&self, text: &str) -> String {
+        let mut redacted = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while !rest.is_empty() {
+            let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let (word, tail) = rest.split_at(word_end);
+            redacted.push_str(&self.redact_word(word));
+
+            let space_end = tail
+                .find(|c: char| !c.is_whitespace())
+                .unwrap_or(tail.len());
+            redacted.push_str(&tail[..space_end]);
+            rest = &tail[space_end..];
+        }
+
+        redacted
+    }
+
+    /// Return a copy of a JSON value with secrets masked
+    ///
+    /// Object fields with secret names have their values replaced, and strings anywhere in the
+    /// value (such as `envs` entries or command `args`) are redacted as text.
+    pub fn redact_value(&self, value: &Value) -> Value {
+        match value {
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(key, value)| {
+                        let value = if self.is_secret_name(key) && !value.is_null() {
+                            Value::String(REDACTED.to_string())
+                        } else {
+                            self.redact_value(value)
+                        };
+                        (key.clone(), value)
+                    })
+                    .collect(),
+            ),
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|item| self.redact_value(item)).collect())
+            }
+            Value::String(text) => Value::String(self.redact_text(text)),
+            other => other.clone(),
+        }
+    }
+
+    /// Mask the value of a single `NAME=value` word if its name is a secret
+    fn redact_word(&self, word: &str) -> String {
+        match word.split_once('=') {
+            Some((name, value)) if !value.is_empty() && self.is_secret_name(name) => {
+                format!("{}={}", name, REDACTED)
+            }
+            _ => word.to_string(),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new(DEFAULT_REDACT_PATTERNS)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Upper-case a name and treat dashes as underscores
+fn normalize_name(name: &str) -> String {
+    name.trim().to_ascii_uppercase().replace('-', "_")
+}
+
+/// Match a name against a glob pattern where `*` matches any run of characters
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard, so the whole name must match
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_redactor_matches_patterns() {
+        let redactor = Redactor::default();
+
+        assert!(redactor.is_secret_name("GITHUB_TOKEN"));
+        assert!(redactor.is_secret_name("openai_api_key"));
+        assert!(redactor.is_secret_name("--api-key"));
+        assert!(!redactor.is_secret_name("PATH"));
+        assert!(!redactor.is_secret_name("KEY"));
+
+        let exact = Redactor::new(["DATABASE_URL", "AWS_*_ID"]);
+        assert!(exact.is_secret_name("database_url"));
+        assert!(exact.is_secret_name("AWS_ACCESS_KEY_ID"));
+        assert!(!exact.is_secret_name("DATABASE_URL_BACKUP"));
+    }
+
+    #[test]
+    fn test_redactor_redacts_text_and_values() {
+        let redactor = Redactor::default();
+
+        assert_eq!(
+            redactor.redact_text("export API_KEY=abc  && run --auth-token=xyz PORT=80"),
+            "export API_KEY=[REDACTED]  && run --auth-token=[REDACTED] PORT=80"
+        );
+
+        let params = json!({
+            "sandbox": "test",
+            "config": {
+                "envs": ["HF_TOKEN=hf_123", "DEBUG=1"],
+                "DB_PASSWORD": "hunter2",
+            },
+            "args": ["--client-secret=s3cr3t", "-v"],
+        });
+        assert_eq!(
+            redactor.redact_value(&params),
+            json!({
+                "sandbox": "test",
+                "config": {
+                    "envs": ["HF_TOKEN=[REDACTED]", "DEBUG=1"],
+                    "DB_PASSWORD": "[REDACTED]",
+                },
+                "args": ["--client-secret=[REDACTED]", "-v"],
+            })
+        );
+    }
+}
@@ -11,6 +11,7 @@
 //! - State management for routes
 
 use axum::{
+    extract::DefaultBodyLimit,
     middleware,
     routing::{get, post},
     Router,
@@ -24,27 +25,55 @@ use crate::{handler, middleware as app_middleware, state::AppState};
 
 /// Create a new router with the given state
 pub fn create_router(state: AppState) -> Router {
+    // Bodies over the limit are rejected with a JSON-RPC error before authentication reads them,
+    // and the JSON extractors accept bodies up to the same limit
+    let max_body_size = state.get_session_manager().get_config().get_max_body_size();
+
     // Create REST API routes - only health endpoint remains here
     let rest_api = Router::new().route("/health", get(handler::health));
 
     // Create JSON-RPC routes with authentication - a single endpoint that handles all RPC methods
     // This now mirrors the structure used in microsandbox-portal
+    // Rate limiting is layered inside authentication, as it counts requests by API key
     let rpc_api = Router::new()
         .route("/", post(handler::json_rpc_handler))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            app_middleware::rate_limit_middleware,
+        ))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             app_middleware::auth_middleware,
-        ));
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            app_middleware::body_limit_middleware,
+        ))
+        .layer(DefaultBodyLimit::max(max_body_size));
 
     // Create MCP routes - separate endpoint for Model Context Protocol
     // Uses smart auth middleware that handles protocol vs tool methods differently
-    let mcp_api =
-        Router::new()
-            .route("/", post(handler::mcp_handler))
-            .layer(middleware::from_fn_with_state(
-                state.clone(),
-                app_middleware::mcp_smart_auth_middleware,
-            ));
+    let mcp_api = Router::new()
+        .route("/", post(handler::mcp_handler))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            app_middleware::rate_limit_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            app_middleware::mcp_smart_auth_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            app_middleware::body_limit_middleware,
+        ))
+        .layer(DefaultBodyLimit::max(max_body_size));
+
+    // Create load balancer probes - unauthenticated, and added after the logging middleware so
+    // frequent polling does not flood the request log
+    let probes = Router::new()
+        .route("/healthz", get(handler::healthz))
+        .route("/readyz", get(handler::readyz));
 
     // Combine all routes with logging middleware
     Router::new()
@@ -52,5 +81,80 @@ pub fn create_router(state: AppState) -> Router {
         .nest("/api/v1/rpc", rpc_api)
         .nest("/mcp", mcp_api)
         .layer(middleware::from_fn(app_middleware::logging_middleware))
+        .merge(probes)
         .with_state(state)
 }
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::{to_bytes, Body},
+        http::{Request, StatusCode},
+    };
+    use microsandbox_utils::DEFAULT_PORTAL_MAX_BODY_SIZE;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    use crate::{config::Config, payload::PAYLOAD_TOO_LARGE_ERROR_CODE, port::PortManager};
+
+    async fn rpc_request(router: Router, padding: usize) -> (StatusCode, Value) {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "method": "sandbox.unknown",
+            "params": { "padding": "x".repeat(padding) },
+            "id": 1,
+        });
+        let request = Request::post("/api/v1/rpc")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_router_body_limit() {
+        let root = std::env::temp_dir().join(format!("msb-body-limit-{}", uuid::Uuid::new_v4()));
+        let config = Config::new(
+            None,
+            "127.0.0.1".to_string(),
+            8080,
+            Some(root.clone()),
+            true,
+        )
+        .unwrap();
+        let port_manager = PortManager::new(&root).await.unwrap();
+        let state = AppState::new(
+            Arc::new(config),
+            Arc::new(tokio::sync::RwLock::new(port_manager)),
+        );
+        let router = create_router(state);
+
+        // Bodies past axum's default limit but within the portal's reach the handler
+        let (status, _) = rpc_request(router.clone(), 4 * 1024 * 1024).await;
+        assert_ne!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Bodies past the limit get the portal's JSON-RPC error, carrying the limit
+        let (status, body) = rpc_request(router, DEFAULT_PORTAL_MAX_BODY_SIZE).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["error"]["code"], json!(PAYLOAD_TOO_LARGE_ERROR_CODE));
+        assert_eq!(
+            body["error"]["data"]["max_body_size"],
+            json!(DEFAULT_PORTAL_MAX_BODY_SIZE)
+        );
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
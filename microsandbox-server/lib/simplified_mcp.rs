@@ -50,10 +50,13 @@
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::fmt;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
+use microsandbox_utils::{MemoryInfo, DEFAULT_PORTAL_MAX_BODY_SIZE};
 use thiserror::Error;
+use tokio::sync::{watch, Notify};
 use tokio::time::interval;
 
 //--------------------------------------------------------------------------------------------------
@@ -91,6 +94,19 @@ impl SandboxFlavor {
         }
     }
 
+    /// Get the default execution timeout for this flavor
+    ///
+    /// Smaller flavors get shorter defaults so that runaway jobs on cheap sandboxes are
+    /// caught early, while larger flavors allow longer-running workloads. Requests can
+    /// override this with an explicit timeout.
+    pub fn default_exec_timeout(&self) -> Duration {
+        match self {
+            Self::Small => Duration::from_secs(30),
+            Self::Medium => Duration::from_secs(120),
+            Self::Large => Duration::from_secs(300),
+        }
+    }
+
     /// Get the string representation of the flavor
     pub fn as_str(&self) -> &'static str {
         match self {
@@ -126,6 +142,41 @@ impl std::str::FromStr for SandboxFlavor {
     }
 }
 
+/// Flavor requested for a new session, either named or chosen by the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum FlavorSelection {
+    /// Pick the flavor from the code or command with the configured flavor rules
+    Auto,
+    /// Use the given flavor
+    Fixed(SandboxFlavor),
+}
+
+impl From<SandboxFlavor> for FlavorSelection {
+    fn from(flavor: SandboxFlavor) -> Self {
+        Self::Fixed(flavor)
+    }
+}
+
+impl std::str::FromStr for FlavorSelection {
+    type Err = SimplifiedMcpError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+        s.parse().map(Self::Fixed)
+    }
+}
+
+impl TryFrom<String> for FlavorSelection {
+    type Error = SimplifiedMcpError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Error Types
 //--------------------------------------------------------------------------------------------------
@@ -137,10 +188,40 @@ pub enum SimplifiedMcpError {
     #[error("Session not found: {0}")]
     SessionNotFound(String),
 
+    /// Session was created with an API key for another namespace
+    #[error("Access denied to session: {0}")]
+    SessionAccessDenied(String),
+
+    /// Namespace is not covered by the caller's API key
+    #[error("Access denied to namespace: {0}")]
+    NamespaceAccessDenied(String),
+
     /// Session creation failed
     #[error("Session creation failed: {0}")]
     SessionCreationFailed(String),
 
+    /// The image of a template is not in any registry
+    #[error("Image {image} not found in any registry (tried: {})", .registries.join(", "))]
+    ImageNotFound {
+        /// The image that was requested
+        image: String,
+        /// The registries that were tried, in order
+        registries: Vec<String>,
+    },
+
+    /// Packages requested for a session could not be installed into its sandbox
+    #[error("Failed to install packages {}: {reason}", .packages.join(", "))]
+    PackageInstallFailed {
+        /// The packages that were requested
+        packages: Vec<String>,
+        /// Why the install failed, including the package manager's output
+        reason: String,
+    },
+
+    /// New sessions are rejected because the server is in maintenance mode
+    #[error("Server is in maintenance mode and is not accepting new sessions")]
+    ServerInMaintenance,
+
     /// Unsupported template
     #[error("Unsupported template: {0}. Supported templates: python, node")]
     UnsupportedLanguage(String),
@@ -250,6 +331,70 @@ impl SimplifiedMcpError {
                 ],
             },
 
+            SimplifiedMcpError::ImageNotFound { image, registries } => UserFriendlyError {
+                error_type: "image_not_found".to_string(),
+                message: format!("Image '{}' was not found in any registry", image),
+                details: Some(format!("Registries tried: {}", registries.join(", "))),
+                suggestions: vec![
+                    format!("Check the image name and tag of '{}' for typos", image),
+                    "Make sure the tag has not been deleted from the registry".to_string(),
+                    "Add the registry that hosts the image to registries.yaml in the microsandbox home".to_string(),
+                    "Use the default 'python' or 'node' template instead".to_string(),
+                ],
+                recovery_actions: vec![
+                    RecoveryAction {
+                        action: "use_python_template".to_string(),
+                        description: "Use the Python template, whose image is known to exist".to_string(),
+                        parameters: Some(json!({"template": "python"})),
+                    },
+                    RecoveryAction {
+                        action: "use_node_template".to_string(),
+                        description: "Use the Node.js template, whose image is known to exist".to_string(),
+                        parameters: Some(json!({"template": "node"})),
+                    }
+                ],
+            },
+
+            SimplifiedMcpError::PackageInstallFailed { packages, reason } => UserFriendlyError {
+                error_type: "package_install_failed".to_string(),
+                message: format!("Failed to install packages: {}", packages.join(", ")),
+                details: Some(reason.clone()),
+                suggestions: vec![
+                    "Check the package names and versions for typos".to_string(),
+                    "Make sure the packages exist for the session's template (pip for python, npm for node)".to_string(),
+                    "Create the session without packages and install them with a command to see the full output".to_string(),
+                ],
+                recovery_actions: vec![
+                    RecoveryAction {
+                        action: "create_session_without_packages".to_string(),
+                        description: "Create the session without installing packages".to_string(),
+                        parameters: Some(json!({"packages": []})),
+                    }
+                ],
+            },
+
+            SimplifiedMcpError::ServerInMaintenance => UserFriendlyError {
+                error_type: "server_maintenance".to_string(),
+                message: "The server is in maintenance mode and is not accepting new sessions".to_string(),
+                details: Some("Sessions that already exist keep working during maintenance".to_string()),
+                suggestions: vec![
+                    "Pass the session_id of an existing session to keep working in it".to_string(),
+                    "Try again once maintenance is over".to_string(),
+                ],
+                recovery_actions: vec![
+                    RecoveryAction {
+                        action: "use_existing_session".to_string(),
+                        description: "List the existing sessions to continue in one of them".to_string(),
+                        parameters: None,
+                    },
+                    RecoveryAction {
+                        action: "retry_operation".to_string(),
+                        description: "Retry after maintenance is over".to_string(),
+                        parameters: Some(json!({"wait_seconds": 60})),
+                    }
+                ],
+            },
+
             SimplifiedMcpError::UnsupportedLanguage(template) => UserFriendlyError {
                 error_type: "unsupported_language".to_string(),
                 message: format!("Template '{}' is not supported", template),
@@ -297,6 +442,35 @@ impl SimplifiedMcpError {
                 ],
             },
 
+            SimplifiedMcpError::SessionAccessDenied(session_id) => UserFriendlyError {
+                error_type: "session_access_denied".to_string(),
+                message: format!("Session '{}' belongs to another namespace", session_id),
+                details: Some("Sessions can only be used with an API key for the namespace that created them".to_string()),
+                suggestions: vec![
+                    "Use the API key the session was created with".to_string(),
+                    "List your own sessions using the get_sessions tool".to_string(),
+                    "Create a new session by calling the tool without specifying a session_id".to_string(),
+                ],
+                recovery_actions: vec![
+                    RecoveryAction {
+                        action: "create_new_session".to_string(),
+                        description: "Create a new session owned by this namespace".to_string(),
+                        parameters: None,
+                    }
+                ],
+            },
+
+            SimplifiedMcpError::NamespaceAccessDenied(namespace) => UserFriendlyError {
+                error_type: "namespace_access_denied".to_string(),
+                message: format!("Namespace '{}' is not covered by this API key", namespace),
+                details: Some("API keys scoped to a namespace can only read that namespace's data".to_string()),
+                suggestions: vec![
+                    "Request the namespace the API key was issued for".to_string(),
+                    "Use an API key for all namespaces to read \"*\"".to_string(),
+                ],
+                recovery_actions: vec![],
+            },
+
             SimplifiedMcpError::InvalidSessionState(reason) => UserFriendlyError {
                 error_type: "invalid_session_state".to_string(),
                 message: "Session is in an invalid state for this operation".to_string(),
@@ -416,6 +590,7 @@ impl SimplifiedMcpError {
                     "Use 'small' for basic tasks (1 CPU, 1GB RAM)".to_string(),
                     "Use 'medium' for moderate workloads (2 CPUs, 2GB RAM)".to_string(),
                     "Use 'large' for intensive tasks (4 CPUs, 4GB RAM)".to_string(),
+                    "Use 'auto' to have the flavor picked from the code when creating a session".to_string(),
                 ],
                 recovery_actions: vec![
                     RecoveryAction {
@@ -491,8 +666,24 @@ pub struct ExecuteCodeRequest {
     pub template: Option<String>,
     /// Optional session ID - if not provided, a new session will be created
     pub session_id: Option<String>,
-    /// Sandbox resource flavor - defaults to Small if not specified
-    pub flavor: Option<SandboxFlavor>,
+    /// Sandbox resource flavor, or auto to pick one from the code - defaults to Small if not specified
+    pub flavor: Option<FlavorSelection>,
+    /// Execution timeout in seconds - defaults to the session flavor's default timeout
+    pub timeout: Option<u64>,
+    /// Memory limit in MB for this execution only - not supported, requests that set it are rejected
+    pub memory_mb: Option<u32>,
+    /// Seed for reproducible runs, exposed as PYTHONHASHSEED / RANDOM_SEED - best-effort only
+    pub seed: Option<u32>,
+    /// Reuse an idle session of the same client, template and flavor when no session ID is given
+    pub reuse_session: Option<bool>,
+    /// Client identifier that scopes reused sessions - defaults to a shared anonymous client
+    pub client_id: Option<String>,
+    /// Wait for a session that is still being created to become ready instead of failing
+    pub wait_for_ready: Option<bool>,
+    /// Priority in the execution queue relative to the namespace's other executions - defaults to Normal
+    pub priority: Option<ExecutionPriority>,
+    /// Only run the admission checks and report what the execution would get, without executing
+    pub validate_only: Option<bool>,
 }
 
 /// Request structure for executing commands in a sandbox
@@ -506,8 +697,65 @@ pub struct ExecuteCommandRequest {
     pub template: Option<String>,
     /// Optional session ID - if not provided, a new session will be created
     pub session_id: Option<String>,
-    /// Sandbox resource flavor - defaults to Small if not specified
-    pub flavor: Option<SandboxFlavor>,
+    /// Sandbox resource flavor, or auto to pick one from the command - defaults to Small if not specified
+    pub flavor: Option<FlavorSelection>,
+    /// Execution timeout in seconds - defaults to the session flavor's default timeout
+    pub timeout: Option<u64>,
+    /// Memory limit in MB for this execution only - not supported, requests that set it are rejected
+    pub memory_mb: Option<u32>,
+    /// Seed for reproducible runs, exposed as PYTHONHASHSEED / RANDOM_SEED - best-effort only
+    pub seed: Option<u32>,
+    /// Reuse an idle session of the same client, template and flavor when no session ID is given
+    pub reuse_session: Option<bool>,
+    /// Client identifier that scopes reused sessions - defaults to a shared anonymous client
+    pub client_id: Option<String>,
+    /// Wait for a session that is still being created to become ready instead of failing
+    pub wait_for_ready: Option<bool>,
+    /// Priority in the execution queue relative to the namespace's other executions - defaults to Normal
+    pub priority: Option<ExecutionPriority>,
+}
+
+/// Request structure for executing code once in a throwaway session
+#[derive(Debug, Deserialize, Clone)]
+pub struct ExecuteOnceRequest {
+    /// Code to execute
+    pub code: String,
+    /// Sandbox template/image to use (python, node)
+    pub template: Option<String>,
+    /// Sandbox resource flavor, or auto to pick one from the code - defaults to Small if not specified
+    pub flavor: Option<FlavorSelection>,
+    /// Execution timeout in seconds - defaults to the flavor's default timeout
+    pub timeout: Option<u64>,
+    /// Memory limit in MB for the execution - not supported, requests that set it are rejected
+    pub memory_mb: Option<u32>,
+    /// Seed for reproducible runs, exposed as PYTHONHASHSEED / RANDOM_SEED - best-effort only
+    pub seed: Option<u32>,
+    /// Priority in the execution queue relative to the namespace's other executions - defaults to Normal
+    pub priority: Option<ExecutionPriority>,
+}
+
+/// Request structure for creating a session ahead of its first execution
+#[derive(Debug, Deserialize, Clone)]
+pub struct CreateSessionRequest {
+    /// Sandbox template/image to use (python, node)
+    pub template: Option<String>,
+    /// Sandbox resource flavor, or auto to pick one from the packages - defaults to Small if not specified
+    pub flavor: Option<FlavorSelection>,
+    /// Wait for the sandbox to start before returning (default: false)
+    pub wait: Option<bool>,
+    /// pip or npm packages, depending on the template, to install when the sandbox starts
+    pub packages: Option<Vec<String>>,
+    /// Code run before every execution in the session, replacing the template's preamble - an empty string disables it
+    pub preamble: Option<String>,
+}
+
+/// Request structure for waiting until a session is ready
+#[derive(Debug, Deserialize, Clone)]
+pub struct WaitForSessionRequest {
+    /// Session ID to wait for
+    pub session_id: String,
+    /// Maximum time to wait in seconds (default: 120)
+    pub timeout: Option<u64>,
 }
 
 /// Request structure for getting session information
@@ -531,6 +779,28 @@ pub struct GetVolumePathRequest {
     pub session_id: Option<String>,
 }
 
+/// Request structure for getting the remaining session quota
+#[derive(Debug, Deserialize, Clone)]
+pub struct GetQuotaRequest {}
+
+/// Request structure for getting the resource usage accumulated by a namespace
+#[derive(Debug, Deserialize, Clone)]
+pub struct GetUsageRequest {
+    /// Namespace to get the usage for, or "*" for all namespaces
+    pub namespace: String,
+}
+
+/// Request structure for getting the execution history of a session
+#[derive(Debug, Deserialize, Clone)]
+pub struct GetExecutionHistoryRequest {
+    /// Session ID to get the history for
+    pub session_id: String,
+    /// Number of entries to skip, counted from the oldest retained entry (default: 0)
+    pub offset: Option<usize>,
+    /// Maximum number of entries to return (default: 20)
+    pub limit: Option<usize>,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Response Data Structures
 //--------------------------------------------------------------------------------------------------
@@ -546,10 +816,91 @@ pub struct ExecutionResponse {
     pub stderr: String,
     /// Exit code (None for code execution, Some for command execution)
     pub exit_code: Option<i32>,
+    /// How the command terminated (None for code execution)
+    #[serde(default)]
+    pub termination: Option<Termination>,
     /// Execution time in milliseconds
     pub execution_time_ms: u64,
     /// Whether a new session was created for this execution
     pub session_created: bool,
+    /// Flavor of the session the execution ran in, including one picked by auto selection
+    #[serde(default)]
+    pub flavor: Option<SandboxFlavor>,
+    /// Why the execution was cut short, if it was
+    #[serde(default)]
+    pub cancellation_reason: Option<CancelReason>,
+}
+
+/// Response to an execute_code request with validate_only set that passed all admission checks
+///
+/// Nothing is created or executed; a request failing a check returns the same error it would
+/// when executed.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExecutionValidation {
+    /// Session the code would run in, or None if a new session would be created
+    pub session_id: Option<String>,
+    /// Whether a new session would be created for the execution
+    pub session_created: bool,
+    /// Flavor of the session, including one picked by auto selection
+    pub flavor: SandboxFlavor,
+    /// Language the code would run in, the primary language of an existing session
+    pub language: String,
+    /// Execution timeout that would apply, in seconds
+    pub timeout_seconds: u64,
+}
+
+/// Why an execution was cut short before it finished
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelReason {
+    /// The client cancelled the execution
+    UserCancelled,
+    /// The execution exceeded its timeout
+    Timeout,
+    /// The server shut down while the execution was running
+    ServerShutdown,
+    /// The execution was killed to free memory
+    MemoryPressure,
+}
+
+impl CancelReason {
+    /// Get the reason a command that terminated this way was cut short, if it was
+    pub fn from_termination(termination: Termination) -> Option<Self> {
+        match termination {
+            Termination::Timeout => Some(CancelReason::Timeout),
+            Termination::OomKilled => Some(CancelReason::MemoryPressure),
+            Termination::Exited(_) | Termination::Signaled(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for CancelReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            CancelReason::UserCancelled => "cancelled by the client",
+            CancelReason::Timeout => "timed out",
+            CancelReason::ServerShutdown => "interrupted by server shutdown",
+            CancelReason::MemoryPressure => "killed to free memory",
+        };
+        f.write_str(reason)
+    }
+}
+
+/// How an executed command terminated
+///
+/// Mirrors the portal's termination report, serialized as `{"kind": "exited", "value": 0}`,
+/// `{"kind": "signaled", "value": 9}`, `{"kind": "timeout"}` or `{"kind": "oom_killed"}`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum Termination {
+    /// The command exited normally with the given exit code
+    Exited(i32),
+    /// The command was killed by the given signal
+    Signaled(i32),
+    /// The command was killed because it exceeded its timeout
+    Timeout,
+    /// The command was killed by the kernel OOM killer
+    OomKilled,
 }
 
 /// Summary information about a session
@@ -569,6 +920,9 @@ pub struct SessionSummary {
     pub last_accessed: String,
     /// Session uptime in seconds
     pub uptime_seconds: u64,
+    /// Packages installed into the session's sandbox when it started
+    #[serde(default)]
+    pub packages: Vec<String>,
 }
 
 /// Response structure for session list queries
@@ -589,6 +943,105 @@ pub struct VolumePathResponse {
     pub available: bool,
 }
 
+/// Response structure for quota queries
+///
+/// Lets clients see how many more sessions they can create before hitting
+/// `ResourceLimitExceeded`. These are the server's global quotas; the sessions of a namespace
+/// may be limited further by `MSB_MAX_SESSIONS_PER_NAMESPACE`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct QuotaResponse {
+    /// Maximum number of concurrent sessions
+    pub max_sessions: usize,
+    /// Number of sessions currently counted against the limit
+    pub active_sessions: usize,
+    /// Number of sessions that can still be created
+    pub remaining_sessions: usize,
+    /// Number of portal ports still available to new sessions
+    pub available_ports: usize,
+    /// Memory reserved by existing sessions, in MB
+    pub allocated_memory_mb: u32,
+    /// vCPUs reserved by existing sessions
+    pub allocated_cpus: u32,
+    /// Host memory not yet reserved by sessions, in MB (None if host memory is unknown)
+    pub remaining_memory_mb: Option<u64>,
+    /// Host CPUs not yet reserved by sessions (None if the CPU count is unknown)
+    pub remaining_cpus: Option<u32>,
+}
+
+/// Resource-time accumulated by the executions of a namespace
+///
+/// Executions are charged for the resources allocated to their session rather than the
+/// resources they actually used: all of the flavor's vCPUs and memory, for as long as the
+/// execution ran.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct NamespaceUsage {
+    /// Number of executions accounted
+    pub executions: u64,
+    /// Total execution time in seconds
+    pub execution_seconds: f64,
+    /// vCPU-seconds allocated to executions
+    pub cpu_seconds: f64,
+    /// Memory-MB-seconds allocated to executions
+    pub memory_mb_seconds: f64,
+}
+
+/// Response structure for usage queries
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UsageResponse {
+    /// Usage per namespace; a requested namespace without executions reports zero usage
+    pub namespaces: BTreeMap<String, NamespaceUsage>,
+}
+
+/// Kind of execution recorded in a session's history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutionKind {
+    /// Code executed through `execute_code`
+    Code,
+    /// Command executed through `execute_command`
+    Command,
+    /// Automatic restart of the session's sandbox after it crashed
+    Restart,
+}
+
+/// A single execution recorded in a session's history
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExecutionHistoryEntry {
+    /// Position of the execution in the session, starting at 1
+    pub sequence: u64,
+    /// Whether code or a command was executed
+    pub kind: ExecutionKind,
+    /// The executed code or command line (truncated)
+    pub input: String,
+    /// Standard output from execution (truncated)
+    pub stdout: String,
+    /// Standard error from execution (truncated)
+    pub stderr: String,
+    /// Exit code, if the execution reported one
+    pub exit_code: Option<i32>,
+    /// Execution time in milliseconds
+    pub execution_time_ms: u64,
+    /// When the execution finished (RFC 3339)
+    pub timestamp: String,
+}
+
+/// Response structure for execution history queries
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExecutionHistoryResponse {
+    /// Session ID the history belongs to
+    pub session_id: String,
+    /// History entries, oldest first
+    pub entries: Vec<ExecutionHistoryEntry>,
+    /// Number of entries currently retained for the session
+    pub total: usize,
+    /// Offset of the first returned entry
+    pub offset: usize,
+    /// Whether more entries are available after this page
+    pub has_more: bool,
+    /// Number of older entries that were dropped because the history is capped
+    pub dropped: u64,
+}
+
 /// Response structure for session stop operations
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct StopSessionResponse {
@@ -642,7 +1095,260 @@ impl TemplateMapping {
 //--------------------------------------------------------------------------------------------------
 
 use std::env;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+use microsandbox_core::config::PathPair;
+use crate::import_policy::ImportPolicy;
+use crate::redact::{Redactor, DEFAULT_REDACT_PATTERNS};
+
+/// Placeholder in the shared volume path that is replaced with a session's namespace
+pub const NAMESPACE_PLACEHOLDER: &str = "{namespace}";
+
+/// Largest code submission accepted when `MSB_MAX_CODE_BYTES` is not set (1 MiB)
+pub const DEFAULT_MAX_CODE_BYTES: usize = 1024 * 1024;
+
+/// Most arguments a command may have when `MSB_MAX_COMMAND_ARGS` is not set
+pub const DEFAULT_MAX_COMMAND_ARGS: usize = 1024;
+
+/// Largest combined length of a command's arguments when `MSB_MAX_COMMAND_ARGS_BYTES` is not
+/// set (256 KiB)
+pub const DEFAULT_MAX_COMMAND_ARGS_BYTES: usize = 256 * 1024;
+
+/// Queue wait after which an execution is treated as one priority higher when
+/// `MSB_PRIORITY_AGING_SECONDS` is not set
+pub const DEFAULT_PRIORITY_AGING_SECONDS: u64 = 30;
+
+/// How long a stopped session stays queryable before it is removed from tracking when
+/// `MSB_STOPPED_SESSION_RETENTION_SECONDS` is not set
+pub const DEFAULT_STOPPED_SESSION_RETENTION_SECONDS: u64 = 300;
+
+/// Largest number of times the sandbox of a session may be restarted after it crashed
+pub const MAX_SESSION_RESTARTS_LIMIT: u32 = 10;
+
+/// Shell sandboxes run scripts and commands with when `MSB_DEFAULT_SHELL` is not set
+///
+/// Images without it fall back to another shell they have when the sandbox starts.
+pub const DEFAULT_GUEST_SHELL: &str = "/bin/bash";
+
+/// Get the existing directory a shared volume path lives under
+///
+/// For a per-namespace template this is the path up to the component containing the placeholder.
+fn shared_volume_root(path: &Path) -> PathBuf {
+    path.components()
+        .take_while(|c| !c.as_os_str().to_string_lossy().contains(NAMESPACE_PLACEHOLDER))
+        .collect()
+}
+
+/// Check whether an absolute host path is within one of the allowed host paths
+///
+/// Both sides are resolved with [`resolve_host_path`] and compared by component, so e.g.
+/// /data2 is not within /data.
+fn is_host_path_allowed(path: &Path, allowed: &[PathBuf]) -> bool {
+    let path = resolve_host_path(path);
+    allowed.iter().any(|prefix| path.starts_with(resolve_host_path(prefix)))
+}
+
+/// Resolve the symlinks of an absolute host path that has no `..` components
+///
+/// The longest part of the path that exists is canonicalized and the rest appended, so a path
+/// that is yet to be created is resolved like its existing parent.
+fn resolve_host_path(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(canonical) = std::fs::canonicalize(existing) {
+            return rest.iter().rev().fold(canonical, |resolved, name| resolved.join(name));
+        }
+
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name);
+                existing = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+/// Guess the template code is written for from its shebang line and language idioms
+///
+/// Returns `None` when the code carries no signals or signals for both languages equally.
+pub fn detect_template(code: &str) -> Option<&'static str> {
+    if let Some(shebang) = code.lines().next().and_then(|line| line.strip_prefix("#!")) {
+        if shebang.contains("python") {
+            return Some("python");
+        }
+        if shebang.contains("node") {
+            return Some("node");
+        }
+    }
+
+    let (mut python, mut node) = (0, 0);
+    for line in code.lines().map(str::trim) {
+        if line.starts_with("from ") && line.contains(" import ")
+            || line.starts_with("def ")
+            || line.starts_with("elif ")
+            || line.starts_with("print(")
+            || line.starts_with("import ") && !line.contains(" from ") && !line.ends_with(';')
+        {
+            python += 1;
+        }
+        if line.contains("require(")
+            || line.contains("console.log(")
+            || line.starts_with("const ")
+            || line.starts_with("let ")
+            || line.starts_with("function ")
+            || line.starts_with("import ") && (line.contains(" from ") || line.ends_with(';'))
+        {
+            node += 1;
+        }
+    }
+
+    match python.cmp(&node) {
+        std::cmp::Ordering::Greater => Some("python"),
+        std::cmp::Ordering::Less => Some("node"),
+        std::cmp::Ordering::Equal => None,
+    }
+}
+
+/// Rules auto flavor selection uses when `MSB_FLAVOR_RULES` is not set
+///
+/// Machine learning frameworks get a large sandbox, data analysis libraries and submissions
+/// carrying more than 256 KiB of inline data a medium one.
+pub const DEFAULT_FLAVOR_RULES: &str = "torch=large,tensorflow=large,keras=large,jax=large,\
+    transformers=large,pandas=medium,numpy=medium,scipy=medium,sklearn=medium,polars=medium,\
+    >262144=medium";
+
+/// What a flavor rule matches in code or a command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlavorPattern {
+    /// The word appears on its own, e.g. as a module that is imported or a package installed
+    Word(String),
+    /// The code or command is longer than this many bytes, e.g. because it embeds data
+    LargerThan(usize),
+}
+
+/// A rule of the table auto flavor selection picks flavors with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlavorRule {
+    /// What the rule matches
+    pub pattern: FlavorPattern,
+    /// Flavor picked when the rule matches
+    pub flavor: SandboxFlavor,
+}
+
+impl std::str::FromStr for FlavorRule {
+    type Err = SimplifiedMcpError;
+
+    /// Parse a `<word>=<flavor>` or `><bytes>=<flavor>` rule
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            SimplifiedMcpError::ConfigurationError(format!(
+                "Invalid flavor rule '{}': expected <word>=<flavor> or ><bytes>=<flavor>",
+                s
+            ))
+        };
+
+        let (pattern, flavor) = s.split_once('=').ok_or_else(invalid)?;
+        let (pattern, flavor) = (pattern.trim(), flavor.trim());
+        let is_word = !pattern.is_empty() && pattern.chars().all(|c| c.is_alphanumeric() || c == '_');
+        let pattern = match pattern.strip_prefix('>') {
+            Some(bytes) => {
+                FlavorPattern::LargerThan(bytes.trim().parse().map_err(|_| invalid())?)
+            }
+            None if is_word => FlavorPattern::Word(pattern.to_string()),
+            None => return Err(invalid()),
+        };
+
+        Ok(Self {
+            pattern,
+            flavor: flavor.parse()?,
+        })
+    }
+}
+
+/// Parse a comma-separated table of flavor rules
+pub fn parse_flavor_rules(rules: &str) -> Result<Vec<FlavorRule>, SimplifiedMcpError> {
+    rules
+        .split(',')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// Parse comma-separated `<namespace>=<sessions>` overrides of the per-namespace session limit
+pub fn parse_namespace_max_sessions(limits: &str) -> Result<HashMap<String, usize>, SimplifiedMcpError> {
+    limits
+        .split(',')
+        .map(str::trim)
+        .filter(|limit| !limit.is_empty())
+        .map(|limit| {
+            limit
+                .split_once('=')
+                .and_then(|(namespace, sessions)| {
+                    let namespace = namespace.trim();
+                    let sessions = sessions.trim().parse::<usize>().ok()?;
+                    (!namespace.is_empty()).then(|| (namespace.to_string(), sessions))
+                })
+                .ok_or_else(|| {
+                    SimplifiedMcpError::ConfigurationError(format!(
+                        "Invalid namespace session limit '{}': expected <namespace>=<sessions>",
+                        limit
+                    ))
+                })
+        })
+        .collect()
+}
+
+/// Pick the flavor for code or a command from the rules it matches
+///
+/// The largest flavor of all matching rules wins, so code importing both numpy and torch gets
+/// the flavor torch asks for. Code matching no rule gets the small flavor.
+pub fn detect_flavor(text: &str, rules: &[FlavorRule]) -> SandboxFlavor {
+    let words: HashSet<&str> = text
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    rules
+        .iter()
+        .filter(|rule| match &rule.pattern {
+            FlavorPattern::Word(word) => words.contains(word.as_str()),
+            FlavorPattern::LargerThan(bytes) => text.len() > *bytes,
+        })
+        .map(|rule| rule.flavor)
+        .max_by_key(|flavor| flavor.get_memory_mb())
+        .unwrap_or_default()
+}
+
+/// What the server warms when it starts, so the first requests do not pay for it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WarmOnStart {
+    /// Nothing is warmed
+    #[default]
+    Off,
+    /// The images of the warmed templates are pulled
+    Images,
+    /// The images are pulled and a sandbox of each warmed template is booted once, with the
+    /// default flavor, and stopped again
+    Sandboxes,
+}
+
+impl std::str::FromStr for WarmOnStart {
+    type Err = SimplifiedMcpError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "off" | "false" => Ok(Self::Off),
+            "images" => Ok(Self::Images),
+            "sandboxes" => Ok(Self::Sandboxes),
+            _ => Err(SimplifiedMcpError::ConfigurationError(format!(
+                "Invalid warm on start mode '{}': expected off, images or sandboxes",
+                s
+            ))),
+        }
+    }
+}
 
 /// Configuration manager for simplified MCP operations
 /// 
@@ -650,7 +1356,7 @@ use std::path::PathBuf;
 /// for the simplified MCP interface.
 #[derive(Debug, Clone)]
 pub struct ConfigurationManager {
-    /// Optional path to shared volume on host system
+    /// Optional path to shared volume on host system, which may contain a `{namespace}` placeholder
     shared_volume_path: Option<PathBuf>,
     /// Path to shared volume inside sandbox containers
     shared_volume_guest_path: String,
@@ -658,22 +1364,86 @@ pub struct ConfigurationManager {
     default_flavor: SandboxFlavor,
     /// Default sandbox template when not specified
     default_template: String,
+    /// Whether code submitted without a template is inspected to pick one
+    detect_template: bool,
+    /// Rules that pick the flavor of sessions requested with the auto flavor
+    flavor_rules: Vec<FlavorRule>,
+    /// Shell that sandbox scripts and commands are run with
+    default_shell: String,
     /// Session timeout duration
     session_timeout: Duration,
+    /// How long stopped sessions stay queryable before they are removed from tracking (zero removes them right away)
+    stopped_session_retention: Duration,
+    /// How many times a session's sandbox is restarted after it crashed (zero leaves crashed sessions failed)
+    max_session_restarts: u32,
     /// Maximum number of concurrent sessions
     max_sessions: usize,
+    /// Maximum number of concurrent sessions owned by a single namespace (None limits namespaces only by `max_sessions`)
+    max_sessions_per_namespace: Option<usize>,
+    /// Per-namespace overrides of `max_sessions_per_namespace`
+    namespace_max_sessions: HashMap<String, usize>,
+    /// Maximum number of concurrent executions across all namespaces
+    max_concurrent_executions: usize,
+    /// How long a queued execution waits before it is treated as one priority higher
+    priority_aging: Duration,
+    /// Maximum number of executions kept in each session's history
+    max_execution_history: usize,
+    /// Programs that command execution is restricted to (None allows any program)
+    allowed_commands: Option<BTreeSet<String>>,
+    /// Host directories that sandbox volumes are restricted to (None allows any host path)
+    allowed_host_paths: Option<Vec<PathBuf>>,
+    /// Modules that executed code may import, checked before it runs on a best-effort basis
+    import_policy: ImportPolicy,
+    /// Largest code submission accepted for execution, in bytes
+    max_code_bytes: usize,
+    /// Most arguments a command may have
+    max_command_args: usize,
+    /// Largest combined length of a command's arguments, in bytes
+    max_command_args_bytes: usize,
+    /// Largest request body the RPC and MCP endpoints accept, in bytes
+    max_body_size: usize,
+    /// Name patterns whose values are masked in logs and execution history
+    redact_patterns: Vec<String>,
+    /// What the server warms when it starts, before it accepts requests
+    warm_on_start: WarmOnStart,
+    /// Templates whose images, and sandboxes, are warmed when the server starts
+    warm_templates: Vec<String>,
+    /// Code run before every execution in sessions of a template, by template
+    template_preambles: BTreeMap<String, String>,
 }
 
 impl ConfigurationManager {
     /// Create a new ConfigurationManager from environment variables
     /// 
     /// Environment variables:
-    /// - `MSB_SHARED_VOLUME_PATH`: Host path for shared volume (optional)
+    /// - `MSB_SHARED_VOLUME_PATH`: Host path for shared volume (optional); a `{namespace}` placeholder gives each namespace its own directory, e.g. "/data/{namespace}"
     /// - `MSB_SHARED_VOLUME_GUEST_PATH`: Guest path for shared volume (default: "/shared")
     /// - `MSB_DEFAULT_FLAVOR`: Default sandbox flavor (default: "small")
     /// - `MSB_DEFAULT_TEMPLATE`: Default sandbox template (default: "python")
+    /// - `MSB_DEFAULT_SHELL`: Shell scripts and commands run with, replaced by one the image has if it lacks it (default: "/bin/bash")
     /// - `MSB_SESSION_TIMEOUT_SECONDS`: Session timeout in seconds (default: 1800)
+    /// - `MSB_STOPPED_SESSION_RETENTION_SECONDS`: How long stopped sessions stay queryable before they are removed, 0 to remove them right away (default: 300)
+    /// - `MSB_MAX_SESSION_RESTARTS`: How many times a session's crashed sandbox is restarted, at most 10 (default: 0, disabled)
     /// - `MSB_MAX_SESSIONS`: Maximum concurrent sessions (default: 10)
+    /// - `MSB_MAX_SESSIONS_PER_NAMESPACE`: Maximum concurrent sessions owned by a single namespace (default: 0, limited only by `MSB_MAX_SESSIONS`)
+    /// - `MSB_NAMESPACE_MAX_SESSIONS`: Comma-separated `<namespace>=<sessions>` overrides of the per-namespace maximum (default: none)
+    /// - `MSB_MAX_CONCURRENT_EXECUTIONS`: Maximum concurrent executions (default: 10)
+    /// - `MSB_PRIORITY_AGING_SECONDS`: Queue wait after which an execution is treated as one priority higher (default: 30)
+    /// - `MSB_MAX_EXECUTION_HISTORY`: Executions kept in each session's history (default: 100)
+    /// - `MSB_ALLOWED_COMMANDS`: Comma-separated programs that commands are restricted to (default: unrestricted)
+    /// - `MSB_ALLOWED_HOST_PATHS`: Comma-separated absolute host directories that sandbox volumes may mount, which must include the shared volume path (default: unrestricted)
+    /// - `MSB_ALLOWED_IMPORTS`: Comma-separated modules that executed code may import, checked best-effort (default: unrestricted)
+    /// - `MSB_DENIED_IMPORTS`: Comma-separated modules that executed code may not import, checked best-effort (default: none)
+    /// - `MSB_DETECT_TEMPLATE`: Infer python or node from code submitted without a template (default: false)
+    /// - `MSB_FLAVOR_RULES`: Comma-separated `<word>=<flavor>` and `><bytes>=<flavor>` rules the auto flavor is picked with (default: [`DEFAULT_FLAVOR_RULES`])
+    /// - `MSB_MAX_CODE_BYTES`: Largest code submission accepted for execution, in bytes (default: 1048576)
+    /// - `MSB_MAX_COMMAND_ARGS`: Most arguments a command may have (default: 1024)
+    /// - `MSB_MAX_COMMAND_ARGS_BYTES`: Largest combined length of a command's arguments, in bytes (default: 262144)
+    /// - `MSB_MAX_BODY_SIZE`: Largest request body the RPC and MCP endpoints accept, in bytes (default: 10485760, the portal's limit)
+    /// - `MSB_REDACT_PATTERNS`: Comma-separated name patterns whose values are redacted (default: "*_TOKEN,*_KEY,*_SECRET,*_PASSWORD")
+    /// - `MSB_WARM_ON_START`: What is warmed before the server accepts requests: `off`, `images` to pull the images of the warmed templates, or `sandboxes` to also boot a sandbox of each once (default: "off")
+    /// - `MSB_WARM_TEMPLATES`: Comma-separated templates warmed on start (default: the default template)
+    /// - `MSB_PYTHON_PREAMBLE`, `MSB_NODE_PREAMBLE`: Code run before every execution in sessions of the template, e.g. "import numpy as np" (default: none)
     pub fn from_env() -> Result<Self, SimplifiedMcpError> {
         let shared_volume_path = env::var("MSB_SHARED_VOLUME_PATH")
             .ok()
@@ -693,23 +1463,169 @@ impl ConfigurationManager {
             .unwrap_or(SandboxFlavor::Small);
         let default_template = env::var("MSB_DEFAULT_TEMPLATE").unwrap_or_else(|_| "python".to_string());
 
+        let detect_template = env::var("MSB_DETECT_TEMPLATE")
+            .ok()
+            .and_then(|s| s.parse::<bool>().ok())
+            .unwrap_or(false);
+        let flavor_rules = parse_flavor_rules(
+            &env::var("MSB_FLAVOR_RULES").unwrap_or_else(|_| DEFAULT_FLAVOR_RULES.to_string()),
+        )?;
+        let default_shell = env::var("MSB_DEFAULT_SHELL").unwrap_or_else(|_| DEFAULT_GUEST_SHELL.to_string());
+
         let session_timeout_seconds = env::var("MSB_SESSION_TIMEOUT_SECONDS")
             .ok()
             .and_then(|s| s.parse::<u64>().ok())
             .unwrap_or(1800); // 30 minutes default
 
+        let stopped_session_retention_seconds = env::var("MSB_STOPPED_SESSION_RETENTION_SECONDS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_STOPPED_SESSION_RETENTION_SECONDS);
+
+        let max_session_restarts = env::var("MSB_MAX_SESSION_RESTARTS")
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(0);
+
         let max_sessions = env::var("MSB_MAX_SESSIONS")
             .ok()
             .and_then(|s| s.parse::<usize>().ok())
             .unwrap_or(10);
 
+        let max_sessions_per_namespace = env::var("MSB_MAX_SESSIONS_PER_NAMESPACE")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .filter(|&sessions| sessions > 0);
+
+        let namespace_max_sessions = env::var("MSB_NAMESPACE_MAX_SESSIONS")
+            .map(|s| parse_namespace_max_sessions(&s))
+            .unwrap_or_else(|_| Ok(HashMap::new()))?;
+
+        let max_concurrent_executions = env::var("MSB_MAX_CONCURRENT_EXECUTIONS")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(10);
+
+        let priority_aging_seconds = env::var("MSB_PRIORITY_AGING_SECONDS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_PRIORITY_AGING_SECONDS);
+
+        let max_execution_history = env::var("MSB_MAX_EXECUTION_HISTORY")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(100);
+
+        let allowed_commands = env::var("MSB_ALLOWED_COMMANDS")
+            .ok()
+            .map(|s| {
+                s.split(',')
+                    .map(|c| c.trim().to_string())
+                    .filter(|c| !c.is_empty())
+                    .collect::<BTreeSet<_>>()
+            })
+            .filter(|commands| !commands.is_empty());
+
+        let allowed_host_paths = env::var("MSB_ALLOWED_HOST_PATHS")
+            .ok()
+            .map(|s| {
+                s.split(',')
+                    .map(str::trim)
+                    .filter(|p| !p.is_empty())
+                    .map(PathBuf::from)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|paths| !paths.is_empty());
+
+        let import_list = |var: &str| {
+            env::var(var).ok().map(|s| s.split(',').map(str::to_string).collect::<Vec<_>>())
+        };
+        let import_policy = ImportPolicy::new(
+            import_list("MSB_ALLOWED_IMPORTS"),
+            import_list("MSB_DENIED_IMPORTS").unwrap_or_default(),
+        );
+
+        let max_code_bytes = env::var("MSB_MAX_CODE_BYTES")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_CODE_BYTES);
+
+        let max_command_args = env::var("MSB_MAX_COMMAND_ARGS")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_COMMAND_ARGS);
+
+        let max_command_args_bytes = env::var("MSB_MAX_COMMAND_ARGS_BYTES")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_COMMAND_ARGS_BYTES);
+
+        let max_body_size = env::var("MSB_MAX_BODY_SIZE")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_PORTAL_MAX_BODY_SIZE);
+
+        let redact_patterns = env::var("MSB_REDACT_PATTERNS")
+            .map(|s| {
+                s.split(',')
+                    .map(|p| p.trim().to_string())
+                    .filter(|p| !p.is_empty())
+                    .collect()
+            })
+            .unwrap_or_else(|_| DEFAULT_REDACT_PATTERNS.iter().map(|p| p.to_string()).collect());
+
+        let warm_on_start = env::var("MSB_WARM_ON_START")
+            .map(|s| s.parse::<WarmOnStart>())
+            .unwrap_or(Ok(WarmOnStart::Off))?;
+
+        let warm_templates = env::var("MSB_WARM_TEMPLATES")
+            .ok()
+            .map(|s| {
+                s.split(',')
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|templates| !templates.is_empty())
+            .unwrap_or_else(|| vec![default_template.clone()]);
+
+        let template_preambles = TemplateMapping::default()
+            .supported_templates()
+            .into_iter()
+            .filter_map(|template| {
+                let preamble = env::var(format!("MSB_{}_PREAMBLE", template.to_uppercase())).ok()?;
+                (!preamble.trim().is_empty()).then(|| (template.clone(), preamble))
+            })
+            .collect();
+
         let config = Self {
             shared_volume_path,
             shared_volume_guest_path,
             default_flavor,
             default_template,
+            detect_template,
+            flavor_rules,
+            default_shell,
             session_timeout: Duration::from_secs(session_timeout_seconds),
+            stopped_session_retention: Duration::from_secs(stopped_session_retention_seconds),
+            max_session_restarts,
             max_sessions,
+            max_sessions_per_namespace,
+            namespace_max_sessions,
+            max_concurrent_executions,
+            priority_aging: Duration::from_secs(priority_aging_seconds),
+            max_execution_history,
+            allowed_commands,
+            allowed_host_paths,
+            import_policy,
+            max_code_bytes,
+            max_command_args,
+            max_command_args_bytes,
+            max_body_size,
+            redact_patterns,
+            warm_on_start,
+            warm_templates,
+            template_preambles,
         };
 
         // Validate configuration
@@ -725,15 +1641,37 @@ impl ConfigurationManager {
             shared_volume_guest_path: "/shared".to_string(),
             default_flavor: SandboxFlavor::Small,
             default_template: "python".to_string(),
+            detect_template: false,
+            flavor_rules: parse_flavor_rules(DEFAULT_FLAVOR_RULES).expect("default flavor rules are valid"),
+            default_shell: DEFAULT_GUEST_SHELL.to_string(),
             session_timeout: Duration::from_secs(1800), // 30 minutes
+            stopped_session_retention: Duration::from_secs(DEFAULT_STOPPED_SESSION_RETENTION_SECONDS),
+            max_session_restarts: 0,
             max_sessions: 10,
+            max_sessions_per_namespace: None,
+            namespace_max_sessions: HashMap::new(),
+            max_concurrent_executions: 10,
+            priority_aging: Duration::from_secs(DEFAULT_PRIORITY_AGING_SECONDS),
+            max_execution_history: 100,
+            allowed_commands: None,
+            allowed_host_paths: None,
+            import_policy: ImportPolicy::default(),
+            max_code_bytes: DEFAULT_MAX_CODE_BYTES,
+            max_command_args: DEFAULT_MAX_COMMAND_ARGS,
+            max_command_args_bytes: DEFAULT_MAX_COMMAND_ARGS_BYTES,
+            max_body_size: DEFAULT_PORTAL_MAX_BODY_SIZE,
+            redact_patterns: DEFAULT_REDACT_PATTERNS.iter().map(|p| p.to_string()).collect(),
+            warm_on_start: WarmOnStart::Off,
+            warm_templates: vec!["python".to_string()],
+            template_preambles: BTreeMap::new(),
         }
     }
 
     /// Validate the configuration
     pub fn validate(&self) -> Result<(), SimplifiedMcpError> {
-        // Validate shared volume path exists if specified
-        if let Some(ref path) = self.shared_volume_path {
+        // Validate shared volume path exists if specified; for a per-namespace template, the
+        // directory the namespace directories are created in must exist
+        if let Some(path) = self.shared_volume_path.as_ref().map(|p| shared_volume_root(p)) {
             if !path.exists() {
                 return Err(SimplifiedMcpError::ConfigurationError(
                     format!("Shared volume path does not exist: {}", path.display())
@@ -753,14 +1691,35 @@ impl ConfigurationManager {
             ));
         }
 
-        // Validate session timeout is reasonable (between 1 minute and 24 hours)
-        let timeout_secs = self.session_timeout.as_secs();
+        // Validate default shell is an absolute guest path
+        if !self.default_shell.starts_with('/') {
+            return Err(SimplifiedMcpError::ConfigurationError(
+                format!("Default shell must be an absolute path: {}", self.default_shell)
+            ));
+        }
+
+        // Validate session timeout is reasonable (between 1 minute and 24 hours)
+        let timeout_secs = self.session_timeout.as_secs();
         if timeout_secs < 60 || timeout_secs > 86400 {
             return Err(SimplifiedMcpError::ConfigurationError(
                 format!("Session timeout must be between 60 and 86400 seconds, got: {}", timeout_secs)
             ));
         }
 
+        // Validate stopped session retention is reasonable (at most 24 hours)
+        if self.stopped_session_retention.as_secs() > 86400 {
+            return Err(SimplifiedMcpError::ConfigurationError(
+                format!("Stopped session retention must be at most 86400 seconds, got: {}", self.stopped_session_retention.as_secs())
+            ));
+        }
+
+        // Validate crashed sandboxes are not restarted endlessly
+        if self.max_session_restarts > MAX_SESSION_RESTARTS_LIMIT {
+            return Err(SimplifiedMcpError::ConfigurationError(
+                format!("Max session restarts must be at most {}, got: {}", MAX_SESSION_RESTARTS_LIMIT, self.max_session_restarts)
+            ));
+        }
+
         // Validate max sessions is reasonable (between 1 and 100)
         if self.max_sessions == 0 || self.max_sessions > 100 {
             return Err(SimplifiedMcpError::ConfigurationError(
@@ -768,6 +1727,110 @@ impl ConfigurationManager {
             ));
         }
 
+        // Validate per-namespace session limits are within the global one
+        let namespace_limits = self
+            .max_sessions_per_namespace
+            .iter()
+            .map(|&sessions| ("default", sessions))
+            .chain(self.namespace_max_sessions.iter().map(|(namespace, &sessions)| (namespace.as_str(), sessions)));
+        for (namespace, sessions) in namespace_limits {
+            if sessions == 0 || sessions > self.max_sessions {
+                return Err(SimplifiedMcpError::ConfigurationError(format!(
+                    "Max sessions of namespace {} must be between 1 and the max sessions ({}), got: {}",
+                    namespace, self.max_sessions, sessions
+                )));
+            }
+        }
+
+        // Validate max concurrent executions is reasonable (between 1 and 1000)
+        if self.max_concurrent_executions == 0 || self.max_concurrent_executions > 1000 {
+            return Err(SimplifiedMcpError::ConfigurationError(
+                format!("Max concurrent executions must be between 1 and 1000, got: {}", self.max_concurrent_executions)
+            ));
+        }
+
+        // Validate priority aging is reasonable (between 1 second and 1 hour)
+        let aging_secs = self.priority_aging.as_secs();
+        if aging_secs == 0 || aging_secs > 3600 {
+            return Err(SimplifiedMcpError::ConfigurationError(
+                format!("Priority aging must be between 1 and 3600 seconds, got: {}", aging_secs)
+            ));
+        }
+
+        // Validate max execution history is reasonable (between 1 and 10000)
+        if self.max_execution_history == 0 || self.max_execution_history > 10000 {
+            return Err(SimplifiedMcpError::ConfigurationError(
+                format!("Max execution history must be between 1 and 10000, got: {}", self.max_execution_history)
+            ));
+        }
+
+        // Validate allowed host paths are absolute and contain the shared volume
+        if let Some(allowed) = &self.allowed_host_paths {
+            if let Some(path) = allowed
+                .iter()
+                .find(|p| !p.is_absolute() || p.components().any(|c| c == Component::ParentDir))
+            {
+                return Err(SimplifiedMcpError::ConfigurationError(
+                    format!("Allowed host paths must be absolute paths without '..', got: {}", path.display())
+                ));
+            }
+            if let Some(path) = self.shared_volume_path.as_ref().map(|p| shared_volume_root(p)) {
+                if !is_host_path_allowed(&path, allowed) {
+                    return Err(SimplifiedMcpError::ConfigurationError(
+                        format!("Shared volume path is outside the allowed host paths: {}", path.display())
+                    ));
+                }
+            }
+        }
+
+        // Validate max code size is reasonable (between 1 KiB and the portal's request body limit)
+        if self.max_code_bytes < 1024 || self.max_code_bytes > DEFAULT_PORTAL_MAX_BODY_SIZE {
+            return Err(SimplifiedMcpError::ConfigurationError(
+                format!("Max code bytes must be between 1024 and {}, got: {}", DEFAULT_PORTAL_MAX_BODY_SIZE, self.max_code_bytes)
+            ));
+        }
+
+        // Validate command argument limits allow at least one argument and fit in a request
+        if self.max_command_args == 0 {
+            return Err(SimplifiedMcpError::ConfigurationError(
+                "Max command args must be greater than 0".to_string()
+            ));
+        }
+        if self.max_command_args_bytes < 1024 || self.max_command_args_bytes > DEFAULT_PORTAL_MAX_BODY_SIZE {
+            return Err(SimplifiedMcpError::ConfigurationError(
+                format!("Max command args bytes must be between 1024 and {}, got: {}", DEFAULT_PORTAL_MAX_BODY_SIZE, self.max_command_args_bytes)
+            ));
+        }
+
+        // Validate the body size limit leaves room for the largest code submission
+        if self.max_body_size < self.max_code_bytes {
+            return Err(SimplifiedMcpError::ConfigurationError(
+                format!("Max body size must be at least the max code bytes ({}), got: {}", self.max_code_bytes, self.max_body_size)
+            ));
+        }
+
+        // Validate the warmed templates only when warming is enabled
+        if self.warm_on_start != WarmOnStart::Off {
+            let mapping = TemplateMapping::default();
+            if let Some(template) = self.warm_templates.iter().find(|t| !mapping.is_supported(t)) {
+                return Err(SimplifiedMcpError::ConfigurationError(
+                    format!("Cannot warm unsupported template: {}", template)
+                ));
+            }
+        }
+
+        // Validate preambles fit in a code submission, as they are sent along with the code
+        for (template, preamble) in &self.template_preambles {
+            if preamble.len() > self.max_code_bytes {
+                return Err(SimplifiedMcpError::ConfigurationError(format!(
+                    "Preamble of template {} is {} bytes, which exceeds the max code bytes of {}",
+                    template,
+                    preamble.len(),
+                    self.max_code_bytes
+                )));
+            }
+        }
+
         Ok(())
     }
 
@@ -776,6 +1839,57 @@ impl ConfigurationManager {
         self.shared_volume_path.as_ref()
     }
 
+    /// Check if the shared volume is isolated per namespace
+    pub fn is_shared_volume_per_namespace(&self) -> bool {
+        self.shared_volume_path
+            .as_ref()
+            .is_some_and(|p| p.to_string_lossy().contains(NAMESPACE_PLACEHOLDER))
+    }
+
+    /// Resolve the shared volume host path for a namespace
+    ///
+    /// For a per-namespace template the `{namespace}` placeholder is substituted and the
+    /// directory is created on first use. A plain path is returned unchanged.
+    pub fn resolve_shared_volume_path(
+        &self,
+        namespace: &str,
+    ) -> Result<Option<PathBuf>, SimplifiedMcpError> {
+        let Some(template) = &self.shared_volume_path else {
+            return Ok(None);
+        };
+
+        if !self.is_shared_volume_per_namespace() {
+            return Ok(Some(template.clone()));
+        }
+
+        // The namespace becomes a single path component, so it must not escape the volume root
+        if namespace.is_empty()
+            || namespace == "."
+            || namespace == ".."
+            || namespace.contains(['/', '\\'])
+        {
+            return Err(SimplifiedMcpError::ValidationError(format!(
+                "Invalid namespace for shared volume: '{}'",
+                namespace
+            )));
+        }
+
+        let path = PathBuf::from(
+            template
+                .to_string_lossy()
+                .replace(NAMESPACE_PLACEHOLDER, namespace),
+        );
+        std::fs::create_dir_all(&path).map_err(|e| {
+            SimplifiedMcpError::InternalError(format!(
+                "Failed to create shared volume directory {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+        Ok(Some(path))
+    }
+
     /// Get the shared volume guest path (path inside sandbox containers)
     pub fn get_shared_volume_guest_path(&self) -> &str {
         &self.shared_volume_guest_path
@@ -791,16 +1905,290 @@ impl ConfigurationManager {
         &self.default_template
     }
 
+    /// Check if code submitted without a template is inspected to pick one
+    pub fn is_template_detection_enabled(&self) -> bool {
+        self.detect_template
+    }
+
+    /// Get the template to run code with when the request does not name one
+    ///
+    /// With detection enabled the template is inferred from the code, falling back to the
+    /// default when the code is ambiguous.
+    pub fn template_for_code(&self, code: &str) -> &str {
+        if self.detect_template {
+            if let Some(template) = detect_template(code) {
+                return template;
+            }
+        }
+
+        &self.default_template
+    }
+
+    /// Get the rules that pick the flavor of sessions requested with the auto flavor
+    pub fn get_flavor_rules(&self) -> &[FlavorRule] {
+        &self.flavor_rules
+    }
+
+    /// Get the flavor to create a session with for the code, command or packages it is for
+    ///
+    /// The auto flavor is picked from `text` with the flavor rules, and no flavor at all means
+    /// the small flavor.
+    pub fn flavor_for(&self, selection: Option<FlavorSelection>, text: &str) -> SandboxFlavor {
+        match selection {
+            Some(FlavorSelection::Fixed(flavor)) => flavor,
+            Some(FlavorSelection::Auto) => {
+                let flavor = detect_flavor(text, &self.flavor_rules);
+                tracing::debug!("Auto flavor selection picked the {} flavor", flavor);
+                flavor
+            }
+            None => SandboxFlavor::default(),
+        }
+    }
+
+    /// Get what the server warms when it starts
+    pub fn get_warm_on_start(&self) -> WarmOnStart {
+        self.warm_on_start
+    }
+
+    /// Get the templates warmed when the server starts
+    pub fn get_warm_templates(&self) -> &[String] {
+        &self.warm_templates
+    }
+
+    /// Dump every setting as JSON, for debugging
+    ///
+    /// Durations are reported in seconds, with null for the ones that are disabled.
+    pub fn dump_state(&self) -> serde_json::Value {
+        json!({
+            "shared_volume_path": self.shared_volume_path,
+            "shared_volume_guest_path": self.shared_volume_guest_path,
+            "default_flavor": self.default_flavor,
+            "default_template": self.default_template,
+            "detect_template": self.detect_template,
+            "flavor_rules": self.flavor_rules.iter().map(|rule| format!("{:?}", rule)).collect::<Vec<_>>(),
+            "default_shell": self.default_shell,
+            "session_timeout_seconds": self.session_timeout.as_secs(),
+            "stopped_session_retention_seconds": self.stopped_session_retention.as_secs(),
+            "max_session_restarts": self.max_session_restarts,
+            "max_sessions": self.max_sessions,
+            "max_sessions_per_namespace": self.max_sessions_per_namespace,
+            "namespace_max_sessions": self.namespace_max_sessions,
+            "max_concurrent_executions": self.max_concurrent_executions,
+            "priority_aging_seconds": self.priority_aging.as_secs(),
+            "max_execution_history": self.max_execution_history,
+            "allowed_commands": self.allowed_commands,
+            "allowed_host_paths": self.allowed_host_paths,
+            "import_policy": format!("{:?}", self.import_policy),
+            "max_code_bytes": self.max_code_bytes,
+            "max_command_args": self.max_command_args,
+            "max_command_args_bytes": self.max_command_args_bytes,
+            "max_body_size": self.max_body_size,
+            "redact_patterns": self.redact_patterns,
+            "warm_on_start": format!("{:?}", self.warm_on_start),
+            "warm_templates": self.warm_templates,
+            "template_preambles": self.template_preambles,
+        })
+    }
+
+    /// Get the code run before every execution in sessions of a template, if any
+    pub fn get_template_preamble(&self, template: &str) -> Option<&str> {
+        self.template_preambles.get(template).map(String::as_str)
+    }
+
+    /// Get the shell sandbox scripts and commands are run with
+    pub fn get_default_shell(&self) -> &str {
+        &self.default_shell
+    }
+
     /// Get the session timeout duration
     pub fn get_session_timeout(&self) -> Duration {
         self.session_timeout
     }
 
+    /// Get how long stopped sessions stay queryable before they are removed from tracking
+    pub fn get_stopped_session_retention(&self) -> Duration {
+        self.stopped_session_retention
+    }
+
+    /// Get how many times a session's sandbox is restarted after it crashed
+    pub fn get_max_session_restarts(&self) -> u32 {
+        self.max_session_restarts
+    }
+
     /// Get the maximum number of concurrent sessions
     pub fn get_max_sessions(&self) -> usize {
         self.max_sessions
     }
 
+    /// Get the maximum number of concurrent sessions a namespace may own, if limited
+    ///
+    /// A namespace's override takes precedence over the default per-namespace maximum.
+    pub fn get_max_sessions_for_namespace(&self, namespace: &str) -> Option<usize> {
+        self.namespace_max_sessions
+            .get(namespace)
+            .copied()
+            .or(self.max_sessions_per_namespace)
+    }
+
+    /// Get the maximum number of concurrent executions
+    pub fn get_max_concurrent_executions(&self) -> usize {
+        self.max_concurrent_executions
+    }
+
+    /// Get how long a queued execution waits before it is treated as one priority higher
+    pub fn get_priority_aging(&self) -> Duration {
+        self.priority_aging
+    }
+
+    /// Get the maximum number of executions kept in each session's history
+    pub fn get_max_execution_history(&self) -> usize {
+        self.max_execution_history
+    }
+
+    /// Get the name patterns whose values are redacted in logs and execution history
+    pub fn get_redact_patterns(&self) -> &[String] {
+        &self.redact_patterns
+    }
+
+    /// Get the programs that command execution is restricted to, if restricted
+    pub fn get_allowed_commands(&self) -> Option<&BTreeSet<String>> {
+        self.allowed_commands.as_ref()
+    }
+
+    /// Check that a command's program is permitted by the command allowlist
+    ///
+    /// Programs are matched exactly, so an allowlisted `python` does not permit `/usr/bin/python`.
+    pub fn check_command_allowed(&self, command: &str) -> Result<(), SimplifiedMcpError> {
+        match &self.allowed_commands {
+            Some(allowed) if !allowed.contains(command) => {
+                Err(SimplifiedMcpError::ValidationError(format!(
+                    "Command '{}' is not allowed. Allowed commands: {}",
+                    command,
+                    allowed.iter().cloned().collect::<Vec<_>>().join(", ")
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Get the host directories that sandbox volumes are restricted to, if restricted
+    pub fn get_allowed_host_paths(&self) -> Option<&[PathBuf]> {
+        self.allowed_host_paths.as_deref()
+    }
+
+    /// Check that a volume only mounts a host path within the allowed host paths
+    ///
+    /// Relative host paths are resolved against `base_dir`, the directory of the sandbox's
+    /// config, and symlinks are followed, so neither can take a mount outside the allowed paths.
+    /// Named volumes are managed under the microsandbox home and always allowed.
+    pub fn check_volume_allowed(&self, volume: &str, base_dir: &Path) -> Result<(), SimplifiedMcpError> {
+        let Some(allowed) = &self.allowed_host_paths else {
+            return Ok(());
+        };
+
+        let pair: PathPair = volume.parse().map_err(|e| {
+            SimplifiedMcpError::ValidationError(format!("Invalid volume '{}': {}", volume, e))
+        })?;
+        if pair.get_volume_name().is_some() {
+            return Ok(());
+        }
+
+        let host = Path::new(pair.get_host().as_str());
+        if host.components().any(|c| c == Component::ParentDir) {
+            return Err(SimplifiedMcpError::ValidationError(format!(
+                "Volume '{}' is not allowed: host paths must not contain '..'",
+                volume
+            )));
+        }
+
+        let host = base_dir.join(host);
+        if !is_host_path_allowed(&host, allowed) {
+            return Err(SimplifiedMcpError::ValidationError(format!(
+                "Volume '{}' mounts {}, which is outside the allowed host paths: {}",
+                volume,
+                resolve_host_path(&host).display(),
+                allowed.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Get the policy for modules that executed code may import
+    pub fn get_import_policy(&self) -> &ImportPolicy {
+        &self.import_policy
+    }
+
+    /// Check that code does not import modules the import policy disallows
+    ///
+    /// This only looks at import statements, so it is a first filter and not a security boundary.
+    pub fn check_imports(&self, code: &str, language: &str) -> Result<(), SimplifiedMcpError> {
+        let disallowed = self.import_policy.disallowed_imports(code, language);
+        if !disallowed.is_empty() {
+            return Err(SimplifiedMcpError::ValidationError(format!(
+                "Code imports disallowed module{}: {}",
+                if disallowed.len() == 1 { "" } else { "s" },
+                disallowed.join(", ")
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Get the largest code submission accepted for execution, in bytes
+    pub fn get_max_code_bytes(&self) -> usize {
+        self.max_code_bytes
+    }
+
+    /// Check that submitted code is within the configured size limit
+    pub fn check_code_size(&self, code: &str) -> Result<(), SimplifiedMcpError> {
+        if code.len() > self.max_code_bytes {
+            return Err(SimplifiedMcpError::ValidationError(format!(
+                "Code is {} bytes, which exceeds the limit of {} bytes",
+                code.len(),
+                self.max_code_bytes
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Get the most arguments a command may have
+    pub fn get_max_command_args(&self) -> usize {
+        self.max_command_args
+    }
+
+    /// Get the largest combined length of a command's arguments, in bytes
+    pub fn get_max_command_args_bytes(&self) -> usize {
+        self.max_command_args_bytes
+    }
+
+    /// Get the largest request body the RPC and MCP endpoints accept, in bytes
+    pub fn get_max_body_size(&self) -> usize {
+        self.max_body_size
+    }
+
+    /// Check that a command's arguments are within the configured count and combined length
+    pub fn check_command_args(&self, args: &[String]) -> Result<(), SimplifiedMcpError> {
+        if args.len() > self.max_command_args {
+            return Err(SimplifiedMcpError::ValidationError(format!(
+                "Command has {} arguments, which exceeds the limit of {} arguments",
+                args.len(),
+                self.max_command_args
+            )));
+        }
+
+        let bytes: usize = args.iter().map(String::len).sum();
+        if bytes > self.max_command_args_bytes {
+            return Err(SimplifiedMcpError::ValidationError(format!(
+                "Command arguments are {} bytes combined, which exceeds the limit of {} bytes",
+                bytes, self.max_command_args_bytes
+            )));
+        }
+
+        Ok(())
+    }
+
     /// Check if shared volume is configured
     pub fn has_shared_volume(&self) -> bool {
         self.shared_volume_path.is_some()
@@ -882,6 +2270,37 @@ pub struct SessionInfo {
     pub last_accessed: Instant,
     /// Current session status
     pub status: SessionStatus,
+    /// Most recent executions in the session, oldest first
+    pub history: VecDeque<ExecutionHistoryEntry>,
+    /// Total number of executions recorded, including entries dropped from the history
+    pub executions_recorded: u64,
+    /// Client the session is reusable by, if it was created in session affinity mode
+    pub affinity_client: Option<String>,
+    /// Packages installed into the sandbox when it starts
+    pub packages: Vec<String>,
+    /// Namespace of the API key the session was created with, if the key was limited to one
+    pub owner: Option<String>,
+    /// When the session was stopped, if it is stopped
+    pub stopped_at: Option<Instant>,
+    /// Number of times the session's sandbox was restarted after it crashed
+    pub restarts: u32,
+    /// Code run before every execution in the session, from its template unless overridden
+    pub preamble: Option<String>,
+}
+
+/// Who a request to the simplified MCP tools is made by, according to its API key
+///
+/// Sessions are owned by the namespace of the API key that created them. A key for one
+/// namespace can only use the sessions it owns, so that knowing another tenant's session ID is
+/// not enough to execute in, inspect or stop it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum SessionCaller {
+    /// Dev mode without authentication, or an API key for all namespaces, which can use every
+    /// session
+    #[default]
+    Unrestricted,
+    /// An API key for a single namespace
+    Namespace(String),
 }
 
 impl SessionInfo {
@@ -903,6 +2322,39 @@ impl SessionInfo {
             created_at: now,
             last_accessed: now,
             status: SessionStatus::Creating,
+            history: VecDeque::new(),
+            executions_recorded: 0,
+            affinity_client: None,
+            packages: Vec::new(),
+            owner: None,
+            stopped_at: None,
+            restarts: 0,
+            preamble: None,
+        }
+    }
+
+    /// Check if this is an idle session the client reuses for the template and flavor
+    fn is_reusable_by(
+        &self,
+        owner: Option<&str>,
+        client_id: &str,
+        template: &str,
+        flavor: SandboxFlavor,
+    ) -> bool {
+        self.owner.as_deref() == owner
+            && self.affinity_client.as_deref() == Some(client_id)
+            && self.language == template
+            && self.flavor == flavor
+            && self.status == SessionStatus::Ready
+    }
+
+    /// Record an execution in the history, dropping the oldest entries beyond `max_entries`
+    pub fn record_execution(&mut self, mut entry: ExecutionHistoryEntry, max_entries: usize) {
+        self.executions_recorded += 1;
+        entry.sequence = self.executions_recorded;
+        self.history.push_back(entry);
+        while self.history.len() > max_entries {
+            self.history.pop_front();
         }
     }
 
@@ -911,6 +2363,24 @@ impl SessionInfo {
         self.last_accessed = Instant::now();
     }
 
+    /// Set the session status, recording when the session was first stopped
+    pub fn set_status(&mut self, status: SessionStatus) {
+        self.stopped_at = match status {
+            SessionStatus::Stopped => Some(self.stopped_at.unwrap_or_else(Instant::now)),
+            _ => None,
+        };
+        self.status = status;
+    }
+
+    /// Check if the session is stopped and has been kept for the retention period
+    ///
+    /// Sessions whose status was set without [`SessionInfo::set_status`] count as stopped since
+    /// they were last accessed.
+    pub fn is_retired(&self, retention: Duration) -> bool {
+        self.status == SessionStatus::Stopped
+            && self.stopped_at.unwrap_or(self.last_accessed).elapsed() >= retention
+    }
+
     /// Get the session uptime in seconds
     pub fn uptime_seconds(&self) -> u64 {
         self.created_at.elapsed().as_secs()
@@ -955,6 +2425,106 @@ impl SessionInfo {
             created_at: format_instant_as_iso8601(self.created_at),
             last_accessed: format_instant_as_iso8601(self.last_accessed),
             uptime_seconds: self.uptime_seconds(),
+            packages: self.packages.clone(),
+        }
+    }
+}
+
+impl SessionCaller {
+    /// Get the caller for an API key with access to the given namespace, `*` for all of them
+    pub fn from_token_namespace(namespace: &str) -> Self {
+        match namespace {
+            "*" => Self::Unrestricted,
+            namespace => Self::Namespace(namespace.to_string()),
+        }
+    }
+
+    /// Get the owner recorded on the sessions the caller creates
+    pub fn owner(&self) -> Option<&str> {
+        match self {
+            Self::Unrestricted => None,
+            Self::Namespace(namespace) => Some(namespace),
+        }
+    }
+
+    /// Check if the caller may use a session
+    ///
+    /// Sessions created without an owner are only available to unrestricted callers.
+    pub fn can_access(&self, session: &SessionInfo) -> bool {
+        match self {
+            Self::Unrestricted => true,
+            Self::Namespace(namespace) => session.owner.as_deref() == Some(namespace),
+        }
+    }
+
+    /// Fail with [`SimplifiedMcpError::SessionAccessDenied`] if the caller may not use a session
+    pub fn check_access(&self, session: &SessionInfo) -> Result<(), SimplifiedMcpError> {
+        if !self.can_access(session) {
+            return Err(SimplifiedMcpError::SessionAccessDenied(session.id.clone()));
+        }
+
+        Ok(())
+    }
+
+    /// Check if the caller may read a namespace's data, `*` meaning all namespaces
+    ///
+    /// Callers scoped to a namespace only get their own, never `*`.
+    pub fn can_access_namespace(&self, namespace: &str) -> bool {
+        match self {
+            Self::Unrestricted => true,
+            Self::Namespace(own) => own == namespace,
+        }
+    }
+}
+
+/// A session that only lives for a single execution
+///
+/// The session is stopped and removed by [`EphemeralSession::teardown`]. If the guard is
+/// dropped before that, because the execution failed early, panicked or was cancelled, the
+/// teardown is spawned onto the runtime instead, so the session never outlives its execution.
+#[derive(Debug)]
+pub struct EphemeralSession {
+    /// The manager the session belongs to
+    manager: Arc<SessionManager>,
+    /// The session's ID, taken once the session is torn down
+    session_id: Option<String>,
+}
+
+impl EphemeralSession {
+    /// Get the ID of the session
+    pub fn session_id(&self) -> &str {
+        self.session_id.as_deref().unwrap_or_default()
+    }
+
+    /// Stop and remove the session
+    pub async fn teardown(mut self) -> Result<(), SimplifiedMcpError> {
+        match self.session_id.take() {
+            Some(session_id) => self.manager.teardown_session(&session_id).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for EphemeralSession {
+    fn drop(&mut self) {
+        let Some(session_id) = self.session_id.take() else {
+            return;
+        };
+
+        let manager = Arc::clone(&self.manager);
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move {
+                    if let Err(e) = manager.teardown_session(&session_id).await {
+                        tracing::error!("Failed to tear down ephemeral session {}: {}", session_id, e);
+                    }
+                });
+            }
+            Err(_) => {
+                // Without a runtime the sandbox can't be stopped, but the session must not linger
+                tracing::warn!("No runtime to stop ephemeral session {}, removing it", session_id);
+                let _ = manager.remove_session(&session_id);
+            }
         }
     }
 }
@@ -968,44 +2538,196 @@ pub struct SessionManager {
     config: ConfigurationManager,
     /// Template to image mapping
     template_mapping: TemplateMapping,
+    /// Notified whenever a session's status changes
+    status_changed: Arc<Notify>,
+    /// Masks secrets in logged request parameters and recorded executions
+    redactor: Redactor,
+    /// Resource-time accumulated per namespace
+    usage: Arc<UsageLedger>,
+    /// Whether new sessions are rejected while the server is under maintenance
+    maintenance: Arc<AtomicBool>,
+    /// Set once in-flight executions are cut short, to the reason they were
+    executions_cancelled: Arc<watch::Sender<Option<CancelReason>>>,
 }
 
 impl SessionManager {
+    /// How often the sandboxes of ready sessions are checked for crashes
+    const CRASH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+    /// How often usage that could not be persisted is written again
+    const USAGE_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
     /// Create a new SessionManager with the given configuration
     pub fn new(config: ConfigurationManager) -> Self {
+        let redactor = Redactor::new(config.get_redact_patterns());
         Self {
             sessions: Arc::new(RwLock::new(HashMap::new())),
             config,
             template_mapping: TemplateMapping::default(),
+            status_changed: Arc::new(Notify::new()),
+            redactor,
+            usage: Arc::new(UsageLedger::default()),
+            maintenance: Arc::new(AtomicBool::new(false)),
+            executions_cancelled: Arc::new(watch::Sender::new(None)),
         }
     }
 
-    /// Create a new session with the specified parameters
-    /// 
-    /// Returns the session ID on success
-    pub async fn create_session(
+    /// Use the given ledger to account the resource usage of executions
+    pub fn with_usage_ledger(mut self, usage: UsageLedger) -> Self {
+        self.usage = Arc::new(usage);
+        self
+    }
+
+    /// Get the redactor for secrets in logs and execution history
+    pub fn get_redactor(&self) -> &Redactor {
+        &self.redactor
+    }
+
+    /// Turn maintenance mode on or off, returning whether it was on before
+    ///
+    /// While maintenance mode is on, creating a session fails with
+    /// [`SimplifiedMcpError::ServerInMaintenance`]. Existing sessions are not affected.
+    pub fn set_maintenance(&self, enabled: bool) -> bool {
+        let was_enabled = self.maintenance.swap(enabled, Ordering::Relaxed);
+        if was_enabled != enabled {
+            tracing::info!("Maintenance mode turned {}", if enabled { "on" } else { "off" });
+        }
+        was_enabled
+    }
+
+    /// Check whether the server is in maintenance mode
+    pub fn is_in_maintenance(&self) -> bool {
+        self.maintenance.load(Ordering::Relaxed)
+    }
+
+    /// Cut all in-flight executions short, so that their results report the given reason
+    ///
+    /// Executions started afterwards are cut short as soon as they start, so this is only meant
+    /// for when the server is going away.
+    pub fn cancel_executions(&self, reason: CancelReason) {
+        tracing::info!("Cancelling in-flight executions: {}", reason);
+        self.executions_cancelled.send_replace(Some(reason));
+    }
+
+    /// Wait until in-flight executions are cut short and get the reason they were
+    pub async fn executions_cancelled(&self) -> CancelReason {
+        let mut receiver = self.executions_cancelled.subscribe();
+        let reason = match receiver.wait_for(Option::is_some).await {
+            Ok(reason) => *reason,
+            // The sender lives as long as the session manager, so this does not happen
+            Err(_) => None,
+        };
+
+        match reason {
+            Some(reason) => reason,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Check that a session for the template could be created right now, without creating it
+    ///
+    /// Fails if the server is in maintenance mode, the template is not supported, or the
+    /// maximum number of sessions of the owner's namespace, if any, or of the server is reached.
+    pub fn check_session_admission(
         &self,
         template: &str,
-        flavor: SandboxFlavor,
-    ) -> Result<String, SimplifiedMcpError> {
+        owner: Option<&str>,
+    ) -> Result<(), SimplifiedMcpError> {
+        let sessions = self.sessions.read().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire read lock: {}", e))
+        })?;
+
+        self.check_admission_in(&sessions, template, owner)
+    }
+
+    /// Check that a session for the template could be added to the given sessions
+    ///
+    /// Callers that go on to insert the session hold the write lock the sessions were read
+    /// under, so no other session can be admitted in between.
+    fn check_admission_in(
+        &self,
+        sessions: &HashMap<String, SessionInfo>,
+        template: &str,
+        owner: Option<&str>,
+    ) -> Result<(), SimplifiedMcpError> {
+        if self.is_in_maintenance() {
+            return Err(SimplifiedMcpError::ServerInMaintenance);
+        }
+
         // Validate template is supported
         if !self.template_mapping.is_supported(template) {
             return Err(SimplifiedMcpError::UnsupportedLanguage(template.to_string()));
         }
 
-        // Check if we've reached the maximum number of sessions
-        {
-            let sessions = self.sessions.read().map_err(|e| {
-                SimplifiedMcpError::InternalError(format!("Failed to acquire read lock: {}", e))
-            })?;
-            
-            if sessions.len() >= self.config.get_max_sessions() {
-                return Err(SimplifiedMcpError::ResourceLimitExceeded(
-                    format!("Maximum number of sessions ({}) reached", self.config.get_max_sessions())
-                ));
+        // Stopped sessions kept for the retention period no longer have a sandbox
+        let live_sessions = || {
+            sessions
+                .values()
+                .filter(|session| session.status != SessionStatus::Stopped)
+        };
+
+        // Check the owner's namespace first, so a tenant at its own limit is told so even when
+        // the server is full as well
+        let namespace_limit = owner.and_then(|namespace| {
+            Some((namespace, self.config.get_max_sessions_for_namespace(namespace)?))
+        });
+        if let Some((namespace, limit)) = namespace_limit {
+            let owned = live_sessions()
+                .filter(|session| session.owner.as_deref() == Some(namespace))
+                .count();
+            if owned >= limit {
+                return Err(SimplifiedMcpError::ResourceLimitExceeded(format!(
+                    "Maximum number of sessions ({}) reached for namespace {}",
+                    limit, namespace
+                )));
             }
         }
 
+        if live_sessions().count() >= self.config.get_max_sessions() {
+            return Err(SimplifiedMcpError::ResourceLimitExceeded(
+                format!("Maximum number of sessions ({}) reached", self.config.get_max_sessions())
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Create a new session with the specified parameters
+    /// 
+    /// Returns the session ID on success
+    pub async fn create_session(
+        &self,
+        template: &str,
+        flavor: SandboxFlavor,
+    ) -> Result<String, SimplifiedMcpError> {
+        self.create_owned_session(template, flavor, None).await
+    }
+
+    /// Create a new session owned by a namespace, if any
+    ///
+    /// The session counts against the maximum number of sessions of its owner's namespace as
+    /// well as the server's. Returns the session ID on success
+    pub async fn create_owned_session(
+        &self,
+        template: &str,
+        flavor: SandboxFlavor,
+        owner: Option<&str>,
+    ) -> Result<String, SimplifiedMcpError> {
+        let mut sessions = self.sessions.write().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire write lock: {}", e))
+        })?;
+        self.check_admission_in(&sessions, template, owner)?;
+
+        // Store session
+        let session_info = self.new_session_info(template, flavor, owner);
+        let session_id = session_info.id.clone();
+        sessions.insert(session_id.clone(), session_info);
+
+        Ok(session_id)
+    }
+
+    /// Build the information of a new session, without tracking it yet
+    fn new_session_info(&self, template: &str, flavor: SandboxFlavor, owner: Option<&str>) -> SessionInfo {
         // Generate unique session ID
         let session_id = format!("session-{}", Uuid::new_v4());
         
@@ -1015,7 +2737,7 @@ impl SessionManager {
 
         // Create session info
         let mut session_info = SessionInfo::new(
-            session_id.clone(),
+            session_id,
             namespace,
             sandbox_name,
             template.to_string(),
@@ -1024,17 +2746,9 @@ impl SessionManager {
 
         // For basic session creation (without sandbox), set status to Ready immediately
         session_info.status = SessionStatus::Ready;
-
-        // Store session
-        {
-            let mut sessions = self.sessions.write().map_err(|e| {
-                SimplifiedMcpError::InternalError(format!("Failed to acquire write lock: {}", e))
-            })?;
-            
-            sessions.insert(session_id.clone(), session_info);
-        }
-
-        Ok(session_id)
+        session_info.owner = owner.map(String::from);
+        session_info.preamble = self.config.get_template_preamble(template).map(String::from);
+        session_info
     }
 
     /// Get session information by ID
@@ -1066,40 +2780,39 @@ impl SessionManager {
                 let new_session_id = self.create_session(template, flavor).await?;
                 self.get_session(&new_session_id)
             }
-            Some(id) => {
-                // Try to get existing session
-                match self.get_session(&id) {
-                    Ok(session) => {
-                        // Validate that the session matches the requested parameters
-                        if session.language != template {
-                            return Err(SimplifiedMcpError::InvalidSessionState(
-                                format!("Session {} is for template '{}', but '{}' was requested", 
-                                    id, session.language, template)
-                            ));
-                        }
-                        
-                        // Check if session is in a valid state
-                        match session.status {
-                            SessionStatus::Stopped => {
-                                return Err(SimplifiedMcpError::InvalidSessionState(
-                                    format!("Session {} has been stopped", id)
-                                ));
-                            }
-                            SessionStatus::Error(ref msg) => {
-                                return Err(SimplifiedMcpError::InvalidSessionState(
-                                    format!("Session {} is in error state: {}", id, msg)
-                                ));
-                            }
-                            _ => {}
-                        }
+            Some(id) => self.get_usable_session(&id, template),
+        }
+    }
 
-                        Ok(session)
-                    }
-                    Err(_) => {
-                        Err(SimplifiedMcpError::SessionNotFound(id))
-                    }
-                }
-            }
+    /// Get an existing session that code for the template can be executed in
+    ///
+    /// Fails if the session does not exist, is for another template, or was stopped or failed.
+    pub fn get_usable_session(
+        &self,
+        session_id: &str,
+        template: &str,
+    ) -> Result<SessionInfo, SimplifiedMcpError> {
+        let session = self
+            .get_session(session_id)
+            .map_err(|_| SimplifiedMcpError::SessionNotFound(session_id.to_string()))?;
+
+        // Validate that the session matches the requested parameters
+        if session.language != template {
+            return Err(SimplifiedMcpError::InvalidSessionState(
+                format!("Session {} is for template '{}', but '{}' was requested", 
+                    session_id, session.language, template)
+            ));
+        }
+
+        // Check if session is in a valid state
+        match session.status {
+            SessionStatus::Stopped => Err(SimplifiedMcpError::InvalidSessionState(
+                format!("Session {} has been stopped", session_id)
+            )),
+            SessionStatus::Error(ref msg) => Err(SimplifiedMcpError::InvalidSessionState(
+                format!("Session {} is in error state: {}", session_id, msg)
+            )),
+            _ => Ok(session),
         }
     }
 
@@ -1124,20 +2837,215 @@ impl SessionManager {
         session_id: &str,
         status: SessionStatus,
     ) -> Result<(), SimplifiedMcpError> {
-        let mut sessions = self.sessions.write().map_err(|e| {
+        Self::set_status_in(&self.sessions, &self.status_changed, session_id, status)
+    }
+
+    /// Update session status and wake readiness waiters
+    fn set_status_in(
+        sessions: &Arc<RwLock<HashMap<String, SessionInfo>>>,
+        status_changed: &Notify,
+        session_id: &str,
+        status: SessionStatus,
+    ) -> Result<(), SimplifiedMcpError> {
+        let mut sessions = sessions.write().map_err(|e| {
             SimplifiedMcpError::InternalError(format!("Failed to acquire write lock: {}", e))
         })?;
 
         match sessions.get_mut(session_id) {
             Some(session) => {
-                session.status = status;
+                session.set_status(status);
                 session.touch(); // Update access time when status changes
+                status_changed.notify_waiters();
                 Ok(())
             }
             None => Err(SimplifiedMcpError::SessionNotFound(session_id.to_string())),
         }
     }
 
+    /// Move a session out of `Creating` once its sandbox has been created or failed to
+    ///
+    /// The status is only changed if the session is still `Creating`, all under one write lock.
+    /// Returns `false` if the session was stopped or removed while its sandbox was starting, so
+    /// the caller can tear down a sandbox nobody owns anymore.
+    fn finish_creating_in(
+        sessions: &RwLock<HashMap<String, SessionInfo>>,
+        status_changed: &Notify,
+        session_id: &str,
+        status: SessionStatus,
+    ) -> Result<bool, SimplifiedMcpError> {
+        let mut sessions = sessions.write().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire write lock: {}", e))
+        })?;
+
+        match sessions.get_mut(session_id) {
+            Some(session) if session.status == SessionStatus::Creating => {
+                session.set_status(status);
+                session.touch();
+                status_changed.notify_waiters();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Wait until a session has finished being created
+    ///
+    /// Returns the session as soon as it leaves the `Creating` state. Fails if the sandbox could
+    /// not be created, the session was stopped, or it is still being created after `timeout`.
+    pub async fn wait_until_ready(
+        &self,
+        session_id: &str,
+        timeout: Duration,
+    ) -> Result<SessionInfo, SimplifiedMcpError> {
+        let deadline = tokio::time::Instant::now() + timeout;
+
+        loop {
+            // Register for the notification before checking the status so a change between the
+            // check and the wait is not missed
+            let notified = self.status_changed.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let session = self.get_session(session_id)?;
+            match session.status {
+                SessionStatus::Creating => {}
+                SessionStatus::Error(ref msg) => {
+                    return Err(SimplifiedMcpError::SessionCreationFailed(format!(
+                        "Session {} failed to start: {}",
+                        session_id, msg
+                    )));
+                }
+                SessionStatus::Stopped => {
+                    return Err(SimplifiedMcpError::InvalidSessionState(format!(
+                        "Session {} has been stopped",
+                        session_id
+                    )));
+                }
+                SessionStatus::Ready | SessionStatus::Running => return Ok(session),
+            }
+
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return Err(SimplifiedMcpError::SessionTimeout(format!(
+                    "Session {} was not ready within {} seconds",
+                    session_id,
+                    timeout.as_secs()
+                )));
+            }
+        }
+    }
+
+    /// Record an execution in a session's history
+    ///
+    /// Secret assignments in the input and outputs are redacted before the entry is stored.
+    pub fn record_execution(
+        &self,
+        session_id: &str,
+        mut entry: ExecutionHistoryEntry,
+    ) -> Result<(), SimplifiedMcpError> {
+        entry.input = self.redactor.redact_text(&entry.input);
+        entry.stdout = self.redactor.redact_text(&entry.stdout);
+        entry.stderr = self.redactor.redact_text(&entry.stderr);
+
+        let mut sessions = self.sessions.write().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire write lock: {}", e))
+        })?;
+
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| SimplifiedMcpError::SessionNotFound(session_id.to_string()))?;
+        let execution_time = Duration::from_millis(entry.execution_time_ms);
+        session.record_execution(entry, self.config.get_max_execution_history());
+        let (namespace, flavor) = (session.namespace.clone(), session.flavor);
+        drop(sessions);
+
+        self.usage.record(&namespace, flavor, execution_time);
+        Ok(())
+    }
+
+    /// Charge an execution that isn't recorded in the history to its session's namespace
+    ///
+    /// Used for executions that were cut off, such as timed out ones, which still held their
+    /// session's resources for the time they ran.
+    pub fn record_usage(&self, session_id: &str, execution_time: Duration) -> Result<(), SimplifiedMcpError> {
+        let session = self.get_session(session_id)?;
+        self.usage.record(&session.namespace, session.flavor, execution_time);
+        Ok(())
+    }
+
+    /// Whether the usage of executions can't be persisted and is only kept in memory
+    pub fn is_usage_degraded(&self) -> bool {
+        self.usage.is_degraded()
+    }
+
+    /// Start a background task that persists usage the usage file could not take, once it can
+    pub fn start_background_usage_retry(&self) -> tokio::task::JoinHandle<()> {
+        let usage = Arc::clone(&self.usage);
+
+        tokio::spawn(async move {
+            let mut interval_timer = interval(Self::USAGE_RETRY_INTERVAL);
+
+            loop {
+                interval_timer.tick().await;
+                usage.retry_save();
+            }
+        })
+    }
+
+    /// Get the resource usage accumulated by a namespace, or by all namespaces for "*"
+    pub fn get_usage(&self, namespace: &str) -> Result<UsageResponse, SimplifiedMcpError> {
+        let namespaces = if namespace == "*" {
+            self.usage.get_all()?
+        } else {
+            BTreeMap::from([(namespace.to_string(), self.usage.get(namespace)?)])
+        };
+
+        Ok(UsageResponse { namespaces })
+    }
+
+    /// Get the resource usage of a namespace the caller may read
+    ///
+    /// Fails with [`SimplifiedMcpError::NamespaceAccessDenied`] if the caller's API key is scoped
+    /// to another namespace, or asks for "*" without covering all namespaces.
+    pub fn get_usage_for(
+        &self,
+        namespace: &str,
+        caller: &SessionCaller,
+    ) -> Result<UsageResponse, SimplifiedMcpError> {
+        if !caller.can_access_namespace(namespace) {
+            return Err(SimplifiedMcpError::NamespaceAccessDenied(namespace.to_string()));
+        }
+
+        self.get_usage(namespace)
+    }
+
+    /// Get a page of a session's execution history, oldest first
+    pub fn get_execution_history(
+        &self,
+        session_id: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<ExecutionHistoryResponse, SimplifiedMcpError> {
+        let sessions = self.sessions.read().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire read lock: {}", e))
+        })?;
+
+        let session = sessions
+            .get(session_id)
+            .ok_or_else(|| SimplifiedMcpError::SessionNotFound(session_id.to_string()))?;
+
+        let total = session.history.len();
+        let entries: Vec<_> = session.history.iter().skip(offset).take(limit).cloned().collect();
+
+        Ok(ExecutionHistoryResponse {
+            session_id: session_id.to_string(),
+            has_more: offset.saturating_add(entries.len()) < total,
+            entries,
+            total,
+            offset,
+            dropped: session.executions_recorded - total as u64,
+        })
+    }
+
     /// Stop a session and mark it as stopped
     pub async fn stop_session(&self, session_id: &str) -> Result<(), SimplifiedMcpError> {
         // Get session info before stopping
@@ -1157,6 +3065,32 @@ impl SessionManager {
         Ok(())
     }
 
+    /// Create a session that is torn down after a single execution
+    pub async fn create_ephemeral_session(
+        self: &Arc<Self>,
+        template: &str,
+        flavor: SandboxFlavor,
+        owner: Option<&str>,
+    ) -> Result<EphemeralSession, SimplifiedMcpError> {
+        let session_id = self.create_owned_session(template, flavor, owner).await?;
+
+        Ok(EphemeralSession {
+            manager: Arc::clone(self),
+            session_id: Some(session_id),
+        })
+    }
+
+    /// Stop a session and remove it from tracking
+    ///
+    /// The session is removed even if stopping it fails.
+    pub async fn teardown_session(&self, session_id: &str) -> Result<(), SimplifiedMcpError> {
+        let stopped = self.stop_session(session_id).await;
+        let removed = self.remove_session(session_id);
+
+        stopped?;
+        removed.map(|_| ())
+    }
+
     /// Remove a session from tracking (used during cleanup)
     pub fn remove_session(&self, session_id: &str) -> Result<SessionInfo, SimplifiedMcpError> {
         let mut sessions = self.sessions.write().map_err(|e| {
@@ -1207,16 +3141,19 @@ impl SessionManager {
 
     /// Clean up expired sessions
     /// 
-    /// Returns the list of session IDs that were cleaned up
+    /// Expired sessions are stopped and stay queryable for the stopped session retention, and
+    /// stopped sessions kept past it are removed. Returns the list of session IDs that were
+    /// cleaned up because they expired.
     pub async fn cleanup_expired_sessions(&self) -> Result<Vec<String>, SimplifiedMcpError> {
         let expired_ids = self.find_expired_sessions()?;
+        let retain = !self.config.get_stopped_session_retention().is_zero();
         let mut cleaned_up = Vec::new();
 
         for session_id in expired_ids {
             match self.stop_session(&session_id).await {
                 Ok(()) => {
-                    // Remove from tracking after successful stop
-                    if let Ok(_) = self.remove_session(&session_id) {
+                    // Remove from tracking after successful stop, unless stopped sessions are retained
+                    if retain || self.remove_session(&session_id).is_ok() {
                         cleaned_up.push(session_id);
                     }
                 }
@@ -1227,9 +3164,40 @@ impl SessionManager {
             }
         }
 
+        self.remove_retired_sessions()?;
+
         Ok(cleaned_up)
     }
 
+    /// Remove the stopped sessions that were kept for the stopped session retention
+    ///
+    /// Returns the list of session IDs that were removed.
+    pub fn remove_retired_sessions(&self) -> Result<Vec<String>, SimplifiedMcpError> {
+        Self::remove_retired_in(&self.sessions, self.config.get_stopped_session_retention())
+    }
+
+    /// Remove the stopped sessions kept for at least `retention` (internal helper shared with
+    /// background cleanup)
+    fn remove_retired_in(
+        sessions: &Arc<RwLock<HashMap<String, SessionInfo>>>,
+        retention: Duration,
+    ) -> Result<Vec<String>, SimplifiedMcpError> {
+        let mut sessions = sessions.write().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire write lock: {}", e))
+        })?;
+
+        let retired: Vec<String> = sessions
+            .values()
+            .filter(|session| session.is_retired(retention))
+            .map(|session| session.id.clone())
+            .collect();
+        for session_id in &retired {
+            sessions.remove(session_id);
+        }
+
+        Ok(retired)
+    }
+
     /// Get the shared volume path for sessions
     pub fn get_volume_path(&self) -> Option<String> {
         if self.config.has_shared_volume() {
@@ -1253,6 +3221,104 @@ impl SessionManager {
         Ok(sessions.len())
     }
 
+    /// Dump the tracked sessions, including stopped ones, and the configuration as JSON, for
+    /// debugging
+    ///
+    /// The dump is passed through the redactor, so it masks secrets like the logs do.
+    pub fn dump_state(&self) -> Result<serde_json::Value, SimplifiedMcpError> {
+        let sessions = self.sessions.read().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire read lock: {}", e))
+        })?;
+
+        let mut sessions: Vec<&SessionInfo> = sessions.values().collect();
+        sessions.sort_by(|a, b| a.id.cmp(&b.id));
+        let sessions: Vec<serde_json::Value> = sessions
+            .into_iter()
+            .map(|session| {
+                json!({
+                    "id": session.id,
+                    "namespace": session.namespace,
+                    "sandbox_name": session.sandbox_name,
+                    "language": session.language,
+                    "flavor": session.flavor,
+                    "status": session.status,
+                    "age_seconds": session.created_at.elapsed().as_secs(),
+                    "idle_seconds": session.last_accessed.elapsed().as_secs(),
+                    "stopped_seconds_ago": session.stopped_at.map(|at| at.elapsed().as_secs()),
+                    "packages": session.packages,
+                    "affinity_client": session.affinity_client,
+                    "owner": session.owner,
+                    "restarts": session.restarts,
+                    "preamble": session.preamble,
+                    "executions_recorded": session.executions_recorded,
+                    "history": session.history,
+                })
+            })
+            .collect();
+
+        let dump = json!({
+            "maintenance": self.is_in_maintenance(),
+            "usage_degraded": self.is_usage_degraded(),
+            "sessions": sessions,
+            "config": self.config.dump_state(),
+        });
+
+        Ok(self.redactor.redact_value(&dump))
+    }
+
+    /// Get resource usage statistics for the current sessions
+    ///
+    /// Every session counts against the session limit until it is stopped, even while it is
+    /// kept queryable afterwards. Each session's sandbox is reached through one portal port, so
+    /// ports run out with the session limit.
+    pub fn get_resource_stats(&self) -> Result<ResourceStats, SimplifiedMcpError> {
+        let sessions = self.sessions.read().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire read lock: {}", e))
+        })?;
+        let live_sessions: Vec<&SessionInfo> = sessions
+            .values()
+            .filter(|session| session.status != SessionStatus::Stopped)
+            .collect();
+
+        let mut total_memory_mb = 0u32;
+        let mut total_cpus = 0u32;
+        let mut flavor_counts = HashMap::new();
+
+        for session in &live_sessions {
+            total_memory_mb += session.flavor.get_memory_mb();
+            total_cpus += session.flavor.get_cpus() as u32;
+
+            *flavor_counts.entry(session.flavor).or_insert(0) += 1;
+        }
+
+        let max_sessions = self.config.get_max_sessions();
+        Ok(ResourceStats {
+            active_sessions: live_sessions.len(),
+            max_sessions,
+            allocated_ports: live_sessions.len(),
+            available_ports: max_sessions.saturating_sub(live_sessions.len()),
+            total_ports: max_sessions,
+            total_memory_mb,
+            total_cpus,
+            flavor_counts,
+        })
+    }
+
+    /// Get the remaining session quota, including host memory and CPU headroom
+    pub fn get_quota(&self) -> Result<QuotaResponse, SimplifiedMcpError> {
+        let stats = self.get_resource_stats()?;
+
+        let host_memory = MemoryInfo::read().unwrap_or_else(|e| {
+            tracing::warn!("Failed to read host memory: {}", e);
+            None
+        });
+        let host_cpus = std::thread::available_parallelism()
+            .ok()
+            .map(|cpus| cpus.get() as u32);
+
+        Ok(QuotaResponse::new(&stats, host_memory, host_cpus))
+    }
+
     /// Get configuration
     pub fn get_config(&self) -> &ConfigurationManager {
         &self.config
@@ -1268,6 +3334,16 @@ impl SessionManager {
         self.config.get_default_template()
     }
 
+    /// Get the template to run code with when the request does not name one
+    pub fn template_for_code(&self, code: &str) -> &str {
+        self.config.template_for_code(code)
+    }
+
+    /// Get the flavor to create a session with, picking an auto flavor from the given text
+    pub fn flavor_for(&self, selection: Option<FlavorSelection>, text: &str) -> SandboxFlavor {
+        self.config.flavor_for(selection, text)
+    }
+
     /// Create a complete session management setup with cleanup
     /// 
     /// This factory method creates a SessionManager, ResourceManager, and CleanupManager
@@ -1329,12 +3405,13 @@ impl SessionManager {
                     expired_ids
                 };
                 
+                let retention = config.get_stopped_session_retention();
                 if !expired_sessions.is_empty() {
                     tracing::info!("Found {} expired sessions for cleanup", expired_sessions.len());
                     
                     // Clean up expired sessions
                     for session_id in expired_sessions {
-                        match Self::cleanup_single_session(&sessions, &session_id).await {
+                        match Self::cleanup_single_session(&sessions, &session_id, retention).await {
                             Ok(()) => {
                                 tracing::info!("Successfully cleaned up expired session: {}", session_id);
                             }
@@ -1344,14 +3421,26 @@ impl SessionManager {
                         }
                     }
                 }
+
+                // Remove the stopped sessions kept long enough
+                match Self::remove_retired_in(&sessions, retention) {
+                    Ok(retired) if !retired.is_empty() => {
+                        tracing::info!("Removed {} stopped sessions past their retention", retired.len());
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::error!("Failed to remove retired sessions: {}", e),
+                }
             }
         })
     }
 
     /// Clean up a single session (internal helper for background cleanup)
+    ///
+    /// The session stays queryable as stopped for `retention`, or is removed right away without one.
     async fn cleanup_single_session(
         sessions: &Arc<RwLock<HashMap<String, SessionInfo>>>,
         session_id: &str,
+        retention: Duration,
     ) -> Result<(), SimplifiedMcpError> {
         // First, get the session info and update its status to stopped
         let session_info = {
@@ -1361,7 +3450,7 @@ impl SessionManager {
             
             match sessions_guard.get_mut(session_id) {
                 Some(session) => {
-                    session.status = SessionStatus::Stopped;
+                    session.set_status(SessionStatus::Stopped);
                     session.clone()
                 }
                 None => {
@@ -1376,6 +3465,10 @@ impl SessionManager {
         tracing::info!("Stopping sandbox for session {}: namespace={}, sandbox_name={}", 
             session_info.id, session_info.namespace, session_info.sandbox_name);
 
+        if !retention.is_zero() {
+            return Ok(());
+        }
+
         // Remove the session from tracking
         {
             let mut sessions_guard = sessions.write().map_err(|e| {
@@ -1398,7 +3491,8 @@ impl SessionManager {
         let mut cleaned_up = Vec::new();
 
         for session_id in expired_ids {
-            match Self::cleanup_single_session(&self.sessions, &session_id).await {
+            let retention = self.config.get_stopped_session_retention();
+            match Self::cleanup_single_session(&self.sessions, &session_id, retention).await {
                 Ok(()) => {
                     cleaned_up.push(session_id);
                 }
@@ -1417,7 +3511,7 @@ impl SessionManager {
 //--------------------------------------------------------------------------------------------------
 
 /// Resource allocation information for a session
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResourceAllocation {
     /// Session ID this allocation belongs to
     pub session_id: String,
@@ -1425,8 +3519,9 @@ pub struct ResourceAllocation {
     pub flavor: SandboxFlavor,
     /// Allocated port number
     pub port: u16,
-    /// When the allocation was created
-    pub allocated_at: Instant,
+    /// Wall-clock time the allocation was created, so that its age stays meaningful when the
+    /// allocation is persisted and loaded again by another server process
+    pub allocated_at: SystemTime,
 }
 
 impl ResourceAllocation {
@@ -1436,13 +3531,23 @@ impl ResourceAllocation {
             session_id,
             flavor,
             port,
-            allocated_at: Instant::now(),
+            allocated_at: SystemTime::now(),
         }
     }
 
+    /// Get the age of this allocation
+    ///
+    /// An allocation time in the future, e.g. after the clock was set back, counts as no age
+    /// rather than an error, so such an allocation is only reaped once it is actually old.
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.allocated_at)
+            .unwrap_or_default()
+    }
+
     /// Get the age of this allocation in seconds
     pub fn age_seconds(&self) -> u64 {
-        self.allocated_at.elapsed().as_secs()
+        self.age().as_secs()
     }
 }
 
@@ -1828,7 +3933,7 @@ impl ResourceManager {
                     
                     let old_allocations: Vec<(String, ResourceAllocation)> = allocations_guard
                         .iter()
-                        .filter(|(_, allocation)| allocation.allocated_at.elapsed() > max_allocation_age)
+                        .filter(|(_, allocation)| allocation.age() > max_allocation_age)
                         .map(|(id, allocation)| (id.clone(), allocation.clone()))
                         .collect();
                     
@@ -1909,6 +4014,24 @@ pub struct ResourceStats {
     pub flavor_counts: HashMap<SandboxFlavor, usize>,
 }
 
+impl QuotaResponse {
+    /// Build the quota from resource statistics and the host's total memory and CPUs
+    pub fn new(stats: &ResourceStats, host_memory: Option<MemoryInfo>, host_cpus: Option<u32>) -> Self {
+        Self {
+            max_sessions: stats.max_sessions,
+            active_sessions: stats.active_sessions,
+            remaining_sessions: stats.max_sessions.saturating_sub(stats.active_sessions),
+            available_ports: stats.available_ports,
+            allocated_memory_mb: stats.total_memory_mb,
+            allocated_cpus: stats.total_cpus,
+            remaining_memory_mb: host_memory.map(|memory| {
+                (memory.total_kib / 1024).saturating_sub(u64::from(stats.total_memory_mb))
+            }),
+            remaining_cpus: host_cpus.map(|cpus| cpus.saturating_sub(stats.total_cpus)),
+        }
+    }
+}
+
 /// Helper function to format Instant as ISO 8601 string
 /// 
 /// Note: This is a simplified implementation. In a real system, you might want to use
@@ -2051,31 +4174,541 @@ pub fn truncate_error_message(message: &str) -> String {
     }
 }
 
-//--------------------------------------------------------------------------------------------------
-// Cleanup Manager
-//--------------------------------------------------------------------------------------------------
-
-/// Comprehensive cleanup manager that coordinates session and resource cleanup
-/// 
-/// This manager handles the coordination between SessionManager and ResourceManager
-/// to ensure that both sessions and their associated resources are properly cleaned up
-/// when they expire or become orphaned.
-#[derive(Debug)]
-pub struct CleanupManager {
-    session_manager: Arc<SessionManager>,
-    resource_manager: Arc<ResourceManager>,
-    config: ConfigurationManager,
-}
+impl ExecutionHistoryEntry {
+    /// Maximum number of characters kept for the input and each output stream
+    const MAX_FIELD_CHARS: usize = 4096;
 
-impl CleanupManager {
-    /// Create a new CleanupManager
+    /// Create a new history entry, truncating the input and outputs
+    ///
+    /// The sequence number is assigned when the entry is recorded in a session.
     pub fn new(
-        session_manager: Arc<SessionManager>,
-        resource_manager: Arc<ResourceManager>,
-        config: ConfigurationManager,
+        kind: ExecutionKind,
+        input: &str,
+        stdout: &str,
+        stderr: &str,
+        exit_code: Option<i32>,
+        execution_time_ms: u64,
     ) -> Self {
         Self {
-            session_manager,
+            sequence: 0,
+            kind,
+            input: truncate_history_field(input),
+            stdout: truncate_history_field(stdout),
+            stderr: truncate_history_field(stderr),
+            exit_code,
+            execution_time_ms,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+/// Truncate a history field to `ExecutionHistoryEntry::MAX_FIELD_CHARS` characters
+fn truncate_history_field(value: &str) -> String {
+    match value.char_indices().nth(ExecutionHistoryEntry::MAX_FIELD_CHARS) {
+        Some((end, _)) => format!("{}... (truncated)", &value[..end]),
+        None => value.to_string(),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Execution Queue
+//--------------------------------------------------------------------------------------------------
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::oneshot;
+
+/// Priority of an execution waiting for a slot in the execution queue
+///
+/// Priorities only order executions within a namespace; namespaces are still served
+/// round-robin, so a namespace cannot use high priorities to get ahead of the others.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutionPriority {
+    /// Background work that can wait, e.g. batch jobs
+    Low,
+    /// Executions that do not ask for a priority
+    #[default]
+    Normal,
+    /// Interactive work a user is waiting on
+    High,
+}
+
+/// Fair execution queue that bounds the number of concurrent executions
+///
+/// When all execution slots are taken, callers wait in a queue per namespace, where
+/// higher priorities go first and equal priorities in arrival order. Executions gain a
+/// priority level for every aging interval they wait, so low priority executions are not
+/// starved by a steady stream of higher ones. Freed slots are handed out round-robin
+/// across namespaces, so a namespace that submits a flood of executions cannot starve
+/// the others.
+#[derive(Debug)]
+pub struct ExecutionQueue {
+    /// Shared queue state
+    state: Arc<Mutex<ExecutionQueueState>>,
+    /// Maximum number of executions running at the same time
+    max_concurrent_executions: usize,
+}
+
+/// Internal state of the execution queue
+#[derive(Debug)]
+struct ExecutionQueueState {
+    /// Number of executions currently holding a slot
+    running: usize,
+    /// Waiting executions per namespace, in arrival order
+    waiting: HashMap<String, VecDeque<QueuedExecution>>,
+    /// Namespaces with waiting executions, in round-robin order
+    rotation: VecDeque<String>,
+    /// How long an execution waits before it is treated as one priority higher
+    priority_aging: Duration,
+    /// Total number of executions that were admitted
+    dispatched: u64,
+    /// Total time admitted executions spent waiting, in milliseconds
+    total_wait_ms: u64,
+    /// Longest time an admitted execution spent waiting, in milliseconds
+    max_wait_ms: u64,
+}
+
+/// An execution waiting for a slot
+#[derive(Debug)]
+struct QueuedExecution {
+    /// Channel used to hand the slot to the waiting execution
+    slot_tx: oneshot::Sender<ExecutionPermit>,
+    /// Priority the execution was queued with
+    priority: ExecutionPriority,
+    /// When the execution was queued
+    queued_at: Instant,
+}
+
+/// A slot in the execution queue
+///
+/// The slot is released, and handed to the next waiting execution, when the permit is dropped.
+#[derive(Debug)]
+pub struct ExecutionPermit {
+    /// Shared queue state, taken when the slot has already been given back
+    state: Option<Arc<Mutex<ExecutionQueueState>>>,
+    /// Time spent waiting for the slot
+    wait_time: Duration,
+}
+
+/// Execution queue statistics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionQueueStats {
+    /// Maximum number of concurrent executions
+    pub max_concurrent_executions: usize,
+    /// Number of executions currently running
+    pub running_executions: usize,
+    /// Total number of executions waiting for a slot
+    pub queue_depth: usize,
+    /// Number of executions waiting for a slot per namespace
+    pub queue_depth_by_namespace: HashMap<String, usize>,
+    /// Number of executions waiting for a slot per requested priority
+    pub queue_depth_by_priority: HashMap<ExecutionPriority, usize>,
+    /// Total number of executions that were admitted
+    pub dispatched_executions: u64,
+    /// Average time admitted executions spent waiting, in milliseconds
+    pub average_wait_ms: u64,
+    /// Longest time an admitted execution spent waiting, in milliseconds
+    pub max_wait_ms: u64,
+}
+
+impl ExecutionQueue {
+    /// Create a new ExecutionQueue with the given number of execution slots
+    pub fn new(max_concurrent_executions: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ExecutionQueueState {
+                running: 0,
+                waiting: HashMap::new(),
+                rotation: VecDeque::new(),
+                priority_aging: Duration::from_secs(DEFAULT_PRIORITY_AGING_SECONDS),
+                dispatched: 0,
+                total_wait_ms: 0,
+                max_wait_ms: 0,
+            })),
+            max_concurrent_executions,
+        }
+    }
+
+    /// Set how long a queued execution waits before it is treated as one priority higher
+    pub fn with_priority_aging(self, priority_aging: Duration) -> Self {
+        if let Ok(mut state) = self.state.lock() {
+            state.priority_aging = priority_aging;
+        }
+        self
+    }
+
+    /// Wait for an execution slot for the given namespace
+    ///
+    /// Returns immediately if a slot is free and nobody is waiting, otherwise the
+    /// execution is queued behind executions from the same namespace that have a
+    /// higher priority, or the same one and arrived earlier.
+    pub async fn acquire(
+        &self,
+        namespace: &str,
+        priority: ExecutionPriority,
+    ) -> Result<ExecutionPermit, SimplifiedMcpError> {
+        let slot_rx = {
+            let mut state = self.state.lock().map_err(|e| {
+                SimplifiedMcpError::InternalError(format!("Failed to acquire queue lock: {}", e))
+            })?;
+
+            if state.running < self.max_concurrent_executions && state.rotation.is_empty() {
+                state.running += 1;
+                state.record_wait(Duration::ZERO);
+                return Ok(ExecutionPermit {
+                    state: Some(Arc::clone(&self.state)),
+                    wait_time: Duration::ZERO,
+                });
+            }
+
+            let (slot_tx, slot_rx) = oneshot::channel();
+            let queue = state.waiting.entry(namespace.to_string()).or_default();
+            queue.push_back(QueuedExecution {
+                slot_tx,
+                priority,
+                queued_at: Instant::now(),
+            });
+
+            if queue.len() == 1 {
+                state.rotation.push_back(namespace.to_string());
+            }
+
+            tracing::debug!(
+                "Execution queued for namespace {} with {:?} priority (running: {})",
+                namespace,
+                priority,
+                state.running
+            );
+
+            slot_rx
+        };
+
+        slot_rx.await.map_err(|_| {
+            SimplifiedMcpError::InternalError("Execution queue was dropped".to_string())
+        })
+    }
+
+    /// Get execution queue statistics
+    pub fn get_stats(&self) -> Result<ExecutionQueueStats, SimplifiedMcpError> {
+        let state = self.state.lock().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire queue lock: {}", e))
+        })?;
+
+        let queue_depth_by_namespace: HashMap<String, usize> = state
+            .waiting
+            .iter()
+            .filter(|(_, queue)| !queue.is_empty())
+            .map(|(namespace, queue)| (namespace.clone(), queue.len()))
+            .collect();
+
+        let mut queue_depth_by_priority: HashMap<ExecutionPriority, usize> = [
+            ExecutionPriority::Low,
+            ExecutionPriority::Normal,
+            ExecutionPriority::High,
+        ]
+        .into_iter()
+        .map(|priority| (priority, 0))
+        .collect();
+        for execution in state.waiting.values().flatten() {
+            *queue_depth_by_priority.entry(execution.priority).or_default() += 1;
+        }
+
+        Ok(ExecutionQueueStats {
+            max_concurrent_executions: self.max_concurrent_executions,
+            running_executions: state.running,
+            queue_depth: queue_depth_by_namespace.values().sum(),
+            queue_depth_by_namespace,
+            queue_depth_by_priority,
+            dispatched_executions: state.dispatched,
+            average_wait_ms: state.total_wait_ms.checked_div(state.dispatched).unwrap_or(0),
+            max_wait_ms: state.max_wait_ms,
+        })
+    }
+
+    /// Get the maximum number of concurrent executions
+    pub fn get_max_concurrent_executions(&self) -> usize {
+        self.max_concurrent_executions
+    }
+}
+
+impl ExecutionQueueState {
+    /// Record the wait time of an admitted execution
+    fn record_wait(&mut self, wait_time: Duration) {
+        let wait_ms = wait_time.as_millis() as u64;
+        self.dispatched += 1;
+        self.total_wait_ms += wait_ms;
+        self.max_wait_ms = self.max_wait_ms.max(wait_ms);
+    }
+
+    /// Hand a free slot to the next waiting execution, rotating across namespaces
+    fn dispatch_next(&mut self, state: &Arc<Mutex<ExecutionQueueState>>) {
+        while let Some(namespace) = self.rotation.pop_front() {
+            let Some(queue) = self.waiting.get_mut(&namespace) else {
+                continue;
+            };
+
+            let next = next_by_priority(queue, self.priority_aging)
+                .and_then(|index| queue.remove(index));
+            if queue.is_empty() {
+                self.waiting.remove(&namespace);
+            } else {
+                self.rotation.push_back(namespace);
+            }
+
+            if let Some(next) = next {
+                let wait_time = next.queued_at.elapsed();
+                let permit = ExecutionPermit {
+                    state: Some(Arc::clone(state)),
+                    wait_time,
+                };
+
+                match next.slot_tx.send(permit) {
+                    Ok(()) => {
+                        self.running += 1;
+                        self.record_wait(wait_time);
+                        return;
+                    }
+                    Err(mut permit) => {
+                        // The caller has gone away; the slot was never taken, so skip to the next one
+                        permit.state = None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl QueuedExecution {
+    /// Get the priority the execution is treated with after waiting, one level higher for
+    /// every aging interval it waited
+    fn effective_priority(&self, priority_aging: Duration) -> u128 {
+        let aged = self
+            .queued_at
+            .elapsed()
+            .as_millis()
+            .checked_div(priority_aging.as_millis())
+            .unwrap_or(0);
+        self.priority as u128 + aged
+    }
+}
+
+/// Get the index of the execution to admit next from a namespace's queue: the one with the
+/// highest effective priority, the earliest one among equals
+fn next_by_priority(queue: &VecDeque<QueuedExecution>, priority_aging: Duration) -> Option<usize> {
+    let mut best: Option<(usize, u128)> = None;
+    for (index, execution) in queue.iter().enumerate() {
+        let priority = execution.effective_priority(priority_aging);
+        if best.is_none_or(|(_, best_priority)| priority > best_priority) {
+            best = Some((index, priority));
+        }
+    }
+    best.map(|(index, _)| index)
+}
+
+impl ExecutionPermit {
+    /// Get the time spent waiting for the execution slot
+    pub fn wait_time(&self) -> Duration {
+        self.wait_time
+    }
+}
+
+impl Drop for ExecutionPermit {
+    fn drop(&mut self) {
+        let Some(shared) = self.state.take() else {
+            return;
+        };
+
+        let result = match shared.lock() {
+            Ok(mut state) => {
+                state.running = state.running.saturating_sub(1);
+                state.dispatch_next(&shared);
+                Ok(())
+            }
+            Err(e) => Err(e.to_string()),
+        };
+
+        if let Err(e) = result {
+            tracing::error!("Failed to acquire queue lock when releasing execution slot: {}", e);
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Usage Accounting
+//--------------------------------------------------------------------------------------------------
+
+/// Ledger of the resource-time accumulated by each namespace's executions
+///
+/// A ledger loaded from a file writes its totals back to the file after every execution, so
+/// they survive server restarts. A default ledger only keeps them in memory.
+///
+/// If the file cannot be written, the ledger degrades to keeping the totals in memory: the
+/// failure is logged once, executions are still accounted, and [`UsageLedger::retry_save`]
+/// writes the totals kept in the meantime once the file is writable again.
+#[derive(Debug, Default)]
+pub struct UsageLedger {
+    /// Accumulated usage per namespace
+    totals: RwLock<BTreeMap<String, NamespaceUsage>>,
+    /// File the totals are persisted to
+    file_path: Option<PathBuf>,
+    /// Whether the last write of the totals failed, so they are only kept in memory
+    unsaved: AtomicBool,
+}
+
+impl NamespaceUsage {
+    /// Add an execution that ran on the given flavor for `execution_time`
+    fn add(&mut self, flavor: SandboxFlavor, execution_time: Duration) {
+        let seconds = execution_time.as_secs_f64();
+        self.executions += 1;
+        self.execution_seconds += seconds;
+        self.cpu_seconds += seconds * f64::from(flavor.get_cpus());
+        self.memory_mb_seconds += seconds * f64::from(flavor.get_memory_mb());
+    }
+}
+
+impl UsageLedger {
+    /// Load the ledger persisted at `file_path`, starting from zero if the file doesn't exist yet
+    pub fn load(file_path: PathBuf) -> Result<Self, SimplifiedMcpError> {
+        let totals = match std::fs::read(&file_path) {
+            Ok(contents) => serde_json::from_slice(&contents).map_err(|e| {
+                SimplifiedMcpError::ConfigurationError(format!(
+                    "Invalid usage file {}: {}",
+                    file_path.display(),
+                    e
+                ))
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => {
+                return Err(SimplifiedMcpError::InternalError(format!(
+                    "Failed to read usage file {}: {}",
+                    file_path.display(),
+                    e
+                )))
+            }
+        };
+
+        Ok(Self {
+            totals: RwLock::new(totals),
+            file_path: Some(file_path),
+            unsaved: AtomicBool::new(false),
+        })
+    }
+
+    /// Charge an execution to a namespace and persist the new totals
+    ///
+    /// The usage is always accounted in memory; failing to persist it only degrades the ledger,
+    /// so accounting never fails an execution.
+    pub fn record(&self, namespace: &str, flavor: SandboxFlavor, execution_time: Duration) {
+        let mut totals = match self.totals.write() {
+            Ok(totals) => totals,
+            Err(e) => {
+                tracing::error!("Failed to acquire usage lock, dropping usage of {}: {}", namespace, e);
+                return;
+            }
+        };
+        totals
+            .entry(namespace.to_string())
+            .or_default()
+            .add(flavor, execution_time);
+
+        self.note_save(self.save(&totals));
+    }
+
+    /// Write the totals kept in memory while the ledger is degraded, leaving it degraded if the
+    /// file is still not writable
+    pub fn retry_save(&self) {
+        if !self.is_degraded() {
+            return;
+        }
+
+        match self.totals.read() {
+            Ok(totals) => self.note_save(self.save(&totals)),
+            Err(e) => tracing::error!("Failed to acquire usage lock, not persisting usage: {}", e),
+        }
+    }
+
+    /// Whether the totals could not be written and are only kept in memory
+    pub fn is_degraded(&self) -> bool {
+        self.unsaved.load(Ordering::Relaxed)
+    }
+
+    /// Get the usage accumulated by a namespace
+    pub fn get(&self, namespace: &str) -> Result<NamespaceUsage, SimplifiedMcpError> {
+        let totals = self.totals.read().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire read lock: {}", e))
+        })?;
+
+        Ok(totals.get(namespace).cloned().unwrap_or_default())
+    }
+
+    /// Get the usage accumulated by every namespace with executions
+    pub fn get_all(&self) -> Result<BTreeMap<String, NamespaceUsage>, SimplifiedMcpError> {
+        let totals = self.totals.read().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire read lock: {}", e))
+        })?;
+
+        Ok(totals.clone())
+    }
+
+    /// Write the totals to the ledger's file, replacing it atomically
+    /// Track whether the totals are persisted, logging only when that changes
+    fn note_save(&self, result: std::io::Result<()>) {
+        match result {
+            Ok(()) => {
+                if self.unsaved.swap(false, Ordering::Relaxed) {
+                    tracing::info!("Usage file is writable again, persisted the usage kept in memory");
+                }
+            }
+            Err(e) => {
+                if !self.unsaved.swap(true, Ordering::Relaxed) {
+                    tracing::error!(
+                        "Failed to persist usage, keeping it in memory until the usage file is writable again: {}",
+                        e
+                    );
+                } else {
+                    tracing::debug!("Usage file is still not writable: {}", e);
+                }
+            }
+        }
+    }
+
+    fn save(&self, totals: &BTreeMap<String, NamespaceUsage>) -> std::io::Result<()> {
+        let Some(file_path) = &self.file_path else {
+            return Ok(());
+        };
+
+        let contents = serde_json::to_vec_pretty(totals)?;
+        let temp_path = file_path.with_extension("tmp");
+        std::fs::write(&temp_path, contents)?;
+        std::fs::rename(&temp_path, file_path)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Cleanup Manager
+//--------------------------------------------------------------------------------------------------
+
+/// Comprehensive cleanup manager that coordinates session and resource cleanup
+/// 
+/// This manager handles the coordination between SessionManager and ResourceManager
+/// to ensure that both sessions and their associated resources are properly cleaned up
+/// when they expire or become orphaned.
+#[derive(Debug)]
+pub struct CleanupManager {
+    session_manager: Arc<SessionManager>,
+    resource_manager: Arc<ResourceManager>,
+    config: ConfigurationManager,
+}
+
+impl CleanupManager {
+    /// Create a new CleanupManager
+    pub fn new(
+        session_manager: Arc<SessionManager>,
+        resource_manager: Arc<ResourceManager>,
+        config: ConfigurationManager,
+    ) -> Self {
+        Self {
+            session_manager,
             resource_manager,
             config,
         }
@@ -2132,6 +4765,15 @@ impl CleanupManager {
                         }
                     }
                 }
+
+                // Remove the stopped sessions kept long enough
+                match session_manager.remove_retired_sessions() {
+                    Ok(retired) if !retired.is_empty() => {
+                        tracing::info!("Removed {} stopped sessions past their retention", retired.len());
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::error!("Failed to remove retired sessions: {}", e),
+                }
             }
         })
     }
@@ -2168,6 +4810,12 @@ impl CleanupManager {
             }
         }
 
+        // Stopped sessions stay queryable for the retention period, after which the cleanup task
+        // removes them
+        if !session_manager.get_config().get_stopped_session_retention().is_zero() {
+            return Ok(());
+        }
+
         // Finally, remove the session from tracking
         match session_manager.remove_session(session_id) {
             Ok(_) => {
@@ -2198,6 +4846,11 @@ impl CleanupManager {
         stats.expired_sessions_found = expired_sessions.len();
 
         for session_id in expired_sessions {
+            let was_running = self
+                .session_manager
+                .get_session(&session_id)
+                .is_ok_and(|session| matches!(session.status, SessionStatus::Running));
+
             match Self::cleanup_session_and_resources(
                 &self.session_manager,
                 &self.resource_manager,
@@ -2205,6 +4858,9 @@ impl CleanupManager {
             ).await {
                 Ok(()) => {
                     stats.sessions_cleaned_up += 1;
+                    if was_running {
+                        stats.interrupted_executions += 1;
+                    }
                 }
                 Err(e) => {
                     tracing::error!("Failed to cleanup session {}: {}", session_id, e);
@@ -2213,6 +4869,9 @@ impl CleanupManager {
             }
         }
 
+        // Remove the stopped sessions kept long enough
+        stats.retired_sessions_removed = self.session_manager.remove_retired_sessions()?.len();
+
         // Get resource statistics
         let resource_stats = self.resource_manager.get_resource_stats()?;
         stats.active_sessions_after_cleanup = resource_stats.active_sessions;
@@ -2253,6 +4912,7 @@ impl CleanupManager {
             running_sessions: 0,
             error_sessions: 0,
             stopped_sessions: 0,
+            sessions_by_namespace: HashMap::new(),
             sessions_near_timeout: 0,
             expired_sessions: 0,
             resource_stats,
@@ -2275,6 +4935,10 @@ impl CleanupManager {
                 SessionStatus::Stopped => health_stats.stopped_sessions += 1,
             }
 
+            if let Some(owner) = session.owner.as_ref().filter(|_| session.status != SessionStatus::Stopped) {
+                *health_stats.sessions_by_namespace.entry(owner.clone()).or_default() += 1;
+            }
+
             // Check if session is near timeout
             if session.last_accessed.elapsed() > near_timeout_threshold && !matches!(session.status, SessionStatus::Stopped) {
                 health_stats.sessions_near_timeout += 1;
@@ -2304,6 +4968,9 @@ impl CleanupManager {
         cleanup_handles.0.abort();
         cleanup_handles.1.abort();
 
+        // Let executions still running report that they were cut short by the shutdown
+        self.session_manager.cancel_executions(CancelReason::ServerShutdown);
+
         // Perform final cleanup of all active sessions
         let all_sessions = self.session_manager.get_sessions(None)?;
         let mut stats = CleanupStats::default();
@@ -2318,6 +4985,9 @@ impl CleanupManager {
                 ).await {
                     Ok(()) => {
                         stats.sessions_cleaned_up += 1;
+                        if matches!(session.status, SessionStatus::Running) {
+                            stats.interrupted_executions += 1;
+                        }
                         tracing::info!("Cleaned up session during shutdown: {}", session.id);
                     }
                     Err(e) => {
@@ -2349,6 +5019,10 @@ pub struct CleanupStats {
     pub sessions_cleaned_up: usize,
     /// Number of cleanup errors encountered
     pub cleanup_errors: usize,
+    /// Number of sessions whose running execution was cut short by the cleanup
+    pub interrupted_executions: usize,
+    /// Number of stopped sessions removed after being kept for the stopped session retention
+    pub retired_sessions_removed: usize,
     /// Number of active sessions after cleanup
     pub active_sessions_after_cleanup: usize,
     /// Number of allocated ports after cleanup
@@ -2372,6 +5046,8 @@ pub struct SystemHealthStats {
     pub error_sessions: usize,
     /// Number of sessions in stopped state
     pub stopped_sessions: usize,
+    /// Number of sessions owned by each namespace, counted against its session limit
+    pub sessions_by_namespace: HashMap<String, usize>,
     /// Number of sessions that are near timeout (75% of timeout elapsed)
     pub sessions_near_timeout: usize,
     /// Number of sessions that should be expired
@@ -2384,9 +5060,13 @@ pub struct SystemHealthStats {
 // Automatic Sandbox Creation
 //--------------------------------------------------------------------------------------------------
 
-use crate::payload::{SandboxStartParams, SandboxConfig};
+use axum::Json;
+use microsandbox_core::config::NAMED_VOLUME_PREFIX;
+use microsandbox_core::management::orchestra;
+use microsandbox_utils::DEFAULT_PACKAGE_CACHE_GUEST_PATH;
+use crate::payload::{JsonRpcRequest, SandboxStartParams, SandboxStopParams, SandboxConfig};
 use crate::state::AppState;
-use crate::handler::sandbox_start_impl;
+use crate::handler::{forward_rpc_to_portal, sandbox_start_impl, sandbox_stop_impl};
 use crate::error::ServerError;
 
 /// Automatic sandbox creator that integrates with existing sandbox_start_impl
@@ -2397,6 +5077,18 @@ pub struct AutomaticSandboxCreator {
 }
 
 impl AutomaticSandboxCreator {
+    /// Maximum number of attempts to start a sandbox, including the first
+    const START_MAX_ATTEMPTS: u32 = 3;
+
+    /// Delay before the first retry, doubled for each further retry
+    const START_RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+    /// Time in seconds that installing the packages requested for a session may take
+    const PACKAGE_INSTALL_TIMEOUT_SECS: u64 = 600;
+
+    /// Number of trailing output lines of a failed package install that are reported
+    const PACKAGE_INSTALL_ERROR_LINES: usize = 20;
+
     /// Create a new AutomaticSandboxCreator
     pub fn new(config: ConfigurationManager) -> Self {
         Self {
@@ -2424,16 +5116,38 @@ impl AutomaticSandboxCreator {
             config: Some(sandbox_config),
         };
 
-        // Call the existing sandbox_start_impl function
-        match sandbox_start_impl(state, start_params).await {
+        // Call the existing sandbox_start_impl function, retrying transient failures
+        let mut attempt = 1;
+        let result = loop {
+            match sandbox_start_impl(state.clone(), start_params.clone()).await {
+                Err(e @ ServerError::TransientError(_)) if attempt < Self::START_MAX_ATTEMPTS => {
+                    let delay = Self::START_RETRY_BASE_DELAY * 2u32.pow(attempt - 1);
+                    tracing::warn!(
+                        "Transient error starting sandbox for session {} (attempt {}/{}), retrying in {:?}: {}",
+                        session_info.id, attempt, Self::START_MAX_ATTEMPTS, delay, e
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
+
+        match result {
             Ok(result) => {
-                tracing::info!("Successfully created sandbox for session {}: {}", 
-                    session_info.id, result);
+                tracing::info!("Successfully created sandbox for session {} after {} attempt(s): {}", 
+                    session_info.id, attempt, result);
+                if !session_info.packages.is_empty() {
+                    self.install_packages(state, session_info).await?;
+                }
                 Ok(result)
             }
-            Err(ServerError::InternalError(msg)) => {
+            Err(ServerError::ImageNotFound { image, registries }) => {
+                Err(SimplifiedMcpError::ImageNotFound { image, registries })
+            }
+            Err(ServerError::InternalError(msg) | ServerError::TransientError(msg)) => {
                 Err(SimplifiedMcpError::SessionCreationFailed(format!(
-                    "Internal error creating sandbox: {}", msg
+                    "Internal error creating sandbox after {} attempt(s): {}", attempt, msg
                 )))
             }
             Err(ServerError::ValidationError(e)) => {
@@ -2449,6 +5163,76 @@ impl AutomaticSandboxCreator {
         }
     }
 
+    /// Install the packages requested for a session into its sandbox
+    ///
+    /// Failures are reported as [`SimplifiedMcpError::PackageInstallFailed`] with the tail of
+    /// the package manager's output, so they can be told apart from the sandbox failing to start
+    /// or from the session's code failing later on.
+    async fn install_packages(
+        &self,
+        state: AppState,
+        session_info: &SessionInfo,
+    ) -> Result<(), SimplifiedMcpError> {
+        let install_failed = |reason: String| SimplifiedMcpError::PackageInstallFailed {
+            packages: session_info.packages.clone(),
+            reason,
+        };
+
+        let request = JsonRpcRequest::new(
+            "sandbox.packages.install".to_string(),
+            json!({
+                "sandbox": session_info.sandbox_name,
+                "namespace": session_info.namespace,
+                "language": session_info.language,
+                "packages": session_info.packages,
+                "timeout": Self::PACKAGE_INSTALL_TIMEOUT_SECS,
+            }),
+            json!(format!("packages-{}", session_info.id)),
+        );
+
+        let (_, Json(response)) = forward_rpc_to_portal(state, request)
+            .await
+            .map_err(|e| install_failed(e.to_string()))?;
+        if let Some(error) = response.error {
+            return Err(install_failed(error.message));
+        }
+
+        let result = response.result.unwrap_or_default();
+        if result.get("success").and_then(|v| v.as_bool()) != Some(true) {
+            let lines: Vec<&str> = result
+                .get("output")
+                .and_then(|v| v.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|line| line.get("text").and_then(|v| v.as_str()))
+                .filter(|text| !text.trim().is_empty())
+                .collect();
+            let tail = &lines[lines.len().saturating_sub(Self::PACKAGE_INSTALL_ERROR_LINES)..];
+
+            let status = match result.pointer("/termination/kind").and_then(|v| v.as_str()) {
+                Some("timeout") => format!("timed out after {} seconds", Self::PACKAGE_INSTALL_TIMEOUT_SECS),
+                Some("oom_killed") => "was killed for running out of memory".to_string(),
+                _ => format!(
+                    "exited with code {}",
+                    result.get("exit_code").and_then(|v| v.as_i64()).unwrap_or(-1)
+                ),
+            };
+
+            return Err(install_failed(format!(
+                "package manager {}:\n{}",
+                status,
+                tail.join("\n")
+            )));
+        }
+
+        tracing::info!(
+            "Installed packages {} for session {}",
+            session_info.packages.join(", "),
+            session_info.id
+        );
+        Ok(())
+    }
+
     /// Generate sandbox configuration based on session information
     fn generate_sandbox_config(&self, session_info: &SessionInfo) -> Result<SandboxConfig, SimplifiedMcpError> {
         // Get the container image for the template
@@ -2458,7 +5242,15 @@ impl AutomaticSandboxCreator {
             .clone();
 
         // Generate volumes configuration with shared volume mapping
-        let volumes = self.generate_volume_mappings();
+        let mut volumes = self.generate_volume_mappings(&session_info.namespace)?;
+
+        // Sessions of a template that install packages share a download cache
+        if !session_info.packages.is_empty() {
+            volumes.push(format!(
+                "{}msb-packages-{}:{}",
+                NAMED_VOLUME_PREFIX, session_info.language, DEFAULT_PACKAGE_CACHE_GUEST_PATH
+            ));
+        }
 
         // Generate ports configuration (empty for now, ports are managed by the existing system)
         let ports = Vec::new();
@@ -2476,26 +5268,31 @@ impl AutomaticSandboxCreator {
             envs,
             depends_on: Vec::new(),
             workdir: None, // Use container default
-            shell: None,   // Use container default
+            shell: Some(self.config.get_default_shell().to_string()),
             scripts: std::collections::HashMap::new(),
             exec: None,
+            run_as_user: None, // Use the unprivileged default
+            dns_servers: Vec::new(),
+            extra_hosts: Vec::new(),
         };
 
         Ok(config)
     }
 
     /// Generate volume mappings including shared volume if configured
-    fn generate_volume_mappings(&self) -> Vec<String> {
+    ///
+    /// A per-namespace shared volume is resolved for the given namespace.
+    fn generate_volume_mappings(&self, namespace: &str) -> Result<Vec<String>, SimplifiedMcpError> {
         let mut volumes = Vec::new();
 
         // Add shared volume mapping if configured
-        if let Some(host_path) = self.config.get_shared_volume_path() {
+        if let Some(host_path) = self.config.resolve_shared_volume_path(namespace)? {
             let guest_path = self.config.get_shared_volume_guest_path();
             let volume_mapping = format!("{}:{}", host_path.display(), guest_path);
             volumes.push(volume_mapping);
         }
 
-        volumes
+        Ok(volumes)
     }
 
     /// Generate environment variables for the sandbox
@@ -2537,23 +5334,49 @@ impl AutomaticSandboxCreator {
     }
 }
 
+/// Check that the packages requested for a session are usable as package manager arguments
+///
+/// Specs cannot be empty, contain whitespace, or start with `-`, which would let them pass
+/// options to pip or npm.
+pub fn validate_packages(packages: &[String]) -> Result<(), SimplifiedMcpError> {
+    for package in packages {
+        if package.is_empty()
+            || package.starts_with('-')
+            || package.chars().any(|c| c.is_whitespace() || c.is_control())
+        {
+            return Err(SimplifiedMcpError::ValidationError(format!(
+                "Invalid package '{}': package specs cannot be empty, contain whitespace or start with '-'",
+                package
+            )));
+        }
+    }
+
+    Ok(())
+}
+
 /// Enhanced SessionManager with automatic sandbox creation
 impl SessionManager {
     /// Create a session with automatic sandbox creation
     /// 
     /// This method extends the basic session creation to automatically create
     /// the underlying sandbox using the existing sandbox_start_impl functionality.
+    /// Any `packages` are installed into the sandbox once it has started. The session is owned
+    /// by the `owner` namespace, if any.
     pub async fn create_session_with_sandbox(
         &self,
         state: AppState,
         language: &str,
         flavor: SandboxFlavor,
+        packages: &[String],
+        owner: Option<&str>,
     ) -> Result<String, SimplifiedMcpError> {
+        validate_packages(packages)?;
+
         // First create the session entry
-        let session_id = self.create_session(language, flavor).await?;
+        let session_id = self.create_owned_session(language, flavor, owner).await?;
         
         // Get the session info
-        let session_info = self.get_session(&session_id)?;
+        let session_info = self.set_session_packages(&session_id, packages)?;
         
         // Update session status to creating
         self.update_session_status(&session_id, SessionStatus::Creating)?;
@@ -2578,33 +5401,387 @@ impl SessionManager {
         }
     }
 
-    /// Get or create a session with automatic sandbox creation
-    /// 
-    /// This method extends get_or_create_session to automatically create sandboxes
-    /// when new sessions are created.
-    pub async fn get_or_create_session_with_sandbox(
+    /// Create a session and start its sandbox in the background
+    ///
+    /// Unlike [`Self::create_session_with_sandbox`], this returns the session ID right away, with
+    /// the session in the `Creating` state. It moves to `Ready` once the sandbox has started, or
+    /// to `Error` if it could not be started. Use [`Self::wait_until_ready`] or poll
+    /// [`Self::get_sessions`] to find out when it is ready.
+    pub async fn create_session_with_sandbox_detached(
         &self,
         state: AppState,
-        session_id: Option<String>,
         language: &str,
         flavor: SandboxFlavor,
-    ) -> Result<SessionInfo, SimplifiedMcpError> {
-        match session_id {
-            None => {
-                // Create new session with sandbox
-                let new_session_id = self.create_session_with_sandbox(state, language, flavor).await?;
-                self.get_session(&new_session_id)
-            }
-            Some(id) => {
-                // Try to get existing session (same logic as before)
-                match self.get_session(&id) {
-                    Ok(session) => {
-                        // Validate that the session matches the requested parameters
-                        if session.language != language {
-                            return Err(SimplifiedMcpError::InvalidSessionState(
-                                format!("Session {} is for language '{}', but '{}' was requested", 
-                                    id, session.language, language)
-                            ));
+        packages: &[String],
+        owner: Option<&str>,
+    ) -> Result<String, SimplifiedMcpError> {
+        validate_packages(packages)?;
+
+        let session_id = self.create_owned_session(language, flavor, owner).await?;
+        let session_info = self.set_session_packages(&session_id, packages)?;
+        self.update_session_status(&session_id, SessionStatus::Creating)?;
+
+        let sessions = Arc::clone(&self.sessions);
+        let status_changed = Arc::clone(&self.status_changed);
+        let creator = AutomaticSandboxCreator::new(self.config.clone());
+        let task_session_id = session_id.clone();
+
+        tokio::spawn(async move {
+            let result = creator.create_sandbox_for_session(state.clone(), &session_info).await;
+            let status = match &result {
+                Ok(_) => {
+                    tracing::info!("Successfully created session {} with sandbox", task_session_id);
+                    SessionStatus::Ready
+                }
+                Err(e) => {
+                    tracing::error!("Failed to create sandbox for session {}: {}", task_session_id, e);
+                    SessionStatus::Error(e.to_string())
+                }
+            };
+
+            match Self::finish_creating_in(&sessions, &status_changed, &task_session_id, status) {
+                Ok(true) => {}
+                // The session was stopped or removed while its sandbox was starting
+                Ok(false) => {
+                    if result.is_ok() {
+                        tracing::info!(
+                            "Session {} was stopped while being created, stopping its sandbox",
+                            task_session_id
+                        );
+                        let params = SandboxStopParams {
+                            sandbox: session_info.sandbox_name.clone(),
+                            namespace: session_info.namespace.clone(),
+                        };
+                        if let Err(e) = sandbox_stop_impl(state, params).await {
+                            tracing::warn!("Failed to stop sandbox of session {}: {}", task_session_id, e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to update status of session {}: {}", task_session_id, e);
+                }
+            }
+        });
+
+        Ok(session_id)
+    }
+
+    /// Start a background task that restarts the sandboxes of crashed sessions
+    ///
+    /// Returns `None` if restarting crashed sessions is disabled in the configuration.
+    pub fn start_background_crash_recovery(
+        &self,
+        state: AppState,
+    ) -> Option<tokio::task::JoinHandle<()>> {
+        if self.config.get_max_session_restarts() == 0 {
+            return None;
+        }
+
+        Some(tokio::spawn(async move {
+            let session_manager = Arc::clone(state.get_session_manager());
+            let mut interval_timer = interval(Self::CRASH_CHECK_INTERVAL);
+
+            loop {
+                interval_timer.tick().await;
+
+                match session_manager.recover_crashed_sessions(&state).await {
+                    Ok(restarted) if !restarted.is_empty() => {
+                        tracing::info!("Restarted the sandboxes of {} crashed sessions", restarted.len());
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::error!("Failed to recover crashed sessions: {}", e);
+                    }
+                }
+            }
+        }))
+    }
+
+    /// Restart the sandboxes of ready sessions that crashed
+    ///
+    /// A session's sandbox has crashed when its supervisor no longer reports it running. It is
+    /// started again under the same name, so the session keeps its ID and the data in its
+    /// volumes, and goes through `Creating` back to `Ready`. Once a session has been restarted
+    /// `MSB_MAX_SESSION_RESTARTS` times, a further crash moves it to `Error` instead. Returns
+    /// the IDs of the sessions whose sandbox was restarted.
+    pub async fn recover_crashed_sessions(
+        &self,
+        state: &AppState,
+    ) -> Result<Vec<String>, SimplifiedMcpError> {
+        let ready_sessions: Vec<SessionInfo> = self
+            .get_sessions(None)?
+            .into_iter()
+            .filter(|session| session.status == SessionStatus::Ready)
+            .collect();
+
+        let mut restarted = Vec::new();
+        for session in ready_sessions {
+            match Self::is_sandbox_running(state, &session).await {
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(e) => {
+                    tracing::warn!("Failed to check the sandbox of session {}: {}", session.id, e);
+                    continue;
+                }
+            }
+
+            if self.restart_crashed_session(state.clone(), &session.id).await? {
+                restarted.push(session.id);
+            }
+        }
+
+        Ok(restarted)
+    }
+
+    /// Check whether the supervisor of a session's sandbox reports it running
+    async fn is_sandbox_running(
+        state: &AppState,
+        session: &SessionInfo,
+    ) -> Result<bool, SimplifiedMcpError> {
+        let namespace_dir = state.get_config().get_namespace_dir().join(&session.namespace);
+        let statuses = orchestra::status(vec![session.sandbox_name.clone()], Some(&namespace_dir), None)
+            .await
+            .map_err(|e| SimplifiedMcpError::InternalError(format!("Failed to get sandbox status: {}", e)))?;
+
+        Ok(statuses
+            .iter()
+            .any(|status| status.name == session.sandbox_name && status.running))
+    }
+
+    /// Restart the sandbox of a crashed session, or fail the session past the restart limit
+    ///
+    /// Returns whether the sandbox was restarted. Sessions that are no longer ready, e.g.
+    /// because an execution claimed them, are left alone.
+    async fn restart_crashed_session(
+        &self,
+        state: AppState,
+        session_id: &str,
+    ) -> Result<bool, SimplifiedMcpError> {
+        let max_restarts = self.config.get_max_session_restarts();
+        let session = {
+            let mut sessions = self.sessions.write().map_err(|e| {
+                SimplifiedMcpError::InternalError(format!("Failed to acquire write lock: {}", e))
+            })?;
+            let Some(session) = sessions.get_mut(session_id) else {
+                return Ok(false);
+            };
+            if session.status != SessionStatus::Ready {
+                return Ok(false);
+            }
+
+            if session.restarts >= max_restarts {
+                tracing::error!(
+                    "Sandbox of session {} crashed after {} restart(s), giving up",
+                    session_id, session.restarts
+                );
+                session.set_status(SessionStatus::Error(format!(
+                    "Sandbox crashed and was already restarted {} time(s)",
+                    session.restarts
+                )));
+                self.status_changed.notify_waiters();
+                return Ok(false);
+            }
+
+            session.restarts += 1;
+            session.set_status(SessionStatus::Creating);
+            self.status_changed.notify_waiters();
+            session.clone()
+        };
+
+        tracing::warn!(
+            "Sandbox of session {} crashed, restarting it (restart {}/{})",
+            session_id, session.restarts, max_restarts
+        );
+        let started = Instant::now();
+        let creator = AutomaticSandboxCreator::new(self.config.clone());
+        let result = creator.create_sandbox_for_session(state, &session).await;
+
+        let summary = format!("Sandbox crashed, restart {} of {}", session.restarts, max_restarts);
+        let (status, stdout, stderr) = match &result {
+            Ok(_) => (SessionStatus::Ready, format!("{}: restarted", summary), String::new()),
+            Err(e) => {
+                tracing::error!("Failed to restart the sandbox of session {}: {}", session_id, e);
+                (SessionStatus::Error(e.to_string()), format!("{}: failed", summary), e.to_string())
+            }
+        };
+        let entry = ExecutionHistoryEntry::new(
+            ExecutionKind::Restart,
+            "restart sandbox",
+            &stdout,
+            &stderr,
+            None,
+            started.elapsed().as_millis() as u64,
+        );
+
+        // The session may have been stopped and removed while its sandbox was restarting
+        let mut sessions = self.sessions.write().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire write lock: {}", e))
+        })?;
+        if let Some(session) = sessions.get_mut(session_id) {
+            session.record_execution(entry, self.config.get_max_execution_history());
+            if session.status == SessionStatus::Creating {
+                session.set_status(status);
+            }
+            self.status_changed.notify_waiters();
+        }
+
+        Ok(result.is_ok())
+    }
+
+    /// Record the caller that created a session as its owner and return the updated session
+    pub fn set_session_owner(
+        &self,
+        session_id: &str,
+        caller: &SessionCaller,
+    ) -> Result<SessionInfo, SimplifiedMcpError> {
+        let mut sessions = self.sessions.write().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire write lock: {}", e))
+        })?;
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| SimplifiedMcpError::SessionNotFound(session_id.to_string()))?;
+        session.owner = caller.owner().map(String::from);
+
+        Ok(session.clone())
+    }
+
+    /// Get a session the caller may use
+    ///
+    /// Fails with [`SimplifiedMcpError::SessionAccessDenied`] if the session belongs to another
+    /// namespace.
+    pub fn get_session_for(
+        &self,
+        session_id: &str,
+        caller: &SessionCaller,
+    ) -> Result<SessionInfo, SimplifiedMcpError> {
+        let session = self.get_session(session_id)?;
+        caller.check_access(&session)?;
+
+        Ok(session)
+    }
+
+    /// Record the packages to install into a session's sandbox and return the updated session
+    fn set_session_packages(
+        &self,
+        session_id: &str,
+        packages: &[String],
+    ) -> Result<SessionInfo, SimplifiedMcpError> {
+        let mut sessions = self.sessions.write().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire write lock: {}", e))
+        })?;
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| SimplifiedMcpError::SessionNotFound(session_id.to_string()))?;
+        session.packages = packages.to_vec();
+
+        Ok(session.clone())
+    }
+
+    /// Replace the preamble run before every execution in a session, or disable it with a blank
+    /// one
+    pub fn set_session_preamble(
+        &self,
+        session_id: &str,
+        preamble: &str,
+    ) -> Result<(), SimplifiedMcpError> {
+        self.config.check_code_size(preamble)?;
+
+        let mut sessions = self.sessions.write().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire write lock: {}", e))
+        })?;
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| SimplifiedMcpError::SessionNotFound(session_id.to_string()))?;
+        session.preamble = Some(preamble.to_string()).filter(|preamble| !preamble.trim().is_empty());
+
+        Ok(())
+    }
+
+    /// Get an idle session for a client, template and flavor, or create one
+    ///
+    /// Used in session affinity mode, where clients that don't pass a session ID reuse a warm
+    /// session instead of starting a new one on every call. Only sessions that were themselves
+    /// created in affinity mode for the same client are reused, and only while they are ready.
+    ///
+    /// Returns the session and whether it was newly created. The lookup and the creation happen
+    /// under one write lock, so concurrent calls of a client never create two sessions, and a
+    /// new session is never visible without its client.
+    pub async fn get_or_create_affine_session(
+        &self,
+        caller: &SessionCaller,
+        client_id: &str,
+        template: &str,
+        flavor: SandboxFlavor,
+    ) -> Result<(SessionInfo, bool), SimplifiedMcpError> {
+        let mut sessions = self.sessions.write().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire write lock: {}", e))
+        })?;
+
+        let reusable = sessions
+            .values_mut()
+            .filter(|session| session.is_reusable_by(caller.owner(), client_id, template, flavor))
+            .max_by_key(|session| session.last_accessed);
+
+        if let Some(session) = reusable {
+            session.touch();
+            tracing::debug!("Reusing session {} for client '{}'", session.id, client_id);
+            return Ok((session.clone(), false));
+        }
+
+        self.check_admission_in(&sessions, template, caller.owner())?;
+        let mut session = self.new_session_info(template, flavor, caller.owner());
+        session.affinity_client = Some(client_id.to_string());
+        sessions.insert(session.id.clone(), session.clone());
+
+        Ok((session, true))
+    }
+
+    /// Find the idle session a client would reuse for the template and flavor, without claiming it
+    pub fn find_reusable_session(
+        &self,
+        caller: &SessionCaller,
+        client_id: &str,
+        template: &str,
+        flavor: SandboxFlavor,
+    ) -> Result<Option<SessionInfo>, SimplifiedMcpError> {
+        let sessions = self.sessions.read().map_err(|e| {
+            SimplifiedMcpError::InternalError(format!("Failed to acquire read lock: {}", e))
+        })?;
+
+        Ok(sessions
+            .values()
+            .filter(|session| session.is_reusable_by(caller.owner(), client_id, template, flavor))
+            .max_by_key(|session| session.last_accessed)
+            .cloned())
+    }
+
+    /// Get or create a session with automatic sandbox creation
+    /// 
+    /// This method extends get_or_create_session to automatically create sandboxes
+    /// when new sessions are created.
+    pub async fn get_or_create_session_with_sandbox(
+        &self,
+        state: AppState,
+        session_id: Option<String>,
+        language: &str,
+        flavor: SandboxFlavor,
+    ) -> Result<SessionInfo, SimplifiedMcpError> {
+        match session_id {
+            None => {
+                // Create new session with sandbox
+                let new_session_id = self.create_session_with_sandbox(state, language, flavor, &[], None).await?;
+                self.get_session(&new_session_id)
+            }
+            Some(id) => {
+                // Try to get existing session (same logic as before)
+                match self.get_session(&id) {
+                    Ok(session) => {
+                        // Validate that the session matches the requested parameters
+                        if session.language != language {
+                            return Err(SimplifiedMcpError::InvalidSessionState(
+                                format!("Session {} is for language '{}', but '{}' was requested", 
+                                    id, session.language, language)
+                            ));
                         }
                         
                         // Check if session is in a valid state
@@ -2633,6 +5810,110 @@ impl SessionManager {
     }
 }
 
+//--------------------------------------------------------------------------------------------------
+// Warm Start
+//--------------------------------------------------------------------------------------------------
+
+use microsandbox_core::{management::image, oci::Reference};
+use crate::handler::log_pull_progress;
+
+/// What was warmed when the server started
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WarmStartReport {
+    /// Images of the warmed templates that were already in the local image store
+    pub cached_images: Vec<String>,
+    /// Images of the warmed templates that had to be pulled
+    pub pulled_images: Vec<String>,
+    /// Templates a sandbox was booted for
+    pub booted_templates: Vec<String>,
+    /// Templates that could not be warmed, with why
+    pub failed: Vec<(String, String)>,
+}
+
+impl SessionManager {
+    /// Warm the templates configured with `MSB_WARM_TEMPLATES`, as `MSB_WARM_ON_START` asks
+    ///
+    /// Pulls the image of each template into the local image store, so the first session of the
+    /// template does not wait for the pull. In `sandboxes` mode, a sandbox of each template is
+    /// then booted once with the default flavor and stopped again, which also prepares its root
+    /// filesystem. A template that fails to warm is logged and skipped; the server still starts.
+    pub async fn warm_on_start(&self, state: AppState) -> WarmStartReport {
+        let mode = self.config.get_warm_on_start();
+        let mut report = WarmStartReport::default();
+        if mode == WarmOnStart::Off {
+            return report;
+        }
+
+        for template in self.config.get_warm_templates() {
+            let Some(image_name) = self.template_mapping.get_image(template) else {
+                report.failed.push((template.clone(), "unsupported template".to_string()));
+                continue;
+            };
+
+            let reference = match image_name.parse::<Reference>() {
+                Ok(reference) => reference,
+                Err(e) => {
+                    report.failed.push((template.clone(), e.to_string()));
+                    continue;
+                }
+            };
+
+            let started = Instant::now();
+            match image::prefetch([reference], Some(&log_pull_progress)).await {
+                Ok(prefetched) if prefetched.pulled.is_empty() => {
+                    tracing::info!("Warm start: image {} for template {} is cached", image_name, template);
+                    report.cached_images.push(image_name.clone());
+                }
+                Ok(_) => {
+                    tracing::info!(
+                        "Warm start: pulled image {} for template {} in {:?}",
+                        image_name, template, started.elapsed()
+                    );
+                    report.pulled_images.push(image_name.clone());
+                }
+                Err(e) => {
+                    tracing::warn!("Warm start: failed to pull image {} for template {}: {}", image_name, template, e);
+                    report.failed.push((template.clone(), e.to_string()));
+                    continue;
+                }
+            }
+
+            if mode != WarmOnStart::Sandboxes {
+                continue;
+            }
+
+            let started = Instant::now();
+            let flavor = self.config.get_default_flavor();
+            match self.create_session_with_sandbox(state.clone(), template, flavor, &[], None).await {
+                Ok(session_id) => {
+                    if let Err(e) = self.teardown_session(&session_id).await {
+                        tracing::warn!("Warm start: failed to stop the {} sandbox it booted: {}", template, e);
+                    }
+                    tracing::info!(
+                        "Warm start: booted a {} sandbox for template {} in {:?}",
+                        flavor, template, started.elapsed()
+                    );
+                    report.booted_templates.push(template.clone());
+                }
+                Err(e) => {
+                    tracing::warn!("Warm start: failed to boot a sandbox for template {}: {}", template, e);
+                    report.failed.push((template.clone(), e.to_string()));
+                }
+            }
+        }
+
+        tracing::info!(
+            "Warm start: {} images cached, {} pulled, {} sandboxes booted, {} templates failed",
+            report.cached_images.len(),
+            report.pulled_images.len(),
+            report.booted_templates.len(),
+            report.failed.len()
+        );
+
+        report
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
@@ -2666,6 +5947,26 @@ mod tests {
         assert!(allocation.age_seconds() < 2); // Should be very recent
     }
 
+    #[test]
+    fn test_resource_allocation_age_survives_persistence() {
+        let mut allocation = ResourceAllocation::new(
+            "test-session".to_string(),
+            SandboxFlavor::Small,
+            8080,
+        );
+        allocation.allocated_at = SystemTime::now() - Duration::from_secs(3 * 3600);
+
+        // A reloaded allocation keeps the age it had before it was persisted
+        let reloaded: ResourceAllocation =
+            serde_json::from_str(&serde_json::to_string(&allocation).unwrap()).unwrap();
+        assert_eq!(reloaded.allocated_at, allocation.allocated_at);
+        assert!(reloaded.age() > Duration::from_secs(2 * 3600));
+
+        // An allocation time in the future counts as no age
+        allocation.allocated_at = SystemTime::now() + Duration::from_secs(3600);
+        assert_eq!(allocation.age(), Duration::ZERO);
+    }
+
     #[test]
     fn test_port_manager_creation() {
         let port_manager = PortManager::new(8000, 8010).unwrap();
@@ -2885,6 +6186,13 @@ mod tests {
         assert_eq!(SandboxFlavor::Large.get_cpus(), 4);
     }
 
+    #[test]
+    fn test_sandbox_flavor_default_exec_timeout() {
+        assert_eq!(SandboxFlavor::Small.default_exec_timeout(), Duration::from_secs(30));
+        assert_eq!(SandboxFlavor::Medium.default_exec_timeout(), Duration::from_secs(120));
+        assert_eq!(SandboxFlavor::Large.default_exec_timeout(), Duration::from_secs(300));
+    }
+
     #[test]
     fn test_sandbox_flavor_from_str() {
         assert_eq!("small".parse::<SandboxFlavor>().unwrap(), SandboxFlavor::Small);
@@ -2940,7 +6248,7 @@ mod tests {
         assert_eq!(request.code, "print('hello')");
         assert_eq!(request.template, Some("python".to_string()));
         assert_eq!(request.session_id, Some("test-session".to_string()));
-        assert_eq!(request.flavor, Some(SandboxFlavor::Medium));
+        assert_eq!(request.flavor, Some(SandboxFlavor::Medium.into()));
     }
 
     #[test]
@@ -2960,7 +6268,7 @@ mod tests {
         assert_eq!(request.args, Some(vec!["-la".to_string()]));
         assert_eq!(request.template, Some("python".to_string()));
         assert_eq!(request.session_id, Some("test-session".to_string()));
-        assert_eq!(request.flavor, Some(SandboxFlavor::Large));
+        assert_eq!(request.flavor, Some(SandboxFlavor::Large.into()));
     }
 
     #[test]
@@ -2970,8 +6278,11 @@ mod tests {
             stdout: "Hello, World!".to_string(),
             stderr: "".to_string(),
             exit_code: Some(0),
+            termination: Some(Termination::Exited(0)),
             execution_time_ms: 150,
             session_created: true,
+            flavor: Some(SandboxFlavor::Small),
+            cancellation_reason: None,
         };
         
         let json = serde_json::to_string(&response).unwrap();
@@ -2979,6 +6290,41 @@ mod tests {
         assert!(json.contains("Hello, World!"));
         assert!(json.contains("150"));
         assert!(json.contains("true"));
+        assert!(json.contains(r#""termination":{"kind":"exited","value":0}"#));
+    }
+
+    #[test]
+    fn test_cancel_reason_serialization() {
+        let cases = [
+            (CancelReason::UserCancelled, "user_cancelled"),
+            (CancelReason::Timeout, "timeout"),
+            (CancelReason::ServerShutdown, "server_shutdown"),
+            (CancelReason::MemoryPressure, "memory_pressure"),
+        ];
+
+        for (reason, expected) in cases {
+            assert_eq!(serde_json::to_value(reason).unwrap(), serde_json::json!(expected));
+        }
+
+        assert_eq!(CancelReason::from_termination(Termination::Timeout), Some(CancelReason::Timeout));
+        assert_eq!(CancelReason::from_termination(Termination::OomKilled), Some(CancelReason::MemoryPressure));
+        assert_eq!(CancelReason::from_termination(Termination::Exited(1)), None);
+        assert_eq!(CancelReason::from_termination(Termination::Signaled(9)), None);
+    }
+
+    #[test]
+    fn test_termination_serialization() {
+        let cases = [
+            (Termination::Exited(1), serde_json::json!({"kind": "exited", "value": 1})),
+            (Termination::Signaled(9), serde_json::json!({"kind": "signaled", "value": 9})),
+            (Termination::Timeout, serde_json::json!({"kind": "timeout"})),
+            (Termination::OomKilled, serde_json::json!({"kind": "oom_killed"})),
+        ];
+
+        for (termination, expected) in cases {
+            assert_eq!(serde_json::to_value(termination).unwrap(), expected);
+            assert_eq!(serde_json::from_value::<Termination>(expected).unwrap(), termination);
+        }
     }
 
     #[test]
@@ -2988,8 +6334,13 @@ mod tests {
         assert_eq!(config.get_shared_volume_path(), None);
         assert_eq!(config.get_shared_volume_guest_path(), "/shared");
         assert_eq!(config.get_default_flavor(), SandboxFlavor::Small);
+        assert_eq!(config.get_default_shell(), DEFAULT_GUEST_SHELL);
         assert_eq!(config.get_session_timeout(), Duration::from_secs(1800));
         assert_eq!(config.get_max_sessions(), 10);
+        assert_eq!(config.get_max_code_bytes(), DEFAULT_MAX_CODE_BYTES);
+        assert_eq!(config.get_max_command_args(), DEFAULT_MAX_COMMAND_ARGS);
+        assert_eq!(config.get_max_command_args_bytes(), DEFAULT_MAX_COMMAND_ARGS_BYTES);
+        assert_eq!(config.get_max_body_size(), DEFAULT_PORTAL_MAX_BODY_SIZE);
         assert!(!config.has_shared_volume());
     }
 
@@ -3003,6 +6354,11 @@ mod tests {
         invalid_config.shared_volume_guest_path = "relative/path".to_string();
         assert!(invalid_config.validate().is_err());
 
+        // Test invalid default shell (not absolute)
+        let mut invalid_config = config.clone();
+        invalid_config.default_shell = "bash".to_string();
+        assert!(invalid_config.validate().is_err());
+
         // Test invalid session timeout (too short)
         let mut invalid_config = config.clone();
         invalid_config.session_timeout = Duration::from_secs(30);
@@ -3022,6 +6378,26 @@ mod tests {
         let mut invalid_config = config.clone();
         invalid_config.max_sessions = 200;
         assert!(invalid_config.validate().is_err());
+
+        // Test invalid stopped session retention (too long)
+        let mut invalid_config = config.clone();
+        invalid_config.stopped_session_retention = Duration::from_secs(86401);
+        assert!(invalid_config.validate().is_err());
+
+        // Test invalid max session restarts (too many)
+        let mut invalid_config = config.clone();
+        invalid_config.max_session_restarts = MAX_SESSION_RESTARTS_LIMIT + 1;
+        assert!(invalid_config.validate().is_err());
+
+        // Test invalid max concurrent executions (zero)
+        let mut invalid_config = config.clone();
+        invalid_config.max_concurrent_executions = 0;
+        assert!(invalid_config.validate().is_err());
+
+        // Test invalid max code size (above the portal body limit)
+        let mut invalid_config = config.clone();
+        invalid_config.max_code_bytes = DEFAULT_PORTAL_MAX_BODY_SIZE + 1;
+        assert!(invalid_config.validate().is_err());
     }
 
     #[test]
@@ -3200,93 +6576,865 @@ mod tests {
         assert!(matches!(result, Err(SimplifiedMcpError::ResourceLimitExceeded(_))));
     }
 
+    #[tokio::test]
+    async fn test_session_manager_max_sessions_per_namespace() {
+        let mut config = ConfigurationManager::default();
+        config.max_sessions = 5;
+        config.max_sessions_per_namespace = Some(1);
+        config.namespace_max_sessions = parse_namespace_max_sessions("tenant-b=2").unwrap();
+        let manager = SessionManager::new(config);
+
+        // A namespace at its own limit is rejected while others can still create sessions
+        manager.create_owned_session("python", SandboxFlavor::Small, Some("tenant-a")).await.unwrap();
+        let result = manager.create_owned_session("python", SandboxFlavor::Small, Some("tenant-a")).await;
+        assert!(matches!(result, Err(SimplifiedMcpError::ResourceLimitExceeded(msg)) if msg.contains("tenant-a")));
+
+        // Overrides take precedence, and unowned sessions only count against the global limit
+        manager.create_owned_session("python", SandboxFlavor::Small, Some("tenant-b")).await.unwrap();
+        manager.create_owned_session("node", SandboxFlavor::Small, Some("tenant-b")).await.unwrap();
+        assert!(manager.check_session_admission("python", Some("tenant-b")).is_err());
+        manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+        manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+
+        // The namespace limit is reported even when the server is full as well
+        let result = manager.check_session_admission("python", Some("tenant-a"));
+        assert!(matches!(result, Err(SimplifiedMcpError::ResourceLimitExceeded(msg)) if msg.contains("tenant-a")));
+        let result = manager.check_session_admission("python", Some("tenant-c"));
+        assert!(matches!(result, Err(SimplifiedMcpError::ResourceLimitExceeded(msg)) if !msg.contains("namespace")));
+    }
+
+    #[tokio::test]
+    async fn test_session_manager_maintenance_mode() {
+        let config = ConfigurationManager::default();
+        let manager = SessionManager::new(config);
+        let existing = manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+
+        // New sessions are rejected while existing ones stay usable
+        assert!(!manager.set_maintenance(true));
+        assert!(manager.is_in_maintenance());
+        let result = manager.create_session("python", SandboxFlavor::Small).await;
+        assert!(matches!(result, Err(SimplifiedMcpError::ServerInMaintenance)));
+        assert!(manager.touch_session(&existing).is_ok());
+        assert_eq!(manager.get_session_count().unwrap(), 1);
+
+        assert!(manager.set_maintenance(false));
+        assert!(manager.create_session("python", SandboxFlavor::Small).await.is_ok());
+    }
+
     #[tokio::test]
     async fn test_session_manager_get_session() {
         let config = ConfigurationManager::default();
         let manager = SessionManager::new(config);
 
-        let session_id = manager.create_session("python", SandboxFlavor::Medium).await.unwrap();
-        
-        // Test successful get
-        let session = manager.get_session(&session_id).unwrap();
-        assert_eq!(session.id, session_id);
-        assert_eq!(session.language, "python");
-        assert_eq!(session.flavor, SandboxFlavor::Medium);
+        let session_id = manager.create_session("python", SandboxFlavor::Medium).await.unwrap();
+        
+        // Test successful get
+        let session = manager.get_session(&session_id).unwrap();
+        assert_eq!(session.id, session_id);
+        assert_eq!(session.language, "python");
+        assert_eq!(session.flavor, SandboxFlavor::Medium);
+
+        // Test get non-existent session
+        let result = manager.get_session("non-existent");
+        assert!(matches!(result, Err(SimplifiedMcpError::SessionNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_session_manager_touch_session() {
+        let config = ConfigurationManager::default();
+        let manager = SessionManager::new(config);
+
+        let session_id = manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+        
+        // Get initial access time
+        let session_before = manager.get_session(&session_id).unwrap();
+        let initial_access = session_before.last_accessed;
+
+        // Wait a bit and touch the session
+        tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        manager.touch_session(&session_id).unwrap();
+
+        // Verify access time was updated
+        let session_after = manager.get_session(&session_id).unwrap();
+        assert!(session_after.last_accessed > initial_access);
+
+        // Test touch non-existent session
+        let result = manager.touch_session("non-existent");
+        assert!(matches!(result, Err(SimplifiedMcpError::SessionNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_session_manager_update_session_status() {
+        let config = ConfigurationManager::default();
+        let manager = SessionManager::new(config);
+
+        let session_id = manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+        
+        // Update status to Ready
+        manager.update_session_status(&session_id, SessionStatus::Ready).unwrap();
+        let session = manager.get_session(&session_id).unwrap();
+        assert_eq!(session.status, SessionStatus::Ready);
+
+        // Update status to Running
+        manager.update_session_status(&session_id, SessionStatus::Running).unwrap();
+        let session = manager.get_session(&session_id).unwrap();
+        assert_eq!(session.status, SessionStatus::Running);
+
+        // Update status to Error
+        let error_msg = "Test error".to_string();
+        manager.update_session_status(&session_id, SessionStatus::Error(error_msg.clone())).unwrap();
+        let session = manager.get_session(&session_id).unwrap();
+        assert_eq!(session.status, SessionStatus::Error(error_msg));
+
+        // Test update non-existent session
+        let result = manager.update_session_status("non-existent", SessionStatus::Ready);
+        assert!(matches!(result, Err(SimplifiedMcpError::SessionNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_session_manager_stop_session() {
+        let config = ConfigurationManager::default();
+        let manager = SessionManager::new(config);
+
+        let session_id = manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+        
+        // Stop the session
+        manager.stop_session(&session_id).await.unwrap();
+        
+        // Verify session is marked as stopped
+        let session = manager.get_session(&session_id).unwrap();
+        assert_eq!(session.status, SessionStatus::Stopped);
+
+        // Test stop non-existent session
+        let result = manager.stop_session("non-existent").await;
+        assert!(matches!(result, Err(SimplifiedMcpError::SessionNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_session_manager_get_quota() {
+        let config = ConfigurationManager::default();
+        let manager = SessionManager::new(config);
+
+        manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+        manager.create_session("node", SandboxFlavor::Medium).await.unwrap();
+
+        let stats = manager.get_resource_stats().unwrap();
+        let host_memory = MemoryInfo { total_kib: 8 * 1024 * 1024, available_kib: 4 * 1024 * 1024 };
+        let quota = QuotaResponse::new(&stats, Some(host_memory), Some(4));
+
+        assert_eq!(quota.max_sessions, 10);
+        assert_eq!(quota.active_sessions, 2);
+        assert_eq!(quota.remaining_sessions, 8);
+        assert_eq!(quota.available_ports, 8);
+        assert_eq!(quota.allocated_memory_mb, 3072);
+        assert_eq!(quota.allocated_cpus, 3);
+        assert_eq!(quota.remaining_memory_mb, Some(8192 - 3072));
+        assert_eq!(quota.remaining_cpus, Some(1));
+
+        // Headroom is unknown when the host can't be inspected
+        let quota = QuotaResponse::new(&stats, None, None);
+        assert_eq!(quota.remaining_memory_mb, None);
+        assert_eq!(quota.remaining_cpus, None);
+    }
+
+    #[tokio::test]
+    async fn test_session_manager_execution_history() {
+        let config = {
+            let _guard = ENV_TEST_MUTEX.lock().unwrap();
+            std::env::set_var("MSB_MAX_EXECUTION_HISTORY", "3");
+            let config = ConfigurationManager::from_env();
+            std::env::remove_var("MSB_MAX_EXECUTION_HISTORY");
+            config.unwrap()
+        };
+        let manager = SessionManager::new(config);
+
+        let session_id = manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+        for i in 0..5 {
+            let entry = ExecutionHistoryEntry::new(
+                ExecutionKind::Code,
+                &format!("print({})", i),
+                &i.to_string(),
+                "",
+                None,
+                1,
+            );
+            manager.record_execution(&session_id, entry).unwrap();
+        }
+
+        // Only the 3 most recent executions are retained
+        let history = manager.get_execution_history(&session_id, 0, 2).unwrap();
+        assert_eq!(history.total, 3);
+        assert_eq!(history.dropped, 2);
+        assert!(history.has_more);
+        let sequences: Vec<_> = history.entries.iter().map(|e| e.sequence).collect();
+        assert_eq!(sequences, vec![3, 4]);
+
+        let history = manager.get_execution_history(&session_id, 2, 2).unwrap();
+        assert!(!history.has_more);
+        assert_eq!(history.entries.len(), 1);
+        assert_eq!(history.entries[0].input, "print(4)");
+
+        let result = manager.get_execution_history("non-existent", 0, 10);
+        assert!(matches!(result, Err(SimplifiedMcpError::SessionNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_session_manager_usage() {
+        let manager = SessionManager::new(ConfigurationManager::default());
+
+        let session_id = manager.create_session("python", SandboxFlavor::Medium).await.unwrap();
+        let namespace = manager.get_session(&session_id).unwrap().namespace;
+        let entry = ExecutionHistoryEntry::new(ExecutionKind::Code, "print(1)", "1", "", None, 1500);
+        manager.record_execution(&session_id, entry).unwrap();
+        manager.record_usage(&session_id, Duration::from_millis(500)).unwrap();
+
+        // Executions are charged for the full flavor while they run
+        let usage = manager.get_usage(&namespace).unwrap();
+        let expected = NamespaceUsage {
+            executions: 2,
+            execution_seconds: 2.0,
+            cpu_seconds: 4.0,
+            memory_mb_seconds: 4096.0,
+        };
+        assert_eq!(usage.namespaces, BTreeMap::from([(namespace.clone(), expected.clone())]));
+
+        // Namespaces without executions report zero usage, "*" lists every charged namespace
+        let usage = manager.get_usage("idle").unwrap();
+        assert_eq!(usage.namespaces["idle"], NamespaceUsage::default());
+        let usage = manager.get_usage("*").unwrap();
+        assert_eq!(usage.namespaces, BTreeMap::from([(namespace.clone(), expected.clone())]));
+
+        // API keys scoped to a namespace only read their own usage, never "*"
+        let tenant = SessionCaller::from_token_namespace(&namespace);
+        let usage = manager.get_usage_for(&namespace, &tenant).unwrap();
+        assert_eq!(usage.namespaces, BTreeMap::from([(namespace.clone(), expected)]));
+        assert!(matches!(
+            manager.get_usage_for("other", &tenant),
+            Err(SimplifiedMcpError::NamespaceAccessDenied(ns)) if ns == "other"
+        ));
+        assert!(matches!(
+            manager.get_usage_for("*", &tenant),
+            Err(SimplifiedMcpError::NamespaceAccessDenied(ns)) if ns == "*"
+        ));
+        assert!(manager.get_usage_for("*", &SessionCaller::Unrestricted).is_ok());
+
+        let result = manager.record_usage("non-existent", Duration::from_secs(1));
+        assert!(matches!(result, Err(SimplifiedMcpError::SessionNotFound(_))));
+    }
+
+    #[test]
+    fn test_usage_ledger_persists_totals() {
+        let root = std::env::temp_dir().join(format!("msb-usage-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let file_path = root.join("namespace.usage");
+
+        // A missing file starts the ledger from zero
+        let ledger = UsageLedger::load(file_path.clone()).unwrap();
+        assert!(ledger.get_all().unwrap().is_empty());
+
+        ledger.record("tenant-a", SandboxFlavor::Small, Duration::from_secs(2));
+        ledger.record("tenant-a", SandboxFlavor::Small, Duration::from_secs(1));
+        ledger.record("tenant-b", SandboxFlavor::Large, Duration::from_secs(1));
+
+        // A reloaded ledger continues from the persisted totals
+        let reloaded = UsageLedger::load(file_path.clone()).unwrap();
+        assert_eq!(reloaded.get_all().unwrap(), ledger.get_all().unwrap());
+        let usage = reloaded.get("tenant-a").unwrap();
+        assert_eq!(usage.executions, 2);
+        assert_eq!(usage.cpu_seconds, 3.0);
+        assert_eq!(usage.memory_mb_seconds, 3072.0);
+
+        // A corrupt file is reported instead of being silently overwritten
+        std::fs::write(&file_path, "not json").unwrap();
+        assert!(UsageLedger::load(file_path).is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_usage_ledger_degrades_until_writable() {
+        let root = std::env::temp_dir().join(format!("msb-usage-{}", Uuid::new_v4()));
+        let file_path = root.join("namespace.usage");
+        let ledger = UsageLedger::load(file_path.clone()).unwrap();
+
+        // Without its directory the file can't be written, but usage is still accounted
+        ledger.record("tenant-a", SandboxFlavor::Small, Duration::from_secs(2));
+        ledger.record("tenant-a", SandboxFlavor::Small, Duration::from_secs(1));
+        assert!(ledger.is_degraded());
+        assert_eq!(ledger.get("tenant-a").unwrap().executions, 2);
+
+        ledger.retry_save();
+        assert!(ledger.is_degraded());
+
+        // Once the file is writable, the usage kept in memory is persisted
+        std::fs::create_dir_all(&root).unwrap();
+        ledger.retry_save();
+        assert!(!ledger.is_degraded());
+        let reloaded = UsageLedger::load(file_path).unwrap();
+        assert_eq!(reloaded.get("tenant-a").unwrap().executions, 2);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_configuration_manager_allowed_commands() {
+        let _guard = ENV_TEST_MUTEX.lock().unwrap();
+
+        // Unrestricted by default
+        std::env::remove_var("MSB_ALLOWED_COMMANDS");
+        let config = ConfigurationManager::from_env().unwrap();
+        assert!(config.get_allowed_commands().is_none());
+        assert!(config.check_command_allowed("rm").is_ok());
+
+        std::env::set_var("MSB_ALLOWED_COMMANDS", "python, pip,,");
+        let config = ConfigurationManager::from_env().unwrap();
+        std::env::remove_var("MSB_ALLOWED_COMMANDS");
+
+        assert_eq!(config.get_allowed_commands().unwrap().len(), 2);
+        assert!(config.check_command_allowed("python").is_ok());
+        assert!(config.check_command_allowed("pip").is_ok());
+        match config.check_command_allowed("/usr/bin/python") {
+            Err(SimplifiedMcpError::ValidationError(msg)) => {
+                assert!(msg.contains("'/usr/bin/python'"));
+                assert!(msg.contains("pip, python"));
+            }
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forward_rpc_rejects_disallowed_commands() {
+        let root = std::env::temp_dir().join(format!("msb-allowed-commands-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let server_config = crate::config::Config::new(
+            None,
+            "127.0.0.1".to_string(),
+            8080,
+            Some(root.clone()),
+            true,
+        )
+        .unwrap();
+        let port_manager = crate::port::PortManager::new(&root).await.unwrap();
+        let state = {
+            let _guard = ENV_TEST_MUTEX.lock().unwrap();
+            std::env::set_var("MSB_ALLOWED_COMMANDS", "python");
+            let state = AppState::new(
+                Arc::new(server_config),
+                Arc::new(tokio::sync::RwLock::new(port_manager)),
+            );
+            std::env::remove_var("MSB_ALLOWED_COMMANDS");
+            state
+        };
+
+        // Commands sent through the RPC endpoint are held to the allowlist like MCP commands
+        let request = JsonRpcRequest::new(
+            "sandbox.command.run".to_string(),
+            json!({
+                "sandbox": "missing",
+                "namespace": "default",
+                "command": "rm",
+                "args": ["-rf", "/"],
+            }),
+            json!(1),
+        );
+        match forward_rpc_to_portal(state, request).await {
+            Err(ServerError::ValidationError(e)) => {
+                assert!(e.to_string().contains("Command 'rm' is not allowed"))
+            }
+            other => panic!("expected a validation error, got {:?}", other.map(|(status, _)| status)),
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_start_rejected_volume_assigns_no_port() {
+        let root = std::env::temp_dir().join(format!("msb-start-volume-{}", Uuid::new_v4()));
+        let allowed = root.join("allowed");
+        std::fs::create_dir_all(&allowed).unwrap();
+        let server_config = crate::config::Config::new(
+            None,
+            "127.0.0.1".to_string(),
+            8080,
+            Some(root.clone()),
+            true,
+        )
+        .unwrap();
+        let port_manager = crate::port::PortManager::new(&root).await.unwrap();
+        let state = {
+            let _guard = ENV_TEST_MUTEX.lock().unwrap();
+            std::env::set_var("MSB_ALLOWED_HOST_PATHS", allowed.display().to_string());
+            let state = AppState::new(
+                Arc::new(server_config),
+                Arc::new(tokio::sync::RwLock::new(port_manager)),
+            );
+            std::env::remove_var("MSB_ALLOWED_HOST_PATHS");
+            state
+        };
+
+        let params = SandboxStartParams {
+            sandbox: "volumes".to_string(),
+            namespace: "default".to_string(),
+            config: Some(
+                serde_json::from_value(json!({
+                    "image": "microsandbox/python",
+                    "volumes": [format!("{}:/data", root.display())],
+                }))
+                .unwrap(),
+            ),
+        };
+
+        // A start rejected before the sandbox is started leaves no portal port assigned
+        let result = sandbox_start_impl(state.clone(), params).await;
+        assert!(matches!(result, Err(ServerError::ValidationError(_))));
+        let port_manager = state.get_port_manager().read().await;
+        assert!(port_manager.get_port("default/volumes").is_none());
+        drop(port_manager);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_configuration_manager_allowed_host_paths() {
+        let root = std::env::temp_dir().join(format!("msb-host-paths-{}", Uuid::new_v4()));
+        let allowed = root.join("allowed");
+        let other = root.join("other");
+        std::fs::create_dir_all(&allowed).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+        std::os::unix::fs::symlink(&other, allowed.join("link")).unwrap();
+        let volume = |host: &Path| format!("{}:/data", host.display());
+
+        // Unrestricted by default
+        let mut config = ConfigurationManager::default();
+        assert!(config.check_volume_allowed(&volume(&other), &root).is_ok());
+
+        config.allowed_host_paths = Some(vec![allowed.clone()]);
+        assert!(config.check_volume_allowed(&volume(&allowed), &root).is_ok());
+        assert!(config.check_volume_allowed(&volume(&allowed.join("new/dir")), &root).is_ok());
+        assert!(config.check_volume_allowed("data:/data", &allowed).is_ok());
+        assert!(config.check_volume_allowed("volume:cache:/cache", &other).is_ok());
+
+        // Paths outside the allowed ones, also through a symlink, a relative path or '..'
+        for (volume, base_dir) in [
+            (volume(&other), &root),
+            (volume(&root.join("allowed2")), &root),
+            (volume(&allowed.join("link/x")), &root),
+            (volume(&allowed.join("../other")), &root),
+            ("data:/data".to_string(), &other),
+            ("/etc".to_string(), &root),
+        ] {
+            assert!(
+                matches!(
+                    config.check_volume_allowed(&volume, base_dir),
+                    Err(SimplifiedMcpError::ValidationError(_))
+                ),
+                "{} should be rejected",
+                volume
+            );
+        }
+
+        // Allowed paths must be absolute and contain the shared volume
+        config.shared_volume_path = Some(allowed.join("{namespace}"));
+        assert!(config.validate().is_ok());
+        config.shared_volume_path = Some(other.clone());
+        assert!(matches!(config.validate(), Err(SimplifiedMcpError::ConfigurationError(_))));
+        config.shared_volume_path = None;
+        config.allowed_host_paths = Some(vec![PathBuf::from("relative")]);
+        assert!(matches!(config.validate(), Err(SimplifiedMcpError::ConfigurationError(_))));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_configuration_manager_warm_on_start() {
+        let _guard = ENV_TEST_MUTEX.lock().unwrap();
+
+        // Nothing is warmed by default, and the default template is the one warmed
+        std::env::remove_var("MSB_WARM_ON_START");
+        std::env::remove_var("MSB_WARM_TEMPLATES");
+        let config = ConfigurationManager::from_env().unwrap();
+        assert_eq!(config.get_warm_on_start(), WarmOnStart::Off);
+        assert_eq!(config.get_warm_templates(), ["python".to_string()]);
+
+        std::env::set_var("MSB_WARM_ON_START", "Sandboxes");
+        std::env::set_var("MSB_WARM_TEMPLATES", "python, node,");
+        let config = ConfigurationManager::from_env();
+        std::env::set_var("MSB_WARM_TEMPLATES", "ruby");
+        let unsupported = ConfigurationManager::from_env();
+        std::env::set_var("MSB_WARM_ON_START", "everything");
+        let invalid = ConfigurationManager::from_env();
+        std::env::remove_var("MSB_WARM_ON_START");
+        std::env::remove_var("MSB_WARM_TEMPLATES");
+
+        let config = config.unwrap();
+        assert_eq!(config.get_warm_on_start(), WarmOnStart::Sandboxes);
+        assert_eq!(config.get_warm_templates(), ["python".to_string(), "node".to_string()]);
+        assert!(matches!(unsupported, Err(SimplifiedMcpError::ConfigurationError(msg)) if msg.contains("ruby")));
+        assert!(matches!(invalid, Err(SimplifiedMcpError::ConfigurationError(msg)) if msg.contains("everything")));
+    }
+
+    #[test]
+    fn test_configuration_manager_namespace_max_sessions() {
+        let _guard = ENV_TEST_MUTEX.lock().unwrap();
+
+        std::env::set_var("MSB_MAX_SESSIONS_PER_NAMESPACE", "3");
+        std::env::set_var("MSB_NAMESPACE_MAX_SESSIONS", "tenant-a=5, tenant-b=1,");
+        let config = ConfigurationManager::from_env();
+        std::env::set_var("MSB_NAMESPACE_MAX_SESSIONS", "tenant-a=50");
+        let too_many = ConfigurationManager::from_env();
+        std::env::set_var("MSB_NAMESPACE_MAX_SESSIONS", "tenant-a");
+        let invalid = ConfigurationManager::from_env();
+        std::env::remove_var("MSB_MAX_SESSIONS_PER_NAMESPACE");
+        std::env::remove_var("MSB_NAMESPACE_MAX_SESSIONS");
+
+        let config = config.unwrap();
+        assert_eq!(config.get_max_sessions_for_namespace("tenant-a"), Some(5));
+        assert_eq!(config.get_max_sessions_for_namespace("tenant-b"), Some(1));
+        assert_eq!(config.get_max_sessions_for_namespace("tenant-c"), Some(3));
+        assert!(matches!(too_many, Err(SimplifiedMcpError::ConfigurationError(msg)) if msg.contains("tenant-a")));
+        assert!(matches!(invalid, Err(SimplifiedMcpError::ConfigurationError(msg)) if msg.contains("tenant-a")));
+
+        // Namespaces are only limited by the global maximum by default
+        let config = ConfigurationManager::from_env().unwrap();
+        assert_eq!(config.get_max_sessions_for_namespace("tenant-a"), None);
+    }
+
+    #[test]
+    fn test_detect_template() {
+        assert_eq!(detect_template("#!/usr/bin/env python3\nx = 1"), Some("python"));
+        assert_eq!(detect_template("#!/usr/bin/env node\nx = 1"), Some("node"));
+        assert_eq!(detect_template("import os\nprint(os.getcwd())"), Some("python"));
+        assert_eq!(detect_template("from math import pi\ndef area(r):\n    return pi * r * r"), Some("python"));
+        assert_eq!(detect_template("const fs = require('fs');\nconsole.log(fs.readdirSync('.'));"), Some("node"));
+        assert_eq!(detect_template("import fs from 'fs';"), Some("node"));
+
+        // No signals, or as many for each language, is ambiguous
+        assert_eq!(detect_template("1 + 1"), None);
+        assert_eq!(detect_template("print('a')\nconsole.log('b')"), None);
+    }
+
+    #[test]
+    fn test_configuration_manager_template_for_code() {
+        let mut config = ConfigurationManager::default();
+        config.default_template = "node".to_string();
+
+        // Detection is opt-in
+        assert!(!config.is_template_detection_enabled());
+        assert_eq!(config.template_for_code("print('hi')"), "node");
+
+        config.detect_template = true;
+        assert_eq!(config.template_for_code("print('hi')"), "python");
+        assert_eq!(config.template_for_code("1 + 1"), "node");
+    }
+
+    #[test]
+    fn test_detect_flavor() {
+        let rules = parse_flavor_rules(DEFAULT_FLAVOR_RULES).unwrap();
+
+        assert_eq!(detect_flavor("print('hi')", &rules), SandboxFlavor::Small);
+        assert_eq!(detect_flavor("import pandas as pd", &rules), SandboxFlavor::Medium);
+        assert_eq!(detect_flavor("import torch.nn as nn", &rules), SandboxFlavor::Large);
+        assert_eq!(detect_flavor("import numpy as np\nfrom torch import nn", &rules), SandboxFlavor::Large);
+        assert_eq!(detect_flavor("const tf = require('@tensorflow/tfjs');", &rules), SandboxFlavor::Large);
+        assert_eq!(detect_flavor("pip install torch", &rules), SandboxFlavor::Large);
+
+        // Words only match on their own, not as part of other identifiers
+        assert_eq!(detect_flavor("torchlight = 1\nmy_numpy_notes = []", &rules), SandboxFlavor::Small);
+
+        // Large inline data gets more memory
+        let data = format!("data = {:?}", "x".repeat(300 * 1024));
+        assert_eq!(detect_flavor(&data, &rules), SandboxFlavor::Medium);
+
+        assert_eq!(detect_flavor("import torch", &[]), SandboxFlavor::Small);
+    }
+
+    #[test]
+    fn test_parse_flavor_rules() {
+        let rules = parse_flavor_rules(" torch = large, >1024=medium,").unwrap();
+        assert_eq!(
+            rules,
+            vec![
+                FlavorRule { pattern: FlavorPattern::Word("torch".to_string()), flavor: SandboxFlavor::Large },
+                FlavorRule { pattern: FlavorPattern::LargerThan(1024), flavor: SandboxFlavor::Medium },
+            ]
+        );
+        assert!(parse_flavor_rules("").unwrap().is_empty());
+
+        assert!(parse_flavor_rules("torch").is_err());
+        assert!(parse_flavor_rules("torch=huge").is_err());
+        assert!(parse_flavor_rules("torch=auto").is_err());
+        assert!(parse_flavor_rules(">1k=medium").is_err());
+        assert!(parse_flavor_rules("@tensorflow/tfjs=large").is_err());
+    }
+
+    #[test]
+    fn test_flavor_selection_deserialization() {
+        let selection: FlavorSelection = serde_json::from_value(json!("auto")).unwrap();
+        assert_eq!(selection, FlavorSelection::Auto);
+        let selection: FlavorSelection = serde_json::from_value(json!("Large")).unwrap();
+        assert_eq!(selection, FlavorSelection::Fixed(SandboxFlavor::Large));
+
+        let error = serde_json::from_value::<FlavorSelection>(json!("huge")).unwrap_err();
+        assert!(error.to_string().contains("Invalid sandbox flavor: huge"));
+    }
+
+    #[test]
+    fn test_configuration_manager_flavor_for() {
+        let mut config = ConfigurationManager::default();
+
+        assert_eq!(config.flavor_for(None, "import torch"), SandboxFlavor::Small);
+        assert_eq!(config.flavor_for(Some(SandboxFlavor::Medium.into()), "import torch"), SandboxFlavor::Medium);
+        assert_eq!(config.flavor_for(Some(FlavorSelection::Auto), "import torch"), SandboxFlavor::Large);
+        assert_eq!(config.flavor_for(Some(FlavorSelection::Auto), "print('hi')"), SandboxFlavor::Small);
+
+        config.flavor_rules = parse_flavor_rules("duckdb=medium").unwrap();
+        assert_eq!(config.flavor_for(Some(FlavorSelection::Auto), "import torch"), SandboxFlavor::Small);
+        assert_eq!(config.flavor_for(Some(FlavorSelection::Auto), "import duckdb"), SandboxFlavor::Medium);
+    }
+
+    #[test]
+    fn test_configuration_manager_code_size_limit() {
+        let mut config = ConfigurationManager::default();
+        config.max_code_bytes = 1024;
+
+        assert!(config.check_code_size(&"x".repeat(1024)).is_ok());
+        match config.check_code_size(&"x".repeat(1025)) {
+            Err(SimplifiedMcpError::ValidationError(msg)) => {
+                assert!(msg.contains("1025 bytes"));
+                assert!(msg.contains("limit of 1024 bytes"));
+            }
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_configuration_manager_command_args_limits() {
+        let mut config = ConfigurationManager::default();
+        config.max_command_args = 2;
+        config.max_command_args_bytes = 1024;
+
+        let args = vec!["-c".to_string(), "x".repeat(1022)];
+        assert!(config.check_command_args(&args).is_ok());
+        assert!(config.check_command_args(&[]).is_ok());
+
+        match config.check_command_args(&["a", "b", "c"].map(String::from)) {
+            Err(SimplifiedMcpError::ValidationError(msg)) => {
+                assert!(msg.contains("3 arguments"));
+                assert!(msg.contains("limit of 2 arguments"));
+            }
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+
+        match config.check_command_args(&["-c".to_string(), "x".repeat(1023)]) {
+            Err(SimplifiedMcpError::ValidationError(msg)) => {
+                assert!(msg.contains("1025 bytes"));
+                assert!(msg.contains("limit of 1024 bytes"));
+            }
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+
+        config.max_command_args = 0;
+        assert!(config.validate().is_err());
+        config.max_command_args = 2;
+        config.max_command_args_bytes = 512;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_configuration_manager_check_imports() {
+        let mut config = ConfigurationManager::default();
+        assert!(config.check_imports("import os", "python").is_ok());
 
-        // Test get non-existent session
-        let result = manager.get_session("non-existent");
-        assert!(matches!(result, Err(SimplifiedMcpError::SessionNotFound(_))));
+        config.import_policy = ImportPolicy::new(None::<Vec<&str>>, ["os", "subprocess"]);
+        assert!(config.check_imports("import json", "python").is_ok());
+        match config.check_imports("import os\nimport subprocess as sp", "python") {
+            Err(SimplifiedMcpError::ValidationError(msg)) => {
+                assert_eq!(msg, "Code imports disallowed modules: os, subprocess");
+            }
+            other => panic!("expected a validation error, got {:?}", other),
+        }
     }
 
     #[tokio::test]
-    async fn test_session_manager_touch_session() {
-        let config = ConfigurationManager::default();
-        let manager = SessionManager::new(config);
+    async fn test_session_manager_affine_session_reuse() {
+        let manager = SessionManager::new(ConfigurationManager::default());
+        let caller = SessionCaller::default();
+
+        let (first, created) = manager
+            .get_or_create_affine_session(&caller, "client-a", "python", SandboxFlavor::Small)
+            .await
+            .unwrap();
+        assert!(created);
+        assert_eq!(first.affinity_client.as_deref(), Some("client-a"));
+
+        // Same client, template and flavor reuses the idle session
+        let (again, created) = manager
+            .get_or_create_affine_session(&caller, "client-a", "python", SandboxFlavor::Small)
+            .await
+            .unwrap();
+        assert!(!created);
+        assert_eq!(again.id, first.id);
+
+        // A different client, template or flavor gets its own session
+        for (client, template, flavor) in [
+            ("client-b", "python", SandboxFlavor::Small),
+            ("client-a", "node", SandboxFlavor::Small),
+            ("client-a", "python", SandboxFlavor::Medium),
+        ] {
+            let (other, created) = manager
+                .get_or_create_affine_session(&caller, client, template, flavor)
+                .await
+                .unwrap();
+            assert!(created);
+            assert_ne!(other.id, first.id);
+        }
 
-        let session_id = manager.create_session("python", SandboxFlavor::Small).await.unwrap();
-        
-        // Get initial access time
-        let session_before = manager.get_session(&session_id).unwrap();
-        let initial_access = session_before.last_accessed;
+        // Busy sessions and sessions created without affinity are not reused
+        manager.update_session_status(&first.id, SessionStatus::Running).unwrap();
+        manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+        let (busy, created) = manager
+            .get_or_create_affine_session(&caller, "client-a", "python", SandboxFlavor::Small)
+            .await
+            .unwrap();
+        assert!(created);
+        assert_ne!(busy.id, first.id);
+
+        // Another namespace using the same client ID does not get the client's session
+        manager.update_session_status(&busy.id, SessionStatus::Ready).unwrap();
+        let tenant = SessionCaller::from_token_namespace("tenant");
+        let (isolated, created) = manager
+            .get_or_create_affine_session(&tenant, "client-a", "python", SandboxFlavor::Small)
+            .await
+            .unwrap();
+        assert!(created);
+        assert_eq!(isolated.owner.as_deref(), Some("tenant"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_session_manager_affine_session_concurrent() {
+        let manager = Arc::new(SessionManager::new(ConfigurationManager::default()));
+
+        // Concurrent first calls of a client share a single new session
+        let calls: Vec<_> = (0..8)
+            .map(|_| {
+                let manager = Arc::clone(&manager);
+                tokio::spawn(async move {
+                    manager
+                        .get_or_create_affine_session(
+                            &SessionCaller::default(),
+                            "client-a",
+                            "python",
+                            SandboxFlavor::Small,
+                        )
+                        .await
+                        .unwrap()
+                })
+            })
+            .collect();
 
-        // Wait a bit and touch the session
-        tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
-        manager.touch_session(&session_id).unwrap();
+        let mut created = 0;
+        for call in calls {
+            let (session, new) = call.await.unwrap();
+            assert_eq!(session.affinity_client.as_deref(), Some("client-a"));
+            created += new as usize;
+        }
+        assert_eq!(created, 1);
+        assert_eq!(manager.get_sessions(None).unwrap().len(), 1);
+    }
 
-        // Verify access time was updated
-        let session_after = manager.get_session(&session_id).unwrap();
-        assert!(session_after.last_accessed > initial_access);
+    #[tokio::test]
+    async fn test_session_caller_access() {
+        let manager = SessionManager::new(ConfigurationManager::default());
+        let tenant = SessionCaller::from_token_namespace("tenant");
+        let other = SessionCaller::from_token_namespace("other");
+        let admin = SessionCaller::from_token_namespace("*");
+        assert_eq!(admin, SessionCaller::Unrestricted);
+
+        let owned = manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+        manager.set_session_owner(&owned, &tenant).unwrap();
+        assert!(manager.get_session_for(&owned, &tenant).is_ok());
+        assert!(manager.get_session_for(&owned, &admin).is_ok());
+        assert!(matches!(
+            manager.get_session_for(&owned, &other),
+            Err(SimplifiedMcpError::SessionAccessDenied(id)) if id == owned
+        ));
 
-        // Test touch non-existent session
-        let result = manager.touch_session("non-existent");
-        assert!(matches!(result, Err(SimplifiedMcpError::SessionNotFound(_))));
+        // Sessions created without a namespace are only for unrestricted callers
+        let unowned = manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+        assert!(manager.get_session_for(&unowned, &admin).is_ok());
+        assert!(manager.get_session_for(&unowned, &tenant).is_err());
     }
 
     #[tokio::test]
-    async fn test_session_manager_update_session_status() {
-        let config = ConfigurationManager::default();
-        let manager = SessionManager::new(config);
-
+    async fn test_session_manager_wait_until_ready() {
+        let manager = Arc::new(SessionManager::new(ConfigurationManager::default()));
         let session_id = manager.create_session("python", SandboxFlavor::Small).await.unwrap();
-        
-        // Update status to Ready
-        manager.update_session_status(&session_id, SessionStatus::Ready).unwrap();
-        let session = manager.get_session(&session_id).unwrap();
-        assert_eq!(session.status, SessionStatus::Ready);
 
-        // Update status to Running
-        manager.update_session_status(&session_id, SessionStatus::Running).unwrap();
-        let session = manager.get_session(&session_id).unwrap();
-        assert_eq!(session.status, SessionStatus::Running);
+        // A ready session is returned immediately
+        let session = manager
+            .wait_until_ready(&session_id, Duration::from_millis(10))
+            .await
+            .unwrap();
+        assert_eq!(session.status, SessionStatus::Ready);
 
-        // Update status to Error
-        let error_msg = "Test error".to_string();
-        manager.update_session_status(&session_id, SessionStatus::Error(error_msg.clone())).unwrap();
-        let session = manager.get_session(&session_id).unwrap();
-        assert_eq!(session.status, SessionStatus::Error(error_msg));
+        // A session still being created times out
+        manager.update_session_status(&session_id, SessionStatus::Creating).unwrap();
+        let result = manager
+            .wait_until_ready(&session_id, Duration::from_millis(10))
+            .await;
+        assert!(matches!(result, Err(SimplifiedMcpError::SessionTimeout(_))));
+
+        // Waiters are woken when the session becomes ready
+        let waiter = {
+            let manager = Arc::clone(&manager);
+            let session_id = session_id.clone();
+            tokio::spawn(async move {
+                manager.wait_until_ready(&session_id, Duration::from_secs(5)).await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        manager.update_session_status(&session_id, SessionStatus::Ready).unwrap();
+        assert_eq!(waiter.await.unwrap().unwrap().id, session_id);
 
-        // Test update non-existent session
-        let result = manager.update_session_status("non-existent", SessionStatus::Ready);
-        assert!(matches!(result, Err(SimplifiedMcpError::SessionNotFound(_))));
+        // A session whose sandbox failed to start is reported as a creation failure
+        manager
+            .update_session_status(&session_id, SessionStatus::Error("boom".to_string()))
+            .unwrap();
+        let result = manager
+            .wait_until_ready(&session_id, Duration::from_millis(10))
+            .await;
+        assert!(matches!(result, Err(SimplifiedMcpError::SessionCreationFailed(_))));
     }
 
     #[tokio::test]
-    async fn test_session_manager_stop_session() {
-        let config = ConfigurationManager::default();
-        let manager = SessionManager::new(config);
-
+    async fn test_session_manager_finish_creating() {
+        let manager = SessionManager::new(ConfigurationManager::default());
         let session_id = manager.create_session("python", SandboxFlavor::Small).await.unwrap();
-        
-        // Stop the session
+        let finish = |status| {
+            SessionManager::finish_creating_in(&manager.sessions, &manager.status_changed, &session_id, status)
+        };
+
+        manager.update_session_status(&session_id, SessionStatus::Creating).unwrap();
+        assert!(finish(SessionStatus::Ready).unwrap());
+        assert_eq!(manager.get_session(&session_id).unwrap().status, SessionStatus::Ready);
+
+        // A session stopped while its sandbox was starting stays stopped
+        manager.update_session_status(&session_id, SessionStatus::Creating).unwrap();
         manager.stop_session(&session_id).await.unwrap();
-        
-        // Verify session is marked as stopped
-        let session = manager.get_session(&session_id).unwrap();
-        assert_eq!(session.status, SessionStatus::Stopped);
+        assert!(!finish(SessionStatus::Ready).unwrap());
+        assert_eq!(manager.get_session(&session_id).unwrap().status, SessionStatus::Stopped);
 
-        // Test stop non-existent session
-        let result = manager.stop_session("non-existent").await;
-        assert!(matches!(result, Err(SimplifiedMcpError::SessionNotFound(_))));
+        manager.remove_session(&session_id).unwrap();
+        assert!(!finish(SessionStatus::Error("boom".to_string())).unwrap());
+    }
+
+    #[test]
+    fn test_execution_history_entry_truncation() {
+        let long_output = "x".repeat(ExecutionHistoryEntry::MAX_FIELD_CHARS + 10);
+        let entry = ExecutionHistoryEntry::new(ExecutionKind::Command, "ls", &long_output, "", Some(0), 1);
+        assert!(entry.stdout.ends_with("... (truncated)"));
+        assert_eq!(entry.stdout.len(), ExecutionHistoryEntry::MAX_FIELD_CHARS + "... (truncated)".len());
+        assert_eq!(entry.input, "ls");
     }
 
     #[tokio::test]
@@ -3391,9 +7539,151 @@ mod tests {
         assert!(cleaned_up.contains(&session1_id));
         assert!(cleaned_up.contains(&session2_id));
 
-        // Verify sessions were removed
+        // Verify sessions stay queryable as stopped for the retention period
         let sessions = manager.get_sessions(None).unwrap();
-        assert_eq!(sessions.len(), 0);
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions.iter().all(|session| session.status == SessionStatus::Stopped));
+    }
+
+    #[tokio::test]
+    async fn test_session_manager_stopped_session_retention() {
+        let mut config = ConfigurationManager::default();
+        config.max_sessions = 1;
+        config.stopped_session_retention = Duration::from_millis(50);
+        let manager = SessionManager::new(config);
+
+        let stopped = manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+        manager.stop_session(&stopped).await.unwrap();
+
+        // A stopped session is still reported, but no longer counts against the session limit
+        assert_eq!(manager.get_session(&stopped).unwrap().status, SessionStatus::Stopped);
+        assert_eq!(manager.get_resource_stats().unwrap().active_sessions, 0);
+        let live = manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+        assert!(manager.remove_retired_sessions().unwrap().is_empty());
+
+        // Once the retention passes, only the stopped session is removed
+        tokio::time::sleep(tokio::time::Duration::from_millis(60)).await;
+        assert_eq!(manager.remove_retired_sessions().unwrap(), vec![stopped.clone()]);
+        assert!(matches!(
+            manager.get_session(&stopped),
+            Err(SimplifiedMcpError::SessionNotFound(_))
+        ));
+        assert!(manager.get_session(&live).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_session_manager_cleanup_without_retention() {
+        let mut config = ConfigurationManager::default();
+        config.session_timeout = Duration::from_millis(50);
+        config.stopped_session_retention = Duration::ZERO;
+        let manager = SessionManager::new(config);
+
+        let session_id = manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+        tokio::time::sleep(tokio::time::Duration::from_millis(60)).await;
+
+        // Without a retention, expired sessions are removed right away
+        assert_eq!(manager.cleanup_expired_sessions().await.unwrap(), vec![session_id]);
+        assert!(manager.get_sessions(None).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_session_manager_dump_state() {
+        let mut config = ConfigurationManager::default();
+        config.max_sessions = 3;
+        let manager = SessionManager::new(config);
+
+        let session_id = manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+        manager
+            .sessions
+            .write()
+            .unwrap()
+            .get_mut(&session_id)
+            .unwrap()
+            .packages = vec!["GITHUB_TOKEN=abc123".to_string()];
+
+        let dump = manager.dump_state().unwrap();
+        assert_eq!(dump["maintenance"], json!(false));
+        assert_eq!(dump["config"]["max_sessions"], json!(3));
+        assert_eq!(dump["config"]["default_flavor"], json!("small"));
+
+        let sessions = dump["sessions"].as_array().unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0]["id"], json!(session_id));
+        assert_eq!(sessions[0]["status"], json!("ready"));
+
+        // Secrets are masked like they are in the logs
+        assert_eq!(sessions[0]["packages"], json!(["GITHUB_TOKEN=[REDACTED]"]));
+    }
+
+    #[tokio::test]
+    async fn test_session_manager_crashed_session_restart_limit() {
+        let root = std::env::temp_dir().join(format!("msb-restart-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let server_config = crate::config::Config::new(
+            None,
+            "127.0.0.1".to_string(),
+            8080,
+            Some(root.clone()),
+            true,
+        )
+        .unwrap();
+        let port_manager = crate::port::PortManager::new(&root).await.unwrap();
+        let state = AppState::new(
+            Arc::new(server_config),
+            Arc::new(tokio::sync::RwLock::new(port_manager)),
+        );
+
+        let mut config = ConfigurationManager::default();
+        config.max_session_restarts = 2;
+        let manager = SessionManager::new(config);
+        let session_id = manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+
+        // Sessions that are not ready, e.g. because an execution claimed them, are left alone
+        manager.update_session_status(&session_id, SessionStatus::Running).unwrap();
+        assert!(!manager.restart_crashed_session(state.clone(), &session_id).await.unwrap());
+        assert_eq!(manager.get_session(&session_id).unwrap().status, SessionStatus::Running);
+
+        // Past the restart limit, a crash fails the session instead of restarting its sandbox
+        manager.update_session_status(&session_id, SessionStatus::Ready).unwrap();
+        manager.sessions.write().unwrap().get_mut(&session_id).unwrap().restarts = 2;
+        assert!(!manager.restart_crashed_session(state, &session_id).await.unwrap());
+        let session = manager.get_session(&session_id).unwrap();
+        assert!(matches!(session.status, SessionStatus::Error(ref msg) if msg.contains("2 time(s)")));
+        assert_eq!(session.restarts, 2);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_session_manager_template_preamble() {
+        let mut config = ConfigurationManager::default();
+        config.template_preambles.insert("python".to_string(), "import numpy as np".to_string());
+        let manager = SessionManager::new(config);
+
+        // Sessions get the preamble of their template, if it has one
+        let python = manager.create_session("python", SandboxFlavor::Small).await.unwrap();
+        let node = manager.create_session("node", SandboxFlavor::Small).await.unwrap();
+        assert_eq!(manager.get_session(&python).unwrap().preamble.as_deref(), Some("import numpy as np"));
+        assert_eq!(manager.get_session(&node).unwrap().preamble, None);
+
+        // A session's own preamble replaces the template's, and a blank one disables it
+        manager.set_session_preamble(&node, "globalThis._ = require('lodash')").unwrap();
+        assert_eq!(
+            manager.get_session(&node).unwrap().preamble.as_deref(),
+            Some("globalThis._ = require('lodash')")
+        );
+        manager.set_session_preamble(&python, "  ").unwrap();
+        assert_eq!(manager.get_session(&python).unwrap().preamble, None);
+
+        // Preambles are limited like code submissions
+        let oversized = "#".repeat(DEFAULT_MAX_CODE_BYTES + 1);
+        assert!(matches!(
+            manager.set_session_preamble(&python, &oversized),
+            Err(SimplifiedMcpError::ValidationError(_))
+        ));
+        let mut config = ConfigurationManager::default();
+        config.template_preambles.insert("python".to_string(), oversized);
+        assert!(matches!(config.validate(), Err(SimplifiedMcpError::ConfigurationError(_))));
     }
 
     #[tokio::test]
@@ -3416,6 +7706,30 @@ mod tests {
         assert!(matches!(result, Err(SimplifiedMcpError::SessionNotFound(_))));
     }
 
+    #[tokio::test]
+    async fn test_session_manager_ephemeral_session() {
+        let config = ConfigurationManager::default();
+        let manager = Arc::new(SessionManager::new(config));
+
+        // Explicit teardown removes the session
+        let session = manager.create_ephemeral_session("python", SandboxFlavor::Small, None).await.unwrap();
+        let session_id = session.session_id().to_string();
+        assert!(manager.get_session(&session_id).is_ok());
+        session.teardown().await.unwrap();
+        assert_eq!(manager.get_session_count().unwrap(), 0);
+
+        // Dropping the guard without a teardown still removes the session
+        let session = manager.create_ephemeral_session("node", SandboxFlavor::Small, None).await.unwrap();
+        drop(session);
+        for _ in 0..10 {
+            if manager.get_session_count().unwrap() == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(manager.get_session_count().unwrap(), 0);
+    }
+
     #[tokio::test]
     async fn test_session_manager_get_session_count() {
         let config = ConfigurationManager::default();
@@ -3556,9 +7870,33 @@ mod tests {
         assert_eq!(sandbox_config.image, Some("microsandbox/python".to_string()));
         assert_eq!(sandbox_config.memory, Some(2048));
         assert_eq!(sandbox_config.cpus, Some(2));
+        assert_eq!(sandbox_config.shell, Some(DEFAULT_GUEST_SHELL.to_string()));
         assert!(sandbox_config.envs.contains(&"MICROSANDBOX_SIMPLIFIED_MCP=true".to_string()));
     }
 
+    #[test]
+    fn test_generate_sandbox_config_package_cache() {
+        let config = ConfigurationManager::default();
+        let creator = AutomaticSandboxCreator::new(config);
+        let cache_volume = format!("volume:msb-packages-node:{}", DEFAULT_PACKAGE_CACHE_GUEST_PATH);
+
+        let mut session_info = SessionInfo::new(
+            "test-session".to_string(),
+            "test-namespace".to_string(),
+            "test-sandbox".to_string(),
+            "node".to_string(),
+            SandboxFlavor::Small,
+        );
+
+        // Only sessions that install packages mount the template's download cache
+        let sandbox_config = creator.generate_sandbox_config(&session_info).unwrap();
+        assert!(!sandbox_config.volumes.contains(&cache_volume));
+
+        session_info.packages = vec!["lodash@4".to_string()];
+        let sandbox_config = creator.generate_sandbox_config(&session_info).unwrap();
+        assert!(sandbox_config.volumes.contains(&cache_volume));
+    }
+
     #[test]
     fn test_generate_sandbox_config_unsupported_language() {
         let config = ConfigurationManager::default();
@@ -3584,10 +7922,37 @@ mod tests {
         let config = ConfigurationManager::default();
         let creator = AutomaticSandboxCreator::new(config);
         
-        let volumes = creator.generate_volume_mappings();
+        let volumes = creator.generate_volume_mappings("default").unwrap();
         assert!(volumes.is_empty());
     }
 
+    #[test]
+    fn test_generate_volume_mappings_per_namespace() {
+        let root = std::env::temp_dir().join(format!("msb-volumes-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+
+        let mut config = ConfigurationManager::default();
+        config.shared_volume_path = Some(root.join(NAMESPACE_PLACEHOLDER));
+        assert!(config.validate().is_ok());
+        assert!(config.is_shared_volume_per_namespace());
+        let creator = AutomaticSandboxCreator::new(config);
+
+        // Each namespace gets its own directory, created on first use
+        let volumes = creator.generate_volume_mappings("tenant-a").unwrap();
+        assert_eq!(volumes, vec![format!("{}:/shared", root.join("tenant-a").display())]);
+        assert!(root.join("tenant-a").is_dir());
+        assert!(!root.join("tenant-b").exists());
+
+        // Namespaces cannot escape the volume root
+        assert!(matches!(
+            creator.generate_volume_mappings(".."),
+            Err(SimplifiedMcpError::ValidationError(_))
+        ));
+        assert!(creator.generate_volume_mappings("a/b").is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
     #[test]
     fn test_generate_environment_variables() {
         let config = ConfigurationManager::default();
@@ -3597,6 +7962,43 @@ mod tests {
         assert!(envs.contains(&"MICROSANDBOX_SIMPLIFIED_MCP=true".to_string()));
     }
 
+    #[test]
+    fn test_is_transient_start_error() {
+        use crate::handler::is_transient_start_error;
+        use microsandbox_core::MicrosandboxError;
+
+        // I/O and extraction races are retried
+        assert!(is_transient_start_error(&MicrosandboxError::Io(std::io::Error::other(
+            "resource busy"
+        ))));
+        assert!(is_transient_start_error(&MicrosandboxError::LayerExtraction(
+            "extraction in progress".to_string()
+        )));
+        assert!(is_transient_start_error(&MicrosandboxError::RootfsNotFound(
+            "python".to_string()
+        )));
+
+        // Images that are too large, for another platform, or that fill the disk are not
+        assert!(!is_transient_start_error(&MicrosandboxError::PullLimitExceeded {
+            image: "python".to_string(),
+            reason: "too many layers".to_string(),
+        }));
+        assert!(!is_transient_start_error(&MicrosandboxError::PlatformNotFound {
+            image: "python".to_string(),
+            platform: "linux/arm64".to_string(),
+            available: vec!["linux/amd64".to_string()],
+        }));
+        assert!(!is_transient_start_error(&MicrosandboxError::DiskFull {
+            layer: "sha256:abc".to_string(),
+            path: PathBuf::from("/tmp/layers"),
+            required: None,
+            available: None,
+        }));
+        assert!(!is_transient_start_error(&MicrosandboxError::ConfigValidation(
+            "bad config".to_string()
+        )));
+    }
+
     #[test]
     fn test_integration_automatic_sandbox_creation_workflow() {
         // This test demonstrates the complete workflow of automatic sandbox creation
@@ -3627,7 +8029,7 @@ mod tests {
         assert!(sandbox_config.ports.is_empty()); // Ports managed by existing system
         assert!(sandbox_config.depends_on.is_empty());
         assert!(sandbox_config.workdir.is_none()); // Use container default
-        assert!(sandbox_config.shell.is_none()); // Use container default
+        assert_eq!(sandbox_config.shell, Some(DEFAULT_GUEST_SHELL.to_string())); // Resolved against the image at start
         assert!(sandbox_config.scripts.is_empty());
         assert!(sandbox_config.exec.is_none());
         
@@ -3718,9 +8120,10 @@ mod tests {
         assert_eq!(cleanup_stats.active_sessions_after_cleanup, 0);
         assert_eq!(cleanup_stats.allocated_ports_after_cleanup, 0);
 
-        // Verify final state
+        // Verify final state, with the stopped sessions kept for the retention period
         let final_health_stats = cleanup_manager.get_system_health().unwrap();
-        assert_eq!(final_health_stats.total_sessions, 0);
+        assert_eq!(final_health_stats.total_sessions, 2);
+        assert_eq!(final_health_stats.stopped_sessions, 2);
         assert_eq!(final_health_stats.active_sessions, 0);
         assert_eq!(final_health_stats.resource_stats.active_sessions, 0);
     }
@@ -3991,6 +8394,202 @@ mod tests {
     }
 
     // Additional tests for CleanupManager
+    #[tokio::test]
+    async fn test_execution_queue_immediate_acquire() {
+        let queue = ExecutionQueue::new(2);
+
+        let permit1 = queue.acquire("ns-a", ExecutionPriority::Normal).await.unwrap();
+        let permit2 = queue.acquire("ns-b", ExecutionPriority::Normal).await.unwrap();
+        assert_eq!(permit1.wait_time(), Duration::ZERO);
+
+        let stats = queue.get_stats().unwrap();
+        assert_eq!(stats.running_executions, 2);
+        assert_eq!(stats.queue_depth, 0);
+        assert_eq!(stats.dispatched_executions, 2);
+
+        drop(permit1);
+        drop(permit2);
+        assert_eq!(queue.get_stats().unwrap().running_executions, 0);
+    }
+
+    #[tokio::test]
+    async fn test_execution_queue_round_robin_fairness() {
+        let queue = Arc::new(ExecutionQueue::new(1));
+        let (order_tx, mut order_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        // Occupy the only slot
+        let blocker = queue.acquire("ns-a", ExecutionPriority::Normal).await.unwrap();
+
+        // Namespace A floods the queue before namespace B submits a single execution
+        let mut handles = Vec::new();
+        for label in ["a1", "a2", "a3", "b1"] {
+            let task_queue = Arc::clone(&queue);
+            let order_tx = order_tx.clone();
+            let namespace = format!("ns-{}", &label[..1]);
+            handles.push(tokio::spawn(async move {
+                let permit = task_queue.acquire(&namespace, ExecutionPriority::Normal).await.unwrap();
+                order_tx.send(label).unwrap();
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                drop(permit);
+            }));
+
+            // Make sure executions are queued in a deterministic order
+            while queue.get_stats().unwrap().queue_depth < handles.len() {
+                tokio::task::yield_now().await;
+            }
+        }
+
+        let stats = queue.get_stats().unwrap();
+        assert_eq!(stats.queue_depth, 4);
+        assert_eq!(stats.queue_depth_by_namespace.get("ns-a"), Some(&3));
+        assert_eq!(stats.queue_depth_by_namespace.get("ns-b"), Some(&1));
+
+        drop(blocker);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        drop(order_tx);
+
+        let mut order = Vec::new();
+        while let Some(label) = order_rx.recv().await {
+            order.push(label);
+        }
+
+        // Namespace B is served after one execution from A, not after all of them
+        assert_eq!(order, vec!["a1", "b1", "a2", "a3"]);
+
+        let stats = queue.get_stats().unwrap();
+        assert_eq!(stats.running_executions, 0);
+        assert_eq!(stats.queue_depth, 0);
+        assert_eq!(stats.dispatched_executions, 5);
+        assert!(stats.max_wait_ms >= 10);
+    }
+
+    #[tokio::test]
+    async fn test_execution_queue_priority_and_aging() {
+        // Queue executions into a single namespace and return the order they were admitted in
+        async fn admission_order(
+            queue: Arc<ExecutionQueue>,
+            executions: Vec<(&'static str, ExecutionPriority, Duration)>,
+        ) -> Vec<&'static str> {
+            let (order_tx, mut order_rx) = tokio::sync::mpsc::unbounded_channel();
+            let blocker = queue.acquire("ns-a", ExecutionPriority::Normal).await.unwrap();
+
+            let mut handles = Vec::new();
+            for (label, priority, delay) in executions {
+                let task_queue = Arc::clone(&queue);
+                let order_tx = order_tx.clone();
+                handles.push(tokio::spawn(async move {
+                    let permit = task_queue.acquire("ns-a", priority).await.unwrap();
+                    order_tx.send(label).unwrap();
+                    drop(permit);
+                }));
+                while queue.get_stats().unwrap().queue_depth < handles.len() {
+                    tokio::task::yield_now().await;
+                }
+                tokio::time::sleep(delay).await;
+            }
+
+            drop(blocker);
+            for handle in handles {
+                handle.await.unwrap();
+            }
+            drop(order_tx);
+
+            let mut order = Vec::new();
+            while let Some(label) = order_rx.recv().await {
+                order.push(label);
+            }
+            order
+        }
+
+        // Higher priorities go first, equal ones in arrival order
+        let queue = Arc::new(ExecutionQueue::new(1).with_priority_aging(Duration::from_secs(3600)));
+        let order = admission_order(
+            Arc::clone(&queue),
+            vec![
+                ("low", ExecutionPriority::Low, Duration::ZERO),
+                ("normal1", ExecutionPriority::Normal, Duration::ZERO),
+                ("high", ExecutionPriority::High, Duration::ZERO),
+                ("normal2", ExecutionPriority::Normal, Duration::ZERO),
+            ],
+        )
+        .await;
+        assert_eq!(order, vec!["high", "normal1", "normal2", "low"]);
+
+        // A low priority execution that waited several aging intervals goes ahead of a high one
+        // queued after it
+        let queue = Arc::new(ExecutionQueue::new(1).with_priority_aging(Duration::from_millis(20)));
+        let order = admission_order(
+            Arc::clone(&queue),
+            vec![
+                ("low", ExecutionPriority::Low, Duration::from_millis(100)),
+                ("high", ExecutionPriority::High, Duration::ZERO),
+            ],
+        )
+        .await;
+        assert_eq!(order, vec!["low", "high"]);
+    }
+
+    #[tokio::test]
+    async fn test_execution_queue_depth_by_priority() {
+        let queue = Arc::new(ExecutionQueue::new(1));
+        let blocker = queue.acquire("ns-a", ExecutionPriority::Normal).await.unwrap();
+
+        let mut handles = Vec::new();
+        for (namespace, priority) in [
+            ("ns-a", ExecutionPriority::High),
+            ("ns-b", ExecutionPriority::High),
+            ("ns-b", ExecutionPriority::Low),
+        ] {
+            let task_queue = Arc::clone(&queue);
+            handles.push(tokio::spawn(async move {
+                drop(task_queue.acquire(namespace, priority).await.unwrap());
+            }));
+        }
+        while queue.get_stats().unwrap().queue_depth < handles.len() {
+            tokio::task::yield_now().await;
+        }
+
+        let stats = queue.get_stats().unwrap();
+        assert_eq!(stats.queue_depth_by_priority.get(&ExecutionPriority::High), Some(&2));
+        assert_eq!(stats.queue_depth_by_priority.get(&ExecutionPriority::Normal), Some(&0));
+        assert_eq!(stats.queue_depth_by_priority.get(&ExecutionPriority::Low), Some(&1));
+        assert_eq!(
+            serde_json::to_value(&stats).unwrap()["queue_depth_by_priority"]["high"],
+            json!(2)
+        );
+
+        drop(blocker);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        assert_eq!(queue.get_stats().unwrap().queue_depth_by_priority.values().sum::<usize>(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_execution_queue_skips_cancelled_waiters() {
+        let queue = Arc::new(ExecutionQueue::new(1));
+        let blocker = queue.acquire("ns-a", ExecutionPriority::Normal).await.unwrap();
+
+        // Queue an execution and then abandon it
+        let task_queue = Arc::clone(&queue);
+        let cancelled = tokio::spawn(async move { task_queue.acquire("ns-a", ExecutionPriority::Normal).await.map(|_| ()) });
+        while queue.get_stats().unwrap().queue_depth < 1 {
+            tokio::task::yield_now().await;
+        }
+        cancelled.abort();
+        let _ = cancelled.await;
+
+        // Releasing the slot must not hand it to the abandoned execution
+        drop(blocker);
+        let stats = queue.get_stats().unwrap();
+        assert_eq!(stats.running_executions, 0);
+        assert_eq!(stats.queue_depth, 0);
+
+        let _permit = queue.acquire("ns-b", ExecutionPriority::Normal).await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_cleanup_manager_creation() {
         let config = ConfigurationManager::default();
@@ -4037,7 +8636,10 @@ mod tests {
         // Create some sessions with different states
         let session1_id = session_manager.create_session("python", SandboxFlavor::Small).await.unwrap();
         let session2_id = session_manager.create_session("node", SandboxFlavor::Medium).await.unwrap();
-        let session3_id = session_manager.create_session("python", SandboxFlavor::Large).await.unwrap();
+        let session3_id = session_manager
+            .create_owned_session("python", SandboxFlavor::Large, Some("tenant-a"))
+            .await
+            .unwrap();
 
         // Update session states
         session_manager.update_session_status(&session1_id, SessionStatus::Ready).unwrap();
@@ -4052,6 +8654,7 @@ mod tests {
         assert_eq!(health.running_sessions, 1);
         assert_eq!(health.error_sessions, 1);
         assert_eq!(health.stopped_sessions, 0);
+        assert_eq!(health.sessions_by_namespace, HashMap::from([("tenant-a".to_string(), 1)]));
     }
 
     #[tokio::test]
@@ -4070,12 +8673,17 @@ mod tests {
         let _allocation1 = resource_manager.allocate_resources(session1_id.clone(), SandboxFlavor::Small).unwrap();
         let _allocation2 = resource_manager.allocate_resources(session2_id.clone(), SandboxFlavor::Medium).unwrap();
 
+        // Leave one session in the middle of an execution
+        session_manager.update_session_status(&session1_id, SessionStatus::Running).unwrap();
+
         // Perform graceful shutdown
         let shutdown_stats = cleanup_manager.graceful_shutdown(cleanup_handles).await.unwrap();
         
         assert_eq!(shutdown_stats.expired_sessions_found, 2);
         assert_eq!(shutdown_stats.sessions_cleaned_up, 2);
         assert_eq!(shutdown_stats.cleanup_errors, 0);
+        assert_eq!(shutdown_stats.interrupted_executions, 1);
+        assert_eq!(session_manager.executions_cancelled().await, CancelReason::ServerShutdown);
         assert_eq!(shutdown_stats.active_sessions_after_cleanup, 0);
         assert_eq!(shutdown_stats.allocated_ports_after_cleanup, 0);
     }
@@ -4333,6 +8941,75 @@ mod tests {
         assert!(has_debug_suggestion);
     }
 
+    #[test]
+    fn test_user_friendly_error_image_not_found() {
+        let error = SimplifiedMcpError::ImageNotFound {
+            image: "microsandbox/pythn".to_string(),
+            registries: vec![
+                "https://mirror.internal".to_string(),
+                "https://registry-1.docker.io".to_string(),
+            ],
+        };
+        assert!(error.to_string().contains("microsandbox/pythn"));
+
+        let user_friendly = error.get_user_friendly_message();
+        assert_eq!(user_friendly.error_type, "image_not_found");
+        assert!(user_friendly.message.contains("microsandbox/pythn"));
+        let details = user_friendly.details.unwrap();
+        assert!(details.contains("https://mirror.internal"));
+        assert!(details.contains("https://registry-1.docker.io"));
+        assert!(user_friendly
+            .suggestions
+            .iter()
+            .any(|suggestion| suggestion.contains("typos")));
+        assert!(user_friendly
+            .recovery_actions
+            .iter()
+            .any(|action| action.action == "use_python_template"));
+    }
+
+    #[test]
+    fn test_user_friendly_error_package_install_failed() {
+        let error = SimplifiedMcpError::PackageInstallFailed {
+            packages: vec!["requets".to_string(), "numpy".to_string()],
+            reason: "package manager exited with code 1:\nERROR: No matching distribution found for requets".to_string(),
+        };
+        assert!(error.to_string().contains("requets, numpy"));
+
+        let user_friendly = error.get_user_friendly_message();
+        assert_eq!(user_friendly.error_type, "package_install_failed");
+        assert!(user_friendly.message.contains("requets"));
+        assert!(user_friendly.details.unwrap().contains("No matching distribution"));
+        assert!(user_friendly
+            .recovery_actions
+            .iter()
+            .any(|action| action.action == "create_session_without_packages"));
+    }
+
+    #[test]
+    fn test_validate_packages() {
+        let packages = |specs: &[&str]| specs.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert!(validate_packages(&[]).is_ok());
+        assert!(validate_packages(&packages(&["requests==2.32.3", "numpy>=2", "lodash@4", "@types/node"])).is_ok());
+
+        assert!(validate_packages(&packages(&[""])).is_err());
+        assert!(validate_packages(&packages(&["--index-url=https://example.com"])).is_err());
+        assert!(validate_packages(&packages(&["requests", "-e ."])).is_err());
+        assert!(validate_packages(&packages(&["requests numpy"])).is_err());
+    }
+
+    #[test]
+    fn test_user_friendly_error_server_maintenance() {
+        let user_friendly = SimplifiedMcpError::ServerInMaintenance.get_user_friendly_message();
+        assert_eq!(user_friendly.error_type, "server_maintenance");
+        assert!(user_friendly.message.contains("maintenance"));
+        assert!(user_friendly
+            .recovery_actions
+            .iter()
+            .any(|action| action.action == "use_existing_session"));
+    }
+
     #[test]
     fn test_user_friendly_error_system_error() {
         let error = SimplifiedMcpError::SystemError("Disk full".to_string());
@@ -4485,8 +9162,11 @@ mod tests {
             stdout: "output".to_string(),
             stderr: "".to_string(),
             exit_code: None,
+            termination: None,
             execution_time_ms: 0,
             session_created: false,
+            flavor: Some(SandboxFlavor::Small),
+            cancellation_reason: None,
         };
         
         let json = serde_json::to_string(&response).unwrap();
@@ -4501,6 +9181,7 @@ mod tests {
             created_at: "just now".to_string(),
             last_accessed: "just now".to_string(),
             uptime_seconds: 0,
+            packages: Vec::new(),
         };
         
         let json = serde_json::to_string(&summary).unwrap();
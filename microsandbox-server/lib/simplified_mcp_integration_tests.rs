@@ -77,7 +77,15 @@ mod integration_tests {
             code: "print('Hello, World!')".to_string(),
             template: Some("python".to_string()),
             session_id: None,
-            flavor: Some(SandboxFlavor::Small),
+            flavor: Some(SandboxFlavor::Small.into()),
+            timeout: None,
+            memory_mb: None,
+            seed: None,
+            reuse_session: None,
+            client_id: None,
+            wait_for_ready: None,
+            priority: None,
+            validate_only: None,
         };
 
         // Simulate session creation and execution
@@ -107,7 +115,14 @@ mod integration_tests {
             args: Some(vec!["-la".to_string()]),
             template: Some("python".to_string()),
             session_id: Some(session_id.clone()),
-            flavor: Some(SandboxFlavor::Small),
+            flavor: Some(SandboxFlavor::Small.into()),
+            timeout: None,
+            memory_mb: None,
+            seed: None,
+            reuse_session: None,
+            client_id: None,
+            wait_for_ready: None,
+            priority: None,
         };
 
         // Verify command request is valid
@@ -797,7 +812,7 @@ mod integration_tests {
         let request: ExecuteCodeRequest = serde_json::from_value(execute_args).unwrap();
         assert_eq!(request.code, "print('Integration test')");
         assert_eq!(request.template, Some("python".to_string()));
-        assert_eq!(request.flavor, Some(SandboxFlavor::Small));
+        assert_eq!(request.flavor, Some(SandboxFlavor::Small.into()));
         assert_eq!(request.session_id, None);
 
         // Simulate the tool handler workflow
@@ -805,7 +820,7 @@ mod integration_tests {
             .get_or_create_session(
                 request.session_id,
                 request.template.as_deref().unwrap_or("python"),
-                request.flavor.unwrap_or(SandboxFlavor::Small),
+                session_manager.flavor_for(request.flavor, &request.code),
             )
             .await
             .unwrap();
@@ -820,8 +835,11 @@ mod integration_tests {
             stdout: "Integration test\n".to_string(),
             stderr: "".to_string(),
             exit_code: Some(0),
+            termination: Some(Termination::Exited(0)),
             execution_time_ms: 150,
             session_created: true,
+            flavor: Some(SandboxFlavor::Small),
+            cancellation_reason: None,
         };
 
         let json_response = serde_json::to_string(&response).unwrap();
@@ -863,7 +881,7 @@ mod integration_tests {
             .get_or_create_session(
                 request.session_id,
                 request.template.as_deref().unwrap_or("python"),
-                request.flavor.unwrap_or(SandboxFlavor::Small),
+                session_manager.flavor_for(request.flavor, &request.command),
             )
             .await
             .unwrap();
@@ -876,8 +894,11 @@ mod integration_tests {
             stdout: "Hello World\n".to_string(),
             stderr: "".to_string(),
             exit_code: Some(0),
+            termination: Some(Termination::Exited(0)),
             execution_time_ms: 50,
             session_created: false,
+            flavor: Some(SandboxFlavor::Small),
+            cancellation_reason: None,
         };
 
         assert_eq!(response.exit_code, Some(0));
@@ -10,15 +10,20 @@
 //! - State initialization and access methods
 //! - Configuration state management
 
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 use tokio::sync::RwLock;
 
 use getset::Getters;
+use microsandbox_utils::NAMESPACE_USAGE_FILE;
 
 use crate::{
     config::Config,
     port::{PortManager, LOCALHOST_IP},
-    simplified_mcp::{SessionManager, ConfigurationManager},
+    rate_limit::RateLimiter,
+    simplified_mcp::{ConfigurationManager, ExecutionQueue, SessionManager, UsageLedger},
     ServerError, ServerResult,
 };
 
@@ -38,6 +43,16 @@ pub struct AppState {
 
     /// The session manager for simplified MCP operations
     session_manager: Arc<SessionManager>,
+
+    /// The fair queue bounding concurrent executions across namespaces
+    execution_queue: Arc<ExecutionQueue>,
+
+    /// The rate limiter bounding the requests of each namespace
+    rate_limiter: Arc<RateLimiter>,
+
+    /// Whether the server is shutting down and should stop receiving new work
+    #[getset(skip)]
+    draining: Arc<AtomicBool>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -54,14 +69,107 @@ impl AppState {
                 ConfigurationManager::default()
             });
         
+        // Create the execution queue before the configuration is moved into the session manager
+        let execution_queue = Arc::new(
+            ExecutionQueue::new(mcp_config.get_max_concurrent_executions())
+                .with_priority_aging(mcp_config.get_priority_aging()),
+        );
+
+        // Keep the usage totals next to the port assignments so they survive restarts
+        let usage_file = config.get_namespace_dir().join(NAMESPACE_USAGE_FILE);
+        let usage = UsageLedger::load(usage_file).unwrap_or_else(|e| {
+            tracing::warn!("Failed to load namespace usage: {}. Usage will not be persisted.", e);
+            UsageLedger::default()
+        });
+
+        let rate_limiter = RateLimiter::from_env().unwrap_or_else(|e| {
+            tracing::warn!("Failed to load rate limits from environment: {}. Requests will not be rate limited.", e);
+            RateLimiter::default()
+        });
+
         // Create session manager with the configuration
-        let session_manager = Arc::new(SessionManager::new(mcp_config));
+        let session_manager = Arc::new(SessionManager::new(mcp_config).with_usage_ledger(usage));
 
         Self {
             config,
             port_manager,
             session_manager,
+            execution_queue,
+            rate_limiter: Arc::new(rate_limiter),
+            draining: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Mark the server as draining, so readiness checks fail and load balancers stop routing
+    /// new work to it
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+    }
+
+    /// Check whether the server is draining
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    /// Get the reasons the server cannot accept new sessions right now
+    ///
+    /// An empty list means the server is ready. The checks only read in-memory state, so this
+    /// is cheap enough to call from a load balancer probe.
+    pub fn get_readiness_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.is_draining() {
+            problems.push("server is draining".to_string());
         }
+
+        if self.session_manager.is_in_maintenance() {
+            problems.push("server is in maintenance mode".to_string());
+        }
+
+        let mcp_config = self.session_manager.get_config();
+        if let Err(e) = mcp_config.validate() {
+            problems.push(format!("invalid configuration: {}", e));
+        }
+
+        match self.session_manager.get_session_count() {
+            Ok(count) if count >= mcp_config.get_max_sessions() => {
+                problems.push(format!(
+                    "session limit of {} reached",
+                    mcp_config.get_max_sessions()
+                ));
+            }
+            Ok(_) => {}
+            Err(e) => problems.push(format!("session manager unavailable: {}", e)),
+        }
+
+        // As many executions waiting as can run at once means the server is overloaded
+        match self.execution_queue.get_stats() {
+            Ok(stats) if stats.queue_depth >= stats.max_concurrent_executions => {
+                problems.push(format!(
+                    "execution queue is saturated with {} waiting executions",
+                    stats.queue_depth
+                ));
+            }
+            Ok(_) => {}
+            Err(e) => problems.push(format!("execution queue unavailable: {}", e)),
+        }
+
+        problems
+    }
+
+    /// Get the parts of the server running in a reduced mode, which keep serving requests
+    ///
+    /// Unlike readiness problems, these don't stop the server from accepting new sessions.
+    pub fn get_degradations(&self) -> Vec<String> {
+        let mut degradations = Vec::new();
+
+        if self.session_manager.is_usage_degraded() {
+            degradations.push(
+                "usage file is not writable, usage is kept in memory until it is".to_string(),
+            );
+        }
+
+        degradations
     }
 
     /// Get a sandbox's portal URL
@@ -49,6 +49,9 @@ pub const DEFAULT_CONFIG: &str = "# Sandbox configurations\nsandboxes:\n";
 /// The default shell to use for the sandbox.
 pub const DEFAULT_SHELL: &str = "/bin/sh";
 
+/// The shells tried, in order, when the image lacks the shell configured for the sandbox.
+pub const DEFAULT_FALLBACK_SHELLS: &[&str] = &["/bin/bash", "/bin/sh", "/bin/ash"];
+
 /// The default path to the msbrun binary.
 pub static DEFAULT_MSBRUN_EXE_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
     let current_exe = std::env::current_exe().unwrap();
@@ -77,3 +80,85 @@ pub const DEFAULT_SERVER_PORT: u16 = 5555;
 
 /// The default microsandbox-portal port.
 pub const DEFAULT_PORTAL_GUEST_PORT: u16 = 4444;
+
+/// The default maximum size of a microsandbox-portal request body, in bytes.
+pub const DEFAULT_PORTAL_MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+
+/// The maximum number of bytes returned by a single microsandbox-portal file read.
+///
+/// Chunks are base64 encoded in the response, so this is kept well below the body size limit.
+pub const DEFAULT_PORTAL_FILE_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The default maximum size, in bytes, of a file read with microsandbox-portal's
+/// `sandbox.file.read`. Larger files are rejected instead of being returned in part.
+pub const DEFAULT_PORTAL_MAX_FILE_READ_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// The maximum number of directory entries returned by a single microsandbox-portal listing.
+pub const DEFAULT_PORTAL_DIR_PAGE_SIZE: usize = 1000;
+
+/// The maximum number of output files a single microsandbox-portal execution can return.
+pub const DEFAULT_PORTAL_MAX_OUTPUT_FILES: usize = 16;
+
+/// The maximum size, in bytes, of an output file returned with the result of a
+/// microsandbox-portal execution. Larger files are read with `sandbox.file.read` instead.
+pub const DEFAULT_PORTAL_OUTPUT_FILE_MAX_SIZE: u64 = 1024 * 1024;
+
+/// The default time in seconds a timed out command is given to exit after `SIGTERM` before the
+/// portal kills it with `SIGKILL`.
+pub const DEFAULT_PORTAL_KILL_GRACE_PERIOD_SECS: u64 = 5;
+
+/// The maximum number of bytes of output microsandbox-portal buffers for a single execution.
+///
+/// Output past the limit is dropped, or the command is paused, as the request asks.
+pub const DEFAULT_PORTAL_MAX_OUTPUT_BYTES: usize = 8 * 1024 * 1024;
+
+/// The size, in bytes, above which microsandbox-portal compresses execution output when the
+/// client asks for compression.
+pub const DEFAULT_PORTAL_COMPRESSION_THRESHOLD: usize = 16 * 1024;
+
+/// The path inside a sandbox where pip and npm keep their download caches.
+///
+/// The server mounts a named volume here for sessions that install packages, so downloads are
+/// reused by later sessions of the same template.
+pub const DEFAULT_PACKAGE_CACHE_GUEST_PATH: &str = "/var/cache/msb";
+
+/// The guest path where the server mounts the upload cache of a sandbox's namespace.
+///
+/// Files uploaded with a checksum are kept here by content, so the same file uploaded to another
+/// sandbox of the namespace is copied from the cache instead of being sent again.
+pub const DEFAULT_UPLOAD_CACHE_GUEST_PATH: &str = "/var/cache/msb-uploads";
+
+/// The unprivileged user that server-started sandboxes execute code and commands as.
+///
+/// microsandbox-portal creates the user if the image does not have it.
+pub const DEFAULT_SANDBOX_USER: &str = "sandbox";
+
+/// The default number of sandboxes `apply` starts at the same time.
+pub const DEFAULT_APPLY_PARALLELISM: usize = 4;
+
+/// The default number of image layers downloaded at the same time, across all pulls in the process.
+pub const DEFAULT_MAX_GLOBAL_PULLS: usize = 6;
+
+/// The default number of layer directories checked at the same time when validating a cached image.
+pub const DEFAULT_LAYER_CHECK_CONCURRENCY: usize = 8;
+
+/// The default time in seconds allowed for validating a cached image's layers before the image is
+/// pulled again.
+pub const DEFAULT_LAYER_CHECK_TIMEOUT_SECS: u64 = 30;
+
+/// The default maximum number of layers an image may have to be pulled.
+pub const DEFAULT_MAX_IMAGE_LAYERS: usize = 256;
+
+/// The default maximum size of an image's layers, in bytes, to be pulled (16 GiB).
+pub const DEFAULT_MAX_IMAGE_SIZE: u64 = 16 * 1024 * 1024 * 1024;
+
+/// The default maximum number of vCPUs a temporary sandbox started with `msb exe` may request.
+pub const DEFAULT_MAX_TEMP_CPUS: u8 = 16;
+
+/// The default minimum amount of memory in MiB a temporary sandbox started with `msb exe` may
+/// request.
+pub const DEFAULT_MIN_TEMP_MEMORY_MIB: u32 = 128;
+
+/// The default maximum amount of memory in MiB a temporary sandbox started with `msb exe` may
+/// request (32 GiB).
+pub const DEFAULT_MAX_TEMP_MEMORY_MIB: u32 = 32 * 1024;
@@ -1,8 +1,13 @@
 //! Utility functions for working with environment variables.
 
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
-use crate::{DEFAULT_MICROSANDBOX_HOME, DEFAULT_OCI_REGISTRY};
+use crate::{
+    DEFAULT_APPLY_PARALLELISM, DEFAULT_LAYER_CHECK_CONCURRENCY, DEFAULT_LAYER_CHECK_TIMEOUT_SECS,
+    DEFAULT_MAX_GLOBAL_PULLS, DEFAULT_MAX_IMAGE_LAYERS, DEFAULT_MAX_IMAGE_SIZE,
+    DEFAULT_MAX_TEMP_CPUS, DEFAULT_MAX_TEMP_MEMORY_MIB, DEFAULT_MICROSANDBOX_HOME,
+    DEFAULT_MIN_TEMP_MEMORY_MIB, DEFAULT_OCI_REGISTRY, PULL_STAGING_SUBDIR,
+};
 
 //--------------------------------------------------------------------------------------------------
 // Constants
@@ -20,6 +25,46 @@ pub const MSBRUN_EXE_ENV_VAR: &str = "MSBRUN_EXE";
 /// Environment variable for the msbserver binary path
 pub const MSBSERVER_EXE_ENV_VAR: &str = "MSBSERVER_EXE";
 
+/// Environment variable for the directory used to stage image pull downloads
+pub const MSB_PULL_STAGING_DIR_ENV_VAR: &str = "MSB_PULL_STAGING_DIR";
+
+/// Environment variable for the uid that owns every file in extracted layers and sandbox rootfs
+pub const MSB_LAYER_UID_ENV_VAR: &str = "MSB_LAYER_UID";
+
+/// Environment variable for the gid that owns every file in extracted layers and sandbox rootfs
+pub const MSB_LAYER_GID_ENV_VAR: &str = "MSB_LAYER_GID";
+
+/// Environment variable for the user that microsandbox-portal executes code and commands as
+pub const MSB_RUN_AS_USER_ENV_VAR: &str = "MSB_RUN_AS_USER";
+
+/// Environment variable for the number of sandboxes `apply` starts at the same time
+pub const MSB_APPLY_PARALLELISM_ENV_VAR: &str = "MSB_APPLY_PARALLELISM";
+
+/// Environment variable for the number of image layers downloaded at the same time by the process
+pub const MSB_MAX_GLOBAL_PULLS_ENV_VAR: &str = "MSB_MAX_GLOBAL_PULLS";
+
+/// Environment variable for the number of layer directories checked at the same time when
+/// validating a cached image
+pub const MSB_LAYER_CHECK_CONCURRENCY_ENV_VAR: &str = "MSB_LAYER_CHECK_CONCURRENCY";
+
+/// Environment variable for the time in seconds allowed for validating a cached image's layers
+pub const MSB_LAYER_CHECK_TIMEOUT_SECS_ENV_VAR: &str = "MSB_LAYER_CHECK_TIMEOUT_SECS";
+
+/// Environment variable for the maximum number of layers an image may have to be pulled
+pub const MSB_MAX_IMAGE_LAYERS_ENV_VAR: &str = "MSB_MAX_IMAGE_LAYERS";
+
+/// Environment variable for the maximum size of an image's layers, in MiB, to be pulled
+pub const MSB_MAX_IMAGE_SIZE_MIB_ENV_VAR: &str = "MSB_MAX_IMAGE_SIZE_MIB";
+
+/// Environment variable for the maximum number of vCPUs a temporary sandbox may request
+pub const MSB_TEMP_MAX_CPUS_ENV_VAR: &str = "MSB_TEMP_MAX_CPUS";
+
+/// Environment variable for the minimum memory, in MiB, a temporary sandbox may request
+pub const MSB_TEMP_MIN_MEMORY_MIB_ENV_VAR: &str = "MSB_TEMP_MIN_MEMORY_MIB";
+
+/// Environment variable for the maximum memory, in MiB, a temporary sandbox may request
+pub const MSB_TEMP_MAX_MEMORY_MIB_ENV_VAR: &str = "MSB_TEMP_MAX_MEMORY_MIB";
+
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
@@ -35,6 +80,105 @@ pub fn get_microsandbox_home_path() -> PathBuf {
     }
 }
 
+/// Returns the path to the directory used to stage image pull downloads.
+/// If the MSB_PULL_STAGING_DIR environment variable is set, returns that path.
+/// Otherwise, returns the staging subdirectory of the microsandbox home directory.
+pub fn get_pull_staging_dir() -> PathBuf {
+    if let Ok(staging_dir) = std::env::var(MSB_PULL_STAGING_DIR_ENV_VAR) {
+        PathBuf::from(staging_dir)
+    } else {
+        get_microsandbox_home_path().join(PULL_STAGING_SUBDIR)
+    }
+}
+
+/// Returns the uid override for extracted layer files.
+/// If the MSB_LAYER_UID environment variable is set to a valid uid, returns that value.
+/// Otherwise, returns `None` and files keep the uid recorded in the layer.
+pub fn get_layer_uid_override() -> Option<u32> {
+    parse_id_override(MSB_LAYER_UID_ENV_VAR)
+}
+
+/// Returns the gid override for extracted layer files.
+/// If the MSB_LAYER_GID environment variable is set to a valid gid, returns that value.
+/// Otherwise, returns `None` and files keep the gid recorded in the layer.
+pub fn get_layer_gid_override() -> Option<u32> {
+    parse_id_override(MSB_LAYER_GID_ENV_VAR)
+}
+
+/// Returns the number of sandboxes `apply` starts at the same time.
+/// If the MSB_APPLY_PARALLELISM environment variable is set to a positive number, returns that value.
+/// Otherwise, returns the default apply parallelism.
+pub fn get_apply_parallelism() -> usize {
+    parse_positive(MSB_APPLY_PARALLELISM_ENV_VAR).unwrap_or(DEFAULT_APPLY_PARALLELISM)
+}
+
+/// Returns the number of image layers the process downloads at the same time, across all pulls.
+/// If the MSB_MAX_GLOBAL_PULLS environment variable is set to a positive number, returns that value.
+/// Otherwise, returns the default maximum of global pulls.
+pub fn get_max_global_pulls() -> usize {
+    parse_positive(MSB_MAX_GLOBAL_PULLS_ENV_VAR).unwrap_or(DEFAULT_MAX_GLOBAL_PULLS)
+}
+
+/// Returns the number of layer directories checked at the same time when validating a cached image.
+/// If the MSB_LAYER_CHECK_CONCURRENCY environment variable is set to a positive number, returns
+/// that value. Otherwise, returns the default layer check concurrency.
+pub fn get_layer_check_concurrency() -> usize {
+    parse_positive(MSB_LAYER_CHECK_CONCURRENCY_ENV_VAR).unwrap_or(DEFAULT_LAYER_CHECK_CONCURRENCY)
+}
+
+/// Returns the time allowed for validating a cached image's layers before the image is pulled again.
+/// If the MSB_LAYER_CHECK_TIMEOUT_SECS environment variable is set to a positive number of seconds,
+/// returns that duration. Otherwise, returns the default layer check timeout.
+pub fn get_layer_check_timeout() -> Duration {
+    let secs = parse_positive(MSB_LAYER_CHECK_TIMEOUT_SECS_ENV_VAR)
+        .map(|secs| secs as u64)
+        .unwrap_or(DEFAULT_LAYER_CHECK_TIMEOUT_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Returns the maximum number of layers an image may have to be pulled.
+/// If the MSB_MAX_IMAGE_LAYERS environment variable is set to a positive number, returns that value.
+/// Otherwise, returns the default maximum of image layers.
+pub fn get_max_image_layers() -> usize {
+    parse_positive(MSB_MAX_IMAGE_LAYERS_ENV_VAR).unwrap_or(DEFAULT_MAX_IMAGE_LAYERS)
+}
+
+/// Returns the maximum size of an image's extracted layers, in bytes, to be pulled.
+/// If the MSB_MAX_IMAGE_SIZE_MIB environment variable is set to a positive number of MiB, returns
+/// that size. Otherwise, returns the default maximum image size.
+pub fn get_max_image_size() -> u64 {
+    parse_positive(MSB_MAX_IMAGE_SIZE_MIB_ENV_VAR)
+        .map(|mib| (mib as u64).saturating_mul(1024 * 1024))
+        .unwrap_or(DEFAULT_MAX_IMAGE_SIZE)
+}
+
+/// Returns the maximum number of vCPUs a temporary sandbox may request.
+/// If the MSB_TEMP_MAX_CPUS environment variable is set to a positive number that fits a vCPU
+/// count, returns that value. Otherwise, returns the default maximum of temporary sandbox vCPUs.
+pub fn get_temp_max_cpus() -> u8 {
+    parse_positive(MSB_TEMP_MAX_CPUS_ENV_VAR)
+        .and_then(|n| u8::try_from(n).ok())
+        .unwrap_or(DEFAULT_MAX_TEMP_CPUS)
+}
+
+/// Returns the minimum memory, in MiB, a temporary sandbox may request.
+/// If the MSB_TEMP_MIN_MEMORY_MIB environment variable is set to a positive number, returns that
+/// value. Otherwise, returns the default minimum of temporary sandbox memory.
+pub fn get_temp_min_memory_mib() -> u32 {
+    parse_positive(MSB_TEMP_MIN_MEMORY_MIB_ENV_VAR)
+        .and_then(|n| u32::try_from(n).ok())
+        .unwrap_or(DEFAULT_MIN_TEMP_MEMORY_MIB)
+}
+
+/// Returns the maximum memory, in MiB, a temporary sandbox may request.
+/// If the MSB_TEMP_MAX_MEMORY_MIB environment variable is set to a positive number, returns that
+/// value. Otherwise, returns the default maximum of temporary sandbox memory.
+pub fn get_temp_max_memory_mib() -> u32 {
+    parse_positive(MSB_TEMP_MAX_MEMORY_MIB_ENV_VAR)
+        .and_then(|n| u32::try_from(n).ok())
+        .unwrap_or(DEFAULT_MAX_TEMP_MEMORY_MIB)
+}
+
 /// Returns the domain for the OCI registry.
 /// If the OCI_REGISTRY_DOMAIN environment variable is set, returns that value.
 /// Otherwise, returns the default OCI registry domain.
@@ -45,3 +189,25 @@ pub fn get_oci_registry() -> String {
         DEFAULT_OCI_REGISTRY.to_string()
     }
 }
+
+fn parse_positive(var: &str) -> Option<usize> {
+    let value = std::env::var(var).ok()?;
+    match value.trim().parse::<usize>() {
+        Ok(n) if n > 0 => Some(n),
+        _ => {
+            tracing::warn!("ignoring invalid {} value: {}", var, value);
+            None
+        }
+    }
+}
+
+fn parse_id_override(var: &str) -> Option<u32> {
+    let value = std::env::var(var).ok()?;
+    match value.trim().parse::<u32>() {
+        Ok(id) => Some(id),
+        Err(_) => {
+            tracing::warn!("ignoring invalid {} value: {}", var, value);
+            None
+        }
+    }
+}
@@ -34,6 +34,11 @@ pub const PATCH_SUBDIR: &str = "patch";
 /// Example: <PROJECT_ROOT>/<MICROSANDBOX_ENV_DIR>/<LOG_SUBDIR>
 pub const LOG_SUBDIR: &str = "log";
 
+/// The directory where the resolved configuration of each started sandbox is stored
+///
+/// Example: <PROJECT_ROOT>/<MICROSANDBOX_ENV_DIR>/<RESOLVED_CONFIG_SUBDIR>
+pub const RESOLVED_CONFIG_SUBDIR: &str = "resolved";
+
 /// The directory where global image layers are stored
 ///
 /// Example: <MICROSANDBOX_HOME_DIR>/<LAYERS_SUBDIR>
@@ -44,6 +49,16 @@ pub const LAYERS_SUBDIR: &str = "layers";
 /// Example: <MICROSANDBOX_HOME_DIR>/<INSTALLS_SUBDIR>
 pub const INSTALLS_SUBDIR: &str = "installs";
 
+/// The directory where image pulls stage their downloads before extraction
+///
+/// Example: <MICROSANDBOX_HOME_DIR>/<PULL_STAGING_SUBDIR>
+pub const PULL_STAGING_SUBDIR: &str = "staging";
+
+/// The directory where named volumes are stored
+///
+/// Example: <MICROSANDBOX_HOME_DIR>/<VOLUMES_SUBDIR>
+pub const VOLUMES_SUBDIR: &str = "volumes";
+
 /// The filename for the project active sandbox database
 ///
 /// Example: <PROJECT_ROOT>/<MICROSANDBOX_ENV_DIR>/<SANDBOX_DB_FILENAME>
@@ -54,6 +69,11 @@ pub const SANDBOX_DB_FILENAME: &str = "sandbox.db";
 /// Example: <MICROSANDBOX_HOME_DIR>/<OCI_DB_FILENAME>
 pub const OCI_DB_FILENAME: &str = "oci.db";
 
+/// The filename for the ordered list of registries that images are pulled from
+///
+/// Example: <MICROSANDBOX_HOME_DIR>/<REGISTRIES_FILENAME>
+pub const REGISTRIES_FILENAME: &str = "registries.yaml";
+
 /// The directory on the microvm where sandbox scripts are stored
 pub const SANDBOX_DIR: &str = ".sandbox";
 
@@ -97,6 +117,16 @@ pub const SERVER_KEY_FILE: &str = "server.key";
 /// Example: <MICROSANDBOX_HOME_DIR>/<NAMESPACE_SUBDIR>/<PORTAL_PORTS_FILE>
 pub const PORTAL_PORTS_FILE: &str = "portal.ports";
 
+/// The file where the resource usage accumulated by each namespace is stored
+///
+/// Example: <MICROSANDBOX_HOME_DIR>/<NAMESPACE_SUBDIR>/<NAMESPACE_USAGE_FILE>
+pub const NAMESPACE_USAGE_FILE: &str = "namespace.usage";
+
+/// The directory of a namespace where files uploaded to its sandboxes are cached by content
+///
+/// Example: <MICROSANDBOX_HOME_DIR>/<NAMESPACE_SUBDIR>/<namespace>/<UPLOAD_CACHE_SUBDIR>
+pub const UPLOAD_CACHE_SUBDIR: &str = "uploads";
+
 /// The XDG home directory
 ///
 /// Example: <HOME>/.local
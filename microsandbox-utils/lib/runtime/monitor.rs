@@ -1,3 +1,5 @@
+use std::io::ErrorKind;
+
 use async_trait::async_trait;
 use tokio::{
     fs::File,
@@ -5,7 +7,14 @@ use tokio::{
     process::{ChildStderr, ChildStdin, ChildStdout},
 };
 
-use crate::MicrosandboxUtilsResult;
+use crate::{MicrosandboxUtilsResult, SupervisorEvent};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The file host memory usage is read from.
+const MEMINFO_PATH: &str = "/proc/meminfo";
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -35,6 +44,16 @@ pub enum ChildIo {
     },
 }
 
+/// A snapshot of host memory usage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryInfo {
+    /// Total memory in KiB.
+    pub total_kib: u64,
+
+    /// Memory available for new allocations without swapping, in KiB.
+    pub available_kib: u64,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Traits
 //--------------------------------------------------------------------------------------------------
@@ -47,4 +66,70 @@ pub trait ProcessMonitor {
 
     /// Stop monitoring
     async fn stop(&mut self) -> MicrosandboxUtilsResult<()>;
+
+    /// Handle a lifecycle event of the monitored process
+    ///
+    /// Called by the supervisor before the event is broadcast to its subscribers. Does nothing
+    /// by default.
+    fn on_event(&mut self, _event: &SupervisorEvent) {}
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl MemoryInfo {
+    /// Reads the current host memory usage.
+    ///
+    /// Returns `None` on hosts without `/proc/meminfo`, such as macOS.
+    pub fn read() -> MicrosandboxUtilsResult<Option<Self>> {
+        match std::fs::read_to_string(MEMINFO_PATH) {
+            Ok(contents) => Ok(Self::parse(&contents)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Parses the contents of `/proc/meminfo`.
+    pub fn parse(meminfo: &str) -> Option<Self> {
+        let field = |name: &str| {
+            meminfo.lines().find_map(|line| {
+                let value = line.strip_prefix(name)?.strip_prefix(':')?;
+                value
+                    .trim()
+                    .trim_end_matches("kB")
+                    .trim()
+                    .parse::<u64>()
+                    .ok()
+            })
+        };
+
+        Some(Self {
+            total_kib: field("MemTotal")?,
+            available_kib: field("MemAvailable")?,
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_info_parse() {
+        let meminfo = "MemTotal:       16000000 kB\n\
+                       MemFree:          500000 kB\n\
+                       MemAvailable:    1200000 kB\n\
+                       Buffers:          100000 kB\n";
+
+        let info = MemoryInfo::parse(meminfo).unwrap();
+        assert_eq!(info.total_kib, 16_000_000);
+        assert_eq!(info.available_kib, 1_200_000);
+
+        assert_eq!(MemoryInfo::parse("MemTotal: 100 kB\n"), None);
+    }
 }
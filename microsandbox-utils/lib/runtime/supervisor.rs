@@ -4,15 +4,19 @@ use nix::{
     unistd::Pid,
 };
 use std::{
-    os::unix::io::{AsRawFd, FromRawFd, IntoRawFd},
+    os::unix::{
+        io::{AsRawFd, FromRawFd, IntoRawFd},
+        process::ExitStatusExt,
+    },
     path::PathBuf,
-    process::Stdio,
+    process::{ExitStatus, Stdio},
 };
 use tokio::{
     fs::{create_dir_all, File},
     io::unix::AsyncFd,
     process::Command,
     signal::unix::{signal, SignalKind},
+    sync::broadcast,
 };
 
 use crate::{
@@ -20,10 +24,48 @@ use crate::{
     RotatingLog,
 };
 
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The number of events kept for subscribers that have not received them yet.
+const SUPERVISOR_EVENT_CAPACITY: usize = 64;
+
 //--------------------------------------------------------------------------------------------------
 // Types
 //--------------------------------------------------------------------------------------------------
 
+/// A change in the lifecycle of the child process managed by a supervisor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SupervisorEvent {
+    /// The child process was started.
+    Started {
+        /// The child process ID
+        pid: u32,
+    },
+
+    /// The supervisor received a shutdown signal and is terminating the child process.
+    Terminating {
+        /// The child process ID
+        pid: u32,
+
+        /// The signal the supervisor received
+        signal: i32,
+    },
+
+    /// The child process exited.
+    Exited {
+        /// The child process ID
+        pid: u32,
+
+        /// The exit code, or `None` if the process was killed by a signal
+        code: Option<i32>,
+
+        /// The signal that killed the process, if any
+        signal: Option<i32>,
+    },
+}
+
 /// A supervisor that manages a child process and its logging.
 pub struct Supervisor<M>
 where
@@ -46,12 +88,26 @@ where
 
     /// The metrics monitor
     process_monitor: M,
+
+    /// The channel lifecycle events are broadcast on
+    events: broadcast::Sender<SupervisorEvent>,
 }
 
 //--------------------------------------------------------------------------------------------------
 // Methods
 //--------------------------------------------------------------------------------------------------
 
+impl SupervisorEvent {
+    /// Creates an `Exited` event from the exit status of the child process.
+    pub fn exited(pid: u32, status: ExitStatus) -> Self {
+        Self::Exited {
+            pid,
+            code: status.code(),
+            signal: status.signal(),
+        }
+    }
+}
+
 impl<M> Supervisor<M>
 where
     M: ProcessMonitor + Send,
@@ -82,9 +138,26 @@ where
             child_pid: None,
             log_dir: log_dir.into(),
             process_monitor,
+            events: broadcast::channel(SUPERVISOR_EVENT_CAPACITY).0,
         }
     }
 
+    /// Subscribes to the lifecycle events of the child process.
+    ///
+    /// Only events sent after subscribing are received. A subscriber that falls more than
+    /// `SUPERVISOR_EVENT_CAPACITY` events behind misses the oldest ones.
+    pub fn subscribe(&self) -> broadcast::Receiver<SupervisorEvent> {
+        self.events.subscribe()
+    }
+
+    /// Passes an event to the process monitor and broadcasts it to subscribers.
+    fn emit(&mut self, event: SupervisorEvent) {
+        self.process_monitor.on_event(&event);
+
+        // Sending only fails when nobody is subscribed
+        let _ = self.events.send(event);
+    }
+
     /// Starts the supervisor and the child process.
     ///
     /// This method:
@@ -187,6 +260,7 @@ where
 
         // Start monitoring
         self.process_monitor.start(child_pid, child_io).await?;
+        self.emit(SupervisorEvent::Started { pid: child_pid });
 
         // Setup signal handlers
         let mut sigterm = signal(SignalKind::terminate())?;
@@ -202,6 +276,8 @@ where
 
                 if status.is_ok() {
                     if let Ok(status) = status {
+                        self.emit(SupervisorEvent::exited(child_pid, status));
+
                         if status.success() {
                             tracing::info!(
                                 "child process {} exited successfully",
@@ -230,6 +306,11 @@ where
                 tracing::info!("received SIGTERM signal");
 
                 if let Some(pid) = self.child_pid.take() {
+                    self.emit(SupervisorEvent::Terminating {
+                        pid,
+                        signal: libc::SIGTERM,
+                    });
+
                     if let Err(e) = nix::sys::signal::kill(Pid::from_raw(pid as i32), nix::sys::signal::Signal::SIGTERM) {
                         tracing::error!(
                             "failed to send SIGTERM to process {}: {}",
@@ -240,11 +321,12 @@ where
                 }
 
                 // Wait for child to exit after sending signal
-                if let Err(e) = child.wait().await {
-                    tracing::error!(
+                match child.wait().await {
+                    Ok(status) => self.emit(SupervisorEvent::exited(child_pid, status)),
+                    Err(e) => tracing::error!(
                         "error waiting for child after SIGTERM: {}",
                         e
-                    );
+                    ),
                 }
             }
             _ = sigint.recv() => {
@@ -254,6 +336,11 @@ where
                 tracing::info!("received SIGINT signal");
 
                 if let Some(pid) = self.child_pid.take() {
+                    self.emit(SupervisorEvent::Terminating {
+                        pid,
+                        signal: libc::SIGINT,
+                    });
+
                     if let Err(e) = nix::sys::signal::kill(Pid::from_raw(pid as i32), nix::sys::signal::Signal::SIGTERM) {
                         tracing::error!(
                             "failed to send SIGTERM to process {}: {}",
@@ -264,11 +351,12 @@ where
                 }
 
                 // Wait for child to exit after sending signal
-                if let Err(e) = child.wait().await {
-                    tracing::error!(
+                match child.wait().await {
+                    Ok(status) => self.emit(SupervisorEvent::exited(child_pid, status)),
+                    Err(e) => tracing::error!(
                         "error waiting for child after SIGINT: {}",
                         e
-                    );
+                    ),
                 }
             }
         }
@@ -278,3 +366,41 @@ where
         Ok(())
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supervisor_event_exited() {
+        let status = std::process::Command::new("sh")
+            .args(["-c", "exit 3"])
+            .status()
+            .unwrap();
+        assert_eq!(
+            SupervisorEvent::exited(7, status),
+            SupervisorEvent::Exited {
+                pid: 7,
+                code: Some(3),
+                signal: None,
+            }
+        );
+
+        let status = std::process::Command::new("sh")
+            .args(["-c", "kill -KILL $$"])
+            .status()
+            .unwrap();
+        assert_eq!(
+            SupervisorEvent::exited(7, status),
+            SupervisorEvent::Exited {
+                pid: 7,
+                code: None,
+                signal: Some(libc::SIGKILL),
+            }
+        );
+    }
+}
@@ -4,13 +4,20 @@ use std::error::Error;
 use std::time::Duration;
 
 use dotenv::dotenv;
-use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{
+    header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    StatusCode,
+};
 use serde::Deserialize;
 use serde_json::{json, Value};
 use uuid::Uuid;
 
+use crate::compression::{decompress_output, OUTPUT_COMPRESSION};
 use crate::{Execution, SandboxError, SandboxOptions};
 
+/// JSON-RPC error code the portal returns for files that exceed its maximum file read size
+const FILE_TOO_LARGE_ERROR_CODE: i64 = -32004;
+
 /// Base implementation for sandbox types
 pub struct SandboxBase {
     /// URL of the Microsandbox server
@@ -109,6 +116,11 @@ impl SandboxBase {
             .send()
             .await?;
 
+        if response.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            let error_text = response.text().await?;
+            return Err(Box::new(payload_too_large_error(&error_text)));
+        }
+
         if !response.status().is_success() {
             let error_text = response.text().await?;
             return Err(Box::new(SandboxError::RequestFailed(error_text)));
@@ -249,6 +261,25 @@ impl SandboxBase {
         &self,
         language: &str,
         code: &str,
+    ) -> Result<Execution, Box<dyn Error + Send + Sync>> {
+        self.run_repl(language, "code", code).await
+    }
+
+    /// Run a script file already in the sandbox, by its absolute path
+    pub async fn run_file(
+        &self,
+        language: &str,
+        guest_path: &str,
+    ) -> Result<Execution, Box<dyn Error + Send + Sync>> {
+        self.run_repl(language, "exec_file", guest_path).await
+    }
+
+    /// Send a `sandbox.repl.run` request running either code or a script file, named by `field`
+    async fn run_repl(
+        &self,
+        language: &str,
+        field: &str,
+        value: &str,
     ) -> Result<Execution, Box<dyn Error + Send + Sync>> {
         if !self.is_started {
             return Err(Box::new(SandboxError::NotStarted));
@@ -258,10 +289,38 @@ impl SandboxBase {
             "sandbox": self.name,
             "namespace": self.namespace,
             "language": language,
-            "code": code,
+            field: value,
+            "compression": OUTPUT_COMPRESSION,
+            "binary_output": true,
         });
 
-        let result: HashMap<String, Value> = self.make_request("sandbox.repl.run", params).await?;
+        let mut result: HashMap<String, Value> =
+            self.make_request("sandbox.repl.run", params).await?;
+        decompress_output(&mut result)?;
         Ok(Execution::new(result))
     }
 }
+
+/// Build an error from a 413 response body: `FileTooLarge` when the JSON-RPC error code says a
+/// file exceeded the read limit, `PayloadTooLarge` otherwise, with the limits the error's data
+/// carries
+fn payload_too_large_error(body: &str) -> SandboxError {
+    let error = serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|response| response.get("error").cloned());
+    let data = |key: &str| {
+        error
+            .as_ref()
+            .and_then(|error| error.get("data")?.get(key)?.as_u64())
+    };
+
+    let code = error.as_ref().and_then(|error| error.get("code")?.as_i64());
+    if code == Some(FILE_TOO_LARGE_ERROR_CODE) {
+        return SandboxError::FileTooLarge {
+            size: data("size"),
+            limit: data("max_file_read_size"),
+        };
+    }
+
+    SandboxError::PayloadTooLarge(data("max_body_size"))
+}
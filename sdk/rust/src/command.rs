@@ -1,5 +1,7 @@
 //! Command execution interface for sandboxes
 
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::error::Error;
@@ -7,6 +9,7 @@ use std::sync::Arc;
 
 use tokio::sync::Mutex;
 
+use crate::compression::{decompress_output, OUTPUT_COMPRESSION};
 use crate::SandboxBase;
 use crate::SandboxError;
 
@@ -22,11 +25,52 @@ pub struct CommandExecution {
     /// Exit code from the command
     exit_code: i32,
 
+    /// How the command terminated
+    termination: Termination,
+
     /// Whether the command was successful
     success: bool,
 
     /// Output lines from the execution
     output_lines: Vec<OutputLine>,
+
+    /// Bytes of output the portal dropped past its output limit
+    dropped_bytes: u64,
+}
+
+/// How a command terminated
+///
+/// Distinguishes a normal exit from a command that was killed, e.g. by a timeout or because it
+/// ran out of memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum Termination {
+    /// The command exited normally with the given exit code
+    Exited(i32),
+
+    /// The command was killed by the given signal
+    Signaled(i32),
+
+    /// The command was stopped because it exceeded its timeout, by the given phase of its
+    /// termination. Older portals do not report the phase
+    Timeout(Option<TerminationPhase>),
+
+    /// The command was killed by the kernel OOM killer
+    OomKilled,
+}
+
+/// The phase of stopping a timed out command that ended it
+///
+/// The portal sends `SIGTERM` and gives the command a grace period to clean up before killing it
+/// with `SIGKILL`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TerminationPhase {
+    /// The command exited within its grace period after `SIGTERM`
+    Sigterm,
+
+    /// The command was still running at the end of its grace period and was killed with `SIGKILL`
+    Sigkill,
 }
 
 /// A single line of output from a command execution
@@ -34,8 +78,10 @@ pub struct CommandExecution {
 struct OutputLine {
     /// Stream type (stdout or stderr)
     stream: String,
-    /// Text content
+    /// Text content, with bytes that are not valid UTF-8 replaced by `U+FFFD`
     text: String,
+    /// Bytes of the line as it was printed
+    bytes: Vec<u8>,
 }
 
 impl CommandExecution {
@@ -65,11 +111,22 @@ impl CommandExecution {
             .and_then(|v| v.as_i64())
             .unwrap_or(-1) as i32;
 
+        // Older portals only report the exit code
+        let termination = output_data
+            .get("termination")
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+            .unwrap_or(Termination::Exited(exit_code));
+
         let success = output_data
             .get("success")
             .and_then(|v| v.as_bool())
             .unwrap_or(false);
 
+        let dropped_bytes = output_data
+            .get("dropped_bytes")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0);
+
         // Process output lines
         let mut output_lines = Vec::new();
         if let Some(output) = output_data.get("output") {
@@ -81,13 +138,20 @@ impl CommandExecution {
                             .and_then(|v| v.as_str())
                             .unwrap_or("")
                             .to_string();
-                        let text = line_obj
-                            .get("text")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("")
-                            .to_string();
-
-                        output_lines.push(OutputLine { stream, text });
+                        let text = line_obj.get("text").and_then(|v| v.as_str()).unwrap_or("");
+
+                        // Lines that are not valid UTF-8 arrive base64 encoded
+                        let bytes = match line_obj.get("encoding").and_then(|v| v.as_str()) {
+                            Some("base64") => BASE64.decode(text).unwrap_or_default(),
+                            _ => text.as_bytes().to_vec(),
+                        };
+                        let text = String::from_utf8_lossy(&bytes).into_owned();
+
+                        output_lines.push(OutputLine {
+                            stream,
+                            text,
+                            bytes,
+                        });
                     }
                 }
             }
@@ -97,8 +161,10 @@ impl CommandExecution {
             command,
             args,
             exit_code,
+            termination,
             success,
             output_lines,
+            dropped_bytes,
         }
     }
 
@@ -117,6 +183,16 @@ impl CommandExecution {
         self.exit_code
     }
 
+    /// Get how the command terminated
+    pub fn termination(&self) -> Termination {
+        self.termination
+    }
+
+    /// Get the number of bytes of output the portal dropped past its output limit
+    pub fn dropped_bytes(&self) -> u64 {
+        self.dropped_bytes
+    }
+
     /// Get the standard output from the command
     pub async fn output(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
         let mut output_text = String::new();
@@ -155,6 +231,30 @@ impl CommandExecution {
         Ok(error_text)
     }
 
+    /// Get the standard output from the command as raw bytes
+    ///
+    /// Unlike [`output`](Self::output), bytes that are not valid UTF-8, e.g. binary data, are
+    /// returned as they were printed.
+    pub fn stdout_bytes(&self) -> Vec<u8> {
+        self.stream_bytes("stdout")
+    }
+
+    /// Get the standard error from the command as raw bytes
+    pub fn stderr_bytes(&self) -> Vec<u8> {
+        self.stream_bytes("stderr")
+    }
+
+    /// Join the lines of a stream, without a trailing newline as in [`output`](Self::output)
+    fn stream_bytes(&self, stream: &str) -> Vec<u8> {
+        let lines: Vec<&[u8]> = self
+            .output_lines
+            .iter()
+            .filter(|line| line.stream == stream)
+            .map(|line| line.bytes.as_slice())
+            .collect();
+        lines.join(&b'\n')
+    }
+
     /// Check if the command was successful (exit code 0)
     pub fn is_success(&self) -> bool {
         self.success
@@ -207,6 +307,8 @@ impl Command {
             "namespace": namespace,
             "command": command,
             "args": args_vec,
+            "compression": OUTPUT_COMPRESSION,
+            "binary_output": true,
         });
 
         // Add timeout if specified
@@ -216,8 +318,9 @@ impl Command {
 
         // Execute command
         let base = self.sandbox.lock().await;
-        let result: HashMap<String, Value> =
+        let mut result: HashMap<String, Value> =
             base.make_request("sandbox.command.run", params).await?;
+        decompress_output(&mut result)?;
 
         Ok(CommandExecution::new(result))
     }
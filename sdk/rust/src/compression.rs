@@ -0,0 +1,121 @@
+//! Transparent decompression of large execution outputs
+//!
+//! Code and command runs ask the portal to compress their output. Outputs above the portal's
+//! size threshold then arrive as a base64 encoded, compressed JSON array in an
+//! `output_compressed` field instead of the usual `output` lines, and are unpacked here before
+//! the result is handed to `Execution` or `CommandExecution`.
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use flate2::read::GzDecoder;
+use serde_json::Value;
+
+use crate::SandboxError;
+
+/// Encoding the portal is asked to compress large outputs with
+pub(crate) const OUTPUT_COMPRESSION: &str = "gzip";
+
+/// Result field holding compressed output lines
+const COMPRESSED_OUTPUT_FIELD: &str = "output_compressed";
+
+/// Replace a compressed output in an execution result with the output lines it holds
+///
+/// Results without compressed output are left untouched.
+pub(crate) fn decompress_output(result: &mut HashMap<String, Value>) -> Result<(), SandboxError> {
+    let Some(compressed) = result.remove(COMPRESSED_OUTPUT_FIELD) else {
+        return Ok(());
+    };
+
+    let encoding = compressed
+        .get("encoding")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    if encoding != OUTPUT_COMPRESSION {
+        return Err(SandboxError::InvalidResponse(format!(
+            "Unsupported output encoding: '{}'",
+            encoding
+        )));
+    }
+
+    let data = compressed
+        .get("data")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| {
+            SandboxError::InvalidResponse("Compressed output has no data".to_string())
+        })?;
+    let data = BASE64.decode(data).map_err(|e| {
+        SandboxError::InvalidResponse(format!("Invalid compressed output data: {}", e))
+    })?;
+
+    let mut json = Vec::new();
+    GzDecoder::new(data.as_slice())
+        .read_to_end(&mut json)
+        .map_err(|e| SandboxError::InvalidResponse(format!("Invalid gzip output: {}", e)))?;
+    let output = serde_json::from_slice(&json)
+        .map_err(|e| SandboxError::InvalidResponse(format!("Invalid compressed output: {}", e)))?;
+
+    result.insert("output".to_string(), output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+    use serde_json::json;
+
+    use super::*;
+
+    /// Compress output lines the way the portal does
+    fn compress(output: &Value) -> String {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(&serde_json::to_vec(output).unwrap())
+            .unwrap();
+        BASE64.encode(encoder.finish().unwrap())
+    }
+
+    fn result(fields: Value) -> HashMap<String, Value> {
+        serde_json::from_value(fields).unwrap()
+    }
+
+    #[test]
+    fn test_decompress_output_round_trip() {
+        let output = json!([
+            { "stream": "stdout", "text": "hello" },
+            { "stream": "stderr", "text": "world" },
+        ]);
+        let mut fields = result(json!({
+            COMPRESSED_OUTPUT_FIELD: { "encoding": OUTPUT_COMPRESSION, "data": compress(&output) },
+            "status": "success",
+        }));
+
+        decompress_output(&mut fields).unwrap();
+        assert_eq!(
+            fields,
+            result(json!({ "output": output, "status": "success" }))
+        );
+    }
+
+    #[test]
+    fn test_decompress_output_leaves_plain_output() {
+        let plain = result(json!({ "output": [{ "stream": "stdout", "text": "hello" }] }));
+        let mut fields = plain.clone();
+        decompress_output(&mut fields).unwrap();
+        assert_eq!(fields, plain);
+    }
+
+    #[test]
+    fn test_decompress_output_rejects_unsupported_encoding() {
+        let mut fields = result(json!({
+            COMPRESSED_OUTPUT_FIELD: { "encoding": "zstd", "data": "" },
+        }));
+        assert!(matches!(
+            decompress_output(&mut fields),
+            Err(SandboxError::InvalidResponse(_))
+        ));
+    }
+}
@@ -22,6 +22,25 @@ pub enum SandboxError {
     /// Invalid response received from server
     InvalidResponse(String),
 
+    /// The request body exceeded the maximum size accepted by the sandbox, in bytes if known
+    PayloadTooLarge(Option<u64>),
+
+    /// A file to download exceeds the maximum size the sandbox reads, with the file's size and
+    /// the limit in bytes if known
+    FileTooLarge {
+        /// Size of the file
+        size: Option<u64>,
+
+        /// Maximum file read size of the sandbox
+        limit: Option<u64>,
+    },
+
+    /// The code run in the sandbox failed, with its error output
+    ExecutionFailed(String),
+
+    /// The output of an execution is not the JSON that was expected
+    InvalidJson(String),
+
     /// General error
     General(String),
 }
@@ -39,6 +58,21 @@ impl fmt::Display for SandboxError {
             SandboxError::InvalidResponse(msg) => {
                 write!(f, "Invalid response from server: {}", msg)
             }
+            SandboxError::PayloadTooLarge(Some(limit)) => {
+                write!(f, "Payload too large: request body exceeds the limit of {} bytes", limit)
+            }
+            SandboxError::PayloadTooLarge(None) => write!(f, "Payload too large"),
+            SandboxError::FileTooLarge {
+                size: Some(size),
+                limit: Some(limit),
+            } => write!(
+                f,
+                "File too large: {} bytes exceeds the read limit of {} bytes",
+                size, limit
+            ),
+            SandboxError::FileTooLarge { .. } => write!(f, "File too large"),
+            SandboxError::ExecutionFailed(stderr) => write!(f, "Execution failed: {}", stderr),
+            SandboxError::InvalidJson(msg) => write!(f, "Output is not valid JSON: {}", msg),
             SandboxError::General(msg) => write!(f, "{}", msg),
         }
     }
@@ -1,9 +1,13 @@
 //! Execution results for code run in sandboxes
 
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 use std::collections::HashMap;
 use std::error::Error;
 
+use crate::SandboxError;
+
 /// Represents a code execution in a sandbox environment
 ///
 /// This struct provides access to the results and output of code
@@ -18,6 +22,8 @@ pub struct Execution {
     language: String,
     /// Whether the execution encountered an error
     has_error: bool,
+    /// Bytes of output the portal dropped past its output limit
+    dropped_bytes: u64,
 }
 
 /// A single line of output from an execution
@@ -25,8 +31,10 @@ pub struct Execution {
 struct OutputLine {
     /// Stream type (stdout or stderr)
     stream: String,
-    /// Text content
+    /// Text content, with bytes that are not valid UTF-8 replaced by `U+FFFD`
     text: String,
+    /// Bytes of the line as it was printed
+    bytes: Vec<u8>,
 }
 
 impl Execution {
@@ -45,6 +53,11 @@ impl Execution {
             .unwrap_or("unknown")
             .to_string();
 
+        let dropped_bytes = output_data
+            .get("dropped_bytes")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0);
+
         // Check if status indicates an error
         if status == "error" || status == "exception" {
             has_error = true;
@@ -60,18 +73,25 @@ impl Execution {
                             .and_then(|v| v.as_str())
                             .unwrap_or("")
                             .to_string();
-                        let text = line_obj
-                            .get("text")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("")
-                            .to_string();
+                        let text = line_obj.get("text").and_then(|v| v.as_str()).unwrap_or("");
+
+                        // Lines that are not valid UTF-8 arrive base64 encoded
+                        let bytes = match line_obj.get("encoding").and_then(|v| v.as_str()) {
+                            Some("base64") => BASE64.decode(text).unwrap_or_default(),
+                            _ => text.as_bytes().to_vec(),
+                        };
+                        let text = String::from_utf8_lossy(&bytes).into_owned();
 
                         // Check for errors in stderr
                         if stream == "stderr" && !text.is_empty() {
                             has_error = true;
                         }
 
-                        output_lines.push(OutputLine { stream, text });
+                        output_lines.push(OutputLine {
+                            stream,
+                            text,
+                            bytes,
+                        });
                     }
                 }
             }
@@ -82,6 +102,7 @@ impl Execution {
             status,
             language,
             has_error,
+            dropped_bytes,
         }
     }
 
@@ -123,6 +144,54 @@ impl Execution {
         Ok(error_text)
     }
 
+    /// Get the standard output from the execution as raw bytes
+    ///
+    /// Unlike [`output`](Self::output), bytes that are not valid UTF-8, e.g. binary data, are
+    /// returned as they were printed.
+    pub fn stdout_bytes(&self) -> Vec<u8> {
+        self.stream_bytes("stdout")
+    }
+
+    /// Get the standard error from the execution as raw bytes
+    pub fn stderr_bytes(&self) -> Vec<u8> {
+        self.stream_bytes("stderr")
+    }
+
+    /// Join the lines of a stream, without a trailing newline as in [`output`](Self::output)
+    fn stream_bytes(&self, stream: &str) -> Vec<u8> {
+        let lines: Vec<&[u8]> = self
+            .output_lines
+            .iter()
+            .filter(|line| line.stream == stream)
+            .map(|line| line.bytes.as_slice())
+            .collect();
+        lines.join(&b'\n')
+    }
+
+    /// Parse the standard output of the execution as JSON
+    ///
+    /// This is meant for code that prints a single JSON document as its result. Fails with
+    /// [`SandboxError::ExecutionFailed`] if the execution had an error, and with
+    /// [`SandboxError::InvalidJson`] if the output cannot be deserialized into `T`.
+    ///
+    /// ```no_run
+    /// # use microsandbox::{BaseSandbox, PythonSandbox};
+    /// # async fn example(sb: &PythonSandbox) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    /// let exec = sb.run("import json; print(json.dumps({'total': 42}))").await?;
+    /// let result: serde_json::Value = exec.json().await?;
+    /// assert_eq!(result["total"], 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn json<T: DeserializeOwned>(&self) -> Result<T, Box<dyn Error + Send + Sync>> {
+        if self.has_error {
+            return Err(Box::new(SandboxError::ExecutionFailed(self.error().await?)));
+        }
+
+        let output = self.output().await?;
+        Ok(serde_json::from_str(&output).map_err(|e| SandboxError::InvalidJson(e.to_string()))?)
+    }
+
     /// Check if the execution contains an error
     pub fn has_error(&self) -> bool {
         self.has_error
@@ -137,4 +206,9 @@ impl Execution {
     pub fn language(&self) -> &str {
         &self.language
     }
+
+    /// Get the number of bytes of output the portal dropped past its output limit
+    pub fn dropped_bytes(&self) -> u64 {
+        self.dropped_bytes
+    }
 }
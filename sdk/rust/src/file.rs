@@ -0,0 +1,344 @@
+//! Streaming file downloads from, deduplicated uploads to and directory listings of sandboxes
+
+use std::error::Error;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use bytes::Bytes;
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+use tokio::sync::Mutex;
+
+use crate::{SandboxBase, SandboxError};
+
+/// A file being downloaded from a sandbox, chunk by chunk
+///
+/// The download is a [`Stream`] of byte chunks, so large files can be written to disk as they
+/// arrive instead of being held in memory. The total size comes with the first chunk, so it is
+/// known before any chunk is consumed, which makes it possible to report progress. Files larger
+/// than the sandbox's maximum file read size fail to start with [`SandboxError::FileTooLarge`]
+/// rather than downloading in part.
+///
+/// ```no_run
+/// # use microsandbox::{BaseSandbox, PythonSandbox};
+/// # async fn example(sb: &PythonSandbox) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// use futures::StreamExt;
+/// use tokio::io::AsyncWriteExt;
+///
+/// let mut download = sb.download_file("/tmp/results.parquet").await?;
+/// println!("Downloading {} bytes", download.size());
+///
+/// let mut out = tokio::fs::File::create("results.parquet").await?;
+/// while let Some(chunk) = download.next().await {
+///     out.write_all(&chunk?).await?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct FileDownload {
+    /// Total size of the file, in bytes
+    size: u64,
+
+    /// Remaining chunks of the file
+    chunks: BoxStream<'static, Result<Bytes, Box<dyn Error + Send + Sync>>>,
+}
+
+/// Number of bytes sent in a single upload chunk
+const UPLOAD_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// A file uploaded to a sandbox
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileUpload {
+    /// Size of the file, in bytes
+    pub size: u64,
+
+    /// Whether the file was copied from the sandbox's upload cache instead of being sent
+    pub from_cache: bool,
+}
+
+/// An entry of a directory in a sandbox
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DirEntryInfo {
+    /// Name of the entry within its directory
+    pub name: String,
+
+    /// Whether the entry is a directory. Symlinks are not followed, so a link to a directory is
+    /// not one
+    pub is_dir: bool,
+
+    /// Size of the entry, in bytes
+    pub size: u64,
+
+    /// Last modification time, in seconds since the Unix epoch
+    pub mtime: Option<i64>,
+}
+
+/// One page returned by the portal's `sandbox.file.list` method
+#[derive(Debug, Deserialize)]
+struct DirPage {
+    /// Entries of the page, sorted by name
+    entries: Vec<DirEntryInfo>,
+
+    /// Offset of the next page, if there are more entries
+    next_offset: Option<usize>,
+}
+
+/// The result of the portal's `sandbox.file.write` method
+#[derive(Debug, Deserialize)]
+struct WriteResult {
+    /// Size of the file written so far, in bytes
+    size: u64,
+
+    /// Whether the file is complete
+    complete: bool,
+}
+
+/// A single chunk returned by the portal's `sandbox.file.read` method
+#[derive(Debug, Deserialize)]
+struct FileChunk {
+    /// Total size of the file, in bytes
+    size: u64,
+
+    /// Base64 encoded chunk data
+    data: String,
+
+    /// Whether the chunk reaches the end of the file
+    eof: bool,
+}
+
+/// Where the next chunk of a download starts
+enum DownloadState {
+    /// The next chunk starts at the given offset
+    Next(u64),
+
+    /// The whole file has been read
+    Done,
+}
+
+impl FileDownload {
+    /// Start downloading a file, fetching its first chunk
+    pub(crate) async fn start(
+        base: Arc<Mutex<SandboxBase>>,
+        path: &str,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let first = read_chunk(&base, path, 0).await?;
+        let size = first.size;
+        let path = path.to_string();
+
+        // The first chunk was already fetched to learn the size; later ones are fetched lazily
+        let (first_bytes, next) = decode_chunk(first, 0)?;
+        let rest = stream::unfold(next, move |state| {
+            let base = base.clone();
+            let path = path.clone();
+            async move {
+                let DownloadState::Next(offset) = state else {
+                    return None;
+                };
+                match read_chunk(&base, &path, offset).await {
+                    Ok(chunk) => match decode_chunk(chunk, offset) {
+                        Ok((bytes, next)) => Some((Ok(bytes), next)),
+                        Err(e) => Some((Err(e), DownloadState::Done)),
+                    },
+                    Err(e) => Some((Err(e), DownloadState::Done)),
+                }
+            }
+        });
+
+        let chunks = stream::once(async move { Ok(first_bytes) })
+            .chain(rest)
+            .filter(|chunk| {
+                let keep = !matches!(chunk, Ok(bytes) if bytes.is_empty());
+                async move { keep }
+            })
+            .boxed();
+
+        Ok(Self { size, chunks })
+    }
+
+    /// Get the total size of the file, in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl Stream for FileDownload {
+    type Item = Result<Bytes, Box<dyn Error + Send + Sync>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.chunks.poll_next_unpin(cx)
+    }
+}
+
+/// Request one chunk of a file from the sandbox
+async fn read_chunk(
+    base: &Arc<Mutex<SandboxBase>>,
+    path: &str,
+    offset: u64,
+) -> Result<FileChunk, Box<dyn Error + Send + Sync>> {
+    let base = base.lock().await;
+    if !base.is_started {
+        return Err(Box::new(SandboxError::NotStarted));
+    }
+
+    let params = json!({
+        "sandbox": base.name,
+        "namespace": base.namespace,
+        "path": path,
+        "offset": offset,
+    });
+
+    base.make_request("sandbox.file.read", params).await
+}
+
+/// Decode a chunk and work out where the next one starts
+fn decode_chunk(
+    chunk: FileChunk,
+    offset: u64,
+) -> Result<(Bytes, DownloadState), Box<dyn Error + Send + Sync>> {
+    let bytes = BASE64
+        .decode(&chunk.data)
+        .map_err(|e| SandboxError::InvalidResponse(format!("Invalid file chunk data: {}", e)))?;
+
+    let next = if chunk.eof {
+        DownloadState::Done
+    } else if bytes.is_empty() {
+        // The file was truncated while it was being downloaded
+        return Err(Box::new(SandboxError::InvalidResponse(format!(
+            "File ended at offset {} before its reported size of {} bytes",
+            offset, chunk.size
+        ))));
+    } else {
+        DownloadState::Next(offset + bytes.len() as u64)
+    };
+
+    Ok((Bytes::from(bytes), next))
+}
+
+/// Upload a file to the sandbox, unless its upload cache already has the same content
+///
+/// Files are identified by their sha256. A file that any sandbox of the namespace was sent before
+/// is copied from the cache, which makes uploading the same dataset to many sandboxes cheap.
+pub(crate) async fn upload_file(
+    base: &Arc<Mutex<SandboxBase>>,
+    local_path: &Path,
+    guest_path: &str,
+) -> Result<FileUpload, Box<dyn Error + Send + Sync>> {
+    let sha256 = hash_file(local_path).await?;
+
+    let cached: WriteResult = write_chunk(
+        base,
+        json!({
+            "path": guest_path,
+            "sha256": sha256,
+        }),
+    )
+    .await?;
+    if cached.complete {
+        return Ok(FileUpload {
+            size: cached.size,
+            from_cache: true,
+        });
+    }
+
+    let mut file = tokio::fs::File::open(local_path).await?;
+    let mut offset = 0;
+    loop {
+        let mut chunk = Vec::new();
+        (&mut file)
+            .take(UPLOAD_CHUNK_SIZE)
+            .read_to_end(&mut chunk)
+            .await?;
+
+        // A short chunk is the last one; a file ending on a chunk boundary ends with an empty one
+        let eof = (chunk.len() as u64) < UPLOAD_CHUNK_SIZE;
+        let written: WriteResult = write_chunk(
+            base,
+            json!({
+                "path": guest_path,
+                "sha256": sha256,
+                "offset": offset,
+                "data": BASE64.encode(&chunk),
+                "eof": eof,
+            }),
+        )
+        .await?;
+        offset = written.size;
+
+        if eof {
+            return Ok(FileUpload {
+                size: written.size,
+                from_cache: false,
+            });
+        }
+    }
+}
+
+/// Send one `sandbox.file.write` request to the sandbox
+async fn write_chunk(
+    base: &Arc<Mutex<SandboxBase>>,
+    mut params: Value,
+) -> Result<WriteResult, Box<dyn Error + Send + Sync>> {
+    let base = base.lock().await;
+    if !base.is_started {
+        return Err(Box::new(SandboxError::NotStarted));
+    }
+
+    params["sandbox"] = json!(base.name);
+    params["namespace"] = json!(base.namespace);
+
+    base.make_request("sandbox.file.write", params).await
+}
+
+/// Compute the sha256 hex digest of a local file
+async fn hash_file(path: &Path) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; UPLOAD_CHUNK_SIZE as usize];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// List the entries of a directory in the sandbox, sorted by name
+///
+/// The sandbox returns large directories a page at a time; the pages are fetched until the
+/// listing is complete.
+pub(crate) async fn list_dir(
+    base: &Arc<Mutex<SandboxBase>>,
+    guest_path: &str,
+) -> Result<Vec<DirEntryInfo>, Box<dyn Error + Send + Sync>> {
+    let base = base.lock().await;
+    if !base.is_started {
+        return Err(Box::new(SandboxError::NotStarted));
+    }
+
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    loop {
+        let params = json!({
+            "sandbox": base.name,
+            "namespace": base.namespace,
+            "path": guest_path,
+            "offset": offset,
+        });
+        let page: DirPage = base.make_request("sandbox.file.list", params).await?;
+        entries.extend(page.entries);
+
+        match page.next_offset {
+            Some(next) if next > offset => offset = next,
+            _ => return Ok(entries),
+        }
+    }
+}
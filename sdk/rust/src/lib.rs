@@ -9,23 +9,33 @@ use async_trait::async_trait;
 // Re-export common types
 pub use base::SandboxBase;
 pub use builder::SandboxOptions;
-pub use command::Command;
+pub use command::{Command, Termination, TerminationPhase};
 pub use error::SandboxError;
 pub use execution::Execution;
+pub use file::{DirEntryInfo, FileDownload, FileUpload};
 pub use metrics::Metrics;
 pub use node::NodeSandbox;
 pub use python::PythonSandbox;
+pub use runtime::{InterpreterInfo, OsInfo, RuntimeInfo};
 pub use start_options::StartOptions;
+pub use stats::SandboxStats;
 
 mod base;
 mod builder;
 mod command;
+mod compression;
 mod error;
 mod execution;
+mod file;
 mod metrics;
 mod node;
 mod python;
+mod runtime;
 mod start_options;
+mod stats;
+
+#[cfg(feature = "test-support")]
+pub mod testing;
 
 /// Base trait for sandbox implementations
 #[async_trait]
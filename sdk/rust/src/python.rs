@@ -1,13 +1,20 @@
 //! Python-specific sandbox implementation
 
 use std::error::Error;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
+use futures::stream::BoxStream;
 use tokio::sync::Mutex;
 
 use crate::command::Command;
-use crate::{BaseSandbox, Execution, Metrics, SandboxBase, SandboxOptions, StartOptions};
+use crate::file::{self, DirEntryInfo, FileDownload, FileUpload};
+use crate::{
+    runtime, stats, BaseSandbox, Execution, Metrics, RuntimeInfo, SandboxBase, SandboxOptions,
+    SandboxStats, StartOptions,
+};
 
 /// Python-specific sandbox for executing Python code
 pub struct PythonSandbox {
@@ -41,10 +48,76 @@ impl PythonSandbox {
         Ok(Command::new(self.base.clone()))
     }
 
+    /// Download a file from the sandbox as a stream of chunks
+    ///
+    /// The file is never buffered whole, on either side, so this works for files far larger
+    /// than memory. See [`FileDownload`] for an example.
+    pub async fn download_file(
+        &self,
+        guest_path: &str,
+    ) -> Result<FileDownload, Box<dyn Error + Send + Sync>> {
+        FileDownload::start(self.base.clone(), guest_path).await
+    }
+
+    /// Upload a local file to the sandbox
+    ///
+    /// The file is sent in chunks, unless a sandbox of the same namespace was sent the same
+    /// content before, in which case the sandbox copies it from its upload cache instead.
+    pub async fn upload_file(
+        &self,
+        local_path: impl AsRef<Path>,
+        guest_path: &str,
+    ) -> Result<FileUpload, Box<dyn Error + Send + Sync>> {
+        file::upload_file(&self.base, local_path.as_ref(), guest_path).await
+    }
+
+    /// Run a script file already in the sandbox, e.g. the entrypoint of a project uploaded with
+    /// [`upload_file`](Self::upload_file), so that it can import the files next to it
+    ///
+    /// The path must be absolute.
+    pub async fn run_file(
+        &self,
+        guest_path: &str,
+    ) -> Result<Execution, Box<dyn Error + Send + Sync>> {
+        let base = self.base.lock().await;
+        base.run_file("python", guest_path).await
+    }
+
+    /// List the entries of a directory in the sandbox with their sizes and modification times
+    ///
+    /// The path must be absolute. Entries are sorted by name.
+    pub async fn list_dir(
+        &self,
+        guest_path: &str,
+    ) -> Result<Vec<DirEntryInfo>, Box<dyn Error + Send + Sync>> {
+        file::list_dir(&self.base, guest_path).await
+    }
+
     /// Get the metrics interface for retrieving sandbox metrics
     pub async fn metrics(&self) -> Result<Metrics, Box<dyn Error + Send + Sync>> {
         Ok(Metrics::new(self.base.clone()))
     }
+
+    /// Get the interpreter versions, installed packages and operating system of the sandbox
+    pub async fn runtime_info(&self) -> Result<RuntimeInfo, Box<dyn Error + Send + Sync>> {
+        runtime::runtime_info(&self.base).await
+    }
+
+    /// Get the current memory, CPU and disk usage of the sandbox
+    pub async fn stats(&self) -> Result<SandboxStats, Box<dyn Error + Send + Sync>> {
+        stats::stats(&self.base).await
+    }
+
+    /// Sample the memory, CPU and disk usage of the sandbox every `interval`, e.g. to watch a long
+    /// run approach its memory limit
+    ///
+    /// The first sample is taken right away. The stream ends after yielding the first error.
+    pub fn stats_stream(
+        &self,
+        interval: Duration,
+    ) -> BoxStream<'static, Result<SandboxStats, Box<dyn Error + Send + Sync>>> {
+        stats::stats_stream(self.base.clone(), interval)
+    }
 }
 
 #[async_trait]
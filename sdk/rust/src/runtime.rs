@@ -0,0 +1,84 @@
+//! Interpreter and operating system information of sandboxes
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::Mutex;
+
+use crate::{SandboxBase, SandboxError};
+
+/// The interpreters and operating system a sandbox provides
+///
+/// ```no_run
+/// # use microsandbox::{BaseSandbox, PythonSandbox};
+/// # async fn example(sb: &PythonSandbox) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// let info = sb.runtime_info().await?;
+/// if let Some(python) = &info.python {
+///     println!("Python {} with numpy {:?}", python.version, python.packages.get("numpy"));
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct RuntimeInfo {
+    /// Python, if the sandbox has it
+    pub python: Option<InterpreterInfo>,
+
+    /// Node.js, if the sandbox has it
+    pub node: Option<InterpreterInfo>,
+
+    /// Operating system of the sandbox
+    pub os: OsInfo,
+}
+
+/// Version of an interpreter and the packages it can import
+#[derive(Debug, Clone, Deserialize)]
+pub struct InterpreterInfo {
+    /// Interpreter version, e.g. `3.12.4` or `20.11.1`
+    pub version: String,
+
+    /// Installed packages by name, with their versions
+    pub packages: BTreeMap<String, String>,
+}
+
+/// Operating system of a sandbox
+#[derive(Debug, Clone, Deserialize)]
+pub struct OsInfo {
+    /// Human-readable name of the distribution, e.g. `Debian GNU/Linux 12 (bookworm)`
+    pub name: Option<String>,
+
+    /// Identifier of the distribution, e.g. `debian`
+    pub id: Option<String>,
+
+    /// Version of the distribution, e.g. `12`
+    pub version: Option<String>,
+
+    /// Release of the kernel the sandbox runs
+    pub kernel: Option<String>,
+
+    /// CPU architecture, e.g. `x86_64` or `aarch64`
+    pub arch: String,
+}
+
+/// Ask the sandbox's portal which interpreters and operating system it provides
+///
+/// The portal probes the sandbox once and answers later requests from its cache, until packages
+/// are installed.
+pub(crate) async fn runtime_info(
+    base: &Arc<Mutex<SandboxBase>>,
+) -> Result<RuntimeInfo, Box<dyn Error + Send + Sync>> {
+    let base = base.lock().await;
+    if !base.is_started {
+        return Err(Box::new(SandboxError::NotStarted));
+    }
+
+    let params = json!({
+        "sandbox": base.name,
+        "namespace": base.namespace,
+    });
+
+    base.make_request("sandbox.runtime.info", params).await
+}
@@ -0,0 +1,94 @@
+//! Live resource usage of sandboxes
+
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::Mutex;
+
+use crate::{SandboxBase, SandboxError};
+
+/// The memory, CPU and disk usage of a sandbox at the time it was sampled
+///
+/// A figure the sandbox could not read is `None`.
+///
+/// ```no_run
+/// # use microsandbox::{BaseSandbox, PythonSandbox};
+/// # async fn example(sb: &PythonSandbox) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// let stats = sb.stats().await?;
+/// if let Some(fraction) = stats.memory_fraction() {
+///     if fraction > 0.9 {
+///         println!("Sandbox is using {:.0}% of its memory", fraction * 100.0);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SandboxStats {
+    /// Memory in use, in bytes
+    pub memory_used_bytes: Option<u64>,
+
+    /// Memory available to the sandbox, in bytes
+    pub memory_limit_bytes: Option<u64>,
+
+    /// Share of the time all CPUs were busy since the previous sample, from 0 to 100
+    pub cpu_percent: Option<f64>,
+
+    /// Space used on the sandbox's root filesystem, in bytes
+    pub disk_used_bytes: Option<u64>,
+
+    /// Size of the sandbox's root filesystem, in bytes
+    pub disk_total_bytes: Option<u64>,
+}
+
+impl SandboxStats {
+    /// Get the fraction of the sandbox's memory in use, from 0 to 1
+    pub fn memory_fraction(&self) -> Option<f64> {
+        match (self.memory_used_bytes, self.memory_limit_bytes) {
+            (Some(used), Some(limit)) if limit > 0 => Some(used as f64 / limit as f64),
+            _ => None,
+        }
+    }
+}
+
+/// Ask the sandbox's portal for its current resource usage
+pub(crate) async fn stats(
+    base: &Arc<Mutex<SandboxBase>>,
+) -> Result<SandboxStats, Box<dyn Error + Send + Sync>> {
+    let base = base.lock().await;
+    if !base.is_started {
+        return Err(Box::new(SandboxError::NotStarted));
+    }
+
+    let params = json!({
+        "sandbox": base.name,
+        "namespace": base.namespace,
+    });
+
+    base.make_request("sandbox.stats", params).await
+}
+
+/// Sample the sandbox's resource usage every `interval`, starting right away
+///
+/// The stream ends after the first failed sample, which it yields, e.g. once the sandbox stops.
+pub(crate) fn stats_stream(
+    base: Arc<Mutex<SandboxBase>>,
+    interval: Duration,
+) -> BoxStream<'static, Result<SandboxStats, Box<dyn Error + Send + Sync>>> {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    stream::unfold(Some((base, ticker)), |state| async move {
+        let (base, mut ticker) = state?;
+        ticker.tick().await;
+        match stats(&base).await {
+            Ok(sample) => Some((Ok(sample), Some((base, ticker)))),
+            Err(e) => Some((Err(e), None)),
+        }
+    })
+    .boxed()
+}
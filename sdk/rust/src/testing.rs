@@ -0,0 +1,257 @@
+//! Test support for code built on top of the SDK
+//!
+//! Only available with the `test-support` feature. [`TestSandbox`] implements [`BaseSandbox`]
+//! without a server or microVM: images are resolved from an in-memory [`TestImageStore`] and
+//! code runs in a local subprocess. This lets downstream crates exercise their session and
+//! tool logic deterministically, without pulling images or touching the network.
+//!
+//! Nothing here provides isolation. Only run trusted test code with it.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::process::Stdio;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::{json, Value};
+use tokio::process::Command as ProcessCommand;
+
+use crate::{BaseSandbox, Execution, Metrics, SandboxError, StartOptions};
+
+/// A fake image: the local interpreter that stands in for the image's runtime
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestImage {
+    /// Language reported in executions, e.g. `python`
+    pub language: String,
+
+    /// Program used to run code, e.g. `python3`
+    pub program: String,
+
+    /// Arguments placed before the code, e.g. `["-c"]`
+    pub args: Vec<String>,
+}
+
+/// In-memory image backend for [`TestSandbox`]
+///
+/// Cloning the store is cheap and clones share the same images, so a test can register
+/// images after sandboxes have been created from it.
+#[derive(Debug, Clone)]
+pub struct TestImageStore {
+    images: Arc<RwLock<HashMap<String, TestImage>>>,
+}
+
+/// Sandbox that runs code in a local subprocess instead of a microVM
+///
+/// ```
+/// use microsandbox::testing::{TestImage, TestImageStore, TestSandbox};
+/// use microsandbox::BaseSandbox;
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// let images = TestImageStore::new();
+/// images.insert("test/shell", TestImage::new("shell", "sh", ["-c"]));
+///
+/// let mut sandbox = TestSandbox::with_images(images, "test/shell");
+/// sandbox.start(None).await?;
+///
+/// let exec = sandbox.run("echo hello").await?;
+/// assert_eq!(exec.output().await?, "hello");
+/// assert!(!exec.has_error());
+/// # Ok(())
+/// # }
+/// ```
+pub struct TestSandbox {
+    /// Images available to this sandbox
+    images: TestImageStore,
+
+    /// Image used when `start` is called without one
+    default_image: String,
+
+    /// Image the sandbox was started with
+    image: Option<TestImage>,
+
+    /// Maximum time a single run may take
+    timeout: Option<Duration>,
+}
+
+impl TestImage {
+    /// Create a new fake image
+    pub fn new(
+        language: impl Into<String>,
+        program: impl Into<String>,
+        args: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        Self {
+            language: language.into(),
+            program: program.into(),
+            args: args.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl TestImageStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self {
+            images: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Register an image under the given name, replacing any existing one
+    pub fn insert(&self, name: impl Into<String>, image: TestImage) {
+        self.images.write().unwrap().insert(name.into(), image);
+    }
+
+    /// Look up an image by name
+    pub fn get(&self, name: &str) -> Option<TestImage> {
+        self.images.read().unwrap().get(name).cloned()
+    }
+}
+
+impl TestSandbox {
+    /// Create a test sandbox backed by the default image store, using `microsandbox/python`
+    /// as its default image
+    pub fn new() -> Self {
+        Self::with_images(TestImageStore::default(), "microsandbox/python")
+    }
+
+    /// Create a test sandbox backed by the given image store
+    pub fn with_images(images: TestImageStore, default_image: impl Into<String>) -> Self {
+        Self {
+            images,
+            default_image: default_image.into(),
+            image: None,
+            timeout: None,
+        }
+    }
+
+    /// Limit how long a single run may take; runs that exceed it fail with
+    /// [`SandboxError::Timeout`]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl Default for TestImageStore {
+    /// A store with `microsandbox/python` and `microsandbox/node` mapped to the local
+    /// `python3` and `node` interpreters
+    fn default() -> Self {
+        let store = Self::new();
+        store.insert(
+            "microsandbox/python",
+            TestImage::new("python", "python3", ["-c"]),
+        );
+        store.insert("microsandbox/node", TestImage::new("nodejs", "node", ["-e"]));
+        store
+    }
+}
+
+impl Default for TestSandbox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl BaseSandbox for TestSandbox {
+    async fn get_default_image(&self) -> String {
+        self.default_image.clone()
+    }
+
+    async fn is_started(&self) -> bool {
+        self.image.is_some()
+    }
+
+    async fn run(&self, code: &str) -> Result<Execution, Box<dyn Error + Send + Sync>> {
+        let image = self.image.as_ref().ok_or(SandboxError::NotStarted)?;
+
+        let child = ProcessCommand::new(&image.program)
+            .args(&image.args)
+            .arg(code)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| {
+                SandboxError::General(format!("Failed to spawn '{}': {}", image.program, e))
+            })?;
+
+        let output = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, child.wait_with_output())
+                .await
+                .map_err(|_| {
+                    SandboxError::Timeout(format!("Execution exceeded {:?}", timeout))
+                })??,
+            None => child.wait_with_output().await?,
+        };
+
+        let mut lines = Vec::new();
+        for (stream, bytes) in [("stdout", &output.stdout), ("stderr", &output.stderr)] {
+            if bytes.is_empty() {
+                continue;
+            }
+
+            // Lines are encoded as the portal encodes them with `binary_output`
+            for line in bytes
+                .strip_suffix(b"\n")
+                .unwrap_or(bytes)
+                .split(|b| *b == b'\n')
+            {
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
+                lines.push(match std::str::from_utf8(line) {
+                    Ok(text) => json!({ "stream": stream, "text": text, "encoding": "utf8" }),
+                    Err(_) => json!({
+                        "stream": stream,
+                        "text": BASE64.encode(line),
+                        "encoding": "base64",
+                    }),
+                });
+            }
+        }
+
+        let status = if output.status.success() {
+            "success"
+        } else {
+            "error"
+        };
+
+        let result: HashMap<String, Value> = HashMap::from([
+            ("status".to_string(), json!(status)),
+            ("language".to_string(), json!(image.language)),
+            ("output".to_string(), Value::Array(lines)),
+        ]);
+
+        Ok(Execution::new(result))
+    }
+
+    async fn start(
+        &mut self,
+        options: Option<StartOptions>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let name = options
+            .and_then(|opts| opts.image)
+            .unwrap_or_else(|| self.default_image.clone());
+
+        let image = self.images.get(&name).ok_or_else(|| {
+            SandboxError::ServerError(format!("Image '{}' not found in test image store", name))
+        })?;
+
+        self.image = Some(image);
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.image = None;
+        Ok(())
+    }
+
+    async fn metrics(&self) -> Result<Metrics, Box<dyn Error + Send + Sync>> {
+        Err(Box::new(SandboxError::General(
+            "Metrics are not available for test sandboxes".to_string(),
+        )))
+    }
+}